@@ -1,22 +1,39 @@
 //! All backend functionality.
 mod account;
+mod age_payload;
 mod credential;
 mod encryption;
 mod file_data;
 mod hashing;
+mod key_file;
+mod recovery;
+mod secret;
+mod shamir;
+mod sharing;
+mod totp;
+mod trash;
 mod vault;
 
 // Re-imports.
 pub use account::{Account, UnlockedAccount};
+pub use age_payload::{AgeIdentity, AgeRecipient};
+pub use credential::CredentialField;
 // pub use credential::Credential;
 // pub use encryption::{
 //     encrypted::{Aes256Key, Aes256Nonce, Encrypted},
 //     traits::{TryFromEncrypted, TryIntoEncrypted},
 // };
-pub use file_data::FileData;
+pub use encryption::cipher_suite::CipherSuite;
+// pub use file_data::FileData;
 // pub use hashing::hashed::{Hash, Hashed, Salt};
+pub use hashing::kdf::KdfParams;
+pub use key_file::KeyFile;
+pub use recovery::RecoveryCode;
+pub use secret::Secret;
+pub use shamir::KeyShare;
+pub use totp::{BackupCode, TotpSecret, TwoFactorCode};
 pub use vault::{
-    database_traits::CredentialUpdateField,
     // database_traits::{AccountUpdateField, CredentialUpdateField, FileDataUpdateField},
     Vault,
+    VaultIssue,
 };
@@ -1,94 +1,444 @@
 //! Functionality for individual dgruft user accounts.
 use color_eyre::eyre::{self, eyre};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
 
 use super::{
-    encryption::encrypted::{
-        new_rand_key, Aes256Key, Encrypted, TryFromEncrypted, TryIntoEncrypted,
+    encryption::{
+        associated_data::{AssociatedData, EntityType},
+        cipher_suite::CipherSuite,
+        encrypted::{new_rand_key, Aes256Key, Encrypted, TryFromEncrypted, TryIntoEncrypted},
     },
-    hashing::hashed::{Hashed, IntoHashed, Salt},
+    hashing::{
+        hashed::{Hashed, IntoHashed, Salt},
+        kdf::KdfParams,
+    },
+    key_file::{KeyFile, KeyFileCheck},
+    recovery::{AccountRecovery, RecoveryCode},
+    secret::Secret,
+    shamir::KeyShare,
+    sharing::{AccountKeypair, PrivateKey},
+    totp::{TotpSecret, TwoFactor, TwoFactorCode},
 };
 
+// The [AssociatedData] field of the account `key` encrypted with the recovery code's key.
+const RECOVERY_ENCRYPTED_KEY_FIELD: &str = "recovery_encrypted_key";
+
+// The [AssociatedData] field of the recovery code's key encrypted with the account `key`.
+const RECOVERY_KEY_FIELD: &str = "encrypted_recovery_key";
+
+// The [AssociatedData] field of the account `key` encrypted with the key split into [KeyShare]s.
+const SHARE_ENCRYPTED_KEY_FIELD: &str = "share_encrypted_key";
+
+// The [AssociatedData] field of the key split into [KeyShare]s encrypted with the account `key`.
+const SHARE_KEY_FIELD: &str = "encrypted_share_key";
+
+// The [AssociatedData] fields of a [RecoveryCode]'s [AccountRecovery].
+const RECOVERY_FIELDS: [&str; 2] = [RECOVERY_ENCRYPTED_KEY_FIELD, RECOVERY_KEY_FIELD];
+
+// The [AssociatedData] fields of the [AccountRecovery] of an account's [KeyShare]s.
+const SHARE_FIELDS: [&str; 2] = [SHARE_ENCRYPTED_KEY_FIELD, SHARE_KEY_FIELD];
+
+// The [AssociatedData] field of the [TotpSecret] encrypted with the once-[Hashed] password.
+const TOTP_SECRET_FIELD: &str = "encrypted_totp_secret";
+
+// The [AssociatedData] field of the account's [PrivateKey] encrypted with the account `key`.
+const PRIVATE_KEY_FIELD: &str = "encrypted_private_key";
+
+// Prepended to the once-[Hashed] password & the [TotpSecret] when deriving the [Aes256Key] which
+// encrypts the `key` of an account with two-factor login.
+const TWO_FACTOR_KEY_DOMAIN: &[u8] = b"dgruft two-factor key";
+
 /// A `dgruft` account with a username, password, and encryption key. Each `dgruft` user has an
 /// account. The account's `password` serves as the primary authenticator.
 ///
 /// ### Role of the `password`
 ///
-/// - The `password`, when [Hashed] a single time through PBKDF2, serves as the [Aes256Key] for
-///     this account's `key`, the [Aes256Key] used to encrypt and decrypt all [Credential],
-///     [FileData], and [FileData] contents owned by this account.
+/// - The `password`, when [Hashed] a single time through the account's [KdfParams], serves as the
+///   [Aes256Key] for this account's `key`, the [Aes256Key] used to encrypt and decrypt all
+///   [Credential], [FileData], and [FileData] contents owned by this account.
 ///
 /// - The double-[Hashed] `password` is stored in the `dgruft` database. When logging in, the
-///     user's entered password is compared against this one to verify that the correct password was
-///     entered.
+///   user's entered password is compared against this one to verify that the correct password was
+///   entered.
+///
+/// Both hashes use the same [KdfParams], which are stored alongside the double-[Hashed]
+/// `password`.
+///
+/// ### Recovery codes
+///
+/// New accounts also get a [RecoveryCode], which is shown to the user once. The account's `key` is
+/// encrypted a second time with the [RecoveryCode], so the account can be recovered & given a new
+/// password if the old one is lost. The [RecoveryCode] can be regenerated or revoked.
+///
+/// ### Key shares
+///
+/// An account's `key` may also be encrypted with a random share key, which is split into
+/// [KeyShare]s with Shamir's secret sharing. Any `threshold` of the [KeyShare]s can recover the
+/// account & give it a new password, like a [RecoveryCode]. The share key is encrypted with the
+/// `key` too. Rotating the `key` revokes the [KeyShare]s, like the old [RecoveryCode], so they
+/// must be split again afterwards.
+///
+/// ### Key files
+///
+/// An account may also require a [KeyFile]. If so, the digest of the [KeyFile] is appended to the
+/// `password` before it is [Hashed], so neither the `password` nor the [KeyFile] can unlock the
+/// account alone. A [KeyFileCheck] is stored so a wrong [KeyFile] can be told apart from a wrong
+/// `password`.
+///
+/// ### Two-factor login
+///
+/// An account may also have two-factor login, in which case a code from an authenticator app is
+/// needed alongside the `password`. The account's [TotpSecret] is encrypted with the [Hashed]
+/// `password`, and the `key` is encrypted with a hash of both the [Hashed] `password` & the
+/// [TotpSecret], so the `key` can't be decrypted by skipping the code check. Anyone with both the
+/// database & the `password` can still decrypt the [TotpSecret], as with any TOTP scheme.
+///
+/// ### Keypairs
+///
+/// Each account has an X25519 keypair. Its [PrivateKey] is encrypted with the `key`, and its public
+/// key is stored in plain so other accounts can share credentials & files with it. Accounts created
+/// before keypairs were added get one the next time they log in.
+///
+/// ### Encryption versions
+///
+/// The `encryption_version` records how this account's data is encrypted:
+///
+/// - `0`: No [Encrypted] is bound to any [AssociatedData].
+///
+/// - `1`: Every [Encrypted] is bound to its owner, entity type, field, and item id through its
+///   [AssociatedData].
+///
+/// Accounts with an outdated `encryption_version` have their data re-encrypted on login.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Account {
     username: String,
     password_salt: Salt<64>,
     dbl_hashed_password: Hashed<32, 64>,
     encrypted_key: Encrypted,
+    encryption_version: u32,
+    key_file_check: Option<KeyFileCheck>,
+    recovery: Option<AccountRecovery>,
+    encrypted_totp_secret: Option<Encrypted>,
+    share_recovery: Option<AccountRecovery>,
+    keypair: Option<AccountKeypair>,
 }
 impl Account {
-    /// Create a new [Account] from a username and a password.
+    /// The `encryption_version` of all new [Account]s.
+    pub const ENCRYPTION_VERSION: u32 = 1;
+
+    /// Create a new [Account] from a username and a password, using the default [KdfParams] and
+    /// [CipherSuite]. Its [RecoveryCode] is discarded.
+    #[cfg(test)]
     pub fn new(username: &str, password: &str) -> eyre::Result<Self> {
+        let (account, _) = Self::new_with_params(
+            username,
+            password,
+            None,
+            KdfParams::default(),
+            CipherSuite::default(),
+        )?;
+        Ok(account)
+    }
+
+    /// Create a new [Account] from a username, a password, and an optional [KeyFile], using the
+    /// given [KdfParams] to hash the password and the given [CipherSuite] to encrypt the key.
+    ///
+    /// Return the new [Account] along with its [RecoveryCode].
+    pub fn new_with_params(
+        username: &str,
+        password: &str,
+        key_file: Option<&KeyFile>,
+        kdf_params: KdfParams,
+        cipher_suite: CipherSuite,
+    ) -> eyre::Result<(Self, RecoveryCode)> {
+        Self::new_with_version(
+            username,
+            password,
+            key_file,
+            kdf_params,
+            cipher_suite,
+            Self::ENCRYPTION_VERSION,
+        )
+    }
+
+    /// Create a new [Account] the way older versions of `dgruft` did, with AES-256-GCM, without
+    /// [AssociatedData], and without a [RecoveryCode].
+    #[cfg(test)]
+    pub(crate) fn new_legacy(
+        username: &str,
+        password: &str,
+        kdf_params: KdfParams,
+    ) -> eyre::Result<Self> {
+        let (account, _) = Self::new_with_version(
+            username,
+            password,
+            None,
+            kdf_params,
+            CipherSuite::Aes256Gcm,
+            0,
+        )?;
+        Ok(Self {
+            recovery: None,
+            keypair: None,
+            ..account
+        })
+    }
+
+    // Helper function: Create a new [Account] with the given `encryption_version`.
+    fn new_with_version(
+        username: &str,
+        password: &str,
+        key_file: Option<&KeyFile>,
+        kdf_params: KdfParams,
+        cipher_suite: CipherSuite,
+        encryption_version: u32,
+    ) -> eyre::Result<(Self, RecoveryCode)> {
         // Generate a random [Aes256Key]. This key is used to encrypt and decrypt all this
-        // account's data. It never changes, even when the password is changed.
+        // account's data. It doesn't change when the password is changed, only when the key is
+        // rotated.
         let key: Aes256Key = new_rand_key();
 
-        // Hash the password once. This [Hashed] password is used as the [Aes256Key] to encrypt and
-        // decrypt this account's `key`.
-        let hashed_password = password.into_hashed_rand_salt();
+        // Encrypt the key with the password & key file.
+        let (hashed_password, dbl_hashed_password, encrypted_key) = lock_key(
+            username,
+            &key,
+            password,
+            key_file,
+            None,
+            kdf_params,
+            cipher_suite,
+            encryption_version,
+        )?;
 
-        // Use the hashed password as the key to encrypt the encryption key.
-        let encrypted_key = key.try_encrypt_with_key(*hashed_password.hash())?;
+        // Encrypt the key with a new recovery code too.
+        let (recovery, recovery_code) = new_recovery(username, &key, cipher_suite)?;
 
-        // Hash the hashed password to store it.
-        let dbl_hashed_password = hashed_password.hash().into_hashed_rand_salt();
+        // Generate the account's keypair.
+        let keypair = new_keypair(username, &key, cipher_suite)?;
 
-        Ok(Self {
-            username: username.to_owned(),
-            password_salt: *hashed_password.salt(),
-            dbl_hashed_password,
-            encrypted_key,
-        })
+        Ok((
+            Self {
+                username: username.to_owned(),
+                password_salt: *hashed_password.salt(),
+                dbl_hashed_password,
+                encrypted_key,
+                encryption_version,
+                key_file_check: key_file.map(KeyFile::new_check),
+                recovery: Some(recovery),
+                encrypted_totp_secret: None,
+                share_recovery: None,
+                keypair: Some(keypair),
+            },
+            recovery_code,
+        ))
     }
 
     /// Create an [Account] from its fields.
+    #[allow(clippy::too_many_arguments)]
     pub fn from_fields(
         username: String,
         password_salt: Salt<64>,
         dbl_hashed_password: Hashed<32, 64>,
         encrypted_key: Encrypted,
+        encryption_version: u32,
+        key_file_check: Option<KeyFileCheck>,
+        recovery: Option<AccountRecovery>,
+        encrypted_totp_secret: Option<Encrypted>,
+        share_recovery: Option<AccountRecovery>,
+        keypair: Option<AccountKeypair>,
     ) -> Self {
         Self {
             username,
             password_salt,
             dbl_hashed_password,
             encrypted_key,
+            encryption_version,
+            key_file_check,
+            recovery,
+            encrypted_totp_secret,
+            share_recovery,
+            keypair,
         }
     }
 
-    /// Unlock this [Account] into an [UnlockedAccount] using its password.
-    pub fn unlock(&self, password: &str) -> eyre::Result<UnlockedAccount> {
-        let hashed_password = password.into_hashed_with_salt(self.password_salt);
+    /// Unlock this [Account] into an [UnlockedAccount] using its password, its [KeyFile], if it
+    /// requires one, & a [TwoFactor], if it has two-factor login.
+    pub fn unlock(
+        &self,
+        password: &str,
+        key_file: Option<&KeyFile>,
+        two_factor: Option<TwoFactor>,
+    ) -> eyre::Result<UnlockedAccount> {
+        // Ensure the right key file was given, if any.
+        match (&self.key_file_check, key_file) {
+            (Some(_), None) => return Err(eyre!("Account {} requires a key file.", self.username)),
+            (None, Some(_)) => {
+                return Err(eyre!("Account {} does not use a key file.", self.username))
+            }
+            (Some(check), Some(key_file)) if !key_file.check_match(check) => {
+                return Err(eyre!("Incorrect key file."))
+            }
+            _ => {}
+        }
+
+        let kdf_params = self.kdf_params();
+        let hashed_password = password_input(password, key_file)
+            .as_slice()
+            .into_hashed_with_salt(self.password_salt, kdf_params)?;
         let dbl_hashed_password = hashed_password
             .hash()
-            .into_hashed_with_salt(*self.dbl_hashed_password.salt());
+            .into_hashed_with_salt(*self.dbl_hashed_password.salt(), kdf_params)?;
 
         // Ensure passwords match
         if dbl_hashed_password.hash() != self.dbl_hashed_password.hash() {
             return Err(eyre!("Incorrect password."));
         }
 
-        // Password OK. Get encryption key.
-        let key = Aes256Key::try_decrypt(&self.encrypted_key, *hashed_password.hash())?;
+        // Password OK. Check the second factor, if required.
+        let mut totp_step = None;
+        let totp_secret = match (&self.encrypted_totp_secret, two_factor) {
+            (None, _) => None,
+            (Some(_), None) => {
+                return Err(eyre!(
+                    "Account {} requires a two-factor code.",
+                    self.username
+                ))
+            }
+            (Some(encrypted_totp_secret), Some(two_factor)) => {
+                let totp_secret = TotpSecret::try_decrypt_with_aad(
+                    encrypted_totp_secret,
+                    &password_key(&hashed_password),
+                    &account_associated_data(&self.username, TOTP_SECRET_FIELD),
+                )?;
+                let is_valid = match two_factor {
+                    TwoFactor::Code(code, unix_time) => {
+                        totp_step = totp_secret.check_code(code, unix_time)?;
+                        totp_step.is_some()
+                    }
+                    TwoFactor::Secret(secret) => *secret == totp_secret,
+                };
+                if !is_valid {
+                    return Err(eyre!("Incorrect two-factor code."));
+                }
+                Some(totp_secret)
+            }
+        };
+
+        // Get encryption key.
+        let key = Aes256Key::try_decrypt_with_aad(
+            &self.encrypted_key,
+            &key_lock_key(&hashed_password, totp_secret.as_ref()),
+            &key_associated_data(&self.username, self.encryption_version),
+        )?;
 
         Ok(UnlockedAccount {
             username: self.username.to_owned(),
-            password: password.to_owned(),
+            password: Secret::new(password.to_owned()),
             hashed_password,
             dbl_hashed_password,
             key,
             encrypted_key: self.encrypted_key.clone(),
+            encryption_version: self.encryption_version,
+            key_file: key_file.cloned(),
+            key_file_check: self.key_file_check.clone(),
+            recovery: self.recovery.clone(),
+            totp_secret,
+            totp_step,
+            encrypted_totp_secret: self.encrypted_totp_secret.clone(),
+            share_recovery: self.share_recovery.clone(),
+            keypair: self.keypair.clone(),
+        })
+    }
+
+    /// Unlock this [Account] into an [UnlockedAccount] using its [RecoveryCode] instead of its
+    /// password, [KeyFile] & two-factor code. The account is given the new password & [KeyFile],
+    /// hashed with the given [KdfParams], and its `key` is re-encrypted with the given
+    /// [CipherSuite].
+    ///
+    /// The [TotpSecret] is encrypted with the old password, so two-factor login is turned off.
+    pub fn recover(
+        &self,
+        recovery_code: &RecoveryCode,
+        new_password: &str,
+        new_key_file: Option<&KeyFile>,
+        kdf_params: KdfParams,
+        cipher_suite: CipherSuite,
+    ) -> eyre::Result<UnlockedAccount> {
+        let recovery = self
+            .recovery
+            .as_ref()
+            .ok_or_else(|| eyre!("Account {} has no recovery code.", self.username))?;
+        let key = Aes256Key::try_decrypt_with_aad(
+            recovery.encrypted_key(),
+            &recovery_code.key(),
+            &account_associated_data(&self.username, RECOVERY_ENCRYPTED_KEY_FIELD),
+        )
+        .map_err(|_| eyre!("Incorrect recovery code."))?;
+        self.reset_password(key, new_password, new_key_file, kdf_params, cipher_suite)
+    }
+
+    /// Unlock this [Account] into an [UnlockedAccount] using at least `threshold` of its
+    /// [KeyShare]s instead of its password, [KeyFile] & two-factor code. The account is given the
+    /// new password & [KeyFile] as in [Account::recover], and two-factor login is turned off.
+    pub fn recover_with_shares(
+        &self,
+        shares: &[KeyShare],
+        new_password: &str,
+        new_key_file: Option<&KeyFile>,
+        kdf_params: KdfParams,
+        cipher_suite: CipherSuite,
+    ) -> eyre::Result<UnlockedAccount> {
+        let share_recovery = self
+            .share_recovery
+            .as_ref()
+            .ok_or_else(|| eyre!("Account {} has no key shares.", self.username))?;
+        let key = Aes256Key::try_decrypt_with_aad(
+            share_recovery.encrypted_key(),
+            &KeyShare::combine(shares)?,
+            &account_associated_data(&self.username, SHARE_ENCRYPTED_KEY_FIELD),
+        )
+        .map_err(|_| eyre!("Incorrect key shares."))?;
+        self.reset_password(key, new_password, new_key_file, kdf_params, cipher_suite)
+    }
+
+    // Helper function: Unlock this [Account] into an [UnlockedAccount] using its decrypted `key`,
+    // giving it a new password & [KeyFile] & turning off two-factor login.
+    fn reset_password(
+        &self,
+        key: Aes256Key,
+        new_password: &str,
+        new_key_file: Option<&KeyFile>,
+        kdf_params: KdfParams,
+        cipher_suite: CipherSuite,
+    ) -> eyre::Result<UnlockedAccount> {
+        let (hashed_password, dbl_hashed_password, encrypted_key) = lock_key(
+            &self.username,
+            &key,
+            new_password,
+            new_key_file,
+            None,
+            kdf_params,
+            cipher_suite,
+            self.encryption_version,
+        )?;
+
+        Ok(UnlockedAccount {
+            username: self.username.to_owned(),
+            password: Secret::new(new_password.to_owned()),
+            hashed_password,
+            dbl_hashed_password,
+            key,
+            encrypted_key,
+            encryption_version: self.encryption_version,
+            key_file: new_key_file.cloned(),
+            key_file_check: new_key_file.map(KeyFile::new_check),
+            recovery: self.recovery.clone(),
+            totp_secret: None,
+            totp_step: None,
+            encrypted_totp_secret: None,
+            share_recovery: self.share_recovery.clone(),
+            keypair: self.keypair.clone(),
         })
     }
 
@@ -111,35 +461,275 @@ impl Account {
     pub fn encrypted_key(&self) -> &Encrypted {
         &self.encrypted_key
     }
+
+    /// Get the `encryption_version` of this [Account].
+    pub fn encryption_version(&self) -> u32 {
+        self.encryption_version
+    }
+
+    /// Get the [KdfParams] used to hash the password of this [Account].
+    pub fn kdf_params(&self) -> KdfParams {
+        self.dbl_hashed_password.kdf_params()
+    }
+
+    /// Get the `key_file_check` of this [Account], which is [Some] iff it requires a [KeyFile].
+    pub fn key_file_check(&self) -> Option<&KeyFileCheck> {
+        self.key_file_check.as_ref()
+    }
+
+    /// Return `true` iff a [KeyFile] is needed to unlock this [Account].
+    pub fn requires_key_file(&self) -> bool {
+        self.key_file_check.is_some()
+    }
+
+    /// Get the `recovery` of this [Account], which is [Some] iff it has a [RecoveryCode].
+    pub fn recovery(&self) -> Option<&AccountRecovery> {
+        self.recovery.as_ref()
+    }
+
+    /// Get the `encrypted_totp_secret` of this [Account], which is [Some] iff it has two-factor
+    /// login.
+    pub fn encrypted_totp_secret(&self) -> Option<&Encrypted> {
+        self.encrypted_totp_secret.as_ref()
+    }
+
+    /// Return `true` iff a two-factor code is needed to unlock this [Account].
+    pub fn requires_two_factor(&self) -> bool {
+        self.encrypted_totp_secret.is_some()
+    }
+
+    /// Get the `share_recovery` of this [Account], which is [Some] iff its `key` has been split
+    /// into [KeyShare]s.
+    pub fn share_recovery(&self) -> Option<&AccountRecovery> {
+        self.share_recovery.as_ref()
+    }
+
+    /// Get the [AccountKeypair] of this [Account], which is [None] iff the account was created
+    /// before keypairs were added & hasn't logged in since.
+    pub fn keypair(&self) -> Option<&AccountKeypair> {
+        self.keypair.as_ref()
+    }
 }
 
 /// An [Account] with all its fields accessible. This data should *never* be written to the disk or
 /// recorded in any other way!
+///
+/// The `password`, `hashed_password`, `key`, `key_file`, and `totp_secret` are zeroed when dropped,
+/// and are never shown by the [fmt::Debug](std::fmt::Debug) implementation.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct UnlockedAccount {
     username: String,
-    password: String,
+    password: Secret<String>,
     hashed_password: Hashed<32, 64>,
     dbl_hashed_password: Hashed<32, 64>,
     key: Aes256Key,
     encrypted_key: Encrypted,
+    encryption_version: u32,
+    key_file: Option<KeyFile>,
+    key_file_check: Option<KeyFileCheck>,
+    recovery: Option<AccountRecovery>,
+    totp_secret: Option<TotpSecret>,
+    totp_step: Option<u64>,
+    encrypted_totp_secret: Option<Encrypted>,
+    share_recovery: Option<AccountRecovery>,
+    keypair: Option<AccountKeypair>,
 }
 impl UnlockedAccount {
-    /// Change the `password` of this [UnlockedAccount].
+    /// Change the `password` & [KeyFile] of this [UnlockedAccount], hashing them with the given
+    /// [KdfParams] and re-encrypting the `key` with the given [CipherSuite]. If no [KeyFile] is
+    /// given, the account no longer requires one.
     ///
-    /// The encryption key itself remains unchanged.
-    pub fn change_password(&mut self, new_password: &str) -> eyre::Result<()> {
-        let new_hashed_password = new_password.into_hashed_rand_salt();
-        let new_encrypted_key = self.key.try_encrypt_with_key(*new_hashed_password.hash())?;
-        let new_dbl_hashed_password = new_hashed_password.hash().into_hashed_rand_salt();
+    /// The encryption key itself remains unchanged, as does two-factor login.
+    pub fn change_password(
+        &mut self,
+        new_password: &str,
+        new_key_file: Option<&KeyFile>,
+        kdf_params: KdfParams,
+        cipher_suite: CipherSuite,
+    ) -> eyre::Result<()> {
+        let (new_hashed_password, new_dbl_hashed_password, new_encrypted_key) = lock_key(
+            &self.username,
+            &self.key,
+            new_password,
+            new_key_file,
+            self.totp_secret.as_ref(),
+            kdf_params,
+            cipher_suite,
+            self.encryption_version,
+        )?;
+        let new_encrypted_totp_secret = match &self.totp_secret {
+            Some(totp_secret) => Some(encrypt_totp_secret(
+                &self.username,
+                totp_secret,
+                &new_hashed_password,
+                cipher_suite,
+            )?),
+            None => None,
+        };
 
+        self.password = Secret::new(new_password.to_owned());
         self.hashed_password = new_hashed_password;
         self.encrypted_key = new_encrypted_key;
         self.dbl_hashed_password = new_dbl_hashed_password;
+        self.key_file = new_key_file.cloned();
+        self.key_file_check = new_key_file.map(KeyFile::new_check);
+        self.encrypted_totp_secret = new_encrypted_totp_secret;
 
         Ok(())
     }
 
+    /// Replace the `key` of this [UnlockedAccount] with a new, random [Aes256Key], encrypting it
+    /// with the current password & the given [CipherSuite].
+    ///
+    /// If the account has a [RecoveryCode], it's replaced with a new one, which is returned. The
+    /// old one's key is encrypted with the old `key`, so it must not unlock the new one. For the
+    /// same reason, the [KeyShare]s, if any, are revoked & must be split again, and the account
+    /// gets a new keypair. Items shared with the account must be encrypted to its new public key
+    /// separately.
+    ///
+    /// The account's data must be re-encrypted with the new `key` separately.
+    pub fn rotate_key(&mut self, cipher_suite: CipherSuite) -> eyre::Result<Option<RecoveryCode>> {
+        let new_key = new_rand_key();
+        let new_encrypted_key = new_key.try_encrypt_with_suite(
+            &key_lock_key(&self.hashed_password, self.totp_secret.as_ref()),
+            cipher_suite,
+            &key_associated_data(&self.username, self.encryption_version),
+        )?;
+        let (new_recovery, new_recovery_code) = match &self.recovery {
+            Some(_) => {
+                let (recovery, recovery_code) =
+                    new_recovery(&self.username, &new_key, cipher_suite)?;
+                (Some(recovery), Some(recovery_code))
+            }
+            None => (None, None),
+        };
+        let new_keypair = match &self.keypair {
+            Some(_) => Some(new_keypair(&self.username, &new_key, cipher_suite)?),
+            None => None,
+        };
+
+        self.encrypted_key = new_encrypted_key;
+        self.recovery = new_recovery;
+        self.share_recovery = None;
+        self.keypair = new_keypair;
+        self.key = new_key;
+        Ok(new_recovery_code)
+    }
+
+    /// Replace the [RecoveryCode] of this [UnlockedAccount] with a new one, encrypted with the
+    /// given [CipherSuite]. The old [RecoveryCode], if any, no longer works.
+    pub fn regenerate_recovery_code(
+        &mut self,
+        cipher_suite: CipherSuite,
+    ) -> eyre::Result<RecoveryCode> {
+        let (recovery, recovery_code) = new_recovery(&self.username, &self.key, cipher_suite)?;
+        self.recovery = Some(recovery);
+        Ok(recovery_code)
+    }
+
+    /// Remove the [RecoveryCode] of this [UnlockedAccount], so the account can only be unlocked
+    /// with its password.
+    pub fn revoke_recovery_code(&mut self) {
+        self.recovery = None;
+    }
+
+    /// Split a new share key for this [UnlockedAccount] into `count` [KeyShare]s, any `threshold` of
+    /// which can recover the account. The `key` is encrypted with the share key using the given
+    /// [CipherSuite]. The old [KeyShare]s, if any, no longer work.
+    pub fn split_key(
+        &mut self,
+        threshold: u8,
+        count: u8,
+        cipher_suite: CipherSuite,
+    ) -> eyre::Result<Vec<KeyShare>> {
+        let share_key = new_rand_key();
+        let shares = KeyShare::split(&share_key, threshold, count)?;
+        self.share_recovery = Some(encrypt_recovery(
+            &self.username,
+            &self.key,
+            &share_key,
+            SHARE_FIELDS,
+            cipher_suite,
+        )?);
+        Ok(shares)
+    }
+
+    /// Remove the [KeyShare]s of this [UnlockedAccount], so they can no longer recover the
+    /// account.
+    pub fn revoke_key_shares(&mut self) {
+        self.share_recovery = None;
+    }
+
+    /// Give this [UnlockedAccount] a new X25519 keypair, encrypting its [PrivateKey] with the given
+    /// [CipherSuite]. Items shared with the old keypair, if any, can no longer be decrypted.
+    pub fn generate_keypair(&mut self, cipher_suite: CipherSuite) -> eyre::Result<()> {
+        self.keypair = Some(new_keypair(&self.username, &self.key, cipher_suite)?);
+        Ok(())
+    }
+
+    /// Decrypt the [PrivateKey] of this [UnlockedAccount].
+    pub fn private_key(&self) -> eyre::Result<PrivateKey> {
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| eyre!("Account {} has no keypair.", self.username))?;
+        PrivateKey::try_decrypt_with_aad(
+            keypair.encrypted_private_key(),
+            &self.key,
+            &account_associated_data(&self.username, PRIVATE_KEY_FIELD),
+        )
+    }
+
+    /// Turn on two-factor login for this [UnlockedAccount] with the given [TotpSecret], encrypting
+    /// the [TotpSecret] & re-encrypting the `key` with the given [CipherSuite].
+    pub fn enable_two_factor(
+        &mut self,
+        totp_secret: &TotpSecret,
+        cipher_suite: CipherSuite,
+    ) -> eyre::Result<()> {
+        let encrypted_totp_secret = encrypt_totp_secret(
+            &self.username,
+            totp_secret,
+            &self.hashed_password,
+            cipher_suite,
+        )?;
+        self.encrypted_key = self.key.try_encrypt_with_suite(
+            &key_lock_key(&self.hashed_password, Some(totp_secret)),
+            cipher_suite,
+            &key_associated_data(&self.username, self.encryption_version),
+        )?;
+        self.totp_secret = Some(totp_secret.clone());
+        self.encrypted_totp_secret = Some(encrypted_totp_secret);
+        Ok(())
+    }
+
+    /// Turn off two-factor login for this [UnlockedAccount], re-encrypting the `key` with the
+    /// given [CipherSuite].
+    pub fn disable_two_factor(&mut self, cipher_suite: CipherSuite) -> eyre::Result<()> {
+        self.encrypted_key = self.key.try_encrypt_with_suite(
+            &password_key(&self.hashed_password),
+            cipher_suite,
+            &key_associated_data(&self.username, self.encryption_version),
+        )?;
+        self.totp_secret = None;
+        self.encrypted_totp_secret = None;
+        Ok(())
+    }
+
+    /// Re-encrypt the `key` of this [UnlockedAccount] for [Account::ENCRYPTION_VERSION] with the
+    /// given [CipherSuite].
+    ///
+    /// The rest of the account's data must be re-encrypted separately.
+    pub fn upgrade_encryption_version(&mut self, cipher_suite: CipherSuite) -> eyre::Result<()> {
+        self.encryption_version = Account::ENCRYPTION_VERSION;
+        self.encrypted_key = self.key.try_encrypt_with_suite(
+            &key_lock_key(&self.hashed_password, self.totp_secret.as_ref()),
+            cipher_suite,
+            &key_associated_data(&self.username, self.encryption_version),
+        )?;
+        Ok(())
+    }
+
     /// Return the `username` of this [UnlockedAccount].
     pub fn username(&self) -> &str {
         &self.username
@@ -147,7 +737,7 @@ impl UnlockedAccount {
 
     /// Return the `password` of this [UnlockedAccount].
     pub fn password(&self) -> &str {
-        &self.password
+        self.password.expose()
     }
 
     /// Return the `hashed_password` of this [UnlockedAccount].
@@ -161,19 +751,243 @@ impl UnlockedAccount {
     }
 
     /// Return the `key` of this [UnlockedAccount].
-    pub fn key(&self) -> Aes256Key {
-        self.key
+    pub fn key(&self) -> &Aes256Key {
+        &self.key
     }
 
     /// Return the `encrypted_key` of this [UnlockedAccount].
     pub fn encrypted_key(&self) -> &Encrypted {
         &self.encrypted_key
     }
+
+    /// Return the `encryption_version` of this [UnlockedAccount].
+    pub fn encryption_version(&self) -> u32 {
+        self.encryption_version
+    }
+
+    /// Return the [KdfParams] used to hash the password of this [UnlockedAccount].
+    pub fn kdf_params(&self) -> KdfParams {
+        self.dbl_hashed_password.kdf_params()
+    }
+
+    /// Return the [KeyFile] used to unlock this [UnlockedAccount], if any.
+    pub fn key_file(&self) -> Option<&KeyFile> {
+        self.key_file.as_ref()
+    }
+
+    /// Return the `key_file_check` of this [UnlockedAccount], which is [Some] iff it requires a
+    /// [KeyFile].
+    pub fn key_file_check(&self) -> Option<&KeyFileCheck> {
+        self.key_file_check.as_ref()
+    }
+
+    /// Return the `recovery` of this [UnlockedAccount], which is [Some] iff it has a
+    /// [RecoveryCode].
+    pub fn recovery(&self) -> Option<&AccountRecovery> {
+        self.recovery.as_ref()
+    }
+
+    /// Return the [TotpSecret] of this [UnlockedAccount], which is [Some] iff it has two-factor
+    /// login.
+    pub fn totp_secret(&self) -> Option<&TotpSecret> {
+        self.totp_secret.as_ref()
+    }
+
+    /// Return the time step of the TOTP code this [UnlockedAccount] was unlocked with, which is
+    /// [Some] iff it was unlocked with a [TwoFactor::Code].
+    pub fn totp_step(&self) -> Option<u64> {
+        self.totp_step
+    }
+
+    /// Return the `encrypted_totp_secret` of this [UnlockedAccount], which is [Some] iff it has
+    /// two-factor login.
+    pub fn encrypted_totp_secret(&self) -> Option<&Encrypted> {
+        self.encrypted_totp_secret.as_ref()
+    }
+
+    /// Return the `share_recovery` of this [UnlockedAccount], which is [Some] iff its `key` has
+    /// been split into [KeyShare]s.
+    pub fn share_recovery(&self) -> Option<&AccountRecovery> {
+        self.share_recovery.as_ref()
+    }
+
+    /// Return the [AccountKeypair] of this [UnlockedAccount], which is [None] iff the account was
+    /// created before keypairs were added & hasn't been given one since.
+    pub fn keypair(&self) -> Option<&AccountKeypair> {
+        self.keypair.as_ref()
+    }
+
+    /// Return the [TwoFactorCode] which unlocks this account again, if it has two-factor login.
+    pub fn two_factor_code(&self) -> Option<TwoFactorCode> {
+        self.totp_secret.clone().map(TwoFactorCode::Secret)
+    }
+}
+
+// Helper function: Hash a password & optional [KeyFile], then use the [Hashed] password & optional
+// [TotpSecret] to encrypt an account's `key`. Return the [Hashed] password, the double-[Hashed]
+// password & the encrypted `key`.
+#[allow(clippy::too_many_arguments)]
+fn lock_key(
+    username: &str,
+    key: &Aes256Key,
+    password: &str,
+    key_file: Option<&KeyFile>,
+    totp_secret: Option<&TotpSecret>,
+    kdf_params: KdfParams,
+    cipher_suite: CipherSuite,
+    encryption_version: u32,
+) -> eyre::Result<(Hashed<32, 64>, Hashed<32, 64>, Encrypted)> {
+    // Hash the password & key file once. This [Hashed] password is used as the [Aes256Key] to
+    // encrypt and decrypt the account's `key`.
+    let hashed_password = password_input(password, key_file)
+        .as_slice()
+        .into_hashed_rand_salt(kdf_params)?;
+
+    // Use the hashed password as the key to encrypt the encryption key.
+    let encrypted_key = key.try_encrypt_with_suite(
+        &key_lock_key(&hashed_password, totp_secret),
+        cipher_suite,
+        &key_associated_data(username, encryption_version),
+    )?;
+
+    // Hash the hashed password to store it.
+    let dbl_hashed_password = hashed_password.hash().into_hashed_rand_salt(kdf_params)?;
+
+    Ok((hashed_password, dbl_hashed_password, encrypted_key))
+}
+
+// Helper function: Generate a new [RecoveryCode] for an account's `key`.
+fn new_recovery(
+    username: &str,
+    key: &Aes256Key,
+    cipher_suite: CipherSuite,
+) -> eyre::Result<(AccountRecovery, RecoveryCode)> {
+    let recovery_code = RecoveryCode::generate();
+    let recovery = encrypt_recovery(
+        username,
+        key,
+        &recovery_code.key(),
+        RECOVERY_FIELDS,
+        cipher_suite,
+    )?;
+    Ok((recovery, recovery_code))
+}
+
+// Helper function: Encrypt an account's `key` with the key of its recovery code or key shares &
+// vice versa. The `fields` are the [AssociatedData] fields of the two [Encrypted]s.
+fn encrypt_recovery(
+    username: &str,
+    key: &Aes256Key,
+    recovery_key: &Aes256Key,
+    fields: [&str; 2],
+    cipher_suite: CipherSuite,
+) -> eyre::Result<AccountRecovery> {
+    Ok(AccountRecovery::from_fields(
+        key.try_encrypt_with_suite(
+            recovery_key,
+            cipher_suite,
+            &account_associated_data(username, fields[0]),
+        )?,
+        recovery_key.try_encrypt_with_suite(
+            key,
+            cipher_suite,
+            &account_associated_data(username, fields[1]),
+        )?,
+    ))
+}
+
+// Helper function: Generate a new X25519 keypair for an account, encrypting the [PrivateKey] with
+// the account's `key`.
+fn new_keypair(
+    username: &str,
+    key: &Aes256Key,
+    cipher_suite: CipherSuite,
+) -> eyre::Result<AccountKeypair> {
+    encrypt_keypair(username, key, &PrivateKey::generate(), cipher_suite)
+}
+
+// Helper function: Encrypt an account's [PrivateKey] with its `key`.
+fn encrypt_keypair(
+    username: &str,
+    key: &Aes256Key,
+    private_key: &PrivateKey,
+    cipher_suite: CipherSuite,
+) -> eyre::Result<AccountKeypair> {
+    Ok(AccountKeypair::from_fields(
+        private_key.public_key(),
+        private_key.try_encrypt_with_suite(
+            key,
+            cipher_suite,
+            &account_associated_data(username, PRIVATE_KEY_FIELD),
+        )?,
+    ))
+}
+
+// Helper function: Encrypt an account's [TotpSecret] with its once-[Hashed] password.
+fn encrypt_totp_secret(
+    username: &str,
+    totp_secret: &TotpSecret,
+    hashed_password: &Hashed<32, 64>,
+    cipher_suite: CipherSuite,
+) -> eyre::Result<Encrypted> {
+    totp_secret.try_encrypt_with_suite(
+        &password_key(hashed_password),
+        cipher_suite,
+        &account_associated_data(username, TOTP_SECRET_FIELD),
+    )
+}
+
+// Helper function: Use the once-[Hashed] password as the [Aes256Key] that encrypts and decrypts an
+// account's `key`.
+fn password_key(hashed_password: &Hashed<32, 64>) -> Aes256Key {
+    Aes256Key::from_bytes(*hashed_password.hash())
+}
+
+// Helper function: Get the [Aes256Key] that encrypts and decrypts an account's `key`. Accounts with
+// two-factor login use a hash of the once-[Hashed] password & the [TotpSecret]. All others use the
+// once-[Hashed] password alone.
+fn key_lock_key(hashed_password: &Hashed<32, 64>, totp_secret: Option<&TotpSecret>) -> Aes256Key {
+    match totp_secret {
+        Some(totp_secret) => Aes256Key::from_bytes(
+            Sha256::new()
+                .chain_update(TWO_FACTOR_KEY_DOMAIN)
+                .chain_update(hashed_password.hash())
+                .chain_update(totp_secret.as_bytes())
+                .finalize()
+                .into(),
+        ),
+        None => password_key(hashed_password),
+    }
+}
+
+// Helper function: Get the bytes hashed into an account's `hashed_password`: the password, followed
+// by the digest of the [KeyFile] if one is used.
+fn password_input(password: &str, key_file: Option<&KeyFile>) -> Zeroizing<Vec<u8>> {
+    let mut input = Zeroizing::new(password.as_bytes().to_vec());
+    if let Some(key_file) = key_file {
+        input.extend_from_slice(key_file.digest());
+    }
+    input
+}
+
+// Helper function: Get the [AssociatedData] bytes of one of an account's recovery, key share,
+// two-factor or keypair fields. These were added after encryption version 1, so they are always bound to their context.
+fn account_associated_data(username: &str, field: &str) -> Vec<u8> {
+    AssociatedData::new(username, EntityType::Account, field, username).to_bytes()
+}
+
+// Helper function: Get the [AssociatedData] bytes of an account's `encrypted_key`. Encryption
+// version 0 used no associated data.
+fn key_associated_data(username: &str, encryption_version: u32) -> Vec<u8> {
+    match encryption_version {
+        0 => Vec::new(),
+        _ => AssociatedData::new(username, EntityType::Account, "key", username).to_bytes(),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use pretty_assertions::assert_eq;
+    use pretty_assertions::{assert_eq, assert_ne};
 
     use super::*;
 
@@ -183,13 +997,473 @@ mod tests {
         let password = "123";
         let account = Account::new(username, password).unwrap();
 
-        let _ = account.unlock("1234").unwrap_err();
-        let unlocked = account.unlock("123").unwrap();
-        let unlocked_again = account.unlock("123").unwrap();
+        let _ = account.unlock("1234", None, None).unwrap_err();
+        let unlocked = account.unlock("123", None, None).unwrap();
+        let unlocked_again = account.unlock("123", None, None).unwrap();
 
         assert_eq!(unlocked.username(), username);
         assert_eq!(unlocked.password(), password);
 
         assert_eq!(unlocked, unlocked_again);
+        assert_eq!(unlocked.kdf_params(), KdfParams::default());
+    }
+
+    #[test]
+    fn change_password_kdf() {
+        let (account, _) = Account::new_with_params(
+            "mr_legacy",
+            "123",
+            None,
+            KdfParams::legacy_pbkdf2(),
+            CipherSuite::Aes256Gcm,
+        )
+        .unwrap();
+        let mut unlocked = account.unlock("123", None, None).unwrap();
+        let key = unlocked.key().clone();
+        assert_eq!(unlocked.kdf_params(), KdfParams::legacy_pbkdf2());
+
+        unlocked
+            .change_password(
+                "456",
+                None,
+                KdfParams::default(),
+                CipherSuite::XChaCha20Poly1305,
+            )
+            .unwrap();
+        assert_eq!(unlocked.kdf_params(), KdfParams::default());
+        assert_eq!(
+            unlocked.encrypted_key().cipher_suite(),
+            CipherSuite::XChaCha20Poly1305
+        );
+
+        let upgraded = Account::from_fields(
+            unlocked.username().to_owned(),
+            *unlocked.hashed_password().salt(),
+            unlocked.dbl_hashed_password().clone(),
+            unlocked.encrypted_key().clone(),
+            unlocked.encryption_version(),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        let _ = upgraded.unlock("123", None, None).unwrap_err();
+        let unlocked_upgraded = upgraded.unlock("456", None, None).unwrap();
+        assert_eq!(unlocked_upgraded.key(), &key);
+        assert_eq!(unlocked_upgraded.kdf_params(), KdfParams::default());
+    }
+
+    #[test]
+    fn rotate_key() {
+        let account = Account::new("mr_test", "123").unwrap();
+        let mut unlocked = account.unlock("123", None, None).unwrap();
+        let old_key = unlocked.key().clone();
+        let old_keypair = unlocked.keypair().unwrap().clone();
+
+        assert!(unlocked
+            .rotate_key(CipherSuite::default())
+            .unwrap()
+            .is_some());
+        assert_ne!(unlocked.key(), &old_key);
+        assert_ne!(
+            unlocked.keypair().unwrap().public_key(),
+            old_keypair.public_key()
+        );
+
+        let rotated = Account::from_fields(
+            unlocked.username().to_owned(),
+            *unlocked.hashed_password().salt(),
+            unlocked.dbl_hashed_password().clone(),
+            unlocked.encrypted_key().clone(),
+            unlocked.encryption_version(),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        assert_eq!(
+            rotated.unlock("123", None, None).unwrap().key(),
+            unlocked.key()
+        );
+
+        // Accounts without a recovery code don't get a new one.
+        unlocked.revoke_recovery_code();
+        assert_eq!(unlocked.rotate_key(CipherSuite::default()).unwrap(), None);
+        assert_eq!(unlocked.recovery(), None);
+    }
+
+    #[test]
+    fn upgrade_encryption_version() {
+        let account = Account::new_legacy("mr_legacy", "123", KdfParams::default()).unwrap();
+        let mut unlocked = account.unlock("123", None, None).unwrap();
+        let key = unlocked.key().clone();
+        assert_eq!(unlocked.encryption_version(), 0);
+
+        unlocked
+            .upgrade_encryption_version(CipherSuite::default())
+            .unwrap();
+        assert_eq!(unlocked.encryption_version(), Account::ENCRYPTION_VERSION);
+
+        // The upgraded key only unlocks alongside the upgraded version.
+        let upgraded = |encryption_version| {
+            Account::from_fields(
+                unlocked.username().to_owned(),
+                *unlocked.hashed_password().salt(),
+                unlocked.dbl_hashed_password().clone(),
+                unlocked.encrypted_key().clone(),
+                encryption_version,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+        let _ = upgraded(0).unlock("123", None, None).unwrap_err();
+        assert_eq!(
+            upgraded(Account::ENCRYPTION_VERSION)
+                .unlock("123", None, None)
+                .unwrap()
+                .key(),
+            &key
+        );
+    }
+
+    #[test]
+    fn key_file() {
+        let key_file = KeyFile::from_bytes(b"the key file");
+        let wrong_key_file = KeyFile::from_bytes(b"not the key file");
+        let (account, _) = Account::new_with_params(
+            "mr_test",
+            "123",
+            Some(&key_file),
+            KdfParams::default(),
+            CipherSuite::default(),
+        )
+        .unwrap();
+        assert!(account.requires_key_file());
+
+        let err = account.unlock("123", None, None).unwrap_err();
+        assert_eq!(err.to_string(), "Account mr_test requires a key file.");
+        let err = account
+            .unlock("123", Some(&wrong_key_file), None)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Incorrect key file.");
+        let err = account.unlock("1234", Some(&key_file), None).unwrap_err();
+        assert_eq!(err.to_string(), "Incorrect password.");
+        let mut unlocked = account.unlock("123", Some(&key_file), None).unwrap();
+        assert_eq!(unlocked.key_file(), Some(&key_file));
+        let key = unlocked.key().clone();
+
+        // Remove the key file.
+        unlocked
+            .change_password("123", None, KdfParams::default(), CipherSuite::default())
+            .unwrap();
+        let changed = Account::from_fields(
+            unlocked.username().to_owned(),
+            *unlocked.hashed_password().salt(),
+            unlocked.dbl_hashed_password().clone(),
+            unlocked.encrypted_key().clone(),
+            unlocked.encryption_version(),
+            unlocked.key_file_check().cloned(),
+            None,
+            None,
+            None,
+            None,
+        );
+        assert!(!changed.requires_key_file());
+        let err = changed.unlock("123", Some(&key_file), None).unwrap_err();
+        assert_eq!(err.to_string(), "Account mr_test does not use a key file.");
+        assert_eq!(changed.unlock("123", None, None).unwrap().key(), &key);
+    }
+
+    #[test]
+    fn recover() {
+        let key_file = KeyFile::from_bytes(b"the key file");
+        let (account, recovery_code) = Account::new_with_params(
+            "mr_test",
+            "123",
+            Some(&key_file),
+            KdfParams::default(),
+            CipherSuite::default(),
+        )
+        .unwrap();
+        let mut unlocked = account.unlock("123", Some(&key_file), None).unwrap();
+        let key = unlocked.key().clone();
+
+        fn recover(
+            account: &Account,
+            recovery_code: &RecoveryCode,
+        ) -> eyre::Result<UnlockedAccount> {
+            account.recover(
+                recovery_code,
+                "456",
+                None,
+                KdfParams::default(),
+                CipherSuite::default(),
+            )
+        }
+        let err = recover(&account, &RecoveryCode::generate()).unwrap_err();
+        assert_eq!(err.to_string(), "Incorrect recovery code.");
+        let recovered = recover(&account, &recovery_code).unwrap();
+        assert_eq!(recovered.key(), &key);
+        assert_eq!(recovered.password(), "456");
+        assert_eq!(recovered.key_file(), None);
+
+        // The recovered account has the new password, and no longer needs the key file.
+        let recovered_account = Account::from_fields(
+            recovered.username().to_owned(),
+            *recovered.hashed_password().salt(),
+            recovered.dbl_hashed_password().clone(),
+            recovered.encrypted_key().clone(),
+            recovered.encryption_version(),
+            recovered.key_file_check().cloned(),
+            recovered.recovery().cloned(),
+            None,
+            None,
+            None,
+        );
+        let _ = recovered_account.unlock("123", None, None).unwrap_err();
+        assert_eq!(
+            recovered_account.unlock("456", None, None).unwrap().key(),
+            &key
+        );
+
+        // Key rotation replaces the recovery code. The old key & recovery code no longer unlock
+        // the new key.
+        let old_key = unlocked.key().clone();
+        let old_recovery = unlocked.recovery().unwrap().clone();
+        let rotated_recovery_code = unlocked
+            .rotate_key(CipherSuite::default())
+            .unwrap()
+            .unwrap();
+        let old_recovery_key = Aes256Key::try_decrypt_with_aad(
+            old_recovery.encrypted_recovery_key(),
+            &old_key,
+            &account_associated_data(unlocked.username(), RECOVERY_KEY_FIELD),
+        )
+        .unwrap();
+        let _ = Aes256Key::try_decrypt_with_aad(
+            unlocked.recovery().unwrap().encrypted_key(),
+            &old_recovery_key,
+            &account_associated_data(unlocked.username(), RECOVERY_ENCRYPTED_KEY_FIELD),
+        )
+        .unwrap_err();
+        let rotated = Account::from_fields(
+            unlocked.username().to_owned(),
+            *unlocked.hashed_password().salt(),
+            unlocked.dbl_hashed_password().clone(),
+            unlocked.encrypted_key().clone(),
+            unlocked.encryption_version(),
+            unlocked.key_file_check().cloned(),
+            unlocked.recovery().cloned(),
+            None,
+            None,
+            None,
+        );
+        let _ = recover(&rotated, &recovery_code).unwrap_err();
+        assert_eq!(
+            recover(&rotated, &rotated_recovery_code).unwrap().key(),
+            unlocked.key()
+        );
+
+        // Regenerated recovery codes replace the old one.
+        let new_recovery_code = unlocked
+            .regenerate_recovery_code(CipherSuite::default())
+            .unwrap();
+        let regenerated = Account::from_fields(
+            unlocked.username().to_owned(),
+            *unlocked.hashed_password().salt(),
+            unlocked.dbl_hashed_password().clone(),
+            unlocked.encrypted_key().clone(),
+            unlocked.encryption_version(),
+            unlocked.key_file_check().cloned(),
+            unlocked.recovery().cloned(),
+            None,
+            None,
+            None,
+        );
+        let _ = recover(&regenerated, &rotated_recovery_code).unwrap_err();
+        let _ = recover(&regenerated, &new_recovery_code).unwrap();
+
+        // Accounts without a recovery code can't be recovered.
+        let legacy = Account::new_legacy("mr_legacy", "123", KdfParams::default()).unwrap();
+        let err = recover(&legacy, &recovery_code).unwrap_err();
+        assert_eq!(err.to_string(), "Account mr_legacy has no recovery code.");
+    }
+
+    #[test]
+    fn recover_with_shares() {
+        let account = Account::new("mr_test", "123").unwrap();
+        let mut unlocked = account.unlock("123", None, None).unwrap();
+        let key = unlocked.key().clone();
+
+        fn recover(account: &Account, shares: &[KeyShare]) -> eyre::Result<UnlockedAccount> {
+            account.recover_with_shares(
+                shares,
+                "456",
+                None,
+                KdfParams::default(),
+                CipherSuite::default(),
+            )
+        }
+        let shares = unlocked.split_key(2, 3, CipherSuite::default()).unwrap();
+        let err = recover(&account, &shares).unwrap_err();
+        assert_eq!(err.to_string(), "Account mr_test has no key shares.");
+
+        let split = Account::from_fields(
+            unlocked.username().to_owned(),
+            *unlocked.hashed_password().salt(),
+            unlocked.dbl_hashed_password().clone(),
+            unlocked.encrypted_key().clone(),
+            unlocked.encryption_version(),
+            unlocked.key_file_check().cloned(),
+            unlocked.recovery().cloned(),
+            None,
+            unlocked.share_recovery().cloned(),
+            None,
+        );
+        let recovered = recover(&split, &shares[1..]).unwrap();
+        assert_eq!(recovered.key(), &key);
+        assert_eq!(recovered.password(), "456");
+        assert_eq!(recovered.share_recovery(), unlocked.share_recovery());
+        let _ = recover(&split, &shares[..1]).unwrap_err();
+
+        // Key shares of another split don't work.
+        let other_shares = KeyShare::split(&new_rand_key(), 2, 3).unwrap();
+        let err = recover(&split, &other_shares).unwrap_err();
+        assert_eq!(err.to_string(), "Incorrect key shares.");
+
+        // Key rotation revokes the key shares.
+        unlocked.rotate_key(CipherSuite::default()).unwrap();
+        assert_eq!(unlocked.share_recovery(), None);
+        let rotated = Account::from_fields(
+            unlocked.username().to_owned(),
+            *unlocked.hashed_password().salt(),
+            unlocked.dbl_hashed_password().clone(),
+            unlocked.encrypted_key().clone(),
+            unlocked.encryption_version(),
+            unlocked.key_file_check().cloned(),
+            unlocked.recovery().cloned(),
+            None,
+            unlocked.share_recovery().cloned(),
+            None,
+        );
+        let err = recover(&rotated, &shares).unwrap_err();
+        assert_eq!(err.to_string(), "Account mr_test has no key shares.");
+    }
+
+    #[test]
+    fn two_factor() {
+        let account = Account::new("mr_test", "123").unwrap();
+        let mut unlocked = account.unlock("123", None, None).unwrap();
+        let key = unlocked.key().clone();
+        let totp_secret = TotpSecret::generate();
+        unlocked
+            .enable_two_factor(&totp_secret, CipherSuite::default())
+            .unwrap();
+
+        fn relock(unlocked: &UnlockedAccount, keep_totp_secret: bool) -> Account {
+            Account::from_fields(
+                unlocked.username().to_owned(),
+                *unlocked.hashed_password().salt(),
+                unlocked.dbl_hashed_password().clone(),
+                unlocked.encrypted_key().clone(),
+                unlocked.encryption_version(),
+                unlocked.key_file_check().cloned(),
+                unlocked.recovery().cloned(),
+                unlocked
+                    .encrypted_totp_secret()
+                    .filter(|_| keep_totp_secret)
+                    .cloned(),
+                None,
+                None,
+            )
+        }
+        let now = 1_700_000_000;
+        let code = totp_secret.code_at(now).unwrap();
+        let wrong_code = if code.expose() == "000000" {
+            "000001"
+        } else {
+            "000000"
+        };
+
+        let enabled = relock(&unlocked, true);
+        assert!(enabled.requires_two_factor());
+        let err = enabled.unlock("123", None, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Account mr_test requires a two-factor code."
+        );
+        let err = enabled
+            .unlock("123", None, Some(TwoFactor::Code(wrong_code, now)))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Incorrect two-factor code.");
+        let _ = enabled
+            .unlock(
+                "123",
+                None,
+                Some(TwoFactor::Secret(&TotpSecret::generate())),
+            )
+            .unwrap_err();
+        let _ = enabled
+            .unlock("1234", None, Some(TwoFactor::Code(code.expose(), now)))
+            .unwrap_err();
+        let unlocked_with_code = enabled
+            .unlock("123", None, Some(TwoFactor::Code(code.expose(), now)))
+            .unwrap();
+        assert_eq!(unlocked_with_code.key(), &key);
+        assert_eq!(unlocked_with_code.totp_secret(), Some(&totp_secret));
+        assert_eq!(unlocked_with_code.totp_step(), Some(now / 30));
+        let unlocked_with_secret = enabled
+            .unlock("123", None, Some(TwoFactor::Secret(&totp_secret)))
+            .unwrap();
+        assert_eq!(unlocked_with_secret.totp_step(), None);
+        assert_eq!(unlocked_with_secret.key(), unlocked_with_code.key());
+        assert_eq!(
+            unlocked_with_secret.totp_secret(),
+            unlocked_with_code.totp_secret()
+        );
+
+        // The key can't be unwrapped without the TOTP secret.
+        let stripped = relock(&unlocked, false);
+        assert!(!stripped.requires_two_factor());
+        let _ = stripped.unlock("123", None, None).unwrap_err();
+
+        // Changing the password keeps two-factor login.
+        unlocked
+            .change_password("456", None, KdfParams::default(), CipherSuite::default())
+            .unwrap();
+        let changed = relock(&unlocked, true);
+        let _ = changed.unlock("456", None, None).unwrap_err();
+        assert_eq!(
+            changed
+                .unlock("456", None, Some(TwoFactor::Code(code.expose(), now)))
+                .unwrap()
+                .key(),
+            &key
+        );
+
+        // Disabling two-factor login.
+        unlocked.disable_two_factor(CipherSuite::default()).unwrap();
+        assert!(unlocked.totp_secret().is_none());
+        let disabled = relock(&unlocked, true);
+        assert!(!disabled.requires_two_factor());
+        assert_eq!(disabled.unlock("456", None, None).unwrap().key(), &key);
+    }
+
+    #[test]
+    fn redacted_debug() {
+        let password = "hunter2_but_longer";
+        let account = Account::new("mr_test", password).unwrap();
+        let unlocked = account.unlock(password, None, None).unwrap();
+
+        let debug = format!("{unlocked:?}");
+        assert!(!debug.contains(password));
+        assert!(!debug.contains(&format!("{:?}", unlocked.key().as_bytes())));
+        assert!(!debug.contains(&format!("{:?}", unlocked.hashed_password().hash())));
+        assert!(debug.contains("mr_test"));
     }
 }
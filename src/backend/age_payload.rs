@@ -0,0 +1,185 @@
+//! Functionality for [age](https://age-encryption.org) payloads, which carry secrets out of & into
+//! the vault without ever writing their plaintext to disk.
+use std::{
+    fmt,
+    io::{BufReader, Read, Write},
+    str::FromStr,
+};
+
+use age::{
+    armor::ArmoredReader, secrecy::SecretString, stream::StreamWriter, x25519, Decryptor, Encryptor,
+};
+use camino::Utf8Path;
+use color_eyre::eyre::{self, eyre};
+
+use super::secret::Secret;
+
+/// Who an age payload is encrypted to: the holder of an X25519 secret key, or anyone who knows a
+/// passphrase.
+pub enum AgeRecipient {
+    /// An X25519 public key, e.g. `age1...`.
+    PublicKey(x25519::Recipient),
+    /// A passphrase, stretched with scrypt.
+    Passphrase(Secret<String>),
+}
+impl fmt::Debug for AgeRecipient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PublicKey(recipient) => write!(f, "PublicKey({})", recipient),
+            Self::Passphrase(passphrase) => write!(f, "Passphrase({:?})", passphrase),
+        }
+    }
+}
+impl FromStr for AgeRecipient {
+    type Err = eyre::Report;
+
+    /// Parse an X25519 public key, e.g. `age1...`.
+    fn from_str(s: &str) -> eyre::Result<Self> {
+        Ok(Self::PublicKey(s.trim().parse().map_err(|err| {
+            eyre!("Invalid age recipient \"{}\": {}.", s.trim(), err)
+        })?))
+    }
+}
+
+/// What an age payload is decrypted with: an X25519 secret key, or the passphrase it was encrypted
+/// to.
+pub enum AgeIdentity {
+    /// An X25519 secret key, e.g. `AGE-SECRET-KEY-1...`.
+    SecretKey(x25519::Identity),
+    /// A passphrase, stretched with scrypt.
+    Passphrase(Secret<String>),
+}
+impl AgeIdentity {
+    /// Read the first X25519 secret key from an age identity file. Blank lines & `#` comments are
+    /// skipped.
+    pub fn from_identity_file<P: AsRef<Utf8Path>>(path: P) -> eyre::Result<Self> {
+        let contents = Secret::new(std::fs::read_to_string(path.as_ref())?);
+        contents
+            .expose()
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+            .ok_or_else(|| eyre!("No age identity found in \"{}\".", path.as_ref()))?
+            .parse()
+    }
+}
+impl fmt::Debug for AgeIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SecretKey(_) => write!(f, "SecretKey(<redacted>)"),
+            Self::Passphrase(passphrase) => write!(f, "Passphrase({:?})", passphrase),
+        }
+    }
+}
+impl FromStr for AgeIdentity {
+    type Err = eyre::Report;
+
+    /// Parse an X25519 secret key, e.g. `AGE-SECRET-KEY-1...`.
+    fn from_str(s: &str) -> eyre::Result<Self> {
+        Ok(Self::SecretKey(
+            s.trim()
+                .parse()
+                .map_err(|err| eyre!("Invalid age identity: {}.", err))?,
+        ))
+    }
+}
+
+/// Wrap the `writer` so that everything written to it is encrypted to the `recipient`. The
+/// returned [StreamWriter] must be finished with [StreamWriter::finish].
+pub(super) fn encrypt_to<W: Write>(
+    recipient: &AgeRecipient,
+    writer: W,
+) -> eyre::Result<StreamWriter<W>> {
+    let encryptor = match recipient {
+        AgeRecipient::PublicKey(public_key) => {
+            Encryptor::with_recipients(std::iter::once(public_key as &dyn age::Recipient))
+                .map_err(|err| eyre!("Failed to encrypt age payload: {}", err))?
+        }
+        AgeRecipient::Passphrase(passphrase) => {
+            Encryptor::with_user_passphrase(secret_string(passphrase))
+        }
+    };
+    Ok(encryptor.wrap_output(writer)?)
+}
+
+/// Wrap the `reader` so that the age payload read from it is decrypted with the `identity`. Both
+/// binary & ASCII-armored payloads are accepted.
+pub(super) fn decrypt_from<R: Read>(identity: &AgeIdentity, reader: R) -> eyre::Result<impl Read> {
+    let decryptor = Decryptor::new(ArmoredReader::new(BufReader::new(reader)))
+        .map_err(|err| eyre!("Failed to read age payload: {}", err))?;
+    let result = match identity {
+        AgeIdentity::SecretKey(secret_key) => {
+            decryptor.decrypt(std::iter::once(secret_key as &dyn age::Identity))
+        }
+        AgeIdentity::Passphrase(passphrase) => {
+            let scrypt_identity = age::scrypt::Identity::new(secret_string(passphrase));
+            decryptor.decrypt(std::iter::once(&scrypt_identity as &dyn age::Identity))
+        }
+    };
+    result.map_err(|err| eyre!("Failed to decrypt age payload: {}", err))
+}
+
+fn secret_string(passphrase: &Secret<String>) -> SecretString {
+    SecretString::from(passphrase.expose().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn round_trip(recipient: &AgeRecipient, identity: &AgeIdentity, plaintext: &[u8]) -> Vec<u8> {
+        let mut writer = encrypt_to(recipient, Vec::new()).unwrap();
+        writer.write_all(plaintext).unwrap();
+        let payload = writer.finish().unwrap();
+        assert!(payload.starts_with(b"age-encryption.org/v1\n"));
+        assert!(!payload.windows(plaintext.len()).any(|w| w == plaintext));
+
+        let mut decrypted = Vec::new();
+        decrypt_from(identity, payload.as_slice())
+            .unwrap()
+            .read_to_end(&mut decrypted)
+            .unwrap();
+        decrypted
+    }
+
+    #[test]
+    fn x25519() {
+        let secret_key = x25519::Identity::generate();
+        let recipient: AgeRecipient = secret_key.to_public().to_string().parse().unwrap();
+        let identity: AgeIdentity =
+            age::secrecy::ExposeSecret::expose_secret(&secret_key.to_string())
+                .parse()
+                .unwrap();
+        let plaintext = b"I'm the Scatman! Ski-ba-bop-ba-dop-bop.";
+        assert_eq!(round_trip(&recipient, &identity, plaintext), plaintext);
+
+        // Another key can't decrypt it.
+        let mut writer = encrypt_to(&recipient, Vec::new()).unwrap();
+        writer.write_all(plaintext).unwrap();
+        let payload = writer.finish().unwrap();
+        let wrong_identity = AgeIdentity::SecretKey(x25519::Identity::generate());
+        assert!(decrypt_from(&wrong_identity, payload.as_slice()).is_err());
+
+        let _ = "age1notakey".parse::<AgeRecipient>().unwrap_err();
+        let _ = "AGE-SECRET-KEY-1NOTAKEY"
+            .parse::<AgeIdentity>()
+            .unwrap_err();
+        assert!(!format!("{identity:?}").contains("AGE-SECRET-KEY"));
+    }
+
+    #[test]
+    fn passphrase() {
+        let recipient = AgeRecipient::Passphrase(Secret::new(String::from("correct horse")));
+        let identity = AgeIdentity::Passphrase(Secret::new(String::from("correct horse")));
+        let plaintext = b"Everybody stutters one way or the other.";
+        assert_eq!(round_trip(&recipient, &identity, plaintext), plaintext);
+
+        let mut writer = encrypt_to(&recipient, Vec::new()).unwrap();
+        writer.write_all(plaintext).unwrap();
+        let payload = writer.finish().unwrap();
+        let wrong_identity = AgeIdentity::Passphrase(Secret::new(String::from("battery staple")));
+        assert!(decrypt_from(&wrong_identity, payload.as_slice()).is_err());
+    }
+}
@@ -1,73 +1,182 @@
 //! Functionality for credentials stored by `dgruft` user accounts.
 use color_eyre::eyre;
+use uuid::Uuid;
 
-use super::encryption::encrypted::{Aes256Key, Encrypted, TryFromEncrypted, TryIntoEncrypted};
+use super::{
+    encryption::{
+        associated_data::{AssociatedData, EntityType},
+        cipher_suite::CipherSuite,
+        encrypted::{Aes256Key, Encrypted, TryFromEncrypted, TryIntoEncrypted},
+    },
+    hashing::{blind_index::blind_index, hashed::Hash},
+    secret::Secret,
+};
+
+// The domain of the blind index of credential names.
+const NAME_INDEX_KEY_DOMAIN: &[u8] = b"dgruft credential name index";
+
+/// All the [Encrypted] fields of a [Credential].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialField {
+    Name,
+    Username,
+    Password,
+    Notes,
+}
+impl CredentialField {
+    /// Return the name of this [CredentialField] as it appears in [AssociatedData].
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Username => "username",
+            Self::Password => "password",
+            Self::Notes => "notes",
+        }
+    }
+}
 
 /// User-defined login information for various sites and services. The main data unit of `dgruft`'s
 /// "password bank" functionality.
 ///
+/// Each [Encrypted] field is bound to the `owner_username`, the `id`, and the name of the field
+/// through its [AssociatedData], so fields can't be swapped between columns or [Credential]s.
+///
 /// ### Fields
 ///
+/// - `id`: The stable, unique id of this [Credential].
+///
 /// - `owner_username`: The username of the [Account] that owns this [Credential].
 ///
 /// - `encrypted_name`: The [Encrypted] name of this [Credential].
 ///
 /// - `encrypted_username`: The [Encrypted] username of this [Credential]. This is the username of
-///     the [Credential] login information, *not* the `dgruft` [Account] username.
+///   the [Credential] login information, *not* the `dgruft` [Account] username.
 ///
 /// - `encrypted_password`: The [Encrypted] password of this [Credential]. This is the password of
-///     the [Credential] login information, *not* the `dgruft` [Account] username.
+///   the [Credential] login information, *not* the `dgruft` [Account] username.
 ///
 /// - `encrypted_notes`: The [Encrypted] notes of this [Credential]. These are user-defined notes
-///     related to the [Credential] information. They can be any text the user wants.
+///   related to the [Credential] information. They can be any text the user wants.
+///
+/// - `name_index`: The blind index of this [Credential]'s name, which lets it be looked up by name
+///   without decrypting the names of all its owner's [Credential]s. Names are unique per account,
+///   which the database enforces through the `name_index`. [None] for [Credential]s created
+///   before blind indexes were added, until their owner next logs in.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Credential {
+    id: String,
     owner_username: String,
     encrypted_name: Encrypted,
     encrypted_username: Encrypted,
     encrypted_password: Encrypted,
     encrypted_notes: Encrypted,
+    name_index: Option<Hash<32>>,
 }
 impl Credential {
-    /// Create a new [Credential].
+    /// Create a new [Credential] with a new, random `id`, encrypting its fields with the given
+    /// [CipherSuite].
     pub fn try_new(
         owner_username: &str,
-        encryption_key: Aes256Key,
+        encryption_key: &Aes256Key,
+        cipher_suite: CipherSuite,
+        name: &str,
+        username: &str,
+        password: &str,
+        notes: &str,
+    ) -> eyre::Result<Self> {
+        Self::try_new_with_id(
+            &Uuid::new_v4().to_string(),
+            owner_username,
+            encryption_key,
+            cipher_suite,
+            name,
+            username,
+            password,
+            notes,
+        )
+    }
+
+    /// Create a new [Credential] with the given `id`, encrypting its fields with the given
+    /// [CipherSuite].
+    #[allow(clippy::too_many_arguments)]
+    pub fn try_new_with_id(
+        id: &str,
+        owner_username: &str,
+        encryption_key: &Aes256Key,
+        cipher_suite: CipherSuite,
         name: &str,
         username: &str,
         password: &str,
         notes: &str,
     ) -> eyre::Result<Self> {
-        let encrypted_name = name.try_encrypt_with_key(encryption_key)?;
-        let encrypted_username = username.try_encrypt_with_key(encryption_key)?;
-        let encrypted_password = password.try_encrypt_with_key(encryption_key)?;
-        let encrypted_notes = notes.try_encrypt_with_key(encryption_key)?;
+        let encrypt = |value: &str, field: CredentialField| {
+            value.try_encrypt_with_suite(
+                encryption_key,
+                cipher_suite,
+                &Self::associated_data(owner_username, id, field),
+            )
+        };
+        let encrypted_name = encrypt(name, CredentialField::Name)?;
+        let encrypted_username = encrypt(username, CredentialField::Username)?;
+        let encrypted_password = encrypt(password, CredentialField::Password)?;
+        let encrypted_notes = encrypt(notes, CredentialField::Notes)?;
         Ok(Self {
+            id: id.to_owned(),
             owner_username: owner_username.to_owned(),
             encrypted_name,
             encrypted_username,
             encrypted_password,
             encrypted_notes,
+            name_index: Some(Self::compute_name_index(encryption_key, name)?),
         })
     }
 
     /// Create a [Credential] from its fields.
     pub fn from_fields(
+        id: String,
         owner_username: String,
         encrypted_name: Encrypted,
         encrypted_username: Encrypted,
         encrypted_password: Encrypted,
         encrypted_notes: Encrypted,
+        name_index: Option<Hash<32>>,
     ) -> Self {
         Self {
+            id,
             owner_username,
             encrypted_name,
             encrypted_username,
             encrypted_password,
             encrypted_notes,
+            name_index,
         }
     }
 
+    /// Compute the [blind_index] of a [Credential] name under the owner's `key`.
+    pub fn compute_name_index(key: &Aes256Key, name: &str) -> eyre::Result<Hash<32>> {
+        blind_index(key, NAME_INDEX_KEY_DOMAIN, name)
+    }
+
+    /// Encrypt a new value for the given field of this [Credential] with the given [CipherSuite].
+    pub fn encrypt_field(
+        &self,
+        key: &Aes256Key,
+        cipher_suite: CipherSuite,
+        field: CredentialField,
+        value: &str,
+    ) -> eyre::Result<Encrypted> {
+        value.try_encrypt_with_suite(
+            key,
+            cipher_suite,
+            &Self::associated_data(&self.owner_username, &self.id, field),
+        )
+    }
+
+    /// Get the `id` of this [Credential].
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
     /// Get the `owner_username` of this [Credential].
     pub fn owner_username(&self) -> &str {
         &self.owner_username
@@ -93,24 +202,254 @@ impl Credential {
         &self.encrypted_notes
     }
 
+    /// Get the `name_index` of this [Credential].
+    pub fn name_index(&self) -> Option<&Hash<32>> {
+        self.name_index.as_ref()
+    }
+
     /// Decrypt the `encrypted_name` of this [Credential].
-    pub fn name<T: TryFromEncrypted>(&self, key: Aes256Key) -> eyre::Result<T> {
-        T::try_decrypt(&self.encrypted_name, key)
+    pub fn name<T: TryFromEncrypted>(&self, key: &Aes256Key) -> eyre::Result<T> {
+        self.decrypt_field(key, CredentialField::Name)
     }
 
     /// Decrypt the `encrypted_username` of this [Credential].
-    pub fn username<T: TryFromEncrypted>(&self, key: Aes256Key) -> eyre::Result<T> {
-        T::try_decrypt(&self.encrypted_username, key)
+    pub fn username<T: TryFromEncrypted>(&self, key: &Aes256Key) -> eyre::Result<T> {
+        self.decrypt_field(key, CredentialField::Username)
     }
 
     /// Decrypt the `encrypted_password` of this [Credential].
-    pub fn password<T: TryFromEncrypted>(&self, key: Aes256Key) -> eyre::Result<T> {
-        T::try_decrypt(&self.encrypted_password, key)
+    pub fn password<T: TryFromEncrypted>(&self, key: &Aes256Key) -> eyre::Result<T> {
+        self.decrypt_field(key, CredentialField::Password)
     }
 
     /// Decrypt the `encrypted_notes` of this [Credential].
-    pub fn notes<T: TryFromEncrypted>(&self, key: Aes256Key) -> eyre::Result<T> {
-        T::try_decrypt(&self.encrypted_notes, key)
+    pub fn notes<T: TryFromEncrypted>(&self, key: &Aes256Key) -> eyre::Result<T> {
+        self.decrypt_field(key, CredentialField::Notes)
+    }
+
+    /// Decrypt the given field of this [Credential].
+    pub fn decrypt_field<T: TryFromEncrypted>(
+        &self,
+        key: &Aes256Key,
+        field: CredentialField,
+    ) -> eyre::Result<T> {
+        let encrypted = match field {
+            CredentialField::Name => &self.encrypted_name,
+            CredentialField::Username => &self.encrypted_username,
+            CredentialField::Password => &self.encrypted_password,
+            CredentialField::Notes => &self.encrypted_notes,
+        };
+        T::try_decrypt_with_aad(
+            encrypted,
+            key,
+            &Self::associated_data(&self.owner_username, &self.id, field),
+        )
+    }
+
+    // Helper function: Get the [AssociatedData] bytes of the given field.
+    fn associated_data(owner_username: &str, id: &str, field: CredentialField) -> Vec<u8> {
+        AssociatedData::new(owner_username, EntityType::Credential, field.as_str(), id).to_bytes()
+    }
+}
+
+/// A previous version of a [Credential]'s username, password & notes, kept when the [Credential]
+/// is updated so that it can be restored.
+///
+/// Each [Encrypted] field is bound to the `owner_username`, the `credential_id`, the `id` &
+/// the name of the field through its [AssociatedData], so versions can't be moved between
+/// [Credential]s.
+///
+/// ### Fields
+///
+/// - `id`: The unique id of this [CredentialVersion].
+///
+/// - `credential_id`: The `id` of the [Credential] this is a version of.
+///
+/// - `owner_username`: The username of the [Account] that owns the [Credential].
+///
+/// - `replaced_at`: The Unix time at which this version was replaced.
+///
+/// - `encrypted_username`: The [Encrypted] username of this version.
+///
+/// - `encrypted_password`: The [Encrypted] password of this version.
+///
+/// - `encrypted_notes`: The [Encrypted] notes of this version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CredentialVersion {
+    id: String,
+    credential_id: String,
+    owner_username: String,
+    replaced_at: u64,
+    encrypted_username: Encrypted,
+    encrypted_password: Encrypted,
+    encrypted_notes: Encrypted,
+}
+impl CredentialVersion {
+    /// Create a new [CredentialVersion] with a new, random `id` from the current username,
+    /// password & notes of the given [Credential], encrypting them with the given [CipherSuite].
+    pub fn try_new(
+        credential: &Credential,
+        encryption_key: &Aes256Key,
+        cipher_suite: CipherSuite,
+        replaced_at: u64,
+    ) -> eyre::Result<Self> {
+        Self::try_new_with_id(
+            &Uuid::new_v4().to_string(),
+            credential.id(),
+            credential.owner_username(),
+            encryption_key,
+            cipher_suite,
+            replaced_at,
+            credential
+                .username::<Secret<String>>(encryption_key)?
+                .expose(),
+            credential
+                .password::<Secret<String>>(encryption_key)?
+                .expose(),
+            credential.notes::<Secret<String>>(encryption_key)?.expose(),
+        )
+    }
+
+    /// Create a new [CredentialVersion] with the given `id`, encrypting its fields with the given
+    /// [CipherSuite].
+    #[allow(clippy::too_many_arguments)]
+    pub fn try_new_with_id(
+        id: &str,
+        credential_id: &str,
+        owner_username: &str,
+        encryption_key: &Aes256Key,
+        cipher_suite: CipherSuite,
+        replaced_at: u64,
+        username: &str,
+        password: &str,
+        notes: &str,
+    ) -> eyre::Result<Self> {
+        let encrypt = |value: &str, field: CredentialField| {
+            value.try_encrypt_with_suite(
+                encryption_key,
+                cipher_suite,
+                &Self::associated_data(owner_username, credential_id, id, field),
+            )
+        };
+        Ok(Self {
+            id: id.to_owned(),
+            credential_id: credential_id.to_owned(),
+            owner_username: owner_username.to_owned(),
+            replaced_at,
+            encrypted_username: encrypt(username, CredentialField::Username)?,
+            encrypted_password: encrypt(password, CredentialField::Password)?,
+            encrypted_notes: encrypt(notes, CredentialField::Notes)?,
+        })
+    }
+
+    /// Create a [CredentialVersion] from its fields.
+    pub fn from_fields(
+        id: String,
+        credential_id: String,
+        owner_username: String,
+        replaced_at: u64,
+        encrypted_username: Encrypted,
+        encrypted_password: Encrypted,
+        encrypted_notes: Encrypted,
+    ) -> Self {
+        Self {
+            id,
+            credential_id,
+            owner_username,
+            replaced_at,
+            encrypted_username,
+            encrypted_password,
+            encrypted_notes,
+        }
+    }
+
+    /// Get the `id` of this [CredentialVersion].
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Get the `credential_id` of this [CredentialVersion].
+    pub fn credential_id(&self) -> &str {
+        &self.credential_id
+    }
+
+    /// Get the `owner_username` of this [CredentialVersion].
+    pub fn owner_username(&self) -> &str {
+        &self.owner_username
+    }
+
+    /// Get the `replaced_at` time of this [CredentialVersion].
+    pub fn replaced_at(&self) -> u64 {
+        self.replaced_at
+    }
+
+    /// Get the `encrypted_username` of this [CredentialVersion].
+    pub fn encrypted_username(&self) -> &Encrypted {
+        &self.encrypted_username
+    }
+
+    /// Get the `encrypted_password` of this [CredentialVersion].
+    pub fn encrypted_password(&self) -> &Encrypted {
+        &self.encrypted_password
+    }
+
+    /// Get the `encrypted_notes` of this [CredentialVersion].
+    pub fn encrypted_notes(&self) -> &Encrypted {
+        &self.encrypted_notes
+    }
+
+    /// Decrypt the `encrypted_username` of this [CredentialVersion].
+    pub fn username<T: TryFromEncrypted>(&self, key: &Aes256Key) -> eyre::Result<T> {
+        self.decrypt_field(key, CredentialField::Username)
+    }
+
+    /// Decrypt the `encrypted_password` of this [CredentialVersion].
+    pub fn password<T: TryFromEncrypted>(&self, key: &Aes256Key) -> eyre::Result<T> {
+        self.decrypt_field(key, CredentialField::Password)
+    }
+
+    /// Decrypt the `encrypted_notes` of this [CredentialVersion].
+    pub fn notes<T: TryFromEncrypted>(&self, key: &Aes256Key) -> eyre::Result<T> {
+        self.decrypt_field(key, CredentialField::Notes)
+    }
+
+    /// Decrypt the given field of this [CredentialVersion]. Versions have no
+    /// [CredentialField::Name].
+    pub fn decrypt_field<T: TryFromEncrypted>(
+        &self,
+        key: &Aes256Key,
+        field: CredentialField,
+    ) -> eyre::Result<T> {
+        let encrypted = match field {
+            CredentialField::Name => {
+                return Err(eyre::eyre!("Credential versions don't keep their name."))
+            }
+            CredentialField::Username => &self.encrypted_username,
+            CredentialField::Password => &self.encrypted_password,
+            CredentialField::Notes => &self.encrypted_notes,
+        };
+        T::try_decrypt_with_aad(
+            encrypted,
+            key,
+            &Self::associated_data(&self.owner_username, &self.credential_id, &self.id, field),
+        )
+    }
+
+    // Helper function: Get the [AssociatedData] bytes of the given field. The item id names both
+    // the [Credential] & the version.
+    fn associated_data(
+        owner_username: &str,
+        credential_id: &str,
+        id: &str,
+        field: CredentialField,
+    ) -> Vec<u8> {
+        AssociatedData::new(
+            owner_username,
+            EntityType::CredentialVersion,
+            field.as_str(),
+            &format!("{}/{}", credential_id, id),
+        )
+        .to_bytes()
     }
 }
 
@@ -118,7 +457,7 @@ impl Credential {
 mod tests {
     use super::*;
 
-    use pretty_assertions::assert_eq;
+    use pretty_assertions::{assert_eq, assert_ne};
 
     use crate::backend::encryption::encrypted::new_rand_key;
 
@@ -133,7 +472,8 @@ mod tests {
         let key = new_rand_key();
         let creds = Credential::try_new(
             TEST_OWNER_USERNAME,
-            key,
+            &key,
+            CipherSuite::Aes256Gcm,
             TEST_NAME,
             TEST_USERNAME,
             TEST_PASSWORD,
@@ -142,12 +482,145 @@ mod tests {
         .unwrap();
 
         assert_eq!(TEST_OWNER_USERNAME, creds.owner_username());
-        assert_eq!(TEST_NAME, &creds.name::<String>(key).unwrap());
+        assert_eq!(
+            creds.encrypted_password().cipher_suite(),
+            CipherSuite::Aes256Gcm
+        );
+        assert_eq!(TEST_NAME, &creds.name::<String>(&key).unwrap());
         assert_eq!(
             TEST_USERNAME.as_bytes(),
-            &creds.username::<Vec<u8>>(key).unwrap()
+            &creds.username::<Vec<u8>>(&key).unwrap()
+        );
+        assert_eq!(TEST_PASSWORD, &creds.password::<String>(&key).unwrap());
+        assert_eq!(TEST_NOTES, &creds.notes::<String>(&key).unwrap());
+    }
+
+    #[test]
+    fn name_index() {
+        let key = new_rand_key();
+        let creds = Credential::try_new(
+            TEST_OWNER_USERNAME,
+            &key,
+            CipherSuite::default(),
+            TEST_NAME,
+            TEST_USERNAME,
+            TEST_PASSWORD,
+            TEST_NOTES,
+        )
+        .unwrap();
+
+        let name_index = Credential::compute_name_index(&key, TEST_NAME).unwrap();
+        assert_eq!(creds.name_index(), Some(&name_index));
+        assert_ne!(
+            Credential::compute_name_index(&key, "My Schploggy Login Info").unwrap(),
+            name_index
+        );
+        assert_ne!(
+            Credential::compute_name_index(&new_rand_key(), TEST_NAME).unwrap(),
+            name_index
+        );
+    }
+
+    #[test]
+    fn tampered_credential() {
+        let key = new_rand_key();
+        let creds = Credential::try_new(
+            TEST_OWNER_USERNAME,
+            &key,
+            CipherSuite::default(),
+            TEST_NAME,
+            TEST_USERNAME,
+            TEST_PASSWORD,
+            TEST_NOTES,
+        )
+        .unwrap();
+
+        // Swapped fields.
+        let swapped = Credential::from_fields(
+            creds.id().to_owned(),
+            creds.owner_username().to_owned(),
+            creds.encrypted_name().clone(),
+            creds.encrypted_username().clone(),
+            creds.encrypted_notes().clone(),
+            creds.encrypted_password().clone(),
+            creds.name_index().copied(),
+        );
+        swapped.name::<String>(&key).unwrap();
+        let _ = swapped.password::<String>(&key).unwrap_err();
+        let _ = swapped.notes::<String>(&key).unwrap_err();
+
+        // Different owner.
+        let moved = Credential::from_fields(
+            creds.id().to_owned(),
+            "someone_else".to_owned(),
+            creds.encrypted_name().clone(),
+            creds.encrypted_username().clone(),
+            creds.encrypted_password().clone(),
+            creds.encrypted_notes().clone(),
+            creds.name_index().copied(),
+        );
+        let _ = moved.name::<String>(&key).unwrap_err();
+
+        // Different id.
+        let moved = Credential::from_fields(
+            Uuid::new_v4().to_string(),
+            creds.owner_username().to_owned(),
+            creds.encrypted_name().clone(),
+            creds.encrypted_username().clone(),
+            creds.encrypted_password().clone(),
+            creds.encrypted_notes().clone(),
+            creds.name_index().copied(),
+        );
+        let _ = moved.name::<String>(&key).unwrap_err();
+    }
+
+    #[test]
+    fn credential_version() {
+        let key = new_rand_key();
+        let creds = Credential::try_new(
+            TEST_OWNER_USERNAME,
+            &key,
+            CipherSuite::default(),
+            TEST_NAME,
+            TEST_USERNAME,
+            TEST_PASSWORD,
+            TEST_NOTES,
+        )
+        .unwrap();
+
+        let version =
+            CredentialVersion::try_new(&creds, &key, CipherSuite::default(), 1234).unwrap();
+        assert_eq!(version.credential_id(), creds.id());
+        assert_eq!(version.replaced_at(), 1234);
+        assert_eq!(TEST_USERNAME, &version.username::<String>(&key).unwrap());
+        assert_eq!(TEST_PASSWORD, &version.password::<String>(&key).unwrap());
+        assert_eq!(TEST_NOTES, &version.notes::<String>(&key).unwrap());
+        let _ = version
+            .decrypt_field::<String>(&key, CredentialField::Name)
+            .unwrap_err();
+
+        // A version can't be moved to another credential.
+        let moved = CredentialVersion::from_fields(
+            version.id().to_owned(),
+            Uuid::new_v4().to_string(),
+            version.owner_username().to_owned(),
+            version.replaced_at(),
+            version.encrypted_username().clone(),
+            version.encrypted_password().clone(),
+            version.encrypted_notes().clone(),
+        );
+        let _ = moved.password::<String>(&key).unwrap_err();
+
+        // The credential's own fields don't decrypt as a version's.
+        let swapped = CredentialVersion::from_fields(
+            version.id().to_owned(),
+            version.credential_id().to_owned(),
+            version.owner_username().to_owned(),
+            version.replaced_at(),
+            creds.encrypted_username().clone(),
+            creds.encrypted_password().clone(),
+            creds.encrypted_notes().clone(),
         );
-        assert_eq!(TEST_PASSWORD, &creds.password::<String>(key).unwrap());
-        assert_eq!(TEST_NOTES, &creds.notes::<String>(key).unwrap());
+        let _ = swapped.password::<String>(&key).unwrap_err();
     }
 }
@@ -1,2 +1,5 @@
+pub mod associated_data;
+pub mod cipher_suite;
 pub mod encrypted;
+pub mod stream;
 pub mod traits;
@@ -0,0 +1,121 @@
+//! Functionality related to the associated data which binds an [Encrypted] to its context.
+use std::{fmt, str::FromStr};
+
+use color_eyre::eyre::{self, eyre};
+
+/// All the types of entities whose fields are [Encrypted] by `dgruft`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EntityType {
+    /// An account. Its only encrypted field is its `key`.
+    Account,
+    /// A credential.
+    Credential,
+    /// A file. Its encrypted fields are its name & contents.
+    File,
+    /// A previous version of a credential. Its encrypted fields are the credential's username,
+    /// password & notes.
+    CredentialVersion,
+}
+impl EntityType {
+    /// Return the name of this [EntityType] as it appears in [AssociatedData].
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Account => "account",
+            Self::Credential => "credential",
+            Self::File => "file",
+            Self::CredentialVersion => "credential version",
+        }
+    }
+}
+impl fmt::Display for EntityType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+impl FromStr for EntityType {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> eyre::Result<Self> {
+        match s {
+            "account" => Ok(Self::Account),
+            "credential" => Ok(Self::Credential),
+            "file" => Ok(Self::File),
+            "credential version" => Ok(Self::CredentialVersion),
+            other => Err(eyre!("Unknown entity type \"{}\".", other)),
+        }
+    }
+}
+
+/// The context of an [Encrypted] field. Its bytes are passed as associated data to the AEAD
+/// cipher, so an [Encrypted] only decrypts in the context it was encrypted in.
+///
+/// ### Fields
+///
+/// - `owner_username`: The username of the account which owns the [Encrypted].
+///
+/// - `entity_type`: The [EntityType] of the entity which the [Encrypted] is a field of.
+///
+/// - `field`: The name of the [Encrypted] field, e.g., `"password"`.
+///
+/// - `item_id`: The stable id of the entity which the [Encrypted] is a field of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssociatedData<'a> {
+    owner_username: &'a str,
+    entity_type: EntityType,
+    field: &'a str,
+    item_id: &'a str,
+}
+impl<'a> AssociatedData<'a> {
+    /// Prefix of the encoded [AssociatedData]. Changing the encoding requires a new version.
+    const VERSION_TAG: &'static [u8] = b"dgruft-aad-v1";
+
+    /// Create new [AssociatedData].
+    pub fn new(
+        owner_username: &'a str,
+        entity_type: EntityType,
+        field: &'a str,
+        item_id: &'a str,
+    ) -> Self {
+        Self {
+            owner_username,
+            entity_type,
+            field,
+            item_id,
+        }
+    }
+
+    /// Encode this [AssociatedData] as bytes.
+    ///
+    /// Every component is prefixed with its length, so no two different [AssociatedData] share an
+    /// encoding.
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for component in [
+            Self::VERSION_TAG,
+            self.owner_username.as_bytes(),
+            self.entity_type.as_str().as_bytes(),
+            self.field.as_bytes(),
+            self.item_id.as_bytes(),
+        ] {
+            bytes.extend_from_slice(&(component.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(component);
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_ne;
+
+    use super::*;
+
+    #[test]
+    fn unambiguous_encoding() {
+        let aad_1 = AssociatedData::new("ab", EntityType::Credential, "c", "id");
+        let aad_2 = AssociatedData::new("a", EntityType::Credential, "bc", "id");
+        let aad_3 = AssociatedData::new("ab", EntityType::File, "c", "id");
+        assert_ne!(aad_1.to_bytes(), aad_2.to_bytes());
+        assert_ne!(aad_1.to_bytes(), aad_3.to_bytes());
+    }
+}
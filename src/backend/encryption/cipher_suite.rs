@@ -0,0 +1,85 @@
+//! Functionality related to the [CipherSuite] enum.
+use std::{fmt, str::FromStr};
+
+use aes_gcm::{
+    aead::{AeadCore, OsRng},
+    Aes256Gcm,
+};
+use chacha20poly1305::XChaCha20Poly1305;
+use color_eyre::eyre::{self, eyre};
+
+use super::encrypted::Nonce;
+
+/// All the AEAD ciphers supported by `dgruft`. Every [CipherSuite] uses a 32-byte key.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CipherSuite {
+    /// AES-256-GCM, with 96-bit nonces. Used by all data encrypted before XChaCha20-Poly1305
+    /// support was added.
+    Aes256Gcm,
+    /// XChaCha20-Poly1305, with 192-bit nonces. Random nonces this long are safe to use for any
+    /// number of encryptions under the same key.
+    #[default]
+    XChaCha20Poly1305,
+}
+impl CipherSuite {
+    /// Return the name under which this [CipherSuite] is stored in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Aes256Gcm => "aes-256-gcm",
+            Self::XChaCha20Poly1305 => "xchacha20-poly1305",
+        }
+    }
+
+    /// Generate a random [Nonce] for this [CipherSuite].
+    pub fn new_rand_nonce(&self) -> Nonce {
+        match self {
+            Self::Aes256Gcm => Nonce::Aes256Gcm(Aes256Gcm::generate_nonce(&mut OsRng).into()),
+            Self::XChaCha20Poly1305 => {
+                Nonce::XChaCha20Poly1305(XChaCha20Poly1305::generate_nonce(&mut OsRng).into())
+            }
+        }
+    }
+}
+impl fmt::Display for CipherSuite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+impl FromStr for CipherSuite {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> eyre::Result<Self> {
+        match s {
+            "aes-256-gcm" => Ok(Self::Aes256Gcm),
+            "xchacha20-poly1305" => Ok(Self::XChaCha20Poly1305),
+            other => Err(eyre!("Unknown cipher suite \"{}\".", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn nonce_lengths() {
+        assert_eq!(CipherSuite::Aes256Gcm.new_rand_nonce().as_bytes().len(), 12);
+        assert_eq!(
+            CipherSuite::XChaCha20Poly1305
+                .new_rand_nonce()
+                .as_bytes()
+                .len(),
+            24
+        );
+        for cipher_suite in [CipherSuite::Aes256Gcm, CipherSuite::XChaCha20Poly1305] {
+            assert_eq!(cipher_suite.new_rand_nonce().cipher_suite(), cipher_suite);
+            assert_eq!(
+                cipher_suite.as_str().parse::<CipherSuite>().unwrap(),
+                cipher_suite
+            );
+        }
+        let _ = "aes-128-gcm".parse::<CipherSuite>().unwrap_err();
+    }
+}
@@ -1,48 +1,152 @@
 //! Functionality related to the [Encrypted] struct.
+use std::fmt;
+
 use aes_gcm::{
-    aead::{Aead, KeyInit, OsRng},
+    aead::{rand_core::RngCore, Aead, KeyInit, OsRng, Payload},
     Aes256Gcm,
 };
+use chacha20poly1305::XChaCha20Poly1305;
 use color_eyre::eyre::{self, eyre};
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 /// Re-export traits.
 pub use super::traits::*;
 
-/// A 12-byte nonce used for AES-256 encryption and decryption.
+use super::cipher_suite::CipherSuite;
+
+/// A 12-byte nonce used for AES-256-GCM encryption and decryption.
 pub type Aes256Nonce = [u8; 12];
 
-/// A 32-byte key used for AES-256 encryption and decryption.
-pub type Aes256Key = [u8; 32];
+/// A 24-byte nonce used for XChaCha20-Poly1305 encryption and decryption.
+pub type XChaCha20Nonce = [u8; 24];
+
+/// A nonce for one of the [CipherSuite]s. The variant records which [CipherSuite] it belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Nonce {
+    /// A nonce for [CipherSuite::Aes256Gcm].
+    Aes256Gcm(Aes256Nonce),
+    /// A nonce for [CipherSuite::XChaCha20Poly1305].
+    XChaCha20Poly1305(XChaCha20Nonce),
+}
+impl Nonce {
+    /// Create a [Nonce] for the given [CipherSuite] from its bytes.
+    pub fn from_bytes(cipher_suite: CipherSuite, bytes: &[u8]) -> eyre::Result<Self> {
+        let wrong_length = || {
+            eyre!(
+                "Nonce: Expected a {} nonce, got {} bytes.",
+                cipher_suite,
+                bytes.len()
+            )
+        };
+        match cipher_suite {
+            CipherSuite::Aes256Gcm => Ok(Self::Aes256Gcm(
+                bytes.try_into().map_err(|_| wrong_length())?,
+            )),
+            CipherSuite::XChaCha20Poly1305 => Ok(Self::XChaCha20Poly1305(
+                bytes.try_into().map_err(|_| wrong_length())?,
+            )),
+        }
+    }
+
+    /// Return the [CipherSuite] this [Nonce] belongs to.
+    pub fn cipher_suite(&self) -> CipherSuite {
+        match self {
+            Self::Aes256Gcm(_) => CipherSuite::Aes256Gcm,
+            Self::XChaCha20Poly1305(_) => CipherSuite::XChaCha20Poly1305,
+        }
+    }
+
+    /// Return the bytes of this [Nonce].
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Aes256Gcm(nonce) => nonce,
+            Self::XChaCha20Poly1305(nonce) => nonce,
+        }
+    }
+}
+
+/// A 32-byte key used for encryption and decryption by every [CipherSuite].
+///
+/// The key is zeroed when dropped, and is never shown by its [fmt::Debug] implementation. It is
+/// deliberately not [Copy]; pass it by reference.
+#[derive(Clone, PartialEq, Eq, Zeroize, ZeroizeOnDrop)]
+pub struct Aes256Key([u8; 32]);
+impl Aes256Key {
+    /// Create an [Aes256Key] from its bytes.
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Return the bytes of this [Aes256Key].
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+impl fmt::Debug for Aes256Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Aes256Key(<redacted>)")
+    }
+}
 
 /// An encrypted byte array.
+///
+/// The [Nonce] records the [CipherSuite] used to encrypt the cipherbytes, so data encrypted with
+/// different [CipherSuite]s can be decrypted alongside each other.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Encrypted {
     cipherbytes: Vec<u8>,
-    nonce: Aes256Nonce,
+    nonce: Nonce,
 }
 impl Encrypted {
-    /// Encrypt a byte slice using a given [Aes256Key] and [Aes256Nonce].
+    /// Encrypt a byte slice using a given [Aes256Key], [Nonce], and associated data. The [Nonce]
+    /// determines the [CipherSuite] used.
+    ///
+    /// The associated data is authenticated but not encrypted. The same associated data must be
+    /// given to [Encrypted::try_decrypt_bytes] for decryption to succeed.
     pub fn try_encrypt_bytes_key_nonce(
         byte_slice: &[u8],
-        key: Aes256Key,
-        nonce: Aes256Nonce,
+        key: &Aes256Key,
+        nonce: Nonce,
+        aad: &[u8],
     ) -> eyre::Result<Encrypted> {
-        let cipher = Aes256Gcm::new(&key.into());
-        match cipher.encrypt(&nonce.into(), byte_slice) {
+        let payload = Payload {
+            msg: byte_slice,
+            aad,
+        };
+        let encrypt_result = match nonce {
+            Nonce::Aes256Gcm(nonce) => {
+                Aes256Gcm::new(key.as_bytes().into()).encrypt(&nonce.into(), payload)
+            }
+            Nonce::XChaCha20Poly1305(nonce) => {
+                XChaCha20Poly1305::new(key.as_bytes().into()).encrypt(&nonce.into(), payload)
+            }
+        };
+        match encrypt_result {
             Ok(cipherbytes) => Ok(Self { cipherbytes, nonce }),
             Err(err) => Err(eyre!("{err:?}")),
         }
     }
 
     /// Create an [Encrypted] from its fields.
-    pub fn from_fields(cipherbytes: Vec<u8>, nonce: Aes256Nonce) -> Self {
+    pub fn from_fields(cipherbytes: Vec<u8>, nonce: Nonce) -> Self {
         Self { cipherbytes, nonce }
     }
 
-    /// Decrypt this [Encrypted] into a byte vector.
-    pub fn try_decrypt_bytes(&self, key: Aes256Key) -> eyre::Result<Vec<u8>> {
-        let cipher = Aes256Gcm::new(&key.into());
-        match cipher.decrypt(&self.nonce.into(), &self.cipherbytes[..]) {
+    /// Decrypt this [Encrypted] into a byte vector, authenticating the given associated data.
+    pub fn try_decrypt_bytes(&self, key: &Aes256Key, aad: &[u8]) -> eyre::Result<Vec<u8>> {
+        let payload = Payload {
+            msg: &self.cipherbytes[..],
+            aad,
+        };
+        let decrypt_result = match self.nonce {
+            Nonce::Aes256Gcm(nonce) => {
+                Aes256Gcm::new(key.as_bytes().into()).decrypt(&nonce.into(), payload)
+            }
+            Nonce::XChaCha20Poly1305(nonce) => {
+                XChaCha20Poly1305::new(key.as_bytes().into()).decrypt(&nonce.into(), payload)
+            }
+        };
+        match decrypt_result {
             Ok(byte_vec) => Ok(byte_vec),
             Err(err) => Err(eyre!("{err:?}")),
         }
@@ -53,15 +157,22 @@ impl Encrypted {
         &self.cipherbytes
     }
 
-    /// Return the [Aes256Nonce] of this [Encrypted].
-    pub fn nonce(&self) -> Aes256Nonce {
+    /// Return the [Nonce] of this [Encrypted].
+    pub fn nonce(&self) -> Nonce {
         self.nonce
     }
+
+    /// Return the [CipherSuite] used to encrypt this [Encrypted].
+    pub fn cipher_suite(&self) -> CipherSuite {
+        self.nonce.cipher_suite()
+    }
 }
 
 /// Generate a random [Aes256Key].
 pub fn new_rand_key() -> Aes256Key {
-    Aes256Gcm::generate_key(&mut OsRng).into()
+    let mut key = Aes256Key([0u8; 32]);
+    OsRng.fill_bytes(&mut key.0);
+    key
 }
 
 #[cfg(test)]
@@ -69,7 +180,10 @@ mod tests {
     use camino::{Utf8Path, Utf8PathBuf};
     use pretty_assertions::assert_eq;
 
-    use super::super::traits::*;
+    use super::{
+        super::{cipher_suite::CipherSuite, traits::*},
+        new_rand_key, Encrypted,
+    };
 
     #[test]
     fn aes_256_consistency() {
@@ -78,24 +192,33 @@ mod tests {
         let (encrypted_1, key) = test_string.clone().try_encrypt_new_key().unwrap();
         let encrypted_2 = test_string
             .clone()
-            .try_encrypt_with_both(key, encrypted_1.nonce())
+            .try_encrypt_with_both(&key, encrypted_1.nonce())
             .unwrap();
 
         assert_eq!(encrypted_1, encrypted_2);
 
-        let decrypted_1 = String::try_decrypt(&encrypted_1, key).unwrap();
-        let decrypted_2 = String::try_decrypt(&encrypted_2, key).unwrap();
+        let decrypted_1 = String::try_decrypt(&encrypted_1, &key).unwrap();
+        let decrypted_2 = String::try_decrypt(&encrypted_2, &key).unwrap();
 
         assert_eq!(decrypted_1, test_string);
         assert_eq!(decrypted_1, decrypted_2);
     }
 
+    #[test]
+    fn redacted_key() {
+        let key = new_rand_key();
+        let key_bytes = format!("{:?}", key.as_bytes());
+        assert!(!format!("{key:?}").contains(&key_bytes));
+        assert!(!format!("{key:#?}").contains(&key_bytes));
+        assert_eq!(format!("{key:?}"), "Aes256Key(<redacted>)");
+    }
+
     #[test]
     fn check_utf8() {
         let test_str = "您好!";
 
         let (encrypted, key) = test_str.try_encrypt_new_key().unwrap();
-        let decrypted = Vec::<u8>::try_decrypt(&encrypted, key).unwrap();
+        let decrypted = Vec::<u8>::try_decrypt(&encrypted, &key).unwrap();
 
         assert_eq!(test_str.as_bytes(), decrypted);
         assert_eq!(test_str, std::str::from_utf8(&decrypted).unwrap());
@@ -108,14 +231,63 @@ mod tests {
 
         let (encrypted_1, key) = test_path_buf.clone().try_encrypt_new_key().unwrap();
         let encrypted_2 = test_path
-            .try_encrypt_with_both(key, encrypted_1.nonce())
+            .try_encrypt_with_both(&key, encrypted_1.nonce())
             .unwrap();
 
         assert_eq!(encrypted_1, encrypted_2);
 
-        let decrypted_1: &[u8] = &Vec::<u8>::try_decrypt(&encrypted_1, key).unwrap();
-        let decrypted_2: &[u8] = &Vec::<u8>::try_decrypt(&encrypted_2, key).unwrap();
+        let decrypted_1: &[u8] = &Vec::<u8>::try_decrypt(&encrypted_1, &key).unwrap();
+        let decrypted_2: &[u8] = &Vec::<u8>::try_decrypt(&encrypted_2, &key).unwrap();
 
         assert_eq!(decrypted_1, decrypted_2);
     }
+
+    #[test]
+    fn associated_data() {
+        let test_str = "top secret";
+        let aad = b"dgruft test aad";
+
+        let (encrypted, key) = test_str.try_encrypt_new_key().unwrap();
+        let _ = String::try_decrypt_with_aad(&encrypted, &key, aad).unwrap_err();
+
+        let encrypted = test_str.try_encrypt_with_aad(&key, aad).unwrap();
+        assert_eq!(
+            String::try_decrypt_with_aad(&encrypted, &key, aad).unwrap(),
+            test_str
+        );
+        let _ = String::try_decrypt(&encrypted, &key).unwrap_err();
+        let _ = String::try_decrypt_with_aad(&encrypted, &key, b"dgruft test aae").unwrap_err();
+    }
+
+    #[test]
+    fn cipher_suites() {
+        let test_str = "the same key, two ciphers";
+        let aad = b"dgruft test aad";
+        let key = new_rand_key();
+
+        let aes = test_str
+            .try_encrypt_with_suite(&key, CipherSuite::Aes256Gcm, aad)
+            .unwrap();
+        let xchacha = test_str
+            .try_encrypt_with_suite(&key, CipherSuite::XChaCha20Poly1305, aad)
+            .unwrap();
+        assert_eq!(aes.cipher_suite(), CipherSuite::Aes256Gcm);
+        assert_eq!(xchacha.cipher_suite(), CipherSuite::XChaCha20Poly1305);
+
+        assert_eq!(
+            String::try_decrypt_with_aad(&aes, &key, aad).unwrap(),
+            test_str
+        );
+        assert_eq!(
+            String::try_decrypt_with_aad(&xchacha, &key, aad).unwrap(),
+            test_str
+        );
+
+        // Decryption dispatches on the nonce, so a nonce can't be reinterpreted as another cipher.
+        let mismatched = Encrypted::from_fields(
+            xchacha.cipherbytes().to_vec(),
+            CipherSuite::Aes256Gcm.new_rand_nonce(),
+        );
+        let _ = String::try_decrypt_with_aad(&mismatched, &key, aad).unwrap_err();
+    }
 }
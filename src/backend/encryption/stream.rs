@@ -0,0 +1,410 @@
+//! Functionality related to chunked, streaming encryption of large contents.
+//!
+//! Contents are split into chunks of a fixed size, each of which is encrypted as its own AEAD
+//! message, so only a couple of chunks are ever held in memory at once. This is the STREAM
+//! construction of [Online Authenticated-Encryption and its Nonce-Reuse
+//! Misuse-Resistance](https://eprint.iacr.org/2015/189.pdf).
+//!
+//! The stream's header [Nonce] is stored like any other [Nonce]. The chunks aren't encrypted with
+//! the given [Aes256Key] itself, but with a key derived from it & the header [Nonce] with
+//! HKDF-SHA256, so every stream has its own key. The [Nonce] of each chunk is the header [Nonce]
+//! with its last 5 bytes replaced by the 32-bit big-endian index of the chunk & a flag which is set
+//! only for the last chunk. Reordered chunks therefore fail to decrypt, as do truncated streams,
+//! whose new last chunk wasn't encrypted as the last one.
+//!
+//! Deriving a key per stream keeps the chunk [Nonce]s of different streams from colliding, even
+//! though only the first 7 bytes of an AES-256-GCM header [Nonce] are left random.
+use std::io::{self, Read, Write};
+
+use color_eyre::eyre::{self, eyre};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use super::encrypted::{Aes256Key, Encrypted, Nonce};
+
+/// The default size of each plaintext chunk, in bytes.
+pub const DEFAULT_CHUNK_SIZE: u32 = 64 * 1024;
+
+// The number of bytes each chunk grows by when encrypted.
+const TAG_SIZE: usize = 16;
+
+// The size of the buffer used by [copy_secret].
+const COPY_BUFFER_SIZE: usize = 8 * 1024;
+
+// The HKDF info of the key each stream's chunks are encrypted with.
+const STREAM_KEY_INFO: &[u8] = b"dgruft stream key";
+
+/// A [Write]r which encrypts everything written to it as a stream, writing the encrypted chunks
+/// to the inner [Write]r.
+///
+/// [StreamEncryptor::finish] must be called once everything has been written. Otherwise, the last
+/// chunk is never written, and the stream fails to decrypt as if it were truncated.
+pub struct StreamEncryptor<W: Write> {
+    writer: W,
+    key: Aes256Key,
+    nonce: Nonce,
+    aad: Vec<u8>,
+    chunk_size: usize,
+    chunk: Zeroizing<Vec<u8>>,
+    index: u32,
+}
+impl<W: Write> StreamEncryptor<W> {
+    /// Create a new [StreamEncryptor] using a given [Aes256Key], header [Nonce], chunk size, and
+    /// associated data.
+    pub fn new(
+        writer: W,
+        key: &Aes256Key,
+        nonce: Nonce,
+        chunk_size: u32,
+        aad: &[u8],
+    ) -> eyre::Result<Self> {
+        let chunk_size = validate_chunk_size(chunk_size)?;
+        Ok(Self {
+            writer,
+            key: stream_key(key, nonce)?,
+            nonce,
+            aad: aad.to_vec(),
+            chunk_size,
+            chunk: Zeroizing::new(Vec::with_capacity(chunk_size)),
+            index: 0,
+        })
+    }
+
+    /// Encrypt & write the last chunk, returning the inner [Write]r.
+    pub fn finish(mut self) -> eyre::Result<W> {
+        self.write_chunk(true)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    // Helper function: Encrypt & write the buffered chunk.
+    fn write_chunk(&mut self, is_last: bool) -> eyre::Result<()> {
+        let encrypted_chunk = Encrypted::try_encrypt_bytes_key_nonce(
+            &self.chunk,
+            &self.key,
+            chunk_nonce(self.nonce, self.index, is_last)?,
+            &self.aad,
+        )?;
+        self.writer.write_all(encrypted_chunk.cipherbytes())?;
+        self.chunk.clear();
+        self.index = self
+            .index
+            .checked_add(1)
+            .ok_or_else(|| eyre!("Stream: Too many chunks."))?;
+        Ok(())
+    }
+}
+impl<W: Write> Write for StreamEncryptor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // A full chunk is only written once more data arrives, as until then it may be the last.
+        if self.chunk.len() == self.chunk_size {
+            self.write_chunk(false).map_err(io::Error::other)?;
+        }
+        let num_bytes = buf.len().min(self.chunk_size - self.chunk.len());
+        self.chunk.extend_from_slice(&buf[..num_bytes]);
+        Ok(num_bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// A [Read]er which decrypts a stream read from the inner [Read]er.
+///
+/// Each chunk can be read as soon as it is authenticated. If the stream turns out to be truncated
+/// or tampered with, reading fails, but the chunks before the bad one have already been read.
+pub struct StreamDecryptor<R: Read> {
+    reader: R,
+    key: Aes256Key,
+    nonce: Nonce,
+    aad: Vec<u8>,
+    encrypted_chunk_size: usize,
+    next_encrypted_chunk: Vec<u8>,
+    chunk: Zeroizing<Vec<u8>>,
+    position: usize,
+    index: u32,
+    is_finished: bool,
+}
+impl<R: Read> StreamDecryptor<R> {
+    /// Create a new [StreamDecryptor] using a given [Aes256Key], header [Nonce], chunk size, and
+    /// associated data.
+    pub fn new(
+        mut reader: R,
+        key: &Aes256Key,
+        nonce: Nonce,
+        chunk_size: u32,
+        aad: &[u8],
+    ) -> eyre::Result<Self> {
+        let encrypted_chunk_size = validate_chunk_size(chunk_size)? + TAG_SIZE;
+        let mut next_encrypted_chunk = Vec::with_capacity(encrypted_chunk_size);
+        read_chunk(&mut reader, &mut next_encrypted_chunk, encrypted_chunk_size)?;
+        Ok(Self {
+            reader,
+            key: stream_key(key, nonce)?,
+            nonce,
+            aad: aad.to_vec(),
+            encrypted_chunk_size,
+            next_encrypted_chunk,
+            chunk: Zeroizing::new(vec![]),
+            position: 0,
+            index: 0,
+            is_finished: false,
+        })
+    }
+
+    // Helper function: Read & decrypt the next chunk.
+    fn read_next_chunk(&mut self) -> eyre::Result<()> {
+        let encrypted_chunk = std::mem::replace(
+            &mut self.next_encrypted_chunk,
+            Vec::with_capacity(self.encrypted_chunk_size),
+        );
+        // Look ahead to find out whether this is the last chunk.
+        read_chunk(
+            &mut self.reader,
+            &mut self.next_encrypted_chunk,
+            self.encrypted_chunk_size,
+        )?;
+        let is_last = self.next_encrypted_chunk.is_empty();
+
+        let encrypted_chunk = Encrypted::from_fields(
+            encrypted_chunk,
+            chunk_nonce(self.nonce, self.index, is_last)?,
+        );
+        self.chunk = match encrypted_chunk.try_decrypt_bytes(&self.key, &self.aad) {
+            Ok(chunk) => Zeroizing::new(chunk),
+            Err(_) => {
+                return Err(eyre!(
+                    "Stream: Failed to decrypt chunk {}. The stream may be truncated, reordered, \
+                    or tampered with.",
+                    self.index
+                ))
+            }
+        };
+        self.position = 0;
+        self.is_finished = is_last;
+        self.index = self
+            .index
+            .checked_add(1)
+            .ok_or_else(|| eyre!("Stream: Too many chunks."))?;
+        Ok(())
+    }
+}
+impl<R: Read> Read for StreamDecryptor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.chunk.len() {
+            if self.is_finished || buf.is_empty() {
+                return Ok(0);
+            }
+            self.read_next_chunk()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        }
+        let num_bytes = buf.len().min(self.chunk.len() - self.position);
+        buf[..num_bytes].copy_from_slice(&self.chunk[self.position..][..num_bytes]);
+        self.position += num_bytes;
+        Ok(num_bytes)
+    }
+}
+
+/// Copy everything from the `reader` to the `writer` like [io::copy], zeroizing the buffer used.
+/// Return the number of bytes copied.
+pub fn copy_secret<R, W>(reader: &mut R, writer: &mut W) -> io::Result<u64>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+{
+    let mut buffer = Zeroizing::new(vec![0u8; COPY_BUFFER_SIZE]);
+    let mut num_bytes = 0;
+    loop {
+        let len = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(num_bytes),
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        writer.write_all(&buffer[..len])?;
+        num_bytes += len as u64;
+    }
+}
+
+// Helper function: Ensure the chunk size is usable.
+fn validate_chunk_size(chunk_size: u32) -> eyre::Result<usize> {
+    match chunk_size {
+        0 => Err(eyre!("Stream: The chunk size must be greater than 0.")),
+        chunk_size => Ok(chunk_size as usize),
+    }
+}
+
+// Helper function: Read up to `max_len` bytes into the cleared buffer, stopping early only at the
+// end of the reader.
+fn read_chunk<R>(reader: &mut R, buffer: &mut Vec<u8>, max_len: usize) -> eyre::Result<()>
+where
+    R: Read,
+{
+    buffer.clear();
+    reader.take(max_len as u64).read_to_end(buffer)?;
+    Ok(())
+}
+
+// Helper function: Derive the key a stream's chunks are encrypted with from the given key & the
+// stream's header [Nonce].
+fn stream_key(key: &Aes256Key, nonce: Nonce) -> eyre::Result<Aes256Key> {
+    let mut stream_key_bytes = Zeroizing::new([0; 32]);
+    Hkdf::<Sha256>::new(Some(nonce.as_bytes()), key.as_bytes())
+        .expand(STREAM_KEY_INFO, stream_key_bytes.as_mut())
+        .map_err(|_| eyre!("Stream: Failed to derive the stream key."))?;
+    Ok(Aes256Key::from_bytes(*stream_key_bytes))
+}
+
+// Helper function: Derive the [Nonce] of a chunk from the header [Nonce].
+fn chunk_nonce(nonce: Nonce, index: u32, is_last: bool) -> eyre::Result<Nonce> {
+    let mut nonce_bytes = nonce.as_bytes().to_vec();
+    let counter_start = nonce_bytes.len() - 5;
+    nonce_bytes[counter_start..counter_start + 4].copy_from_slice(&index.to_be_bytes());
+    nonce_bytes[counter_start + 4] = is_last as u8;
+    Nonce::from_bytes(nonce.cipher_suite(), &nonce_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::{assert_eq, assert_ne};
+
+    use super::{
+        super::{cipher_suite::CipherSuite, encrypted::new_rand_key},
+        *,
+    };
+
+    fn encrypt(plaintext: &[u8], key: &Aes256Key, nonce: Nonce, chunk_size: u32) -> Vec<u8> {
+        let mut encryptor = StreamEncryptor::new(vec![], key, nonce, chunk_size, b"aad").unwrap();
+        copy_secret(&mut &plaintext[..], &mut encryptor).unwrap();
+        encryptor.finish().unwrap()
+    }
+
+    fn decrypt(
+        ciphertext: &[u8],
+        key: &Aes256Key,
+        nonce: Nonce,
+        chunk_size: u32,
+        aad: &[u8],
+    ) -> eyre::Result<Vec<u8>> {
+        let mut decryptor = StreamDecryptor::new(ciphertext, key, nonce, chunk_size, aad)?;
+        let mut plaintext = vec![];
+        copy_secret(&mut decryptor, &mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    fn round_trip(plaintext: &[u8], chunk_size: u32) {
+        for cipher_suite in [CipherSuite::Aes256Gcm, CipherSuite::XChaCha20Poly1305] {
+            let key = new_rand_key();
+            let nonce = cipher_suite.new_rand_nonce();
+            let ciphertext = encrypt(plaintext, &key, nonce, chunk_size);
+            if !plaintext.is_empty() {
+                assert_ne!(&ciphertext[..plaintext.len()], plaintext);
+            }
+
+            let decrypted = decrypt(&ciphertext, &key, nonce, chunk_size, b"aad").unwrap();
+            assert_eq!(decrypted, plaintext);
+
+            let _ = decrypt(&ciphertext, &key, nonce, chunk_size, b"other").unwrap_err();
+            let _ = decrypt(&ciphertext, &new_rand_key(), nonce, chunk_size, b"aad").unwrap_err();
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        round_trip(b"", 4);
+        round_trip(b"abc", 4);
+        round_trip(b"abcd", 4);
+        round_trip(b"abcdefghij", 4);
+        round_trip("dgruft很酷。".repeat(10000).as_bytes(), DEFAULT_CHUNK_SIZE);
+        assert!(StreamEncryptor::new(
+            vec![],
+            &new_rand_key(),
+            CipherSuite::default().new_rand_nonce(),
+            0,
+            &[],
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn stream_keys() {
+        let key = new_rand_key();
+        let chunk_size = 4;
+        let encrypted_chunk_size = chunk_size as usize + TAG_SIZE;
+        for cipher_suite in [CipherSuite::Aes256Gcm, CipherSuite::XChaCha20Poly1305] {
+            // Header nonces which only differ in the bytes replaced by the chunk index give the
+            // same chunk nonces, but each stream has its own key.
+            let nonce = cipher_suite.new_rand_nonce();
+            let mut other_nonce_bytes = nonce.as_bytes().to_vec();
+            *other_nonce_bytes.last_mut().unwrap() ^= 1;
+            let other_nonce = Nonce::from_bytes(cipher_suite, &other_nonce_bytes).unwrap();
+            assert_eq!(
+                chunk_nonce(nonce, 0, false).unwrap(),
+                chunk_nonce(other_nonce, 0, false).unwrap()
+            );
+            let ciphertext = encrypt(b"abcdefghij", &key, nonce, chunk_size);
+            assert_ne!(
+                ciphertext,
+                encrypt(b"abcdefghij", &key, other_nonce, chunk_size)
+            );
+            let _ = decrypt(&ciphertext, &key, other_nonce, chunk_size, b"aad").unwrap_err();
+
+            // The chunks aren't encrypted with the key itself.
+            let first_chunk = Encrypted::from_fields(
+                ciphertext[..encrypted_chunk_size].to_vec(),
+                chunk_nonce(nonce, 0, false).unwrap(),
+            );
+            let _ = first_chunk.try_decrypt_bytes(&key, b"aad").unwrap_err();
+            assert_eq!(
+                first_chunk
+                    .try_decrypt_bytes(&stream_key(&key, nonce).unwrap(), b"aad")
+                    .unwrap(),
+                b"abcd"
+            );
+        }
+    }
+
+    #[test]
+    fn tampering_detected() {
+        let key = new_rand_key();
+        let nonce = CipherSuite::default().new_rand_nonce();
+        let chunk_size = 4;
+        let encrypted_chunk_size = chunk_size as usize + TAG_SIZE;
+        let ciphertext = encrypt(b"abcdefghij", &key, nonce, chunk_size);
+        assert_eq!(ciphertext.len(), 10 + 3 * TAG_SIZE);
+
+        // Truncated at a chunk boundary.
+        let truncated = &ciphertext[..2 * encrypted_chunk_size];
+        let _ = decrypt(truncated, &key, nonce, chunk_size, b"aad").unwrap_err();
+
+        // Truncated mid-chunk.
+        let truncated = &ciphertext[..ciphertext.len() - 1];
+        let _ = decrypt(truncated, &key, nonce, chunk_size, b"aad").unwrap_err();
+
+        // Chunks swapped.
+        let mut reordered = ciphertext.clone();
+        reordered[..encrypted_chunk_size]
+            .copy_from_slice(&ciphertext[encrypted_chunk_size..2 * encrypted_chunk_size]);
+        reordered[encrypted_chunk_size..2 * encrypted_chunk_size]
+            .copy_from_slice(&ciphertext[..encrypted_chunk_size]);
+        let _ = decrypt(&reordered, &key, nonce, chunk_size, b"aad").unwrap_err();
+
+        // Chunk appended.
+        let mut extended = ciphertext.clone();
+        extended.extend_from_slice(&ciphertext[..encrypted_chunk_size]);
+        let _ = decrypt(&extended, &key, nonce, chunk_size, b"aad").unwrap_err();
+
+        // Empty.
+        let _ = decrypt(&[], &key, nonce, chunk_size, b"aad").unwrap_err();
+
+        // Never finished.
+        let mut encryptor = StreamEncryptor::new(vec![], &key, nonce, chunk_size, b"aad").unwrap();
+        encryptor.write_all(b"abcdefghij").unwrap();
+        let _ = decrypt(encryptor.writer.as_slice(), &key, nonce, chunk_size, b"aad").unwrap_err();
+    }
+}
@@ -1,40 +1,77 @@
 //! Functionality related to encrypting and decrypting different types.
-use aes_gcm::{
-    aead::{AeadCore, OsRng},
-    Aes256Gcm,
-};
 use camino::{Utf8Path, Utf8PathBuf};
 use color_eyre::eyre::{self, eyre};
+use zeroize::{Zeroize, Zeroizing};
 
-use super::encrypted::*;
+use super::{super::secret::Secret, cipher_suite::CipherSuite, encrypted::*};
 
-/// Implementors of this trait can be AES-256 encrypted & converted into an [Encrypted].
+/// Implementors of this trait can be encrypted & converted into an [Encrypted].
 ///
-/// Most implementations of this trait implement *only* [TryIntoEncrypted::try_encrypt_with_both].
+/// Most implementations of this trait implement *only* [TryIntoEncrypted::try_encrypt_with_all].
 /// The easiest way to do this is to convert the type to a `u8` slice then return
 /// [Encrypted::try_encrypt_bytes_key_nonce].
+///
+/// The `aad` ("additional authenticated data") of the `_aad` and `_all` variants binds the
+/// [Encrypted] to its context. The same `aad` must be given when decrypting.
+///
+/// Unless a [CipherSuite] or [Nonce] is given, the default [CipherSuite] is used.
 pub trait TryIntoEncrypted {
-    /// Encrypt using a randomly-generated [Aes256Key] and [Aes256Nonce].
+    /// Encrypt using a randomly-generated [Aes256Key] and [Nonce].
     #[allow(dead_code)]
     fn try_encrypt_new_key(self) -> eyre::Result<(Encrypted, Aes256Key)>
     where
         Self: Sized,
     {
         let key = new_rand_key();
-        Ok((self.try_encrypt_with_key(key)?, key))
+        Ok((self.try_encrypt_with_key(&key)?, key))
+    }
+
+    /// Encrypt using a given [Aes256Key] and randomly-generated [Nonce].
+    fn try_encrypt_with_key(self, key: &Aes256Key) -> eyre::Result<Encrypted>
+    where
+        Self: Sized,
+    {
+        self.try_encrypt_with_aad(key, &[])
+    }
+
+    /// Encrypt using a given [Aes256Key], randomly-generated [Nonce], and associated data.
+    fn try_encrypt_with_aad(self, key: &Aes256Key, aad: &[u8]) -> eyre::Result<Encrypted>
+    where
+        Self: Sized,
+    {
+        self.try_encrypt_with_suite(key, CipherSuite::default(), aad)
+    }
+
+    /// Encrypt using a given [Aes256Key], [CipherSuite], and associated data, with a
+    /// randomly-generated [Nonce].
+    fn try_encrypt_with_suite(
+        self,
+        key: &Aes256Key,
+        cipher_suite: CipherSuite,
+        aad: &[u8],
+    ) -> eyre::Result<Encrypted>
+    where
+        Self: Sized,
+    {
+        self.try_encrypt_with_all(key, cipher_suite.new_rand_nonce(), aad)
     }
 
-    /// Encrypt using a given [Aes256Key] and randomly-generated [Aes256Nonce].
-    fn try_encrypt_with_key(self, key: Aes256Key) -> eyre::Result<Encrypted>
+    /// Encrypt using a given [Aes256Key] and [Nonce].
+    #[allow(dead_code)]
+    fn try_encrypt_with_both(self, key: &Aes256Key, nonce: Nonce) -> eyre::Result<Encrypted>
     where
         Self: Sized,
     {
-        let nonce: Aes256Nonce = Aes256Gcm::generate_nonce(&mut OsRng).into();
-        self.try_encrypt_with_both(key, nonce)
+        self.try_encrypt_with_all(key, nonce, &[])
     }
 
-    /// Encrypt using a given [Aes256Key] and [Aes256Nonce].
-    fn try_encrypt_with_both(self, key: Aes256Key, nonce: Aes256Nonce) -> eyre::Result<Encrypted>
+    /// Encrypt using a given [Aes256Key], [Nonce], and associated data.
+    fn try_encrypt_with_all(
+        self,
+        key: &Aes256Key,
+        nonce: Nonce,
+        aad: &[u8],
+    ) -> eyre::Result<Encrypted>
     where
         Self: Sized;
 }
@@ -43,49 +80,82 @@ pub trait TryIntoEncrypted {
 macro_rules! impl_to_encrypted_byte_vec {
     ($($t:ty),+) => {
         $(impl TryIntoEncrypted for $t {
-            fn try_encrypt_with_both(
+            fn try_encrypt_with_all(
                 self,
-                key: Aes256Key,
-                nonce: Aes256Nonce,
+                key: &Aes256Key,
+                nonce: Nonce,
+                aad: &[u8],
             ) -> eyre::Result<Encrypted> {
-                let byte_vec: Vec<u8> = match self.try_into() {
-                    Ok(byte_vec) => byte_vec,
+                let byte_vec: Zeroizing<Vec<u8>> = match self.try_into() {
+                    Ok(byte_vec) => Zeroizing::new(byte_vec),
                     Err(_) => return Err(
                         eyre!("TryIntoEncrypted: Failed to convert to byte slice.")
                     ),
                 };
-                Encrypted::try_encrypt_bytes_key_nonce(&byte_vec, key, nonce)
+                Encrypted::try_encrypt_bytes_key_nonce(&byte_vec, key, nonce, aad)
             }
         })*
     }
 }
-impl_to_encrypted_byte_vec!(Vec<u8>, &[u8], String, &str, Aes256Key, Aes256Nonce);
+impl_to_encrypted_byte_vec!(Vec<u8>, &[u8], String, &str, Aes256Nonce);
+
+impl TryIntoEncrypted for &Aes256Key {
+    fn try_encrypt_with_all(
+        self,
+        key: &Aes256Key,
+        nonce: Nonce,
+        aad: &[u8],
+    ) -> eyre::Result<Encrypted> {
+        Encrypted::try_encrypt_bytes_key_nonce(self.as_bytes(), key, nonce, aad)
+    }
+}
 
 macro_rules! impl_to_encrypted_camino {
     ($($t:ty),+) => {
         $(impl TryIntoEncrypted for $t {
-            fn try_encrypt_with_both(
+            fn try_encrypt_with_all(
                 self,
-                key: Aes256Key,
-                nonce: Aes256Nonce
+                key: &Aes256Key,
+                nonce: Nonce,
+                aad: &[u8],
             ) -> eyre::Result<Encrypted> {
                 let path_string = self.to_string();
                 let byte_slice: &[u8] = path_string.as_bytes();
-                Encrypted::try_encrypt_bytes_key_nonce(byte_slice, key, nonce)
+                Encrypted::try_encrypt_bytes_key_nonce(byte_slice, key, nonce, aad)
             }
         })*
     }
 }
 impl_to_encrypted_camino!(Utf8PathBuf, &Utf8Path);
 
-/// Implementors of this trait can be AES-256 decrypted & converted from an [Encrypted].
+/// Implementors of this trait can be decrypted & converted from an [Encrypted].
 ///
-/// Most implementors of this trait implement [TryFromEncrypted::try_decrypt] by getting the result
-/// of [Encrypted::try_decrypt_bytes], then converting the resulting [Vec<u8>] into the
-/// implementing type.
+/// Most implementors of this trait implement only [TryFromEncrypted::try_from_decrypted_bytes],
+/// which converts the already-decrypted [Vec<u8>] into the implementing type.
 pub trait TryFromEncrypted {
     /// Decrypt the [Encrypted] into the implementing type.
-    fn try_decrypt(encrypted: &Encrypted, key: Aes256Key) -> eyre::Result<Self>
+    fn try_decrypt(encrypted: &Encrypted, key: &Aes256Key) -> eyre::Result<Self>
+    where
+        Self: Sized,
+    {
+        Self::try_decrypt_with_aad(encrypted, key, &[])
+    }
+
+    /// Decrypt the [Encrypted] into the implementing type, authenticating the given associated
+    /// data.
+    fn try_decrypt_with_aad(
+        encrypted: &Encrypted,
+        key: &Aes256Key,
+        aad: &[u8],
+    ) -> eyre::Result<Self>
+    where
+        Self: Sized,
+    {
+        Self::try_from_decrypted_bytes(encrypted.try_decrypt_bytes(key, aad)?)
+    }
+
+    /// Convert decrypted bytes into the implementing type.
+    fn try_from_decrypted_bytes(decrypted_bytes: Vec<u8>) -> eyre::Result<Self>
     where
         Self: Sized;
 }
@@ -94,8 +164,7 @@ pub trait TryFromEncrypted {
 macro_rules! impl_from_encrypted_byte_vec {
     ($($t:ty),+) => {
         $(impl TryFromEncrypted for $t {
-            fn try_decrypt(encrypted: &Encrypted, key: Aes256Key) -> eyre::Result<Self> {
-                let decrypted_bytes: Vec<u8> = encrypted.try_decrypt_bytes(key)?;
+            fn try_from_decrypted_bytes(decrypted_bytes: Vec<u8>) -> eyre::Result<Self> {
                 match Self::try_from(decrypted_bytes) {
                     Ok(decrypted_self) => Ok(decrypted_self),
                     Err(_) => return Err(
@@ -106,13 +175,35 @@ macro_rules! impl_from_encrypted_byte_vec {
         })*
     }
 }
-impl_from_encrypted_byte_vec!(Vec<u8>, Aes256Key, Aes256Nonce);
+impl_from_encrypted_byte_vec!(Vec<u8>, Aes256Nonce);
+
+impl TryFromEncrypted for Aes256Key {
+    fn try_from_decrypted_bytes(decrypted_bytes: Vec<u8>) -> eyre::Result<Self> {
+        let mut key_bytes = Zeroizing::new([0u8; 32]);
+        let decrypted_vec = Zeroizing::new(decrypted_bytes);
+        if decrypted_vec.len() != key_bytes.len() {
+            return Err(eyre!(
+                "TryFromEncrypted: Failed to convert from byte vector."
+            ));
+        }
+        key_bytes.copy_from_slice(&decrypted_vec);
+        Ok(Aes256Key::from_bytes(*key_bytes))
+    }
+}
+
+impl<T> TryFromEncrypted for Secret<T>
+where
+    T: TryFromEncrypted + Zeroize,
+{
+    fn try_from_decrypted_bytes(decrypted_bytes: Vec<u8>) -> eyre::Result<Self> {
+        Ok(Secret::new(T::try_from_decrypted_bytes(decrypted_bytes)?))
+    }
+}
 
 macro_rules! impl_from_encrypted_utf8 {
     ($($t:ty),+) => {
         $(impl TryFromEncrypted for $t {
-            fn try_decrypt(encrypted: &Encrypted, key: Aes256Key) -> eyre::Result<Self> {
-                let decrypted_bytes: Vec<u8> = encrypted.try_decrypt_bytes(key)?;
+            fn try_from_decrypted_bytes(decrypted_bytes: Vec<u8>) -> eyre::Result<Self> {
                 match String::from_utf8(decrypted_bytes) {
                     Ok(decrypted_string) => match Self::try_from(decrypted_string) {
                         Ok(decrypted_self) => Ok(decrypted_self),
@@ -120,11 +211,12 @@ macro_rules! impl_from_encrypted_utf8 {
                             eyre!("TryFromEncrypted: Failed to convert from String.")
                         ),
                     },
-                    Err(_) => return Err(
-                        eyre!(
+                    Err(err) => {
+                        err.into_bytes().zeroize();
+                        Err(eyre!(
                             "TryFromEncrypted: Failed to convert to String from byte vector."
-                        )
-                    ),
+                        ))
+                    }
                 }
             }
         })*
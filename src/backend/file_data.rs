@@ -1,56 +1,172 @@
 //! Functionality for data associated with user files stored in the `dgruft` filesystem.
 use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre;
+use uuid::Uuid;
 
-use super::encryption::encrypted::Aes256Nonce;
+use super::{
+    encryption::{
+        associated_data::{AssociatedData, EntityType},
+        cipher_suite::CipherSuite,
+        encrypted::{Aes256Key, Encrypted, Nonce, TryFromEncrypted, TryIntoEncrypted},
+    },
+    hashing::{blind_index::blind_index, hashed::Hash},
+};
 
+// The domain of the blind index of filenames.
+const NAME_INDEX_KEY_DOMAIN: &[u8] = b"dgruft filename index";
+
+/// The name of a file stored in the `dgruft` filesystem, as it is kept in the [Database].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Filename {
+    /// An [Encrypted] filename.
+    Encrypted(Encrypted),
+    /// The plaintext filename of a file created before filenames were encrypted. It is encrypted
+    /// the next time the file's owner logs in.
+    Legacy(String),
+}
+
+/// The data of a file stored in the `dgruft` filesystem.
+///
+/// The file's encrypted name & contents are bound to the `owner_username` and the `id` through
+/// their [AssociatedData], so they can't be moved onto another file. The file itself is named after
+/// its `id`, so its real name can't be seen on disk.
+///
+/// ### Fields
+///
+/// - `id`: The stable, unique id of this [FileData].
+///
+/// - `path`: The path of the file in the `dgruft` filesystem.
+///
+/// - `filename`: The [Filename] of the file.
+///
+/// - `owner_username`: The username of the [Account] that owns the file.
+///
+/// - `contents_nonce`: The [Nonce] used to encrypt the file's contents. It also records the
+///   [CipherSuite](super::encryption::cipher_suite::CipherSuite) used.
+///
+/// - `contents_chunk_size`: The size of the chunks the file's contents are encrypted in, as a
+///   stream headed by the `contents_nonce`. 0 if the contents are encrypted as a single message.
+///
+/// - `name_index`: The blind index of the file's name, which lets it be looked up by name without
+///   decrypting the names of all its owner's files. Filenames are unique per account, which the
+///   database enforces through the `name_index`. [None] for files created before blind indexes
+///   were added, until their owner next logs in, & for files in the trash.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct FileData {
+    id: String,
     path: Utf8PathBuf,
-    filename: String,
+    filename: Filename,
     owner_username: String,
-    contents_nonce: Aes256Nonce,
+    contents_nonce: Nonce,
+    contents_chunk_size: u32,
+    name_index: Option<Hash<32>>,
 }
 impl FileData {
-    /// Create a new, empty [FileData].
-    pub fn new<P>(
-        path: P,
-        filename: String,
-        owner_username: String,
-        contents_nonce: Aes256Nonce,
-    ) -> Self
+    /// Create a new [FileData] with a new, random `id`, encrypting its `filename` with the given
+    /// [CipherSuite]. The file is placed in the given account file directory & named after its `id`.
+    pub fn try_new<P>(
+        account_file_dir: P,
+        owner_username: &str,
+        encryption_key: &Aes256Key,
+        cipher_suite: CipherSuite,
+        filename: &str,
+        contents_nonce: Nonce,
+        contents_chunk_size: u32,
+    ) -> eyre::Result<Self>
     where
         P: AsRef<Utf8Path>,
     {
-        Self {
-            path: path.as_ref().into(),
-            filename,
-            owner_username,
+        let id = Uuid::new_v4().to_string();
+        let encrypted_filename = filename.try_encrypt_with_suite(
+            encryption_key,
+            cipher_suite,
+            &Self::filename_associated_data(owner_username, &id),
+        )?;
+        Ok(Self {
+            path: account_file_dir.as_ref().join(&id),
+            id,
+            filename: Filename::Encrypted(encrypted_filename),
+            owner_username: owner_username.to_owned(),
             contents_nonce,
-        }
+            contents_chunk_size,
+            name_index: Some(Self::compute_name_index(encryption_key, filename)?),
+        })
     }
 
     /// Create a [FileData] from its fields.
     pub fn from_fields(
+        id: String,
         path: Utf8PathBuf,
-        filename: String,
+        filename: Filename,
         owner_username: String,
-        contents_nonce: Aes256Nonce,
+        contents_nonce: Nonce,
+        contents_chunk_size: u32,
+        name_index: Option<Hash<32>>,
     ) -> Self {
         Self {
+            id,
             path,
             filename,
             owner_username,
             contents_nonce,
+            contents_chunk_size,
+            name_index,
+        }
+    }
+
+    /// Compute the [blind_index] of a filename under the owner's `key`.
+    pub fn compute_name_index(key: &Aes256Key, filename: &str) -> eyre::Result<Hash<32>> {
+        blind_index(key, NAME_INDEX_KEY_DOMAIN, filename)
+    }
+
+    /// Return the [AssociatedData] bytes used to encrypt the contents of this [FileData]'s file.
+    pub fn contents_associated_data(&self) -> Vec<u8> {
+        AssociatedData::new(&self.owner_username, EntityType::File, "contents", &self.id).to_bytes()
+    }
+
+    /// Encrypt a new `filename` for this [FileData] with the given [CipherSuite].
+    pub fn encrypt_filename(
+        &self,
+        key: &Aes256Key,
+        cipher_suite: CipherSuite,
+        filename: &str,
+    ) -> eyre::Result<Encrypted> {
+        filename.try_encrypt_with_suite(
+            key,
+            cipher_suite,
+            &Self::filename_associated_data(&self.owner_username, &self.id),
+        )
+    }
+
+    /// Decrypt the `filename` of this [FileData]. [Filename::Legacy] filenames are returned as-is.
+    pub fn filename<T: TryFromEncrypted>(&self, key: &Aes256Key) -> eyre::Result<T> {
+        match &self.filename {
+            Filename::Encrypted(encrypted_filename) => T::try_decrypt_with_aad(
+                encrypted_filename,
+                key,
+                &Self::filename_associated_data(&self.owner_username, &self.id),
+            ),
+            Filename::Legacy(filename) => T::try_from_decrypted_bytes(filename.as_bytes().to_vec()),
         }
     }
 
+    // Helper function: Get the [AssociatedData] bytes of the `filename`.
+    fn filename_associated_data(owner_username: &str, id: &str) -> Vec<u8> {
+        AssociatedData::new(owner_username, EntityType::File, "filename", id).to_bytes()
+    }
+
+    /// Return the `id` of this [FileData].
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
     /// Return the `path` of this [FileData].
     pub fn path(&self) -> &Utf8Path {
         &self.path
     }
 
-    /// Return the `filename` of this [FileData].
-    pub fn filename(&self) -> &str {
+    /// Return the stored [Filename] of this [FileData].
+    pub fn stored_filename(&self) -> &Filename {
         &self.filename
     }
 
@@ -60,7 +176,139 @@ impl FileData {
     }
 
     /// Return the `contents_nonce` of this [FileData].
-    pub fn contents_nonce(&self) -> Aes256Nonce {
+    pub fn contents_nonce(&self) -> Nonce {
         self.contents_nonce
     }
+
+    /// Return the `contents_chunk_size` of this [FileData].
+    pub fn contents_chunk_size(&self) -> u32 {
+        self.contents_chunk_size
+    }
+
+    /// Return the `name_index` of this [FileData].
+    pub fn name_index(&self) -> Option<&Hash<32>> {
+        self.name_index.as_ref()
+    }
+}
+
+/// A previous revision of a file's encrypted contents, kept when the file is updated so that it
+/// can be restored.
+///
+/// A revision keeps the ciphertext its file had before the update, along with the contents header
+/// it was encrypted with, so each revision has its own [Nonce]. The ciphertext is still bound to
+/// the file's `owner_username` & `id` through its [AssociatedData].
+///
+/// ### Fields
+///
+/// - `id`: The unique id of this [FileRevision].
+///
+/// - `file_id`: The `id` of the [FileData] this is a revision of.
+///
+/// - `owner_username`: The username of the [Account] that owns the file.
+///
+/// - `path`: The path of this revision's contents in the `dgruft` filesystem.
+///
+/// - `replaced_at`: The Unix time at which this revision was replaced.
+///
+/// - `contents_nonce`: The [Nonce] this revision's contents were encrypted with.
+///
+/// - `contents_chunk_size`: The size of the chunks this revision's contents were encrypted in.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FileRevision {
+    id: String,
+    file_id: String,
+    owner_username: String,
+    path: Utf8PathBuf,
+    replaced_at: u64,
+    contents_nonce: Nonce,
+    contents_chunk_size: u32,
+}
+impl FileRevision {
+    /// Create a new [FileRevision] with a new, random `id` from the current contents header of the
+    /// given [FileData]. Its contents are kept at the path given by `revision_path` for its `id`.
+    pub fn new<F>(file_data: &FileData, replaced_at: u64, revision_path: F) -> Self
+    where
+        F: FnOnce(&str) -> Utf8PathBuf,
+    {
+        let id = Uuid::new_v4().to_string();
+        Self {
+            path: revision_path(&id),
+            id,
+            file_id: file_data.id().to_owned(),
+            owner_username: file_data.owner_username().to_owned(),
+            replaced_at,
+            contents_nonce: file_data.contents_nonce(),
+            contents_chunk_size: file_data.contents_chunk_size(),
+        }
+    }
+
+    /// Create a [FileRevision] from its fields.
+    pub fn from_fields(
+        id: String,
+        file_id: String,
+        owner_username: String,
+        path: Utf8PathBuf,
+        replaced_at: u64,
+        contents_nonce: Nonce,
+        contents_chunk_size: u32,
+    ) -> Self {
+        Self {
+            id,
+            file_id,
+            owner_username,
+            path,
+            replaced_at,
+            contents_nonce,
+            contents_chunk_size,
+        }
+    }
+
+    /// Return the given [FileData] of this revision's file with this revision's `path` & contents
+    /// header, so that this revision's contents can be read like the file's.
+    pub fn contents_file_data(&self, file_data: &FileData) -> FileData {
+        FileData::from_fields(
+            file_data.id().to_owned(),
+            self.path.clone(),
+            file_data.stored_filename().clone(),
+            self.owner_username.clone(),
+            self.contents_nonce,
+            self.contents_chunk_size,
+            file_data.name_index().copied(),
+        )
+    }
+
+    /// Return the `id` of this [FileRevision].
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Return the `file_id` of this [FileRevision].
+    pub fn file_id(&self) -> &str {
+        &self.file_id
+    }
+
+    /// Return the `owner_username` of this [FileRevision].
+    pub fn owner_username(&self) -> &str {
+        &self.owner_username
+    }
+
+    /// Return the `path` of this [FileRevision].
+    pub fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+
+    /// Return the `replaced_at` time of this [FileRevision].
+    pub fn replaced_at(&self) -> u64 {
+        self.replaced_at
+    }
+
+    /// Return the `contents_nonce` of this [FileRevision].
+    pub fn contents_nonce(&self) -> Nonce {
+        self.contents_nonce
+    }
+
+    /// Return the `contents_chunk_size` of this [FileRevision].
+    pub fn contents_chunk_size(&self) -> u32 {
+        self.contents_chunk_size
+    }
 }
@@ -1,2 +1,4 @@
+pub mod blind_index;
 pub mod hashed;
+pub mod kdf;
 mod traits;
@@ -0,0 +1,47 @@
+//! Keyed blind indexes, which let names be looked up without decrypting them.
+use color_eyre::eyre;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::{super::encryption::encrypted::Aes256Key, hashed::Hash};
+
+/// Compute the blind index of a `name`: its HMAC-SHA256 under an index key, which is the
+/// HMAC-SHA256 of the `domain` under the owner's `key`. Each kind of name has its own `domain`, so
+/// equal names of different kinds have unrelated indexes.
+pub fn blind_index(key: &Aes256Key, domain: &[u8], name: &str) -> eyre::Result<Hash<32>> {
+    let mut key_mac = <Hmac<Sha256> as Mac>::new_from_slice(key.as_bytes())?;
+    key_mac.update(domain);
+    let index_key = Aes256Key::from_bytes(key_mac.finalize().into_bytes().into());
+
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(index_key.as_bytes())?;
+    mac.update(name.as_bytes());
+    Ok(mac.finalize().into_bytes().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::{assert_eq, assert_ne};
+
+    use super::{super::super::encryption::encrypted::new_rand_key, *};
+
+    const TEST_DOMAIN: &[u8] = b"dgruft test index";
+    const TEST_DOMAIN_DIFFERENT: &[u8] = b"dgruft test indey";
+    const TEST_NAME: &str = "name";
+
+    #[test]
+    fn blind_index_consistency() {
+        let key = new_rand_key();
+        let index = blind_index(&key, TEST_DOMAIN, TEST_NAME).unwrap();
+
+        assert_eq!(index, blind_index(&key, TEST_DOMAIN, TEST_NAME).unwrap());
+        assert_ne!(index, blind_index(&key, TEST_DOMAIN, "other").unwrap());
+        assert_ne!(
+            index,
+            blind_index(&key, TEST_DOMAIN_DIFFERENT, TEST_NAME).unwrap()
+        );
+        assert_ne!(
+            index,
+            blind_index(&new_rand_key(), TEST_DOMAIN, TEST_NAME).unwrap()
+        );
+    }
+}
@@ -1,42 +1,61 @@
 //! Functionality related to the [Hashed] struct.
-use pbkdf2::pbkdf2_hmac;
-use sha2::Sha256;
+use std::fmt;
+
+use color_eyre::eyre;
+use zeroize::Zeroize;
 
 /// Re-export traits
 pub use super::traits::*;
 
+use super::kdf::KdfParams;
+
 /// A fixed-length, H-byte cryptographic hash.
 pub type Hash<const H: usize> = [u8; H];
 
 /// A fixed-length, S-byte cryptographic salt.
 pub type Salt<const S: usize> = [u8; S];
 
-/// H bytes hashed and salted using PBKDF2-HMAC-SHA256 & a S-byte salt.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// H bytes hashed and salted using a key derivation function & a S-byte salt.
+///
+/// The hash is zeroed when dropped, and is never shown by its [fmt::Debug] implementation.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Hashed<const H: usize, const S: usize> {
     hash: Hash<H>,
     salt: Salt<S>,
+    kdf_params: KdfParams,
 }
 impl<const H: usize, const S: usize> Hashed<H, S> {
-    const NUM_ITERATIONS: u32 = 50_000;
-
-    /// Hash and salt a byte slice using a given salt.
-    pub fn hash_with_salt(byte_slice: &[u8], salt: Salt<S>) -> Self {
+    /// Hash and salt a byte slice using a given salt and [KdfParams].
+    pub fn hash_with_salt(
+        byte_slice: &[u8],
+        salt: Salt<S>,
+        kdf_params: KdfParams,
+    ) -> eyre::Result<Self> {
         let mut hash: Hash<H> = [0u8; H];
-        pbkdf2_hmac::<Sha256>(byte_slice, &salt, Self::NUM_ITERATIONS, &mut hash);
+        kdf_params.derive(byte_slice, &salt, &mut hash)?;
 
-        Self { hash, salt }
+        Ok(Self {
+            hash,
+            salt,
+            kdf_params,
+        })
     }
 
     /// Create a [Hashed] from its fields.
-    pub fn from_fields(hash: Hash<H>, salt: Salt<S>) -> Self {
-        Self { hash, salt }
+    pub fn from_fields(hash: Hash<H>, salt: Salt<S>, kdf_params: KdfParams) -> Self {
+        Self {
+            hash,
+            salt,
+            kdf_params,
+        }
     }
 
     /// Check whether the given bytes match the bytes used to make this [Hashed].
     pub fn check_match(&self, byte_slice: &[u8]) -> bool {
-        let hashed_input = Self::hash_with_salt(byte_slice, self.salt);
-        self.hash() == hashed_input.hash()
+        match Self::hash_with_salt(byte_slice, self.salt, self.kdf_params) {
+            Ok(hashed_input) => self.hash() == hashed_input.hash(),
+            Err(_) => false,
+        }
     }
 
     /// Return the [Hash] of this [Hashed].
@@ -48,6 +67,25 @@ impl<const H: usize, const S: usize> Hashed<H, S> {
     pub fn salt(&self) -> &Salt<S> {
         &self.salt
     }
+
+    /// Return the [KdfParams] of this [Hashed].
+    pub fn kdf_params(&self) -> KdfParams {
+        self.kdf_params
+    }
+}
+impl<const H: usize, const S: usize> Drop for Hashed<H, S> {
+    fn drop(&mut self) {
+        self.hash.zeroize();
+    }
+}
+impl<const H: usize, const S: usize> fmt::Debug for Hashed<H, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hashed")
+            .field("hash", &"<redacted>")
+            .field("salt", &self.salt)
+            .field("kdf_params", &self.kdf_params)
+            .finish()
+    }
 }
 
 #[cfg(test)]
@@ -66,11 +104,17 @@ mod tests {
         Utf8PathBuf::from(TEST_PATH_STR)
     }
 
+    fn pbkdf2() -> KdfParams {
+        KdfParams::legacy_pbkdf2()
+    }
+
     #[test]
     fn pbkdf2_consistency() {
-        let hash_1: Hashed<32, 64> = TEST_BYTES.into_hashed_rand_salt();
-        let hash_2: Hashed<32, 64> = TEST_BYTES.into_hashed_rand_salt();
-        let hash_1_dupe: Hashed<32, 64> = TEST_BYTES.into_hashed_with_salt(*hash_1.salt());
+        let hash_1: Hashed<32, 64> = TEST_BYTES.into_hashed_rand_salt(pbkdf2()).unwrap();
+        let hash_2: Hashed<32, 64> = TEST_BYTES.into_hashed_rand_salt(pbkdf2()).unwrap();
+        let hash_1_dupe: Hashed<32, 64> = TEST_BYTES
+            .into_hashed_with_salt(*hash_1.salt(), pbkdf2())
+            .unwrap();
 
         assert_eq!(hash_1, hash_1_dupe);
         assert_ne!(hash_1, hash_2);
@@ -84,10 +128,10 @@ mod tests {
 
     #[test]
     fn pbkdf2_consistency_diff_lengths() {
-        let hash_10: Hashed<10, 32> = TEST_UTF8.into_hashed_rand_salt();
+        let hash_10: Hashed<10, 32> = TEST_UTF8.into_hashed_rand_salt(pbkdf2()).unwrap();
         let salt = *hash_10.salt();
-        let hash_17: Hashed<17, 32> = TEST_UTF8.into_hashed_with_salt(salt);
-        let hash_123: Hashed<123, 32> = TEST_UTF8.into_hashed_with_salt(salt);
+        let hash_17: Hashed<17, 32> = TEST_UTF8.into_hashed_with_salt(salt, pbkdf2()).unwrap();
+        let hash_123: Hashed<123, 32> = TEST_UTF8.into_hashed_with_salt(salt, pbkdf2()).unwrap();
 
         assert!(TEST_UTF8.check_match(&hash_10));
         assert!(TEST_UTF8.check_match(&hash_17));
@@ -96,12 +140,20 @@ mod tests {
 
     #[test]
     fn hash_utf8_paths() {
-        let hashed_path_buf: Hashed<32, 64> = test_utf8_path_buf().into_hashed_rand_salt();
+        let hashed_path_buf: Hashed<32, 64> = test_utf8_path_buf()
+            .into_hashed_rand_salt(pbkdf2())
+            .unwrap();
         let salt = *hashed_path_buf.salt();
-        let hashed_path: Hashed<32, 64> =
-            test_utf8_path_buf().as_path().into_hashed_with_salt(salt);
-        let hashed_str: Hashed<32, 64> = TEST_PATH_STR.into_hashed_with_salt(salt);
-        let hashed_bytes: Hashed<32, 64> = TEST_PATH_STR.as_bytes().into_hashed_with_salt(salt);
+        let hashed_path: Hashed<32, 64> = test_utf8_path_buf()
+            .as_path()
+            .into_hashed_with_salt(salt, pbkdf2())
+            .unwrap();
+        let hashed_str: Hashed<32, 64> =
+            TEST_PATH_STR.into_hashed_with_salt(salt, pbkdf2()).unwrap();
+        let hashed_bytes: Hashed<32, 64> = TEST_PATH_STR
+            .as_bytes()
+            .into_hashed_with_salt(salt, pbkdf2())
+            .unwrap();
 
         assert_eq!(hashed_path, hashed_path_buf);
         assert_eq!(hashed_path_buf, hashed_str);
@@ -112,4 +164,24 @@ mod tests {
         assert!(TEST_PATH_STR.check_match(&hashed_path_buf));
         assert!(TEST_PATH_STR.as_bytes().check_match(&hashed_path_buf));
     }
+
+    #[test]
+    fn argon2id_consistency() {
+        let params = KdfParams::default();
+        let hash_1: Hashed<32, 64> = TEST_BYTES.into_hashed_rand_salt(params).unwrap();
+        let hash_1_dupe: Hashed<32, 64> = TEST_BYTES
+            .into_hashed_with_salt(*hash_1.salt(), params)
+            .unwrap();
+        let hash_1_pbkdf2: Hashed<32, 64> = TEST_BYTES
+            .into_hashed_with_salt(*hash_1.salt(), pbkdf2())
+            .unwrap();
+
+        assert_eq!(hash_1, hash_1_dupe);
+        assert_eq!(hash_1.kdf_params(), params);
+        assert_ne!(hash_1.hash(), hash_1_pbkdf2.hash());
+
+        assert!(TEST_BYTES.check_match(&hash_1));
+        assert!(TEST_BYTES.check_match(&hash_1_pbkdf2));
+        assert!(!TEST_BYTES_DIFFERENT.check_match(&hash_1));
+    }
 }
@@ -0,0 +1,273 @@
+//! Functionality related to the key derivation functions (KDFs) used to hash passwords.
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use color_eyre::eyre::{self, eyre};
+use pbkdf2::pbkdf2_hmac;
+use sha2::Sha256;
+
+/// All the key derivation algorithms supported by `dgruft`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum KdfAlgorithm {
+    /// PBKDF2-HMAC-SHA256. Only used by accounts created before Argon2id support was added.
+    Pbkdf2HmacSha256,
+    /// Argon2id. Used by all new accounts.
+    Argon2id,
+}
+impl KdfAlgorithm {
+    /// Return the name under which this [KdfAlgorithm] is stored in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pbkdf2HmacSha256 => "pbkdf2-hmac-sha256",
+            Self::Argon2id => "argon2id",
+        }
+    }
+}
+impl fmt::Display for KdfAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+impl FromStr for KdfAlgorithm {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> eyre::Result<Self> {
+        match s {
+            "pbkdf2-hmac-sha256" => Ok(Self::Pbkdf2HmacSha256),
+            "argon2id" => Ok(Self::Argon2id),
+            other => Err(eyre!("Unknown key derivation algorithm \"{}\".", other)),
+        }
+    }
+}
+
+/// A [KdfAlgorithm] along with the cost parameters it is run with.
+///
+/// ### Fields
+///
+/// - `algorithm`: The [KdfAlgorithm] used.
+///
+/// - `memory_kib`: The memory cost in KiB. Unused by PBKDF2.
+///
+/// - `time_cost`: The number of passes for Argon2id, or the number of iterations for PBKDF2.
+///
+/// - `parallelism`: The degree of parallelism. Unused by PBKDF2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct KdfParams {
+    algorithm: KdfAlgorithm,
+    memory_kib: u32,
+    time_cost: u32,
+    parallelism: u32,
+}
+impl KdfParams {
+    /// Default Argon2id memory cost, as recommended by OWASP.
+    const ARGON2ID_MEMORY_KIB: u32 = 19_456;
+    /// Default Argon2id time cost, as recommended by OWASP.
+    const ARGON2ID_TIME_COST: u32 = 2;
+    /// Default Argon2id parallelism, as recommended by OWASP.
+    const ARGON2ID_PARALLELISM: u32 = 1;
+    /// Upper bound on the time cost chosen by [KdfParams::calibrate].
+    const ARGON2ID_MAX_TIME_COST: u32 = 64;
+    /// The number of PBKDF2 iterations used by legacy accounts.
+    #[cfg(test)]
+    const PBKDF2_ITERATIONS: u32 = 50_000;
+
+    /// The PBKDF2-HMAC-SHA256 [KdfParams] used by accounts created by older versions of `dgruft`.
+    #[cfg(test)]
+    pub fn legacy_pbkdf2() -> Self {
+        Self {
+            algorithm: KdfAlgorithm::Pbkdf2HmacSha256,
+            memory_kib: 0,
+            time_cost: Self::PBKDF2_ITERATIONS,
+            parallelism: 1,
+        }
+    }
+
+    /// Create [KdfParams] from their fields, ensuring that the parameters are valid.
+    pub fn from_fields(
+        algorithm: KdfAlgorithm,
+        memory_kib: u32,
+        time_cost: u32,
+        parallelism: u32,
+    ) -> eyre::Result<Self> {
+        match algorithm {
+            KdfAlgorithm::Argon2id => {
+                if let Err(err) = Params::new(memory_kib, time_cost, parallelism, None) {
+                    return Err(eyre!("Invalid Argon2id parameters: {err}."));
+                }
+            }
+            KdfAlgorithm::Pbkdf2HmacSha256 => {
+                if time_cost == 0 {
+                    return Err(eyre!("PBKDF2 iteration count must be greater than 0."));
+                }
+            }
+        }
+        Ok(Self {
+            algorithm,
+            memory_kib,
+            time_cost,
+            parallelism,
+        })
+    }
+
+    /// Find the Argon2id [KdfParams] which take roughly `target` to unlock an account on this
+    /// machine.
+    ///
+    /// Unlocking an account runs the KDF twice, so each run is tuned to half the `target`. The
+    /// result is never weaker than [KdfParams::default].
+    pub fn calibrate(target: Duration) -> eyre::Result<Self> {
+        let mut params = Self::default();
+
+        // Time a single run with the default parameters.
+        let start = Instant::now();
+        params.derive(b"dgruft calibration", &[0u8; 64], &mut [0u8; 32])?;
+        let elapsed = start.elapsed().as_nanos().max(1);
+
+        // Scale the time cost linearly to reach the target.
+        let per_run_target = (target / 2).as_nanos();
+        let scaled_time_cost = u128::from(params.time_cost) * per_run_target / elapsed;
+        params.time_cost = u32::try_from(scaled_time_cost)
+            .unwrap_or(u32::MAX)
+            .clamp(Self::ARGON2ID_TIME_COST, Self::ARGON2ID_MAX_TIME_COST);
+
+        Ok(params)
+    }
+
+    /// Derive `output.len()` bytes from the given input and salt.
+    pub fn derive(&self, input: &[u8], salt: &[u8], output: &mut [u8]) -> eyre::Result<()> {
+        match self.algorithm {
+            KdfAlgorithm::Pbkdf2HmacSha256 => {
+                pbkdf2_hmac::<Sha256>(input, salt, self.time_cost, output);
+                Ok(())
+            }
+            KdfAlgorithm::Argon2id => {
+                let params = match Params::new(
+                    self.memory_kib,
+                    self.time_cost,
+                    self.parallelism,
+                    Some(output.len()),
+                ) {
+                    Ok(params) => params,
+                    Err(err) => return Err(eyre!("Invalid Argon2id parameters: {err}.")),
+                };
+                let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+                match argon2.hash_password_into(input, salt, output) {
+                    Ok(()) => Ok(()),
+                    Err(err) => Err(eyre!("Argon2id hashing failed: {err}.")),
+                }
+            }
+        }
+    }
+
+    /// Check whether these [KdfParams] are weaker than the `other` [KdfParams], i.e., whether
+    /// something hashed with these parameters should be re-hashed with the `other` ones.
+    pub fn is_weaker_than(&self, other: &Self) -> bool {
+        if self.algorithm != other.algorithm {
+            // Argon2id is always preferred over PBKDF2.
+            return other.algorithm == KdfAlgorithm::Argon2id;
+        }
+        match self.algorithm {
+            KdfAlgorithm::Pbkdf2HmacSha256 => self.time_cost < other.time_cost,
+            KdfAlgorithm::Argon2id => {
+                self.memory_kib < other.memory_kib || self.time_cost < other.time_cost
+            }
+        }
+    }
+
+    /// Return the [KdfAlgorithm] of these [KdfParams].
+    pub fn algorithm(&self) -> KdfAlgorithm {
+        self.algorithm
+    }
+
+    /// Return the `memory_kib` of these [KdfParams].
+    pub fn memory_kib(&self) -> u32 {
+        self.memory_kib
+    }
+
+    /// Return the `time_cost` of these [KdfParams].
+    pub fn time_cost(&self) -> u32 {
+        self.time_cost
+    }
+
+    /// Return the `parallelism` of these [KdfParams].
+    pub fn parallelism(&self) -> u32 {
+        self.parallelism
+    }
+}
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            algorithm: KdfAlgorithm::Argon2id,
+            memory_kib: Self::ARGON2ID_MEMORY_KIB,
+            time_cost: Self::ARGON2ID_TIME_COST,
+            parallelism: Self::ARGON2ID_PARALLELISM,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::{assert_eq, assert_ne};
+
+    use super::*;
+
+    #[test]
+    fn algorithm_names() {
+        for algorithm in [KdfAlgorithm::Pbkdf2HmacSha256, KdfAlgorithm::Argon2id] {
+            assert_eq!(
+                algorithm.as_str().parse::<KdfAlgorithm>().unwrap(),
+                algorithm
+            );
+        }
+        let _ = "scrypt".parse::<KdfAlgorithm>().unwrap_err();
+    }
+
+    #[test]
+    fn invalid_params() {
+        let _ = KdfParams::from_fields(KdfAlgorithm::Argon2id, 1, 2, 1).unwrap_err();
+        let _ = KdfParams::from_fields(KdfAlgorithm::Argon2id, 19_456, 0, 1).unwrap_err();
+        let _ = KdfParams::from_fields(KdfAlgorithm::Pbkdf2HmacSha256, 0, 0, 1).unwrap_err();
+        KdfParams::from_fields(KdfAlgorithm::Argon2id, 19_456, 2, 1).unwrap();
+    }
+
+    #[test]
+    fn derive_consistency() {
+        let salt = [7u8; 64];
+        for params in [KdfParams::default(), KdfParams::legacy_pbkdf2()] {
+            let mut out_1 = [0u8; 32];
+            let mut out_2 = [0u8; 32];
+            let mut out_3 = [0u8; 32];
+            params.derive(b"password", &salt, &mut out_1).unwrap();
+            params.derive(b"password", &salt, &mut out_2).unwrap();
+            params.derive(b"passwore", &salt, &mut out_3).unwrap();
+            assert_eq!(out_1, out_2);
+            assert_ne!(out_1, out_3);
+        }
+    }
+
+    #[test]
+    fn weaker_than() {
+        let default = KdfParams::default();
+        let stronger = KdfParams::from_fields(KdfAlgorithm::Argon2id, 65_536, 3, 1).unwrap();
+
+        assert!(KdfParams::legacy_pbkdf2().is_weaker_than(&default));
+        assert!(!default.is_weaker_than(&KdfParams::legacy_pbkdf2()));
+        assert!(default.is_weaker_than(&stronger));
+        assert!(!stronger.is_weaker_than(&default));
+        assert!(!default.is_weaker_than(&default));
+    }
+
+    #[test]
+    fn calibrate() {
+        assert_eq!(
+            KdfParams::calibrate(Duration::ZERO).unwrap(),
+            KdfParams::default()
+        );
+        let calibrated = KdfParams::calibrate(Duration::from_millis(200)).unwrap();
+        assert_eq!(calibrated.algorithm(), KdfAlgorithm::Argon2id);
+        assert!(!calibrated.is_weaker_than(&KdfParams::default()));
+    }
+}
@@ -1,14 +1,13 @@
 //! Functionality related to hashing different types.
 use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre;
 use rand_chacha::{
     rand_core::{RngCore, SeedableRng},
     ChaCha20Rng,
 };
+use zeroize::Zeroizing;
 
-use super::{
-    super::encryption::encrypted::{Aes256Key, Aes256Nonce},
-    hashed::*,
-};
+use super::{super::encryption::encrypted::Aes256Nonce, hashed::*, kdf::KdfParams};
 
 /// Implementors of this trait can be hashed and salted into a [Hashed].
 ///
@@ -21,22 +20,27 @@ use super::{
 /// The easiest way to implement [IntoHashed::check_match] is to convert the type to a `u8` slice
 /// then return [Hashed::check_match].
 pub trait IntoHashed<const H: usize, const S: usize> {
-    /// Hash and salt using a randomly-generated salt.
+    /// Hash and salt using a randomly-generated salt and the given [KdfParams].
     #[allow(dead_code)]
-    fn into_hashed_rand_salt(self) -> Hashed<H, S>
+    fn into_hashed_rand_salt(self, kdf_params: KdfParams) -> eyre::Result<Hashed<H, S>>
     where
         Self: Sized,
     {
         let mut salt: Salt<S> = [0u8; S];
         let mut rng = ChaCha20Rng::from_entropy();
         rng.fill_bytes(&mut salt);
-        self.into_hashed_with_salt(salt)
+        self.into_hashed_with_salt(salt, kdf_params)
     }
 
-    /// Hash and salt using a given salt.
-    fn into_hashed_with_salt(self, salt: Salt<S>) -> Hashed<H, S>;
+    /// Hash and salt using a given salt and [KdfParams].
+    fn into_hashed_with_salt(
+        self,
+        salt: Salt<S>,
+        kdf_params: KdfParams,
+    ) -> eyre::Result<Hashed<H, S>>;
 
-    /// Check whether the given entity, when hashed, matches the given salt.
+    /// Check whether the given entity, when hashed with the same salt and [KdfParams], matches the
+    /// given [Hashed].
     #[allow(dead_code)]
     fn check_match(self, hashed: &Hashed<H, S>) -> bool;
 }
@@ -45,27 +49,35 @@ pub trait IntoHashed<const H: usize, const S: usize> {
 macro_rules! impl_into_hashed_byte_vec {
     ($($t:ty),+) => {
         $(impl<const H: usize, const S: usize> IntoHashed<H, S> for $t {
-            fn into_hashed_with_salt(self, salt: Salt<S>) -> Hashed<H, S> {
-                let byte_vec: Vec<u8> = self.into();
-                Hashed::hash_with_salt(&byte_vec, salt)
+            fn into_hashed_with_salt(
+                self,
+                salt: Salt<S>,
+                kdf_params: KdfParams,
+            ) -> eyre::Result<Hashed<H, S>> {
+                let byte_vec: Zeroizing<Vec<u8>> = Zeroizing::new(self.into());
+                Hashed::hash_with_salt(&byte_vec, salt, kdf_params)
             }
 
             fn check_match(self, hashed: &Hashed<H, S>) -> bool {
-                let byte_vec: Vec<u8> = self.into();
+                let byte_vec: Zeroizing<Vec<u8>> = Zeroizing::new(self.into());
                 hashed.check_match(&byte_vec)
             }
         })*
     }
 }
-impl_into_hashed_byte_vec!(Vec<u8>, &[u8], String, &str, Aes256Key, Aes256Nonce);
+impl_into_hashed_byte_vec!(Vec<u8>, &[u8], String, &str, [u8; 32], Aes256Nonce);
 
 macro_rules! impl_into_hashed_camino {
     ($($t:ty),+) => {
         $(impl<const H: usize, const S: usize> IntoHashed<H, S> for $t {
-            fn into_hashed_with_salt(self, salt: Salt<S>) -> Hashed<H, S> {
+            fn into_hashed_with_salt(
+                self,
+                salt: Salt<S>,
+                kdf_params: KdfParams,
+            ) -> eyre::Result<Hashed<H, S>> {
                 let path_string = self.to_string();
                 let byte_slice: &[u8] = path_string.as_bytes();
-                Hashed::hash_with_salt(byte_slice, salt)
+                Hashed::hash_with_salt(byte_slice, salt, kdf_params)
             }
 
             fn check_match(self, hashed: &Hashed<H, S>) -> bool {
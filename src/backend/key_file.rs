@@ -0,0 +1,197 @@
+//! Functionality for key files, which may be required alongside an account's password.
+use std::{
+    fs::File,
+    io::{self, Write},
+};
+
+use camino::Utf8Path;
+use color_eyre::eyre::{self, eyre};
+use rand_chacha::{
+    rand_core::{RngCore, SeedableRng},
+    ChaCha20Rng,
+};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use super::{
+    encryption::stream::copy_secret,
+    hashing::hashed::{Hash, Salt},
+    secret::Secret,
+};
+
+// The number of random bytes in a generated key file.
+const GENERATED_KEY_FILE_SIZE: usize = 64;
+
+// Prepended to everything hashed into a [KeyFileCheck], so it can't be mistaken for any other hash.
+const CHECK_DOMAIN: &[u8] = b"dgruft key file check";
+
+/// A key file: any local file whose contents must be provided alongside an account's password to
+/// unlock the account.
+///
+/// Only the SHA-256 digest of the file is kept. It is mixed into the password before the password
+/// is hashed, so the account's `key` can't be decrypted without both. The digest is zeroed when
+/// dropped, and is never shown by the [fmt::Debug](std::fmt::Debug) implementation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyFile {
+    digest: Secret<Hash<32>>,
+}
+impl KeyFile {
+    /// Read the key file at the given path.
+    pub fn open<P>(path: P) -> eyre::Result<Self>
+    where
+        P: AsRef<Utf8Path>,
+    {
+        let path = path.as_ref();
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(eyre!("Key file {} does not exist.", path))
+            }
+            Err(err) => return Err(eyre!("Failed to open key file {}: {}", path, err)),
+        };
+        if file.metadata()?.len() == 0 {
+            return Err(eyre!("Key file {} is empty.", path));
+        }
+
+        let mut hasher = Sha256::new();
+        copy_secret(&mut file, &mut hasher)?;
+        Ok(Self::from_digest(hasher.finalize().into()))
+    }
+
+    /// Generate a new key file of random bytes at the given path. Fails if the path already
+    /// exists.
+    pub fn generate<P>(path: P) -> eyre::Result<Self>
+    where
+        P: AsRef<Utf8Path>,
+    {
+        let path = path.as_ref();
+        let mut bytes = Zeroizing::new([0u8; GENERATED_KEY_FILE_SIZE]);
+        ChaCha20Rng::from_entropy().fill_bytes(bytes.as_mut());
+
+        let mut file = match File::options().write(true).create_new(true).open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(eyre!("Key file {} already exists.", path))
+            }
+            Err(err) => return Err(eyre!("Failed to create key file {}: {}", path, err)),
+        };
+        file.write_all(bytes.as_ref())?;
+        file.sync_all()?;
+
+        Ok(Self::from_bytes(bytes.as_ref()))
+    }
+
+    /// Create a [KeyFile] from the contents of a key file.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::from_digest(Sha256::digest(bytes).into())
+    }
+
+    // Helper function: Create a [KeyFile] from its digest.
+    fn from_digest(digest: Hash<32>) -> Self {
+        Self {
+            digest: Secret::new(digest),
+        }
+    }
+
+    /// Return the SHA-256 digest of this [KeyFile].
+    pub fn digest(&self) -> &Hash<32> {
+        self.digest.expose()
+    }
+
+    /// Create a new [KeyFileCheck] for this [KeyFile] using a random salt.
+    pub fn new_check(&self) -> KeyFileCheck {
+        let mut salt: Salt<32> = [0u8; 32];
+        ChaCha20Rng::from_entropy().fill_bytes(&mut salt);
+        KeyFileCheck::from_fields(salt, self.check_hash(&salt))
+    }
+
+    /// Check whether this [KeyFile] is the one the given [KeyFileCheck] was made from.
+    pub fn check_match(&self, check: &KeyFileCheck) -> bool {
+        self.check_hash(check.salt()) == *check.hash()
+    }
+
+    // Helper function: Hash the digest of this [KeyFile] with the given salt.
+    fn check_hash(&self, salt: &Salt<32>) -> Hash<32> {
+        Sha256::new()
+            .chain_update(CHECK_DOMAIN)
+            .chain_update(salt)
+            .chain_update(self.digest())
+            .finalize()
+            .into()
+    }
+}
+
+/// The salted hash of a [KeyFile], stored for each account which requires one.
+///
+/// This is only used to tell the user that they gave the wrong key file rather than the wrong
+/// password. Generated key files are random, so the hash can't be used to guess them.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct KeyFileCheck {
+    salt: Salt<32>,
+    hash: Hash<32>,
+}
+impl KeyFileCheck {
+    /// Create a [KeyFileCheck] from its fields.
+    pub fn from_fields(salt: Salt<32>, hash: Hash<32>) -> Self {
+        Self { salt, hash }
+    }
+
+    /// Return the salt of this [KeyFileCheck].
+    pub fn salt(&self) -> &Salt<32> {
+        &self.salt
+    }
+
+    /// Return the hash of this [KeyFileCheck].
+    pub fn hash(&self) -> &Hash<32> {
+        &self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use camino::Utf8PathBuf;
+    use pretty_assertions::{assert_eq, assert_ne};
+
+    use super::*;
+
+    fn test_path(name: &str) -> Utf8PathBuf {
+        let path = Utf8PathBuf::from(format!("tests/{name}"));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn generate_open() {
+        let path = test_path("key_file_generate_open");
+        let generated = KeyFile::generate(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap().len(), GENERATED_KEY_FILE_SIZE);
+        assert_eq!(KeyFile::open(&path).unwrap(), generated);
+        assert_eq!(KeyFile::from_bytes(&fs::read(&path).unwrap()), generated);
+
+        // Existing files are never overwritten.
+        let err = KeyFile::generate(&path).unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+        assert_eq!(KeyFile::open(&path).unwrap(), generated);
+        fs::remove_file(&path).unwrap();
+
+        let err = KeyFile::open(&path).unwrap_err();
+        assert!(err.to_string().contains("does not exist"));
+
+        fs::write(&path, b"").unwrap();
+        let err = KeyFile::open(&path).unwrap_err();
+        assert!(err.to_string().contains("is empty"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn check_match() {
+        let key_file = KeyFile::from_bytes(b"my key file");
+        let check = key_file.new_check();
+        assert!(key_file.check_match(&check));
+        assert!(!KeyFile::from_bytes(b"my key filf").check_match(&check));
+        assert_ne!(key_file.new_check(), check);
+        assert!(!format!("{key_file:?}").contains(&format!("{:?}", key_file.digest())));
+    }
+}
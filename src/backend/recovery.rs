@@ -0,0 +1,239 @@
+//! Functionality for recovery codes, which can unlock an account whose password has been lost.
+use std::str::FromStr;
+
+use color_eyre::eyre::{self, eyre};
+use rand_chacha::{
+    rand_core::{RngCore, SeedableRng},
+    ChaCha20Rng,
+};
+use sha2::{Digest, Sha256};
+use zeroize::{Zeroize, Zeroizing};
+
+use super::{
+    encryption::encrypted::{Aes256Key, Encrypted},
+    secret::Secret,
+};
+
+// The number of random bytes in a [RecoveryCode].
+const RECOVERY_CODE_SIZE: usize = 20;
+
+// The number of characters in each dash-separated group of an encoded code.
+const GROUP_LEN: usize = 4;
+
+// The Crockford base-32 alphabet, which leaves out the easily-confused I, L, O & U.
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+// Prepended to the [RecoveryCode] when deriving its [Aes256Key].
+const KEY_DOMAIN: &[u8] = b"dgruft recovery key";
+
+/// A high-entropy recovery code, which can unlock an account & set a new password when the old
+/// password is lost.
+///
+/// The code is 160 random bits, shown to the user as 32 Crockford base-32 characters in groups of
+/// four. It is zeroed when dropped, and is never shown by the [fmt::Debug](std::fmt::Debug)
+/// implementation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecoveryCode {
+    bytes: Secret<[u8; RECOVERY_CODE_SIZE]>,
+}
+impl RecoveryCode {
+    /// Generate a new, random [RecoveryCode].
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; RECOVERY_CODE_SIZE];
+        ChaCha20Rng::from_entropy().fill_bytes(&mut bytes);
+        let recovery_code = Self {
+            bytes: Secret::new(bytes),
+        };
+        bytes.zeroize();
+        recovery_code
+    }
+
+    /// Encode this [RecoveryCode] in the human-typeable form shown to the user, e.g.
+    /// `7KQ2-M0XR-...`.
+    pub fn encode(&self) -> Secret<String> {
+        encode_code(self.bytes.expose())
+    }
+
+    /// Derive the [Aes256Key] which this [RecoveryCode] stands for.
+    pub fn key(&self) -> Aes256Key {
+        Aes256Key::from_bytes(
+            Sha256::new()
+                .chain_update(KEY_DOMAIN)
+                .chain_update(self.bytes.expose())
+                .finalize()
+                .into(),
+        )
+    }
+}
+impl FromStr for RecoveryCode {
+    type Err = eyre::Report;
+
+    /// Parse an encoded [RecoveryCode]. Case, dashes & whitespace are ignored, and the letters
+    /// I, L & O are read as the digits they resemble.
+    fn from_str(s: &str) -> eyre::Result<Self> {
+        Ok(Self {
+            bytes: Secret::new(*parse_code::<RECOVERY_CODE_SIZE>(s, "recovery code")?),
+        })
+    }
+}
+
+/// The encrypted keys which let an account be unlocked with its [RecoveryCode].
+///
+/// - The `encrypted_key` is the account's `key`, encrypted with the [RecoveryCode]'s [Aes256Key].
+///
+/// - The `encrypted_recovery_key` is the [RecoveryCode]'s [Aes256Key], encrypted with the account's
+///   `key`. It lets the `encrypted_key` be replaced when the account's `key` is rotated, without
+///   needing the [RecoveryCode] itself.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AccountRecovery {
+    encrypted_key: Encrypted,
+    encrypted_recovery_key: Encrypted,
+}
+impl AccountRecovery {
+    /// Create an [AccountRecovery] from its fields.
+    pub fn from_fields(encrypted_key: Encrypted, encrypted_recovery_key: Encrypted) -> Self {
+        Self {
+            encrypted_key,
+            encrypted_recovery_key,
+        }
+    }
+
+    /// Return the `encrypted_key` of this [AccountRecovery].
+    pub fn encrypted_key(&self) -> &Encrypted {
+        &self.encrypted_key
+    }
+
+    /// Return the `encrypted_recovery_key` of this [AccountRecovery].
+    pub fn encrypted_recovery_key(&self) -> &Encrypted {
+        &self.encrypted_recovery_key
+    }
+}
+
+/// Encode the bytes of a code in groups of Crockford base-32 characters. The number of bytes must
+/// be a multiple of 5.
+pub(super) fn encode_code(bytes: &[u8]) -> Secret<String> {
+    let encoded_len = bytes.len() * 8 / 5;
+    let mut encoded = String::with_capacity(encoded_len + encoded_len / GROUP_LEN);
+    let mut buffer: u64 = 0;
+    let mut num_bits = 0;
+    let mut num_chars = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | u64::from(*byte);
+        num_bits += 8;
+        while num_bits >= 5 {
+            num_bits -= 5;
+            if num_chars > 0 && num_chars % GROUP_LEN == 0 {
+                encoded.push('-');
+            }
+            encoded.push(ALPHABET[((buffer >> num_bits) & 0x1f) as usize] as char);
+            num_chars += 1;
+        }
+    }
+    buffer.zeroize();
+    Secret::new(encoded)
+}
+
+/// Parse a code encoded by [encode_code] into exactly `N` bytes. Case, dashes & whitespace are
+/// ignored, and the letters I, L & O are read as the digits they resemble. The `code_name` is
+/// used in error messages.
+pub(super) fn parse_code<const N: usize>(
+    s: &str,
+    code_name: &str,
+) -> eyre::Result<Zeroizing<[u8; N]>> {
+    let mut bytes = Zeroizing::new([0u8; N]);
+    let mut buffer: u64 = 0;
+    let mut num_bits = 0;
+    let mut num_bytes = 0;
+    for c in s.chars().filter(|c| *c != '-' && !c.is_whitespace()) {
+        let c = match c.to_ascii_uppercase() {
+            'O' => '0',
+            'I' | 'L' => '1',
+            c => c,
+        };
+        let value = ALPHABET
+            .iter()
+            .position(|a| *a as char == c)
+            .ok_or_else(|| eyre!("Invalid {}: unexpected character '{}'.", code_name, c))?;
+        if num_bytes == N {
+            return Err(eyre!("Invalid {}: too long.", code_name));
+        }
+        buffer = (buffer << 5) | value as u64;
+        num_bits += 5;
+        if num_bits >= 8 {
+            num_bits -= 8;
+            bytes[num_bytes] = (buffer >> num_bits) as u8;
+            num_bytes += 1;
+        }
+    }
+    buffer.zeroize();
+    if num_bytes < N {
+        return Err(eyre!("Invalid {}: too short.", code_name));
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::{assert_eq, assert_ne};
+
+    use super::*;
+
+    #[test]
+    fn encode_parse() {
+        let recovery_code = RecoveryCode::generate();
+        assert_ne!(recovery_code, RecoveryCode::generate());
+
+        // Each character encodes 5 bits.
+        let encoded_len = RECOVERY_CODE_SIZE * 8 / 5;
+        let encoded = recovery_code.encode();
+        assert_eq!(
+            encoded.expose().len(),
+            encoded_len + encoded_len / GROUP_LEN - 1
+        );
+        assert!(encoded
+            .expose()
+            .split('-')
+            .all(|group| group.len() == GROUP_LEN));
+        assert_eq!(
+            encoded.expose().parse::<RecoveryCode>().unwrap(),
+            recovery_code
+        );
+
+        // Typed sloppily.
+        let sloppy = encoded
+            .expose()
+            .to_lowercase()
+            .replace('-', " ")
+            .replace('0', "o")
+            .replace('1', "l");
+        assert_eq!(sloppy.parse::<RecoveryCode>().unwrap(), recovery_code);
+        assert_eq!(
+            sloppy.parse::<RecoveryCode>().unwrap().key(),
+            recovery_code.key()
+        );
+
+        let _ = encoded.expose()[1..].parse::<RecoveryCode>().unwrap_err();
+        let _ = format!("{}0", encoded.expose())
+            .parse::<RecoveryCode>()
+            .unwrap_err();
+        let _ = encoded
+            .expose()
+            .replacen(|c: char| c.is_ascii_alphanumeric(), "U", 1)
+            .parse::<RecoveryCode>()
+            .unwrap_err();
+        assert!(!format!("{recovery_code:?}").contains(encoded.expose()));
+    }
+
+    #[test]
+    fn known_encoding() {
+        let recovery_code: RecoveryCode =
+            "0000-0000-0000-0000-0000-0000-0000-001Z".parse().unwrap();
+        let mut bytes = [0u8; RECOVERY_CODE_SIZE];
+        bytes[RECOVERY_CODE_SIZE - 1] = 0x3f;
+        assert_eq!(recovery_code.bytes.expose(), &bytes);
+        assert_eq!(
+            recovery_code.encode().expose(),
+            "0000-0000-0000-0000-0000-0000-0000-001Z"
+        );
+    }
+}
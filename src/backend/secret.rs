@@ -0,0 +1,50 @@
+//! Functionality for keeping secrets out of memory & out of logs.
+use std::fmt;
+
+use zeroize::Zeroize;
+
+/// A secret value, such as a password or decrypted data. The value is zeroed when the [Secret] is
+/// dropped, and is never shown by the [Secret]'s [fmt::Debug] implementation.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret<T: Zeroize>(T);
+impl<T: Zeroize> Secret<T> {
+    /// Wrap a value in a [Secret].
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    /// Return a reference to the secret value.
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+impl<T: Zeroize> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+impl<T: Zeroize> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(<redacted>)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn redacted_debug() {
+        let secret = Secret::new(String::from("hunter2"));
+        assert_eq!(secret.expose(), "hunter2");
+        assert!(!format!("{secret:?}").contains("hunter2"));
+        assert!(!format!("{secret:#?}").contains("hunter2"));
+    }
+}
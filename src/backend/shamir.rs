@@ -0,0 +1,365 @@
+//! Functionality for key shares, which split a key so that any `threshold` of them can rebuild it.
+use std::str::FromStr;
+
+use color_eyre::eyre::{self, eyre};
+use rand_chacha::{
+    rand_core::{RngCore, SeedableRng},
+    ChaCha20Rng,
+};
+use sha2::{Digest, Sha256};
+use zeroize::{Zeroize, Zeroizing};
+
+use super::{
+    encryption::encrypted::Aes256Key,
+    recovery::{encode_code, parse_code},
+    secret::Secret,
+};
+
+// The version of the encoded [KeyShare] format.
+const FORMAT_VERSION: u8 = 1;
+
+// The number of random bytes identifying the split a [KeyShare] came from.
+const SET_ID_SIZE: usize = 4;
+
+// The number of bytes of the [KeyShare] checksum.
+const CHECKSUM_SIZE: usize = 5;
+
+// The number of bytes of the [KeyShare] before its checksum: the format version, threshold, count,
+// index, set id & value.
+const PAYLOAD_SIZE: usize = 4 + SET_ID_SIZE + 32;
+
+// The number of bytes of an encoded [KeyShare].
+const ENCODED_SIZE: usize = PAYLOAD_SIZE + CHECKSUM_SIZE;
+
+// Prepended to every encoded [KeyShare], so it can't be mistaken for any other code.
+const PREFIX: &str = "DGRUFT-SHARE";
+
+// Prepended to the payload of a [KeyShare] when computing its checksum.
+const CHECKSUM_DOMAIN: &[u8] = b"dgruft key share checksum";
+
+/// One share of an [Aes256Key] split with Shamir's secret sharing over GF(256). Any `threshold` of
+/// the `count` shares of a split rebuild the [Aes256Key]; fewer reveal nothing about it.
+///
+/// Each [KeyShare] is shown to the user as `DGRUFT-SHARE-` followed by Crockford base-32
+/// characters, which encode the format version, threshold, count, index, the id of its split, the
+/// share itself & a checksum, so typos are caught before the shares are combined. The share is
+/// zeroed when dropped, and is never shown by the [fmt::Debug](std::fmt::Debug) implementation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyShare {
+    set_id: [u8; SET_ID_SIZE],
+    threshold: u8,
+    count: u8,
+    index: u8,
+    value: Secret<[u8; 32]>,
+}
+impl KeyShare {
+    /// Split an [Aes256Key] into `count` [KeyShare]s, any `threshold` of which rebuild it.
+    pub fn split(key: &Aes256Key, threshold: u8, count: u8) -> eyre::Result<Vec<Self>> {
+        if threshold < 2 {
+            return Err(eyre!(
+                "At least 2 key shares must be needed to rebuild the key."
+            ));
+        }
+        if threshold > count {
+            return Err(eyre!(
+                "Can't need {} key shares when only {} are made.",
+                threshold,
+                count
+            ));
+        }
+
+        let mut rng = ChaCha20Rng::from_entropy();
+        let mut set_id = [0u8; SET_ID_SIZE];
+        rng.fill_bytes(&mut set_id);
+
+        // Each byte of the key is the constant term of its own random polynomial of degree
+        // `threshold - 1`. Each share holds the value of every polynomial at its index.
+        let mut coefficients = Zeroizing::new(vec![0u8; (threshold as usize - 1) * 32]);
+        rng.fill_bytes(&mut coefficients);
+        let shares = (1..=count)
+            .map(|index| {
+                let mut value = [0u8; 32];
+                for (i, byte) in value.iter_mut().enumerate() {
+                    // Horner's method, from the highest coefficient down to the key byte.
+                    let mut y = 0u8;
+                    for coefficient in coefficients.chunks_exact(32).rev() {
+                        y = gf_mul(y, index) ^ coefficient[i];
+                    }
+                    *byte = gf_mul(y, index) ^ key.as_bytes()[i];
+                }
+                let share = Self {
+                    set_id,
+                    threshold,
+                    count,
+                    index,
+                    value: Secret::new(value),
+                };
+                value.zeroize();
+                share
+            })
+            .collect();
+        Ok(shares)
+    }
+
+    /// Rebuild the [Aes256Key] which the given [KeyShare]s were split from. At least `threshold`
+    /// distinct [KeyShare]s of the same split must be given.
+    pub fn combine(shares: &[Self]) -> eyre::Result<Aes256Key> {
+        let first = shares
+            .first()
+            .ok_or_else(|| eyre!("No key shares given."))?;
+        let mut distinct: Vec<&Self> = Vec::with_capacity(first.threshold as usize);
+        for share in shares {
+            if share.set_id != first.set_id
+                || share.threshold != first.threshold
+                || share.count != first.count
+            {
+                return Err(eyre!("Key shares are from different splits."));
+            }
+            match distinct.iter().find(|other| other.index == share.index) {
+                Some(other) if other.value != share.value => {
+                    return Err(eyre!("Key share {} was given twice.", share.index))
+                }
+                Some(_) => {}
+                None => distinct.push(share),
+            }
+        }
+        if distinct.len() < first.threshold as usize {
+            return Err(eyre!(
+                "{} key shares are needed, but only {} were given.",
+                first.threshold,
+                distinct.len()
+            ));
+        }
+        distinct.truncate(first.threshold as usize);
+
+        // Lagrange interpolation at 0.
+        let mut key = Zeroizing::new([0u8; 32]);
+        for share in &distinct {
+            let mut basis = 1u8;
+            for other in &distinct {
+                if other.index != share.index {
+                    basis = gf_mul(
+                        basis,
+                        gf_mul(other.index, gf_inv(other.index ^ share.index)),
+                    );
+                }
+            }
+            for (byte, y) in key.iter_mut().zip(share.value.expose()) {
+                *byte ^= gf_mul(*y, basis);
+            }
+        }
+        Ok(Aes256Key::from_bytes(*key))
+    }
+
+    /// Return the number of [KeyShare]s needed to rebuild the key.
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Return the number of [KeyShare]s the key was split into.
+    pub fn count(&self) -> u8 {
+        self.count
+    }
+
+    /// Return the index of this [KeyShare], from 1 to `count`.
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Encode this [KeyShare] in the human-typeable form shown to the user, e.g.
+    /// `DGRUFT-SHARE-04G0-...`.
+    pub fn encode(&self) -> Secret<String> {
+        let mut bytes = Zeroizing::new([0u8; ENCODED_SIZE]);
+        bytes[..4].copy_from_slice(&[FORMAT_VERSION, self.threshold, self.count, self.index]);
+        bytes[4..4 + SET_ID_SIZE].copy_from_slice(&self.set_id);
+        bytes[4 + SET_ID_SIZE..PAYLOAD_SIZE].copy_from_slice(self.value.expose());
+        let checksum = checksum(&bytes[..PAYLOAD_SIZE]);
+        bytes[PAYLOAD_SIZE..].copy_from_slice(&checksum);
+        Secret::new(format!("{}-{}", PREFIX, encode_code(&bytes[..]).expose()))
+    }
+}
+impl FromStr for KeyShare {
+    type Err = eyre::Report;
+
+    /// Parse an encoded [KeyShare]. Case, dashes & whitespace are ignored, and the letters I, L &
+    /// O are read as the digits they resemble.
+    fn from_str(s: &str) -> eyre::Result<Self> {
+        let compact: Zeroizing<String> = Zeroizing::new(
+            s.chars()
+                .filter(|c| *c != '-' && !c.is_whitespace())
+                .collect(),
+        );
+        let prefix = PREFIX.replace('-', "");
+        let code = match compact.get(..prefix.len()) {
+            Some(start) if start.eq_ignore_ascii_case(&prefix) => &compact[prefix.len()..],
+            _ => {
+                return Err(eyre!(
+                    "Invalid key share: expected it to start with {}.",
+                    PREFIX
+                ))
+            }
+        };
+        let bytes = parse_code::<ENCODED_SIZE>(code, "key share")?;
+        if checksum(&bytes[..PAYLOAD_SIZE]) != bytes[PAYLOAD_SIZE..] {
+            return Err(eyre!(
+                "Invalid key share: checksum mismatch. Check it for typos."
+            ));
+        }
+        let [version, threshold, count, index] = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if version != FORMAT_VERSION {
+            return Err(eyre!("Invalid key share: unknown version {}.", version));
+        }
+        if threshold < 2 || threshold > count || index == 0 || index > count {
+            return Err(eyre!("Invalid key share: inconsistent header."));
+        }
+        let mut set_id = [0u8; SET_ID_SIZE];
+        set_id.copy_from_slice(&bytes[4..4 + SET_ID_SIZE]);
+        let mut value = [0u8; 32];
+        value.copy_from_slice(&bytes[4 + SET_ID_SIZE..PAYLOAD_SIZE]);
+        let share = Self {
+            set_id,
+            threshold,
+            count,
+            index,
+            value: Secret::new(value),
+        };
+        value.zeroize();
+        Ok(share)
+    }
+}
+
+// Helper function: Compute the checksum of a [KeyShare] payload.
+fn checksum(payload: &[u8]) -> [u8; CHECKSUM_SIZE] {
+    let digest = Sha256::new()
+        .chain_update(CHECKSUM_DOMAIN)
+        .chain_update(payload)
+        .finalize();
+    let mut checksum = [0u8; CHECKSUM_SIZE];
+    checksum.copy_from_slice(&digest[..CHECKSUM_SIZE]);
+    checksum
+}
+
+// Helper function: Multiply two elements of GF(256), using the AES polynomial
+// x^8 + x^4 + x^3 + x + 1. Runs in constant time.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    for _ in 0..8 {
+        product ^= a & 0u8.wrapping_sub(b & 1);
+        a = (a << 1) ^ (0x1b & 0u8.wrapping_sub(a >> 7));
+        b >>= 1;
+    }
+    product
+}
+
+// Helper function: Invert a non-zero element of GF(256), as a^254.
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1u8;
+    let mut power = a;
+    let mut exponent = 254u8;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = gf_mul(result, power);
+        }
+        power = gf_mul(power, power);
+        exponent >>= 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::{assert_eq, assert_ne};
+
+    use super::{super::encryption::encrypted::new_rand_key, *};
+
+    #[test]
+    fn gf_arithmetic() {
+        // From FIPS 197, section 4.2.
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        assert_eq!(gf_mul(0x57, 0x13), 0xfe);
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn split_combine() {
+        let key = new_rand_key();
+        let shares = KeyShare::split(&key, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+        assert!(shares
+            .iter()
+            .enumerate()
+            .all(|(i, share)| share.index() as usize == i + 1
+                && share.threshold() == 3
+                && share.count() == 5));
+
+        // Any 3 shares rebuild the key.
+        for subset in [[0, 1, 2], [4, 2, 0], [1, 3, 4], [2, 3, 4]] {
+            let subset: Vec<KeyShare> = subset.iter().map(|i| shares[*i].clone()).collect();
+            assert_eq!(KeyShare::combine(&subset).unwrap(), key);
+        }
+        assert_eq!(KeyShare::combine(&shares).unwrap(), key);
+
+        // Fewer than 3 don't.
+        let err = KeyShare::combine(&shares[..2]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "3 key shares are needed, but only 2 were given."
+        );
+        let err = KeyShare::combine(&[shares[0].clone(), shares[0].clone(), shares[1].clone()])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "3 key shares are needed, but only 2 were given."
+        );
+
+        // Shares of different splits can't be mixed.
+        let other_shares = KeyShare::split(&key, 3, 5).unwrap();
+        assert_ne!(other_shares[0], shares[0]);
+        let err = KeyShare::combine(&[
+            shares[0].clone(),
+            shares[1].clone(),
+            other_shares[2].clone(),
+        ])
+        .unwrap_err();
+        assert_eq!(err.to_string(), "Key shares are from different splits.");
+
+        let _ = KeyShare::split(&key, 1, 5).unwrap_err();
+        let _ = KeyShare::split(&key, 4, 3).unwrap_err();
+        assert_eq!(KeyShare::split(&key, 255, 255).unwrap().len(), 255);
+    }
+
+    #[test]
+    fn encode_parse() {
+        let shares = KeyShare::split(&new_rand_key(), 2, 3).unwrap();
+        let encoded = shares[1].encode();
+        assert!(encoded.expose().starts_with("DGRUFT-SHARE-"));
+        assert_eq!(encoded.expose().parse::<KeyShare>().unwrap(), shares[1]);
+        assert_eq!(
+            format!("  {}\n", encoded.expose().to_lowercase().replace('-', " "))
+                .parse::<KeyShare>()
+                .unwrap(),
+            shares[1]
+        );
+
+        // Typos are caught by the checksum.
+        let last = encoded.expose().chars().last().unwrap();
+        let typo = format!(
+            "{}{}",
+            &encoded.expose()[..encoded.expose().len() - 1],
+            if last == '0' { '1' } else { '0' }
+        );
+        let err = typo.parse::<KeyShare>().unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch"));
+
+        let err = encoded.expose()["DGRUFT-".len()..]
+            .parse::<KeyShare>()
+            .unwrap_err();
+        assert!(err.to_string().contains("expected it to start with"));
+        let _ = encoded.expose()[..encoded.expose().len() - 1]
+            .parse::<KeyShare>()
+            .unwrap_err();
+        assert!(!format!("{:?}", shares[1]).contains(&format!("{:?}", shares[1].value.expose())));
+    }
+}
@@ -0,0 +1,780 @@
+//! Functionality for sharing credentials & files between accounts with public-key encryption.
+use std::fmt;
+
+use camino::Utf8Path;
+use color_eyre::eyre::{self, eyre};
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+use super::{
+    credential::{Credential, CredentialField},
+    encryption::{
+        associated_data::{AssociatedData, EntityType},
+        cipher_suite::CipherSuite,
+        encrypted::{
+            new_rand_key, Aes256Key, Encrypted, Nonce, TryFromEncrypted, TryIntoEncrypted,
+        },
+    },
+    file_data::{FileData, Filename},
+    secret::Secret,
+};
+
+// The [AssociatedData] field of a shared item's key encrypted with its owner's `key`.
+const ITEM_KEY_FIELD: &str = "item_key";
+
+// The [AssociatedData] field of a shared item's key encrypted to a recipient's [PublicKey].
+const SHARED_ITEM_KEY_FIELD: &str = "shared_item_key";
+
+// Prepended to the Diffie-Hellman shared secret & both public keys when deriving the [Aes256Key]
+// which encrypts a shared item's key to a recipient.
+const WRAP_KEY_DOMAIN: &[u8] = b"dgruft shared item key";
+
+/// An account's X25519 private key. Other accounts encrypt the keys of the items they share with
+/// the account to its public key.
+///
+/// The private key is zeroed when dropped, and is never shown by the [fmt::Debug] implementation.
+#[derive(Clone)]
+pub struct PrivateKey(StaticSecret);
+impl PrivateKey {
+    /// Generate a new, random [PrivateKey].
+    pub fn generate() -> Self {
+        Self(StaticSecret::random_from_rng(ChaCha20Rng::from_entropy()))
+    }
+
+    /// Return the public key of this [PrivateKey].
+    pub fn public_key(&self) -> [u8; 32] {
+        PublicKey::from(&self.0).to_bytes()
+    }
+}
+impl fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PrivateKey(<redacted>)")
+    }
+}
+impl PartialEq for PrivateKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_bytes() == other.0.as_bytes()
+    }
+}
+impl Eq for PrivateKey {}
+impl TryIntoEncrypted for &PrivateKey {
+    fn try_encrypt_with_all(
+        self,
+        key: &Aes256Key,
+        nonce: Nonce,
+        aad: &[u8],
+    ) -> eyre::Result<Encrypted> {
+        Encrypted::try_encrypt_bytes_key_nonce(self.0.as_bytes(), key, nonce, aad)
+    }
+}
+impl TryFromEncrypted for PrivateKey {
+    fn try_from_decrypted_bytes(decrypted_bytes: Vec<u8>) -> eyre::Result<Self> {
+        let decrypted_vec = Zeroizing::new(decrypted_bytes);
+        let key_bytes: Zeroizing<[u8; 32]> = Zeroizing::new(
+            decrypted_vec
+                .as_slice()
+                .try_into()
+                .map_err(|_| eyre!("TryFromEncrypted: Failed to convert from byte vector."))?,
+        );
+        Ok(Self(StaticSecret::from(*key_bytes)))
+    }
+}
+
+/// The X25519 keypair of an account.
+///
+/// - `public_key`: The public key, which is stored in plain.
+///
+/// - `encrypted_private_key`: The [PrivateKey], encrypted with the account's `key`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AccountKeypair {
+    public_key: [u8; 32],
+    encrypted_private_key: Encrypted,
+}
+impl AccountKeypair {
+    /// Create an [AccountKeypair] from its fields.
+    pub fn from_fields(public_key: [u8; 32], encrypted_private_key: Encrypted) -> Self {
+        Self {
+            public_key,
+            encrypted_private_key,
+        }
+    }
+
+    /// Return the `public_key` of this [AccountKeypair].
+    pub fn public_key(&self) -> &[u8; 32] {
+        &self.public_key
+    }
+
+    /// Return the `encrypted_private_key` of this [AccountKeypair].
+    pub fn encrypted_private_key(&self) -> &Encrypted {
+        &self.encrypted_private_key
+    }
+}
+
+/// The owner's shared copy of one of their [Credential]s. Sharing a [Credential] gives it a random
+/// item key, which encrypts the shared copy & is itself encrypted with the owner's `key`.
+///
+/// - `item_id`: The `id` of the owner's [Credential].
+///
+/// - `encrypted_item_key`: The item key, encrypted with the owner's `key`.
+///
+/// - `credential`: The shared copy, encrypted with the item key. Its `id` is the id of the shared
+///   item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedCredential {
+    item_id: String,
+    encrypted_item_key: Encrypted,
+    credential: Credential,
+}
+impl SharedCredential {
+    /// Create a new [SharedCredential] with the given `shared_id` from the owner's [Credential],
+    /// encrypting it with the item key & the item key with the owner's `key`.
+    pub fn try_new(
+        shared_id: &str,
+        credential: &Credential,
+        key: &Aes256Key,
+        item_key: &Aes256Key,
+        cipher_suite: CipherSuite,
+    ) -> eyre::Result<Self> {
+        let owner_username = credential.owner_username();
+        let shared_copy = Credential::try_new_with_id(
+            shared_id,
+            owner_username,
+            item_key,
+            cipher_suite,
+            credential.name::<Secret<String>>(key)?.expose(),
+            credential.username::<Secret<String>>(key)?.expose(),
+            credential.password::<Secret<String>>(key)?.expose(),
+            credential.notes::<Secret<String>>(key)?.expose(),
+        )?;
+        Ok(Self {
+            item_id: credential.id().to_owned(),
+            encrypted_item_key: encrypt_item_key(
+                owner_username,
+                EntityType::Credential,
+                shared_id,
+                key,
+                item_key,
+                cipher_suite,
+            )?,
+            credential: Credential::from_fields(
+                shared_copy.id().to_owned(),
+                owner_username.to_owned(),
+                shared_copy.encrypted_name().clone(),
+                shared_copy.encrypted_username().clone(),
+                shared_copy.encrypted_password().clone(),
+                shared_copy.encrypted_notes().clone(),
+                None,
+            ),
+        })
+    }
+
+    /// Create a [SharedCredential] from its fields.
+    pub fn from_fields(
+        item_id: String,
+        encrypted_item_key: Encrypted,
+        credential: Credential,
+    ) -> Self {
+        Self {
+            item_id,
+            encrypted_item_key,
+            credential,
+        }
+    }
+
+    /// Return a copy of this [SharedCredential] with the given field re-encrypted with the item
+    /// key, which is decrypted with the owner's `key`.
+    pub fn with_field(
+        &self,
+        key: &Aes256Key,
+        cipher_suite: CipherSuite,
+        field: CredentialField,
+        new_value: &str,
+    ) -> eyre::Result<Self> {
+        let item_key = self.item_key(key)?;
+        let encrypted_new_value =
+            self.credential
+                .encrypt_field(&item_key, cipher_suite, field, new_value)?;
+        let credential = &self.credential;
+        let mut encrypted_fields = [
+            credential.encrypted_name().clone(),
+            credential.encrypted_username().clone(),
+            credential.encrypted_password().clone(),
+            credential.encrypted_notes().clone(),
+        ];
+        encrypted_fields[match field {
+            CredentialField::Name => 0,
+            CredentialField::Username => 1,
+            CredentialField::Password => 2,
+            CredentialField::Notes => 3,
+        }] = encrypted_new_value;
+        let [encrypted_name, encrypted_username, encrypted_password, encrypted_notes] =
+            encrypted_fields;
+        Ok(Self {
+            credential: Credential::from_fields(
+                credential.id().to_owned(),
+                credential.owner_username().to_owned(),
+                encrypted_name,
+                encrypted_username,
+                encrypted_password,
+                encrypted_notes,
+                None,
+            ),
+            ..self.clone()
+        })
+    }
+
+    /// Decrypt the item key of this [SharedCredential] with the owner's `key`.
+    pub fn item_key(&self, key: &Aes256Key) -> eyre::Result<Aes256Key> {
+        decrypt_item_key(
+            self.owner_username(),
+            EntityType::Credential,
+            self.id(),
+            key,
+            &self.encrypted_item_key,
+        )
+    }
+
+    /// Re-encrypt the item key of this [SharedCredential] with the owner's new `key`.
+    pub fn rewrap(
+        &self,
+        old_key: &Aes256Key,
+        new_key: &Aes256Key,
+        cipher_suite: CipherSuite,
+    ) -> eyre::Result<Self> {
+        Ok(Self {
+            encrypted_item_key: encrypt_item_key(
+                self.owner_username(),
+                EntityType::Credential,
+                self.id(),
+                new_key,
+                &self.item_key(old_key)?,
+                cipher_suite,
+            )?,
+            ..self.clone()
+        })
+    }
+
+    /// Return the id of the shared item.
+    pub fn id(&self) -> &str {
+        self.credential.id()
+    }
+
+    /// Return the `owner_username` of this [SharedCredential].
+    pub fn owner_username(&self) -> &str {
+        self.credential.owner_username()
+    }
+
+    /// Return the `item_id` of this [SharedCredential].
+    pub fn item_id(&self) -> &str {
+        &self.item_id
+    }
+
+    /// Return the `encrypted_item_key` of this [SharedCredential].
+    pub fn encrypted_item_key(&self) -> &Encrypted {
+        &self.encrypted_item_key
+    }
+
+    /// Return the shared copy of the [Credential].
+    pub fn credential(&self) -> &Credential {
+        &self.credential
+    }
+}
+
+/// The owner's shared copy of one of their files, like a [SharedCredential].
+///
+/// - `item_id`: The `id` of the owner's [FileData].
+///
+/// - `encrypted_item_key`: The item key, encrypted with the owner's `key`.
+///
+/// - `file_data`: The [FileData] of the shared copy, whose filename & contents are encrypted with
+///   the item key. Its `id` is the id of the shared item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedFile {
+    item_id: String,
+    encrypted_item_key: Encrypted,
+    file_data: FileData,
+}
+impl SharedFile {
+    /// Create a new [SharedFile] with a new, random id from the owner's [FileData], encrypting the
+    /// filename with the item key & the item key with the owner's `key`. The shared copy is placed
+    /// in the given account file directory, but its contents must be encrypted to it separately.
+    pub fn try_new<P>(
+        account_file_dir: P,
+        file_data: &FileData,
+        key: &Aes256Key,
+        item_key: &Aes256Key,
+        cipher_suite: CipherSuite,
+        contents_nonce: Nonce,
+        contents_chunk_size: u32,
+    ) -> eyre::Result<Self>
+    where
+        P: AsRef<Utf8Path>,
+    {
+        let owner_username = file_data.owner_username();
+        let shared_copy = FileData::try_new(
+            account_file_dir,
+            owner_username,
+            item_key,
+            cipher_suite,
+            file_data.filename::<Secret<String>>(key)?.expose(),
+            contents_nonce,
+            contents_chunk_size,
+        )?;
+        // Shared copies are found through their share grants rather than by name, so they aren't
+        // indexed.
+        let shared_copy = FileData::from_fields(
+            shared_copy.id().to_owned(),
+            shared_copy.path().to_owned(),
+            shared_copy.stored_filename().clone(),
+            shared_copy.owner_username().to_owned(),
+            shared_copy.contents_nonce(),
+            shared_copy.contents_chunk_size(),
+            None,
+        );
+        Ok(Self {
+            item_id: file_data.id().to_owned(),
+            encrypted_item_key: encrypt_item_key(
+                owner_username,
+                EntityType::File,
+                shared_copy.id(),
+                key,
+                item_key,
+                cipher_suite,
+            )?,
+            file_data: shared_copy,
+        })
+    }
+
+    /// Create a [SharedFile] from its fields.
+    pub fn from_fields(
+        item_id: String,
+        encrypted_item_key: Encrypted,
+        file_data: FileData,
+    ) -> Self {
+        Self {
+            item_id,
+            encrypted_item_key,
+            file_data,
+        }
+    }
+
+    /// Return a copy of this [SharedFile] whose contents are encrypted with a new item key, header
+    /// [Nonce] & chunk size. The filename & the item key are re-encrypted with the owner's `key`.
+    pub fn with_item_key(
+        &self,
+        key: &Aes256Key,
+        new_item_key: &Aes256Key,
+        cipher_suite: CipherSuite,
+        contents_nonce: Nonce,
+        contents_chunk_size: u32,
+    ) -> eyre::Result<Self> {
+        let encrypted_filename = self.file_data.encrypt_filename(
+            new_item_key,
+            cipher_suite,
+            self.file_data
+                .filename::<Secret<String>>(&self.item_key(key)?)?
+                .expose(),
+        )?;
+        Ok(Self {
+            item_id: self.item_id.clone(),
+            encrypted_item_key: encrypt_item_key(
+                self.owner_username(),
+                EntityType::File,
+                self.id(),
+                key,
+                new_item_key,
+                cipher_suite,
+            )?,
+            file_data: FileData::from_fields(
+                self.id().to_owned(),
+                self.file_data.path().to_owned(),
+                Filename::Encrypted(encrypted_filename),
+                self.owner_username().to_owned(),
+                contents_nonce,
+                contents_chunk_size,
+                None,
+            ),
+        })
+    }
+
+    /// Decrypt the item key of this [SharedFile] with the owner's `key`.
+    pub fn item_key(&self, key: &Aes256Key) -> eyre::Result<Aes256Key> {
+        decrypt_item_key(
+            self.owner_username(),
+            EntityType::File,
+            self.id(),
+            key,
+            &self.encrypted_item_key,
+        )
+    }
+
+    /// Re-encrypt the item key of this [SharedFile] with the owner's new `key`.
+    pub fn rewrap(
+        &self,
+        old_key: &Aes256Key,
+        new_key: &Aes256Key,
+        cipher_suite: CipherSuite,
+    ) -> eyre::Result<Self> {
+        Ok(Self {
+            encrypted_item_key: encrypt_item_key(
+                self.owner_username(),
+                EntityType::File,
+                self.id(),
+                new_key,
+                &self.item_key(old_key)?,
+                cipher_suite,
+            )?,
+            ..self.clone()
+        })
+    }
+
+    /// Return the id of the shared item.
+    pub fn id(&self) -> &str {
+        self.file_data.id()
+    }
+
+    /// Return the `owner_username` of this [SharedFile].
+    pub fn owner_username(&self) -> &str {
+        self.file_data.owner_username()
+    }
+
+    /// Return the `item_id` of this [SharedFile].
+    pub fn item_id(&self) -> &str {
+        &self.item_id
+    }
+
+    /// Return the `encrypted_item_key` of this [SharedFile].
+    pub fn encrypted_item_key(&self) -> &Encrypted {
+        &self.encrypted_item_key
+    }
+
+    /// Return the [FileData] of the shared copy.
+    pub fn file_data(&self) -> &FileData {
+        &self.file_data
+    }
+}
+
+/// A recipient's access to a shared item: the item key, encrypted to the recipient's public key.
+///
+/// The item key is encrypted with a hash of the X25519 Diffie-Hellman shared secret of a random
+/// ephemeral key & the recipient's public key. Only the recipient's [PrivateKey] can derive it
+/// again from the `ephemeral_public_key`.
+///
+/// - `shared_id`: The id of the shared item.
+///
+/// - `owner_username`: The username of the account which owns the shared item.
+///
+/// - `recipient_username`: The username of the account which the item is shared with.
+///
+/// - `entity_type`: Whether the shared item is a credential or a file.
+///
+/// - `ephemeral_public_key`: The public key of the ephemeral key.
+///
+/// - `encrypted_item_key`: The item key, encrypted to the recipient.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ShareGrant {
+    shared_id: String,
+    owner_username: String,
+    recipient_username: String,
+    entity_type: EntityType,
+    ephemeral_public_key: [u8; 32],
+    encrypted_item_key: Encrypted,
+}
+impl ShareGrant {
+    /// Create a new [ShareGrant], encrypting the item key to the recipient's public key with the
+    /// given [CipherSuite].
+    pub fn try_new(
+        shared_id: &str,
+        owner_username: &str,
+        recipient_username: &str,
+        entity_type: EntityType,
+        item_key: &Aes256Key,
+        recipient_public_key: &[u8; 32],
+        cipher_suite: CipherSuite,
+    ) -> eyre::Result<Self> {
+        let ephemeral_secret = EphemeralSecret::random_from_rng(ChaCha20Rng::from_entropy());
+        let ephemeral_public_key = PublicKey::from(&ephemeral_secret).to_bytes();
+        let shared_secret =
+            ephemeral_secret.diffie_hellman(&PublicKey::from(*recipient_public_key));
+        if !shared_secret.was_contributory() {
+            return Err(eyre!(
+                "Invalid public key of account {}.",
+                recipient_username
+            ));
+        }
+        let wrap_key = wrap_key(
+            shared_secret.as_bytes(),
+            &ephemeral_public_key,
+            recipient_public_key,
+        );
+        let encrypted_item_key = item_key.try_encrypt_with_suite(
+            &wrap_key,
+            cipher_suite,
+            &AssociatedData::new(
+                owner_username,
+                entity_type,
+                SHARED_ITEM_KEY_FIELD,
+                shared_id,
+            )
+            .to_bytes(),
+        )?;
+        Ok(Self {
+            shared_id: shared_id.to_owned(),
+            owner_username: owner_username.to_owned(),
+            recipient_username: recipient_username.to_owned(),
+            entity_type,
+            ephemeral_public_key,
+            encrypted_item_key,
+        })
+    }
+
+    /// Create a [ShareGrant] from its fields.
+    pub fn from_fields(
+        shared_id: String,
+        owner_username: String,
+        recipient_username: String,
+        entity_type: EntityType,
+        ephemeral_public_key: [u8; 32],
+        encrypted_item_key: Encrypted,
+    ) -> Self {
+        Self {
+            shared_id,
+            owner_username,
+            recipient_username,
+            entity_type,
+            ephemeral_public_key,
+            encrypted_item_key,
+        }
+    }
+
+    /// Decrypt the item key of this [ShareGrant] with the recipient's [PrivateKey].
+    pub fn item_key(&self, private_key: &PrivateKey) -> eyre::Result<Aes256Key> {
+        let ephemeral_public_key = PublicKey::from(self.ephemeral_public_key);
+        let shared_secret = private_key.0.diffie_hellman(&ephemeral_public_key);
+        let wrap_key = wrap_key(
+            shared_secret.as_bytes(),
+            &self.ephemeral_public_key,
+            &private_key.public_key(),
+        );
+        Aes256Key::try_decrypt_with_aad(
+            &self.encrypted_item_key,
+            &wrap_key,
+            &AssociatedData::new(
+                &self.owner_username,
+                self.entity_type,
+                SHARED_ITEM_KEY_FIELD,
+                &self.shared_id,
+            )
+            .to_bytes(),
+        )
+    }
+
+    /// Re-encrypt the item key of this [ShareGrant] to the recipient's new public key, decrypting
+    /// it with their old [PrivateKey].
+    pub fn rewrap(
+        &self,
+        old_private_key: &PrivateKey,
+        new_public_key: &[u8; 32],
+        cipher_suite: CipherSuite,
+    ) -> eyre::Result<Self> {
+        Self::try_new(
+            &self.shared_id,
+            &self.owner_username,
+            &self.recipient_username,
+            self.entity_type,
+            &self.item_key(old_private_key)?,
+            new_public_key,
+            cipher_suite,
+        )
+    }
+
+    /// Return the `shared_id` of this [ShareGrant].
+    pub fn shared_id(&self) -> &str {
+        &self.shared_id
+    }
+
+    /// Return the `owner_username` of this [ShareGrant].
+    pub fn owner_username(&self) -> &str {
+        &self.owner_username
+    }
+
+    /// Return the `recipient_username` of this [ShareGrant].
+    pub fn recipient_username(&self) -> &str {
+        &self.recipient_username
+    }
+
+    /// Return the [EntityType] of the item shared by this [ShareGrant].
+    pub fn entity_type(&self) -> EntityType {
+        self.entity_type
+    }
+
+    /// Return the `ephemeral_public_key` of this [ShareGrant].
+    pub fn ephemeral_public_key(&self) -> &[u8; 32] {
+        &self.ephemeral_public_key
+    }
+
+    /// Return the `encrypted_item_key` of this [ShareGrant].
+    pub fn encrypted_item_key(&self) -> &Encrypted {
+        &self.encrypted_item_key
+    }
+}
+
+/// A [Credential] or [FileData] shared with an account, along with the item key which decrypts
+/// it. Its owner is its `owner_username`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shared<T> {
+    item: T,
+    key: Aes256Key,
+}
+impl<T> Shared<T> {
+    /// Wrap a shared item & its item key in a [Shared].
+    pub fn new(item: T, key: Aes256Key) -> Self {
+        Self { item, key }
+    }
+
+    /// Return the shared item.
+    pub fn item(&self) -> &T {
+        &self.item
+    }
+
+    /// Return the item key, which decrypts the shared item.
+    pub fn key(&self) -> &Aes256Key {
+        &self.key
+    }
+}
+
+/// Return a new, random id for a shared item along with a new, random item key.
+pub fn new_shared_item() -> (String, Aes256Key) {
+    (Uuid::new_v4().to_string(), new_rand_key())
+}
+
+// Helper function: Encrypt a shared item's key with its owner's `key`.
+fn encrypt_item_key(
+    owner_username: &str,
+    entity_type: EntityType,
+    shared_id: &str,
+    key: &Aes256Key,
+    item_key: &Aes256Key,
+    cipher_suite: CipherSuite,
+) -> eyre::Result<Encrypted> {
+    item_key.try_encrypt_with_suite(
+        key,
+        cipher_suite,
+        &AssociatedData::new(owner_username, entity_type, ITEM_KEY_FIELD, shared_id).to_bytes(),
+    )
+}
+
+// Helper function: Decrypt a shared item's key with its owner's `key`.
+fn decrypt_item_key(
+    owner_username: &str,
+    entity_type: EntityType,
+    shared_id: &str,
+    key: &Aes256Key,
+    encrypted_item_key: &Encrypted,
+) -> eyre::Result<Aes256Key> {
+    Aes256Key::try_decrypt_with_aad(
+        encrypted_item_key,
+        key,
+        &AssociatedData::new(owner_username, entity_type, ITEM_KEY_FIELD, shared_id).to_bytes(),
+    )
+}
+
+// Helper function: Derive the [Aes256Key] which encrypts a shared item's key to a recipient.
+fn wrap_key(
+    shared_secret: &[u8; 32],
+    ephemeral_public_key: &[u8; 32],
+    recipient_public_key: &[u8; 32],
+) -> Aes256Key {
+    Aes256Key::from_bytes(
+        Sha256::new()
+            .chain_update(WRAP_KEY_DOMAIN)
+            .chain_update(shared_secret)
+            .chain_update(ephemeral_public_key)
+            .chain_update(recipient_public_key)
+            .finalize()
+            .into(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::{assert_eq, assert_ne};
+
+    use super::*;
+
+    #[test]
+    fn grant_item_key() {
+        let recipient_key = PrivateKey::generate();
+        let item_key = new_rand_key();
+        let grant = ShareGrant::try_new(
+            "shared item",
+            "owner",
+            "recipient",
+            EntityType::Credential,
+            &item_key,
+            &recipient_key.public_key(),
+            CipherSuite::default(),
+        )
+        .unwrap();
+        assert_eq!(grant.item_key(&recipient_key).unwrap(), item_key);
+        assert!(
+            !format!("{recipient_key:?}").contains(&format!("{:?}", recipient_key.0.as_bytes()))
+        );
+
+        // Each grant uses a new ephemeral key.
+        let other_grant = ShareGrant::try_new(
+            "shared item",
+            "owner",
+            "recipient",
+            EntityType::Credential,
+            &item_key,
+            &recipient_key.public_key(),
+            CipherSuite::default(),
+        )
+        .unwrap();
+        assert_ne!(
+            grant.ephemeral_public_key(),
+            other_grant.ephemeral_public_key()
+        );
+
+        // Other private keys can't decrypt the item key.
+        let _ = grant.item_key(&PrivateKey::generate()).unwrap_err();
+
+        // The item key is bound to its context.
+        let moved_grant = ShareGrant::from_fields(
+            String::from("other item"),
+            grant.owner_username().to_owned(),
+            grant.recipient_username().to_owned(),
+            grant.entity_type(),
+            *grant.ephemeral_public_key(),
+            grant.encrypted_item_key().clone(),
+        );
+        let _ = moved_grant.item_key(&recipient_key).unwrap_err();
+
+        // The all-zero public key is rejected.
+        let _ = ShareGrant::try_new(
+            "shared item",
+            "owner",
+            "recipient",
+            EntityType::Credential,
+            &item_key,
+            &[0u8; 32],
+            CipherSuite::default(),
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn private_key_to_from_encrypted() {
+        let private_key = PrivateKey::generate();
+        let key = new_rand_key();
+        let encrypted = (&private_key).try_encrypt_with_aad(&key, b"aad").unwrap();
+        let decrypted = PrivateKey::try_decrypt_with_aad(&encrypted, &key, b"aad").unwrap();
+        assert_eq!(decrypted, private_key);
+        assert_eq!(decrypted.public_key(), private_key.public_key());
+        assert_ne!(PrivateKey::generate(), private_key);
+    }
+}
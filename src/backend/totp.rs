@@ -0,0 +1,519 @@
+//! Functionality for two-factor login with RFC 6238 time-based one-time passwords (TOTP).
+use std::{
+    fmt::Write,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::{self, eyre};
+use hmac::{Hmac, Mac};
+use qrcode::{render::unicode::Dense1x2, QrCode};
+use rand_chacha::{
+    rand_core::{RngCore, SeedableRng},
+    ChaCha20Rng,
+};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+use zeroize::{Zeroize, Zeroizing};
+
+use super::{
+    encryption::{
+        associated_data::{AssociatedData, EntityType},
+        cipher_suite::CipherSuite,
+        encrypted::{Aes256Key, Encrypted, Nonce, TryFromEncrypted, TryIntoEncrypted},
+    },
+    recovery::{encode_code, parse_code},
+    secret::Secret,
+};
+
+// The number of random bytes in a [TotpSecret]. RFC 4226 recommends 160 bits.
+const SECRET_SIZE: usize = 20;
+
+// The number of seconds each TOTP code is valid for.
+const PERIOD: u64 = 30;
+
+// The number of digits in each TOTP code.
+const DIGITS: u32 = 6;
+
+// The number of periods before & after the current one whose codes are also accepted, to allow for
+// clock drift.
+const SKEW: u64 = 1;
+
+// The name shown for `dgruft` accounts in authenticator apps.
+const ISSUER: &str = "dgruft";
+
+// The RFC 4648 base-32 alphabet, which authenticator apps expect TOTP secrets to be encoded in.
+const SECRET_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+// The number of random bytes in a [BackupCode].
+const BACKUP_CODE_SIZE: usize = 10;
+
+// Prepended to the [BackupCode] when deriving its [Aes256Key].
+const BACKUP_KEY_DOMAIN: &[u8] = b"dgruft backup code key";
+
+// The [AssociatedData] field of the [TotpSecret] encrypted with a [BackupCode]'s key.
+const BACKUP_SECRET_FIELD: &str = "backup_totp_secret";
+
+/// The number of [BackupCode]s generated whenever two-factor login is enabled or its backup codes
+/// are regenerated.
+pub const NUM_BACKUP_CODES: usize = 8;
+
+/// The secret shared between `dgruft` & the user's authenticator app, from which the current TOTP
+/// code is computed.
+///
+/// Codes are 6 digits long, change every 30 seconds & are computed with HMAC-SHA1, which is what
+/// authenticator apps expect. The secret is zeroed when dropped, is compared in constant time, and
+/// is never shown by the [fmt::Debug](std::fmt::Debug) implementation.
+#[derive(Debug, Clone)]
+pub struct TotpSecret {
+    bytes: Secret<[u8; SECRET_SIZE]>,
+}
+impl TotpSecret {
+    /// Generate a new, random [TotpSecret].
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; SECRET_SIZE];
+        ChaCha20Rng::from_entropy().fill_bytes(&mut bytes);
+        let secret = Self::from_bytes(&bytes);
+        bytes.zeroize();
+        secret
+    }
+
+    /// Create a [TotpSecret] from its bytes.
+    pub fn from_bytes(bytes: &[u8; SECRET_SIZE]) -> Self {
+        Self {
+            bytes: Secret::new(*bytes),
+        }
+    }
+
+    /// Return the bytes of this [TotpSecret].
+    pub fn as_bytes(&self) -> &[u8; SECRET_SIZE] {
+        self.bytes.expose()
+    }
+
+    /// Compute the TOTP code which is valid at the given Unix time.
+    #[cfg(test)]
+    pub fn code_at(&self, unix_time: u64) -> eyre::Result<Secret<String>> {
+        self.code_for_step(unix_time / PERIOD)
+    }
+
+    /// Return the time step of the given code if it's valid at the given Unix time, or [None]
+    /// otherwise. Codes from the periods just before & after the current one are also accepted.
+    /// Whitespace in the code is ignored.
+    ///
+    /// Every accepted period's code is compared in constant time, so the time taken doesn't reveal
+    /// how much of the code was right, nor which period it was from.
+    pub fn check_code(&self, code: &str, unix_time: u64) -> eyre::Result<Option<u64>> {
+        let code: Zeroizing<String> =
+            Zeroizing::new(code.chars().filter(|c| !c.is_whitespace()).collect());
+        let step = unix_time / PERIOD;
+        let mut matched_step = None;
+        for step in step.saturating_sub(SKEW)..=step + SKEW {
+            let step_code = self.code_for_step(step)?;
+            if bool::from(step_code.expose().as_bytes().ct_eq(code.as_bytes())) {
+                matched_step = Some(step);
+            }
+        }
+        Ok(matched_step)
+    }
+
+    /// Encode this [TotpSecret] in unpadded RFC 4648 base-32, the form authenticator apps accept
+    /// when the secret is typed in by hand.
+    pub fn encode(&self) -> Secret<String> {
+        let mut encoded = String::with_capacity(SECRET_SIZE * 8 / 5);
+        let mut buffer: u64 = 0;
+        let mut num_bits = 0;
+        for byte in self.bytes.expose() {
+            buffer = (buffer << 8) | u64::from(*byte);
+            num_bits += 8;
+            while num_bits >= 5 {
+                num_bits -= 5;
+                encoded.push(SECRET_ALPHABET[((buffer >> num_bits) & 0x1f) as usize] as char);
+            }
+        }
+        buffer.zeroize();
+        Secret::new(encoded)
+    }
+
+    /// Return the `otpauth://` URI which enrols this [TotpSecret] in an authenticator app under the
+    /// given username.
+    pub fn uri(&self, username: &str) -> Secret<String> {
+        Secret::new(format!(
+            "otpauth://totp/{issuer}:{username}?secret={secret}&issuer={issuer}&algorithm=SHA1\
+            &digits={DIGITS}&period={PERIOD}",
+            issuer = ISSUER,
+            username = percent_encode(username),
+            secret = self.encode().expose(),
+        ))
+    }
+
+    /// Render the [TotpSecret::uri] as a QR code which can be printed to a terminal & scanned by
+    /// an authenticator app.
+    pub fn qr_code(&self, username: &str) -> eyre::Result<Secret<String>> {
+        let qr_code = QrCode::new(self.uri(username).expose().as_bytes())
+            .map_err(|err| eyre!("Failed to create QR code: {}", err))?;
+        // Terminals are usually light-on-dark, so the colours are inverted.
+        Ok(Secret::new(
+            qr_code
+                .render::<Dense1x2>()
+                .dark_color(Dense1x2::Light)
+                .light_color(Dense1x2::Dark)
+                .build(),
+        ))
+    }
+
+    // Helper function: Compute the TOTP code of the given time step, as in RFC 4226.
+    fn code_for_step(&self, step: u64) -> eyre::Result<Secret<String>> {
+        let mut mac = <Hmac<Sha1> as Mac>::new_from_slice(self.bytes.expose())?;
+        mac.update(&step.to_be_bytes());
+        let digest = Zeroizing::new(<[u8; 20]>::from(mac.finalize().into_bytes()));
+        let offset = usize::from(digest[19] & 0x0f);
+        let mut truncated = u32::from_be_bytes([
+            digest[offset] & 0x7f,
+            digest[offset + 1],
+            digest[offset + 2],
+            digest[offset + 3],
+        ]);
+        let code = Secret::new(format!(
+            "{:0width$}",
+            truncated % 10u32.pow(DIGITS),
+            width = DIGITS as usize
+        ));
+        truncated.zeroize();
+        Ok(code)
+    }
+}
+impl PartialEq for TotpSecret {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes.expose().ct_eq(other.bytes.expose()).into()
+    }
+}
+impl Eq for TotpSecret {}
+impl TryIntoEncrypted for &TotpSecret {
+    fn try_encrypt_with_all(
+        self,
+        key: &Aes256Key,
+        nonce: Nonce,
+        aad: &[u8],
+    ) -> eyre::Result<Encrypted> {
+        Encrypted::try_encrypt_bytes_key_nonce(self.bytes.expose(), key, nonce, aad)
+    }
+}
+impl TryFromEncrypted for TotpSecret {
+    fn try_from_decrypted_bytes(decrypted_bytes: Vec<u8>) -> eyre::Result<Self> {
+        let decrypted_vec = Zeroizing::new(decrypted_bytes);
+        let bytes: Zeroizing<[u8; SECRET_SIZE]> = Zeroizing::new(
+            decrypted_vec
+                .as_slice()
+                .try_into()
+                .map_err(|_| eyre!("TryFromEncrypted: Failed to convert from byte vector."))?,
+        );
+        Ok(Self::from_bytes(&bytes))
+    }
+}
+
+/// A single-use code which can stand in for a TOTP code when the user's authenticator app is lost.
+///
+/// The code is 80 random bits, shown to the user as 16 Crockford base-32 characters in groups of
+/// four. It is zeroed when dropped, is compared in constant time, and is never shown by the
+/// [fmt::Debug](std::fmt::Debug) implementation.
+#[derive(Debug, Clone)]
+pub struct BackupCode {
+    bytes: Secret<[u8; BACKUP_CODE_SIZE]>,
+}
+impl BackupCode {
+    /// Generate a new, random [BackupCode].
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; BACKUP_CODE_SIZE];
+        ChaCha20Rng::from_entropy().fill_bytes(&mut bytes);
+        let backup_code = Self {
+            bytes: Secret::new(bytes),
+        };
+        bytes.zeroize();
+        backup_code
+    }
+
+    /// Encode this [BackupCode] in the human-typeable form shown to the user, e.g.
+    /// `7KQ2-M0XR-4D9T-VA1E`.
+    pub fn encode(&self) -> Secret<String> {
+        encode_code(self.bytes.expose())
+    }
+
+    /// Derive the [Aes256Key] which this [BackupCode] stands for.
+    pub fn key(&self) -> Aes256Key {
+        Aes256Key::from_bytes(
+            Sha256::new()
+                .chain_update(BACKUP_KEY_DOMAIN)
+                .chain_update(self.bytes.expose())
+                .finalize()
+                .into(),
+        )
+    }
+}
+impl PartialEq for BackupCode {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes.expose().ct_eq(other.bytes.expose()).into()
+    }
+}
+impl Eq for BackupCode {}
+impl FromStr for BackupCode {
+    type Err = eyre::Report;
+
+    /// Parse an encoded [BackupCode]. Case, dashes & whitespace are ignored, and the letters I, L
+    /// & O are read as the digits they resemble.
+    fn from_str(s: &str) -> eyre::Result<Self> {
+        Ok(Self {
+            bytes: Secret::new(*parse_code::<BACKUP_CODE_SIZE>(s, "backup code")?),
+        })
+    }
+}
+
+/// A [BackupCode]'s copy of an account's [TotpSecret]. Each [BackupCode] of an account has one,
+/// which is deleted once the [BackupCode] has been used.
+///
+/// - `id`: The unique identifier of this [TotpBackup].
+///
+/// - `owner_username`: The username of the account which this [TotpBackup] belongs to.
+///
+/// - `encrypted_secret`: The account's [TotpSecret], encrypted with the [BackupCode]'s
+///   [Aes256Key].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TotpBackup {
+    id: String,
+    owner_username: String,
+    encrypted_secret: Encrypted,
+}
+impl TotpBackup {
+    /// Create a new [TotpBackup] with a new, random `id`, encrypting the [TotpSecret] with the
+    /// given [BackupCode] & [CipherSuite].
+    pub fn try_new(
+        owner_username: &str,
+        secret: &TotpSecret,
+        backup_code: &BackupCode,
+        cipher_suite: CipherSuite,
+    ) -> eyre::Result<Self> {
+        let id = Uuid::new_v4().to_string();
+        let encrypted_secret = secret.try_encrypt_with_suite(
+            &backup_code.key(),
+            cipher_suite,
+            &Self::associated_data(owner_username, &id),
+        )?;
+        Ok(Self {
+            id,
+            owner_username: owner_username.to_owned(),
+            encrypted_secret,
+        })
+    }
+
+    /// Create a [TotpBackup] from its fields.
+    pub fn from_fields(id: String, owner_username: String, encrypted_secret: Encrypted) -> Self {
+        Self {
+            id,
+            owner_username,
+            encrypted_secret,
+        }
+    }
+
+    /// Decrypt the [TotpSecret] of this [TotpBackup] with the given [BackupCode]. Fails if this
+    /// [TotpBackup] doesn't belong to the [BackupCode].
+    pub fn try_unlock(&self, backup_code: &BackupCode) -> eyre::Result<TotpSecret> {
+        TotpSecret::try_decrypt_with_aad(
+            &self.encrypted_secret,
+            &backup_code.key(),
+            &Self::associated_data(&self.owner_username, &self.id),
+        )
+    }
+
+    /// Get the `id` of this [TotpBackup].
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Get the `owner_username` of this [TotpBackup].
+    pub fn owner_username(&self) -> &str {
+        &self.owner_username
+    }
+
+    /// Get the `encrypted_secret` of this [TotpBackup].
+    pub fn encrypted_secret(&self) -> &Encrypted {
+        &self.encrypted_secret
+    }
+
+    // Helper function: Get the [AssociatedData] bytes of the `encrypted_secret`.
+    fn associated_data(owner_username: &str, id: &str) -> Vec<u8> {
+        AssociatedData::new(owner_username, EntityType::Account, BACKUP_SECRET_FIELD, id).to_bytes()
+    }
+}
+
+/// A second factor given by the user when logging into an account with two-factor login.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TwoFactorCode {
+    /// The current code shown by the user's authenticator app.
+    Totp(Secret<String>),
+    /// One of the account's [BackupCode]s. It no longer works once it has been used.
+    Backup(BackupCode),
+    /// The [TotpSecret] itself, as held by an account which has already been unlocked.
+    Secret(TotpSecret),
+}
+impl FromStr for TwoFactorCode {
+    type Err = eyre::Report;
+
+    /// Parse a [TwoFactorCode] typed by the user: either a TOTP code or an encoded [BackupCode].
+    fn from_str(s: &str) -> eyre::Result<Self> {
+        let trimmed = s.trim();
+        if trimmed.len() == DIGITS as usize && trimmed.chars().all(|c| c.is_ascii_digit()) {
+            Ok(Self::Totp(Secret::new(trimmed.to_owned())))
+        } else {
+            Ok(Self::Backup(trimmed.parse().map_err(|_| {
+                eyre!("Invalid two-factor code: expected a TOTP code or a backup code.")
+            })?))
+        }
+    }
+}
+
+/// The proof of a [TotpSecret] which an account with two-factor login is unlocked with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TwoFactor<'a> {
+    /// A TOTP code, checked at the given Unix time.
+    Code(&'a str, u64),
+    /// The [TotpSecret] itself, as decrypted with a [BackupCode] or held by an unlocked account.
+    Secret(&'a TotpSecret),
+}
+
+/// Return the current Unix time in seconds.
+pub fn unix_time_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+// Helper function: Percent-encode everything but the unreserved characters of RFC 3986.
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{:02X}", byte);
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::{assert_eq, assert_ne};
+
+    use super::*;
+
+    // The SHA-1 secret of the RFC 6238 test vectors.
+    const RFC_SECRET: &[u8; SECRET_SIZE] = b"12345678901234567890";
+
+    #[test]
+    fn rfc_6238_vectors() {
+        let secret = TotpSecret::from_bytes(RFC_SECRET);
+        for (unix_time, code) in [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ] {
+            assert_eq!(secret.code_at(unix_time).unwrap().expose(), code);
+        }
+        assert_eq!(secret.encode().expose(), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+    }
+
+    #[test]
+    fn check_code() {
+        let secret = TotpSecret::generate();
+        assert_ne!(secret, TotpSecret::generate());
+
+        let now = 1_700_000_000;
+        let step = Some(now / PERIOD);
+        let code = secret.code_at(now).unwrap();
+        assert_eq!(secret.check_code(code.expose(), now).unwrap(), step);
+        assert_eq!(
+            secret
+                .check_code(&format!(" {} ", code.expose()), now)
+                .unwrap(),
+            step
+        );
+        // Clock drift of one period either way is allowed, & the code's own step is returned.
+        assert_eq!(
+            secret.check_code(code.expose(), now + PERIOD).unwrap(),
+            step
+        );
+        assert_eq!(
+            secret.check_code(code.expose(), now - PERIOD).unwrap(),
+            step
+        );
+        assert_eq!(
+            secret.check_code(code.expose(), now + 3 * PERIOD).unwrap(),
+            None
+        );
+        assert_eq!(
+            secret.check_code(code.expose(), now - 3 * PERIOD).unwrap(),
+            None
+        );
+        assert_eq!(secret.check_code("", now).unwrap(), None);
+    }
+
+    #[test]
+    fn uri() {
+        let secret = TotpSecret::from_bytes(RFC_SECRET);
+        assert_eq!(
+            secret.uri("mr test@home").expose(),
+            "otpauth://totp/dgruft:mr%20test%40home?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ\
+            &issuer=dgruft&algorithm=SHA1&digits=6&period=30"
+        );
+        assert!(!secret.qr_code("mr_test").unwrap().expose().is_empty());
+        assert!(!format!("{secret:?}").contains(secret.encode().expose()));
+    }
+
+    #[test]
+    fn backup_codes() {
+        let backup_code = BackupCode::generate();
+        assert_ne!(backup_code, BackupCode::generate());
+        let encoded = backup_code.encode();
+        assert_eq!(encoded.expose().len(), 19);
+        assert_eq!(encoded.expose().parse::<BackupCode>().unwrap(), backup_code);
+        assert!(!format!("{backup_code:?}").contains(encoded.expose()));
+
+        let secret = TotpSecret::generate();
+        let backup =
+            TotpBackup::try_new("mr_test", &secret, &backup_code, CipherSuite::default()).unwrap();
+        assert_eq!(backup.try_unlock(&backup_code).unwrap(), secret);
+        let _ = backup.try_unlock(&BackupCode::generate()).unwrap_err();
+
+        // Moved to another account.
+        let moved = TotpBackup::from_fields(
+            backup.id().to_owned(),
+            "someone_else".to_owned(),
+            backup.encrypted_secret().clone(),
+        );
+        let _ = moved.try_unlock(&backup_code).unwrap_err();
+    }
+
+    #[test]
+    fn parse_two_factor_code() {
+        assert_eq!(
+            " 123456\n".parse::<TwoFactorCode>().unwrap(),
+            TwoFactorCode::Totp(Secret::new("123456".to_owned()))
+        );
+        let backup_code = BackupCode::generate();
+        assert_eq!(
+            backup_code
+                .encode()
+                .expose()
+                .to_lowercase()
+                .parse::<TwoFactorCode>()
+                .unwrap(),
+            TwoFactorCode::Backup(backup_code)
+        );
+        let _ = "12345".parse::<TwoFactorCode>().unwrap_err();
+        let _ = "not a code".parse::<TwoFactorCode>().unwrap_err();
+    }
+}
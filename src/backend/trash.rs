@@ -0,0 +1,57 @@
+//! The trash, where deleted credentials, files & accounts are kept until they're purged.
+use super::encryption::associated_data::EntityType;
+
+/// A credential, file or account in its owner's trash. The item itself stays where it was,
+/// encrypted, but is left out of lookups & listings until it's restored or purged.
+///
+/// - `owner_username`: The username of the account which owns the trashed item. A trashed account
+///   owns itself.
+///
+/// - `entity_type`: Whether the trashed item is a credential, a file or an account.
+///
+/// - `item_id`: The id of the trashed credential or file, or the username of the trashed account.
+///
+/// - `trashed_at`: The Unix time at which the item was moved to the trash.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TrashedItem {
+    owner_username: String,
+    entity_type: EntityType,
+    item_id: String,
+    trashed_at: u64,
+}
+impl TrashedItem {
+    /// Create a [TrashedItem] from its fields.
+    pub fn from_fields(
+        owner_username: String,
+        entity_type: EntityType,
+        item_id: String,
+        trashed_at: u64,
+    ) -> Self {
+        Self {
+            owner_username,
+            entity_type,
+            item_id,
+            trashed_at,
+        }
+    }
+
+    /// Return the username of the account which owns the trashed item.
+    pub fn owner_username(&self) -> &str {
+        &self.owner_username
+    }
+
+    /// Return whether the trashed item is a credential, a file or an account.
+    pub fn entity_type(&self) -> EntityType {
+        self.entity_type
+    }
+
+    /// Return the id of the trashed item.
+    pub fn item_id(&self) -> &str {
+        &self.item_id
+    }
+
+    /// Return the Unix time at which the item was moved to the trash.
+    pub fn trashed_at(&self) -> u64 {
+        self.trashed_at
+    }
+}
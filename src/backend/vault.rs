@@ -1,94 +1,675 @@
 //! All saving, loading, and editing of `dgruft` data is handled through here.
-use std::fs;
+use std::{
+    collections::HashSet,
+    fs::{self},
+    io::{self, Read, Write},
+};
 
+use base64ct::{Base64, Encoding};
 use camino::{Utf8Path, Utf8PathBuf};
 use color_eyre::eyre::{self, eyre};
+use rand_chacha::{
+    rand_core::{RngCore, SeedableRng},
+    ChaCha20Rng,
+};
+use rusqlite::{types::Value, Transaction};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
 
+mod archive;
+mod backup;
+mod check;
 mod database;
 pub mod database_traits;
+mod file_store;
 mod filesystem;
+mod history;
+mod issues;
+mod migrations;
+mod sharing;
 mod sql_schemas;
 mod sql_statements;
+mod streams;
+mod trash;
 
 use super::{
     account::{Account, UnlockedAccount},
-    credential::Credential,
-    encryption::encrypted::{
-        Aes256Key, Aes256Nonce, Encrypted, TryFromEncrypted, TryIntoEncrypted,
+    age_payload::{decrypt_from, encrypt_to, AgeIdentity, AgeRecipient},
+    credential::{Credential, CredentialField, CredentialVersion},
+    encryption::{
+        associated_data::EntityType,
+        cipher_suite::CipherSuite,
+        encrypted::{Aes256Key, Encrypted, Nonce, TryFromEncrypted},
+        stream::{copy_secret, DEFAULT_CHUNK_SIZE},
+    },
+    file_data::{FileData, FileRevision, Filename},
+    hashing::{
+        hashed::{Hash, Salt},
+        kdf::KdfParams,
+    },
+    key_file::KeyFile,
+    recovery::RecoveryCode,
+    secret::Secret,
+    shamir::KeyShare,
+    sharing::{ShareGrant, SharedCredential, SharedFile},
+    totp::{
+        unix_time_now, BackupCode, TotpBackup, TotpSecret, TwoFactor, TwoFactorCode,
+        NUM_BACKUP_CODES,
     },
-    file_data::FileData,
-    hashing::hashed::{Hash, Salt},
+    trash::TrashedItem,
 };
 use database::Database;
 use database_traits::{
-    AccountUpdateField, CredentialUpdateField, FileDataUpdateField, HasSqlStatements,
+    credential_primary_key, optional_encrypted_into_db, AccountUpdateField, CredentialUpdateField,
+    FileDataUpdateField, FileRevisionUpdateField, HasSqlStatements, IntoSql, SharedFileUpdateField,
     TryFromDatabase,
 };
+use file_store::FileStore;
 use filesystem::{
-    get_account_file_dir, get_file_path, new_account_file_dir, new_file, open_file,
-    read_file_bytes, verify_writeable_dir, write_file,
+    get_account_file_dir, get_pending_file_path, get_replaced_file_path, get_revision_file_path,
+    open_file, verify_writeable_dir,
 };
+pub use issues::VaultIssue;
 
 /// The [Vault] is reponsible for all saving, loading, and editing of `dgruft` data. It handles the
 /// [Database] and the filesystem together to ensure that the two remain consistent when interacted
 /// with by other code.
+///
+/// The [Vault]'s [KdfParams] are the current password hashing parameters. New accounts are created
+/// with them, and accounts hashed with weaker parameters are upgraded to them on login.
+///
+/// The [Vault]'s [CipherSuite] is used to encrypt all new data. It is saved in the [Database], so
+/// it persists between sessions. Data encrypted with a different [CipherSuite] remains readable.
+///
+/// In privacy mode, each account is stored under a salted hash of its username instead of the
+/// username itself, so usernames can't be read from the [Database] or the filesystem. The
+/// [Vault]'s functions still take plain usernames. Privacy mode can only be enabled before any
+/// accounts are created, & can't be disabled.
+///
+/// Two-factor codes are checked against the [Vault]'s clock, which returns the current Unix time.
+/// The clock also timestamps the previous versions kept when a [Credential] or file is updated, &
+/// the items moved to the trash.
+///
+/// The encrypted contents of files are kept in a directory beside the [Database], or, in the
+/// single-file layout, inside the [Database] itself, so the whole [Vault] is one file. The layout
+/// is saved in the [Database], & a [Vault] can be converted between the two.
 #[derive(Debug)]
 pub struct Vault {
     database: Database,
-    filesystem_directory: Utf8PathBuf,
+    file_store: FileStore,
+    kdf_params: KdfParams,
+    cipher_suite: CipherSuite,
+    privacy_salt: Option<Salt<32>>,
+    credential_history_limit: u32,
+    file_revision_limit: u32,
+    file_revision_max_age: Option<u32>,
+    trash_retention: Option<u32>,
+    clock: fn() -> u64,
 }
 impl Vault {
     /// Connect to this [Vault]. For async programs, only one [Vault] should be loaded at a time.
+    ///
+    /// The `filesystem_directory` holds the encrypted contents of files. It is ignored if the
+    /// [Vault] is a single file.
     pub fn connect<P>(database_path: P, filesystem_directory: P) -> eyre::Result<Self>
     where
         P: AsRef<Utf8Path>,
     {
-        // Ensure the filesystem directory is a valid directory with write permissions.
-        verify_writeable_dir(&filesystem_directory)?;
+        // Connect to the database.
+        let database = Database::connect(database_path.as_ref())?;
+
+        // Load the saved file store layout.
+        let file_store = match database.select_setting(Self::FILE_STORE_SETTING)? {
+            Some(layout) if layout == FileStore::Database.layout() => FileStore::Database,
+            _ => {
+                // Ensure the filesystem directory is a valid directory with write permissions.
+                verify_writeable_dir(&filesystem_directory)?;
+                FileStore::Directory(filesystem_directory.as_ref().into())
+            }
+        };
+
+        Self::load(database, file_store)
+    }
 
+    /// Connect to the single-file [Vault] at the given path. An empty [Vault] becomes a
+    /// single-file [Vault]; one which keeps its files in a directory must be converted first.
+    pub fn connect_single_file<P>(database_path: P) -> eyre::Result<Self>
+    where
+        P: AsRef<Utf8Path>,
+    {
         // Connect to the database.
         let database = Database::connect(database_path.as_ref())?;
 
+        // Ensure the vault is, or can become, a single file.
+        let layout = FileStore::Database.layout();
+        if database
+            .select_setting(Self::FILE_STORE_SETTING)?
+            .as_deref()
+            != Some(layout)
+        {
+            if !database.select_all_entries::<Account>()?.is_empty() {
+                return Err(eyre!(
+                    "Failed to connect to Vault: {:?} keeps its files in a directory. Convert it to a single file first.",
+                    database_path.as_ref()
+                ));
+            }
+            database.update_setting(Self::FILE_STORE_SETTING, layout)?;
+        }
+
+        Self::load(database, FileStore::Database)
+    }
+
+    // Helper function: Load the settings of the [Vault] with the given [Database] & [FileStore].
+    fn load(database: Database, file_store: FileStore) -> eyre::Result<Self> {
+        // Load the saved cipher suite.
+        let cipher_suite = match database.select_setting(Self::CIPHER_SUITE_SETTING)? {
+            Some(name) => name.parse()?,
+            None => CipherSuite::default(),
+        };
+
+        // Load the privacy mode salt, if any.
+        let privacy_salt = match database.select_setting(Self::PRIVACY_SALT_SETTING)? {
+            Some(b64_salt) => Some(
+                Base64::decode_vec(&b64_salt)?
+                    .try_into()
+                    .map_err(|_| eyre!("Invalid privacy mode salt."))?,
+            ),
+            None => None,
+        };
+
+        // Load the number of previous versions kept of each credential.
+        let credential_history_limit =
+            match database.select_setting(Self::CREDENTIAL_HISTORY_LIMIT_SETTING)? {
+                Some(limit) => limit.parse()?,
+                None => Self::DEFAULT_CREDENTIAL_HISTORY_LIMIT,
+            };
+
+        // Load how many previous revisions of each file are kept, & for how long.
+        let file_revision_limit =
+            match database.select_setting(Self::FILE_REVISION_LIMIT_SETTING)? {
+                Some(limit) => limit.parse()?,
+                None => Self::DEFAULT_FILE_REVISION_LIMIT,
+            };
+        let file_revision_max_age =
+            match database.select_setting(Self::FILE_REVISION_MAX_AGE_SETTING)? {
+                Some(days) => Some(days.parse()?).filter(|days| *days > 0),
+                None => None,
+            };
+
+        // Load how many days items are kept in the trash for.
+        let trash_retention = match database.select_setting(Self::TRASH_RETENTION_SETTING)? {
+            Some(days) => Some(days.parse()?).filter(|days| *days > 0),
+            None => Some(Self::DEFAULT_TRASH_RETENTION),
+        };
+
         Ok(Self {
             database,
-            filesystem_directory: filesystem_directory.as_ref().into(),
+            file_store,
+            kdf_params: KdfParams::default(),
+            cipher_suite,
+            privacy_salt,
+            credential_history_limit,
+            file_revision_limit,
+            file_revision_max_age,
+            trash_retention,
+            clock: unix_time_now,
         })
     }
 
+    // The name of the setting which stores the vault's cipher suite.
+    const CIPHER_SUITE_SETTING: &'static str = "cipher_suite";
+
+    // The name of the setting which stores the vault's privacy mode salt. It only exists in privacy
+    // mode.
+    const PRIVACY_SALT_SETTING: &'static str = "privacy_salt";
+
+    // The name of the setting which stores where the vault keeps the contents of its files. Vaults
+    // without it keep them in a directory.
+    const FILE_STORE_SETTING: &'static str = "file_store";
+
+    // The name of the setting which stores how many previous versions of each credential are kept.
+    const CREDENTIAL_HISTORY_LIMIT_SETTING: &'static str = "credential_history_limit";
+
+    // The number of previous versions of each credential kept by vaults without the setting.
+    const DEFAULT_CREDENTIAL_HISTORY_LIMIT: u32 = 10;
+
+    // The name of the setting which stores how many previous revisions of each file are kept.
+    const FILE_REVISION_LIMIT_SETTING: &'static str = "file_revision_limit";
+
+    // The number of previous revisions of each file kept by vaults without the setting.
+    const DEFAULT_FILE_REVISION_LIMIT: u32 = 10;
+
+    // The name of the setting which stores how many days previous revisions of files are kept for.
+    // Vaults without it, or with it set to 0, keep them until they are pruned by count.
+    const FILE_REVISION_MAX_AGE_SETTING: &'static str = "file_revision_max_age_days";
+
+    // The name of the setting which stores how many days items are kept in the trash for. With it
+    // set to 0, they are kept until they are purged.
+    const TRASH_RETENTION_SETTING: &'static str = "trash_retention_days";
+
+    // The number of days items are kept in the trash for by vaults without the setting.
+    const DEFAULT_TRASH_RETENTION: u32 = 30;
+
+    // Prepended to everything hashed into an account name in privacy mode.
+    const ACCOUNT_NAME_DOMAIN: &'static [u8] = b"dgruft account name";
+
+    /// Set the clock of this [Vault], which returns the current Unix time.
+    #[cfg(test)]
+    pub(crate) fn set_clock(&mut self, clock: fn() -> u64) {
+        self.clock = clock;
+    }
+
+    /// Set the current [KdfParams] of this [Vault].
+    pub fn set_kdf_params(&mut self, kdf_params: KdfParams) {
+        self.kdf_params = kdf_params;
+    }
+
+    /// Get the current [CipherSuite] of this [Vault].
+    pub fn cipher_suite(&self) -> CipherSuite {
+        self.cipher_suite
+    }
+
+    /// Set the current [CipherSuite] of this [Vault], saving it to the [Database].
+    ///
+    /// Existing data isn't re-encrypted; it is encrypted with the new [CipherSuite] the next time
+    /// it is changed.
+    pub fn set_cipher_suite(&mut self, cipher_suite: CipherSuite) -> eyre::Result<()> {
+        self.database
+            .update_setting(Self::CIPHER_SUITE_SETTING, cipher_suite.as_str())?;
+        self.cipher_suite = cipher_suite;
+        Ok(())
+    }
+
+    /// Get the number of previous versions of each [Credential] this [Vault] keeps.
+    pub fn credential_history_limit(&self) -> u32 {
+        self.credential_history_limit
+    }
+
+    /// Set the number of previous versions of each [Credential] this [Vault] keeps, saving it to
+    /// the [Database]. Older versions are deleted straight away. A limit of 0 keeps no history.
+    pub fn set_credential_history_limit(&mut self, limit: u32) -> eyre::Result<()> {
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        Database::transaction_update_setting(
+            Self::CREDENTIAL_HISTORY_LIMIT_SETTING,
+            &limit.to_string(),
+            &tx,
+        )?;
+        Database::transaction_prune_credential_history(limit, &tx)?;
+        // Commit the database transaction.
+        tx.commit()?;
+        self.credential_history_limit = limit;
+        Ok(())
+    }
+
+    /// Get the number of previous revisions of each file this [Vault] keeps.
+    pub fn file_revision_limit(&self) -> u32 {
+        self.file_revision_limit
+    }
+
+    /// Set the number of previous revisions of each file this [Vault] keeps, saving it to the
+    /// [Database]. Older revisions are deleted straight away. A limit of 0 keeps no revisions.
+    pub fn set_file_revision_limit(&mut self, limit: u32) -> eyre::Result<()> {
+        self.save_file_revision_settings(limit, self.file_revision_max_age)?;
+        self.file_revision_limit = limit;
+        Ok(())
+    }
+
+    /// Get the number of days this [Vault] keeps previous revisions of files for, if they're only
+    /// kept for so long.
+    pub fn file_revision_max_age(&self) -> Option<u32> {
+        self.file_revision_max_age
+    }
+
+    /// Set the number of days this [Vault] keeps previous revisions of files for, saving it to the
+    /// [Database]. Older revisions are deleted straight away. With no maximum age, revisions are
+    /// only pruned by count.
+    pub fn set_file_revision_max_age(&mut self, days: Option<u32>) -> eyre::Result<()> {
+        let days = days.filter(|days| *days > 0);
+        self.save_file_revision_settings(self.file_revision_limit, days)?;
+        self.file_revision_max_age = days;
+        Ok(())
+    }
+
+    // Helper function: Save the given file revision settings to the [Database], deleting the
+    // revisions they no longer keep.
+    fn save_file_revision_settings(
+        &mut self,
+        limit: u32,
+        max_age: Option<u32>,
+    ) -> eyre::Result<()> {
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        Database::transaction_update_setting(
+            Self::FILE_REVISION_LIMIT_SETTING,
+            &limit.to_string(),
+            &tx,
+        )?;
+        Database::transaction_update_setting(
+            Self::FILE_REVISION_MAX_AGE_SETTING,
+            &max_age.unwrap_or(0).to_string(),
+            &tx,
+        )?;
+        let pruned = Self::transaction_prune_file_revisions(limit, max_age, (self.clock)(), &tx)?;
+        // Commit the database transaction, then delete the pruned revisions' contents.
+        tx.commit()?;
+        self.remove_revision_contents(&pruned)
+    }
+
+    /// Get the number of days this [Vault] keeps items in the trash for, if they're only kept for
+    /// so long.
+    pub fn trash_retention(&self) -> Option<u32> {
+        self.trash_retention
+    }
+
+    /// Set the number of days this [Vault] keeps items in the trash for, saving it to the
+    /// [Database]. Items trashed longer ago are purged when their owner next logs in. With no
+    /// retention period, items are kept in the trash until they are purged.
+    pub fn set_trash_retention(&mut self, days: Option<u32>) -> eyre::Result<()> {
+        let days = days.filter(|days| *days > 0);
+        self.database.update_setting(
+            Self::TRASH_RETENTION_SETTING,
+            &days.unwrap_or(0).to_string(),
+        )?;
+        self.trash_retention = days;
+        Ok(())
+    }
+
+    /// Return the schema version of this [Vault]'s [Database].
+    pub fn schema_version(&self) -> eyre::Result<u32> {
+        self.database.schema_version()
+    }
+
+    /// Return whether this [Vault] is in privacy mode.
+    pub fn privacy_mode(&self) -> bool {
+        self.privacy_salt.is_some()
+    }
+
+    /// Enable privacy mode for this [Vault], saving it to the [Database]. Fails if the [Vault]
+    /// already has accounts.
+    pub fn enable_privacy_mode(&mut self) -> eyre::Result<()> {
+        if self.privacy_mode() {
+            return Err(eyre!("Privacy mode is already enabled."));
+        }
+        if !self.load_all::<Account>()?.is_empty() {
+            return Err(eyre!(
+                "Failed to enable privacy mode: Privacy mode can only be enabled before any accounts are created."
+            ));
+        }
+        let mut salt: Salt<32> = [0u8; 32];
+        ChaCha20Rng::from_entropy().fill_bytes(&mut salt);
+        self.database
+            .update_setting(Self::PRIVACY_SALT_SETTING, &Base64::encode_string(&salt))?;
+        self.privacy_salt = Some(salt);
+        Ok(())
+    }
+
+    /// Return whether this [Vault] is a single file, keeping the contents of its files in the
+    /// [Database].
+    pub fn is_single_file(&self) -> bool {
+        self.file_store == FileStore::Database
+    }
+
+    /// Convert this [Vault] to a single file, moving the contents of its files into the
+    /// [Database]. The account directories are deleted once the conversion is committed.
+    pub fn convert_to_single_file(&mut self) -> eyre::Result<()> {
+        let FileStore::Directory(filesystem_directory) = &self.file_store else {
+            return Err(eyre!("The vault is already a single file."));
+        };
+        let account_dirs = self
+            .load_all::<Account>()?
+            .iter()
+            .map(|account| get_account_file_dir(filesystem_directory, account.username()))
+            .collect::<eyre::Result<Vec<Utf8PathBuf>>>()?;
+        let files_data = self.load_all::<FileData>()?;
+        let shared_files = self.load_all::<SharedFile>()?;
+        let file_revisions = self.load_all::<FileRevision>()?;
+        let new_store = FileStore::Database;
+
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Copy every file, including pending siblings, into the database.
+        for account_dir in &account_dirs {
+            for entry in fs::read_dir(account_dir)? {
+                let path = Utf8PathBuf::try_from(entry?.path())?;
+                new_store.write_new(&tx, &Self::moved_path(&new_store, &path)?, |writer| {
+                    io::copy(&mut open_file(&path)?, writer)?;
+                    Ok(())
+                })?;
+            }
+        }
+        // Move the files' data to their new paths & save the new layout.
+        Self::transaction_move_files(&new_store, &files_data, &shared_files, &file_revisions, &tx)?;
+        // Commit the database transaction.
+        tx.commit()?;
+        self.file_store = new_store;
+
+        // Delete the account directories.
+        for account_dir in account_dirs {
+            fs::remove_dir_all(account_dir)?;
+        }
+        Ok(())
+    }
+
+    /// Convert this single-file [Vault] to keep the contents of its files in account directories
+    /// in the given `filesystem_directory`, moving them out of the [Database]. The account
+    /// directories must not exist yet.
+    pub fn convert_to_directory<P>(&mut self, filesystem_directory: P) -> eyre::Result<()>
+    where
+        P: AsRef<Utf8Path>,
+    {
+        if !self.is_single_file() {
+            return Err(eyre!("The vault already keeps its files in a directory."));
+        }
+        verify_writeable_dir(&filesystem_directory)?;
+        let accounts = self.load_all::<Account>()?;
+        let files_data = self.load_all::<FileData>()?;
+        let shared_files = self.load_all::<SharedFile>()?;
+        let file_revisions = self.load_all::<FileRevision>()?;
+        let new_store = FileStore::Directory(filesystem_directory.as_ref().into());
+
+        // Copy every file, including pending siblings, into the account directories.
+        let conn = self.database.connection();
+        let mut account_dirs = Vec::with_capacity(accounts.len());
+        let copy_result = accounts
+            .iter()
+            .try_for_each(|account| {
+                new_store.new_account_dir(account.username())?;
+                account_dirs.push(new_store.account_dir(account.username())?);
+                Ok::<(), eyre::Report>(())
+            })
+            .and_then(|()| {
+                FileStore::select_paths(conn)?.iter().try_for_each(|path| {
+                    let mut contents = self.file_store.open(conn, path)?;
+                    let new_path = Self::moved_path(&new_store, path)?;
+                    new_store.write_new(conn, &new_path, |writer| {
+                        io::copy(&mut contents, writer)?;
+                        Ok(())
+                    })
+                })
+            });
+        // Remove the files from the database, move the files' data to their new paths & save the
+        // new layout, deleting the account directories on a failure.
+        let commit_result = copy_result.and_then(|()| {
+            let tx = self.database.open_transaction()?;
+            FileStore::remove_all(&tx)?;
+            Self::transaction_move_files(
+                &new_store,
+                &files_data,
+                &shared_files,
+                &file_revisions,
+                &tx,
+            )?;
+            Ok(tx.commit()?)
+        });
+        if let Err(err) = commit_result {
+            for account_dir in account_dirs {
+                let _ = fs::remove_dir_all(account_dir);
+            }
+            return Err(err);
+        }
+        self.file_store = new_store;
+        Ok(())
+    }
+
+    // Helper function: Return the path in the `new_store` of the file at `path` in the other
+    // layout. Either way, a file's path ends with its account name & its own name.
+    fn moved_path(new_store: &FileStore, path: &Utf8Path) -> eyre::Result<Utf8PathBuf> {
+        let (Some(account_name), Some(file_name)) = (
+            path.parent().and_then(Utf8Path::file_name),
+            path.file_name(),
+        ) else {
+            return Err(eyre!("{:?} is not in an account's file directory.", path));
+        };
+        Ok(new_store.account_dir(account_name)?.join(file_name))
+    }
+
+    // Helper function: Move the given [FileData], [SharedFile]s & [FileRevision]s to their paths in
+    // the `new_store` & save its layout.
+    fn transaction_move_files(
+        new_store: &FileStore,
+        files_data: &[FileData],
+        shared_files: &[SharedFile],
+        file_revisions: &[FileRevision],
+        tx: &Transaction,
+    ) -> eyre::Result<()> {
+        for file_data in files_data {
+            let num_rows = Database::transaction_update::<FileData, &Utf8Path, &Utf8Path, 1, 1>(
+                [file_data.path()],
+                FileDataUpdateField::Path,
+                [&Self::moved_path(new_store, file_data.path())?],
+                tx,
+            )?;
+            Self::validate_one_row(num_rows)?;
+        }
+        for shared_file in shared_files {
+            let num_rows = Database::transaction_update::<SharedFile, &str, &Utf8Path, 1, 1>(
+                [shared_file.id()],
+                SharedFileUpdateField::Path,
+                [&Self::moved_path(
+                    new_store,
+                    shared_file.file_data().path(),
+                )?],
+                tx,
+            )?;
+            Self::validate_one_row(num_rows)?;
+        }
+        for revision in file_revisions {
+            let num_rows = Database::transaction_update::<FileRevision, &str, &Utf8Path, 1, 1>(
+                [revision.id()],
+                FileRevisionUpdateField::Path,
+                [&Self::moved_path(new_store, revision.path())?],
+                tx,
+            )?;
+            Self::validate_one_row(num_rows)?;
+        }
+        Database::transaction_update_setting(Self::FILE_STORE_SETTING, new_store.layout(), tx)
+    }
+
+    // Helper function: Return the name the account with the given `username` is stored under. In
+    // privacy mode, this is the hex-encoded, salted SHA-256 hash of the `username`. Otherwise, it
+    // is the `username` itself.
+    fn account_name(&self, username: &str) -> String {
+        match &self.privacy_salt {
+            Some(salt) => Sha256::new()
+                .chain_update(Self::ACCOUNT_NAME_DOMAIN)
+                .chain_update(salt)
+                .chain_update(username)
+                .finalize()
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect(),
+            None => username.to_owned(),
+        }
+    }
+
     // ACCOUNT FUNCTIONALITY
 
-    /// Create a new [Account] & add it to the [Database].
-    pub fn create_new_account<U, P>(&mut self, username: U, password: P) -> eyre::Result<()>
+    /// Create a new [Account] & add it to the [Database]. If a [KeyFile] is given, the account
+    /// requires it to be unlocked.
+    ///
+    /// Return the account's [RecoveryCode]. It is not stored anywhere, so it must be shown to the
+    /// user now.
+    pub fn create_new_account<U, P>(
+        &mut self,
+        username: U,
+        password: P,
+        key_file: Option<&KeyFile>,
+    ) -> eyre::Result<RecoveryCode>
     where
         U: AsRef<str>,
         P: AsRef<str>,
     {
+        let account_name = self.account_name(username.as_ref());
         // Create a new account.
-        let account = Account::new(username.as_ref(), password.as_ref())?;
+        let (account, recovery_code) = Account::new_with_params(
+            &account_name,
+            password.as_ref(),
+            key_file,
+            self.kdf_params,
+            self.cipher_suite,
+        )?;
         // Open a new database transaction.
         let tx = self.database.open_transaction()?;
         // Attempt to add the account to the database.
         Database::transaction_insert(account, &tx)?;
         // Attempt to create the account's files directory.
-        new_account_file_dir(&self.filesystem_directory, username.as_ref())?;
+        self.file_store.new_account_dir(&account_name)?;
         // Commit the transaction to the database.
-        Ok(tx.commit()?)
+        tx.commit()?;
+        Ok(recovery_code)
     }
 
-    /// Delete an [Account] from the [Database], rolling back the changes on failure.
+    /// Move the [Account] with the given `username` to the trash. It can't log in until it's
+    /// restored, & everything it shares stops being shared.
     pub fn delete_account<S>(&mut self, username: S) -> eyre::Result<()>
     where
         S: AsRef<str>,
     {
-        // Get the path of this username's directory.
-        let account_dir = get_account_file_dir(&self.filesystem_directory, username.as_ref())?;
+        let account = self.load_account(&username)?;
+        let account_name = account.username();
+        if self
+            .select_trashed_ids(account_name, EntityType::Account)?
+            .contains(account_name)
+        {
+            return Err(eyre!(
+                "Account \"{}\" is already in the trash.",
+                username.as_ref()
+            ));
+        }
+        // Stop sharing its credentials & files.
+        for shared_credential in self
+            .database
+            .select_owned_entries::<SharedCredential, &str, 1>([account_name])?
+        {
+            self.remove_shared_copy(shared_credential.id(), EntityType::Credential)?;
+        }
+        for shared_file in self
+            .database
+            .select_owned_entries::<SharedFile, &str, 1>([account_name])?
+        {
+            self.remove_shared_copy(shared_file.id(), EntityType::File)?;
+        }
+        self.database.insert_entry(TrashedItem::from_fields(
+            account_name.to_owned(),
+            EntityType::Account,
+            account_name.to_owned(),
+            (self.clock)(),
+        ))
+    }
+
+    // Helper function: Delete the [Account] stored under the given `account_name` & everything it
+    // owns, rolling back the changes on failure.
+    fn remove_account(&mut self, account_name: &str) -> eyre::Result<()> {
+        // Ensure this username's directory exists.
+        self.file_store.account_dir(account_name)?;
         // Open a new database transaction.
         let tx = self.database.open_transaction()?;
         // Attempt to delete the account's database entry, cascading all of the account's files and
         // credentials.
-        Database::transaction_delete::<Account, &str, 1>([username.as_ref()], &tx)?;
+        Database::transaction_delete::<Account, &str, 1>([account_name], &tx)?;
         // Attempt to delete the account's files directory.
-        fs::remove_dir_all(account_dir)?;
+        self.file_store.remove_account_dir(&tx, account_name)?;
         // Commit the transaction to the database.
         Ok(tx.commit()?)
     }
@@ -98,20 +679,65 @@ impl Vault {
     where
         S: AsRef<str>,
     {
-        // Get the path of this username's directory.
-        get_account_file_dir(&self.filesystem_directory, username.as_ref())?;
+        let account_name = self.account_name(username.as_ref());
+        // Ensure this username's directory exists.
+        self.file_store.account_dir(&account_name)?;
         // Get the account from the database.
         let loaded_account = self
             .database
-            .select_entry_err_none::<Account, &str, 1>([username.as_ref()])?;
+            .select_entry_err_none::<Account, &str, 1>([&account_name])?;
         Ok(loaded_account)
     }
 
-    /// Load an [UnlockedAccount] with the given `username`.
+    /// Load an [UnlockedAccount] with the given `username`, password, [KeyFile], if the account
+    /// requires one, & [TwoFactorCode], if the account has two-factor login. A [BackupCode] no
+    /// longer works once it has been used to unlock the account, & neither does a TOTP code, or any
+    /// code from an earlier time step.
+    ///
+    /// If the account's password was hashed with [KdfParams] weaker than those of this [Vault], the
+    /// password is re-hashed with the [Vault]'s [KdfParams].
+    ///
+    /// If the account's data was encrypted by an older version of `dgruft`, it is re-encrypted for
+    /// the current [Account::ENCRYPTION_VERSION].
+    ///
+    /// If any of the account's files were created before filenames were encrypted, their filenames
+    /// are encrypted & the files are renamed after their ids.
+    ///
+    /// Once the account is unlocked, its items which have been in the trash for longer than the
+    /// [Vault]'s trash retention period are purged. Accounts in the trash can't be unlocked until
+    /// they're restored.
     pub fn load_unlocked_account<U, P>(
-        &self,
+        &mut self,
+        username: U,
+        password: P,
+        key_file: Option<&KeyFile>,
+        two_factor_code: Option<&TwoFactorCode>,
+    ) -> eyre::Result<UnlockedAccount>
+    where
+        U: AsRef<str>,
+        P: AsRef<str>,
+    {
+        if self.is_account_trashed(username.as_ref())? {
+            return Err(eyre!(
+                "Account \"{}\" is in the trash. Restore it to log in.",
+                username.as_ref()
+            ));
+        }
+        let account_name = self.account_name(username.as_ref());
+        let unlocked_account =
+            self.unlock_account(username, password, key_file, two_factor_code)?;
+        self.purge_expired_trash(&account_name)?;
+        Ok(unlocked_account)
+    }
+
+    // Helper function: Unlock the [Account] with the given `username` like
+    // [Vault::load_unlocked_account], whether or not it's in the trash.
+    fn unlock_account<U, P>(
+        &mut self,
         username: U,
         password: P,
+        key_file: Option<&KeyFile>,
+        two_factor_code: Option<&TwoFactorCode>,
     ) -> eyre::Result<UnlockedAccount>
     where
         U: AsRef<str>,
@@ -119,16 +745,293 @@ impl Vault {
     {
         // Load the account.
         let loaded_account = self.load_account(username.as_ref())?;
+        // Find the TOTP secret backup which the backup code unlocks, if one was given.
+        let used_backup = match two_factor_code {
+            Some(TwoFactorCode::Backup(backup_code)) => {
+                Some(self.unlock_totp_backup(loaded_account.username(), backup_code)?)
+            }
+            _ => None,
+        };
+        let two_factor = match (two_factor_code, &used_backup) {
+            (Some(TwoFactorCode::Totp(code)), _) => {
+                Some(TwoFactor::Code(code.expose(), (self.clock)()))
+            }
+            (Some(TwoFactorCode::Secret(totp_secret)), _) => Some(TwoFactor::Secret(totp_secret)),
+            (_, Some((_, totp_secret))) => Some(TwoFactor::Secret(totp_secret)),
+            _ => None,
+        };
         // Unlock the account.
-        loaded_account.unlock(password.as_ref())
+        let mut unlocked_account =
+            loaded_account.unlock(password.as_ref(), key_file, two_factor)?;
+
+        // Use up the TOTP code's time step, refusing codes which were already used.
+        if let Some(totp_step) = unlocked_account.totp_step() {
+            let tx = self.database.open_transaction()?;
+            if !Database::transaction_advance_totp_step(
+                unlocked_account.username(),
+                totp_step,
+                &tx,
+            )? {
+                return Err(eyre!(
+                    "This two-factor code was already used. Wait for the next one."
+                ));
+            }
+            tx.commit()?;
+        }
+
+        // Use up the backup code.
+        if let Some((totp_backup, _)) = used_backup {
+            let tx = self.database.open_transaction()?;
+            Database::transaction_delete::<TotpBackup, &str, 2>(
+                [totp_backup.owner_username(), totp_backup.id()],
+                &tx,
+            )?;
+            tx.commit()?;
+        }
+
+        // Upgrade outdated password hashes.
+        if unlocked_account
+            .kdf_params()
+            .is_weaker_than(&self.kdf_params)
+        {
+            unlocked_account.change_password(
+                password.as_ref(),
+                key_file,
+                self.kdf_params,
+                self.cipher_suite,
+            )?;
+            let tx = self.database.open_transaction()?;
+            Self::transaction_update_password(&unlocked_account, &tx)?;
+            tx.commit()?;
+        }
+
+        // Finish any interrupted key rotation or file update.
+        self.finish_pending_files(&unlocked_account)?;
+
+        // Encrypt plaintext filenames.
+        self.encrypt_legacy_filenames(&unlocked_account)?;
+
+        // Upgrade outdated encryption.
+        if unlocked_account.encryption_version() < Account::ENCRYPTION_VERSION {
+            self.upgrade_encryption_version(&mut unlocked_account)?;
+            // Replace the old files with the re-encrypted ones.
+            self.finish_pending_files(&unlocked_account)?;
+        }
+
+        // Index the names of credentials & files which lack a name index.
+        self.index_legacy_credentials(&unlocked_account)?;
+        self.index_legacy_files(&unlocked_account)?;
+
+        // Give the account a keypair if it lacks one.
+        if unlocked_account.keypair().is_none() {
+            unlocked_account.generate_keypair(self.cipher_suite)?;
+            let tx = self.database.open_transaction()?;
+            Self::transaction_update_keypair(&unlocked_account, &tx)?;
+            tx.commit()?;
+        }
+
+        Ok(unlocked_account)
+    }
+
+    // Helper function: Find the [TotpBackup] of the account stored under the given `account_name`
+    // which the given [BackupCode] unlocks. Return it along with its decrypted [TotpSecret].
+    fn unlock_totp_backup(
+        &self,
+        account_name: &str,
+        backup_code: &BackupCode,
+    ) -> eyre::Result<(TotpBackup, TotpSecret)> {
+        for totp_backup in self
+            .database
+            .select_owned_entries::<TotpBackup, &str, 1>([account_name])?
+        {
+            if let Ok(totp_secret) = totp_backup.try_unlock(backup_code) {
+                return Ok((totp_backup, totp_secret));
+            }
+        }
+        Err(eyre!("Incorrect two-factor code."))
+    }
+
+    // Helper function: Encrypt the [Filename::Legacy] filenames of an [UnlockedAccount]'s files,
+    // renaming each file after its id. Each file is renamed before its new [FileData] is committed,
+    // so if this is interrupted, the file is found under its new name the next time.
+    fn encrypt_legacy_filenames(&mut self, unlocked_account: &UnlockedAccount) -> eyre::Result<()> {
+        let username = unlocked_account.username();
+        for file_data in self.select_account_files_data(username)? {
+            let Filename::Legacy(filename) = file_data.stored_filename() else {
+                continue;
+            };
+            // Create the new file data.
+            let new_path = self.file_store.file_path(username, file_data.id())?;
+            let encrypted_filename =
+                file_data.encrypt_filename(unlocked_account.key(), self.cipher_suite, filename)?;
+            let new_file_data = FileData::from_fields(
+                file_data.id().to_owned(),
+                new_path.clone(),
+                Filename::Encrypted(encrypted_filename),
+                username.to_owned(),
+                file_data.contents_nonce(),
+                file_data.contents_chunk_size(),
+                None,
+            );
+            // Rename the file.
+            let conn = self.database.connection();
+            if self.file_store.exists(conn, file_data.path())? {
+                self.file_store.replace(conn, file_data.path(), &new_path)?;
+            }
+            // Replace the file data, renaming the file back on a failure.
+            let commit_result = self.database.open_transaction().and_then(|tx| {
+                Database::transaction_delete::<FileData, &Utf8Path, 1>([file_data.path()], &tx)?;
+                Database::transaction_insert(new_file_data, &tx)?;
+                Ok(tx.commit()?)
+            });
+            if let Err(err) = commit_result {
+                let conn = self.database.connection();
+                let _ = self.file_store.replace(conn, &new_path, file_data.path());
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    // Helper function: Give a name index to each of an [UnlockedAccount]'s credentials which lacks
+    // one. A credential whose name is already indexed by another credential stays unindexed, so it
+    // can still be loaded, but no new credential can take its name.
+    fn index_legacy_credentials(&mut self, unlocked_account: &UnlockedAccount) -> eyre::Result<()> {
+        let username = unlocked_account.username();
+        let key = unlocked_account.key();
+        let credentials = self.select_account_credentials(username)?;
+        let mut name_indices: HashSet<Hash<32>> = credentials
+            .iter()
+            .filter_map(|credential| credential.name_index().copied())
+            .collect();
+        // Trashed credentials stay unindexed until they're restored.
+        let trashed_ids = self.select_trashed_ids(username, EntityType::Credential)?;
+
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        for credential in credentials.iter().filter(|credential| {
+            credential.name_index().is_none() && !trashed_ids.contains(credential.id())
+        }) {
+            let name_index = Credential::compute_name_index(
+                key,
+                credential.name::<Secret<String>>(key)?.expose(),
+            )?;
+            if !name_indices.insert(name_index) {
+                continue;
+            }
+            let num_rows = Database::transaction_update::<Credential, Value, &[u8], 2, 1>(
+                credential_primary_key(username, credential.encrypted_name().cipherbytes()),
+                CredentialUpdateField::NameIndex,
+                [&name_index],
+                &tx,
+            )?;
+            Self::validate_one_row(num_rows)?;
+        }
+        // Commit the database transaction.
+        Ok(tx.commit()?)
+    }
+
+    // Helper function: Give a name index to each of an [UnlockedAccount]'s files which lacks one,
+    // like [Vault::index_legacy_credentials].
+    fn index_legacy_files(&mut self, unlocked_account: &UnlockedAccount) -> eyre::Result<()> {
+        let username = unlocked_account.username();
+        let key = unlocked_account.key();
+        let files_data = self.select_account_files_data(username)?;
+        let mut name_indices: HashSet<Hash<32>> = files_data
+            .iter()
+            .filter_map(|file_data| file_data.name_index().copied())
+            .collect();
+        // Trashed files stay unindexed until they're restored.
+        let trashed_ids = self.select_trashed_ids(username, EntityType::File)?;
+
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        for file_data in files_data.iter().filter(|file_data| {
+            file_data.name_index().is_none() && !trashed_ids.contains(file_data.id())
+        }) {
+            let name_index = FileData::compute_name_index(
+                key,
+                file_data.filename::<Secret<String>>(key)?.expose(),
+            )?;
+            if !name_indices.insert(name_index) {
+                continue;
+            }
+            Self::transaction_update_file_name_index(file_data.path(), &name_index, &tx)?;
+        }
+        // Commit the database transaction.
+        Ok(tx.commit()?)
     }
 
-    /// Change the password of an [Account].
+    // Helper function: Re-encrypt all the data of an [UnlockedAccount] with encryption version 0,
+    // binding each [Encrypted] to its [AssociatedData]. The re-encrypted files are written to their
+    // pending siblings.
+    fn upgrade_encryption_version(
+        &mut self,
+        unlocked_account: &mut UnlockedAccount,
+    ) -> eyre::Result<()> {
+        let username = unlocked_account.username().to_owned();
+        let key = unlocked_account.key();
+        let credentials = self.select_account_credentials(&username)?;
+        let files_data = self.select_account_files_data(&username)?;
+
+        // Re-encrypt each file to its pending sibling, binding it to its associated data.
+        let new_nonces = Self::write_pending_files(
+            &self.file_store,
+            self.database.connection(),
+            &files_data,
+            key,
+            |_| vec![],
+            key,
+            self.cipher_suite,
+        )?;
+
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Replace each credential with a re-encrypted one.
+        for credential in credentials {
+            // The names are indexed afterwards, once any duplicate names can be found.
+            let upgraded_credential = Self::without_name_index(Credential::try_new_with_id(
+                credential.id(),
+                &username,
+                key,
+                self.cipher_suite,
+                Secret::<String>::try_decrypt(credential.encrypted_name(), key)?.expose(),
+                Secret::<String>::try_decrypt(credential.encrypted_username(), key)?.expose(),
+                Secret::<String>::try_decrypt(credential.encrypted_password(), key)?.expose(),
+                Secret::<String>::try_decrypt(credential.encrypted_notes(), key)?.expose(),
+            )?);
+            Database::transaction_delete::<Credential, Value, 2>(
+                credential_primary_key(&username, credential.encrypted_name().cipherbytes()),
+                &tx,
+            )?;
+            Database::transaction_insert(upgraded_credential, &tx)?;
+        }
+        // Update the contents header of each file.
+        for (file_data, new_nonce) in files_data.iter().zip(new_nonces) {
+            Self::transaction_update_contents_header(
+                file_data.path(),
+                new_nonce,
+                DEFAULT_CHUNK_SIZE,
+                &tx,
+            )?;
+        }
+        // Re-encrypt the account key & update the encryption version.
+        unlocked_account.upgrade_encryption_version(self.cipher_suite)?;
+        Self::transaction_update_password(unlocked_account, &tx)?;
+        // Commit the database transaction.
+        Ok(tx.commit()?)
+    }
+
+    /// Change the password & [KeyFile] of an [Account]. If no new [KeyFile] is given, the account
+    /// no longer requires one.
     pub fn change_account_password<U, O, N>(
         &mut self,
         username: U,
         old_password: O,
+        old_key_file: Option<&KeyFile>,
+        two_factor_code: Option<&TwoFactorCode>,
         new_password: N,
+        new_key_file: Option<&KeyFile>,
     ) -> eyre::Result<()>
     where
         U: AsRef<str>,
@@ -136,421 +1039,3352 @@ impl Vault {
         N: AsRef<str>,
     {
         // Load & unlock the account.
-        let mut unlocked_account =
-            self.load_unlocked_account(username.as_ref(), old_password.as_ref())?;
+        let mut unlocked_account = self.load_unlocked_account(
+            username.as_ref(),
+            old_password.as_ref(),
+            old_key_file,
+            two_factor_code,
+        )?;
         // Change unlocked account's password.
-        unlocked_account.change_password(new_password.as_ref())?;
+        unlocked_account.change_password(
+            new_password.as_ref(),
+            new_key_file,
+            self.kdf_params,
+            self.cipher_suite,
+        )?;
 
         // Open a new database transaction.
         let tx = self.database.open_transaction()?;
-        // Update the password salt.
-        let num_rows = Database::transaction_update::<Account, &str, Salt<64>, 1, 1>(
-            [username.as_ref()],
-            AccountUpdateField::PasswordSalt,
-            [*unlocked_account.hashed_password().salt()],
-            &tx,
-        )?;
-        Self::validate_one_row(num_rows)?;
-        // Update the double-hashed password hash.
-        let num_rows = Database::transaction_update::<Account, &str, Hash<32>, 1, 1>(
-            [username.as_ref()],
-            AccountUpdateField::DblHashedPasswordHash,
-            [*unlocked_account.dbl_hashed_password().hash()],
-            &tx,
-        )?;
-        Self::validate_one_row(num_rows)?;
-        // Update the double-hashed password salt.
-        let num_rows = Database::transaction_update::<Account, &str, Salt<64>, 1, 1>(
-            [username.as_ref()],
-            AccountUpdateField::DblHashedPasswordSalt,
-            [*unlocked_account.dbl_hashed_password().salt()],
-            &tx,
-        )?;
-        Self::validate_one_row(num_rows)?;
-        // Update the encrypted key cipherbytes.
-        let num_rows = Database::transaction_update::<Account, &str, &[u8], 1, 1>(
-            [username.as_ref()],
-            AccountUpdateField::EncryptedKeyCipherbytes,
-            [unlocked_account.encrypted_key().cipherbytes()],
-            &tx,
-        )?;
-        Self::validate_one_row(num_rows)?;
-        // Update the encrypted key nonce.
-        let num_rows = Database::transaction_update::<Account, &str, Aes256Nonce, 1, 1>(
-            [username.as_ref()],
-            AccountUpdateField::EncryptedKeyNonce,
-            [unlocked_account.encrypted_key().nonce()],
-            &tx,
-        )?;
-        Self::validate_one_row(num_rows)?;
+        // Update the password-derived fields.
+        Self::transaction_update_password(&unlocked_account, &tx)?;
         // Commit the database transaction.
         Ok(tx.commit()?)
     }
 
-    // CREDENTIAL FUNCTIONALITY
-
-    /// Create a new [Credential] & add it to the [Database].
-    pub fn create_credential<A, B, C, D, E>(
+    /// Unlock an [Account] with its [RecoveryCode] & give it a new password & [KeyFile]. If no new
+    /// [KeyFile] is given, the account no longer requires one.
+    ///
+    /// The [RecoveryCode] remains valid until it is regenerated or revoked. Two-factor login is
+    /// turned off, & its [BackupCode]s no longer work.
+    pub fn recover_account<U, N>(
         &mut self,
-        owner_username: A,
-        key: Aes256Key,
-        name: B,
-        username: C,
-        password: D,
-        notes: E,
-    ) -> eyre::Result<()>
-    where
-        A: AsRef<str>,
-        B: AsRef<str>,
-        C: AsRef<str>,
-        D: AsRef<str>,
-        E: AsRef<str>,
+        username: U,
+        recovery_code: &RecoveryCode,
+        new_password: N,
+        new_key_file: Option<&KeyFile>,
+    ) -> eyre::Result<UnlockedAccount>
+    where
+        U: AsRef<str>,
+        N: AsRef<str>,
     {
-        // Create the credential.
-        let credential = Credential::try_new(
-            owner_username.as_ref(),
-            key,
-            name.as_ref(),
+        // Load & recover the account.
+        let unlocked_account = self.load_account(username.as_ref())?.recover(
+            recovery_code,
+            new_password.as_ref(),
+            new_key_file,
+            self.kdf_params,
+            self.cipher_suite,
+        )?;
+        self.commit_recovery(unlocked_account)
+    }
+
+    /// Unlock an [Account] with at least `threshold` of its [KeyShare]s & give it a new password &
+    /// [KeyFile]. If no new [KeyFile] is given, the account no longer requires one.
+    ///
+    /// The [KeyShare]s remain valid until they are split again or revoked. Two-factor login is
+    /// turned off, & its [BackupCode]s no longer work.
+    pub fn recover_account_with_shares<U, N>(
+        &mut self,
+        username: U,
+        shares: &[KeyShare],
+        new_password: N,
+        new_key_file: Option<&KeyFile>,
+    ) -> eyre::Result<UnlockedAccount>
+    where
+        U: AsRef<str>,
+        N: AsRef<str>,
+    {
+        // Load & recover the account.
+        let unlocked_account = self.load_account(username.as_ref())?.recover_with_shares(
+            shares,
+            new_password.as_ref(),
+            new_key_file,
+            self.kdf_params,
+            self.cipher_suite,
+        )?;
+        self.commit_recovery(unlocked_account)
+    }
+
+    // Helper function: Save the new password of a recovered [UnlockedAccount] & remove its
+    // [BackupCode]s.
+    fn commit_recovery(
+        &mut self,
+        unlocked_account: UnlockedAccount,
+    ) -> eyre::Result<UnlockedAccount> {
+        let old_totp_backups = self
+            .database
+            .select_owned_entries::<TotpBackup, &str, 1>([unlocked_account.username()])?;
+
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Update the password-derived fields.
+        Self::transaction_update_password(&unlocked_account, &tx)?;
+        // Remove the backup codes.
+        Self::transaction_replace_totp_backups(
+            &unlocked_account,
+            &old_totp_backups,
+            self.cipher_suite,
+            &tx,
+        )?;
+        // Commit the database transaction.
+        tx.commit()?;
+
+        // Finish any interrupted key rotation or file update.
+        self.finish_pending_files(&unlocked_account)?;
+        Ok(unlocked_account)
+    }
+
+    /// Replace the [RecoveryCode] of an [Account] with a new one, returning the new
+    /// [RecoveryCode]. The old [RecoveryCode], if any, no longer works.
+    pub fn regenerate_recovery_code<U, P>(
+        &mut self,
+        username: U,
+        password: P,
+        key_file: Option<&KeyFile>,
+        two_factor_code: Option<&TwoFactorCode>,
+    ) -> eyre::Result<RecoveryCode>
+    where
+        U: AsRef<str>,
+        P: AsRef<str>,
+    {
+        // Load & unlock the account.
+        let mut unlocked_account = self.load_unlocked_account(
             username.as_ref(),
             password.as_ref(),
-            notes.as_ref(),
+            key_file,
+            two_factor_code,
         )?;
-        // Verify that the credential will be unique.
-        if self.load_credential(&owner_username, &name, key).is_ok() {
-            return
-                Err(
-                    eyre!(
-                        "Failed to create new credential: A credential named \"{}\" owned by account \"{}\" already exists.",
-                        name.as_ref(),
-                        owner_username.as_ref()
-                        )
-                    );
-        }
+        // Generate a new recovery code.
+        let recovery_code = unlocked_account.regenerate_recovery_code(self.cipher_suite)?;
 
         // Open a new database transaction.
         let tx = self.database.open_transaction()?;
-        // Attempt to add a credential to the database.
-        Database::transaction_insert(credential, &tx)?;
+        // Update the recovery fields.
+        Self::transaction_update_recovery(&unlocked_account, &tx)?;
         // Commit the database transaction.
-        Ok(tx.commit()?)
+        tx.commit()?;
+        Ok(recovery_code)
     }
 
-    /// Delete a [Credential] from the [Database], rolling back the changes on failure.
-    pub fn delete_credential<O, N>(
+    /// Remove the [RecoveryCode] of an [Account], so it can only be unlocked with its password.
+    pub fn revoke_recovery_code<U, P>(
         &mut self,
-        owner_username: O,
-        name: N,
-        key: Aes256Key,
+        username: U,
+        password: P,
+        key_file: Option<&KeyFile>,
+        two_factor_code: Option<&TwoFactorCode>,
     ) -> eyre::Result<()>
     where
-        O: AsRef<str>,
-        N: AsRef<str>,
+        U: AsRef<str>,
+        P: AsRef<str>,
     {
-        // Find the credential to delete.
-        let loaded_credential = self.load_credential(&owner_username, &name, key)?;
+        // Load & unlock the account.
+        let mut unlocked_account = self.load_unlocked_account(
+            username.as_ref(),
+            password.as_ref(),
+            key_file,
+            two_factor_code,
+        )?;
+        // Remove the recovery code.
+        unlocked_account.revoke_recovery_code();
+
         // Open a new database transaction.
         let tx = self.database.open_transaction()?;
-        // Delete the credential.
-        Database::transaction_delete::<Credential, &[u8], 2>(
-            [
-                owner_username.as_ref().as_bytes(),
-                loaded_credential.encrypted_name().cipherbytes(),
-            ],
-            &tx,
-        )?;
+        // Clear the recovery fields.
+        Self::transaction_update_recovery(&unlocked_account, &tx)?;
         // Commit the database transaction.
         Ok(tx.commit()?)
     }
 
-    /// Load the [Credential] with the given `owner_username` & `name`.
-    pub fn load_credential<O, N>(
-        &self,
-        owner_username: O,
-        name: N,
-        key: Aes256Key,
-    ) -> eyre::Result<Credential>
+    /// Split a new share key of an [Account] into `count` [KeyShare]s, any `threshold` of which
+    /// can recover the account, & return them. The old [KeyShare]s, if any, no longer work.
+    #[allow(clippy::too_many_arguments)]
+    pub fn split_account_key<U, P>(
+        &mut self,
+        username: U,
+        password: P,
+        key_file: Option<&KeyFile>,
+        two_factor_code: Option<&TwoFactorCode>,
+        threshold: u8,
+        count: u8,
+    ) -> eyre::Result<Vec<KeyShare>>
     where
-        O: AsRef<str>,
-        N: AsRef<str>,
+        U: AsRef<str>,
+        P: AsRef<str>,
     {
-        // Get the credentials owned by this account.
-        let owned_credentials = self.load_account_credentials(owner_username.as_ref())?;
-        // Find the credential that matches the given name.
-        for credential in owned_credentials {
-            if credential.name::<String>(key)? == name.as_ref() {
-                // Match found.
-                return Ok(credential);
-            }
-        }
-        Err(eyre!(
-            "No credentials named \"{}\" are owned by account \"{}\".",
-            name.as_ref(),
-            owner_username.as_ref()
-        ))
-    }
+        // Load & unlock the account.
+        let mut unlocked_account = self.load_unlocked_account(
+            username.as_ref(),
+            password.as_ref(),
+            key_file,
+            two_factor_code,
+        )?;
+        // Split a new share key.
+        let shares = unlocked_account.split_key(threshold, count, self.cipher_suite)?;
 
-    /// Load all [Credential] belonging to the given `owner_username`.
-    pub fn load_account_credentials<S>(&self, owner_username: S) -> eyre::Result<Vec<Credential>>
-    where
-        S: AsRef<str>,
-    {
-        self.database
-            .select_owned_entries([owner_username.as_ref()])
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Update the recovery fields.
+        Self::transaction_update_recovery(&unlocked_account, &tx)?;
+        // Commit the database transaction.
+        tx.commit()?;
+        Ok(shares)
     }
 
-    /// Update a [Credential]'s field.
-    pub fn update_credential<O, N, V>(
+    /// Remove the [KeyShare]s of an [Account], so they can no longer recover it.
+    pub fn revoke_key_shares<U, P>(
         &mut self,
-        owner_username: O,
-        name: N,
-        key: Aes256Key,
-        cipherbytes_field: CredentialUpdateField,
-        nonce_field: CredentialUpdateField,
-        new_value: V,
+        username: U,
+        password: P,
+        key_file: Option<&KeyFile>,
+        two_factor_code: Option<&TwoFactorCode>,
     ) -> eyre::Result<()>
     where
-        O: AsRef<str>,
-        N: AsRef<str>,
-        V: AsRef<str>,
+        U: AsRef<str>,
+        P: AsRef<str>,
     {
-        // Load the credential.
-        let credential = self.load_credential(owner_username.as_ref(), name.as_ref(), key)?;
-        // Get the primary key of the credential.
-        let primary_key = [
-            owner_username.as_ref().as_bytes(),
-            credential.encrypted_name().cipherbytes(),
-        ];
-        // Encrypt the new value.
-        let encrypted_new_value = new_value.as_ref().try_encrypt_with_key(key)?;
-        // Open a new transaction.
-        let tx = self.database.open_transaction()?;
-        // Update the field's cipherbytes.
-        let num_rows = Database::transaction_update::<Credential, &[u8], &[u8], 2, 1>(
-            primary_key,
-            cipherbytes_field,
-            [encrypted_new_value.cipherbytes()],
-            &tx,
-        )?;
-        Self::validate_one_row(num_rows)?;
-        // Update the field's nonce.
-        let num_rows = Database::transaction_update::<Credential, &[u8], Aes256Nonce, 2, 1>(
-            primary_key,
-            nonce_field,
-            [encrypted_new_value.nonce()],
-            &tx,
+        // Load & unlock the account.
+        let mut unlocked_account = self.load_unlocked_account(
+            username.as_ref(),
+            password.as_ref(),
+            key_file,
+            two_factor_code,
         )?;
-        Self::validate_one_row(num_rows)?;
-        // Commit the transaction.
+        // Remove the key shares.
+        unlocked_account.revoke_key_shares();
+
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Clear the recovery fields.
+        Self::transaction_update_recovery(&unlocked_account, &tx)?;
+        // Commit the database transaction.
         Ok(tx.commit()?)
     }
 
-    // FILE FUNCTIONALITY
-
-    /// Create a new file, along with its corresponding [FileData], & add it to the [Database].
-    pub fn create_file<F, O, E>(
+    /// Turn on two-factor login for an [Account] with the given [TotpSecret], returning the
+    /// account's new [BackupCode]s. The `code` must be the current code of the [TotpSecret], to
+    /// confirm that it has been added to the user's authenticator app.
+    pub fn enable_two_factor<U, P>(
         &mut self,
-        filename: F,
-        owner_username: O,
-        contents: E,
-        key: Aes256Key,
-    ) -> eyre::Result<()>
+        username: U,
+        password: P,
+        key_file: Option<&KeyFile>,
+        totp_secret: &TotpSecret,
+        code: &str,
+    ) -> eyre::Result<Vec<BackupCode>>
     where
-        F: AsRef<str>,
-        O: AsRef<str>,
-        E: TryIntoEncrypted,
+        U: AsRef<str>,
+        P: AsRef<str>,
     {
-        // Get the future path of this file.
-        let file_path = get_file_path(&self.filesystem_directory, &owner_username, &filename)?;
-
-        // Encrypt the contents of the new file.
-        let encrypted_contents = contents.try_encrypt_with_key(key)?;
-
-        // Create the file data.
-        let file_data = FileData::new(
-            &file_path,
-            filename.as_ref().to_owned(),
-            owner_username.as_ref().to_owned(),
-            encrypted_contents.nonce().to_owned(),
-        );
+        // Ensure that two-factor login isn't already on.
+        if self.load_account(username.as_ref())?.requires_two_factor() {
+            return Err(eyre!(
+                "Two-factor login is already enabled for account {}.",
+                username.as_ref()
+            ));
+        }
+        // Load & unlock the account.
+        let mut unlocked_account =
+            self.load_unlocked_account(username.as_ref(), password.as_ref(), key_file, None)?;
+        // Ensure that the authenticator app has the TOTP secret.
+        let Some(totp_step) = totp_secret.check_code(code, (self.clock)())? else {
+            return Err(eyre!("Incorrect two-factor code."));
+        };
+        // Turn on two-factor login.
+        unlocked_account.enable_two_factor(totp_secret, self.cipher_suite)?;
+        let old_totp_backups = self
+            .database
+            .select_owned_entries::<TotpBackup, &str, 1>([unlocked_account.username()])?;
 
         // Open a new database transaction.
         let tx = self.database.open_transaction()?;
-        // Attempt to add the file data to the database.
-        Database::transaction_insert(file_data, &tx)?;
-        // Attempt to create a new file with the encrypted contents.
-        new_file(file_path, encrypted_contents.cipherbytes())?;
-        // Commit the transaction.
-        Ok(tx.commit()?)
+        // Update the password-derived fields.
+        Self::transaction_update_password(&unlocked_account, &tx)?;
+        // Add the backup codes.
+        let backup_codes = Self::transaction_replace_totp_backups(
+            &unlocked_account,
+            &old_totp_backups,
+            self.cipher_suite,
+            &tx,
+        )?;
+        // Don't let the code be used again to log in. A code of an older TOTP secret may already
+        // have used its time step, which is fine.
+        Database::transaction_advance_totp_step(unlocked_account.username(), totp_step, &tx)?;
+        // Commit the database transaction.
+        tx.commit()?;
+        Ok(backup_codes)
     }
 
-    /// Delete a file and its corresponding [FileData] from the [Database], rolling back the
-    /// changes on a failure.
-    pub fn delete_file<U, F>(&mut self, username: U, filename: F) -> eyre::Result<()>
+    /// Turn off two-factor login for an [Account]. Its [BackupCode]s no longer work.
+    pub fn disable_two_factor<U, P>(
+        &mut self,
+        username: U,
+        password: P,
+        key_file: Option<&KeyFile>,
+        two_factor_code: Option<&TwoFactorCode>,
+    ) -> eyre::Result<()>
     where
         U: AsRef<str>,
-        F: AsRef<str>,
+        P: AsRef<str>,
     {
-        // Get the file path.
-        let file_path = get_file_path(&self.filesystem_directory, &username, &filename)?;
+        // Load & unlock the account.
+        let mut unlocked_account = self.load_unlocked_account(
+            username.as_ref(),
+            password.as_ref(),
+            key_file,
+            two_factor_code,
+        )?;
+        if unlocked_account.totp_secret().is_none() {
+            return Err(eyre!(
+                "Two-factor login isn't enabled for account {}.",
+                username.as_ref()
+            ));
+        }
+        // Turn off two-factor login.
+        unlocked_account.disable_two_factor(self.cipher_suite)?;
+        let old_totp_backups = self
+            .database
+            .select_owned_entries::<TotpBackup, &str, 1>([unlocked_account.username()])?;
+
         // Open a new database transaction.
         let tx = self.database.open_transaction()?;
-        // Delete the file data entry.
-        Database::transaction_delete::<FileData, &Utf8Path, 1>([&file_path], &tx)?;
-        // Delete the file.
-        fs::remove_file(&file_path)?;
+        // Update the password-derived fields.
+        Self::transaction_update_password(&unlocked_account, &tx)?;
+        // Remove the backup codes.
+        Self::transaction_replace_totp_backups(
+            &unlocked_account,
+            &old_totp_backups,
+            self.cipher_suite,
+            &tx,
+        )?;
         // Commit the database transaction.
         Ok(tx.commit()?)
     }
 
-    /// Load the file and [FileData] with the given `owner_username` & `filename`.
-    pub fn load_file<U, F, E>(
-        &self,
+    /// Replace the [BackupCode]s of an [Account] with two-factor login with new ones, returning
+    /// the new [BackupCode]s. The old [BackupCode]s no longer work.
+    pub fn regenerate_backup_codes<U, P>(
+        &mut self,
         username: U,
-        filename: F,
-        key: Aes256Key,
-    ) -> eyre::Result<(FileData, E)>
+        password: P,
+        key_file: Option<&KeyFile>,
+        two_factor_code: Option<&TwoFactorCode>,
+    ) -> eyre::Result<Vec<BackupCode>>
     where
         U: AsRef<str>,
-        F: AsRef<str>,
-        E: TryFromEncrypted,
+        P: AsRef<str>,
     {
-        // Get the file path.
-        let file_path = get_file_path(&self.filesystem_directory, &username, &filename)?;
-        // Load the file data.
-        let file_data: FileData = self.database.select_entry_err_none([&file_path])?;
-        // Load the encrypted file contents.
-        let file = open_file(&file_path)?;
-        let encrypted_file_bytes = read_file_bytes(&file)?;
-        let encrypted_file =
-            Encrypted::from_fields(encrypted_file_bytes, file_data.contents_nonce());
-        // Decrypt and load the file contents.
-        let decrypted_contents: E = E::try_decrypt(&encrypted_file, key)?;
+        // Load & unlock the account.
+        let unlocked_account = self.load_unlocked_account(
+            username.as_ref(),
+            password.as_ref(),
+            key_file,
+            two_factor_code,
+        )?;
+        if unlocked_account.totp_secret().is_none() {
+            return Err(eyre!(
+                "Two-factor login isn't enabled for account {}.",
+                username.as_ref()
+            ));
+        }
+        let old_totp_backups = self
+            .database
+            .select_owned_entries::<TotpBackup, &str, 1>([unlocked_account.username()])?;
 
-        Ok((file_data, decrypted_contents))
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Replace the backup codes.
+        let backup_codes = Self::transaction_replace_totp_backups(
+            &unlocked_account,
+            &old_totp_backups,
+            self.cipher_suite,
+            &tx,
+        )?;
+        // Commit the database transaction.
+        tx.commit()?;
+        Ok(backup_codes)
     }
 
-    /// Load all [FileData] belonging to the given `owner_username`.
-    pub fn load_account_files_data<S>(&self, owner_username: S) -> eyre::Result<Vec<FileData>>
+    /// Return the number of unused [BackupCode]s of an [Account].
+    pub fn count_backup_codes<S>(&self, username: S) -> eyre::Result<usize>
     where
         S: AsRef<str>,
     {
-        self.database
-            .select_owned_entries([owner_username.as_ref()])
+        Ok(self
+            .database
+            .select_owned_entries::<TotpBackup, &str, 1>([&self.account_name(username.as_ref())])?
+            .len())
     }
 
-    /// Update a file's content.
-    pub fn update_file<U, F, B>(
+    /// Replace the key of an [Account] with a new, random [Aes256Key], re-encrypting all its
+    /// [Credential]s and files with the new key.
+    ///
+    /// If the account has a [RecoveryCode], it's replaced with a new one, which is returned. It is
+    /// not stored anywhere, so it must be shown to the user. Any [KeyShare]s are revoked, & the
+    /// account gets a new keypair, to which the items shared with it are re-encrypted.
+    ///
+    /// The re-encrypted files are first written next to the originals. They replace the originals
+    /// only once the new key has been committed to the [Database]. If this is interrupted, the
+    /// rotation is either finished or undone the next time the account is loaded.
+    pub fn rotate_account_key<U, P>(
         &mut self,
         username: U,
-        filename: F,
-        key: Aes256Key,
-        new_file_contents: B,
-    ) -> eyre::Result<()>
+        password: P,
+        key_file: Option<&KeyFile>,
+        two_factor_code: Option<&TwoFactorCode>,
+    ) -> eyre::Result<Option<RecoveryCode>>
     where
         U: AsRef<str>,
-        F: AsRef<str>,
-        B: AsRef<[u8]>,
+        P: AsRef<str>,
     {
-        // Get the file path.
-        let file_path = get_file_path(&self.filesystem_directory, &username, &filename)?;
-        // Encrypt the new file contents.
-        let encrypted_contents = new_file_contents.as_ref().try_encrypt_with_key(key)?;
-        // Open a new transaction.
-        let tx = self.database.open_transaction()?;
-        // Update the file data contents nonce.
-        let num_rows = Database::transaction_update::<FileData, &Utf8Path, Aes256Nonce, 1, 1>(
-            [&file_path],
-            FileDataUpdateField::ContentsNonce,
-            [encrypted_contents.nonce()],
-            &tx,
+        // Load & unlock the account.
+        let mut unlocked_account = self.load_unlocked_account(
+            username.as_ref(),
+            password.as_ref(),
+            key_file,
+            two_factor_code,
         )?;
-        Self::validate_one_row(num_rows)?;
-        // Write the cipherbytes to the file.
-        write_file(&file_path, encrypted_contents.cipherbytes())?;
-        // Commit the transaction.
-        Ok(tx.commit()?)
-    }
-    // Helper function: Ensure that exactly one row was updated.
-    fn validate_one_row(num_rows: usize) -> eyre::Result<()> {
-        match num_rows {
-            1 => Ok(()),
-            num => Err(eyre!(
-                "Tried to update 1 row; {num} matches found. No changes to database were made."
-            )),
-        }
+        // Re-encrypt everything & commit the new key.
+        let recovery_code = self.commit_key_rotation(&mut unlocked_account)?;
+        // Replace the old files with the re-encrypted ones.
+        self.finish_pending_files(&unlocked_account)?;
+        Ok(recovery_code)
     }
 
-    // GENERAL
+    // Helper function: Rotate the key of an [UnlockedAccount], writing its re-encrypted files to
+    // their pending siblings & committing everything else to the [Database]. Return the account's
+    // new [RecoveryCode], if it has one.
+    fn commit_key_rotation(
+        &mut self,
+        unlocked_account: &mut UnlockedAccount,
+    ) -> eyre::Result<Option<RecoveryCode>> {
+        let username = unlocked_account.username().to_owned();
+        let old_key = unlocked_account.key().clone();
+        let old_private_key = match unlocked_account.keypair() {
+            Some(_) => Some(unlocked_account.private_key()?),
+            None => None,
+        };
+        let recovery_code = unlocked_account.rotate_key(self.cipher_suite)?;
+        let new_key = unlocked_account.key();
 
-    /// Load all of a given entity.
-    pub fn load_all<T>(&self) -> eyre::Result<Vec<T>>
-    where
-        T: TryFromDatabase + HasSqlStatements,
-    {
+        let credentials = self.select_account_credentials(&username)?;
+        let credential_versions = self
+            .database
+            .select_owned_entries::<CredentialVersion, &str, 1>([&username])?;
+        let files_data = self.select_account_files_data(&username)?;
+        let file_revisions = self
+            .database
+            .select_owned_entries::<FileRevision, &str, 1>([&username])?;
+        let shared_credentials = self
+            .database
+            .select_owned_entries::<SharedCredential, &str, 1>([&username])?;
+        let shared_files = self
+            .database
+            .select_owned_entries::<SharedFile, &str, 1>([&username])?;
+        let received_share_grants = self.database.select_received_share_grants(&username)?;
+
+        // Write the re-encrypted files & file revisions to their pending siblings. Revisions are
+        // encrypted like the file they belong to.
+        let mut contents_data = files_data.clone();
+        for revision in &file_revisions {
+            let file_data = files_data
+                .iter()
+                .find(|file_data| file_data.id() == revision.file_id())
+                .ok_or_else(|| eyre!("File revision {} has no file.", revision.id()))?;
+            contents_data.push(revision.contents_file_data(file_data));
+        }
+        let mut new_nonces = Self::write_pending_files(
+            &self.file_store,
+            self.database.connection(),
+            &contents_data,
+            &old_key,
+            FileData::contents_associated_data,
+            new_key,
+            self.cipher_suite,
+        )?;
+        let revision_nonces = new_nonces.split_off(files_data.len());
+
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Replace each credential with a re-encrypted one.
+        for credential in credentials {
+            let rotated_credential = Credential::try_new_with_id(
+                credential.id(),
+                &username,
+                new_key,
+                self.cipher_suite,
+                credential.name::<Secret<String>>(&old_key)?.expose(),
+                credential.username::<Secret<String>>(&old_key)?.expose(),
+                credential.password::<Secret<String>>(&old_key)?.expose(),
+                credential.notes::<Secret<String>>(&old_key)?.expose(),
+            )?;
+            // Credentials left unindexed because of a duplicate name stay unindexed.
+            let rotated_credential = if credential.name_index().is_some() {
+                rotated_credential
+            } else {
+                Self::without_name_index(rotated_credential)
+            };
+            Database::transaction_delete::<Credential, Value, 2>(
+                credential_primary_key(&username, credential.encrypted_name().cipherbytes()),
+                &tx,
+            )?;
+            Database::transaction_insert(rotated_credential, &tx)?;
+        }
+        // Replace each previous version of a credential with a re-encrypted one.
+        for version in credential_versions {
+            let rotated_version = CredentialVersion::try_new_with_id(
+                version.id(),
+                version.credential_id(),
+                &username,
+                new_key,
+                self.cipher_suite,
+                version.replaced_at(),
+                version.username::<Secret<String>>(&old_key)?.expose(),
+                version.password::<Secret<String>>(&old_key)?.expose(),
+                version.notes::<Secret<String>>(&old_key)?.expose(),
+            )?;
+            Database::transaction_delete::<CredentialVersion, &str, 2>(
+                [&username, version.id()],
+                &tx,
+            )?;
+            Database::transaction_insert(rotated_version, &tx)?;
+        }
+        // Update the contents header & encrypted filename of each file.
+        for (file_data, new_nonce) in files_data.iter().zip(new_nonces) {
+            Self::transaction_update_contents_header(
+                file_data.path(),
+                new_nonce,
+                DEFAULT_CHUNK_SIZE,
+                &tx,
+            )?;
+            if let Filename::Encrypted(_) = file_data.stored_filename() {
+                let filename = file_data.filename::<Secret<String>>(&old_key)?;
+                let rotated_filename =
+                    file_data.encrypt_filename(new_key, self.cipher_suite, filename.expose())?;
+                Self::transaction_update_filename(file_data.path(), &rotated_filename, &tx)?;
+                if file_data.name_index().is_some() {
+                    Self::transaction_update_file_name_index(
+                        file_data.path(),
+                        &FileData::compute_name_index(new_key, filename.expose())?,
+                        &tx,
+                    )?;
+                }
+            }
+        }
+        // Update the contents header of each file revision.
+        for (revision, new_nonce) in file_revisions.iter().zip(revision_nonces) {
+            Self::transaction_update_revision_contents_header(
+                revision.id(),
+                new_nonce,
+                DEFAULT_CHUNK_SIZE,
+                &tx,
+            )?;
+        }
+        // Re-encrypt the item key of each shared item. The shared copies are encrypted with their
+        // item keys, so they stay as they are.
+        for shared_credential in shared_credentials {
+            Database::transaction_delete::<SharedCredential, &str, 1>(
+                [shared_credential.id()],
+                &tx,
+            )?;
+            Database::transaction_insert(
+                shared_credential.rewrap(&old_key, new_key, self.cipher_suite)?,
+                &tx,
+            )?;
+        }
+        for shared_file in shared_files {
+            Database::transaction_delete::<SharedFile, &str, 1>([shared_file.id()], &tx)?;
+            Database::transaction_insert(
+                shared_file.rewrap(&old_key, new_key, self.cipher_suite)?,
+                &tx,
+            )?;
+        }
+        // Re-encrypt the item key of each item shared with the account to its new public key.
+        if let (Some(old_private_key), Some(new_keypair)) =
+            (&old_private_key, unlocked_account.keypair())
+        {
+            for share_grant in received_share_grants {
+                Database::transaction_delete::<ShareGrant, &str, 2>(
+                    [share_grant.shared_id(), share_grant.recipient_username()],
+                    &tx,
+                )?;
+                Database::transaction_insert(
+                    share_grant.rewrap(
+                        old_private_key,
+                        new_keypair.public_key(),
+                        self.cipher_suite,
+                    )?,
+                    &tx,
+                )?;
+            }
+        }
+        // Update the encrypted key.
+        Self::transaction_update_password(unlocked_account, &tx)?;
+        // Replace the recovery code & revoke the key shares.
+        Self::transaction_update_recovery(unlocked_account, &tx)?;
+        // Replace the keypair.
+        Self::transaction_update_keypair(unlocked_account, &tx)?;
+        // Commit the database transaction.
+        tx.commit()?;
+        Ok(recovery_code)
+    }
+
+    // Helper function: Resolve the pending siblings of an [UnlockedAccount]'s files & shared file
+    // copies & file revisions left behind by a key rotation, encryption upgrade, file update, or
+    // unsharing. Pending files which decrypt with the account's current key (or the shared copy's
+    // item key) & contents headers were committed, so they replace the originals. All others are
+    // deleted. When a file update was committed, the replaced contents are first moved to their
+    // revision if that hadn't happened yet. The replaced siblings of files are resolved first.
+    fn finish_pending_files(&self, unlocked_account: &UnlockedAccount) -> eyre::Result<()> {
+        let conn = self.database.connection();
+        let files_data = self.select_account_files_data(unlocked_account.username())?;
+        let file_revisions = self
+            .database
+            .select_owned_entries::<FileRevision, &str, 1>([unlocked_account.username()])?;
+        // Revisions are encrypted like the file they belong to.
+        let revisions_data = file_revisions.iter().filter_map(|revision| {
+            files_data
+                .iter()
+                .find(|file_data| file_data.id() == revision.file_id())
+                .map(|file_data| (Some(revision), revision.contents_file_data(file_data)))
+        });
+        let contents_data = files_data
+            .iter()
+            .map(|file_data| (None, file_data.clone()))
+            .chain(revisions_data.collect::<Vec<_>>());
+        for (revision, contents_data) in contents_data {
+            if revision.is_none() {
+                self.finish_replaced_file(unlocked_account.key(), &contents_data, &file_revisions)?;
+            }
+            let pending_path = get_pending_file_path(contents_data.path(), contents_data.id());
+            if !self.file_store.exists(conn, &pending_path)? {
+                continue;
+            }
+            let is_committed = Self::open_contents(
+                &self.file_store,
+                conn,
+                &contents_data,
+                &pending_path,
+                unlocked_account.key(),
+                &contents_data.contents_associated_data(),
+            )
+            .and_then(|mut contents| Ok(copy_secret(&mut contents, &mut io::sink())?))
+            .is_ok();
+            if !is_committed {
+                self.file_store.remove(conn, &pending_path)?;
+                continue;
+            }
+            // A file update's revision is always its file's newest one.
+            if revision.is_none() {
+                let newest_revision = file_revisions
+                    .iter()
+                    .find(|revision| revision.file_id() == contents_data.id());
+                if let Some(newest_revision) = newest_revision {
+                    if !self.file_store.exists(conn, newest_revision.path())? {
+                        self.file_store.replace(
+                            conn,
+                            contents_data.path(),
+                            newest_revision.path(),
+                        )?;
+                    }
+                }
+            }
+            self.file_store
+                .replace(conn, &pending_path, contents_data.path())?;
+        }
+        for shared_file in self
+            .database
+            .select_owned_entries::<SharedFile, &str, 1>([unlocked_account.username()])?
+        {
+            let file_data = shared_file.file_data();
+            let pending_path = get_pending_file_path(file_data.path(), file_data.id());
+            if !self.file_store.exists(conn, &pending_path)? {
+                continue;
+            }
+            let is_committed = shared_file
+                .item_key(unlocked_account.key())
+                .and_then(|item_key| {
+                    let mut contents = Self::open_contents(
+                        &self.file_store,
+                        conn,
+                        file_data,
+                        &pending_path,
+                        &item_key,
+                        &file_data.contents_associated_data(),
+                    )?;
+                    Ok(copy_secret(&mut contents, &mut io::sink())?)
+                })
+                .is_ok();
+            if is_committed {
+                self.file_store
+                    .replace(conn, &pending_path, file_data.path())?;
+            } else {
+                self.file_store.remove(conn, &pending_path)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Helper function: Resolve the replaced sibling of a file left behind by a file update. If the
+    // update was committed, the file's contents decrypt with its contents header, so the replaced
+    // contents are moved to its newest revision, unless that already has its contents. Otherwise,
+    // the replaced contents are moved back into place.
+    fn finish_replaced_file(
+        &self,
+        key: &Aes256Key,
+        file_data: &FileData,
+        file_revisions: &[FileRevision],
+    ) -> eyre::Result<()> {
+        let conn = self.database.connection();
+        let replaced_path = get_replaced_file_path(file_data.path(), file_data.id());
+        if !self.file_store.exists(conn, &replaced_path)? {
+            return Ok(());
+        }
+        let is_committed = Self::open_contents(
+            &self.file_store,
+            conn,
+            file_data,
+            file_data.path(),
+            key,
+            &file_data.contents_associated_data(),
+        )
+        .and_then(|mut contents| Ok(copy_secret(&mut contents, &mut io::sink())?))
+        .is_ok();
+        if !is_committed {
+            return self
+                .file_store
+                .replace(conn, &replaced_path, file_data.path());
+        }
+        // A file update's revision is always its file's newest one.
+        let newest_revision = file_revisions
+            .iter()
+            .find(|revision| revision.file_id() == file_data.id());
+        match newest_revision {
+            Some(revision) if !self.file_store.exists(conn, revision.path())? => self
+                .file_store
+                .replace(conn, &replaced_path, revision.path()),
+            _ => self.file_store.remove(conn, &replaced_path),
+        }
+    }
+
+    // CREDENTIAL FUNCTIONALITY
+
+    /// Create a new [Credential] & add it to the [Database].
+    pub fn create_credential<A, B, C, D, E>(
+        &mut self,
+        owner_username: A,
+        key: &Aes256Key,
+        name: B,
+        username: C,
+        password: D,
+        notes: E,
+    ) -> eyre::Result<()>
+    where
+        A: AsRef<str>,
+        B: AsRef<str>,
+        C: AsRef<str>,
+        D: AsRef<str>,
+        E: AsRef<str>,
+    {
+        // Create the credential.
+        let credential = Credential::try_new(
+            &self.account_name(owner_username.as_ref()),
+            key,
+            self.cipher_suite,
+            name.as_ref(),
+            username.as_ref(),
+            password.as_ref(),
+            notes.as_ref(),
+        )?;
+        // Verify that the credential will be unique.
+        if self.load_credential(&owner_username, &name, key).is_ok() {
+            return
+                Err(
+                    eyre!(
+                        "Failed to create new credential: A credential named \"{}\" owned by account \"{}\" already exists.",
+                        name.as_ref(),
+                        owner_username.as_ref()
+                        )
+                    );
+        }
+
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Attempt to add a credential to the database.
+        Database::transaction_insert(credential, &tx)?;
+        // Commit the database transaction.
+        Ok(tx.commit()?)
+    }
+
+    /// Move the [Credential] with the given `owner_username` & `name` to its owner's trash, where
+    /// it stays encrypted until it's restored or purged. If the [Credential] is shared, it stops
+    /// being shared. Its name is free to be used by another [Credential] in the meantime.
+    pub fn delete_credential<O, N>(
+        &mut self,
+        owner_username: O,
+        name: N,
+        key: &Aes256Key,
+    ) -> eyre::Result<()>
+    where
+        O: AsRef<str>,
+        N: AsRef<str>,
+    {
+        let credential = self.load_credential(&owner_username, &name, key)?;
+        // Stop sharing it.
+        if let Some(shared_credential) = self.select_shared_credential(&credential)? {
+            self.remove_shared_copy(shared_credential.id(), EntityType::Credential)?;
+        }
+        let trashed_item = TrashedItem::from_fields(
+            credential.owner_username().to_owned(),
+            EntityType::Credential,
+            credential.id().to_owned(),
+            (self.clock)(),
+        );
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Free up its name.
+        let num_rows = Database::transaction_update::<Credential, Value, &[u8], 2, 1>(
+            credential_primary_key(
+                credential.owner_username(),
+                credential.encrypted_name().cipherbytes(),
+            ),
+            CredentialUpdateField::NameIndex,
+            [&[]],
+            &tx,
+        )?;
+        Self::validate_one_row(num_rows)?;
+        Database::transaction_insert(trashed_item, &tx)?;
+        // Commit the database transaction.
+        Ok(tx.commit()?)
+    }
+
+    // Helper function: Delete the given [Credential], its shared copy & its previous versions from
+    // the [Database].
+    fn remove_credential(&mut self, credential: &Credential) -> eyre::Result<()> {
+        // Find its shared copy, if it's shared.
+        let shared_credential = self.select_shared_credential(credential)?;
+        let share_grants = match &shared_credential {
+            Some(shared_credential) => self.database.select_share_grants(shared_credential.id())?,
+            None => Vec::new(),
+        };
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Stop sharing the credential.
+        if let Some(shared_credential) = shared_credential {
+            Self::transaction_delete_share_grants(&share_grants, &tx)?;
+            Database::transaction_delete::<SharedCredential, &str, 1>(
+                [shared_credential.id()],
+                &tx,
+            )?;
+        }
+        // Delete its previous versions.
+        Database::transaction_delete_credential_history(
+            credential.owner_username(),
+            credential.id(),
+            &tx,
+        )?;
+        // Take it out of the trash, if it's there.
+        Database::transaction_delete_trashed_item(
+            credential.owner_username(),
+            EntityType::Credential,
+            credential.id(),
+            &tx,
+        )?;
+        // Delete the credential.
+        Database::transaction_delete::<Credential, Value, 2>(
+            credential_primary_key(
+                credential.owner_username(),
+                credential.encrypted_name().cipherbytes(),
+            ),
+            &tx,
+        )?;
+        // Commit the database transaction.
+        Ok(tx.commit()?)
+    }
+
+    /// Load the [Credential] with the given `owner_username` & `name`.
+    pub fn load_credential<O, N>(
+        &self,
+        owner_username: O,
+        name: N,
+        key: &Aes256Key,
+    ) -> eyre::Result<Credential>
+    where
+        O: AsRef<str>,
+        N: AsRef<str>,
+    {
+        let account_name = self.account_name(owner_username.as_ref());
+        // Look the credential up by the blind index of its name.
+        let name_index = Credential::compute_name_index(key, name.as_ref())?;
+        if let Some(credential) = self
+            .database
+            .select_entry_by_name_index::<Credential, Value, 2>([
+                account_name.as_str().into_sql(),
+                name_index.into_sql(),
+            ])?
+        {
+            return Ok(credential);
+        }
+        // Otherwise, find the unindexed credential that matches the given name. Trashed credentials
+        // are unindexed too, but are left out.
+        let trashed_ids = self.select_trashed_ids(&account_name, EntityType::Credential)?;
+        for credential in self
+            .select_account_credentials(&account_name)?
+            .into_iter()
+            .filter(|credential| {
+                credential.name_index().is_none() && !trashed_ids.contains(credential.id())
+            })
+        {
+            if credential.name::<String>(key)? == name.as_ref() {
+                // Match found.
+                return Ok(credential);
+            }
+        }
+        Err(eyre!(
+            "No credentials named \"{}\" are owned by account \"{}\".",
+            name.as_ref(),
+            owner_username.as_ref()
+        ))
+    }
+
+    /// Decrypt the [Credential] with the given `owner_username` & `name`, writing it to the
+    /// `writer` as an age payload encrypted to the `recipient`.
+    ///
+    /// The payload is plain text with one line each for the credential's name, username, password
+    /// & notes.
+    pub fn export_credential_age<O, N, W>(
+        &self,
+        owner_username: O,
+        name: N,
+        key: &Aes256Key,
+        recipient: &AgeRecipient,
+        writer: W,
+    ) -> eyre::Result<()>
+    where
+        O: AsRef<str>,
+        N: AsRef<str>,
+        W: Write,
+    {
+        let credential = self.load_credential(owner_username, &name, key)?;
+        let username = credential.username::<Secret<String>>(key)?;
+        let password = credential.password::<Secret<String>>(key)?;
+        let notes = credential.notes::<Secret<String>>(key)?;
+        let text = Zeroizing::new(format!(
+            "Credential \"{}\"\nUsername: {}\nPassword: {}\nNotes: {}\n",
+            name.as_ref(),
+            username.expose().trim_end_matches('\n'),
+            password.expose().trim_end_matches('\n'),
+            notes.expose().trim_end_matches('\n'),
+        ));
+
+        let mut payload = encrypt_to(recipient, writer)?;
+        payload.write_all(text.as_bytes())?;
+        payload.finish()?.flush()?;
+        Ok(())
+    }
+
+    /// Load all [Credential] belonging to the given `owner_username`, except those in the trash.
+    pub fn load_account_credentials<S>(&self, owner_username: S) -> eyre::Result<Vec<Credential>>
+    where
+        S: AsRef<str>,
+    {
+        let account_name = self.account_name(owner_username.as_ref());
+        let trashed_ids = self.select_trashed_ids(&account_name, EntityType::Credential)?;
+        let mut credentials = self.select_account_credentials(&account_name)?;
+        credentials.retain(|credential| !trashed_ids.contains(credential.id()));
+        Ok(credentials)
+    }
+
+    // Helper function: Select all [Credential]s belonging to the account stored under the given
+    // `account_name`.
+    fn select_account_credentials(&self, account_name: &str) -> eyre::Result<Vec<Credential>> {
+        self.database.select_owned_entries([account_name])
+    }
+
+    // Helper function: Return the given [Credential] without its `name_index`.
+    fn without_name_index(credential: Credential) -> Credential {
+        Credential::from_fields(
+            credential.id().to_owned(),
+            credential.owner_username().to_owned(),
+            credential.encrypted_name().clone(),
+            credential.encrypted_username().clone(),
+            credential.encrypted_password().clone(),
+            credential.encrypted_notes().clone(),
+            None,
+        )
+    }
+
+    /// Update several of a [Credential]'s fields at once. If the [Credential] is shared, its shared
+    /// copy is updated too.
+    ///
+    /// If any value changes, the [Credential]'s previous username, password & notes are kept as a
+    /// [CredentialVersion], up to the [Vault]'s credential history limit.
+    ///
+    /// The [CredentialField::Name] of a [Credential] can't be updated.
+    pub fn update_credential_fields<O, N>(
+        &mut self,
+        owner_username: O,
+        name: N,
+        key: &Aes256Key,
+        changes: &[(CredentialField, &str)],
+    ) -> eyre::Result<()>
+    where
+        O: AsRef<str>,
+        N: AsRef<str>,
+    {
+        if changes
+            .iter()
+            .any(|(field, _)| *field == CredentialField::Name)
+        {
+            return Err(eyre!("The name of a credential can't be updated."));
+        }
+        // Load the credential.
+        let credential = self.load_credential(owner_username.as_ref(), name.as_ref(), key)?;
+        // Get the primary key of the credential.
+        let primary_key = credential_primary_key(
+            credential.owner_username(),
+            credential.encrypted_name().cipherbytes(),
+        );
+        // Keep the current values as a previous version, if any of them change.
+        let mut is_changed = false;
+        for (field, new_value) in changes {
+            is_changed |= credential
+                .decrypt_field::<Secret<String>>(key, *field)?
+                .expose()
+                != new_value;
+        }
+        let version = if is_changed && self.credential_history_limit > 0 {
+            Some(CredentialVersion::try_new(
+                &credential,
+                key,
+                self.cipher_suite,
+                (self.clock)(),
+            )?)
+        } else {
+            None
+        };
+        // Encrypt the new values.
+        let encrypted_new_values = changes
+            .iter()
+            .map(|(field, new_value)| {
+                Ok((
+                    *field,
+                    credential.encrypt_field(key, self.cipher_suite, *field, new_value)?,
+                ))
+            })
+            .collect::<eyre::Result<Vec<_>>>()?;
+        // Encrypt the new values for the shared copy too, if the credential is shared.
+        let mut updated_shared_credential = self.select_shared_credential(&credential)?;
+        for (field, new_value) in changes {
+            updated_shared_credential = updated_shared_credential
+                .map(|shared_credential| {
+                    shared_credential.with_field(key, self.cipher_suite, *field, new_value)
+                })
+                .transpose()?;
+        }
+        // Open a new transaction.
+        let tx = self.database.open_transaction()?;
+        // Keep the previous version.
+        if let Some(version) = version {
+            Database::transaction_insert(version, &tx)?;
+            Database::transaction_prune_credential_history(self.credential_history_limit, &tx)?;
+        }
+        for (field, encrypted_new_value) in encrypted_new_values {
+            Self::transaction_update_credential_field(
+                primary_key.clone(),
+                field,
+                &encrypted_new_value,
+                &tx,
+            )?;
+        }
+        // Replace the shared copy.
+        if let Some(updated_shared_credential) = updated_shared_credential {
+            Database::transaction_delete::<SharedCredential, &str, 1>(
+                [updated_shared_credential.id()],
+                &tx,
+            )?;
+            Database::transaction_insert(updated_shared_credential, &tx)?;
+        }
+        // Commit the transaction.
+        Ok(tx.commit()?)
+    }
+
+    // Helper function: Update one of the [Encrypted] fields of the [Credential] with the given
+    // primary key using the current [Transaction].
+    fn transaction_update_credential_field(
+        primary_key: [Value; 2],
+        field: CredentialField,
+        encrypted_new_value: &Encrypted,
+        tx: &Transaction,
+    ) -> eyre::Result<()> {
+        // Get the database fields of the credential field.
+        let (cipherbytes_field, nonce_field, cipher_field) = match field {
+            CredentialField::Name => {
+                return Err(eyre!("The name of a credential can't be updated."))
+            }
+            CredentialField::Username => (
+                CredentialUpdateField::UsernameCipherbytes,
+                CredentialUpdateField::UsernameNonce,
+                CredentialUpdateField::UsernameCipher,
+            ),
+            CredentialField::Password => (
+                CredentialUpdateField::PasswordCipherbytes,
+                CredentialUpdateField::PasswordNonce,
+                CredentialUpdateField::PasswordCipher,
+            ),
+            CredentialField::Notes => (
+                CredentialUpdateField::NotesCipherbytes,
+                CredentialUpdateField::NotesNonce,
+                CredentialUpdateField::NotesCipher,
+            ),
+        };
+        // Update the field's cipherbytes.
+        let num_rows = Database::transaction_update::<Credential, Value, &[u8], 2, 1>(
+            primary_key.clone(),
+            cipherbytes_field,
+            [encrypted_new_value.cipherbytes()],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)?;
+        // Update the field's nonce.
+        let num_rows = Database::transaction_update::<Credential, Value, Nonce, 2, 1>(
+            primary_key.clone(),
+            nonce_field,
+            [encrypted_new_value.nonce()],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)?;
+        // Update the field's cipher suite.
+        let num_rows = Database::transaction_update::<Credential, Value, CipherSuite, 2, 1>(
+            primary_key,
+            cipher_field,
+            [encrypted_new_value.cipher_suite()],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)
+    }
+
+    // FILE FUNCTIONALITY
+
+    /// Create a new file, along with its corresponding [FileData], & add it to the [Database].
+    pub fn create_file<F, O, B>(
+        &mut self,
+        filename: F,
+        owner_username: O,
+        contents: B,
+        key: &Aes256Key,
+    ) -> eyre::Result<()>
+    where
+        F: AsRef<str>,
+        O: AsRef<str>,
+        B: AsRef<[u8]>,
+    {
+        self.import_file(filename, owner_username, contents.as_ref(), key)
+    }
+
+    /// Create a new file with the contents read from the `reader`, along with its corresponding
+    /// [FileData], & add it to the [Database].
+    ///
+    /// The contents are encrypted in chunks as they are read, so they are never all held in
+    /// memory at once. They are written to the file's pending sibling, which only becomes the file
+    /// once it's complete, so an interrupted import never leaves a partial file behind.
+    pub fn import_file<F, O, R>(
+        &mut self,
+        filename: F,
+        owner_username: O,
+        reader: R,
+        key: &Aes256Key,
+    ) -> eyre::Result<()>
+    where
+        F: AsRef<str>,
+        O: AsRef<str>,
+        R: Read,
+    {
+        // Verify that the file will be unique.
+        if self.load_file_data(&owner_username, &filename, key).is_ok() {
+            return Err(eyre!(
+                "Failed to create new file: A file named \"{}\" owned by account \"{}\" already exists.",
+                filename.as_ref(),
+                owner_username.as_ref()
+            ));
+        }
+
+        // Create the file data. The file is named after its id.
+        let account_name = self.account_name(owner_username.as_ref());
+        let file_data = FileData::try_new(
+            self.file_store.account_dir(&account_name)?,
+            &account_name,
+            key,
+            self.cipher_suite,
+            filename.as_ref(),
+            self.cipher_suite.new_rand_nonce(),
+            DEFAULT_CHUNK_SIZE,
+        )?;
+        let file_path = file_data.path().to_owned();
+        let nonce = file_data.contents_nonce();
+        let aad = file_data.contents_associated_data();
+
+        let pending_path = get_pending_file_path(&file_path, file_data.id());
+
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Attempt to add the file data to the database.
+        Database::transaction_insert(file_data, &tx)?;
+        // Attempt to encrypt the contents to the pending sibling.
+        Self::write_contents(
+            &self.file_store,
+            &tx,
+            reader,
+            &pending_path,
+            key,
+            nonce,
+            &aad,
+        )?;
+        // Move the pending sibling into place & commit the transaction, deleting the file on a
+        // failure.
+        let commit_result = self
+            .file_store
+            .replace(&tx, &pending_path, &file_path)
+            .and_then(|_| Ok(tx.commit()?));
+        if let Err(err) = commit_result {
+            let conn = self.database.connection();
+            let _ = self.file_store.remove_if_exists(conn, &pending_path);
+            let _ = self.file_store.remove_if_exists(conn, &file_path);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Create a new file with the contents of the age payload read from the `reader`, decrypted
+    /// with the `identity`.
+    ///
+    /// The payload is decrypted straight into the new file, so its plaintext never touches the
+    /// disk.
+    pub fn import_file_age<F, O, R>(
+        &mut self,
+        filename: F,
+        owner_username: O,
+        identity: &AgeIdentity,
+        reader: R,
+        key: &Aes256Key,
+    ) -> eyre::Result<()>
+    where
+        F: AsRef<str>,
+        O: AsRef<str>,
+        R: Read,
+    {
+        let payload = decrypt_from(identity, reader)?;
+        self.import_file(filename, owner_username, payload, key)
+    }
+
+    /// Move the file with the given `owner_username` & `filename` to its owner's trash, where it
+    /// stays encrypted until it's restored or purged. If the file is shared, it stops being shared.
+    pub fn delete_file<O, F>(
+        &mut self,
+        owner_username: O,
+        filename: F,
+        key: &Aes256Key,
+    ) -> eyre::Result<()>
+    where
+        O: AsRef<str>,
+        F: AsRef<str>,
+    {
+        let file_data = self.load_file_data(&owner_username, &filename, key)?;
+        // Stop sharing it.
+        if let Some(shared_file) = self.select_shared_file(&file_data)? {
+            self.remove_shared_copy(shared_file.id(), EntityType::File)?;
+        }
+        let trashed_item = TrashedItem::from_fields(
+            file_data.owner_username().to_owned(),
+            EntityType::File,
+            file_data.id().to_owned(),
+            (self.clock)(),
+        );
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Free up its name.
+        Self::transaction_update_file_name_index(file_data.path(), &[], &tx)?;
+        Database::transaction_insert(trashed_item, &tx)?;
+        // Commit the database transaction.
+        Ok(tx.commit()?)
+    }
+
+    // Helper function: Delete the file with the given [FileData], its shared copy, its revisions,
+    // & their entries in the [Database]. Files which are already missing are skipped if
+    // `missing_ok`.
+    fn remove_file(&mut self, file_data: &FileData, missing_ok: bool) -> eyre::Result<()> {
+        // Find its revisions.
+        let revisions = self
+            .database
+            .select_file_revisions(file_data.owner_username(), file_data.id())?;
+        // Find its shared copy, if it's shared.
+        let shared_file = self.select_shared_file(file_data)?;
+        let share_grants = match &shared_file {
+            Some(shared_file) => self.database.select_share_grants(shared_file.id())?,
+            None => Vec::new(),
+        };
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Stop sharing the file & delete its shared copy.
+        if let Some(shared_file) = shared_file {
+            Self::transaction_delete_share_grants(&share_grants, &tx)?;
+            Database::transaction_delete::<SharedFile, &str, 1>([shared_file.id()], &tx)?;
+            if missing_ok {
+                self.file_store
+                    .remove_if_exists(&tx, shared_file.file_data().path())?;
+            } else {
+                self.file_store
+                    .remove(&tx, shared_file.file_data().path())?;
+            }
+        }
+        // Delete its revisions. Missing revisions are reported & deleted by repairs too.
+        for revision in &revisions {
+            Database::transaction_delete::<FileRevision, &str, 1>([revision.id()], &tx)?;
+            self.file_store.remove_if_exists(&tx, revision.path())?;
+        }
+        // Take it out of the trash, if it's there.
+        Database::transaction_delete_trashed_item(
+            file_data.owner_username(),
+            EntityType::File,
+            file_data.id(),
+            &tx,
+        )?;
+        // Delete the file data entry.
+        Database::transaction_delete::<FileData, &Utf8Path, 1>([file_data.path()], &tx)?;
+        // Delete the file.
+        if missing_ok {
+            self.file_store.remove_if_exists(&tx, file_data.path())?;
+        } else {
+            self.file_store.remove(&tx, file_data.path())?;
+        }
+        // Commit the database transaction.
+        Ok(tx.commit()?)
+    }
+
+    /// Load the file and [FileData] with the given `owner_username` & `filename`.
+    pub fn load_file<U, F, E>(
+        &self,
+        username: U,
+        filename: F,
+        key: &Aes256Key,
+    ) -> eyre::Result<(FileData, E)>
+    where
+        U: AsRef<str>,
+        F: AsRef<str>,
+        E: TryFromEncrypted,
+    {
+        // Load the file data.
+        let file_data = self.load_file_data(&username, &filename, key)?;
+        let file_path = file_data.path();
+        // Decrypt the file contents. They are never longer than the encrypted file.
+        let conn = self.database.connection();
+        let mut decrypted_bytes = Zeroizing::new(Vec::with_capacity(
+            self.file_store.size(conn, file_path)?.try_into()?,
+        ));
+        let aad = file_data.contents_associated_data();
+        let mut contents =
+            Self::open_contents(&self.file_store, conn, &file_data, file_path, key, &aad)?;
+        copy_secret(&mut contents, &mut *decrypted_bytes)?;
+        // Load the file contents.
+        let decrypted_contents = E::try_from_decrypted_bytes(std::mem::take(&mut decrypted_bytes))?;
+
+        Ok((file_data, decrypted_contents))
+    }
+
+    /// Decrypt the file with the given `owner_username` & `filename`, writing its contents to the
+    /// `writer`. Return the number of bytes written.
+    ///
+    /// The contents are decrypted in chunks as they are written, so they are never all held in
+    /// memory at once. If the file turns out to be truncated or tampered with, an error is
+    /// returned, but the contents before the bad chunk have already been written.
+    pub fn export_file<U, F, W>(
+        &self,
+        username: U,
+        filename: F,
+        key: &Aes256Key,
+        mut writer: W,
+    ) -> eyre::Result<u64>
+    where
+        U: AsRef<str>,
+        F: AsRef<str>,
+        W: Write,
+    {
+        // Load the file data.
+        let file_data = self.load_file_data(&username, &filename, key)?;
+        let file_path = file_data.path();
+        // Decrypt the file contents into the writer.
+        let aad = file_data.contents_associated_data();
+        let mut contents = Self::open_contents(
+            &self.file_store,
+            self.database.connection(),
+            &file_data,
+            file_path,
+            key,
+            &aad,
+        )?;
+        let num_bytes = copy_secret(&mut contents, &mut writer)?;
+        writer.flush()?;
+        Ok(num_bytes)
+    }
+
+    /// Decrypt the file with the given `owner_username` & `filename`, writing its contents to the
+    /// `writer` as an age payload encrypted to the `recipient`. Return the size of the contents in
+    /// bytes.
+    pub fn export_file_age<U, F, W>(
+        &self,
+        username: U,
+        filename: F,
+        key: &Aes256Key,
+        recipient: &AgeRecipient,
+        writer: W,
+    ) -> eyre::Result<u64>
+    where
+        U: AsRef<str>,
+        F: AsRef<str>,
+        W: Write,
+    {
+        let mut payload = encrypt_to(recipient, writer)?;
+        let num_bytes = self.export_file(username, filename, key, &mut payload)?;
+        payload.finish()?.flush()?;
+        Ok(num_bytes)
+    }
+
+    /// Ensure that the file with the given `owner_username` & `filename` decrypts successfully,
+    /// without keeping its contents. Return the size of its contents in bytes.
+    pub fn verify_file<U, F>(&self, username: U, filename: F, key: &Aes256Key) -> eyre::Result<u64>
+    where
+        U: AsRef<str>,
+        F: AsRef<str>,
+    {
+        self.export_file(username, filename, key, io::sink())
+    }
+
+    /// Load the [FileData] with the given `owner_username` & `filename`.
+    pub fn load_file_data<O, F>(
+        &self,
+        owner_username: O,
+        filename: F,
+        key: &Aes256Key,
+    ) -> eyre::Result<FileData>
+    where
+        O: AsRef<str>,
+        F: AsRef<str>,
+    {
+        let account_name = self.account_name(owner_username.as_ref());
+        // Look the file up by the blind index of its name.
+        let name_index = FileData::compute_name_index(key, filename.as_ref())?;
+        if let Some(file_data) = self
+            .database
+            .select_entry_by_name_index::<FileData, Value, 2>([
+                account_name.as_str().into_sql(),
+                name_index.into_sql(),
+            ])?
+        {
+            return Ok(file_data);
+        }
+        // Otherwise, find the unindexed file that matches the given name. Trashed files are
+        // unindexed too, but are left out.
+        let trashed_ids = self.select_trashed_ids(&account_name, EntityType::File)?;
+        for file_data in self
+            .select_account_files_data(&account_name)?
+            .into_iter()
+            .filter(|file_data| {
+                file_data.name_index().is_none() && !trashed_ids.contains(file_data.id())
+            })
+        {
+            if file_data.filename::<String>(key)? == filename.as_ref() {
+                // Match found.
+                return Ok(file_data);
+            }
+        }
+        Err(eyre!(
+            "No files named \"{}\" are owned by account \"{}\".",
+            filename.as_ref(),
+            owner_username.as_ref()
+        ))
+    }
+
+    /// Load all [FileData] belonging to the given `owner_username`, except those in the trash.
+    pub fn load_account_files_data<S>(&self, owner_username: S) -> eyre::Result<Vec<FileData>>
+    where
+        S: AsRef<str>,
+    {
+        let account_name = self.account_name(owner_username.as_ref());
+        let trashed_ids = self.select_trashed_ids(&account_name, EntityType::File)?;
+        let mut files_data = self.select_account_files_data(&account_name)?;
+        files_data.retain(|file_data| !trashed_ids.contains(file_data.id()));
+        Ok(files_data)
+    }
+
+    // Helper function: Select all [FileData] belonging to the account stored under the given
+    // `account_name`.
+    fn select_account_files_data(&self, account_name: &str) -> eyre::Result<Vec<FileData>> {
+        self.database.select_owned_entries([account_name])
+    }
+
+    /// Update a file's content.
+    pub fn update_file<U, F, B>(
+        &mut self,
+        username: U,
+        filename: F,
+        key: &Aes256Key,
+        new_file_contents: B,
+    ) -> eyre::Result<()>
+    where
+        U: AsRef<str>,
+        F: AsRef<str>,
+        B: AsRef<[u8]>,
+    {
+        self.update_file_from(username, filename, key, new_file_contents.as_ref())
+    }
+
+    /// Replace a file's content with the contents read from the `reader`. If the file is shared,
+    /// its shared copy is updated too.
+    ///
+    /// The new contents are encrypted in chunks to the file's pending sibling as they are read.
+    /// The pending sibling replaces the file before the new [FileData] is committed to the
+    /// [Database], & the replaced contents are moved back if that fails. If this is interrupted,
+    /// the update is either finished or undone the next time the account is loaded.
+    ///
+    /// The replaced contents are kept as a [FileRevision], up to the [Vault]'s file revision
+    /// limit & maximum age.
+    pub fn update_file_from<U, F, R>(
+        &mut self,
+        username: U,
+        filename: F,
+        key: &Aes256Key,
+        reader: R,
+    ) -> eyre::Result<()>
+    where
+        U: AsRef<str>,
+        F: AsRef<str>,
+        R: Read,
+    {
+        // Load the file data.
+        let file_data = self.load_file_data(&username, &filename, key)?;
+        let file_path = file_data.path();
+        // Encrypt the new file contents to the pending sibling.
+        let pending_path = get_pending_file_path(file_path, file_data.id());
+        let nonce = self.cipher_suite.new_rand_nonce();
+        Self::write_contents(
+            &self.file_store,
+            self.database.connection(),
+            reader,
+            &pending_path,
+            key,
+            nonce,
+            &file_data.contents_associated_data(),
+        )?;
+        // Keep the replaced contents as a revision, unless no revisions are kept.
+        let revision = (self.file_revision_limit > 0).then(|| {
+            FileRevision::new(&file_data, (self.clock)(), |revision_id| {
+                get_revision_file_path(file_path, revision_id)
+            })
+        });
+        // Move the replaced contents to the replaced sibling & the pending sibling into place,
+        // then commit the new file data contents header & the revision, pruning old revisions.
+        // Each rename is synced to the disk first, so the header is never committed without its
+        // contents.
+        let replaced_path = get_replaced_file_path(file_path, file_data.id());
+        let update_result = self.database.open_transaction().and_then(|tx| {
+            self.file_store.replace(&tx, file_path, &replaced_path)?;
+            self.file_store.replace(&tx, &pending_path, file_path)?;
+            Self::transaction_update_contents_header(file_path, nonce, DEFAULT_CHUNK_SIZE, &tx)?;
+            if let Some(revision) = &revision {
+                Database::transaction_insert(revision.clone(), &tx)?;
+            }
+            let pruned = Self::transaction_prune_file_revisions(
+                self.file_revision_limit,
+                self.file_revision_max_age,
+                (self.clock)(),
+                &tx,
+            )?;
+            tx.commit()?;
+            Ok(pruned)
+        });
+        let conn = self.database.connection();
+        let pruned = match update_result {
+            Ok(pruned) => pruned,
+            Err(err) => {
+                // Move the replaced contents back. In the database layout, the renames were already
+                // rolled back with the transaction.
+                let _ = self.file_store.replace(conn, &replaced_path, file_path);
+                let _ = self.file_store.remove_if_exists(conn, &pending_path);
+                return Err(err);
+            }
+        };
+        // Move the replaced contents to the revision.
+        match &revision {
+            Some(revision) => self
+                .file_store
+                .replace(conn, &replaced_path, revision.path())?,
+            None => self.file_store.remove(conn, &replaced_path)?,
+        }
+        self.remove_revision_contents(&pruned)?;
+        // Copy the new contents to the shared copy.
+        let Some(shared_file) = self.select_shared_file(&file_data)? else {
+            return Ok(());
+        };
+        let updated_file_data = self
+            .database
+            .select_entry_err_none::<FileData, &Utf8Path, 1>([file_path])?;
+        let item_key = shared_file.item_key(key)?;
+        self.replace_shared_file(&updated_file_data, key, &shared_file, &item_key, &[], &[])
+    }
+
+    // Helper function: Update all the password-derived fields of an [Account] using the given
+    // [UnlockedAccount].
+    fn transaction_update_password(
+        unlocked_account: &UnlockedAccount,
+        tx: &Transaction,
+    ) -> eyre::Result<()> {
+        // Update the password salt.
+        let num_rows = Database::transaction_update::<Account, &str, Salt<64>, 1, 1>(
+            [unlocked_account.username()],
+            AccountUpdateField::PasswordSalt,
+            [*unlocked_account.hashed_password().salt()],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)?;
+        // Update the double-hashed password hash.
+        let num_rows = Database::transaction_update::<Account, &str, Hash<32>, 1, 1>(
+            [unlocked_account.username()],
+            AccountUpdateField::DblHashedPasswordHash,
+            [*unlocked_account.dbl_hashed_password().hash()],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)?;
+        // Update the double-hashed password salt.
+        let num_rows = Database::transaction_update::<Account, &str, Salt<64>, 1, 1>(
+            [unlocked_account.username()],
+            AccountUpdateField::DblHashedPasswordSalt,
+            [*unlocked_account.dbl_hashed_password().salt()],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)?;
+        // Update the encrypted key cipherbytes.
+        let num_rows = Database::transaction_update::<Account, &str, &[u8], 1, 1>(
+            [unlocked_account.username()],
+            AccountUpdateField::EncryptedKeyCipherbytes,
+            [unlocked_account.encrypted_key().cipherbytes()],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)?;
+        // Update the encrypted key nonce.
+        let num_rows = Database::transaction_update::<Account, &str, Nonce, 1, 1>(
+            [unlocked_account.username()],
+            AccountUpdateField::EncryptedKeyNonce,
+            [unlocked_account.encrypted_key().nonce()],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)?;
+        // Update the encrypted key cipher suite.
+        let num_rows = Database::transaction_update::<Account, &str, CipherSuite, 1, 1>(
+            [unlocked_account.username()],
+            AccountUpdateField::EncryptedKeyCipher,
+            [unlocked_account.encrypted_key().cipher_suite()],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)?;
+        let kdf_params = unlocked_account.kdf_params();
+        // Update the KDF algorithm.
+        let num_rows = Database::transaction_update::<Account, &str, &str, 1, 1>(
+            [unlocked_account.username()],
+            AccountUpdateField::KdfAlgorithm,
+            [kdf_params.algorithm().as_str()],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)?;
+        // Update the KDF cost parameters.
+        for (field, value) in [
+            (AccountUpdateField::KdfMemoryKib, kdf_params.memory_kib()),
+            (AccountUpdateField::KdfTimeCost, kdf_params.time_cost()),
+            (AccountUpdateField::KdfParallelism, kdf_params.parallelism()),
+        ] {
+            let num_rows = Database::transaction_update::<Account, &str, u32, 1, 1>(
+                [unlocked_account.username()],
+                field,
+                [value],
+                tx,
+            )?;
+            Self::validate_one_row(num_rows)?;
+        }
+        // Update the encryption version.
+        let num_rows = Database::transaction_update::<Account, &str, u32, 1, 1>(
+            [unlocked_account.username()],
+            AccountUpdateField::EncryptionVersion,
+            [unlocked_account.encryption_version()],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)?;
+        // Update the key file check. Accounts which don't require a key file have none.
+        let key_file_check = unlocked_account.key_file_check();
+        for (field, value) in [
+            (
+                AccountUpdateField::KeyFileSalt,
+                key_file_check.map_or(&[][..], |check| check.salt()),
+            ),
+            (
+                AccountUpdateField::KeyFileHash,
+                key_file_check.map_or(&[][..], |check| check.hash()),
+            ),
+        ] {
+            let num_rows = Database::transaction_update::<Account, &str, &[u8], 1, 1>(
+                [unlocked_account.username()],
+                field,
+                [value],
+                tx,
+            )?;
+            Self::validate_one_row(num_rows)?;
+        }
+        // Update the encrypted TOTP secret. Accounts without two-factor login have none.
+        let values = optional_encrypted_into_db(unlocked_account.encrypted_totp_secret());
+        for (field, value) in [
+            AccountUpdateField::EncryptedTotpSecretCipherbytes,
+            AccountUpdateField::EncryptedTotpSecretNonce,
+            AccountUpdateField::EncryptedTotpSecretCipher,
+        ]
+        .into_iter()
+        .zip(values)
+        {
+            let num_rows = Database::transaction_update::<Account, &str, Value, 1, 1>(
+                [unlocked_account.username()],
+                field,
+                [value],
+                tx,
+            )?;
+            Self::validate_one_row(num_rows)?;
+        }
+        Ok(())
+    }
+
+    // Helper function: Replace the given old [TotpBackup]s of an [UnlockedAccount] with new ones,
+    // returning their [BackupCode]s. Accounts without two-factor login get no new [BackupCode]s.
+    fn transaction_replace_totp_backups(
+        unlocked_account: &UnlockedAccount,
+        old_totp_backups: &[TotpBackup],
+        cipher_suite: CipherSuite,
+        tx: &Transaction,
+    ) -> eyre::Result<Vec<BackupCode>> {
+        for totp_backup in old_totp_backups {
+            Database::transaction_delete::<TotpBackup, &str, 2>(
+                [totp_backup.owner_username(), totp_backup.id()],
+                tx,
+            )?;
+        }
+        let Some(totp_secret) = unlocked_account.totp_secret() else {
+            return Ok(Vec::new());
+        };
+        let mut backup_codes = Vec::with_capacity(NUM_BACKUP_CODES);
+        for _ in 0..NUM_BACKUP_CODES {
+            let backup_code = BackupCode::generate();
+            Database::transaction_insert(
+                TotpBackup::try_new(
+                    unlocked_account.username(),
+                    totp_secret,
+                    &backup_code,
+                    cipher_suite,
+                )?,
+                tx,
+            )?;
+            backup_codes.push(backup_code);
+        }
+        Ok(backup_codes)
+    }
+
+    // Helper function: Update the recovery & key share fields of an [Account] using the given
+    // [UnlockedAccount]. Accounts without a recovery code or key shares have empty fields for them.
+    fn transaction_update_recovery(
+        unlocked_account: &UnlockedAccount,
+        tx: &Transaction,
+    ) -> eyre::Result<()> {
+        let recovery = unlocked_account.recovery();
+        let share_recovery = unlocked_account.share_recovery();
+        for (encrypted, [cipherbytes_field, nonce_field, cipher_field]) in [
+            (
+                recovery.map(|recovery| recovery.encrypted_key()),
+                [
+                    AccountUpdateField::RecoveryEncryptedKeyCipherbytes,
+                    AccountUpdateField::RecoveryEncryptedKeyNonce,
+                    AccountUpdateField::RecoveryEncryptedKeyCipher,
+                ],
+            ),
+            (
+                recovery.map(|recovery| recovery.encrypted_recovery_key()),
+                [
+                    AccountUpdateField::EncryptedRecoveryKeyCipherbytes,
+                    AccountUpdateField::EncryptedRecoveryKeyNonce,
+                    AccountUpdateField::EncryptedRecoveryKeyCipher,
+                ],
+            ),
+            (
+                share_recovery.map(|recovery| recovery.encrypted_key()),
+                [
+                    AccountUpdateField::ShareEncryptedKeyCipherbytes,
+                    AccountUpdateField::ShareEncryptedKeyNonce,
+                    AccountUpdateField::ShareEncryptedKeyCipher,
+                ],
+            ),
+            (
+                share_recovery.map(|recovery| recovery.encrypted_recovery_key()),
+                [
+                    AccountUpdateField::EncryptedShareKeyCipherbytes,
+                    AccountUpdateField::EncryptedShareKeyNonce,
+                    AccountUpdateField::EncryptedShareKeyCipher,
+                ],
+            ),
+        ] {
+            let values = optional_encrypted_into_db(encrypted);
+            for (field, value) in [cipherbytes_field, nonce_field, cipher_field]
+                .into_iter()
+                .zip(values)
+            {
+                let num_rows = Database::transaction_update::<Account, &str, Value, 1, 1>(
+                    [unlocked_account.username()],
+                    field,
+                    [value],
+                    tx,
+                )?;
+                Self::validate_one_row(num_rows)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Helper function: Update the keypair fields of an [Account] using the given [UnlockedAccount].
+    // Accounts without a keypair have empty fields for it.
+    fn transaction_update_keypair(
+        unlocked_account: &UnlockedAccount,
+        tx: &Transaction,
+    ) -> eyre::Result<()> {
+        let keypair = unlocked_account.keypair();
+        let [cipherbytes, nonce, cipher] =
+            optional_encrypted_into_db(keypair.map(|keypair| keypair.encrypted_private_key()));
+        let values = [
+            keypair
+                .map_or(&[][..], |keypair| keypair.public_key())
+                .into_sql(),
+            cipherbytes,
+            nonce,
+            cipher,
+        ];
+        for (field, value) in [
+            AccountUpdateField::PublicKey,
+            AccountUpdateField::EncryptedPrivateKeyCipherbytes,
+            AccountUpdateField::EncryptedPrivateKeyNonce,
+            AccountUpdateField::EncryptedPrivateKeyCipher,
+        ]
+        .into_iter()
+        .zip(values)
+        {
+            let num_rows = Database::transaction_update::<Account, &str, Value, 1, 1>(
+                [unlocked_account.username()],
+                field,
+                [value],
+                tx,
+            )?;
+            Self::validate_one_row(num_rows)?;
+        }
+        Ok(())
+    }
+
+    // Helper function: Delete the given [ShareGrant]s.
+    fn transaction_delete_share_grants(
+        share_grants: &[ShareGrant],
+        tx: &Transaction,
+    ) -> eyre::Result<()> {
+        for share_grant in share_grants {
+            Database::transaction_delete::<ShareGrant, &str, 2>(
+                [share_grant.shared_id(), share_grant.recipient_username()],
+                tx,
+            )?;
+        }
+        Ok(())
+    }
+
+    // Helper function: Update the contents nonce, cipher suite & chunk size of the [FileData] at
+    // the given path.
+    fn transaction_update_contents_header(
+        path: &Utf8Path,
+        nonce: Nonce,
+        chunk_size: u32,
+        tx: &Transaction,
+    ) -> eyre::Result<()> {
+        let num_rows = Database::transaction_update::<FileData, &Utf8Path, Nonce, 1, 1>(
+            [path],
+            FileDataUpdateField::ContentsNonce,
+            [nonce],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)?;
+        let num_rows = Database::transaction_update::<FileData, &Utf8Path, CipherSuite, 1, 1>(
+            [path],
+            FileDataUpdateField::ContentsCipher,
+            [nonce.cipher_suite()],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)?;
+        let num_rows = Database::transaction_update::<FileData, &Utf8Path, u32, 1, 1>(
+            [path],
+            FileDataUpdateField::ContentsChunkSize,
+            [chunk_size],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)
+    }
+
+    // Helper function: Update the contents nonce, cipher suite & chunk size of the [FileRevision]
+    // with the given id.
+    fn transaction_update_revision_contents_header(
+        revision_id: &str,
+        nonce: Nonce,
+        chunk_size: u32,
+        tx: &Transaction,
+    ) -> eyre::Result<()> {
+        let num_rows = Database::transaction_update::<FileRevision, &str, Nonce, 1, 1>(
+            [revision_id],
+            FileRevisionUpdateField::ContentsNonce,
+            [nonce],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)?;
+        let num_rows = Database::transaction_update::<FileRevision, &str, CipherSuite, 1, 1>(
+            [revision_id],
+            FileRevisionUpdateField::ContentsCipher,
+            [nonce.cipher_suite()],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)?;
+        let num_rows = Database::transaction_update::<FileRevision, &str, u32, 1, 1>(
+            [revision_id],
+            FileRevisionUpdateField::ContentsChunkSize,
+            [chunk_size],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)
+    }
+
+    // Helper function: Update the encrypted filename of the [FileData] at the given path.
+    fn transaction_update_filename(
+        path: &Utf8Path,
+        encrypted_filename: &Encrypted,
+        tx: &Transaction,
+    ) -> eyre::Result<()> {
+        let num_rows = Database::transaction_update::<FileData, &Utf8Path, &[u8], 1, 1>(
+            [path],
+            FileDataUpdateField::FilenameCipherbytes,
+            [encrypted_filename.cipherbytes()],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)?;
+        let num_rows = Database::transaction_update::<FileData, &Utf8Path, Nonce, 1, 1>(
+            [path],
+            FileDataUpdateField::FilenameNonce,
+            [encrypted_filename.nonce()],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)?;
+        let num_rows = Database::transaction_update::<FileData, &Utf8Path, CipherSuite, 1, 1>(
+            [path],
+            FileDataUpdateField::FilenameCipher,
+            [encrypted_filename.cipher_suite()],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)
+    }
+
+    // Helper function: Update the filename blind index of the [FileData] at the given path. An
+    // empty `name_index` leaves the file unindexed.
+    fn transaction_update_file_name_index(
+        path: &Utf8Path,
+        name_index: &[u8],
+        tx: &Transaction,
+    ) -> eyre::Result<()> {
+        let num_rows = Database::transaction_update::<FileData, &Utf8Path, &[u8], 1, 1>(
+            [path],
+            FileDataUpdateField::NameIndex,
+            [name_index],
+            tx,
+        )?;
+        Self::validate_one_row(num_rows)
+    }
+
+    // Helper function: Ensure that exactly one row was updated.
+    fn validate_one_row(num_rows: usize) -> eyre::Result<()> {
+        match num_rows {
+            1 => Ok(()),
+            num => Err(eyre!(
+                "Tried to update 1 row; {num} matches found. No changes to database were made."
+            )),
+        }
+    }
+
+    // GENERAL
+
+    /// Load all of a given entity.
+    pub fn load_all<T>(&self) -> eyre::Result<Vec<T>>
+    where
+        T: TryFromDatabase + HasSqlStatements,
+    {
         self.database.select_all_entries::<T>()
     }
-}
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::{assert_eq, assert_ne};
+    use std::fs;
+
+    use super::{
+        super::encryption::encrypted::TryIntoEncrypted,
+        filesystem::{get_file_path, new_account_file_dir},
+        *,
+    };
+
+    const TEST_DIR_STR: &str = "tests/test_vault_dir";
+
+    pub(super) fn test_dir() -> Utf8PathBuf {
+        Utf8PathBuf::from(TEST_DIR_STR)
+    }
+
+    pub(super) fn db_path(db_name: &str) -> Utf8PathBuf {
+        let mut db_path = test_dir();
+        db_path.push(db_name);
+        db_path
+    }
+
+    pub(super) fn fs_dir(fs_name: &str) -> Utf8PathBuf {
+        let mut fs_dir = test_dir();
+        fs_dir.push(fs_name);
+        fs_dir
+    }
+
+    pub(super) fn refresh_test_db(db_name: &str) {
+        fs::create_dir_all(test_dir()).unwrap();
+        let _ = fs::remove_file(db_path(db_name));
+        fs::File::create_new(db_path(db_name)).unwrap();
+    }
+
+    pub(super) fn refresh_test_fs(fs_name: &str) {
+        let _ = fs::remove_dir_all(fs_dir(fs_name));
+        fs::create_dir(fs_dir(fs_name)).unwrap();
+    }
+
+    #[test]
+    fn connect_ok() {
+        let db_name = "connect_ok.db";
+        let fs_name = "connect_ok";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+        Vault::connect(db_path, fs_dir).unwrap();
+    }
+
+    #[test]
+    fn connect_non_dir() {
+        let db_name = "connect_non_dir.db";
+        let fs_name = "connect_non_dir";
+        let db_path = db_path(db_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+        let _ = Vault::connect(db_path, "src/backend/vault.rs".into()).unwrap_err();
+    }
+
+    #[test]
+    fn create_del_accs() {
+        let db_name = "create_del_accs.db";
+        let fs_name = "create_del_accs";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+
+        let username1 = "mr_test";
+        let password1 = "open sesame!";
+        vault
+            .create_new_account(username1, password1, None)
+            .unwrap();
+        let _ = vault
+            .create_new_account(username1, password1, None)
+            .unwrap_err();
+
+        let username2 = "mr_awesome";
+        let password2 = "let me in!!!!!!";
+        vault
+            .create_new_account(username2, password2, None)
+            .unwrap();
+
+        let _ = vault
+            .create_new_account(username1, password2, None)
+            .unwrap_err();
+
+        let loaded_acc1 = vault.load_account(username1).unwrap();
+        assert_eq!(loaded_acc1.username(), username1);
+
+        let unlocked_acc1 = vault
+            .load_unlocked_account(username1, password1, None, None)
+            .unwrap();
+        let _ = vault
+            .load_unlocked_account(username1, "wrong password", None, None)
+            .unwrap_err();
+
+        assert_eq!(unlocked_acc1.password(), password1);
+
+        let mut dir1 = fs_dir.clone();
+        dir1.push(username1);
+
+        let mut dir2 = fs_dir.clone();
+        dir2.push(username2);
+
+        let mut bad_dir = fs_dir.clone();
+        bad_dir.push("mistah_doesnt_exist");
+
+        fs::metadata(&dir1).unwrap();
+        fs::metadata(&dir2).unwrap();
+
+        let _ = vault.delete_account("mistah_doesnt_exist").unwrap_err();
+
+        vault.delete_account(username1).unwrap();
+        let _ = vault.delete_account(username1).unwrap_err();
+        let _ = vault
+            .load_unlocked_account(username1, password1, None, None)
+            .unwrap_err();
+        fs::metadata(&dir1).unwrap();
+        vault
+            .purge_account(username1, password1, None, None)
+            .unwrap();
+
+        let _ = vault.load_account(username1).unwrap_err();
+        vault.load_account(username2).unwrap();
+
+        fs::metadata(&dir1).unwrap_err();
+        fs::metadata(&dir2).unwrap();
+    }
+
+    #[test]
+    fn create_del_creds() {
+        let db_name = "create_del_creds.db";
+        let fs_name = "create_del_creds";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+
+        let username1 = "mr_test";
+        let password1 = "open sesame!";
+        vault
+            .create_new_account(username1, password1, None)
+            .unwrap();
+        let unlocked1 = vault
+            .load_unlocked_account(username1, password1, None, None)
+            .unwrap();
+        assert!(vault
+            .load_account_credentials(username1)
+            .unwrap()
+            .is_empty());
+
+        let username2 = "mr_awesome";
+        let password2 = "let me in!!!!!!";
+        vault
+            .create_new_account(username2, password2, None)
+            .unwrap();
+        let unlocked2 = vault
+            .load_unlocked_account(username2, password2, None, None)
+            .unwrap();
+        assert!(vault
+            .load_account_credentials(username2)
+            .unwrap()
+            .is_empty());
+
+        vault
+            .create_credential(
+                username1,
+                unlocked1.key(),
+                "my bank account",
+                "1234 5678 9012 3456",
+                "letmeinpls",
+                "expiry date: 12/34",
+            )
+            .unwrap();
+
+        assert_eq!(vault.load_account_credentials(username1).unwrap().len(), 1);
+        assert!(vault
+            .load_account_credentials(username2)
+            .unwrap()
+            .is_empty());
+
+        let _ = vault
+            .create_credential(
+                username1,
+                unlocked1.key(),
+                "my bank account",
+                "blahblahblah",
+                "kjsahdkasd",
+                "credential names must be unique!",
+            )
+            .unwrap_err();
+
+        assert_eq!(vault.load_account_credentials(username1).unwrap().len(), 1);
+        assert!(vault
+            .load_account_credentials(username2)
+            .unwrap()
+            .is_empty());
+
+        vault
+            .create_credential(
+                username1,
+                unlocked1.key(),
+                "Sploogle account",
+                "notarealemail@sploogle.blahblahblah",
+                "1234_i_love_sploogle_1234",
+                "",
+            )
+            .unwrap();
+
+        assert_eq!(vault.load_account_credentials(username1).unwrap().len(), 2);
+        assert!(vault
+            .load_account_credentials(username2)
+            .unwrap()
+            .is_empty());
+
+        vault
+            .create_credential(
+                username2,
+                unlocked2.key(),
+                "my bank account",
+                "0987 6543 2109 8765",
+                "UnbreakablePassword!1234",
+                "remember to change your password next Tuesday!",
+            )
+            .unwrap();
+
+        assert_eq!(vault.load_account_credentials(username1).unwrap().len(), 2);
+        assert_eq!(vault.load_account_credentials(username2).unwrap().len(), 1);
+
+        // Wrong name.
+        let _ = vault
+            .delete_credential(username1, "Foogle account", unlocked1.key())
+            .unwrap_err();
+        // Wrong key.
+        let _ = vault
+            .delete_credential(username1, "Sploogle account", unlocked2.key())
+            .unwrap_err();
+        // Wrong account.
+        let _ = vault
+            .delete_credential(username2, "Sploogle account", unlocked1.key())
+            .unwrap_err();
+        vault
+            .delete_credential(username1, "Sploogle account", unlocked1.key())
+            .unwrap();
+
+        let _ = vault
+            .load_credential(username1, "Sploogle account", unlocked1.key())
+            .unwrap_err();
+        vault
+            .load_credential(username1, "my bank account", unlocked1.key())
+            .unwrap();
+        vault
+            .load_credential(username2, "my bank account", unlocked2.key())
+            .unwrap();
+
+        vault.delete_account(username2).unwrap();
+        let _ = vault
+            .load_unlocked_account(username2, password2, None, None)
+            .unwrap_err();
+        vault
+            .purge_account(username2, password2, None, None)
+            .unwrap();
+
+        let _ = vault
+            .load_credential(username2, "my bank account", unlocked2.key())
+            .unwrap_err();
+        vault
+            .load_credential(username1, "my bank account", unlocked1.key())
+            .unwrap();
+    }
+
+    #[test]
+    fn create_del_files() {
+        let db_name = "create_del_files.db";
+        let fs_name = "create_del_files";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+
+        let username1 = "mr_test";
+        let password1 = "open sesame!";
+        vault
+            .create_new_account(username1, password1, None)
+            .unwrap();
+        let unlocked1 = vault
+            .load_unlocked_account(username1, password1, None, None)
+            .unwrap();
+        assert!(vault.load_account_files_data(username1).unwrap().is_empty());
+
+        let username2 = "mr_awesome";
+        let password2 = "let me in!!!!!!";
+        vault
+            .create_new_account(username2, password2, None)
+            .unwrap();
+        let unlocked2 = vault
+            .load_unlocked_account(username2, password2, None, None)
+            .unwrap();
+        assert!(vault.load_account_files_data(username2).unwrap().is_empty());
+
+        // Add some files.
+        vault
+            .create_file(
+                "shopping list",
+                "mr_test",
+                "eggs\nmilk\nbread",
+                unlocked1.key(),
+            )
+            .unwrap();
+        vault
+            .create_file(
+                "my secret",
+                "mr_test",
+                "Sometimes even I, the great Mr. Test, get tired of tests sometimes...",
+                unlocked1.key(),
+            )
+            .unwrap();
+        let _ = vault
+            .create_file("my secret", "mr_test", "No dupes allowed!", unlocked1.key())
+            .unwrap_err();
+
+        vault.create_file(
+            "my secret", 
+            "mr_awesome", 
+            "i wish i wasn't the second account ALL the time...\n\nsometimes a guy just wants to be \"number one\", yennow?",
+            unlocked2.key()
+            ).unwrap();
+        vault
+            .create_file("中文", "mr_awesome", "加拿大很美丽", unlocked2.key())
+            .unwrap();
+        vault
+            .create_file("blah blah blah", "mr_awesome", "", unlocked2.key())
+            .unwrap();
+
+        // Open some files.
+        assert_eq!(vault.load_account_files_data("mr_test").unwrap().len(), 2);
+        assert_eq!(
+            vault.load_account_files_data("mr_awesome").unwrap().len(),
+            3
+        );
+
+        let (test_shop_fd, test_shop_contents): (FileData, String) = vault
+            .load_file("mr_test", "shopping list", unlocked1.key())
+            .unwrap();
+        assert_eq!(
+            test_shop_fd.filename::<String>(unlocked1.key()).unwrap(),
+            "shopping list"
+        );
+        assert_eq!(test_shop_contents, "eggs\nmilk\nbread");
+
+        let (test_secret_fd, test_secret_contents): (FileData, String) = vault
+            .load_file("mr_test", "my secret", unlocked1.key())
+            .unwrap();
+        assert_eq!(
+            test_secret_fd.filename::<String>(unlocked1.key()).unwrap(),
+            "my secret"
+        );
+        assert_eq!(
+            test_secret_contents,
+            "Sometimes even I, the great Mr. Test, get tired of tests sometimes..."
+        );
+
+        let (awesome_secret_fd, awesome_secret_contents): (FileData, String) = vault
+            .load_file("mr_awesome", "my secret", unlocked2.key())
+            .unwrap();
+        assert_eq!(
+            awesome_secret_fd
+                .filename::<String>(unlocked2.key())
+                .unwrap(),
+            "my secret"
+        );
+        assert_eq!(
+            awesome_secret_contents,
+            "i wish i wasn't the second account ALL the time...\n\nsometimes a guy just wants to be \"number one\", yennow?"
+        );
+
+        let (awesome_zhongwen_fd, awesome_zhongwen_contents): (FileData, String) = vault
+            .load_file("mr_awesome", "中文", unlocked2.key())
+            .unwrap();
+        assert_eq!(
+            awesome_zhongwen_fd
+                .filename::<String>(unlocked2.key())
+                .unwrap(),
+            "中文"
+        );
+        assert_eq!(awesome_zhongwen_contents, "加拿大很美丽");
+
+        // Files are named after their ids, not their filenames.
+        let zhongwen_path = awesome_zhongwen_fd.path().to_owned();
+        assert_eq!(
+            zhongwen_path,
+            get_file_path(&fs_dir, "mr_awesome", awesome_zhongwen_fd.id()).unwrap()
+        );
+        assert!(fs::read_dir(fs_dir.join("mr_awesome"))
+            .unwrap()
+            .all(|entry| entry.unwrap().file_name() != "中文"));
+        let _ = vault
+            .load_file_data("mr_awesome", "中文", unlocked1.key())
+            .unwrap_err();
+
+        // Ensure that the file will not be deleted on database error.
+        // Force delete database entry improperly to cause error.
+        vault
+            .database
+            .delete_entry::<FileData, &Utf8Path, 1>([&zhongwen_path])
+            .unwrap();
+        let _ = vault
+            .delete_file("mr_awesome", "中文", unlocked2.key())
+            .unwrap_err();
+        open_file(zhongwen_path).unwrap();
+        assert_eq!(
+            vault.load_account_files_data("mr_awesome").unwrap().len(),
+            2
+        );
+
+        // Ensure that database entry will not be deleted on file error.
+        // Force delete file to ensure file error.
+        let blah_fd = vault
+            .load_file_data("mr_awesome", "blah blah blah", unlocked2.key())
+            .unwrap();
+        fs::remove_file(blah_fd.path()).unwrap();
+        vault
+            .delete_file("mr_awesome", "blah blah blah", unlocked2.key())
+            .unwrap();
+        let _ = vault
+            .purge_trashed_file("mr_awesome", "blah blah blah", unlocked2.key())
+            .unwrap_err();
+        let _ = vault
+            .load_file::<&str, &str, Vec<u8>>("mr_awesome", "blah blah blah", unlocked2.key())
+            .unwrap_err();
+        assert_eq!(
+            vault.load_account_files_data("mr_awesome").unwrap().len(),
+            1
+        );
+        assert_eq!(
+            vault.load_trashed_files_data("mr_awesome").unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn change_password() {
+        let db_name = "change_password.db";
+        let fs_name = "change_password";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+
+        let username = "mr_test";
+        let password = "open sesame!";
+        let new_password = "mr. test is the best!";
+        vault.create_new_account(username, password, None).unwrap();
+        let key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+
+        let filename = "f";
+        let contents = "blah blah blah. this is a test. 我要茶";
+        vault
+            .create_file(filename, username, contents, &key)
+            .unwrap();
+        let (_, fcontents): (_, String) = vault.load_file(username, filename, &key).unwrap();
+        assert_eq!(fcontents, contents);
+
+        vault
+            .change_account_password(username, password, None, None, new_password, None)
+            .unwrap();
+
+        let _ = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap_err();
+        let key = vault
+            .load_unlocked_account(username, new_password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+        let (_, fcontents): (_, String) = vault.load_file(username, filename, &key).unwrap();
+        assert_eq!(fcontents, contents);
+    }
+
+    #[test]
+    fn recover_account() {
+        let db_name = "recover_account.db";
+        let fs_name = "recover_account";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+
+        let username = "mr_forgetful";
+        let password = "open sesame!";
+        let new_password = "open barley!";
+        let recovery_code = vault.create_new_account(username, password, None).unwrap();
+        let key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+        vault
+            .create_credential(username, &key, "c", "u", "p", "n")
+            .unwrap();
+        vault.create_file("f", username, "contents", &key).unwrap();
+
+        // Rotating the key replaces the recovery code.
+        let rotated_recovery_code = vault
+            .rotate_account_key(username, password, None, None)
+            .unwrap()
+            .unwrap();
+
+        let _ = vault
+            .recover_account(username, &RecoveryCode::generate(), new_password, None)
+            .unwrap_err();
+        let _ = vault
+            .recover_account(username, &recovery_code, new_password, None)
+            .unwrap_err();
+        let recovered = vault
+            .recover_account(username, &rotated_recovery_code, new_password, None)
+            .unwrap();
+        let key = recovered.key().clone();
+        let _ = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap_err();
+        assert_eq!(
+            vault
+                .load_unlocked_account(username, new_password, None, None)
+                .unwrap()
+                .key(),
+            &key
+        );
+        let credential = vault.load_credential(username, "c", &key).unwrap();
+        assert_eq!(
+            credential
+                .password::<Secret<String>>(&key)
+                .unwrap()
+                .expose(),
+            "p"
+        );
+        let (_, contents): (_, String) = vault.load_file(username, "f", &key).unwrap();
+        assert_eq!(contents, "contents");
+
+        // Regenerating the recovery code invalidates the old one.
+        let new_recovery_code = vault
+            .regenerate_recovery_code(username, new_password, None, None)
+            .unwrap();
+        let _ = vault
+            .recover_account(username, &recovery_code, password, None)
+            .unwrap_err();
+        vault
+            .recover_account(username, &new_recovery_code, password, None)
+            .unwrap();
+
+        // Revoked recovery codes don't work at all.
+        vault
+            .revoke_recovery_code(username, password, None, None)
+            .unwrap();
+        assert!(vault.load_account(username).unwrap().recovery().is_none());
+        let _ = vault
+            .recover_account(username, &new_recovery_code, new_password, None)
+            .unwrap_err();
+        vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap();
+    }
+
+    #[test]
+    fn key_file_accounts() {
+        let db_name = "key_file_accounts.db";
+        let fs_name = "key_file_accounts";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+
+        let username = "mr_key_file";
+        let password = "open sesame!";
+        let key_file = KeyFile::from_bytes(b"key file contents");
+        let new_key_file = KeyFile::from_bytes(b"new key file contents");
+        vault.set_kdf_params(KdfParams::legacy_pbkdf2());
+        vault
+            .create_new_account(username, password, Some(&key_file))
+            .unwrap();
+        assert!(vault.load_account(username).unwrap().requires_key_file());
+
+        // Both the password and the key file are needed.
+        let _ = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap_err();
+        let _ = vault
+            .load_unlocked_account(username, password, Some(&new_key_file), None)
+            .unwrap_err();
+        let _ = vault
+            .load_unlocked_account(username, "wrong password", Some(&key_file), None)
+            .unwrap_err();
+
+        // Upgrading the password hash keeps the key file.
+        vault.set_kdf_params(KdfParams::default());
+        let key = vault
+            .load_unlocked_account(username, password, Some(&key_file), None)
+            .unwrap()
+            .key()
+            .clone();
+        assert_eq!(
+            vault.load_account(username).unwrap().kdf_params(),
+            KdfParams::default()
+        );
+        let _ = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap_err();
+        vault
+            .create_credential(username, &key, "c", "u", "p", "n")
+            .unwrap();
+
+        // Replace the key file.
+        vault
+            .change_account_password(
+                username,
+                password,
+                Some(&key_file),
+                None,
+                password,
+                Some(&new_key_file),
+            )
+            .unwrap();
+        let _ = vault
+            .load_unlocked_account(username, password, Some(&key_file), None)
+            .unwrap_err();
+        let unlocked = vault
+            .load_unlocked_account(username, password, Some(&new_key_file), None)
+            .unwrap();
+        assert_eq!(unlocked.key(), &key);
+
+        // Stop requiring a key file.
+        vault
+            .change_account_password(
+                username,
+                password,
+                Some(&new_key_file),
+                None,
+                password,
+                None,
+            )
+            .unwrap();
+        assert!(!vault.load_account(username).unwrap().requires_key_file());
+        let key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+        let credential = vault.load_credential(username, "c", &key).unwrap();
+        assert_eq!(
+            credential
+                .password::<Secret<String>>(&key)
+                .unwrap()
+                .expose(),
+            "p"
+        );
+    }
 
-#[cfg(test)]
-mod tests {
-    use pretty_assertions::assert_eq;
-    use std::fs;
+    #[test]
+    fn two_factor() {
+        let db_name = "two_factor.db";
+        let fs_name = "two_factor";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
 
-    use super::*;
+        // The clock's time is the given TOTP time step.
+        fn clock<const STEP: u64>() -> u64 {
+            1_700_000_000 + STEP * 30
+        }
 
-    const TEST_DIR_STR: &str = "tests/test_vault_dir";
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+        vault.set_clock(clock::<0>);
 
-    fn test_dir() -> Utf8PathBuf {
-        Utf8PathBuf::from(TEST_DIR_STR)
+        let username = "mr_cautious";
+        let password = "open sesame!";
+        let new_password = "open barley!";
+        vault.create_new_account(username, password, None).unwrap();
+        let key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+        vault
+            .create_credential(username, &key, "c", "u", "p", "n")
+            .unwrap();
+
+        // Two-factor login is only turned on once the authenticator app has the secret.
+        let totp_secret = TotpSecret::generate();
+        let code_at = |unix_time: u64| -> TwoFactorCode {
+            totp_secret
+                .code_at(unix_time)
+                .unwrap()
+                .expose()
+                .parse()
+                .unwrap()
+        };
+        let code = totp_secret.code_at(clock::<0>()).unwrap();
+        let _ = vault
+            .enable_two_factor(
+                username,
+                password,
+                None,
+                &totp_secret,
+                totp_secret.code_at(clock::<10>()).unwrap().expose(),
+            )
+            .unwrap_err();
+        assert!(!vault.load_account(username).unwrap().requires_two_factor());
+        let backup_codes = vault
+            .enable_two_factor(username, password, None, &totp_secret, code.expose())
+            .unwrap();
+        assert_eq!(backup_codes.len(), NUM_BACKUP_CODES);
+        assert_eq!(
+            vault.count_backup_codes(username).unwrap(),
+            NUM_BACKUP_CODES
+        );
+        assert!(vault.load_account(username).unwrap().requires_two_factor());
+        let err = vault
+            .enable_two_factor(username, password, None, &totp_secret, code.expose())
+            .unwrap_err();
+        assert!(err.to_string().contains("already enabled"));
+
+        // The code which turned two-factor login on can't be used to log in.
+        let err = vault
+            .load_unlocked_account(username, password, None, Some(&code_at(clock::<0>())))
+            .unwrap_err();
+        assert!(err.to_string().contains("already used"));
+
+        // The account can't be unlocked without a valid code.
+        vault.set_clock(clock::<1>);
+        let two_factor_code = code_at(clock::<1>());
+        let err = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap_err();
+        assert!(err.to_string().contains("requires a two-factor code"));
+        let wrong_code: TwoFactorCode =
+            if totp_secret.code_at(clock::<1>()).unwrap().expose() == "000000" {
+                "000001"
+            } else {
+                "000000"
+            }
+            .parse()
+            .unwrap();
+        let err = vault
+            .load_unlocked_account(username, password, None, Some(&wrong_code))
+            .unwrap_err();
+        assert!(err.to_string().contains("Incorrect two-factor code"));
+        let _ = vault
+            .load_unlocked_account(username, "wrong password", None, Some(&two_factor_code))
+            .unwrap_err();
+        let unlocked = vault
+            .load_unlocked_account(username, password, None, Some(&two_factor_code))
+            .unwrap();
+        assert_eq!(unlocked.key(), &key);
+        assert_eq!(unlocked.totp_secret(), Some(&totp_secret));
+
+        // Each code works once, & codes from earlier time steps stop working too.
+        let err = vault
+            .load_unlocked_account(username, password, None, Some(&two_factor_code))
+            .unwrap_err();
+        assert!(err.to_string().contains("already used"));
+        let _ = vault
+            .load_unlocked_account(username, password, None, Some(&code_at(clock::<0>())))
+            .unwrap_err();
+        vault.set_clock(clock::<2>);
+        let _ = vault
+            .load_unlocked_account(username, password, None, Some(&two_factor_code))
+            .unwrap_err();
+        vault
+            .load_unlocked_account(username, password, None, Some(&code_at(clock::<2>())))
+            .unwrap();
+
+        // Old codes stop working.
+        vault.set_clock(clock::<10>);
+        let _ = vault
+            .load_unlocked_account(username, password, None, Some(&code_at(clock::<3>())))
+            .unwrap_err();
+
+        // Each backup code works once.
+        let backup_code = TwoFactorCode::Backup(backup_codes[0].clone());
+        assert_eq!(
+            vault
+                .load_unlocked_account(username, password, None, Some(&backup_code))
+                .unwrap()
+                .key(),
+            &key
+        );
+        assert_eq!(
+            vault.count_backup_codes(username).unwrap(),
+            NUM_BACKUP_CODES - 1
+        );
+        let _ = vault
+            .load_unlocked_account(username, password, None, Some(&backup_code))
+            .unwrap_err();
+        let _ = vault
+            .load_unlocked_account(
+                username,
+                password,
+                None,
+                Some(&TwoFactorCode::Backup(BackupCode::generate())),
+            )
+            .unwrap_err();
+
+        // Changing the password & rotating the key keep two-factor login.
+        vault
+            .change_account_password(
+                username,
+                password,
+                None,
+                Some(&code_at(clock::<10>())),
+                new_password,
+                None,
+            )
+            .unwrap();
+        let _ = vault
+            .load_unlocked_account(username, new_password, None, None)
+            .unwrap_err();
+        vault.set_clock(clock::<11>);
+        let recovery_code = vault
+            .rotate_account_key(username, new_password, None, Some(&code_at(clock::<11>())))
+            .unwrap()
+            .unwrap();
+        vault.set_clock(clock::<12>);
+        let unlocked = vault
+            .load_unlocked_account(username, new_password, None, Some(&code_at(clock::<12>())))
+            .unwrap();
+        let key = unlocked.key().clone();
+        let credential = vault.load_credential(username, "c", &key).unwrap();
+        assert_eq!(credential.password::<String>(&key).unwrap(), "p");
+        assert_eq!(
+            vault
+                .load_unlocked_account(
+                    username,
+                    new_password,
+                    None,
+                    Some(&TwoFactorCode::Backup(backup_codes[1].clone())),
+                )
+                .unwrap()
+                .key(),
+            &key
+        );
+
+        // Regenerating the backup codes invalidates the old ones.
+        vault.set_clock(clock::<13>);
+        let new_backup_codes = vault
+            .regenerate_backup_codes(username, new_password, None, Some(&code_at(clock::<13>())))
+            .unwrap();
+        assert_eq!(
+            vault.count_backup_codes(username).unwrap(),
+            NUM_BACKUP_CODES
+        );
+        let _ = vault
+            .load_unlocked_account(
+                username,
+                new_password,
+                None,
+                Some(&TwoFactorCode::Backup(backup_codes[2].clone())),
+            )
+            .unwrap_err();
+
+        // Turning two-factor login off.
+        vault
+            .disable_two_factor(
+                username,
+                new_password,
+                None,
+                Some(&TwoFactorCode::Backup(new_backup_codes[0].clone())),
+            )
+            .unwrap();
+        assert!(!vault.load_account(username).unwrap().requires_two_factor());
+        assert_eq!(vault.count_backup_codes(username).unwrap(), 0);
+        let err = vault
+            .disable_two_factor(username, new_password, None, None)
+            .unwrap_err();
+        assert!(err.to_string().contains("isn't enabled"));
+        assert_eq!(
+            vault
+                .load_unlocked_account(username, new_password, None, None)
+                .unwrap()
+                .key(),
+            &key
+        );
+
+        // Removing the TOTP secret from the database doesn't unlock the account.
+        let totp_secret = TotpSecret::generate();
+        let code = totp_secret.code_at(clock::<13>()).unwrap();
+        vault
+            .enable_two_factor(username, new_password, None, &totp_secret, code.expose())
+            .unwrap();
+        for (field, value) in [
+            AccountUpdateField::EncryptedTotpSecretCipherbytes,
+            AccountUpdateField::EncryptedTotpSecretNonce,
+            AccountUpdateField::EncryptedTotpSecretCipher,
+        ]
+        .into_iter()
+        .zip(optional_encrypted_into_db(None))
+        {
+            vault
+                .database
+                .update_entry::<Account, &str, Value, 1, 1>([username], field, [value])
+                .unwrap();
+        }
+        assert!(!vault.load_account(username).unwrap().requires_two_factor());
+        let _ = vault
+            .load_unlocked_account(username, new_password, None, None)
+            .unwrap_err();
+
+        // Recovering the account turns two-factor login off.
+        let recovered = vault
+            .recover_account(username, &recovery_code, password, None)
+            .unwrap();
+        assert_eq!(recovered.key(), &key);
+        assert!(recovered.totp_secret().is_none());
+        assert_eq!(vault.count_backup_codes(username).unwrap(), 0);
+        vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap();
     }
 
-    fn db_path(db_name: &str) -> Utf8PathBuf {
-        let mut db_path = test_dir();
-        db_path.push(db_name);
-        db_path
+    #[test]
+    fn key_shares() {
+        let db_name = "key_shares.db";
+        let fs_name = "key_shares";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+
+        let username = "ms_team_lead";
+        let password = "open sesame!";
+        let new_password = "open barley!";
+        vault.create_new_account(username, password, None).unwrap();
+        let key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+        vault
+            .create_credential(username, &key, "c", "u", "p", "n")
+            .unwrap();
+        let err = vault
+            .recover_account_with_shares(username, &[], new_password, None)
+            .unwrap_err();
+        assert!(err.to_string().contains("has no key shares"));
+
+        let shares = vault
+            .split_account_key(username, password, None, None, 3, 5)
+            .unwrap();
+        assert_eq!(shares.len(), 5);
+        assert!(vault
+            .load_account(username)
+            .unwrap()
+            .share_recovery()
+            .is_some());
+
+        // Any 3 of the key shares recover the account.
+        let err = vault
+            .recover_account_with_shares(username, &shares[..2], new_password, None)
+            .unwrap_err();
+        assert!(err.to_string().contains("3 key shares are needed"));
+        let recovered = vault
+            .recover_account_with_shares(
+                username,
+                &[shares[4].clone(), shares[1].clone(), shares[2].clone()],
+                new_password,
+                None,
+            )
+            .unwrap();
+        let key = recovered.key().clone();
+        let _ = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap_err();
+        assert_eq!(
+            vault
+                .load_unlocked_account(username, new_password, None, None)
+                .unwrap()
+                .key(),
+            &key
+        );
+        let credential = vault.load_credential(username, "c", &key).unwrap();
+        assert_eq!(credential.password::<String>(&key).unwrap(), "p");
+
+        // Splitting the key again invalidates the old key shares.
+        let new_shares = vault
+            .split_account_key(username, new_password, None, None, 2, 2)
+            .unwrap();
+        let err = vault
+            .recover_account_with_shares(username, &shares[..3], password, None)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Incorrect key shares.");
+        vault
+            .recover_account_with_shares(username, &new_shares, password, None)
+            .unwrap();
+
+        // Rotating the key revokes the key shares, so they must be split again.
+        vault
+            .rotate_account_key(username, password, None, None)
+            .unwrap();
+        assert!(vault
+            .load_account(username)
+            .unwrap()
+            .share_recovery()
+            .is_none());
+        let err = vault
+            .recover_account_with_shares(username, &new_shares, new_password, None)
+            .unwrap_err();
+        assert!(err.to_string().contains("has no key shares"));
+        let new_shares = vault
+            .split_account_key(username, password, None, None, 2, 2)
+            .unwrap();
+
+        // Revoked key shares don't work at all.
+        vault
+            .revoke_key_shares(username, password, None, None)
+            .unwrap();
+        assert!(vault
+            .load_account(username)
+            .unwrap()
+            .share_recovery()
+            .is_none());
+        let _ = vault
+            .recover_account_with_shares(username, &new_shares, new_password, None)
+            .unwrap_err();
+        vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap();
     }
 
-    fn fs_dir(fs_name: &str) -> Utf8PathBuf {
-        let mut fs_dir = test_dir();
-        fs_dir.push(fs_name);
-        fs_dir
+    #[test]
+    fn upgrade_kdf_on_login() {
+        let db_name = "upgrade_kdf_on_login.db";
+        let fs_name = "upgrade_kdf_on_login";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+
+        // Create an account the way older versions of `dgruft` did.
+        let username = "mr_legacy";
+        let password = "open sesame!";
+        vault.set_kdf_params(KdfParams::legacy_pbkdf2());
+        vault.create_new_account(username, password, None).unwrap();
+        let key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+        assert_eq!(
+            vault.load_account(username).unwrap().kdf_params(),
+            KdfParams::legacy_pbkdf2()
+        );
+
+        // Logging in with the current parameters upgrades the account.
+        vault.set_kdf_params(KdfParams::default());
+        let _ = vault
+            .load_unlocked_account(username, "wrong password", None, None)
+            .unwrap_err();
+        assert_eq!(
+            vault.load_account(username).unwrap().kdf_params(),
+            KdfParams::legacy_pbkdf2()
+        );
+        let unlocked = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap();
+        assert_eq!(unlocked.key(), &key);
+        assert_eq!(
+            vault.load_account(username).unwrap().kdf_params(),
+            KdfParams::default()
+        );
+
+        // The upgraded account still unlocks with the same password and key.
+        let unlocked = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap();
+        assert_eq!(unlocked.key(), &key);
+        assert_eq!(unlocked.kdf_params(), KdfParams::default());
     }
 
-    fn refresh_test_db(db_name: &str) {
-        let _ = fs::remove_file(db_path(db_name));
-        fs::File::create_new(db_path(db_name)).unwrap();
+    #[test]
+    fn tampered_credentials() {
+        let db_name = "tampered_credentials.db";
+        let fs_name = "tampered_credentials";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+
+        let username = "mr_test";
+        let password = "open sesame!";
+        vault.create_new_account(username, password, None).unwrap();
+        let key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+
+        vault
+            .create_credential(username, &key, "c1", "u1", "p1", "n1")
+            .unwrap();
+        vault
+            .create_credential(username, &key, "c2", "u2", "p2", "n2")
+            .unwrap();
+        let c1 = vault.load_credential(username, "c1", &key).unwrap();
+        let c2 = vault.load_credential(username, "c2", &key).unwrap();
+        let c1_primary_key = credential_primary_key(username, c1.encrypted_name().cipherbytes());
+
+        // Swap c1's password into its notes.
+        vault
+            .database
+            .update_entry::<Credential, Value, &[u8], 2, 1>(
+                c1_primary_key.clone(),
+                CredentialUpdateField::NotesCipherbytes,
+                [c1.encrypted_password().cipherbytes()],
+            )
+            .unwrap();
+        vault
+            .database
+            .update_entry::<Credential, Value, Nonce, 2, 1>(
+                c1_primary_key.clone(),
+                CredentialUpdateField::NotesNonce,
+                [c1.encrypted_password().nonce()],
+            )
+            .unwrap();
+        let tampered_c1 = vault.load_credential(username, "c1", &key).unwrap();
+        assert_eq!(tampered_c1.password::<String>(&key).unwrap(), "p1");
+        let _ = tampered_c1.notes::<String>(&key).unwrap_err();
+
+        // Move c2's password into c1.
+        vault
+            .database
+            .update_entry::<Credential, Value, &[u8], 2, 1>(
+                c1_primary_key.clone(),
+                CredentialUpdateField::PasswordCipherbytes,
+                [c2.encrypted_password().cipherbytes()],
+            )
+            .unwrap();
+        vault
+            .database
+            .update_entry::<Credential, Value, Nonce, 2, 1>(
+                c1_primary_key.clone(),
+                CredentialUpdateField::PasswordNonce,
+                [c2.encrypted_password().nonce()],
+            )
+            .unwrap();
+        let tampered_c1 = vault.load_credential(username, "c1", &key).unwrap();
+        let _ = tampered_c1.password::<String>(&key).unwrap_err();
+        assert_eq!(tampered_c1.username::<String>(&key).unwrap(), "u1");
     }
 
-    fn refresh_test_fs(fs_name: &str) {
-        let _ = fs::remove_dir_all(fs_dir(fs_name));
-        fs::create_dir(fs_dir(fs_name)).unwrap();
+    #[test]
+    fn moved_file() {
+        let db_name = "moved_file.db";
+        let fs_name = "moved_file";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+
+        let username = "mr_test";
+        let password = "open sesame!";
+        vault.create_new_account(username, password, None).unwrap();
+        let key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+
+        vault.create_file("a", username, "aaaa", &key).unwrap();
+        vault.create_file("b", username, "bbbb", &key).unwrap();
+        let (a_fd, _): (FileData, String) = vault.load_file(username, "a", &key).unwrap();
+
+        // Move a's ciphertext & nonce onto b.
+        let b_fd = vault.load_file_data(username, "b", &key).unwrap();
+        let b_path = b_fd.path();
+        fs::copy(a_fd.path(), b_path).unwrap();
+        vault
+            .database
+            .update_entry::<FileData, &Utf8Path, Nonce, 1, 1>(
+                [b_path],
+                FileDataUpdateField::ContentsNonce,
+                [a_fd.contents_nonce()],
+            )
+            .unwrap();
+        let _ = vault
+            .load_file::<&str, &str, String>(username, "b", &key)
+            .unwrap_err();
+        let (_, a_contents): (FileData, String) = vault.load_file(username, "a", &key).unwrap();
+        assert_eq!(a_contents, "aaaa");
     }
 
     #[test]
-    fn connect_ok() {
-        let db_name = "connect_ok.db";
-        let fs_name = "connect_ok";
+    fn upgrade_encryption_on_login() {
+        let db_name = "upgrade_encryption_on_login.db";
+        let fs_name = "upgrade_encryption_on_login";
         let db_path = db_path(db_name);
         let fs_dir = fs_dir(fs_name);
         refresh_test_db(db_name);
         refresh_test_fs(fs_name);
-        Vault::connect(db_path, fs_dir).unwrap();
-    }
 
-    #[test]
-    fn connect_non_dir() {
-        let db_name = "connect_non_dir.db";
-        let fs_name = "connect_non_dir";
-        let db_path = db_path(db_name);
-        refresh_test_db(db_name);
-        refresh_test_fs(fs_name);
-        let _ = Vault::connect(db_path, "src/backend/vault.rs".into()).unwrap_err();
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+
+        // Create an account, credential, & file the way older versions of `dgruft` did.
+        let username = "mr_legacy";
+        let password = "open sesame!";
+        let account = Account::new_legacy(username, password, KdfParams::default()).unwrap();
+        let key = account.unlock(password, None, None).unwrap().key().clone();
+        vault.database.insert_entry(account).unwrap();
+        new_account_file_dir(&fs_dir, username).unwrap();
+
+        let legacy_credential = Credential::from_fields(
+            "legacy-credential-id".to_owned(),
+            username.to_owned(),
+            "c".try_encrypt_with_key(&key).unwrap(),
+            "u".try_encrypt_with_key(&key).unwrap(),
+            "p".try_encrypt_with_key(&key).unwrap(),
+            "n".try_encrypt_with_key(&key).unwrap(),
+            None,
+        );
+        vault.database.insert_entry(legacy_credential).unwrap();
+
+        let file_path = fs_dir.join(username).join("f");
+        let encrypted_contents = "legacy contents".try_encrypt_with_key(&key).unwrap();
+        let legacy_file_data = FileData::from_fields(
+            "legacy-file-id".to_owned(),
+            file_path.clone(),
+            Filename::Legacy("f".to_owned()),
+            username.to_owned(),
+            encrypted_contents.nonce(),
+            0,
+            None,
+        );
+        vault.database.insert_entry(legacy_file_data).unwrap();
+        fs::write(&file_path, encrypted_contents.cipherbytes()).unwrap();
+
+        // This file was renamed after its id before its filename encryption was interrupted.
+        let encrypted_contents = "interrupted contents".try_encrypt_with_key(&key).unwrap();
+        let interrupted_file_data = FileData::from_fields(
+            "interrupted-file-id".to_owned(),
+            fs_dir.join(username).join("g"),
+            Filename::Legacy("g".to_owned()),
+            username.to_owned(),
+            encrypted_contents.nonce(),
+            0,
+            None,
+        );
+        vault.database.insert_entry(interrupted_file_data).unwrap();
+        fs::write(
+            get_file_path(&fs_dir, username, "interrupted-file-id").unwrap(),
+            encrypted_contents.cipherbytes(),
+        )
+        .unwrap();
+
+        // Legacy data doesn't decrypt with associated data.
+        let _ = vault.load_credential(username, "c", &key).unwrap_err();
+        let _ = vault
+            .load_file::<&str, &str, String>(username, "f", &key)
+            .unwrap_err();
+
+        // Logging in upgrades the account.
+        let _ = vault
+            .load_unlocked_account(username, "wrong password", None, None)
+            .unwrap_err();
+        assert_eq!(
+            vault.load_account(username).unwrap().encryption_version(),
+            0
+        );
+        let unlocked = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap();
+        assert_eq!(unlocked.key(), &key);
+        assert_eq!(
+            vault.load_account(username).unwrap().encryption_version(),
+            Account::ENCRYPTION_VERSION
+        );
+
+        let credential = vault.load_credential(username, "c", &key).unwrap();
+        assert_eq!(credential.id(), "legacy-credential-id");
+        assert!(credential.name_index().is_some());
+        assert_eq!(credential.username::<String>(&key).unwrap(), "u");
+        assert_eq!(credential.password::<String>(&key).unwrap(), "p");
+        assert_eq!(credential.notes::<String>(&key).unwrap(), "n");
+        let (file_data, contents): (FileData, String) =
+            vault.load_file(username, "f", &key).unwrap();
+        assert_eq!(contents, "legacy contents");
+
+        // Logging in also encrypts & indexes the filenames & renames the files after their ids.
+        assert!(matches!(
+            file_data.stored_filename(),
+            Filename::Encrypted(_)
+        ));
+        assert_eq!(
+            file_data.name_index(),
+            Some(&FileData::compute_name_index(&key, "f").unwrap())
+        );
+        assert_eq!(
+            file_data.path(),
+            get_file_path(&fs_dir, username, "legacy-file-id").unwrap()
+        );
+        assert!(!file_path.try_exists().unwrap());
+        let (file_data, contents): (FileData, String) =
+            vault.load_file(username, "g", &key).unwrap();
+        assert_eq!(contents, "interrupted contents");
+        assert_eq!(
+            file_data.path(),
+            get_file_path(&fs_dir, username, "interrupted-file-id").unwrap()
+        );
+
+        // The upgraded account still unlocks with the same password and key.
+        let unlocked = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap();
+        assert_eq!(unlocked.key(), &key);
     }
 
     #[test]
-    fn create_del_accs() {
-        let db_name = "create_del_accs.db";
-        let fs_name = "create_del_accs";
+    fn rotate_key() {
+        let db_name = "rotate_key.db";
+        let fs_name = "rotate_key";
         let db_path = db_path(db_name);
         let fs_dir = fs_dir(fs_name);
         refresh_test_db(db_name);
@@ -558,55 +4392,109 @@ mod tests {
 
         let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
 
-        let username1 = "mr_test";
-        let password1 = "open sesame!";
-        vault.create_new_account(username1, password1).unwrap();
-        let _ = vault.create_new_account(username1, password1).unwrap_err();
-
-        let username2 = "mr_awesome";
-        let password2 = "let me in!!!!!!";
-        vault.create_new_account(username2, password2).unwrap();
-
-        let _ = vault.create_new_account(username1, password2).unwrap_err();
-
-        let loaded_acc1 = vault.load_account(username1).unwrap();
-        assert_eq!(loaded_acc1.username(), username1);
+        let username = "mr_test";
+        let password = "open sesame!";
+        vault.create_new_account(username, password, None).unwrap();
+        let old_key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+        vault
+            .create_credential(username, &old_key, "c", "u", "p", "n")
+            .unwrap();
+        vault
+            .create_file("f1", username, "file 1", &old_key)
+            .unwrap();
+        vault.create_file("f2", username, "", &old_key).unwrap();
 
-        let unlocked_acc1 = vault.load_unlocked_account(username1, password1).unwrap();
         let _ = vault
-            .load_unlocked_account(username1, "wrong password")
+            .rotate_account_key(username, "wrong password", None, None)
             .unwrap_err();
+        vault
+            .rotate_account_key(username, password, None, None)
+            .unwrap();
 
-        assert_eq!(unlocked_acc1.password(), password1);
+        let new_key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+        assert_ne!(new_key, old_key);
 
-        let mut dir1 = fs_dir.clone();
-        dir1.push(username1);
+        let _ = vault.load_credential(username, "c", &old_key).unwrap_err();
+        let credential = vault.load_credential(username, "c", &new_key).unwrap();
+        assert_eq!(credential.username::<String>(&new_key).unwrap(), "u");
+        assert_eq!(credential.password::<String>(&new_key).unwrap(), "p");
+        assert_eq!(credential.notes::<String>(&new_key).unwrap(), "n");
 
-        let mut dir2 = fs_dir.clone();
-        dir2.push(username2);
+        let _ = vault
+            .load_file::<&str, &str, String>(username, "f1", &old_key)
+            .unwrap_err();
+        let (_, f1): (FileData, String) = vault.load_file(username, "f1", &new_key).unwrap();
+        assert_eq!(f1, "file 1");
+        let (_, f2): (FileData, String) = vault.load_file(username, "f2", &new_key).unwrap();
+        assert_eq!(f2, "");
 
-        let mut bad_dir = fs_dir.clone();
-        bad_dir.push("mistah_doesnt_exist");
+        // No pending files are left behind.
+        let account_dir = get_account_file_dir(&fs_dir, username).unwrap();
+        assert_eq!(fs::read_dir(account_dir).unwrap().count(), 2);
+    }
 
-        fs::metadata(&dir1).unwrap();
-        fs::metadata(&dir2).unwrap();
+    #[test]
+    fn interrupted_key_rotation() {
+        let db_name = "interrupted_key_rotation.db";
+        let fs_name = "interrupted_key_rotation";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
 
-        let _ = vault.delete_account("mistah_doesnt_exist").unwrap_err();
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
 
-        vault.delete_account(username1).unwrap();
-        let _ = vault.delete_account(username1).unwrap_err();
+        let username = "mr_test";
+        let password = "open sesame!";
+        vault.create_new_account(username, password, None).unwrap();
+        let mut unlocked = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap();
+        let old_key = unlocked.key().clone();
+        vault
+            .create_file("f", username, "contents", &old_key)
+            .unwrap();
+        let (file_data, _): (FileData, String) = vault.load_file(username, "f", &old_key).unwrap();
+        let pending_path = get_pending_file_path(file_data.path(), file_data.id());
 
-        let _ = vault.load_account(username1).unwrap_err();
-        vault.load_account(username2).unwrap();
+        // Interrupted before the new key was committed: the pending file is discarded.
+        fs::write(&pending_path, "garbage").unwrap();
+        let unlocked_again = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap();
+        assert_eq!(unlocked_again.key(), &old_key);
+        assert!(!pending_path.try_exists().unwrap());
+        let (_, contents): (FileData, String) = vault.load_file(username, "f", &old_key).unwrap();
+        assert_eq!(contents, "contents");
 
-        fs::metadata(&dir1).unwrap_err();
-        fs::metadata(&dir2).unwrap();
+        // Interrupted after the new key was committed: the pending file replaces the original.
+        vault.commit_key_rotation(&mut unlocked).unwrap();
+        let new_key = unlocked.key().clone();
+        assert!(pending_path.try_exists().unwrap());
+        let _ = vault
+            .load_file::<&str, &str, String>(username, "f", &new_key)
+            .unwrap_err();
+        let unlocked_again = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap();
+        assert_eq!(unlocked_again.key(), &new_key);
+        assert!(!pending_path.try_exists().unwrap());
+        let (_, contents): (FileData, String) = vault.load_file(username, "f", &new_key).unwrap();
+        assert_eq!(contents, "contents");
     }
 
     #[test]
-    fn create_del_creds() {
-        let db_name = "create_del_creds.db";
-        let fs_name = "create_del_creds";
+    fn update_credential() {
+        let db_name = "update_credential.db";
+        let fs_name = "update_credential";
         let db_path = db_path(db_name);
         let fs_dir = fs_dir(fs_name);
         refresh_test_db(db_name);
@@ -614,129 +4502,282 @@ mod tests {
 
         let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
 
-        let username1 = "mr_test";
-        let password1 = "open sesame!";
-        vault.create_new_account(username1, password1).unwrap();
-        let unlocked1 = vault.load_unlocked_account(username1, password1).unwrap();
-        assert!(vault
-            .load_account_credentials(username1)
+        let username = "mr_test";
+        let password = "open sesame!";
+        vault.create_new_account(username, password, None).unwrap();
+        let key = vault
+            .load_unlocked_account(username, password, None, None)
             .unwrap()
-            .is_empty());
+            .key()
+            .clone();
 
-        let username2 = "mr_awesome";
-        let password2 = "let me in!!!!!!";
-        vault.create_new_account(username2, password2).unwrap();
-        let unlocked2 = vault.load_unlocked_account(username2, password2).unwrap();
-        assert!(vault
-            .load_account_credentials(username2)
-            .unwrap()
-            .is_empty());
+        let c_name = "c1";
+        let c_username = "my_account";
+        let c_password = "my_password";
+        let c_notes = "my_notes";
 
         vault
-            .create_credential(
-                username1,
-                unlocked1.key(),
-                "my bank account",
-                "1234 5678 9012 3456",
-                "letmeinpls",
-                "expiry date: 12/34",
-            )
+            .create_credential(username, &key, c_name, c_username, c_password, c_notes)
             .unwrap();
+        let loaded_c = vault.load_credential(username, c_name, &key).unwrap();
+        assert_eq!(loaded_c.username::<String>(&key).unwrap(), c_username);
 
-        assert_eq!(vault.load_account_credentials(username1).unwrap().len(), 1);
-        assert!(vault
-            .load_account_credentials(username2)
-            .unwrap()
-            .is_empty());
+        let new_c_username = "my_new_account";
 
-        let _ = vault
-            .create_credential(
-                username1,
-                unlocked1.key(),
-                "my bank account",
-                "blahblahblah",
-                "kjsahdkasd",
-                "credential names must be unique!",
+        vault
+            .update_credential_fields(
+                username,
+                c_name,
+                &key,
+                &[(CredentialField::Username, new_c_username)],
             )
-            .unwrap_err();
+            .unwrap();
+        let loaded_c = vault.load_credential(username, c_name, &key).unwrap();
+        assert_eq!(loaded_c.username::<String>(&key).unwrap(), new_c_username);
 
-        assert_eq!(vault.load_account_credentials(username1).unwrap().len(), 1);
-        assert!(vault
-            .load_account_credentials(username2)
-            .unwrap()
-            .is_empty());
+        let new_c_password = "my_new_password";
 
         vault
-            .create_credential(
-                username1,
-                unlocked1.key(),
-                "Sploogle account",
-                "notarealemail@sploogle.blahblahblah",
-                "1234_i_love_sploogle_1234",
-                "",
+            .update_credential_fields(
+                username,
+                c_name,
+                &key,
+                &[(CredentialField::Password, new_c_password)],
             )
             .unwrap();
+        let loaded_c = vault.load_credential(username, c_name, &key).unwrap();
+        assert_eq!(loaded_c.password::<String>(&key).unwrap(), new_c_password);
 
-        assert_eq!(vault.load_account_credentials(username1).unwrap().len(), 2);
-        assert!(vault
-            .load_account_credentials(username2)
-            .unwrap()
-            .is_empty());
+        let new_c_notes = "my_new_notes";
 
         vault
-            .create_credential(
-                username2,
-                unlocked2.key(),
-                "my bank account",
-                "0987 6543 2109 8765",
-                "UnbreakablePassword!1234",
-                "remember to change your password next Tuesday!",
+            .update_credential_fields(
+                username,
+                c_name,
+                &key,
+                &[(CredentialField::Notes, new_c_notes)],
             )
             .unwrap();
+        let loaded_c = vault.load_credential(username, c_name, &key).unwrap();
+        assert_eq!(loaded_c.notes::<String>(&key).unwrap(), new_c_notes);
 
-        assert_eq!(vault.load_account_credentials(username1).unwrap().len(), 2);
-        assert_eq!(vault.load_account_credentials(username2).unwrap().len(), 1);
-
-        // Wrong name.
-        let _ = vault
-            .delete_credential(username1, "Foogle account", unlocked1.key())
-            .unwrap_err();
-        // Wrong key.
-        let _ = vault
-            .delete_credential(username1, "Sploogle account", unlocked2.key())
-            .unwrap_err();
-        // Wrong account.
         let _ = vault
-            .delete_credential(username2, "Sploogle account", unlocked1.key())
+            .update_credential_fields(username, c_name, &key, &[(CredentialField::Name, "c2")])
             .unwrap_err();
+    }
+
+    #[test]
+    fn credential_name_index() {
+        let db_name = "credential_name_index.db";
+        let fs_name = "credential_name_index";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+
+        let username = "mr_test";
+        let password = "open sesame!";
+        vault.create_new_account(username, password, None).unwrap();
+        let key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+
         vault
-            .delete_credential(username1, "Sploogle account", unlocked1.key())
+            .create_credential(username, &key, "c1", "u1", "p1", "n1")
             .unwrap();
+        let c1 = vault.load_credential(username, "c1", &key).unwrap();
+        assert_eq!(
+            c1.name_index(),
+            Some(&Credential::compute_name_index(&key, "c1").unwrap())
+        );
+
+        // The database won't store two credentials with the same name.
+        let duplicate = Credential::try_new(
+            username,
+            &key,
+            CipherSuite::default(),
+            "c1",
+            "u2",
+            "p2",
+            "n2",
+        )
+        .unwrap();
+        let _ = vault.database.insert_entry(duplicate.clone()).unwrap_err();
 
+        // Credentials created before name indices were added are found by their names.
+        let legacy = Credential::try_new(
+            username,
+            &key,
+            CipherSuite::default(),
+            "c2",
+            "u3",
+            "p3",
+            "n3",
+        )
+        .unwrap();
+        vault
+            .database
+            .insert_entry(Vault::without_name_index(legacy.clone()))
+            .unwrap();
+        vault
+            .database
+            .insert_entry(Vault::without_name_index(duplicate.clone()))
+            .unwrap();
+        let loaded_legacy = vault.load_credential(username, "c2", &key).unwrap();
+        assert_eq!(loaded_legacy.id(), legacy.id());
+        assert_eq!(loaded_legacy.name_index(), None);
         let _ = vault
-            .load_credential(username1, "Sploogle account", unlocked1.key())
+            .create_credential(username, &key, "c2", "u4", "p4", "n4")
             .unwrap_err();
+
+        // Logging in indexes them, except for those whose names are already indexed.
         vault
-            .load_credential(username1, "my bank account", unlocked1.key())
+            .load_unlocked_account(username, password, None, None)
+            .unwrap();
+        let loaded_legacy = vault.load_credential(username, "c2", &key).unwrap();
+        assert_eq!(loaded_legacy.name_index(), legacy.name_index());
+        assert_eq!(vault.load_credential(username, "c1", &key).unwrap(), c1);
+        let credentials = vault.load_account_credentials(username).unwrap();
+        assert_eq!(credentials.len(), 3);
+        assert_eq!(
+            credentials
+                .iter()
+                .filter(|credential| credential.name_index().is_none())
+                .map(|credential| credential.id())
+                .collect::<Vec<&str>>(),
+            vec![duplicate.id()]
+        );
+
+        // Both survive a key rotation.
+        vault
+            .rotate_account_key(username, password, None, None)
             .unwrap();
+        let key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+        assert_eq!(
+            vault.load_credential(username, "c1", &key).unwrap().id(),
+            c1.id()
+        );
+        assert_eq!(
+            vault.load_credential(username, "c2", &key).unwrap().id(),
+            legacy.id()
+        );
+        assert_eq!(vault.load_account_credentials(username).unwrap().len(), 3);
+
+        // Deleting the indexed credential leaves the unindexed one findable.
+        vault.delete_credential(username, "c1", &key).unwrap();
+        assert_eq!(
+            vault.load_credential(username, "c1", &key).unwrap().id(),
+            duplicate.id()
+        );
+    }
+
+    #[test]
+    fn file_name_index() {
+        let db_name = "file_name_index.db";
+        let fs_name = "file_name_index";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+
+        let username = "mr_test";
+        let password = "open sesame!";
+        vault.create_new_account(username, password, None).unwrap();
+        let key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+
+        vault.create_file("f1", username, "1", &key).unwrap();
+        let f1 = vault.load_file_data(username, "f1", &key).unwrap();
+        assert_eq!(
+            f1.name_index(),
+            Some(&FileData::compute_name_index(&key, "f1").unwrap())
+        );
+
+        // The database won't store two files with the same name.
+        let account_file_dir = vault.file_store.account_dir(username).unwrap();
+        let duplicate = FileData::try_new(
+            &account_file_dir,
+            username,
+            &key,
+            CipherSuite::default(),
+            "f1",
+            f1.contents_nonce(),
+            f1.contents_chunk_size(),
+        )
+        .unwrap();
+        let _ = vault.database.insert_entry(duplicate).unwrap_err();
+
+        // Files created before name indices were added are found by their names.
+        vault.create_file("f2", username, "2", &key).unwrap();
+        let legacy = vault.load_file_data(username, "f2", &key).unwrap();
         vault
-            .load_credential(username2, "my bank account", unlocked2.key())
+            .database
+            .update_entry::<FileData, &Utf8Path, &[u8], 1, 1>(
+                [legacy.path()],
+                FileDataUpdateField::NameIndex,
+                [&[]],
+            )
             .unwrap();
+        let loaded_legacy = vault.load_file_data(username, "f2", &key).unwrap();
+        assert_eq!(loaded_legacy.id(), legacy.id());
+        assert_eq!(loaded_legacy.name_index(), None);
+        let _ = vault.create_file("f2", username, "3", &key).unwrap_err();
 
-        vault.delete_account(username2).unwrap();
+        // Logging in indexes them.
+        vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap();
+        assert_eq!(vault.load_file_data(username, "f2", &key).unwrap(), legacy);
 
+        // Trashed files are unindexed, so their names can be taken, & are indexed again once
+        // they're restored.
+        vault.delete_file(username, "f1", &key).unwrap();
+        let _ = vault.load_file_data(username, "f1", &key).unwrap_err();
+        vault.create_file("f1", username, "4", &key).unwrap();
         let _ = vault
-            .load_credential(username2, "my bank account", unlocked2.key())
+            .restore_trashed_file(username, "f1", &key)
             .unwrap_err();
+        vault.delete_file(username, "f1", &key).unwrap();
+        vault.purge_trashed_file(username, "f1", &key).unwrap();
+        vault.restore_trashed_file(username, "f1", &key).unwrap();
+        assert_eq!(vault.load_file_data(username, "f1", &key).unwrap(), f1);
+
+        // The names stay indexed through a key rotation.
         vault
-            .load_credential(username1, "my bank account", unlocked1.key())
+            .rotate_account_key(username, password, None, None)
             .unwrap();
+        let key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+        for (filename, file_data) in [("f1", &f1), ("f2", &legacy)] {
+            let rotated = vault.load_file_data(username, filename, &key).unwrap();
+            assert_eq!(rotated.id(), file_data.id());
+            assert_eq!(
+                rotated.name_index(),
+                Some(&FileData::compute_name_index(&key, filename).unwrap())
+            );
+        }
     }
 
     #[test]
-    fn create_del_files() {
-        let db_name = "create_del_files.db";
-        let fs_name = "create_del_files";
+    fn update_file() {
+        let db_name = "update_file.db";
+        let fs_name = "update_file";
         let db_path = db_path(db_name);
         let fs_dir = fs_dir(fs_name);
         refresh_test_db(db_name);
@@ -744,166 +4785,304 @@ mod tests {
 
         let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
 
-        let username1 = "mr_test";
-        let password1 = "open sesame!";
-        vault.create_new_account(username1, password1).unwrap();
-        let unlocked1 = vault.load_unlocked_account(username1, password1).unwrap();
-        assert!(vault.load_account_files_data(username1).unwrap().is_empty());
+        let username = "mr_test";
+        let password = "open sesame!";
+        vault.create_new_account(username, password, None).unwrap();
+        let key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
 
-        let username2 = "mr_awesome";
-        let password2 = "let me in!!!!!!";
-        vault.create_new_account(username2, password2).unwrap();
-        let unlocked2 = vault.load_unlocked_account(username2, password2).unwrap();
-        assert!(vault.load_account_files_data(username2).unwrap().is_empty());
+        let filename = "f";
+        let old_contents = "this is a test.";
+        vault
+            .create_file(filename, username, old_contents, &key)
+            .unwrap();
+        let (loaded_file_data, decrypted_contents): (FileData, String) =
+            vault.load_file(username, filename, &key).unwrap();
+        assert_eq!(loaded_file_data.filename::<String>(&key).unwrap(), filename);
+        assert_eq!(decrypted_contents, old_contents);
 
-        // Add some files.
+        let new_contents = "this is a test, i sure hope it works!";
         vault
-            .create_file(
-                "shopping list",
-                "mr_test",
-                "eggs\nmilk\nbread",
-                unlocked1.key(),
-            )
+            .update_file(username, filename, &key, new_contents)
             .unwrap();
+
+        let (loaded_file_data, decrypted_contents): (FileData, String) =
+            vault.load_file(username, filename, &key).unwrap();
+        assert_eq!(loaded_file_data.filename::<String>(&key).unwrap(), filename);
+        assert_eq!(decrypted_contents, new_contents);
+
+        // Shrinking edits leave no stale bytes behind.
+        let shorter_contents = "short";
         vault
-            .create_file(
-                "my secret",
-                "mr_test",
-                "Sometimes even I, the great Mr. Test, get tired of tests sometimes...",
-                unlocked1.key(),
+            .update_file(username, filename, &key, shorter_contents)
+            .unwrap();
+        let (_, decrypted_contents): (FileData, String) =
+            vault.load_file(username, filename, &key).unwrap();
+        assert_eq!(decrypted_contents, shorter_contents);
+
+        // An interrupted write leaves the original untouched.
+        struct Interrupted;
+        impl Read for Interrupted {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("interrupted"))
+            }
+        }
+        let pending_path = get_pending_file_path(loaded_file_data.path(), loaded_file_data.id());
+        let _ = vault
+            .update_file_from(
+                username,
+                filename,
+                &key,
+                b"this will never be written".chain(Interrupted),
+            )
+            .unwrap_err();
+        let (_, decrypted_contents): (FileData, String) =
+            vault.load_file(username, filename, &key).unwrap();
+        assert_eq!(decrypted_contents, shorter_contents);
+        assert!(!pending_path.try_exists().unwrap());
+
+        // A failed rename before the commit leaves the original untouched.
+        struct RemovePending(Utf8PathBuf);
+        impl Read for RemovePending {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                let _ = fs::remove_file(&self.0);
+                Ok(0)
+            }
+        }
+        let replaced_path = get_replaced_file_path(loaded_file_data.path(), loaded_file_data.id());
+        let file_data = vault.load_file_data(username, filename, &key).unwrap();
+        let num_revisions = vault
+            .load_file_revisions(username, filename, &key)
+            .unwrap()
+            .len();
+        let _ = vault
+            .update_file_from(
+                username,
+                filename,
+                &key,
+                b"this will never be committed".chain(RemovePending(pending_path.clone())),
             )
+            .unwrap_err();
+        let (unchanged_file_data, decrypted_contents): (FileData, String) =
+            vault.load_file(username, filename, &key).unwrap();
+        assert_eq!(decrypted_contents, shorter_contents);
+        assert_eq!(
+            unchanged_file_data.contents_nonce(),
+            file_data.contents_nonce()
+        );
+        assert_eq!(
+            vault
+                .load_file_revisions(username, filename, &key)
+                .unwrap()
+                .len(),
+            num_revisions
+        );
+        assert!(!replaced_path.try_exists().unwrap());
+        assert_eq!(vault.check(None).unwrap(), vec![]);
+
+        // An update interrupted between the renames & the commit is undone on the next login.
+        fs::copy(loaded_file_data.path(), &replaced_path).unwrap();
+        fs::write(loaded_file_data.path(), "uncommitted contents").unwrap();
+        vault
+            .load_unlocked_account(username, password, None, None)
             .unwrap();
+        let (_, decrypted_contents): (FileData, String) =
+            vault.load_file(username, filename, &key).unwrap();
+        assert_eq!(decrypted_contents, shorter_contents);
+        assert!(!replaced_path.try_exists().unwrap());
+
+        // An interrupted import leaves nothing behind.
+        let account_dir = get_account_file_dir(&fs_dir, username).unwrap();
+        let num_files = fs::read_dir(&account_dir).unwrap().count();
         let _ = vault
-            .create_file("my secret", "mr_test", "No dupes allowed!", unlocked1.key())
+            .import_file("g", username, b"partial".chain(Interrupted), &key)
             .unwrap_err();
+        let _ = vault.load_file_data(username, "g", &key).unwrap_err();
+        assert_eq!(fs::read_dir(account_dir).unwrap().count(), num_files);
+        assert_eq!(vault.check(None).unwrap(), vec![]);
+    }
 
-        vault.create_file(
-            "my secret", 
-            "mr_awesome", 
-            "i wish i wasn't the second account ALL the time...\n\nsometimes a guy just wants to be \"number one\", yennow?",
-            unlocked2.key()
-            ).unwrap();
+    #[test]
+    fn privacy_mode() {
+        let db_name = "privacy_mode.db";
+        let fs_name = "privacy_mode";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+        assert!(!vault.privacy_mode());
+        vault.enable_privacy_mode().unwrap();
+        assert!(vault.privacy_mode());
+        let _ = vault.enable_privacy_mode().unwrap_err();
+
+        let username = "mr_private";
+        let password = "open sesame!";
+        vault.create_new_account(username, password, None).unwrap();
+        let unlocked = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap();
         vault
-            .create_file("中文", "mr_awesome", "加拿大很美丽", unlocked2.key())
+            .create_credential(username, unlocked.key(), "c", "u", "p", "n")
             .unwrap();
         vault
-            .create_file("blah blah blah", "mr_awesome", "", unlocked2.key())
+            .create_file("f", username, "contents", unlocked.key())
             .unwrap();
 
-        // Open some files.
-        assert_eq!(vault.load_account_files_data("mr_test").unwrap().len(), 2);
+        // The username isn't stored anywhere.
+        let accounts = vault.load_all::<Account>().unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert_ne!(accounts[0].username(), username);
+        assert_eq!(accounts[0].username().len(), 64);
+        assert_eq!(unlocked.username(), accounts[0].username());
+        let account_dirs = fs::read_dir(&fs_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(account_dirs, [accounts[0].username()]);
         assert_eq!(
-            vault.load_account_files_data("mr_awesome").unwrap().len(),
-            3
+            vault
+                .load_credential(username, "c", unlocked.key())
+                .unwrap()
+                .owner_username(),
+            accounts[0].username()
         );
+        let db_bytes = fs::read(&db_path).unwrap();
+        assert!(!db_bytes
+            .windows(username.len())
+            .any(|window| window == username.as_bytes()));
 
-        let (test_shop_fd, test_shop_contents): (FileData, String) = vault
-            .load_file("mr_test", "shopping list", unlocked1.key())
+        // Privacy mode persists, & everything still works by username.
+        drop(vault);
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+        assert!(vault.privacy_mode());
+        let unlocked = vault
+            .load_unlocked_account(username, password, None, None)
             .unwrap();
-        assert_eq!(test_shop_fd.filename(), "shopping list");
-        assert_eq!(test_shop_contents, "eggs\nmilk\nbread");
-
-        let (test_secret_fd, test_secret_contents): (FileData, String) = vault
-            .load_file("mr_test", "my secret", unlocked1.key())
+        let _ = vault
+            .load_unlocked_account("mr_public", password, None, None)
+            .unwrap_err();
+        let credential = vault
+            .load_credential(username, "c", unlocked.key())
             .unwrap();
-        assert_eq!(test_secret_fd.filename(), "my secret");
-        assert_eq!(
-            test_secret_contents,
-            "Sometimes even I, the great Mr. Test, get tired of tests sometimes..."
-        );
-
-        let (awesome_secret_fd, awesome_secret_contents): (FileData, String) = vault
-            .load_file("mr_awesome", "my secret", unlocked2.key())
+        assert_eq!(credential.password::<String>(unlocked.key()).unwrap(), "p");
+        let (_, contents): (FileData, String) =
+            vault.load_file(username, "f", unlocked.key()).unwrap();
+        assert_eq!(contents, "contents");
+        vault
+            .rotate_account_key(username, password, unlocked.key_file(), None)
             .unwrap();
-        assert_eq!(awesome_secret_fd.filename(), "my secret");
-        assert_eq!(
-            awesome_secret_contents,
-            "i wish i wasn't the second account ALL the time...\n\nsometimes a guy just wants to be \"number one\", yennow?"
-        );
-
-        let (awesome_zhongwen_fd, awesome_zhongwen_contents): (FileData, String) = vault
-            .load_file("mr_awesome", "中文", unlocked2.key())
+        vault.delete_account(username).unwrap();
+        vault
+            .purge_account(username, password, unlocked.key_file(), None)
             .unwrap();
-        assert_eq!(awesome_zhongwen_fd.filename(), "中文");
-        assert_eq!(awesome_zhongwen_contents, "加拿大很美丽");
+        assert!(vault.load_all::<Account>().unwrap().is_empty());
+        assert_eq!(fs::read_dir(&fs_dir).unwrap().count(), 0);
+    }
 
-        // Ensure that the file will not be deleted on database error.
-        // Force delete database entry improperly to cause error.
-        let zhongwen_path =
-            get_file_path(&vault.filesystem_directory, "mr_awesome", "中文").unwrap();
+    #[test]
+    fn late_privacy_mode() {
+        let db_name = "late_privacy_mode.db";
+        let fs_name = "late_privacy_mode";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        // Privacy mode can't be enabled once there are accounts.
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
         vault
-            .database
-            .delete_entry::<FileData, &Utf8Path, 1>([&zhongwen_path])
+            .create_new_account("mr_public", "open sesame!", None)
             .unwrap();
-        let _ = vault.delete_file("mr_awesome", "中文").unwrap_err();
-        open_file(zhongwen_path).unwrap();
-        assert_eq!(
-            vault.load_account_files_data("mr_awesome").unwrap().len(),
-            2
-        );
-
-        // Ensure that database entry will not be deleted on file error.
-        // Force delete file to ensure file error.
-        let blah_path =
-            get_file_path(&vault.filesystem_directory, "mr_awesome", "blah blah blah").unwrap();
-        fs::remove_file(&blah_path).unwrap();
-        let _ = vault
-            .delete_file("mr_awesome", "blah blah blah")
-            .unwrap_err();
-        let _ = vault
-            .load_file::<&str, &str, Vec<u8>>("mr_awesome", "blah blah blah", unlocked2.key())
-            .unwrap_err();
-        assert_eq!(
-            vault.load_account_files_data("mr_awesome").unwrap().len(),
-            2
-        );
+        let _ = vault.enable_privacy_mode().unwrap_err();
+        assert!(!vault.privacy_mode());
+        let vault = Vault::connect(&db_path, &fs_dir).unwrap();
+        assert!(!vault.privacy_mode());
     }
 
     #[test]
-    fn change_password() {
-        let db_name = "change_password.db";
-        let fs_name = "change_password";
+    fn switch_cipher_suite() {
+        let db_name = "switch_cipher_suite.db";
+        let fs_name = "switch_cipher_suite";
         let db_path = db_path(db_name);
         let fs_dir = fs_dir(fs_name);
         refresh_test_db(db_name);
         refresh_test_fs(fs_name);
 
         let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+        assert_eq!(vault.cipher_suite(), CipherSuite::default());
+        vault.set_cipher_suite(CipherSuite::Aes256Gcm).unwrap();
 
         let username = "mr_test";
         let password = "open sesame!";
-        let new_password = "mr. test is the best!";
-        vault.create_new_account(username, password).unwrap();
+        vault.create_new_account(username, password, None).unwrap();
         let key = vault
-            .load_unlocked_account(username, password)
+            .load_unlocked_account(username, password, None, None)
             .unwrap()
-            .key();
-
-        let filename = "f";
-        let contents = "blah blah blah. this is a test. 我要茶";
+            .key()
+            .clone();
         vault
-            .create_file(filename, username, contents, key)
+            .create_credential(username, &key, "c", "u", "p", "n")
             .unwrap();
-        let (_, fcontents): (_, String) = vault.load_file(username, filename, key).unwrap();
-        assert_eq!(fcontents, contents);
+        vault.create_file("f", username, "old", &key).unwrap();
 
+        // The cipher suite persists between connections.
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+        assert_eq!(vault.cipher_suite(), CipherSuite::Aes256Gcm);
         vault
-            .change_account_password(username, password, new_password)
+            .set_cipher_suite(CipherSuite::XChaCha20Poly1305)
             .unwrap();
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+        assert_eq!(vault.cipher_suite(), CipherSuite::XChaCha20Poly1305);
 
-        let _ = vault.load_unlocked_account(username, password).unwrap_err();
-        let key = vault
-            .load_unlocked_account(username, new_password)
-            .unwrap()
-            .key();
-        let (_, fcontents): (_, String) = vault.load_file(username, filename, key).unwrap();
-        assert_eq!(fcontents, contents);
+        // Old data remains readable.
+        let credential = vault.load_credential(username, "c", &key).unwrap();
+        assert_eq!(
+            credential.encrypted_password().cipher_suite(),
+            CipherSuite::Aes256Gcm
+        );
+        assert_eq!(credential.password::<String>(&key).unwrap(), "p");
+        let (file_data, contents): (FileData, String) =
+            vault.load_file(username, "f", &key).unwrap();
+        assert_eq!(
+            file_data.contents_nonce().cipher_suite(),
+            CipherSuite::Aes256Gcm
+        );
+        assert_eq!(contents, "old");
+
+        // Changed data is encrypted with the new cipher suite.
+        vault
+            .update_credential_fields(username, "c", &key, &[(CredentialField::Password, "new p")])
+            .unwrap();
+        vault.update_file(username, "f", &key, "new").unwrap();
+        let credential = vault.load_credential(username, "c", &key).unwrap();
+        assert_eq!(
+            credential.encrypted_password().cipher_suite(),
+            CipherSuite::XChaCha20Poly1305
+        );
+        assert_eq!(
+            credential.encrypted_username().cipher_suite(),
+            CipherSuite::Aes256Gcm
+        );
+        assert_eq!(credential.password::<String>(&key).unwrap(), "new p");
+        assert_eq!(credential.username::<String>(&key).unwrap(), "u");
+        let (file_data, contents): (FileData, String) =
+            vault.load_file(username, "f", &key).unwrap();
+        assert_eq!(
+            file_data.contents_nonce().cipher_suite(),
+            CipherSuite::XChaCha20Poly1305
+        );
+        assert_eq!(contents, "new");
     }
 
     #[test]
-    fn update_credential() {
-        let db_name = "update_credential.db";
-        let fs_name = "update_credential";
+    fn age_export_import() {
+        let db_name = "age_export_import.db";
+        let fs_name = "age_export_import";
         let db_path = db_path(db_name);
         let fs_dir = fs_dir(fs_name);
         refresh_test_db(db_name);
@@ -913,106 +5092,164 @@ mod tests {
 
         let username = "mr_test";
         let password = "open sesame!";
-        vault.create_new_account(username, password).unwrap();
+        vault.create_new_account(username, password, None).unwrap();
         let key = vault
-            .load_unlocked_account(username, password)
+            .load_unlocked_account(username, password, None, None)
             .unwrap()
-            .key();
+            .key()
+            .clone();
 
-        let c_name = "c1";
-        let c_username = "my_account";
-        let c_password = "my_password";
-        let c_notes = "my_notes";
+        let secret_key = age::x25519::Identity::generate();
+        let recipient = AgeRecipient::PublicKey(secret_key.to_public());
+        let identity = AgeIdentity::SecretKey(secret_key);
 
+        // Files round-trip through an age payload.
+        let contents = b"The quick brown fox jumps over the lazy dog.";
         vault
-            .create_credential(username, key, c_name, c_username, c_password, c_notes)
+            .import_file("original", username, &contents[..], &key)
             .unwrap();
-        let loaded_c = vault.load_credential(username, c_name, key).unwrap();
-        assert_eq!(loaded_c.username::<String>(key).unwrap(), c_username);
-
-        let new_c_username = "my_new_account";
-
+        let mut payload = vec![];
+        let num_bytes = vault
+            .export_file_age(username, "original", &key, &recipient, &mut payload)
+            .unwrap();
+        assert_eq!(num_bytes, contents.len() as u64);
+        assert!(!payload
+            .windows(contents.len())
+            .any(|window| window == contents));
         vault
-            .update_credential(
-                username,
-                c_name,
-                key,
-                CredentialUpdateField::UsernameCipherbytes,
-                CredentialUpdateField::UsernameNonce,
-                new_c_username,
-            )
+            .import_file_age("copy", username, &identity, payload.as_slice(), &key)
             .unwrap();
-        let loaded_c = vault.load_credential(username, c_name, key).unwrap();
-        assert_eq!(loaded_c.username::<String>(key).unwrap(), new_c_username);
+        let (_, loaded): (FileData, Vec<u8>) = vault.load_file(username, "copy", &key).unwrap();
+        assert_eq!(loaded, contents);
 
-        let new_c_password = "my_new_password";
+        // The wrong identity creates no file.
+        let wrong_identity = AgeIdentity::SecretKey(age::x25519::Identity::generate());
+        let _ = vault
+            .import_file_age("wrong", username, &wrong_identity, payload.as_slice(), &key)
+            .unwrap_err();
+        let _ = vault.load_file_data(username, "wrong", &key).unwrap_err();
+        assert_eq!(vault.load_account_files_data(username).unwrap().len(), 2);
 
+        // Credentials are exported as text.
         vault
-            .update_credential(
+            .create_credential(
                 username,
-                c_name,
-                key,
-                CredentialUpdateField::PasswordCipherbytes,
-                CredentialUpdateField::PasswordNonce,
-                new_c_password,
+                &key,
+                "my bank account",
+                "1234 5678 9012 3456",
+                "letmeinpls",
+                "expiry date: 12/34",
             )
             .unwrap();
-        let loaded_c = vault.load_credential(username, c_name, key).unwrap();
-        assert_eq!(loaded_c.password::<String>(key).unwrap(), new_c_password);
-
-        let new_c_notes = "my_new_notes";
-
+        let mut payload = vec![];
         vault
-            .update_credential(
-                username,
-                c_name,
-                key,
-                CredentialUpdateField::NotesCipherbytes,
-                CredentialUpdateField::NotesNonce,
-                new_c_notes,
-            )
+            .export_credential_age(username, "my bank account", &key, &recipient, &mut payload)
+            .unwrap();
+        let mut exported = String::new();
+        decrypt_from(&identity, payload.as_slice())
+            .unwrap()
+            .read_to_string(&mut exported)
             .unwrap();
-        let loaded_c = vault.load_credential(username, c_name, key).unwrap();
-        assert_eq!(loaded_c.notes::<String>(key).unwrap(), new_c_notes);
+        assert_eq!(
+            exported,
+            "Credential \"my bank account\"\nUsername: 1234 5678 9012 3456\nPassword: letmeinpls\nNotes: expiry date: 12/34\n"
+        );
+        let _ = vault
+            .export_credential_age(username, "nonexistent", &key, &recipient, &mut vec![])
+            .unwrap_err();
     }
 
     #[test]
-    fn update_file() {
-        let db_name = "update_file.db";
-        let fs_name = "update_file";
+    fn single_file_vault() {
+        let db_name = "single_file_vault.db";
+        let fs_name = "single_file_vault";
         let db_path = db_path(db_name);
         let fs_dir = fs_dir(fs_name);
         refresh_test_db(db_name);
         refresh_test_fs(fs_name);
 
-        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+        let mut vault = Vault::connect_single_file(&db_path).unwrap();
+        assert!(vault.is_single_file());
 
-        let username = "mr_test";
         let password = "open sesame!";
-        vault.create_new_account(username, password).unwrap();
+        vault.create_new_account("owner", password, None).unwrap();
+        vault.create_new_account("other", password, None).unwrap();
         let key = vault
-            .load_unlocked_account(username, password)
+            .load_unlocked_account("owner", password, None, None)
             .unwrap()
-            .key();
-
-        let filename = "f";
-        let old_contents = "this is a test.";
+            .key()
+            .clone();
         vault
-            .create_file(filename, username, old_contents, key)
+            .create_file("f1", "owner", "contents 1", &key)
             .unwrap();
-        let (loaded_file_data, decrypted_contents): (FileData, String) =
-            vault.load_file(username, filename, key).unwrap();
-        assert_eq!(loaded_file_data.filename(), filename);
-        assert_eq!(decrypted_contents, old_contents);
+        vault
+            .create_file("f2", "owner", "contents 2", &key)
+            .unwrap();
+        vault
+            .update_file("owner", "f2", &key, "new contents 2")
+            .unwrap();
+        vault.share_file("owner", "f1", &key, "other").unwrap();
+        vault.delete_file("owner", "f2", &key).unwrap();
+        vault.purge_trashed_file("owner", "f2", &key).unwrap();
+        vault
+            .create_file("f2", "owner", "contents 2", &key)
+            .unwrap();
+        vault
+            .rotate_account_key("owner", password, None, None)
+            .unwrap();
+        let key = vault
+            .load_unlocked_account("owner", password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+        assert_eq!(vault.check(None).unwrap(), vec![]);
 
-        let new_contents = "this is a test, i sure hope it works!";
+        // Nothing is kept outside the database.
+        assert_eq!(fs::read_dir(&fs_dir).unwrap().count(), 0);
+        let (_, contents): (FileData, String) = vault.load_file("owner", "f1", &key).unwrap();
+        assert_eq!(contents, "contents 1");
+
+        // The layout is saved, so the directory is ignored.
+        drop(vault);
+        let mut vault = Vault::connect(&db_path, &fs_dir.join("missing")).unwrap();
+        assert!(vault.is_single_file());
+
+        // Convert the vault to the directory layout & back again.
+        vault.convert_to_directory(&fs_dir).unwrap();
+        assert!(!vault.is_single_file());
+        assert_eq!(
+            fs::read_dir(get_account_file_dir(&fs_dir, "owner").unwrap())
+                .unwrap()
+                .count(),
+            3
+        );
+        assert_eq!(vault.check(None).unwrap(), vec![]);
+        drop(vault);
+        let _ = Vault::connect_single_file(&db_path).unwrap_err();
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+        let (_, contents): (FileData, String) = vault.load_file("owner", "f2", &key).unwrap();
+        assert_eq!(contents, "contents 2");
+
+        vault.convert_to_single_file().unwrap();
+        assert!(vault.is_single_file());
+        assert_eq!(fs::read_dir(&fs_dir).unwrap().count(), 0);
+        assert_eq!(vault.check(None).unwrap(), vec![]);
+        let (_, contents): (FileData, String) = vault.load_file("owner", "f1", &key).unwrap();
+        assert_eq!(contents, "contents 1");
+        let other = vault
+            .load_unlocked_account("other", password, None, None)
+            .unwrap();
+        let mut shared_contents = Vec::new();
         vault
-            .update_file(username, filename, key, new_contents)
+            .export_shared_file(&other, "f1", &mut shared_contents)
             .unwrap();
+        assert_eq!(shared_contents, b"contents 1");
 
-        let (loaded_file_data, decrypted_contents): (FileData, String) =
-            vault.load_file(username, filename, key).unwrap();
-        assert_eq!(loaded_file_data.filename(), filename);
-        assert_eq!(decrypted_contents, new_contents);
+        // Purging an account deletes its files.
+        vault.delete_account("owner").unwrap();
+        vault.purge_account("owner", password, None, None).unwrap();
+        assert!(FileStore::select_paths(vault.database.connection())
+            .unwrap()
+            .is_empty());
     }
 }
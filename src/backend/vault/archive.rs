@@ -0,0 +1,399 @@
+//! The format of [Vault](super::Vault) backup archives, which hold a snapshot of the
+//! [Database](super::database::Database) & the encrypted contents of every file.
+//!
+//! An archive is a header line, followed by its entries, each a line giving its size & name
+//! followed by its bytes. It ends with a manifest listing the SHA-256 hash, size & name of every
+//! entry, & the SHA-256 hash of the manifest itself:
+//!
+//! ```text
+//! dgruft-backup 1
+//! entry <size> dgruft.db
+//! <bytes>
+//! entry <size> files/<account name>/<file name>
+//! <bytes>
+//! manifest <number of entries>
+//! <sha-256> <size> dgruft.db
+//! <sha-256> <size> files/<account name>/<file name>
+//! checksum <sha-256 of the manifest>
+//! ```
+//!
+//! Archives are read & written unencrypted here; the [Vault](super::Vault) wraps them in an age
+//! payload.
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Write},
+};
+
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::{self, eyre};
+use sha2::{Digest, Sha256};
+
+/// The name of the archive entry holding the [Database](super::database::Database).
+pub const DATABASE_ENTRY: &str = "dgruft.db";
+
+/// The directory of the archive entries holding the encrypted contents of files.
+pub const FILES_ENTRY_DIR: &str = "files";
+
+// The first line of every archive, giving the version of the format.
+const HEADER: &str = "dgruft-backup 1";
+
+// The longest line allowed outside of an entry's bytes.
+const MAX_LINE_LEN: u64 = 1024;
+
+/// Writes entries to a backup archive, keeping track of the manifest as it goes.
+pub struct ArchiveWriter<W: Write> {
+    writer: W,
+    manifest: Vec<ManifestLine>,
+}
+impl<W: Write> ArchiveWriter<W> {
+    /// Start a new archive by writing its header to the `writer`.
+    pub fn new(mut writer: W) -> eyre::Result<Self> {
+        writeln!(writer, "{}", HEADER)?;
+        Ok(Self {
+            writer,
+            manifest: Vec::new(),
+        })
+    }
+
+    /// Add an entry with the given `name` & `size`, read from `reader`. Fails if the `reader`
+    /// doesn't hold exactly `size` bytes.
+    pub fn add_entry<R: Read>(
+        &mut self,
+        name: &Utf8Path,
+        size: u64,
+        reader: R,
+    ) -> eyre::Result<()> {
+        validate_entry_name(name)?;
+        writeln!(self.writer, "entry {} {}", size, name)?;
+        let mut hashing_writer = HashingWriter::new(&mut self.writer);
+        let num_bytes = io::copy(&mut reader.take(size + 1), &mut hashing_writer)?;
+        if num_bytes != size {
+            return Err(eyre!(
+                "{:?} changed size while being backed up: expected {} bytes, found {}.",
+                name,
+                size,
+                num_bytes
+            ));
+        }
+        self.manifest.push(ManifestLine {
+            hash: hashing_writer.finalize(),
+            size,
+            name: name.to_owned(),
+        });
+        Ok(())
+    }
+
+    /// Write the manifest & its checksum, returning the inner writer.
+    pub fn finish(mut self) -> eyre::Result<W> {
+        let manifest = manifest_text(&self.manifest);
+        self.writer.write_all(manifest.as_bytes())?;
+        writeln!(self.writer, "checksum {}", hex(&Sha256::digest(&manifest)))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Extract every entry of the archive read from `reader` into the `staging_dir`, which must
+/// already exist, & verify them against the manifest. Return the names of the entries.
+///
+/// Nothing extracted should be used unless this succeeds.
+pub fn extract_archive<R: Read>(
+    reader: R,
+    staging_dir: &Utf8Path,
+) -> eyre::Result<Vec<Utf8PathBuf>> {
+    let mut reader = BufReader::new(reader);
+    if read_line(&mut reader)? != HEADER {
+        return Err(eyre!("Not a dgruft backup archive."));
+    }
+
+    // Extract the entries, hashing them as they're written.
+    let mut extracted = Vec::new();
+    let mut names = HashSet::new();
+    let num_entries = loop {
+        let line = read_line(&mut reader)?;
+        if let Some(num_entries) = line.strip_prefix("manifest ") {
+            break num_entries.parse::<usize>()?;
+        }
+        let Some((size, name)) = line
+            .strip_prefix("entry ")
+            .and_then(|entry| entry.split_once(' '))
+        else {
+            return Err(eyre!(
+                "Malformed backup archive: unexpected line {:?}.",
+                line
+            ));
+        };
+        let size: u64 = size.parse()?;
+        let name = Utf8PathBuf::from(name);
+        validate_entry_name(&name)?;
+        if !names.insert(name.clone()) {
+            return Err(eyre!("Malformed backup archive: {:?} appears twice.", name));
+        }
+
+        let path = staging_dir.join(&name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut hashing_writer = HashingWriter::new(File::create_new(&path)?);
+        let num_bytes = io::copy(&mut (&mut reader).take(size), &mut hashing_writer)?;
+        if num_bytes != size {
+            return Err(eyre!(
+                "Backup archive is truncated: {:?} is incomplete.",
+                name
+            ));
+        }
+        hashing_writer.inner.sync_all()?;
+        extracted.push(ManifestLine {
+            hash: hashing_writer.finalize(),
+            size,
+            name,
+        });
+    };
+
+    // Read the manifest & check it against both its checksum & the extracted entries.
+    let mut manifest = Vec::with_capacity(num_entries.min(extracted.len()));
+    for _ in 0..num_entries {
+        manifest.push(read_line(&mut reader)?.parse::<ManifestLine>()?);
+    }
+    let checksum = read_line(&mut reader)?;
+    if checksum.strip_prefix("checksum ")
+        != Some(hex(&Sha256::digest(manifest_text(&manifest))).as_str())
+    {
+        return Err(eyre!("Backup archive manifest doesn't match its checksum."));
+    }
+    if !reader.fill_buf()?.is_empty() {
+        return Err(eyre!(
+            "Malformed backup archive: unexpected data after the checksum."
+        ));
+    }
+    if manifest.len() != extracted.len() {
+        return Err(eyre!(
+            "Backup archive manifest lists {} entries, but the archive holds {}.",
+            manifest.len(),
+            extracted.len()
+        ));
+    }
+    for (listed, found) in manifest.iter().zip(&extracted) {
+        if listed != found {
+            return Err(eyre!(
+                "Backup archive entry {:?} doesn't match the manifest.",
+                found.name
+            ));
+        }
+    }
+
+    Ok(extracted.into_iter().map(|line| line.name).collect())
+}
+
+/// Return the archive entry name of the file at `path`, from its account name & its own name.
+pub fn file_entry_name(path: &Utf8Path) -> eyre::Result<Utf8PathBuf> {
+    let (Some(account_name), Some(file_name)) = (
+        path.parent().and_then(Utf8Path::file_name),
+        path.file_name(),
+    ) else {
+        return Err(eyre!("{:?} is not in an account's file directory.", path));
+    };
+    Ok(Utf8Path::new(FILES_ENTRY_DIR)
+        .join(account_name)
+        .join(file_name))
+}
+
+// Helper function: Ensure an entry name is either the database entry or a file entry, so that
+// entries can't be extracted outside of the staging directory.
+fn validate_entry_name(name: &Utf8Path) -> eyre::Result<()> {
+    let components = name.components().collect::<Vec<Utf8Component>>();
+    let valid = match components.as_slice() {
+        [Utf8Component::Normal(name)] => *name == DATABASE_ENTRY,
+        [Utf8Component::Normal(dir), Utf8Component::Normal(_), Utf8Component::Normal(_)] => {
+            *dir == FILES_ENTRY_DIR
+        }
+        _ => false,
+    };
+    if !valid || name.as_str().contains(['\n', '\\']) {
+        return Err(eyre!("Invalid backup archive entry name {:?}.", name));
+    }
+    Ok(())
+}
+
+// Helper function: Read one line, without its line ending.
+fn read_line<R: BufRead>(reader: &mut R) -> eyre::Result<String> {
+    let mut line = String::new();
+    reader.take(MAX_LINE_LEN).read_line(&mut line)?;
+    match line.strip_suffix('\n') {
+        Some(line) => Ok(line.to_owned()),
+        None => Err(eyre!("Backup archive is truncated or malformed.")),
+    }
+}
+
+// Helper function: The text of the manifest listing the given entries.
+fn manifest_text(manifest: &[ManifestLine]) -> String {
+    let mut text = format!("manifest {}\n", manifest.len());
+    for line in manifest {
+        text.push_str(&format!("{} {} {}\n", line.hash, line.size, line.name));
+    }
+    text
+}
+
+// Helper function: Hex-encode some bytes.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+// One entry listed in the manifest.
+#[derive(Debug, PartialEq, Eq)]
+struct ManifestLine {
+    hash: String,
+    size: u64,
+    name: Utf8PathBuf,
+}
+impl std::str::FromStr for ManifestLine {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> eyre::Result<Self> {
+        let mut parts = s.splitn(3, ' ');
+        let (Some(hash), Some(size), Some(name)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(eyre!("Malformed backup archive manifest line {:?}.", s));
+        };
+        Ok(Self {
+            hash: hash.to_owned(),
+            size: size.parse()?,
+            name: name.into(),
+        })
+    }
+}
+
+// A [Write]r which hashes everything written through it.
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+}
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    fn finalize(self) -> String {
+        hex(&self.hasher.finalize())
+    }
+}
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let num_bytes = self.inner.write(buf)?;
+        self.hasher.update(&buf[..num_bytes]);
+        Ok(num_bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const TEST_DIR_STR: &str = "tests/test_backup_dir";
+
+    fn staging_dir(name: &str) -> Utf8PathBuf {
+        let dir = Utf8Path::new(TEST_DIR_STR).join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn test_archive() -> Vec<u8> {
+        let mut writer = ArchiveWriter::new(Vec::new()).unwrap();
+        let database = b"not really a database";
+        writer
+            .add_entry(
+                Utf8Path::new(DATABASE_ENTRY),
+                database.len() as u64,
+                &database[..],
+            )
+            .unwrap();
+        let file = b"encrypted\nfile\ncontents";
+        writer
+            .add_entry(
+                &file_entry_name(Utf8Path::new("data/mr_test/file_id")).unwrap(),
+                file.len() as u64,
+                &file[..],
+            )
+            .unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = staging_dir("round_trip");
+        let names = extract_archive(test_archive().as_slice(), &dir).unwrap();
+        assert_eq!(
+            names,
+            vec![
+                Utf8PathBuf::from("dgruft.db"),
+                Utf8PathBuf::from("files/mr_test/file_id")
+            ]
+        );
+        assert_eq!(
+            fs::read(dir.join("files/mr_test/file_id")).unwrap(),
+            b"encrypted\nfile\ncontents"
+        );
+    }
+
+    #[test]
+    fn wrong_size() {
+        let mut writer = ArchiveWriter::new(Vec::new()).unwrap();
+        let _ = writer
+            .add_entry(Utf8Path::new(DATABASE_ENTRY), 100, &b"too short"[..])
+            .unwrap_err();
+    }
+
+    #[test]
+    fn tampered() {
+        let archive = test_archive();
+        let position = archive.windows(9).position(|w| w == b"encrypted").unwrap();
+
+        // A changed entry doesn't match the manifest.
+        let mut tampered = archive.clone();
+        tampered[position] = b'E';
+        let dir = staging_dir("tampered_entry");
+        let _ = extract_archive(tampered.as_slice(), &dir).unwrap_err();
+
+        // A changed manifest doesn't match its checksum.
+        let position = archive.windows(9).rposition(|w| w == b" dgruft.d").unwrap();
+        let mut tampered = archive.clone();
+        tampered[position - 1] ^= 1;
+        let dir = staging_dir("tampered_manifest");
+        let _ = extract_archive(tampered.as_slice(), &dir).unwrap_err();
+
+        // A truncated archive is incomplete.
+        let dir = staging_dir("tampered_truncated");
+        let _ = extract_archive(&archive[..archive.len() - 10], &dir).unwrap_err();
+    }
+
+    #[test]
+    fn invalid_entry_names() {
+        for name in [
+            "../dgruft.db",
+            "/dgruft.db",
+            "files/../../escaped",
+            "files/mr_test",
+            "files/mr_test/../file_id",
+            "other/mr_test/file_id",
+            "files/mr_test/file_id/extra",
+        ] {
+            let _ = validate_entry_name(Utf8Path::new(name)).unwrap_err();
+        }
+
+        let dir = staging_dir("invalid_entry_names");
+        let archive = format!("{}\nentry 4 ../escaped\noops", HEADER);
+        let _ = extract_archive(archive.as_bytes(), &dir).unwrap_err();
+        assert!(!Utf8Path::new(TEST_DIR_STR).join("escaped").exists());
+    }
+}
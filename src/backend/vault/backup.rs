@@ -0,0 +1,444 @@
+//! Backing up a [Vault](super::Vault) to an [archive](super::archive) & restoring it from one.
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs::{self, File},
+    io::Read,
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::{self, eyre};
+use rusqlite::DatabaseName;
+
+use super::{
+    super::{
+        account::Account,
+        age_payload::{decrypt_from, encrypt_to, AgeIdentity, AgeRecipient},
+        file_data::{FileData, FileRevision},
+        sharing::SharedFile,
+    },
+    archive::{extract_archive, file_entry_name, ArchiveWriter, DATABASE_ENTRY, FILES_ENTRY_DIR},
+    database::Database,
+    file_store::FileStore,
+    filesystem::{
+        get_pending_file_path, new_empty_file, remove_file_if_exists, replace_file,
+        verify_writeable_dir,
+    },
+    Vault,
+};
+
+impl Vault {
+    /// Back up this [Vault] to a new archive at `destination`, encrypted to the `recipient` as an
+    /// age payload. Return the number of files archived.
+    ///
+    /// The archive holds a snapshot of the [Database], taken with SQLite's online backup API so
+    /// the [Vault] can stay in use, & the encrypted contents of every file the snapshot refers to,
+    /// along with a checksummed manifest. It is written beside the `destination` & only moved
+    /// there once it is complete.
+    pub fn backup<P>(&self, recipient: &AgeRecipient, destination: P) -> eyre::Result<usize>
+    where
+        P: AsRef<Utf8Path>,
+    {
+        let destination = destination.as_ref();
+        if destination.try_exists()? {
+            return Err(eyre!(
+                "Failed to back up vault: {:?} already exists.",
+                destination
+            ));
+        }
+        let snapshot_path = get_pending_file_path(destination, "snapshot");
+        let archive_path = get_pending_file_path(destination, "archive");
+
+        let result = self
+            .write_backup(recipient, &snapshot_path, &archive_path)
+            .and_then(|num_files| {
+                replace_file(&archive_path, destination)?;
+                Ok(num_files)
+            });
+        let _ = remove_file_if_exists(&snapshot_path);
+        if result.is_err() {
+            let _ = remove_file_if_exists(&archive_path);
+        }
+        result
+    }
+
+    // Helper function: Snapshot the [Database] to `snapshot_path`, then write the backup archive
+    // to `archive_path`. Return the number of files archived.
+    fn write_backup(
+        &self,
+        recipient: &AgeRecipient,
+        snapshot_path: &Utf8Path,
+        archive_path: &Utf8Path,
+    ) -> eyre::Result<usize> {
+        // Snapshot the database.
+        self.database
+            .connection()
+            .backup(DatabaseName::Main, snapshot_path, None)?;
+
+        // Find the files the snapshot refers to. A single-file vault's files are in the snapshot.
+        let mut file_paths = BTreeSet::new();
+        if let FileStore::Directory(_) = self.file_store {
+            let snapshot = Database::connect_read_only(snapshot_path)?;
+            for file_data in snapshot.select_all_entries::<FileData>()? {
+                file_paths.insert(file_data.path().to_owned());
+            }
+            for shared_file in snapshot.select_all_entries::<SharedFile>()? {
+                file_paths.insert(shared_file.file_data().path().to_owned());
+            }
+            for revision in snapshot.select_all_entries::<FileRevision>()? {
+                file_paths.insert(revision.path().to_owned());
+            }
+        }
+
+        // Write the snapshot & the files to the archive.
+        let mut age_writer = encrypt_to(recipient, new_empty_file(archive_path)?)?;
+        let mut archive = ArchiveWriter::new(&mut age_writer)?;
+        archive.add_entry(
+            Utf8Path::new(DATABASE_ENTRY),
+            fs::metadata(snapshot_path)?.len(),
+            File::open(snapshot_path)?,
+        )?;
+        for path in &file_paths {
+            archive.add_entry(
+                &file_entry_name(path)?,
+                fs::metadata(path)?.len(),
+                File::open(path)?,
+            )?;
+        }
+        archive.finish()?;
+        age_writer.finish()?.sync_all()?;
+
+        Ok(file_paths.len())
+    }
+
+    /// Verify the backup archive read from `archive`, decrypting it with the `identity`, & restore
+    /// it to `database_path`, returning the restored [Vault]. Its files are restored to account
+    /// directories in the `filesystem_directory`, which is only optional if the backed-up [Vault]
+    /// was a single file.
+    ///
+    /// The archive is extracted & verified beside the `database_path` before anything is touched.
+    /// Any [Vault] already at the `database_path` is then replaced, along with its account
+    /// directories, which are set aside until the restored [Vault] is in place & put back on a
+    /// failure.
+    pub fn restore_backup<R: Read>(
+        identity: &AgeIdentity,
+        archive: R,
+        database_path: &Utf8Path,
+        filesystem_directory: Option<&Utf8Path>,
+    ) -> eyre::Result<Self> {
+        // Leftovers of an interrupted restore are never used, so they're cleared away.
+        let staging_dir = get_pending_file_path(database_path, "restore");
+        if staging_dir.try_exists()? {
+            fs::remove_dir_all(&staging_dir)?;
+        }
+        fs::create_dir(&staging_dir)?;
+        let result = Self::install_backup(
+            identity,
+            archive,
+            database_path,
+            filesystem_directory,
+            &staging_dir,
+        );
+        let _ = fs::remove_dir_all(&staging_dir);
+        result?;
+
+        match filesystem_directory {
+            Some(filesystem_directory) => Self::connect(database_path, filesystem_directory),
+            None => Self::connect_single_file(database_path),
+        }
+    }
+
+    // Helper function: Extract & verify the backup archive in the `staging_dir`, then move the
+    // restored database & account directories into place.
+    fn install_backup<R: Read>(
+        identity: &AgeIdentity,
+        archive: R,
+        database_path: &Utf8Path,
+        filesystem_directory: Option<&Utf8Path>,
+        staging_dir: &Utf8Path,
+    ) -> eyre::Result<()> {
+        // Extract & verify the archive.
+        let entries = extract_archive(decrypt_from(identity, archive)?, staging_dir)?
+            .into_iter()
+            .collect::<HashSet<Utf8PathBuf>>();
+        if !entries.contains(Utf8Path::new(DATABASE_ENTRY)) {
+            return Err(eyre!("The backup archive holds no database."));
+        }
+
+        // Ensure every file the restored database refers to was archived, unless they're all in
+        // the database.
+        // The restored database is upgraded to the current schema, which needs no backup since it's
+        // a copy.
+        let staged_database_path = staging_dir.join(DATABASE_ENTRY);
+        let mut staged_database = Database::connect_copy(&staged_database_path)?;
+        let accounts = staged_database.select_all_entries::<Account>()?;
+        let files_data = staged_database.select_all_entries::<FileData>()?;
+        let shared_files = staged_database.select_all_entries::<SharedFile>()?;
+        let file_revisions = staged_database.select_all_entries::<FileRevision>()?;
+        let new_store = match (
+            staged_database
+                .select_setting(Self::FILE_STORE_SETTING)?
+                .as_deref()
+                == Some(FileStore::Database.layout()),
+            filesystem_directory,
+        ) {
+            (true, _) => None,
+            (false, Some(filesystem_directory)) => {
+                verify_writeable_dir(filesystem_directory)?;
+                Some(FileStore::Directory(filesystem_directory.into()))
+            }
+            (false, None) => {
+                return Err(eyre!(
+                    "The backed-up vault keeps its files in a directory, so it can't be restored as a single file."
+                ))
+            }
+        };
+        let file_paths = files_data
+            .iter()
+            .map(FileData::path)
+            .chain(shared_files.iter().map(|shared| shared.file_data().path()))
+            .chain(file_revisions.iter().map(FileRevision::path));
+        for path in file_paths.filter(|_| new_store.is_some()) {
+            let entry_name = file_entry_name(path)?;
+            if !entries.contains(&entry_name) {
+                return Err(eyre!("The backup archive is missing {:?}.", entry_name));
+            }
+        }
+
+        // Plan to set aside the vault being replaced, along with its account directories if it
+        // can be read, then to move the restored account directories into place.
+        let mut moves = Vec::new();
+        if let (Some(filesystem_directory), true) = (filesystem_directory, database_path.is_file())
+        {
+            let replaced_accounts = Database::connect_read_only(database_path)
+                .and_then(|database| database.select_all_entries::<Account>())
+                .map_err(|err| {
+                    eyre!(
+                        "Failed to read the vault being replaced: {} Restore the backup into a separate directory instead.",
+                        err
+                    )
+                })?;
+            for account in replaced_accounts {
+                let dir = filesystem_directory.join(account.username());
+                if dir.is_dir() {
+                    moves.push((dir.clone(), get_pending_file_path(&dir, "replaced")));
+                }
+            }
+        }
+        if database_path.try_exists()? {
+            moves.push((
+                database_path.to_owned(),
+                get_pending_file_path(database_path, "replaced"),
+            ));
+        }
+        let num_replaced = moves.len();
+        if let Some(filesystem_directory) = filesystem_directory.filter(|_| new_store.is_some()) {
+            for account in &accounts {
+                let staged_dir = staging_dir.join(FILES_ENTRY_DIR).join(account.username());
+                fs::create_dir_all(&staged_dir)?;
+                moves.push((staged_dir, filesystem_directory.join(account.username())));
+            }
+        }
+
+        // Make the moves, move the files' data to their restored paths & move the restored
+        // database into place, putting everything back on a failure.
+        let mut num_moved = 0;
+        let install_result = moves
+            .iter()
+            .try_for_each(|(from, to)| {
+                if to.try_exists()? {
+                    return Err(eyre!("Failed to restore backup: {:?} already exists.", to));
+                }
+                fs::rename(from, to)?;
+                num_moved += 1;
+                Ok(())
+            })
+            .and_then(|()| {
+                if let Some(new_store) = &new_store {
+                    let tx = staged_database.open_transaction()?;
+                    Self::transaction_move_files(
+                        new_store,
+                        &files_data,
+                        &shared_files,
+                        &file_revisions,
+                        &tx,
+                    )?;
+                    tx.commit()?;
+                }
+                drop(staged_database);
+                replace_file(&staged_database_path, database_path)
+            });
+        if let Err(err) = install_result {
+            for (from, to) in moves[..num_moved].iter().rev() {
+                let _ = fs::rename(to, from);
+            }
+            return Err(err);
+        }
+
+        // Delete the replaced vault.
+        for (_, replaced) in &moves[..num_replaced] {
+            if replaced.is_dir() {
+                fs::remove_dir_all(replaced)?;
+            } else {
+                fs::remove_file(replaced)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use std::fs;
+
+    use super::super::{
+        super::{
+            age_payload::{AgeIdentity, AgeRecipient},
+            file_data::FileData,
+        },
+        tests::{db_path, fs_dir, refresh_test_db, refresh_test_fs, test_dir},
+        Vault,
+    };
+
+    #[test]
+    fn backup_restore() {
+        let db_name = "backup_restore.db";
+        let fs_name = "backup_restore";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+        let password = "open sesame!";
+        vault.create_new_account("owner", password, None).unwrap();
+        vault.create_new_account("other", password, None).unwrap();
+        let key = vault
+            .load_unlocked_account("owner", password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+        vault
+            .create_credential("owner", &key, "c1", "user", "pass", "notes")
+            .unwrap();
+        vault
+            .create_file("f1", "owner", "contents 1", &key)
+            .unwrap();
+        vault.share_file("owner", "f1", &key, "other").unwrap();
+
+        // Back up the vault. Backups are never overwritten.
+        let secret_key = age::x25519::Identity::generate();
+        let recipient: AgeRecipient = secret_key.to_public().to_string().parse().unwrap();
+        let identity = AgeIdentity::SecretKey(secret_key);
+        let backup_path = test_dir().join("backup_restore.age");
+        let _ = fs::remove_file(&backup_path);
+        assert_eq!(vault.backup(&recipient, &backup_path).unwrap(), 2);
+        let _ = vault.backup(&recipient, &backup_path).unwrap_err();
+        let open_backup = || fs::File::open(&backup_path).unwrap();
+
+        // Restore the backup into a separate directory.
+        let restore_dir = test_dir().join("backup_restore_into");
+        refresh_test_fs("backup_restore_into");
+        let mut restored = Vault::restore_backup(
+            &identity,
+            open_backup(),
+            &restore_dir.join("dgruft.db"),
+            Some(&restore_dir),
+        )
+        .unwrap();
+        assert_eq!(restored.check(None).unwrap(), vec![]);
+        assert_eq!(fs::read_dir(&restore_dir).unwrap().count(), 3);
+        restored.load_credential("owner", "c1", &key).unwrap();
+        let (_, contents): (FileData, String) = restored.load_file("owner", "f1", &key).unwrap();
+        assert_eq!(contents, "contents 1");
+        let other = restored
+            .load_unlocked_account("other", password, None, None)
+            .unwrap();
+        let mut shared_contents = Vec::new();
+        restored
+            .export_shared_file(&other, "f1", &mut shared_contents)
+            .unwrap();
+        assert_eq!(shared_contents, b"contents 1");
+
+        // A backup which keeps its files in a directory can't become a single file.
+        let _ = Vault::restore_backup(
+            &identity,
+            open_backup(),
+            &test_dir().join("backup_restore_single.db"),
+            None,
+        )
+        .unwrap_err();
+
+        // Restoring over the vault undoes every change made since the backup.
+        vault.create_new_account("later", password, None).unwrap();
+        vault.delete_file("owner", "f1", &key).unwrap();
+        drop(vault);
+        let vault =
+            Vault::restore_backup(&identity, open_backup(), &db_path, Some(&fs_dir)).unwrap();
+        let _ = vault.load_account("later").unwrap_err();
+        assert!(!fs_dir.join("later").exists());
+        let (_, contents): (FileData, String) = vault.load_file("owner", "f1", &key).unwrap();
+        assert_eq!(contents, "contents 1");
+        assert_eq!(vault.check(None).unwrap(), vec![]);
+        assert!(fs::read_dir(test_dir()).unwrap().all(|entry| !entry
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .starts_with(&format!("{}.", db_name))));
+        drop(vault);
+
+        // Tampered archives & the wrong identity are rejected, leaving the vault untouched.
+        let mut tampered = fs::read(&backup_path).unwrap();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        let _ = Vault::restore_backup(&identity, tampered.as_slice(), &db_path, Some(&fs_dir))
+            .unwrap_err();
+        let wrong_identity = AgeIdentity::SecretKey(age::x25519::Identity::generate());
+        let _ = Vault::restore_backup(&wrong_identity, open_backup(), &db_path, Some(&fs_dir))
+            .unwrap_err();
+        let vault = Vault::connect(&db_path, &fs_dir).unwrap();
+        let (_, contents): (FileData, String) = vault.load_file("owner", "f1", &key).unwrap();
+        assert_eq!(contents, "contents 1");
+        assert_eq!(vault.check(None).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn backup_restore_single_file() {
+        let db_name = "backup_restore_single_file.db";
+        let db_path = db_path(db_name);
+        refresh_test_db(db_name);
+
+        let mut vault = Vault::connect_single_file(&db_path).unwrap();
+        let password = "open sesame!";
+        vault.create_new_account("owner", password, None).unwrap();
+        let key = vault
+            .load_unlocked_account("owner", password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+        vault
+            .create_file("f1", "owner", "contents 1", &key)
+            .unwrap();
+
+        // The files are all in the database, so none are archived.
+        let secret_key = age::x25519::Identity::generate();
+        let recipient: AgeRecipient = secret_key.to_public().to_string().parse().unwrap();
+        let identity = AgeIdentity::SecretKey(secret_key);
+        let backup_path = test_dir().join("backup_restore_single_file.age");
+        let _ = fs::remove_file(&backup_path);
+        assert_eq!(vault.backup(&recipient, &backup_path).unwrap(), 0);
+
+        let restored_path = test_dir().join("backup_restore_single_file_restored.db");
+        let _ = fs::remove_file(&restored_path);
+        let restored = Vault::restore_backup(
+            &identity,
+            fs::File::open(&backup_path).unwrap(),
+            &restored_path,
+            None,
+        )
+        .unwrap();
+        assert!(restored.is_single_file());
+        let (_, contents): (FileData, String) = restored.load_file("owner", "f1", &key).unwrap();
+        assert_eq!(contents, "contents 1");
+    }
+}
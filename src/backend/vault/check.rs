@@ -0,0 +1,597 @@
+//! Checking the coherency of a [Vault](super::Vault) & repairing the
+//! [VaultIssue](super::VaultIssue)s found.
+use std::{collections::HashSet, fs, io};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::{self, eyre};
+use rusqlite::types::Value;
+
+use super::{
+    super::{
+        account::{Account, UnlockedAccount},
+        credential::{Credential, CredentialField},
+        encryption::{associated_data::EntityType, stream::copy_secret},
+        file_data::{FileData, FileRevision},
+        secret::Secret,
+        sharing::{SharedCredential, SharedFile},
+    },
+    database::Database,
+    database_traits::credential_primary_key,
+    file_store::FileStore,
+    filesystem::{get_pending_file_path, get_replaced_file_path},
+    Vault, VaultIssue,
+};
+
+impl Vault {
+    /// Find any missing or orphaned directories, files & file revisions, & any shared copies which
+    /// outlived their originals.
+    ///
+    /// If an [UnlockedAccount] is given, its credentials & files are also checked for entries
+    /// which can't be decrypted with its key, & for credentials with the same name.
+    pub fn check(
+        &self,
+        unlocked_account: Option<&UnlockedAccount>,
+    ) -> eyre::Result<Vec<VaultIssue>> {
+        let accounts = self.database.select_all_entries::<Account>()?;
+        let credentials = self.database.select_all_entries::<Credential>()?;
+        let files_data = self.database.select_all_entries::<FileData>()?;
+        let shared_credentials = self.database.select_all_entries::<SharedCredential>()?;
+        let shared_files = self.database.select_all_entries::<SharedFile>()?;
+        let file_revisions = self.database.select_all_entries::<FileRevision>()?;
+        let conn = self.database.connection();
+        let mut issues = Vec::new();
+
+        // Every account has a file directory.
+        let account_names: HashSet<&str> =
+            accounts.iter().map(|account| account.username()).collect();
+        if let FileStore::Directory(filesystem_directory) = &self.file_store {
+            for account in &accounts {
+                let path = filesystem_directory.join(account.username());
+                if !path.is_dir() {
+                    issues.push(VaultIssue::MissingAccountDirectory {
+                        owner_username: account.username().to_owned(),
+                        path,
+                    });
+                }
+            }
+        }
+
+        // Every file & shared copy has its encrypted contents. Their pending & replaced siblings
+        // are resolved the next time their owner logs in.
+        let mut known_paths = HashSet::new();
+        let mut pending_file_ids = HashSet::new();
+        for file_data in &files_data {
+            let pending_path = get_pending_file_path(file_data.path(), file_data.id());
+            let replaced_path = get_replaced_file_path(file_data.path(), file_data.id());
+            if self.file_store.exists(conn, &pending_path)?
+                || self.file_store.exists(conn, &replaced_path)?
+            {
+                pending_file_ids.insert(file_data.id());
+            }
+            known_paths.insert(pending_path);
+            known_paths.insert(replaced_path);
+            known_paths.insert(file_data.path().to_owned());
+            if !self.file_store.exists(conn, file_data.path())? {
+                issues.push(VaultIssue::MissingFile {
+                    owner_username: file_data.owner_username().to_owned(),
+                    path: file_data.path().to_owned(),
+                });
+            }
+        }
+        for shared_file in &shared_files {
+            let file_data = shared_file.file_data();
+            known_paths.insert(get_pending_file_path(file_data.path(), file_data.id()));
+            known_paths.insert(file_data.path().to_owned());
+            if !files_data
+                .iter()
+                .any(|original| original.id() == shared_file.item_id())
+            {
+                issues.push(VaultIssue::OrphanedSharedCopy {
+                    owner_username: shared_file.owner_username().to_owned(),
+                    shared_id: shared_file.id().to_owned(),
+                    entity_type: EntityType::File,
+                });
+            } else if !self.file_store.exists(conn, file_data.path())? {
+                issues.push(VaultIssue::MissingSharedFile {
+                    owner_username: shared_file.owner_username().to_owned(),
+                    shared_id: shared_file.id().to_owned(),
+                    path: file_data.path().to_owned(),
+                });
+            }
+        }
+        // A file update moves the replaced contents to its revision after it's committed, so a
+        // revision of a file with a pending or replaced sibling may not have its contents yet.
+        for revision in &file_revisions {
+            known_paths.insert(get_pending_file_path(revision.path(), revision.file_id()));
+            known_paths.insert(revision.path().to_owned());
+            if !pending_file_ids.contains(revision.file_id())
+                && !self.file_store.exists(conn, revision.path())?
+            {
+                issues.push(VaultIssue::MissingFileRevision {
+                    owner_username: revision.owner_username().to_owned(),
+                    id: revision.id().to_owned(),
+                    path: revision.path().to_owned(),
+                });
+            }
+        }
+        for shared_credential in &shared_credentials {
+            if !credentials
+                .iter()
+                .any(|original| original.id() == shared_credential.item_id())
+            {
+                issues.push(VaultIssue::OrphanedSharedCopy {
+                    owner_username: shared_credential.owner_username().to_owned(),
+                    shared_id: shared_credential.id().to_owned(),
+                    entity_type: EntityType::Credential,
+                });
+            }
+        }
+
+        // Every directory belongs to an account, & every file in it to a file or shared copy.
+        match &self.file_store {
+            FileStore::Directory(filesystem_directory) => {
+                for entry in fs::read_dir(filesystem_directory)? {
+                    let entry = entry?;
+                    if !entry.file_type()?.is_dir() {
+                        continue;
+                    }
+                    let dir_path = Utf8PathBuf::try_from(entry.path())?;
+                    if !dir_path
+                        .file_name()
+                        .is_some_and(|name| account_names.contains(name))
+                    {
+                        issues.push(VaultIssue::OrphanedDirectory { path: dir_path });
+                        continue;
+                    }
+                    for file_entry in fs::read_dir(&dir_path)? {
+                        let path = Utf8PathBuf::try_from(file_entry?.path())?;
+                        if !known_paths.contains(&path) {
+                            issues.push(VaultIssue::OrphanedFile { path });
+                        }
+                    }
+                }
+            }
+            FileStore::Database => {
+                for path in FileStore::select_paths(conn)? {
+                    if !known_paths.contains(&path) {
+                        issues.push(VaultIssue::OrphanedFile { path });
+                    }
+                }
+            }
+        }
+
+        // The unlocked account's entries decrypt, & its credentials have unique names.
+        if let Some(unlocked_account) = unlocked_account {
+            self.check_account_entries(unlocked_account, &credentials, &files_data, &mut issues)?;
+        }
+
+        issues.sort();
+        Ok(issues)
+    }
+
+    // Helper function: Check that the credentials & files of an [UnlockedAccount] decrypt with
+    // its key, & that its credentials have unique names.
+    fn check_account_entries(
+        &self,
+        unlocked_account: &UnlockedAccount,
+        credentials: &[Credential],
+        files_data: &[FileData],
+        issues: &mut Vec<VaultIssue>,
+    ) -> eyre::Result<()> {
+        let username = unlocked_account.username();
+        let key = unlocked_account.key();
+        // Trashed credentials don't take up their names.
+        let trashed_ids = self.select_trashed_ids(username, EntityType::Credential)?;
+
+        let mut name_counts: Vec<(String, usize)> = Vec::new();
+        for credential in credentials
+            .iter()
+            .filter(|credential| credential.owner_username() == username)
+        {
+            let decrypted = credential.name::<String>(key).and_then(|name| {
+                credential.username::<Secret<String>>(key)?;
+                credential.password::<Secret<String>>(key)?;
+                credential.notes::<Secret<String>>(key)?;
+                Ok(name)
+            });
+            match decrypted {
+                Ok(_) if trashed_ids.contains(credential.id()) => {}
+                Ok(name) => match name_counts.iter_mut().find(|(other, _)| *other == name) {
+                    Some((_, count)) => *count += 1,
+                    None => name_counts.push((name, 1)),
+                },
+                Err(_) => issues.push(VaultIssue::UndecryptableCredential {
+                    owner_username: username.to_owned(),
+                    id: credential.id().to_owned(),
+                }),
+            }
+        }
+        for (name, count) in name_counts {
+            if count > 1 {
+                issues.push(VaultIssue::DuplicateCredentialName {
+                    owner_username: username.to_owned(),
+                    name,
+                    count,
+                });
+            }
+        }
+
+        for file_data in files_data
+            .iter()
+            .filter(|file_data| file_data.owner_username() == username)
+        {
+            // Missing files have already been reported.
+            let conn = self.database.connection();
+            if !self.file_store.exists(conn, file_data.path())? {
+                continue;
+            }
+            let is_decryptable = file_data
+                .filename::<Secret<String>>(key)
+                .and_then(|_| {
+                    let mut contents = Self::open_contents(
+                        &self.file_store,
+                        conn,
+                        file_data,
+                        file_data.path(),
+                        key,
+                        &file_data.contents_associated_data(),
+                    )?;
+                    Ok(copy_secret(&mut contents, &mut io::sink())?)
+                })
+                .is_ok();
+            if !is_decryptable {
+                issues.push(VaultIssue::UndecryptableFile {
+                    owner_username: username.to_owned(),
+                    path: file_data.path().to_owned(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Fix a [VaultIssue] found by [Vault::check]. See [VaultIssue] for how each is fixed.
+    ///
+    /// Renaming duplicate credentials requires their owner's [UnlockedAccount].
+    pub fn repair(
+        &mut self,
+        issue: &VaultIssue,
+        unlocked_account: Option<&UnlockedAccount>,
+    ) -> eyre::Result<()> {
+        match issue {
+            VaultIssue::MissingAccountDirectory { owner_username, .. } => {
+                self.file_store.new_account_dir(owner_username)
+            }
+            VaultIssue::OrphanedDirectory { path } => Ok(fs::remove_dir_all(path)?),
+            VaultIssue::OrphanedFile { path } => {
+                self.file_store.remove(self.database.connection(), path)
+            }
+            VaultIssue::MissingFile { path, .. } | VaultIssue::UndecryptableFile { path, .. } => {
+                let file_data = self
+                    .database
+                    .select_entry_err_none::<FileData, &Utf8Path, 1>([path])?;
+                self.remove_file(&file_data, true)
+            }
+            VaultIssue::MissingSharedFile { shared_id, .. } => {
+                self.remove_shared_copy(shared_id, EntityType::File)
+            }
+            VaultIssue::MissingFileRevision { id, .. } => {
+                let tx = self.database.open_transaction()?;
+                Database::transaction_delete::<FileRevision, &str, 1>([id], &tx)?;
+                Ok(tx.commit()?)
+            }
+            VaultIssue::OrphanedSharedCopy {
+                shared_id,
+                entity_type,
+                ..
+            } => self.remove_shared_copy(shared_id, *entity_type),
+            VaultIssue::UndecryptableCredential { owner_username, id } => {
+                let credential = self
+                    .select_account_credentials(owner_username)?
+                    .into_iter()
+                    .find(|credential| credential.id() == id)
+                    .ok_or_else(|| eyre!("No credential with id {} exists.", id))?;
+                self.remove_credential(&credential)
+            }
+            VaultIssue::DuplicateCredentialName {
+                owner_username,
+                name,
+                ..
+            } => match unlocked_account {
+                Some(unlocked_account) if unlocked_account.username() == owner_username => {
+                    self.rename_duplicate_credentials(unlocked_account, name)
+                }
+                _ => Err(eyre!(
+                    "Log in as the owner of credential \"{}\" to rename its duplicates.",
+                    name
+                )),
+            },
+        }
+    }
+
+    // Helper function: Delete the shared copy with the given id & stop sharing it.
+    pub(super) fn remove_shared_copy(
+        &mut self,
+        shared_id: &str,
+        entity_type: EntityType,
+    ) -> eyre::Result<()> {
+        let share_grants = self.database.select_share_grants(shared_id)?;
+        let shared_file = match entity_type {
+            EntityType::File => self
+                .database
+                .select_entry::<SharedFile, &str, 1>([shared_id])?,
+            _ => None,
+        };
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Stop sharing the shared copy & delete it.
+        Self::transaction_delete_share_grants(&share_grants, &tx)?;
+        match entity_type {
+            EntityType::File => {
+                Database::transaction_delete::<SharedFile, &str, 1>([shared_id], &tx)?
+            }
+            _ => Database::transaction_delete::<SharedCredential, &str, 1>([shared_id], &tx)?,
+        }
+        if let Some(shared_file) = shared_file {
+            self.file_store
+                .remove_if_exists(&tx, shared_file.file_data().path())?;
+        }
+        // Commit the database transaction.
+        Ok(tx.commit()?)
+    }
+
+    // Helper function: Rename all but one of the [UnlockedAccount]'s credentials with the given
+    // `name` to `"<name> (2)"`, `"<name> (3)"`, & so on. The credential which keeps the name is
+    // the one its name index points to, if any.
+    fn rename_duplicate_credentials(
+        &mut self,
+        unlocked_account: &UnlockedAccount,
+        name: &str,
+    ) -> eyre::Result<()> {
+        let username = unlocked_account.username();
+        let key = unlocked_account.key();
+        let credentials = self.select_account_credentials(username)?;
+        let mut names = credentials
+            .iter()
+            .map(|credential| credential.name::<String>(key))
+            .collect::<eyre::Result<HashSet<String>>>()?;
+        let trashed_ids = self.select_trashed_ids(username, EntityType::Credential)?;
+        let mut duplicates = Vec::new();
+        for credential in credentials {
+            if !trashed_ids.contains(credential.id()) && credential.name::<String>(key)? == name {
+                duplicates.push(credential);
+            }
+        }
+        duplicates.sort_by_key(|credential| credential.name_index().is_none());
+
+        let mut renamed = Vec::new();
+        let mut number = 2;
+        for credential in duplicates.iter().skip(1) {
+            let new_name = loop {
+                let new_name = format!("{} ({})", name, number);
+                number += 1;
+                if names.insert(new_name.clone()) {
+                    break new_name;
+                }
+            };
+            let renamed_credential = Credential::try_new_with_id(
+                credential.id(),
+                username,
+                key,
+                self.cipher_suite,
+                &new_name,
+                credential.username::<Secret<String>>(key)?.expose(),
+                credential.password::<Secret<String>>(key)?.expose(),
+                credential.notes::<Secret<String>>(key)?.expose(),
+            )?;
+            // Rename the shared copy too, if the credential is shared.
+            let shared_credential = self
+                .select_shared_credential(credential)?
+                .map(|shared_credential| {
+                    shared_credential.with_field(
+                        key,
+                        self.cipher_suite,
+                        CredentialField::Name,
+                        &new_name,
+                    )
+                })
+                .transpose()?;
+            renamed.push((credential, renamed_credential, shared_credential));
+        }
+
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Replace each duplicate with its renamed credential.
+        for (credential, renamed_credential, shared_credential) in renamed {
+            Database::transaction_delete::<Credential, Value, 2>(
+                credential_primary_key(username, credential.encrypted_name().cipherbytes()),
+                &tx,
+            )?;
+            Database::transaction_insert(renamed_credential, &tx)?;
+            if let Some(shared_credential) = shared_credential {
+                Database::transaction_delete::<SharedCredential, &str, 1>(
+                    [shared_credential.id()],
+                    &tx,
+                )?;
+                Database::transaction_insert(shared_credential, &tx)?;
+            }
+        }
+        // Commit the database transaction.
+        Ok(tx.commit()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rusqlite::types::Value;
+    use std::fs;
+
+    use super::super::{
+        super::{
+            credential::Credential,
+            encryption::{associated_data::EntityType, cipher_suite::CipherSuite},
+        },
+        database_traits::{credential_primary_key, CredentialUpdateField},
+        filesystem::get_account_file_dir,
+        tests::{db_path, fs_dir, refresh_test_db, refresh_test_fs},
+        Vault, VaultIssue,
+    };
+
+    #[test]
+    fn check_repair() {
+        let db_name = "check_repair.db";
+        let fs_name = "check_repair";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+
+        let username = "mr_test";
+        let password = "open sesame!";
+        vault.create_new_account(username, password, None).unwrap();
+        vault.create_new_account("other", password, None).unwrap();
+        let unlocked = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap();
+        let key = unlocked.key().clone();
+        for name in ["c1", "c2", "c3"] {
+            vault
+                .create_credential(username, &key, name, "u", "p", "n")
+                .unwrap();
+        }
+        for filename in ["f1", "f2", "f3"] {
+            vault
+                .create_file(filename, username, filename, &key)
+                .unwrap();
+        }
+        vault
+            .share_credential(username, "c3", &key, "other")
+            .unwrap();
+        vault.share_file(username, "f3", &key, "other").unwrap();
+        assert_eq!(vault.check(Some(&unlocked)).unwrap(), vec![]);
+
+        let account_dir = get_account_file_dir(&fs_dir, username).unwrap();
+        let c2 = vault.load_credential(username, "c2", &key).unwrap();
+        let c3 = vault.load_credential(username, "c3", &key).unwrap();
+        let f1 = vault.load_file_data(username, "f1", &key).unwrap();
+        let f2 = vault.load_file_data(username, "f2", &key).unwrap();
+        let shared_file = vault
+            .select_shared_file(&vault.load_file_data(username, "f3", &key).unwrap())
+            .unwrap()
+            .unwrap();
+        let shared_credential = vault.select_shared_credential(&c3).unwrap().unwrap();
+
+        // Break the vault in every way.
+        fs::remove_file(f1.path()).unwrap();
+        fs::write(f2.path(), b"not the encrypted contents").unwrap();
+        fs::remove_file(shared_file.file_data().path()).unwrap();
+        fs::write(account_dir.join("stray"), b"stray").unwrap();
+        fs::create_dir(fs_dir.join("ghost")).unwrap();
+        fs::remove_dir(fs_dir.join("other")).unwrap();
+        vault
+            .database
+            .update_entry::<Credential, Value, &[u8], 2, 1>(
+                credential_primary_key(username, c2.encrypted_name().cipherbytes()),
+                CredentialUpdateField::NotesCipherbytes,
+                [c2.encrypted_password().cipherbytes()],
+            )
+            .unwrap();
+        vault
+            .database
+            .delete_entry::<Credential, Value, 2>(credential_primary_key(
+                username,
+                c3.encrypted_name().cipherbytes(),
+            ))
+            .unwrap();
+        let duplicate = Credential::try_new(
+            username,
+            &key,
+            CipherSuite::default(),
+            "c1",
+            "u2",
+            "p2",
+            "n2",
+        )
+        .unwrap();
+        vault
+            .database
+            .insert_entry(Vault::without_name_index(duplicate.clone()))
+            .unwrap();
+
+        let mut expected = vec![
+            VaultIssue::MissingAccountDirectory {
+                owner_username: "other".to_owned(),
+                path: fs_dir.join("other"),
+            },
+            VaultIssue::OrphanedDirectory {
+                path: fs_dir.join("ghost"),
+            },
+            VaultIssue::OrphanedFile {
+                path: account_dir.join("stray"),
+            },
+            VaultIssue::MissingFile {
+                owner_username: username.to_owned(),
+                path: f1.path().to_owned(),
+            },
+            VaultIssue::MissingSharedFile {
+                owner_username: username.to_owned(),
+                shared_id: shared_file.id().to_owned(),
+                path: shared_file.file_data().path().to_owned(),
+            },
+            VaultIssue::OrphanedSharedCopy {
+                owner_username: username.to_owned(),
+                shared_id: shared_credential.id().to_owned(),
+                entity_type: EntityType::Credential,
+            },
+        ];
+        assert_eq!(vault.check(None).unwrap(), expected);
+        expected.extend([
+            VaultIssue::UndecryptableCredential {
+                owner_username: username.to_owned(),
+                id: c2.id().to_owned(),
+            },
+            VaultIssue::UndecryptableFile {
+                owner_username: username.to_owned(),
+                path: f2.path().to_owned(),
+            },
+            VaultIssue::DuplicateCredentialName {
+                owner_username: username.to_owned(),
+                name: "c1".to_owned(),
+                count: 2,
+            },
+        ]);
+        let issues = vault.check(Some(&unlocked)).unwrap();
+        assert_eq!(issues, expected);
+
+        // Renaming duplicates needs their owner.
+        let _ = vault.repair(issues.last().unwrap(), None).unwrap_err();
+        for issue in &issues {
+            vault.repair(issue, Some(&unlocked)).unwrap();
+        }
+        assert_eq!(vault.check(Some(&unlocked)).unwrap(), vec![]);
+
+        assert!(fs_dir.join("other").is_dir());
+        assert!(!fs_dir.join("ghost").exists());
+        let mut credential_names = vault
+            .load_account_credentials(username)
+            .unwrap()
+            .iter()
+            .map(|credential| credential.name::<String>(&key).unwrap())
+            .collect::<Vec<String>>();
+        credential_names.sort();
+        assert_eq!(credential_names, ["c1", "c1 (2)"]);
+        let renamed = vault.load_credential(username, "c1 (2)", &key).unwrap();
+        assert_eq!(renamed.id(), duplicate.id());
+        assert_eq!(renamed.password::<String>(&key).unwrap(), "p2");
+        let files_data = vault.load_account_files_data(username).unwrap();
+        assert_eq!(files_data.len(), 1);
+        assert_eq!(files_data[0].filename::<String>(&key).unwrap(), "f3");
+        let other = vault
+            .load_unlocked_account("other", password, None, None)
+            .unwrap();
+        assert!(vault.load_shared_credentials(&other).unwrap().is_empty());
+        assert!(vault.load_shared_files_data(&other).unwrap().is_empty());
+    }
+}
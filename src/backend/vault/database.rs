@@ -2,9 +2,24 @@ use std::{array::IntoIter, iter::Map};
 
 use camino::Utf8Path;
 use color_eyre::eyre::{self, eyre};
-use rusqlite::{config::DbConfig, params_from_iter, Connection, OpenFlags, Transaction};
-
-use super::{database_traits::*, sql_schemas::*};
+use rusqlite::{
+    config::DbConfig, params_from_iter, types::Value, Connection, OpenFlags, Transaction,
+};
+
+use super::{
+    super::{
+        credential::CredentialVersion, encryption::associated_data::EntityType,
+        file_data::FileRevision, sharing::ShareGrant, trash::TrashedItem,
+    },
+    database_traits::*,
+    migrations::{migrate, migrate_copy, schema_version, SCHEMA_VERSION},
+    sql_statements::{
+        ADVANCE_TOTP_STEP, DELETE_CREDENTIAL_HISTORY, DELETE_TRASHED_ITEM,
+        PRUNE_CREDENTIAL_HISTORY, SELECT_CREDENTIAL_HISTORY, SELECT_EXPIRED_TRASHED_ITEMS,
+        SELECT_FILE_REVISIONS_OF_FILE, SELECT_PRUNABLE_FILE_REVISIONS,
+        SELECT_RECIPIENT_SHARE_GRANTS, SELECT_SETTING, SELECT_SHARED_ITEM_GRANTS, UPSERT_SETTING,
+    },
+};
 
 #[derive(Debug)]
 pub struct Database {
@@ -12,27 +27,109 @@ pub struct Database {
     connection: Connection,
 }
 impl Database {
-    /// Open a new connection to the database at the given path.
+    /// Open a new connection to the database at the given path. Databases written by older
+    /// versions of `dgruft` are backed up & upgraded to the current schema version.
     pub fn connect<P>(path: P) -> eyre::Result<Self>
     where
         P: AsRef<Utf8Path>,
     {
+        Self::open(path.as_ref(), migrate)
+    }
+
+    /// Open a new connection to the copy of a database at the given path, such as one extracted
+    /// from a backup archive. Copies written by older versions of `dgruft` are upgraded to the
+    /// current schema version without being backed up.
+    pub fn connect_copy<P>(path: P) -> eyre::Result<Self>
+    where
+        P: AsRef<Utf8Path>,
+    {
+        Self::open(path.as_ref(), migrate_copy)
+    }
+
+    /// Open a new read-only connection to the database at the given path, which is neither
+    /// upgraded nor otherwise written to. Only databases of the current schema version can be
+    /// read.
+    pub fn connect_read_only<P>(path: P) -> eyre::Result<Self>
+    where
+        P: AsRef<Utf8Path>,
+    {
+        let path = path.as_ref();
         let connection = Connection::open_with_flags(
-            path.as_ref(),
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        let version = schema_version(&connection)?;
+        if version != SCHEMA_VERSION {
+            return Err(eyre!(
+                "The vault database at {:?} has schema version {}, but only schema version {} can be read without upgrading it.",
+                path,
+                version,
+                SCHEMA_VERSION
+            ));
+        }
+        Ok(Self { connection })
+    }
+
+    // Helper function: Open a new read-write connection to the database at the given path &
+    // bring its schema up to date with `upgrade`.
+    fn open(
+        path: &Utf8Path,
+        upgrade: fn(&mut Connection, &Utf8Path) -> eyre::Result<()>,
+    ) -> eyre::Result<Self> {
+        let mut connection = Connection::open_with_flags(
+            path,
             OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX,
         )?;
         connection.set_db_config(DbConfig::SQLITE_DBCONFIG_ENABLE_FKEY, true)?;
 
-        // Create tables iff they don't exist
-        connection.execute(CREATE_ACCOUNTS, ())?;
-        connection.execute(CREATE_CREDENTIALS, ())?;
-        connection.execute(CREATE_FILES_DATA, ())?;
+        // Bring the schema up to date.
+        upgrade(&mut connection, path)?;
 
         Ok(Self { connection })
     }
 
+    /// Return the schema version of the database.
+    pub fn schema_version(&self) -> eyre::Result<u32> {
+        schema_version(&self.connection)
+    }
+
+    /// Retrieve the value of the setting with the given name.
+    ///
+    /// Return [Ok<None>] if the setting has never been set.
+    pub fn select_setting(&self, name: &str) -> eyre::Result<Option<String>> {
+        let query_result = self
+            .connection
+            .query_row(SELECT_SETTING, [name], |row| row.get::<usize, String>(0));
+        match query_result {
+            Ok(value) => Ok(Some(value)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(err) => Err(eyre!("{err:?}")),
+        }
+    }
+
+    /// Set the value of the setting with the given name.
+    pub fn update_setting(&self, name: &str, value: &str) -> eyre::Result<()> {
+        self.connection.execute(UPSERT_SETTING, (name, value))?;
+        Ok(())
+    }
+
+    /// Set the value of the setting with the given name using the current [Transaction].
+    pub fn transaction_update_setting(
+        name: &str,
+        value: &str,
+        tx: &Transaction,
+    ) -> eyre::Result<()> {
+        tx.execute(UPSERT_SETTING, (name, value))?;
+        Ok(())
+    }
+
+    /// Return the SQLite [Connection] of the database.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
     /// Open a new database [Transaction].
-    pub fn open_transaction(&mut self) -> eyre::Result<Transaction> {
+    pub fn open_transaction(&mut self) -> eyre::Result<Transaction<'_>> {
         Ok(self.connection.transaction()?)
     }
 
@@ -45,7 +142,7 @@ impl Database {
     ) -> eyre::Result<Option<T>>
     where
         T: TryFromDatabase + HasSqlStatements,
-        U: IntoB64,
+        U: IntoSql,
     {
         let mut statement = self.connection.prepare(T::sql_select())?;
         let params = Self::get_params_iter(primary_key_arr);
@@ -67,7 +164,7 @@ impl Database {
     ) -> eyre::Result<T>
     where
         T: TryFromDatabase + HasSqlStatements,
-        U: IntoB64,
+        U: IntoSql,
     {
         match self.select_entry(primary_key_arr) {
             Ok(Some(entry)) => Ok(entry),
@@ -78,6 +175,29 @@ impl Database {
         }
     }
 
+    /// Select the entry owned by the given account with the given name index. Return [None] if no
+    /// entries were found.
+    pub fn select_entry_by_name_index<T, U, const N: usize>(
+        &self,
+        name_index_key_arr: [U; N],
+    ) -> eyre::Result<Option<T>>
+    where
+        T: TryFromDatabase + HasNameIndex,
+        U: IntoSql,
+    {
+        let mut statement = self.connection.prepare(T::sql_select_by_name_index())?;
+        let params = Self::get_params_iter(name_index_key_arr);
+
+        let query_result = statement.query_row(params_from_iter(params), |row| {
+            Ok(T::try_from_database(row))
+        });
+        match query_result {
+            Ok(entry) => Ok(Some(entry?)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(err) => Err(eyre!("{err:?}")),
+        }
+    }
+
     /// Select all entries with a given foreign key.
     pub fn select_owned_entries<T, U, const N: usize>(
         &self,
@@ -85,7 +205,7 @@ impl Database {
     ) -> eyre::Result<Vec<T>>
     where
         T: TryFromDatabase + OwnedByAccount,
-        U: IntoB64,
+        U: IntoSql,
     {
         let mut statement = self.connection.prepare(T::sql_select_owned())?;
         let params = Self::get_params_iter(foreign_key_arr);
@@ -100,6 +220,169 @@ impl Database {
         Ok(results)
     }
 
+    /// Select all [ShareGrant]s of the shared item with the given id.
+    pub fn select_share_grants(&self, shared_id: &str) -> eyre::Result<Vec<ShareGrant>> {
+        self.select_share_grants_with(SELECT_SHARED_ITEM_GRANTS, shared_id)
+    }
+
+    /// Select all [ShareGrant]s of items shared with the given account.
+    pub fn select_received_share_grants(
+        &self,
+        recipient_username: &str,
+    ) -> eyre::Result<Vec<ShareGrant>> {
+        self.select_share_grants_with(SELECT_RECIPIENT_SHARE_GRANTS, recipient_username)
+    }
+
+    // Helper function— select all [ShareGrant]s matching the given statement's only parameter.
+    fn select_share_grants_with(
+        &self,
+        select_statement: &str,
+        param: &str,
+    ) -> eyre::Result<Vec<ShareGrant>> {
+        let mut statement = self.connection.prepare(select_statement)?;
+        let rows = statement.query_map([param], |row| Ok(ShareGrant::try_from_database(row)))?;
+
+        let mut results = Vec::new();
+        for query_result in rows {
+            results.push(query_result??);
+        }
+        Ok(results)
+    }
+
+    /// Select all [CredentialVersion]s of the credential with the given owner & id, newest first.
+    pub fn select_credential_history(
+        &self,
+        owner_username: &str,
+        credential_id: &str,
+    ) -> eyre::Result<Vec<CredentialVersion>> {
+        let mut statement = self.connection.prepare(SELECT_CREDENTIAL_HISTORY)?;
+        let rows = statement.query_map([owner_username, credential_id], |row| {
+            Ok(CredentialVersion::try_from_database(row))
+        })?;
+
+        let mut results = Vec::new();
+        for query_result in rows {
+            results.push(query_result??);
+        }
+        Ok(results)
+    }
+
+    /// Delete all [CredentialVersion]s of the credential with the given owner & id using the
+    /// current [Transaction].
+    pub fn transaction_delete_credential_history(
+        owner_username: &str,
+        credential_id: &str,
+        tx: &Transaction,
+    ) -> eyre::Result<()> {
+        tx.execute(DELETE_CREDENTIAL_HISTORY, [owner_username, credential_id])?;
+        Ok(())
+    }
+
+    /// Delete all but the newest `limit` [CredentialVersion]s of each credential using the current
+    /// [Transaction].
+    pub fn transaction_prune_credential_history(limit: u32, tx: &Transaction) -> eyre::Result<()> {
+        tx.execute(PRUNE_CREDENTIAL_HISTORY, [limit])?;
+        Ok(())
+    }
+
+    /// Select all [FileRevision]s of the file with the given owner & id, newest first.
+    pub fn select_file_revisions(
+        &self,
+        owner_username: &str,
+        file_id: &str,
+    ) -> eyre::Result<Vec<FileRevision>> {
+        let mut statement = self.connection.prepare(SELECT_FILE_REVISIONS_OF_FILE)?;
+        let rows = statement.query_map([owner_username, file_id], |row| {
+            Ok(FileRevision::try_from_database(row))
+        })?;
+
+        let mut results = Vec::new();
+        for query_result in rows {
+            results.push(query_result??);
+        }
+        Ok(results)
+    }
+
+    /// Delete all but the newest `limit` [FileRevision]s of each file, along with any replaced
+    /// before the Unix time `replaced_before`, using the current [Transaction]. Return the deleted
+    /// [FileRevision]s, whose contents are left for the caller to delete.
+    pub fn transaction_prune_file_revisions(
+        limit: u32,
+        replaced_before: u64,
+        tx: &Transaction,
+    ) -> eyre::Result<Vec<FileRevision>> {
+        let mut statement = tx.prepare(SELECT_PRUNABLE_FILE_REVISIONS)?;
+        let rows = statement.query_map(
+            params_from_iter([limit.into_sql(), replaced_before.into_sql()]),
+            |row| Ok(FileRevision::try_from_database(row)),
+        )?;
+
+        let mut results = Vec::new();
+        for query_result in rows {
+            results.push(query_result??);
+        }
+        for revision in &results {
+            Self::transaction_delete::<FileRevision, &str, 1>([revision.id()], tx)?;
+        }
+        Ok(results)
+    }
+
+    /// Select all [TrashedItem]s of the given `owner_username` which were moved to the trash before
+    /// the Unix time `trashed_before`.
+    pub fn select_expired_trash(
+        &self,
+        owner_username: &str,
+        trashed_before: u64,
+    ) -> eyre::Result<Vec<TrashedItem>> {
+        let mut statement = self.connection.prepare(SELECT_EXPIRED_TRASHED_ITEMS)?;
+        let rows = statement.query_map(
+            [owner_username.into_sql(), trashed_before.into_sql()],
+            |row| Ok(TrashedItem::try_from_database(row)),
+        )?;
+
+        let mut results = Vec::new();
+        for query_result in rows {
+            results.push(query_result??);
+        }
+        Ok(results)
+    }
+
+    /// Take the item with the given owner, [EntityType] & id out of the trash using the current
+    /// [Transaction], if it's in the trash.
+    pub fn transaction_delete_trashed_item(
+        owner_username: &str,
+        entity_type: EntityType,
+        item_id: &str,
+        tx: &Transaction,
+    ) -> eyre::Result<()> {
+        tx.execute(
+            DELETE_TRASHED_ITEM,
+            params_from_iter([
+                owner_username.into_sql(),
+                entity_type.into_sql(),
+                item_id.into_sql(),
+            ]),
+        )?;
+        Ok(())
+    }
+
+    /// Record the given time step as the last one the given `owner_username` logged in with a TOTP
+    /// code from, using the current [Transaction].
+    ///
+    /// Return `false` & record nothing if the same or a later time step was already recorded, as
+    /// each TOTP code may only be used once.
+    pub fn transaction_advance_totp_step(
+        owner_username: &str,
+        step: u64,
+        tx: &Transaction,
+    ) -> eyre::Result<bool> {
+        let num_rows = tx.execute(
+            ADVANCE_TOTP_STEP,
+            params_from_iter([owner_username.into_sql(), step.into_sql()]),
+        )?;
+        Ok(num_rows == 1)
+    }
+
     /// Select all entries of a given type.
     pub fn select_all_entries<T>(&self) -> eyre::Result<Vec<T>>
     where
@@ -116,10 +399,11 @@ impl Database {
     }
 
     /// Delete a specific entry based on the given primary key.
+    #[cfg(test)]
     pub fn delete_entry<T, U, const N: usize>(&self, primary_key_arr: [U; N]) -> eyre::Result<()>
     where
         T: HasSqlStatements,
-        U: IntoB64,
+        U: IntoSql,
     {
         Self::connection_delete::<T, U, N>(primary_key_arr, &self.connection)
     }
@@ -131,7 +415,7 @@ impl Database {
     ) -> eyre::Result<()>
     where
         T: HasSqlStatements,
-        U: IntoB64,
+        U: IntoSql,
     {
         Self::connection_delete::<T, U, N>(primary_key_arr, tx)
     }
@@ -143,7 +427,7 @@ impl Database {
     ) -> eyre::Result<()>
     where
         T: HasSqlStatements,
-        U: IntoB64,
+        U: IntoSql,
     {
         let mut statement = conn.prepare(T::sql_delete())?;
         let params = Self::get_params_iter(primary_key_arr);
@@ -161,7 +445,7 @@ impl Database {
     pub fn insert_entry<T>(&self, entry: T) -> eyre::Result<()>
     where
         T: IntoDatabase + HasSqlStatements,
-        T::FixedSizeStringArray: rusqlite::Params,
+        T::FixedSizeValueArray: IntoIterator<Item = Value>,
     {
         Self::connection_insert::<T>(entry, &self.connection)
     }
@@ -170,7 +454,7 @@ impl Database {
     pub fn transaction_insert<T>(entry: T, tx: &Transaction) -> eyre::Result<()>
     where
         T: IntoDatabase + HasSqlStatements,
-        T::FixedSizeStringArray: rusqlite::Params,
+        T::FixedSizeValueArray: IntoIterator<Item = Value>,
     {
         Self::connection_insert::<T>(entry, tx)
     }
@@ -179,9 +463,11 @@ impl Database {
     fn connection_insert<T>(entry: T, conn: &Connection) -> eyre::Result<()>
     where
         T: IntoDatabase + HasSqlStatements,
-        T::FixedSizeStringArray: rusqlite::Params,
+        T::FixedSizeValueArray: IntoIterator<Item = Value>,
     {
-        let num_rows = conn.execute(T::sql_insert(), entry.into_database())?;
+        // rusqlite only implements [rusqlite::Params] for arrays of up to 32 entries, so the entries
+        // are passed as an iterator instead.
+        let num_rows = conn.execute(T::sql_insert(), params_from_iter(entry.into_database()))?;
         if num_rows == 0 {
             Err(eyre!("Failed to insert row."))
         } else if num_rows == 1 {
@@ -192,6 +478,7 @@ impl Database {
     }
 
     /// Update a specific entry. Returns the number of changed rows.
+    #[cfg(test)]
     pub fn update_entry<T, U, V, const N: usize, const M: usize>(
         &self,
         selector_arr: [U; N],
@@ -200,8 +487,8 @@ impl Database {
     ) -> eyre::Result<usize>
     where
         T: HasSqlStatements,
-        U: IntoB64 + Clone,
-        V: IntoB64,
+        U: IntoSql + Clone,
+        V: IntoSql,
     {
         Self::connection_update::<T, U, V, N, M>(
             selector_arr,
@@ -220,8 +507,8 @@ impl Database {
     ) -> eyre::Result<usize>
     where
         T: HasSqlStatements,
-        U: IntoB64 + Clone,
-        V: IntoB64,
+        U: IntoSql + Clone,
+        V: IntoSql,
     {
         Self::connection_update::<T, U, V, N, M>(selector_arr, update_field, new_values_arr, tx)
     }
@@ -235,20 +522,20 @@ impl Database {
     ) -> eyre::Result<usize>
     where
         T: HasSqlStatements,
-        U: IntoB64 + Clone,
-        V: IntoB64,
+        U: IntoSql + Clone,
+        V: IntoSql,
     {
         let mut statement = conn.prepare(T::sql_update(update_field))?;
         // Parameters format: The updated fields followed by the primary key appended on to the end.
         let mut params_vec = new_values_arr
             .into_iter()
-            .map(|e| e.into_b64())
-            .collect::<Vec<String>>();
+            .map(|e| e.into_sql())
+            .collect::<Vec<Value>>();
         params_vec.append(
             &mut selector_arr
                 .into_iter()
-                .map(|e| e.into_b64())
-                .collect::<Vec<String>>(),
+                .map(|e| e.into_sql())
+                .collect::<Vec<Value>>(),
         );
 
         let num_rows = statement.execute(params_from_iter(params_vec))?;
@@ -263,11 +550,11 @@ impl Database {
     // Helper function to get SQLite params from an array.
     fn get_params_iter<U, const N: usize>(
         params_arr: [U; N],
-    ) -> Map<IntoIter<U, N>, impl FnMut(U) -> String>
+    ) -> Map<IntoIter<U, N>, impl FnMut(U) -> Value>
     where
-        U: IntoB64,
+        U: IntoSql,
     {
-        params_arr.into_iter().map(|e| e.into_b64())
+        params_arr.into_iter().map(|e| e.into_sql())
     }
 }
 
@@ -276,19 +563,29 @@ mod tests {
     use std::{
         fs::{self, remove_file, File},
         io::Write,
+        time::Instant,
     };
 
+    use base64ct::{Base64, Encoding};
     use camino::{Utf8Path, Utf8PathBuf};
-    use pretty_assertions::assert_eq;
+    use pretty_assertions::{assert_eq, assert_ne};
+    use tempfile::TempDir;
 
     use super::{
         super::super::{
             account::Account,
-            credential::Credential,
-            encryption::encrypted::{
-                new_rand_key, Aes256Nonce, Encrypted, TryFromEncrypted, TryIntoEncrypted,
+            credential::{Credential, CredentialField},
+            encryption::{
+                cipher_suite::CipherSuite,
+                encrypted::{new_rand_key, Encrypted, Nonce, TryFromEncrypted, TryIntoEncrypted},
             },
-            file_data::FileData,
+            file_data::{FileData, Filename},
+            hashing::kdf::KdfParams,
+            key_file::KeyFile,
+        },
+        super::{
+            migrations::{migrate_to, SCHEMA_VERSION},
+            sql_statements::UPSERT_SETTING,
         },
         *,
     };
@@ -314,6 +611,24 @@ mod tests {
         Ok(())
     }
 
+    // Encode a value the way it was stored before schema version 3.
+    fn legacy_b64(value: Value) -> String {
+        match value {
+            Value::Blob(bytes) => Base64::encode_string(&bytes),
+            Value::Text(text) => Base64::encode_string(text.as_bytes()),
+            Value::Integer(int) => {
+                Base64::encode_string(&u32::try_from(int).unwrap().to_be_bytes())
+            }
+            _ => panic!("Unexpected database value {value:?}."),
+        }
+    }
+
+    // Get the `CREATE TABLE` statement of a table from before schema version 3.
+    // Get the path to a database in the given temporary directory.
+    fn temp_db_path(dir: &TempDir, name: &str) -> Utf8PathBuf {
+        Utf8PathBuf::try_from(dir.path().join(name)).unwrap()
+    }
+
     #[test]
     fn account_to_from() {
         let db_path = "tests/account_to_from.db";
@@ -329,6 +644,473 @@ mod tests {
         assert_eq!(account, loaded_account);
 
         assert_eq!(loaded_account.username(), username);
+
+        // Accounts requiring a key file keep their key file check.
+        let key_file = KeyFile::from_bytes(b"Mister Test's key file");
+        let username = "Mister Key File";
+        let (account, _) = Account::new_with_params(
+            username,
+            password,
+            Some(&key_file),
+            KdfParams::default(),
+            CipherSuite::default(),
+        )
+        .unwrap();
+
+        db.insert_entry(account.clone()).unwrap();
+        let loaded_account: Account = db.select_entry([username]).unwrap().unwrap();
+
+        assert_eq!(account, loaded_account);
+        assert!(loaded_account.requires_key_file());
+        let _ = loaded_account.unlock(password, None, None).unwrap_err();
+        let _ = loaded_account
+            .unlock(password, Some(&key_file), None)
+            .unwrap();
+    }
+
+    #[test]
+    fn legacy_accounts_table() {
+        // Upgrades leave backups beside the database.
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = &temp_db_path(&dir, "legacy_accounts_table.db");
+        let account = Account::new_legacy("legacy", "123", KdfParams::legacy_pbkdf2()).unwrap();
+        {
+            // Create the accounts table as it was before KDF parameters were stored.
+            let connection = Connection::open(db_path).unwrap();
+            connection
+                .execute(
+                    "CREATE TABLE accounts (
+                        username TEXT PRIMARY KEY,
+                        password_salt TEXT NOT NULL,
+                        dbl_hashed_password_hash TEXT NOT NULL,
+                        dbl_hashed_password_salt TEXT NOT NULL,
+                        encrypted_key_cipherbytes TEXT NOT NULL,
+                        encrypted_key_nonce TEXT NOT NULL
+                    )",
+                    (),
+                )
+                .unwrap();
+            let fields = account.clone().into_database().map(legacy_b64);
+            connection
+                .execute(
+                    "INSERT INTO accounts VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    rusqlite::params_from_iter(&fields[..6]),
+                )
+                .unwrap();
+        }
+
+        let db = Database::connect(db_path).unwrap();
+        let loaded_account: Account = db.select_entry(["legacy"]).unwrap().unwrap();
+        assert_eq!(loaded_account, account);
+        assert_eq!(loaded_account.kdf_params(), KdfParams::legacy_pbkdf2());
+        assert_eq!(loaded_account.encryption_version(), 0);
+        assert!(!loaded_account.requires_key_file());
+        assert!(loaded_account.recovery().is_none());
+        loaded_account.unlock("123", None, None).unwrap();
+    }
+
+    #[test]
+    fn legacy_missing_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = &temp_db_path(&dir, "legacy_missing_ids.db");
+        let account = Account::new_legacy("legacy", "123", KdfParams::legacy_pbkdf2()).unwrap();
+        {
+            // Create the tables as they were before ids were added.
+            let connection = Connection::open(db_path).unwrap();
+            connection
+                .execute(
+                    "CREATE TABLE accounts (
+                        username TEXT PRIMARY KEY,
+                        password_salt TEXT NOT NULL,
+                        dbl_hashed_password_hash TEXT NOT NULL,
+                        dbl_hashed_password_salt TEXT NOT NULL,
+                        encrypted_key_cipherbytes TEXT NOT NULL,
+                        encrypted_key_nonce TEXT NOT NULL
+                    )",
+                    (),
+                )
+                .unwrap();
+            connection
+                .execute(
+                    "CREATE TABLE credentials (
+                        owner_username TEXT NOT NULL,
+                        encrypted_name_cipherbytes TEXT NOT NULL,
+                        encrypted_name_nonce TEXT NOT NULL,
+                        encrypted_username_cipherbytes TEXT NOT NULL,
+                        encrypted_username_nonce TEXT NOT NULL,
+                        encrypted_password_cipherbytes TEXT NOT NULL,
+                        encrypted_password_nonce TEXT NOT NULL,
+                        encrypted_notes_cipherbytes TEXT NOT NULL,
+                        encrypted_notes_nonce TEXT NOT NULL,
+                        PRIMARY KEY(owner_username, encrypted_name_cipherbytes)
+                    )",
+                    (),
+                )
+                .unwrap();
+            connection
+                .execute(
+                    "CREATE TABLE files_data (
+                        path TEXT PRIMARY KEY,
+                        filename TEXT NOT NULL,
+                        owner_username TEXT NOT NULL,
+                        contents_nonce TEXT NOT NULL
+                    )",
+                    (),
+                )
+                .unwrap();
+            let fields = account.clone().into_database().map(legacy_b64);
+            connection
+                .execute(
+                    "INSERT INTO accounts VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    rusqlite::params_from_iter(&fields[..6]),
+                )
+                .unwrap();
+            for name in ["c1", "c2"] {
+                let fields = Credential::try_new(
+                    "legacy",
+                    &new_rand_key(),
+                    CipherSuite::Aes256Gcm,
+                    name,
+                    "u",
+                    "p",
+                    "n",
+                )
+                .unwrap()
+                .into_database()
+                .map(legacy_b64);
+                // Skip the id & cipher suite columns.
+                let legacy_fields = [1, 2, 3, 5, 6, 8, 9, 11, 12].map(|i| &fields[i]);
+                connection
+                    .execute(
+                        "INSERT INTO credentials VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                        rusqlite::params_from_iter(legacy_fields),
+                    )
+                    .unwrap();
+            }
+            for path in ["tests/f1", "tests/f2"] {
+                let fields = FileData::from_fields(
+                    String::new(),
+                    path.into(),
+                    Filename::Legacy(path.to_owned()),
+                    "legacy".to_owned(),
+                    Nonce::Aes256Gcm([0u8; 12]),
+                    0,
+                    None,
+                )
+                .into_database()
+                .map(legacy_b64);
+                connection
+                    .execute(
+                        "INSERT INTO files_data VALUES (?1, ?2, ?3, ?4)",
+                        rusqlite::params_from_iter(&fields[1..5]),
+                    )
+                    .unwrap();
+            }
+        }
+
+        let db = Database::connect(db_path).unwrap();
+        let credentials = db
+            .select_owned_entries::<Credential, &str, 1>(["legacy"])
+            .unwrap();
+        let files_data = db
+            .select_owned_entries::<FileData, &str, 1>(["legacy"])
+            .unwrap();
+        assert_eq!(credentials.len(), 2);
+        assert_eq!(files_data.len(), 2);
+        assert!(!credentials[0].id().is_empty());
+        assert!(!files_data[0].id().is_empty());
+        assert_ne!(credentials[0].id(), credentials[1].id());
+        assert_ne!(files_data[0].id(), files_data[1].id());
+        assert!(files_data
+            .iter()
+            .all(|file_data| matches!(file_data.stored_filename(), Filename::Legacy(_))));
+        assert_eq!(
+            files_data[0].filename::<String>(&new_rand_key()).unwrap(),
+            files_data[0].path().as_str()
+        );
+    }
+
+    #[test]
+    fn schema_migrations() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = &temp_db_path(&dir, "schema_migrations.db");
+        let backup_paths = || {
+            fs::read_dir(dir.path())
+                .unwrap()
+                .map(|entry| Utf8PathBuf::try_from(entry.unwrap().path()).unwrap())
+                .filter(|path| path.as_str().ends_with(".bak"))
+                .collect::<Vec<_>>()
+        };
+
+        // New databases are created at the current version, without a backup.
+        fs::File::create_new(db_path).unwrap();
+        let db = Database::connect(db_path).unwrap();
+        assert_eq!(schema_version(&db.connection).unwrap(), SCHEMA_VERSION);
+        assert!(backup_paths().is_empty());
+        let account = Account::new("mr_test", "123").unwrap();
+        db.insert_entry(account.clone()).unwrap();
+        drop(db);
+
+        // Databases from before schema versions were recorded are backed up & upgraded.
+        Connection::open(db_path)
+            .unwrap()
+            .pragma_update(None, "user_version", 0)
+            .unwrap();
+        let db = Database::connect(db_path).unwrap();
+        assert_eq!(schema_version(&db.connection).unwrap(), SCHEMA_VERSION);
+        assert_eq!(db.select_entry(["mr_test"]).unwrap(), Some(account.clone()));
+        assert_eq!(backup_paths().len(), 1);
+        drop(db);
+
+        // Up-to-date databases aren't backed up again.
+        let _ = Database::connect(db_path).unwrap();
+        assert_eq!(backup_paths().len(), 1);
+
+        // The backup is a working copy of the database.
+        let backup_path = backup_paths().remove(0);
+        assert!(backup_path
+            .file_name()
+            .unwrap()
+            .starts_with("schema_migrations.db.v0-"));
+        let backup = Connection::open(&backup_path).unwrap();
+        assert_eq!(schema_version(&backup).unwrap(), 0);
+        drop(backup);
+        let backup_db = Database::connect(&backup_path).unwrap();
+        assert_eq!(
+            backup_db.select_entry(["mr_test"]).unwrap(),
+            Some(account.clone())
+        );
+        drop(backup_db);
+        for backup_path in backup_paths() {
+            fs::remove_file(backup_path).unwrap();
+        }
+
+        // Read-only connections can't write, & refuse databases which need upgrading.
+        let db = Database::connect_read_only(db_path).unwrap();
+        assert_eq!(db.select_entry(["mr_test"]).unwrap(), Some(account.clone()));
+        let _ = db
+            .insert_entry(Account::new("mr_other", "123").unwrap())
+            .unwrap_err();
+        drop(db);
+        Connection::open(db_path)
+            .unwrap()
+            .pragma_update(None, "user_version", 0)
+            .unwrap();
+        let _ = Database::connect_read_only(db_path).unwrap_err();
+        assert_eq!(
+            schema_version(&Connection::open(db_path).unwrap()).unwrap(),
+            0
+        );
+
+        // Copies are upgraded without a backup.
+        let db = Database::connect_copy(db_path).unwrap();
+        assert_eq!(schema_version(&db.connection).unwrap(), SCHEMA_VERSION);
+        assert_eq!(db.select_entry(["mr_test"]).unwrap(), Some(account));
+        drop(db);
+        assert!(backup_paths().is_empty());
+
+        // Databases written by a newer version are refused & left alone.
+        Connection::open(db_path)
+            .unwrap()
+            .pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+        let err = Database::connect(db_path).unwrap_err();
+        assert!(err.to_string().contains("newer version of dgruft"));
+        assert_eq!(
+            schema_version(&Connection::open(db_path).unwrap()).unwrap(),
+            SCHEMA_VERSION + 1
+        );
+        assert!(backup_paths().is_empty());
+    }
+
+    #[test]
+    fn blob_columns_migration() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = &temp_db_path(&dir, "blob_columns_migration.db");
+        let username = "mr_b64";
+        let account = Account::new(username, "123").unwrap();
+        let key = new_rand_key();
+        let credential =
+            Credential::try_new(username, &key, CipherSuite::default(), "c", "u", "p", "n")
+                .unwrap();
+        let file_path = format!("{username}/f");
+        {
+            // Create a schema version 2 database, which stores every value as base-64 TEXT.
+            let mut connection = Connection::open(db_path).unwrap();
+            migrate_to(&mut connection, 2).unwrap();
+            connection
+                .execute(
+                    Account::sql_insert(),
+                    rusqlite::params_from_iter(account.clone().into_database().map(legacy_b64)),
+                )
+                .unwrap();
+            connection
+                .execute(
+                    Credential::sql_insert(),
+                    rusqlite::params_from_iter(credential.clone().into_database().map(legacy_b64)),
+                )
+                .unwrap();
+            connection
+                .execute(
+                    UPSERT_SETTING,
+                    (
+                        Base64::encode_string(b"cipher_suite"),
+                        Base64::encode_string(b"xchacha20-poly1305"),
+                    ),
+                )
+                .unwrap();
+            connection
+                .execute(
+                    "INSERT INTO file_contents (path, owner_username, contents) VALUES (?1, ?2, ?3)",
+                    (
+                        Base64::encode_string(file_path.as_bytes()),
+                        Base64::encode_string(username.as_bytes()),
+                        b"contents".to_vec(),
+                    ),
+                )
+                .unwrap();
+        }
+
+        let db = Database::connect(db_path).unwrap();
+        assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+        assert_eq!(db.select_entry([username]).unwrap(), Some(account));
+        let loaded_credential: Credential = db
+            .select_entry_by_name_index([
+                username.into_sql(),
+                credential.name_index().unwrap().into_sql(),
+            ])
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded_credential, credential);
+        assert_eq!(loaded_credential.password::<String>(&key).unwrap(), "p");
+        assert_eq!(
+            db.select_setting("cipher_suite").unwrap().as_deref(),
+            Some("xchacha20-poly1305")
+        );
+        let (path, owner_username): (String, String) = db
+            .connection
+            .query_row(
+                "SELECT path, owner_username FROM file_contents",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(path, file_path);
+        assert_eq!(owner_username, username);
+        let (path, chunk_index, contents): (String, u32, Vec<u8>) = db
+            .connection
+            .query_row(
+                "SELECT path, chunk_index, contents FROM file_chunks",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(path, file_path);
+        assert_eq!(chunk_index, 0);
+        assert_eq!(contents, b"contents");
+
+        // Every table is STRICT, so values of the wrong type are refused.
+        let non_strict_tables: u32 = db
+            .connection
+            .query_row(
+                "SELECT count(*) FROM pragma_table_list WHERE schema = 'main' AND type = 'table' AND strict = 0 AND name NOT LIKE 'sqlite_%'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(non_strict_tables, 0);
+        let _ = db
+            .update_entry::<Account, &str, &str, 1, 1>(
+                [username],
+                AccountUpdateField::PasswordSalt,
+                ["not bytes"],
+            )
+            .unwrap_err();
+
+        // Duplicate name indices are still refused, but credentials without one are allowed.
+        let _ = db.insert_entry(credential.clone()).unwrap_err();
+        for name in ["c2", "c3"] {
+            let unindexed =
+                Credential::try_new(username, &key, CipherSuite::default(), name, "", "", "")
+                    .unwrap();
+            db.insert_entry(Credential::from_fields(
+                unindexed.id().to_owned(),
+                unindexed.owner_username().to_owned(),
+                unindexed.encrypted_name().clone(),
+                unindexed.encrypted_username().clone(),
+                unindexed.encrypted_password().clone(),
+                unindexed.encrypted_notes().clone(),
+                None,
+            ))
+            .unwrap();
+        }
+    }
+
+    // Must be manually run; run with
+    // `cargo t --release list_large_account -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn list_large_account() {
+        const ENTRIES: usize = 10_000;
+        const RUNS: u32 = 20;
+
+        let db_path = "tests/list_large_account.db";
+        let mut db = refresh_test_db(db_path);
+        let username = "mr_big";
+        db.insert_entry(Account::new(username, "123").unwrap())
+            .unwrap();
+        let key = new_rand_key();
+        let tx = db.open_transaction().unwrap();
+        for i in 0..ENTRIES {
+            let name = format!("credential {i}");
+            let credential = Credential::try_new(
+                username,
+                &key,
+                CipherSuite::default(),
+                &name,
+                "username",
+                "password",
+                "notes",
+            )
+            .unwrap();
+            Database::transaction_insert(credential, &tx).unwrap();
+            let file_data = FileData::try_new(
+                "tests",
+                username,
+                &key,
+                CipherSuite::default(),
+                &format!("file {i}"),
+                Nonce::Aes256Gcm([0u8; 12]),
+                0,
+            )
+            .unwrap();
+            Database::transaction_insert(file_data, &tx).unwrap();
+        }
+        tx.commit().unwrap();
+
+        let start = Instant::now();
+        for _ in 0..RUNS {
+            let credentials = db
+                .select_owned_entries::<Credential, &str, 1>([username])
+                .unwrap();
+            assert_eq!(credentials.len(), ENTRIES);
+        }
+        let credentials_time = start.elapsed() / RUNS;
+        let start = Instant::now();
+        for _ in 0..RUNS {
+            let files_data = db
+                .select_owned_entries::<FileData, &str, 1>([username])
+                .unwrap();
+            assert_eq!(files_data.len(), ENTRIES);
+        }
+        let files_data_time = start.elapsed() / RUNS;
+
+        println!("Listing {ENTRIES} credentials took {credentials_time:?}.");
+        println!("Listing {ENTRIES} files took {files_data_time:?}.");
+        println!(
+            "Database size: {} KiB.",
+            fs::metadata(db_path).unwrap().len() / 1024
+        );
     }
 
     #[test]
@@ -345,8 +1127,16 @@ mod tests {
         let password = "blahblahblah";
         let notes = "dgruft很酷。";
 
-        let cred =
-            Credential::try_new(owner_username, key, name, username, password, notes).unwrap();
+        let cred = Credential::try_new(
+            owner_username,
+            &key,
+            CipherSuite::default(),
+            name,
+            username,
+            password,
+            notes,
+        )
+        .unwrap();
 
         // Trying to insert a credential without an existing, matching account should fail.
         let _ = db.insert_entry(cred.clone()).unwrap_err();
@@ -363,19 +1153,19 @@ mod tests {
 
         db.insert_entry(cred.clone()).unwrap();
         let loaded_cred: Credential = db
-            .select_entry([
-                cred.owner_username().as_bytes(),
+            .select_entry(credential_primary_key(
+                cred.owner_username(),
                 cred.encrypted_name().cipherbytes(),
-            ])
+            ))
             .unwrap()
             .unwrap();
 
         assert_eq!(cred, loaded_cred);
 
-        assert_eq!(loaded_cred.name::<String>(key).unwrap(), name);
-        assert_eq!(loaded_cred.username::<String>(key).unwrap(), username);
-        assert_eq!(loaded_cred.password::<String>(key).unwrap(), password);
-        assert_eq!(loaded_cred.notes::<String>(key).unwrap(), notes);
+        assert_eq!(loaded_cred.name::<String>(&key).unwrap(), name);
+        assert_eq!(loaded_cred.username::<String>(&key).unwrap(), username);
+        assert_eq!(loaded_cred.password::<String>(&key).unwrap(), password);
+        assert_eq!(loaded_cred.notes::<String>(&key).unwrap(), notes);
     }
 
     #[test]
@@ -383,7 +1173,7 @@ mod tests {
         let db_path = "tests/file_data_to_from.db";
         let db = refresh_test_db(db_path);
 
-        let path = Utf8PathBuf::from("src/backend/vault/database_traits.rs");
+        let dir = Utf8PathBuf::from("tests/");
         let filename = String::from("database_traits.rs");
         let owner_username = String::from("i'm da owner");
         let owner_password = "open sesame!";
@@ -393,29 +1183,38 @@ mod tests {
         let account = Account::new(&owner_username, owner_password).unwrap();
         db.insert_entry(account).unwrap();
 
-        let file_data = FileData::new(
-            path.clone(),
-            filename.clone(),
-            owner_username.clone(),
+        let file_data = FileData::try_new(
+            &dir,
+            &owner_username,
+            &key,
+            CipherSuite::default(),
+            &filename,
             contents_nonce,
-        );
+            0,
+        )
+        .unwrap();
+        let path = file_data.path().to_owned();
 
         db.insert_entry(file_data.clone()).unwrap();
-        let loaded_file_data = db.select_entry([&path]).unwrap().unwrap();
+        let loaded_file_data: FileData = db.select_entry([&path]).unwrap().unwrap();
 
         assert_eq!(file_data, loaded_file_data);
 
-        assert_eq!(path, loaded_file_data.path());
-        assert_eq!(filename, loaded_file_data.filename());
+        assert_eq!(dir.join(file_data.id()), loaded_file_data.path());
+        assert_eq!(filename, loaded_file_data.filename::<String>(&key).unwrap());
+        let _ = loaded_file_data
+            .filename::<String>(&new_rand_key())
+            .unwrap_err();
         assert_eq!(owner_username, loaded_file_data.owner_username());
         assert_eq!(contents_nonce, loaded_file_data.contents_nonce());
+        assert_eq!(0, loaded_file_data.contents_chunk_size());
 
         let decrypted_contents = String::try_decrypt(
             &Encrypted::from_fields(
                 encrypted_contents.cipherbytes().to_vec(),
                 loaded_file_data.contents_nonce(),
             ),
-            key,
+            &key,
         )
         .unwrap();
         assert_eq!(decrypted_contents, "test");
@@ -433,69 +1232,81 @@ mod tests {
 
         let accs = db.select_all_entries::<Account>().unwrap();
         assert_eq!(accs.len(), 2);
-        assert!(accs.iter().any(|e| *e == acc1));
-        assert!(accs.iter().any(|e| *e == acc2));
+        assert!(accs.contains(&acc1));
+        assert!(accs.contains(&acc2));
 
         let acc3 = Account::new("a3", "p3").unwrap();
         db.insert_entry(acc3.clone()).unwrap();
 
         let accs = db.select_all_entries::<Account>().unwrap();
         assert_eq!(accs.len(), 3);
-        assert!(accs.iter().any(|e| *e == acc1));
-        assert!(accs.iter().any(|e| *e == acc2));
-        assert!(accs.iter().any(|e| *e == acc3));
+        assert!(accs.contains(&acc1));
+        assert!(accs.contains(&acc2));
+        assert!(accs.contains(&acc3));
 
         db.delete_entry::<Account, &str, 1>(["a1"]).unwrap();
 
         let accs = db.select_all_entries::<Account>().unwrap();
         assert_eq!(accs.len(), 2);
-        assert!(accs.iter().any(|e| *e == acc2));
-        assert!(accs.iter().any(|e| *e == acc3));
-
-        let f21 = FileData::new(
-            Utf8PathBuf::from("tests/f21"),
-            "f21".to_owned(),
-            "a2".to_owned(),
-            [0u8; 12],
-        );
+        assert!(accs.contains(&acc2));
+        assert!(accs.contains(&acc3));
+
+        let f21 = FileData::try_new(
+            "tests",
+            "a2",
+            &new_rand_key(),
+            CipherSuite::default(),
+            "f21",
+            Nonce::Aes256Gcm([0u8; 12]),
+            0,
+        )
+        .unwrap();
         db.insert_entry(f21.clone()).unwrap();
 
-        let f31 = FileData::new(
-            Utf8PathBuf::from("tests/f31"),
-            "f31".to_owned(),
-            "a3".to_owned(),
-            [0u8; 12],
-        );
+        let f31 = FileData::try_new(
+            "tests",
+            "a3",
+            &new_rand_key(),
+            CipherSuite::default(),
+            "f31",
+            Nonce::Aes256Gcm([0u8; 12]),
+            0,
+        )
+        .unwrap();
         db.insert_entry(f31.clone()).unwrap();
 
         let files = db.select_all_entries::<FileData>().unwrap();
         assert_eq!(files.len(), 2);
-        assert!(files.iter().any(|e| *e == f21));
-        assert!(files.iter().any(|e| *e == f31));
-
-        let f32 = FileData::new(
-            Utf8PathBuf::from("tests/f32"),
-            "f32".to_owned(),
-            "a3".to_owned(),
-            [0u8; 12],
-        );
+        assert!(files.contains(&f21));
+        assert!(files.contains(&f31));
+
+        let f32 = FileData::try_new(
+            "tests",
+            "a3",
+            &new_rand_key(),
+            CipherSuite::default(),
+            "f32",
+            Nonce::Aes256Gcm([0u8; 12]),
+            0,
+        )
+        .unwrap();
         db.insert_entry(f32.clone()).unwrap();
 
         let files = db.select_all_entries::<FileData>().unwrap();
         assert_eq!(files.len(), 3);
-        assert!(files.iter().any(|e| *e == f21));
-        assert!(files.iter().any(|e| *e == f31));
-        assert!(files.iter().any(|e| *e == f32));
+        assert!(files.contains(&f21));
+        assert!(files.contains(&f31));
+        assert!(files.contains(&f32));
 
         db.delete_entry::<Account, &str, 1>(["a3"]).unwrap();
 
         let accs = db.select_all_entries::<Account>().unwrap();
         assert_eq!(accs.len(), 1);
-        assert!(accs.iter().any(|e| *e == acc2));
+        assert!(accs.contains(&acc2));
 
         let files = db.select_all_entries::<FileData>().unwrap();
         assert_eq!(files.len(), 1);
-        assert!(files.iter().any(|e| *e == f21));
+        assert!(files.contains(&f21));
 
         db.delete_entry::<Account, &str, 1>(["a2"]).unwrap();
 
@@ -523,45 +1334,69 @@ mod tests {
         let acc_2 = Account::new(uname_2, pwd_2).unwrap();
         db.insert_entry(acc_2.clone()).unwrap();
 
-        let filename_1_1 = "f_1_1";
-        let mut path_1_1 = dir.clone();
-        path_1_1.push(filename_1_1);
         let (contents_1_1, key_1_1) = "test".try_encrypt_new_key().unwrap();
-        let f_1_1 = FileData::new(
-            &path_1_1,
-            "f_1_1".to_string(),
-            uname_1.to_string(),
+        let f_1_1 = FileData::try_new(
+            &dir,
+            uname_1,
+            &key_1_1,
+            CipherSuite::default(),
+            "f_1_1",
             contents_1_1.nonce(),
-        );
+            0,
+        )
+        .unwrap();
+        let path_1_1 = f_1_1.path().to_owned();
         db.insert_entry(f_1_1.clone()).unwrap();
 
-        let filename_1_2 = "f_1_2";
-        let mut path_1_2 = dir.clone();
-        path_1_2.push(filename_1_2);
-        let contents_1_2 = "test".try_encrypt_with_key(key_1_1).unwrap();
-        let f_1_2 = FileData::new(
-            &path_1_2,
-            "f_1_2".to_string(),
-            uname_1.to_string(),
+        let contents_1_2 = "test".try_encrypt_with_key(&key_1_1).unwrap();
+        let f_1_2 = FileData::try_new(
+            &dir,
+            uname_1,
+            &key_1_1,
+            CipherSuite::default(),
+            "f_1_2",
             contents_1_2.nonce(),
-        );
+            0,
+        )
+        .unwrap();
+        let path_1_2 = f_1_2.path().to_owned();
         db.insert_entry(f_1_2.clone()).unwrap();
 
-        let filename_2_1 = "f_2_1";
-        let mut path_2_1 = dir.clone();
-        path_2_1.push(filename_2_1);
         let (contents_2_1, key_2_1) = "test".try_encrypt_new_key().unwrap();
-        let f_2_1 = FileData::new(
-            &path_2_1,
-            "f_2_1".to_string(),
-            uname_2.to_string(),
+        let f_2_1 = FileData::try_new(
+            &dir,
+            uname_2,
+            &key_2_1,
+            CipherSuite::default(),
+            "f_2_1",
             contents_2_1.nonce(),
-        );
+            0,
+        )
+        .unwrap();
+        let path_2_1 = f_2_1.path().to_owned();
         db.insert_entry(f_2_1.clone()).unwrap();
 
-        let cred_1 = Credential::try_new(uname_1, key_1_1, "cred_1", "u1", "p1", "").unwrap();
+        let cred_1 = Credential::try_new(
+            uname_1,
+            &key_1_1,
+            CipherSuite::default(),
+            "cred_1",
+            "u1",
+            "p1",
+            "",
+        )
+        .unwrap();
         db.insert_entry(cred_1.clone()).unwrap();
-        let cred_2 = Credential::try_new(uname_2, key_2_1, "cred_2", "u2", "p2", "").unwrap();
+        let cred_2 = Credential::try_new(
+            uname_2,
+            &key_2_1,
+            CipherSuite::default(),
+            "cred_2",
+            "u2",
+            "p2",
+            "",
+        )
+        .unwrap();
         db.insert_entry(cred_2.clone()).unwrap();
 
         assert!(db
@@ -573,10 +1408,10 @@ mod tests {
             .unwrap()
             .is_some());
         assert!(db
-            .select_entry::<Credential, &[u8], 2>([
-                cred_1.owner_username().as_bytes(),
+            .select_entry::<Credential, Value, 2>(credential_primary_key(
+                cred_1.owner_username(),
                 cred_1.encrypted_name().cipherbytes()
-            ])
+            ))
             .unwrap()
             .is_some());
 
@@ -589,10 +1424,10 @@ mod tests {
             .unwrap()
             .is_none());
         assert!(db
-            .select_entry::<Credential, &[u8], 2>([
-                cred_1.owner_username().as_bytes(),
+            .select_entry::<Credential, Value, 2>(credential_primary_key(
+                cred_1.owner_username(),
                 cred_1.encrypted_name().cipherbytes()
-            ])
+            ))
             .unwrap()
             .is_none());
         assert!(db
@@ -612,17 +1447,17 @@ mod tests {
             .unwrap()
             .is_some());
         assert!(db
-            .select_entry::<Credential, &[u8], 2>([
-                cred_2.owner_username().as_bytes(),
+            .select_entry::<Credential, Value, 2>(credential_primary_key(
+                cred_2.owner_username(),
                 cred_2.encrypted_name().cipherbytes()
-            ])
+            ))
             .unwrap()
             .is_some());
 
-        db.delete_entry::<Credential, &[u8], 2>([
-            cred_2.owner_username().as_bytes(),
+        db.delete_entry::<Credential, Value, 2>(credential_primary_key(
+            cred_2.owner_username(),
             cred_2.encrypted_name().cipherbytes(),
-        ])
+        ))
         .unwrap();
         assert!(db
             .select_entry::<Account, &str, 1>([uname_2])
@@ -633,10 +1468,10 @@ mod tests {
             .unwrap()
             .is_some());
         assert!(db
-            .select_entry::<Credential, &[u8], 2>([
-                cred_2.owner_username().as_bytes(),
+            .select_entry::<Credential, Value, 2>(credential_primary_key(
+                cred_2.owner_username(),
                 cred_2.encrypted_name().cipherbytes()
-            ])
+            ))
             .unwrap()
             .is_none());
 
@@ -651,10 +1486,10 @@ mod tests {
             .unwrap()
             .is_none());
         assert!(db
-            .select_entry::<Credential, &[u8], 2>([
-                cred_2.owner_username().as_bytes(),
+            .select_entry::<Credential, Value, 2>(credential_primary_key(
+                cred_2.owner_username(),
                 cred_2.encrypted_name().cipherbytes()
-            ])
+            ))
             .unwrap()
             .is_none());
     }
@@ -671,22 +1506,24 @@ mod tests {
         let account = Account::new(username, password).unwrap();
         db.insert_entry(account).unwrap();
 
-        let f_filename = "f";
-        let mut f_path = dir.clone();
-        f_path.push(f_filename);
         let f_contents = "this is my file.";
-        let (f_encrypted, _) = f_contents.try_encrypt_new_key().unwrap();
-        let f = FileData::new(
-            &f_path,
-            f_filename.to_owned(),
-            username.to_owned(),
+        let (f_encrypted, f_key) = f_contents.try_encrypt_new_key().unwrap();
+        let f = FileData::try_new(
+            &dir,
+            username,
+            &f_key,
+            CipherSuite::default(),
+            "f",
             f_encrypted.nonce(),
-        );
+            0,
+        )
+        .unwrap();
+        let f_path = f.path().to_owned();
         db.insert_entry(f).unwrap();
 
         let f_contents = "this is my file, her name is f.";
         let (f_encrypted, key) = f_contents.try_encrypt_new_key().unwrap();
-        db.update_entry::<FileData, &Utf8Path, Aes256Nonce, 1, 1>(
+        db.update_entry::<FileData, &Utf8Path, Nonce, 1, 1>(
             [&f_path],
             FileDataUpdateField::ContentsNonce,
             [f_encrypted.nonce()],
@@ -708,17 +1545,32 @@ mod tests {
         let c_username = "myusername";
         let c_password = "mypassword";
         let c_notes = "mynotes";
-        let c =
-            Credential::try_new(username, key, c_name, c_username, c_password, c_notes).unwrap();
-        let c_primary_key = [username.as_bytes(), c.encrypted_name().cipherbytes()];
+        let c = Credential::try_new(
+            username,
+            &key,
+            CipherSuite::default(),
+            c_name,
+            c_username,
+            c_password,
+            c_notes,
+        )
+        .unwrap();
+        let c_primary_key = credential_primary_key(username, c.encrypted_name().cipherbytes());
         db.insert_entry(c.clone()).unwrap();
 
         let c_username = "mynewusername";
-        let ec_username = c_username.try_encrypt_with_key(key).unwrap();
+        let ec_username = c
+            .encrypt_field(
+                &key,
+                CipherSuite::default(),
+                CredentialField::Username,
+                c_username,
+            )
+            .unwrap();
 
         assert_eq!(
-            db.update_entry::<Credential, &[u8], &[u8], 2, 1>(
-                c_primary_key,
+            db.update_entry::<Credential, Value, &[u8], 2, 1>(
+                c_primary_key.clone(),
                 CredentialUpdateField::UsernameCipherbytes,
                 [ec_username.cipherbytes()],
             )
@@ -726,8 +1578,8 @@ mod tests {
             1
         );
         assert_eq!(
-            db.update_entry::<Credential, &[u8], Aes256Nonce, 2, 1>(
-                c_primary_key,
+            db.update_entry::<Credential, Value, Nonce, 2, 1>(
+                c_primary_key.clone(),
                 CredentialUpdateField::UsernameNonce,
                 [ec_username.nonce()],
             )
@@ -736,14 +1588,14 @@ mod tests {
         );
 
         let _ = db
-            .update_entry::<Credential, &[u8], &[u8], 2, 1>(
-                [username.as_bytes(), b"DNE"],
+            .update_entry::<Credential, Value, &[u8], 2, 1>(
+                credential_primary_key(username, b"DNE"),
                 CredentialUpdateField::UsernameCipherbytes,
                 [b"DNE"],
             )
             .unwrap_err();
         let c: Credential = db.select_entry(c_primary_key).unwrap().unwrap();
-        assert_eq!(c.username::<String>(key).unwrap(), c_username);
+        assert_eq!(c.username::<String>(&key).unwrap(), c_username);
     }
 
     #[test]
@@ -855,9 +1707,6 @@ mod tests {
 
     #[test]
     fn rollback_update_fail() {
-        let file_path = Utf8PathBuf::from("tests/update-rollback-test");
-        let _ = delete_a_file(&file_path);
-
         let db_path = "tests/rollback_update_fail.db";
         let mut db = refresh_test_db(db_path);
 
@@ -869,15 +1718,20 @@ mod tests {
         let test_content1 = "this is my file.";
         let test_content2 = "this is my file, and i like it very much.";
 
-        let (encrypted_contents1, _) = test_content1.try_encrypt_new_key().unwrap();
+        let (encrypted_contents1, key) = test_content1.try_encrypt_new_key().unwrap();
         let (encrypted_contents2, _) = test_content2.try_encrypt_new_key().unwrap();
 
-        let file_data = FileData::new(
-            &file_path,
-            "update_rollback_test".to_owned(),
-            username.to_owned(),
+        let file_data = FileData::try_new(
+            "tests",
+            username,
+            &key,
+            CipherSuite::default(),
+            "update_rollback_test",
             encrypted_contents1.nonce(),
-        );
+            0,
+        )
+        .unwrap();
+        let file_path = file_data.path().to_owned();
         db.insert_entry(file_data).unwrap();
 
         {
@@ -893,7 +1747,7 @@ mod tests {
             }
 
             let tx = db.open_transaction().unwrap();
-            Database::transaction_update::<FileData, &Utf8Path, Aes256Nonce, 1, 1>(
+            Database::transaction_update::<FileData, &Utf8Path, Nonce, 1, 1>(
                 [&file_path],
                 FileDataUpdateField::ContentsNonce,
                 [encrypted_contents2.nonce()],
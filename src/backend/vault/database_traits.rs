@@ -1,17 +1,29 @@
 //! These traits determine how different types can be converted into types accepted by the `dgruft`
 //! database.
 
-use base64ct::{Base64, Encoding};
 use camino::{Utf8Path, Utf8PathBuf};
 use color_eyre::eyre::{self, eyre};
+use rusqlite::types::Value;
 
 use super::{
     super::{
         account::Account,
-        credential::Credential,
-        encryption::encrypted::{Aes256Key, Aes256Nonce, Encrypted},
-        file_data::FileData,
-        hashing::hashed::{Hash, Hashed, Salt},
+        credential::{Credential, CredentialVersion},
+        encryption::{
+            associated_data::EntityType,
+            cipher_suite::CipherSuite,
+            encrypted::{Encrypted, Nonce},
+        },
+        file_data::{FileData, FileRevision, Filename},
+        hashing::{
+            hashed::{Hashed, Salt},
+            kdf::{KdfAlgorithm, KdfParams},
+        },
+        key_file::KeyFileCheck,
+        recovery::AccountRecovery,
+        sharing::{AccountKeypair, ShareGrant, SharedCredential, SharedFile},
+        totp::TotpBackup,
+        trash::TrashedItem,
     },
     sql_statements::*,
 };
@@ -44,6 +56,33 @@ pub enum AccountUpdateField {
     DblHashedPasswordSalt,
     EncryptedKeyCipherbytes,
     EncryptedKeyNonce,
+    EncryptedKeyCipher,
+    KdfAlgorithm,
+    KdfMemoryKib,
+    KdfTimeCost,
+    KdfParallelism,
+    EncryptionVersion,
+    KeyFileSalt,
+    KeyFileHash,
+    RecoveryEncryptedKeyCipherbytes,
+    RecoveryEncryptedKeyNonce,
+    RecoveryEncryptedKeyCipher,
+    EncryptedRecoveryKeyCipherbytes,
+    EncryptedRecoveryKeyNonce,
+    EncryptedRecoveryKeyCipher,
+    EncryptedTotpSecretCipherbytes,
+    EncryptedTotpSecretNonce,
+    EncryptedTotpSecretCipher,
+    ShareEncryptedKeyCipherbytes,
+    ShareEncryptedKeyNonce,
+    ShareEncryptedKeyCipher,
+    EncryptedShareKeyCipherbytes,
+    EncryptedShareKeyNonce,
+    EncryptedShareKeyCipher,
+    PublicKey,
+    EncryptedPrivateKeyCipherbytes,
+    EncryptedPrivateKeyNonce,
+    EncryptedPrivateKeyCipher,
 }
 impl HasSqlStatements for Account {
     type UpdateField = AccountUpdateField;
@@ -71,6 +110,65 @@ impl HasSqlStatements for Account {
             AccountUpdateField::DblHashedPasswordSalt => UPDATE_ACCOUNT_DBL_HASHED_PASSWORD_SALT,
             AccountUpdateField::EncryptedKeyCipherbytes => UPDATE_ACCOUNT_ENCRYPTED_KEY_CIPHERBYTES,
             AccountUpdateField::EncryptedKeyNonce => UPDATE_ACCOUNT_ENCRYPTED_KEY_NONCE,
+            AccountUpdateField::EncryptedKeyCipher => UPDATE_ACCOUNT_ENCRYPTED_KEY_CIPHER,
+            AccountUpdateField::KdfAlgorithm => UPDATE_ACCOUNT_KDF_ALGORITHM,
+            AccountUpdateField::KdfMemoryKib => UPDATE_ACCOUNT_KDF_MEMORY_KIB,
+            AccountUpdateField::KdfTimeCost => UPDATE_ACCOUNT_KDF_TIME_COST,
+            AccountUpdateField::KdfParallelism => UPDATE_ACCOUNT_KDF_PARALLELISM,
+            AccountUpdateField::EncryptionVersion => UPDATE_ACCOUNT_ENCRYPTION_VERSION,
+            AccountUpdateField::KeyFileSalt => UPDATE_ACCOUNT_KEY_FILE_SALT,
+            AccountUpdateField::KeyFileHash => UPDATE_ACCOUNT_KEY_FILE_HASH,
+            AccountUpdateField::RecoveryEncryptedKeyCipherbytes => {
+                UPDATE_ACCOUNT_RECOVERY_ENCRYPTED_KEY_CIPHERBYTES
+            }
+            AccountUpdateField::RecoveryEncryptedKeyNonce => {
+                UPDATE_ACCOUNT_RECOVERY_ENCRYPTED_KEY_NONCE
+            }
+            AccountUpdateField::RecoveryEncryptedKeyCipher => {
+                UPDATE_ACCOUNT_RECOVERY_ENCRYPTED_KEY_CIPHER
+            }
+            AccountUpdateField::EncryptedRecoveryKeyCipherbytes => {
+                UPDATE_ACCOUNT_ENCRYPTED_RECOVERY_KEY_CIPHERBYTES
+            }
+            AccountUpdateField::EncryptedRecoveryKeyNonce => {
+                UPDATE_ACCOUNT_ENCRYPTED_RECOVERY_KEY_NONCE
+            }
+            AccountUpdateField::EncryptedRecoveryKeyCipher => {
+                UPDATE_ACCOUNT_ENCRYPTED_RECOVERY_KEY_CIPHER
+            }
+            AccountUpdateField::EncryptedTotpSecretCipherbytes => {
+                UPDATE_ACCOUNT_ENCRYPTED_TOTP_SECRET_CIPHERBYTES
+            }
+            AccountUpdateField::EncryptedTotpSecretNonce => {
+                UPDATE_ACCOUNT_ENCRYPTED_TOTP_SECRET_NONCE
+            }
+            AccountUpdateField::EncryptedTotpSecretCipher => {
+                UPDATE_ACCOUNT_ENCRYPTED_TOTP_SECRET_CIPHER
+            }
+            AccountUpdateField::ShareEncryptedKeyCipherbytes => {
+                UPDATE_ACCOUNT_SHARE_ENCRYPTED_KEY_CIPHERBYTES
+            }
+            AccountUpdateField::ShareEncryptedKeyNonce => UPDATE_ACCOUNT_SHARE_ENCRYPTED_KEY_NONCE,
+            AccountUpdateField::ShareEncryptedKeyCipher => {
+                UPDATE_ACCOUNT_SHARE_ENCRYPTED_KEY_CIPHER
+            }
+            AccountUpdateField::EncryptedShareKeyCipherbytes => {
+                UPDATE_ACCOUNT_ENCRYPTED_SHARE_KEY_CIPHERBYTES
+            }
+            AccountUpdateField::EncryptedShareKeyNonce => UPDATE_ACCOUNT_ENCRYPTED_SHARE_KEY_NONCE,
+            AccountUpdateField::EncryptedShareKeyCipher => {
+                UPDATE_ACCOUNT_ENCRYPTED_SHARE_KEY_CIPHER
+            }
+            AccountUpdateField::PublicKey => UPDATE_ACCOUNT_PUBLIC_KEY,
+            AccountUpdateField::EncryptedPrivateKeyCipherbytes => {
+                UPDATE_ACCOUNT_ENCRYPTED_PRIVATE_KEY_CIPHERBYTES
+            }
+            AccountUpdateField::EncryptedPrivateKeyNonce => {
+                UPDATE_ACCOUNT_ENCRYPTED_PRIVATE_KEY_NONCE
+            }
+            AccountUpdateField::EncryptedPrivateKeyCipher => {
+                UPDATE_ACCOUNT_ENCRYPTED_PRIVATE_KEY_CIPHER
+            }
         }
     }
 }
@@ -80,10 +178,14 @@ impl HasSqlStatements for Account {
 pub enum CredentialUpdateField {
     UsernameCipherbytes,
     UsernameNonce,
+    UsernameCipher,
     PasswordCipherbytes,
     PasswordNonce,
+    PasswordCipher,
     NotesCipherbytes,
     NotesNonce,
+    NotesCipher,
+    NameIndex,
 }
 impl HasSqlStatements for Credential {
     type UpdateField = CredentialUpdateField;
@@ -110,21 +212,33 @@ impl HasSqlStatements for Credential {
                 UPDATE_CREDENTIAL_ENCRYPTED_USERNAME_CIPHERBYTES
             }
             CredentialUpdateField::UsernameNonce => UPDATE_CREDENTIAL_ENCRYPTED_USERNAME_NONCE,
+            CredentialUpdateField::UsernameCipher => UPDATE_CREDENTIAL_ENCRYPTED_USERNAME_CIPHER,
             CredentialUpdateField::PasswordCipherbytes => {
                 UPDATE_CREDENTIAL_ENCRYPTED_PASSWORD_CIPHERBYTES
             }
             CredentialUpdateField::PasswordNonce => UPDATE_CREDENTIAL_ENCRYPTED_PASSWORD_NONCE,
+            CredentialUpdateField::PasswordCipher => UPDATE_CREDENTIAL_ENCRYPTED_PASSWORD_CIPHER,
             CredentialUpdateField::NotesCipherbytes => {
                 UPDATE_CREDENTIAL_ENCRYPTED_NOTES_CIPHERBYTES
             }
             CredentialUpdateField::NotesNonce => UPDATE_CREDENTIAL_ENCRYPTED_NOTES_NONCE,
+            CredentialUpdateField::NotesCipher => UPDATE_CREDENTIAL_ENCRYPTED_NOTES_CIPHER,
+            CredentialUpdateField::NameIndex => UPDATE_CREDENTIAL_NAME_INDEX,
         }
     }
 }
 
 /// All the fields of [FileData] entries that may be updated.
+#[allow(clippy::enum_variant_names)]
 pub enum FileDataUpdateField {
+    Path,
     ContentsNonce,
+    ContentsCipher,
+    ContentsChunkSize,
+    FilenameCipherbytes,
+    FilenameNonce,
+    FilenameCipher,
+    NameIndex,
 }
 impl HasSqlStatements for FileData {
     type UpdateField = FileDataUpdateField;
@@ -147,64 +261,474 @@ impl HasSqlStatements for FileData {
 
     fn sql_update(field: Self::UpdateField) -> &'static str {
         match field {
+            FileDataUpdateField::Path => UPDATE_FILE_DATA_PATH,
             FileDataUpdateField::ContentsNonce => UPDATE_FILE_DATA_CONTENTS_NONCE,
+            FileDataUpdateField::ContentsCipher => UPDATE_FILE_DATA_CONTENTS_CIPHER,
+            FileDataUpdateField::ContentsChunkSize => UPDATE_FILE_DATA_CONTENTS_CHUNK_SIZE,
+            FileDataUpdateField::FilenameCipherbytes => {
+                UPDATE_FILE_DATA_ENCRYPTED_FILENAME_CIPHERBYTES
+            }
+            FileDataUpdateField::FilenameNonce => UPDATE_FILE_DATA_ENCRYPTED_FILENAME_NONCE,
+            FileDataUpdateField::FilenameCipher => UPDATE_FILE_DATA_ENCRYPTED_FILENAME_CIPHER,
+            FileDataUpdateField::NameIndex => UPDATE_FILE_DATA_NAME_INDEX,
+        }
+    }
+}
+
+/// [CredentialVersion] entries have no fields that may be updated. They are only inserted &
+/// deleted.
+pub enum CredentialVersionUpdateField {}
+impl HasSqlStatements for CredentialVersion {
+    type UpdateField = CredentialVersionUpdateField;
+
+    fn sql_insert() -> &'static str {
+        INSERT_CREDENTIAL_VERSION
+    }
+
+    fn sql_delete() -> &'static str {
+        DELETE_CREDENTIAL_VERSION
+    }
+
+    fn sql_select() -> &'static str {
+        SELECT_CREDENTIAL_VERSION
+    }
+
+    fn sql_select_all() -> &'static str {
+        SELECT_ALL_CREDENTIAL_VERSIONS
+    }
+
+    fn sql_update(field: Self::UpdateField) -> &'static str {
+        match field {}
+    }
+}
+
+/// All the fields of [FileRevision] entries that may be updated.
+#[allow(clippy::enum_variant_names)]
+pub enum FileRevisionUpdateField {
+    Path,
+    ContentsNonce,
+    ContentsCipher,
+    ContentsChunkSize,
+}
+impl HasSqlStatements for FileRevision {
+    type UpdateField = FileRevisionUpdateField;
+
+    fn sql_insert() -> &'static str {
+        INSERT_FILE_REVISION
+    }
+
+    fn sql_delete() -> &'static str {
+        DELETE_FILE_REVISION
+    }
+
+    fn sql_select() -> &'static str {
+        SELECT_FILE_REVISION
+    }
+
+    fn sql_select_all() -> &'static str {
+        SELECT_ALL_FILE_REVISIONS
+    }
+
+    fn sql_update(field: Self::UpdateField) -> &'static str {
+        match field {
+            FileRevisionUpdateField::Path => UPDATE_FILE_REVISION_PATH,
+            FileRevisionUpdateField::ContentsNonce => UPDATE_FILE_REVISION_CONTENTS_NONCE,
+            FileRevisionUpdateField::ContentsCipher => UPDATE_FILE_REVISION_CONTENTS_CIPHER,
+            FileRevisionUpdateField::ContentsChunkSize => UPDATE_FILE_REVISION_CONTENTS_CHUNK_SIZE,
         }
     }
 }
 
-/// This trait defines how the given struct gets converted into an array of base-64-encoded strings
-/// for storage in the database.
+/// [TotpBackup] entries have no fields that may be updated. They are only inserted & deleted.
+pub enum TotpBackupUpdateField {}
+impl HasSqlStatements for TotpBackup {
+    type UpdateField = TotpBackupUpdateField;
+
+    fn sql_insert() -> &'static str {
+        INSERT_TOTP_BACKUP
+    }
+
+    fn sql_delete() -> &'static str {
+        DELETE_TOTP_BACKUP
+    }
+
+    fn sql_select() -> &'static str {
+        SELECT_TOTP_BACKUP
+    }
+
+    fn sql_select_all() -> &'static str {
+        SELECT_ALL_TOTP_BACKUPS
+    }
+
+    fn sql_update(field: Self::UpdateField) -> &'static str {
+        match field {}
+    }
+}
+
+/// [SharedCredential] entries have no fields that may be updated. They are only inserted & deleted.
+pub enum SharedCredentialUpdateField {}
+impl HasSqlStatements for SharedCredential {
+    type UpdateField = SharedCredentialUpdateField;
+
+    fn sql_insert() -> &'static str {
+        INSERT_SHARED_CREDENTIAL
+    }
+
+    fn sql_delete() -> &'static str {
+        DELETE_SHARED_CREDENTIAL
+    }
+
+    fn sql_select() -> &'static str {
+        SELECT_SHARED_CREDENTIAL
+    }
+
+    fn sql_select_all() -> &'static str {
+        SELECT_ALL_SHARED_CREDENTIALS
+    }
+
+    fn sql_update(field: Self::UpdateField) -> &'static str {
+        match field {}
+    }
+}
+
+/// Only the path of a [SharedFile] entry may be updated. Otherwise, they are only inserted &
+/// deleted.
+pub enum SharedFileUpdateField {
+    Path,
+}
+impl HasSqlStatements for SharedFile {
+    type UpdateField = SharedFileUpdateField;
+
+    fn sql_insert() -> &'static str {
+        INSERT_SHARED_FILE_DATA
+    }
+
+    fn sql_delete() -> &'static str {
+        DELETE_SHARED_FILE_DATA
+    }
+
+    fn sql_select() -> &'static str {
+        SELECT_SHARED_FILE_DATA
+    }
+
+    fn sql_select_all() -> &'static str {
+        SELECT_ALL_SHARED_FILES_DATA
+    }
+
+    fn sql_update(field: Self::UpdateField) -> &'static str {
+        match field {
+            SharedFileUpdateField::Path => UPDATE_SHARED_FILE_DATA_PATH,
+        }
+    }
+}
+
+/// [ShareGrant] entries have no fields that may be updated. They are only inserted & deleted.
+pub enum ShareGrantUpdateField {}
+impl HasSqlStatements for ShareGrant {
+    type UpdateField = ShareGrantUpdateField;
+
+    fn sql_insert() -> &'static str {
+        INSERT_SHARE_GRANT
+    }
+
+    fn sql_delete() -> &'static str {
+        DELETE_SHARE_GRANT
+    }
+
+    fn sql_select() -> &'static str {
+        SELECT_SHARE_GRANT
+    }
+
+    fn sql_select_all() -> &'static str {
+        SELECT_ALL_SHARE_GRANTS
+    }
+
+    fn sql_update(field: Self::UpdateField) -> &'static str {
+        match field {}
+    }
+}
+
+/// [TrashedItem] entries have no fields that may be updated. They are only inserted & deleted.
+pub enum TrashedItemUpdateField {}
+impl HasSqlStatements for TrashedItem {
+    type UpdateField = TrashedItemUpdateField;
+
+    fn sql_insert() -> &'static str {
+        INSERT_TRASHED_ITEM
+    }
+
+    fn sql_delete() -> &'static str {
+        DELETE_TRASHED_ITEM
+    }
+
+    fn sql_select() -> &'static str {
+        SELECT_TRASHED_ITEM
+    }
+
+    fn sql_select_all() -> &'static str {
+        SELECT_ALL_TRASHED_ITEMS
+    }
+
+    fn sql_update(field: Self::UpdateField) -> &'static str {
+        match field {}
+    }
+}
+
+/// This trait defines how the given struct gets converted into an array of SQLite values for
+/// storage in the database.
 pub trait IntoDatabase {
-    type FixedSizeStringArray;
+    type FixedSizeValueArray;
 
     /// This function determines how the struct gets converted into the database format.
-    fn into_database(self) -> Self::FixedSizeStringArray;
+    fn into_database(self) -> Self::FixedSizeValueArray;
 }
 
 // Implementations
 impl IntoDatabase for Account {
-    type FixedSizeStringArray = [String; 6];
-    fn into_database(self) -> Self::FixedSizeStringArray {
+    type FixedSizeValueArray = [Value; 33];
+    fn into_database(self) -> Self::FixedSizeValueArray {
+        let kdf_params = self.kdf_params();
+        let key_file_check = self.key_file_check();
+        let recovery = self.recovery();
+        let [recovery_encrypted_key_cipherbytes, recovery_encrypted_key_nonce, recovery_encrypted_key_cipher] =
+            optional_encrypted_into_db(recovery.map(|recovery| recovery.encrypted_key()));
+        let [encrypted_recovery_key_cipherbytes, encrypted_recovery_key_nonce, encrypted_recovery_key_cipher] =
+            optional_encrypted_into_db(recovery.map(|recovery| recovery.encrypted_recovery_key()));
+        let [encrypted_totp_secret_cipherbytes, encrypted_totp_secret_nonce, encrypted_totp_secret_cipher] =
+            optional_encrypted_into_db(self.encrypted_totp_secret());
+        let share_recovery = self.share_recovery();
+        let [share_encrypted_key_cipherbytes, share_encrypted_key_nonce, share_encrypted_key_cipher] =
+            optional_encrypted_into_db(share_recovery.map(|recovery| recovery.encrypted_key()));
+        let [encrypted_share_key_cipherbytes, encrypted_share_key_nonce, encrypted_share_key_cipher] =
+            optional_encrypted_into_db(
+                share_recovery.map(|recovery| recovery.encrypted_recovery_key()),
+            );
+        let keypair = self.keypair();
+        let [encrypted_private_key_cipherbytes, encrypted_private_key_nonce, encrypted_private_key_cipher] =
+            optional_encrypted_into_db(keypair.map(|keypair| keypair.encrypted_private_key()));
         [
-            self.username().into_b64(),
-            self.password_salt().into_b64(),
-            self.dbl_hashed_password().hash().into_b64(),
-            self.dbl_hashed_password().salt().into_b64(),
-            self.encrypted_key().cipherbytes().into_b64(),
-            self.encrypted_key().nonce().into_b64(),
+            self.username().into_sql(),
+            self.password_salt().into_sql(),
+            self.dbl_hashed_password().hash().into_sql(),
+            self.dbl_hashed_password().salt().into_sql(),
+            self.encrypted_key().cipherbytes().into_sql(),
+            self.encrypted_key().nonce().into_sql(),
+            self.encrypted_key().cipher_suite().into_sql(),
+            kdf_params.algorithm().as_str().into_sql(),
+            kdf_params.memory_kib().into_sql(),
+            kdf_params.time_cost().into_sql(),
+            kdf_params.parallelism().into_sql(),
+            self.encryption_version().into_sql(),
+            key_file_check
+                .map_or(&[][..], |check| check.salt())
+                .into_sql(),
+            key_file_check
+                .map_or(&[][..], |check| check.hash())
+                .into_sql(),
+            recovery_encrypted_key_cipherbytes,
+            recovery_encrypted_key_nonce,
+            recovery_encrypted_key_cipher,
+            encrypted_recovery_key_cipherbytes,
+            encrypted_recovery_key_nonce,
+            encrypted_recovery_key_cipher,
+            encrypted_totp_secret_cipherbytes,
+            encrypted_totp_secret_nonce,
+            encrypted_totp_secret_cipher,
+            share_encrypted_key_cipherbytes,
+            share_encrypted_key_nonce,
+            share_encrypted_key_cipher,
+            encrypted_share_key_cipherbytes,
+            encrypted_share_key_nonce,
+            encrypted_share_key_cipher,
+            keypair
+                .map_or(&[][..], |keypair| keypair.public_key())
+                .into_sql(),
+            encrypted_private_key_cipherbytes,
+            encrypted_private_key_nonce,
+            encrypted_private_key_cipher,
         ]
     }
 }
 impl IntoDatabase for Credential {
-    type FixedSizeStringArray = [String; 9];
-    fn into_database(self) -> Self::FixedSizeStringArray {
+    type FixedSizeValueArray = [Value; 15];
+    fn into_database(self) -> Self::FixedSizeValueArray {
+        [
+            self.id().into_sql(),
+            self.owner_username().into_sql(),
+            self.encrypted_name().cipherbytes().into_sql(),
+            self.encrypted_name().nonce().into_sql(),
+            self.encrypted_name().cipher_suite().into_sql(),
+            self.encrypted_username().cipherbytes().into_sql(),
+            self.encrypted_username().nonce().into_sql(),
+            self.encrypted_username().cipher_suite().into_sql(),
+            self.encrypted_password().cipherbytes().into_sql(),
+            self.encrypted_password().nonce().into_sql(),
+            self.encrypted_password().cipher_suite().into_sql(),
+            self.encrypted_notes().cipherbytes().into_sql(),
+            self.encrypted_notes().nonce().into_sql(),
+            self.encrypted_notes().cipher_suite().into_sql(),
+            self.name_index()
+                .map_or(&[][..], |name_index| name_index)
+                .into_sql(),
+        ]
+    }
+}
+impl IntoDatabase for CredentialVersion {
+    type FixedSizeValueArray = [Value; 13];
+    fn into_database(self) -> Self::FixedSizeValueArray {
+        [
+            self.id().into_sql(),
+            self.credential_id().into_sql(),
+            self.owner_username().into_sql(),
+            self.replaced_at().into_sql(),
+            self.encrypted_username().cipherbytes().into_sql(),
+            self.encrypted_username().nonce().into_sql(),
+            self.encrypted_username().cipher_suite().into_sql(),
+            self.encrypted_password().cipherbytes().into_sql(),
+            self.encrypted_password().nonce().into_sql(),
+            self.encrypted_password().cipher_suite().into_sql(),
+            self.encrypted_notes().cipherbytes().into_sql(),
+            self.encrypted_notes().nonce().into_sql(),
+            self.encrypted_notes().cipher_suite().into_sql(),
+        ]
+    }
+}
+impl IntoDatabase for TotpBackup {
+    type FixedSizeValueArray = [Value; 5];
+    fn into_database(self) -> Self::FixedSizeValueArray {
         [
-            self.owner_username().into_b64(),
-            self.encrypted_name().cipherbytes().into_b64(),
-            self.encrypted_name().nonce().into_b64(),
-            self.encrypted_username().cipherbytes().into_b64(),
-            self.encrypted_username().nonce().into_b64(),
-            self.encrypted_password().cipherbytes().into_b64(),
-            self.encrypted_password().nonce().into_b64(),
-            self.encrypted_notes().cipherbytes().into_b64(),
-            self.encrypted_notes().nonce().into_b64(),
+            self.id().into_sql(),
+            self.owner_username().into_sql(),
+            self.encrypted_secret().cipherbytes().into_sql(),
+            self.encrypted_secret().nonce().into_sql(),
+            self.encrypted_secret().cipher_suite().into_sql(),
         ]
     }
 }
 impl IntoDatabase for FileData {
-    type FixedSizeStringArray = [String; 4];
-    fn into_database(self) -> Self::FixedSizeStringArray {
+    type FixedSizeValueArray = [Value; 11];
+    fn into_database(self) -> Self::FixedSizeValueArray {
+        let (legacy_filename, encrypted_filename) = match self.stored_filename() {
+            Filename::Encrypted(encrypted_filename) => ("", Some(encrypted_filename)),
+            Filename::Legacy(filename) => (filename.as_str(), None),
+        };
+        let [encrypted_filename_cipherbytes, encrypted_filename_nonce, encrypted_filename_cipher] =
+            optional_encrypted_into_db(encrypted_filename);
         [
-            self.path().into_b64(),
-            self.filename().into_b64(),
-            self.owner_username().into_b64(),
-            self.contents_nonce().into_b64(),
+            self.id().into_sql(),
+            self.path().into_sql(),
+            legacy_filename.into_sql(),
+            self.owner_username().into_sql(),
+            self.contents_nonce().into_sql(),
+            self.contents_nonce().cipher_suite().into_sql(),
+            self.contents_chunk_size().into_sql(),
+            encrypted_filename_cipherbytes,
+            encrypted_filename_nonce,
+            encrypted_filename_cipher,
+            self.name_index()
+                .map_or(&[][..], |name_index| name_index)
+                .into_sql(),
+        ]
+    }
+}
+impl IntoDatabase for FileRevision {
+    type FixedSizeValueArray = [Value; 8];
+    fn into_database(self) -> Self::FixedSizeValueArray {
+        [
+            self.id().into_sql(),
+            self.file_id().into_sql(),
+            self.owner_username().into_sql(),
+            self.path().into_sql(),
+            self.replaced_at().into_sql(),
+            self.contents_nonce().into_sql(),
+            self.contents_nonce().cipher_suite().into_sql(),
+            self.contents_chunk_size().into_sql(),
+        ]
+    }
+}
+
+impl IntoDatabase for SharedCredential {
+    type FixedSizeValueArray = [Value; 18];
+    fn into_database(self) -> Self::FixedSizeValueArray {
+        let credential = self.credential();
+        [
+            self.id().into_sql(),
+            self.owner_username().into_sql(),
+            self.item_id().into_sql(),
+            self.encrypted_item_key().cipherbytes().into_sql(),
+            self.encrypted_item_key().nonce().into_sql(),
+            self.encrypted_item_key().cipher_suite().into_sql(),
+            credential.encrypted_name().cipherbytes().into_sql(),
+            credential.encrypted_name().nonce().into_sql(),
+            credential.encrypted_name().cipher_suite().into_sql(),
+            credential.encrypted_username().cipherbytes().into_sql(),
+            credential.encrypted_username().nonce().into_sql(),
+            credential.encrypted_username().cipher_suite().into_sql(),
+            credential.encrypted_password().cipherbytes().into_sql(),
+            credential.encrypted_password().nonce().into_sql(),
+            credential.encrypted_password().cipher_suite().into_sql(),
+            credential.encrypted_notes().cipherbytes().into_sql(),
+            credential.encrypted_notes().nonce().into_sql(),
+            credential.encrypted_notes().cipher_suite().into_sql(),
+        ]
+    }
+}
+impl IntoDatabase for SharedFile {
+    type FixedSizeValueArray = [Value; 13];
+    fn into_database(self) -> Self::FixedSizeValueArray {
+        let file_data = self.file_data();
+        let encrypted_filename = match file_data.stored_filename() {
+            Filename::Encrypted(encrypted_filename) => Some(encrypted_filename),
+            Filename::Legacy(_) => None,
+        };
+        let [encrypted_filename_cipherbytes, encrypted_filename_nonce, encrypted_filename_cipher] =
+            optional_encrypted_into_db(encrypted_filename);
+        [
+            self.id().into_sql(),
+            file_data.path().into_sql(),
+            self.owner_username().into_sql(),
+            self.item_id().into_sql(),
+            self.encrypted_item_key().cipherbytes().into_sql(),
+            self.encrypted_item_key().nonce().into_sql(),
+            self.encrypted_item_key().cipher_suite().into_sql(),
+            file_data.contents_nonce().into_sql(),
+            file_data.contents_nonce().cipher_suite().into_sql(),
+            file_data.contents_chunk_size().into_sql(),
+            encrypted_filename_cipherbytes,
+            encrypted_filename_nonce,
+            encrypted_filename_cipher,
+        ]
+    }
+}
+impl IntoDatabase for ShareGrant {
+    type FixedSizeValueArray = [Value; 8];
+    fn into_database(self) -> Self::FixedSizeValueArray {
+        [
+            self.shared_id().into_sql(),
+            self.owner_username().into_sql(),
+            self.recipient_username().into_sql(),
+            self.entity_type().into_sql(),
+            self.ephemeral_public_key().into_sql(),
+            self.encrypted_item_key().cipherbytes().into_sql(),
+            self.encrypted_item_key().nonce().into_sql(),
+            self.encrypted_item_key().cipher_suite().into_sql(),
+        ]
+    }
+}
+impl IntoDatabase for TrashedItem {
+    type FixedSizeValueArray = [Value; 4];
+    fn into_database(self) -> Self::FixedSizeValueArray {
+        [
+            self.owner_username().into_sql(),
+            self.entity_type().into_sql(),
+            self.item_id().into_sql(),
+            self.trashed_at().into_sql(),
         ]
     }
 }
 
-/// This trait defines how the given struct gets converted from a vector of base-64-encoded strings
-/// for retrieval from the database.
+/// This trait defines how the given struct gets converted from a row of SQLite values for
+/// retrieval from the database.
 pub trait TryFromDatabase {
     /// This function determines how the struct gets converted into the database format.
     fn try_from_database(row: &rusqlite::Row) -> eyre::Result<Self>
@@ -215,73 +739,371 @@ pub trait TryFromDatabase {
 // Implementations
 impl TryFromDatabase for Account {
     fn try_from_database(row: &rusqlite::Row) -> eyre::Result<Self> {
-        let username = b64_to_utf8(&row.get::<usize, String>(0)?)?;
-        let password_salt = b64_to_fixed(&row.get::<usize, String>(1)?)?;
-        let dbl_hashed_password =
-            hashed_from_db(&row.get::<usize, String>(2)?, &row.get::<usize, String>(3)?)?;
-        let encrypted_key =
-            encrypted_from_db(&row.get::<usize, String>(4)?, &row.get::<usize, String>(5)?)?;
+        let username = row.get::<usize, String>(0)?;
+        let password_salt = row.get::<usize, Salt<64>>(1)?;
+        let kdf_params = kdf_params_from_db(
+            &row.get::<usize, String>(7)?,
+            row.get::<usize, u32>(8)?,
+            row.get::<usize, u32>(9)?,
+            row.get::<usize, u32>(10)?,
+        )?;
+        let dbl_hashed_password = Hashed::from_fields(row.get(2)?, row.get(3)?, kdf_params);
+        let encrypted_key = encrypted_from_db(
+            row.get::<usize, Vec<u8>>(4)?,
+            &row.get::<usize, Vec<u8>>(5)?,
+            &row.get::<usize, String>(6)?,
+        )?;
+        let encryption_version = row.get::<usize, u32>(11)?;
+        let key_file_check = key_file_check_from_db(
+            &row.get::<usize, Vec<u8>>(12)?,
+            &row.get::<usize, Vec<u8>>(13)?,
+        )?;
+        let recovery = recovery_from_db(
+            optional_encrypted_from_db(row, 14)?,
+            optional_encrypted_from_db(row, 17)?,
+        )?;
+        let encrypted_totp_secret = optional_encrypted_from_db(row, 20)?;
+        let share_recovery = recovery_from_db(
+            optional_encrypted_from_db(row, 23)?,
+            optional_encrypted_from_db(row, 26)?,
+        )?;
+        let keypair = keypair_from_db(
+            &row.get::<usize, Vec<u8>>(29)?,
+            optional_encrypted_from_db(row, 30)?,
+        )?;
 
         Ok(Self::from_fields(
             username,
             password_salt,
             dbl_hashed_password,
             encrypted_key,
+            encryption_version,
+            key_file_check,
+            recovery,
+            encrypted_totp_secret,
+            share_recovery,
+            keypair,
         ))
     }
 }
 impl TryFromDatabase for Credential {
     fn try_from_database(row: &rusqlite::Row) -> eyre::Result<Self> {
-        let owner_username = b64_to_utf8(&row.get::<usize, String>(0)?)?;
-        let encrypted_name =
-            encrypted_from_db(&row.get::<usize, String>(1)?, &row.get::<usize, String>(2)?)?;
-        let encrypted_username =
-            encrypted_from_db(&row.get::<usize, String>(3)?, &row.get::<usize, String>(4)?)?;
-        let encrypted_password =
-            encrypted_from_db(&row.get::<usize, String>(5)?, &row.get::<usize, String>(6)?)?;
-        let encrypted_notes =
-            encrypted_from_db(&row.get::<usize, String>(7)?, &row.get::<usize, String>(8)?)?;
+        let id = row.get::<usize, String>(0)?;
+        let owner_username = row.get::<usize, String>(1)?;
+        let encrypted_name = encrypted_from_row(row, 2)?;
+        let encrypted_username = encrypted_from_row(row, 5)?;
+        let encrypted_password = encrypted_from_row(row, 8)?;
+        let encrypted_notes = encrypted_from_row(row, 11)?;
+        let name_index_bytes = row.get::<usize, Vec<u8>>(14)?;
+        let name_index = if name_index_bytes.is_empty() {
+            None
+        } else {
+            Some(bytes_to_fixed(&name_index_bytes)?)
+        };
 
         Ok(Self::from_fields(
+            id,
             owner_username,
             encrypted_name,
             encrypted_username,
             encrypted_password,
             encrypted_notes,
+            name_index,
         ))
     }
 }
+impl TryFromDatabase for CredentialVersion {
+    fn try_from_database(row: &rusqlite::Row) -> eyre::Result<Self> {
+        let id = row.get::<usize, String>(0)?;
+        let credential_id = row.get::<usize, String>(1)?;
+        let owner_username = row.get::<usize, String>(2)?;
+        let replaced_at = row.get::<usize, u64>(3)?;
+        let encrypted_username = encrypted_from_row(row, 4)?;
+        let encrypted_password = encrypted_from_row(row, 7)?;
+        let encrypted_notes = encrypted_from_row(row, 10)?;
+
+        Ok(Self::from_fields(
+            id,
+            credential_id,
+            owner_username,
+            replaced_at,
+            encrypted_username,
+            encrypted_password,
+            encrypted_notes,
+        ))
+    }
+}
+impl TryFromDatabase for TotpBackup {
+    fn try_from_database(row: &rusqlite::Row) -> eyre::Result<Self> {
+        let id = row.get::<usize, String>(0)?;
+        let owner_username = row.get::<usize, String>(1)?;
+        let encrypted_secret = encrypted_from_row(row, 2)?;
+
+        Ok(Self::from_fields(id, owner_username, encrypted_secret))
+    }
+}
 impl TryFromDatabase for FileData {
     fn try_from_database(row: &rusqlite::Row) -> eyre::Result<Self> {
-        let path = b64_to_utf8_path(&row.get::<usize, String>(0)?)?;
-        let filename = b64_to_utf8(&row.get::<usize, String>(1)?)?;
-        let owner_username = b64_to_utf8(&row.get::<usize, String>(2)?)?;
-        let contents_nonce = b64_to_fixed(&row.get::<usize, String>(3)?)?;
+        let id = row.get::<usize, String>(0)?;
+        let path = Utf8PathBuf::from(row.get::<usize, String>(1)?);
+        let filename = match optional_encrypted_from_db(row, 7)? {
+            Some(encrypted_filename) => Filename::Encrypted(encrypted_filename),
+            None => Filename::Legacy(row.get::<usize, String>(2)?),
+        };
+        let owner_username = row.get::<usize, String>(3)?;
+        let contents_nonce = nonce_from_db(
+            &row.get::<usize, Vec<u8>>(4)?,
+            &row.get::<usize, String>(5)?,
+        )?;
+        let contents_chunk_size = row.get::<usize, u32>(6)?;
+        let name_index_bytes = row.get::<usize, Vec<u8>>(10)?;
+        let name_index = if name_index_bytes.is_empty() {
+            None
+        } else {
+            Some(bytes_to_fixed(&name_index_bytes)?)
+        };
 
         Ok(Self::from_fields(
+            id,
             path,
             filename,
             owner_username,
             contents_nonce,
+            contents_chunk_size,
+            name_index,
+        ))
+    }
+}
+impl TryFromDatabase for FileRevision {
+    fn try_from_database(row: &rusqlite::Row) -> eyre::Result<Self> {
+        let id = row.get::<usize, String>(0)?;
+        let file_id = row.get::<usize, String>(1)?;
+        let owner_username = row.get::<usize, String>(2)?;
+        let path = Utf8PathBuf::from(row.get::<usize, String>(3)?);
+        let replaced_at = row.get::<usize, u64>(4)?;
+        let contents_nonce = nonce_from_db(
+            &row.get::<usize, Vec<u8>>(5)?,
+            &row.get::<usize, String>(6)?,
+        )?;
+        let contents_chunk_size = row.get::<usize, u32>(7)?;
+
+        Ok(Self::from_fields(
+            id,
+            file_id,
+            owner_username,
+            path,
+            replaced_at,
+            contents_nonce,
+            contents_chunk_size,
+        ))
+    }
+}
+
+impl TryFromDatabase for SharedCredential {
+    fn try_from_database(row: &rusqlite::Row) -> eyre::Result<Self> {
+        let id = row.get::<usize, String>(0)?;
+        let owner_username = row.get::<usize, String>(1)?;
+        let item_id = row.get::<usize, String>(2)?;
+        let encrypted_item_key = encrypted_from_row(row, 3)?;
+        let encrypted_name = encrypted_from_row(row, 6)?;
+        let encrypted_username = encrypted_from_row(row, 9)?;
+        let encrypted_password = encrypted_from_row(row, 12)?;
+        let encrypted_notes = encrypted_from_row(row, 15)?;
+
+        Ok(Self::from_fields(
+            item_id,
+            encrypted_item_key,
+            Credential::from_fields(
+                id,
+                owner_username,
+                encrypted_name,
+                encrypted_username,
+                encrypted_password,
+                encrypted_notes,
+                None,
+            ),
+        ))
+    }
+}
+impl TryFromDatabase for SharedFile {
+    fn try_from_database(row: &rusqlite::Row) -> eyre::Result<Self> {
+        let id = row.get::<usize, String>(0)?;
+        let path = Utf8PathBuf::from(row.get::<usize, String>(1)?);
+        let owner_username = row.get::<usize, String>(2)?;
+        let item_id = row.get::<usize, String>(3)?;
+        let encrypted_item_key = encrypted_from_row(row, 4)?;
+        let contents_nonce = nonce_from_db(
+            &row.get::<usize, Vec<u8>>(7)?,
+            &row.get::<usize, String>(8)?,
+        )?;
+        let contents_chunk_size = row.get::<usize, u32>(9)?;
+        let encrypted_filename = encrypted_from_row(row, 10)?;
+
+        Ok(Self::from_fields(
+            item_id,
+            encrypted_item_key,
+            FileData::from_fields(
+                id,
+                path,
+                Filename::Encrypted(encrypted_filename),
+                owner_username,
+                contents_nonce,
+                contents_chunk_size,
+                None,
+            ),
+        ))
+    }
+}
+impl TryFromDatabase for ShareGrant {
+    fn try_from_database(row: &rusqlite::Row) -> eyre::Result<Self> {
+        let shared_id = row.get::<usize, String>(0)?;
+        let owner_username = row.get::<usize, String>(1)?;
+        let recipient_username = row.get::<usize, String>(2)?;
+        let entity_type: EntityType = row.get::<usize, String>(3)?.parse()?;
+        let ephemeral_public_key = row.get::<usize, [u8; 32]>(4)?;
+        let encrypted_item_key = encrypted_from_row(row, 5)?;
+
+        Ok(Self::from_fields(
+            shared_id,
+            owner_username,
+            recipient_username,
+            entity_type,
+            ephemeral_public_key,
+            encrypted_item_key,
+        ))
+    }
+}
+impl TryFromDatabase for TrashedItem {
+    fn try_from_database(row: &rusqlite::Row) -> eyre::Result<Self> {
+        let owner_username = row.get::<usize, String>(0)?;
+        let entity_type: EntityType = row.get::<usize, String>(1)?.parse()?;
+        let item_id = row.get::<usize, String>(2)?;
+        let trashed_at = row.get::<usize, u64>(3)?;
+
+        Ok(Self::from_fields(
+            owner_username,
+            entity_type,
+            item_id,
+            trashed_at,
         ))
     }
 }
 
 // Helper function to get an [Encrypted] from database entries.
-fn encrypted_from_db(b64_cipherbytes: &str, b64_nonce: &str) -> eyre::Result<Encrypted> {
-    let cipherbytes: Vec<u8> = b64_to_bytes(b64_cipherbytes)?;
-    let nonce: Aes256Nonce = b64_to_fixed(b64_nonce)?;
+fn encrypted_from_db(cipherbytes: Vec<u8>, nonce: &[u8], cipher: &str) -> eyre::Result<Encrypted> {
+    let nonce = nonce_from_db(nonce, cipher)?;
     Ok(Encrypted::from_fields(cipherbytes, nonce))
 }
 
-// Helper function to get a [Hashed] from database entries.
-fn hashed_from_db<const H: usize, const S: usize>(
-    b64_hash: &str,
-    b64_salt: &str,
-) -> eyre::Result<Hashed<H, S>> {
-    let hash: Hash<H> = b64_to_fixed(b64_hash)?;
-    let salt: Salt<S> = b64_to_fixed(b64_salt)?;
-    Ok(Hashed::from_fields(hash, salt))
+// Helper function to get an [Encrypted] from the cipherbytes, nonce & cipher columns of a row,
+// starting at the given column.
+fn encrypted_from_row(row: &rusqlite::Row, start: usize) -> eyre::Result<Encrypted> {
+    encrypted_from_db(
+        row.get::<usize, Vec<u8>>(start)?,
+        &row.get::<usize, Vec<u8>>(start + 1)?,
+        &row.get::<usize, String>(start + 2)?,
+    )
+}
+
+// Helper function to get a [Nonce] from database entries.
+fn nonce_from_db(nonce: &[u8], cipher: &str) -> eyre::Result<Nonce> {
+    let cipher_suite: CipherSuite = cipher.parse()?;
+    Nonce::from_bytes(cipher_suite, nonce)
+}
+
+// Helper function to get an optional [Encrypted] from the cipherbytes, nonce & cipher columns of a
+// row, starting at the given column. Missing [Encrypted]s have empty entries.
+fn optional_encrypted_from_db(
+    row: &rusqlite::Row,
+    start: usize,
+) -> eyre::Result<Option<Encrypted>> {
+    let cipherbytes = row.get::<usize, Vec<u8>>(start)?;
+    let nonce = row.get::<usize, Vec<u8>>(start + 1)?;
+    let cipher = row.get::<usize, String>(start + 2)?;
+    if cipherbytes.is_empty() && nonce.is_empty() && cipher.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(encrypted_from_db(cipherbytes, &nonce, &cipher)?))
+}
+
+/// Get the database entries of an optional [Encrypted]. Missing [Encrypted]s have empty entries.
+pub fn optional_encrypted_into_db(encrypted: Option<&Encrypted>) -> [Value; 3] {
+    match encrypted {
+        Some(encrypted) => [
+            encrypted.cipherbytes().into_sql(),
+            encrypted.nonce().into_sql(),
+            encrypted.cipher_suite().into_sql(),
+        ],
+        None => [
+            Value::Blob(Vec::new()),
+            Value::Blob(Vec::new()),
+            Value::Text(String::new()),
+        ],
+    }
+}
+
+// Helper function to get an optional [AccountRecovery] from its two optional [Encrypted]s.
+fn recovery_from_db(
+    encrypted_key: Option<Encrypted>,
+    encrypted_recovery_key: Option<Encrypted>,
+) -> eyre::Result<Option<AccountRecovery>> {
+    match (encrypted_key, encrypted_recovery_key) {
+        (Some(encrypted_key), Some(encrypted_recovery_key)) => Ok(Some(
+            AccountRecovery::from_fields(encrypted_key, encrypted_recovery_key),
+        )),
+        (None, None) => Ok(None),
+        _ => Err(eyre!("Database: Incomplete account recovery fields.")),
+    }
+}
+
+// Helper function to get an optional [AccountKeypair] from its public key's database entry & its
+// optional encrypted private key. Accounts without a keypair have an empty public key.
+fn keypair_from_db(
+    public_key: &[u8],
+    encrypted_private_key: Option<Encrypted>,
+) -> eyre::Result<Option<AccountKeypair>> {
+    match (public_key.is_empty(), encrypted_private_key) {
+        (false, Some(encrypted_private_key)) => Ok(Some(AccountKeypair::from_fields(
+            bytes_to_fixed(public_key)?,
+            encrypted_private_key,
+        ))),
+        (true, None) => Ok(None),
+        _ => Err(eyre!("Database: Incomplete account keypair fields.")),
+    }
+}
+
+// Helper function to get an optional [KeyFileCheck] from database entries. Accounts which don't
+// require a key file have an empty salt & hash.
+fn key_file_check_from_db(salt: &[u8], hash: &[u8]) -> eyre::Result<Option<KeyFileCheck>> {
+    if salt.is_empty() && hash.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(KeyFileCheck::from_fields(
+        bytes_to_fixed(salt)?,
+        bytes_to_fixed(hash)?,
+    )))
+}
+
+// Helper function to get [KdfParams] from database entries.
+fn kdf_params_from_db(
+    algorithm: &str,
+    memory_kib: u32,
+    time_cost: u32,
+    parallelism: u32,
+) -> eyre::Result<KdfParams> {
+    let algorithm: KdfAlgorithm = algorithm.parse()?;
+    KdfParams::from_fields(algorithm, memory_kib, time_cost, parallelism)
+}
+
+/// Get the primary key of the [Credential] entry with the given owner & encrypted name.
+pub fn credential_primary_key(
+    owner_username: &str,
+    encrypted_name_cipherbytes: &[u8],
+) -> [Value; 2] {
+    [
+        owner_username.into_sql(),
+        encrypted_name_cipherbytes.into_sql(),
+    ]
 }
 
 /// Implementors of this trait have entries that can be sorted and selected by an owning [Account]
@@ -302,66 +1124,148 @@ impl OwnedByAccount for FileData {
         SELECT_ACCOUNT_FILES_DATA
     }
 }
+impl OwnedByAccount for CredentialVersion {
+    fn sql_select_owned() -> &'static str {
+        SELECT_ACCOUNT_CREDENTIAL_VERSIONS
+    }
+}
+impl OwnedByAccount for FileRevision {
+    fn sql_select_owned() -> &'static str {
+        SELECT_ACCOUNT_FILE_REVISIONS
+    }
+}
+impl OwnedByAccount for TotpBackup {
+    fn sql_select_owned() -> &'static str {
+        SELECT_ACCOUNT_TOTP_BACKUPS
+    }
+}
+impl OwnedByAccount for SharedCredential {
+    fn sql_select_owned() -> &'static str {
+        SELECT_ACCOUNT_SHARED_CREDENTIALS
+    }
+}
+impl OwnedByAccount for SharedFile {
+    fn sql_select_owned() -> &'static str {
+        SELECT_ACCOUNT_SHARED_FILES_DATA
+    }
+}
+impl OwnedByAccount for ShareGrant {
+    fn sql_select_owned() -> &'static str {
+        SELECT_ACCOUNT_SHARE_GRANTS
+    }
+}
+impl OwnedByAccount for TrashedItem {
+    fn sql_select_owned() -> &'static str {
+        SELECT_ACCOUNT_TRASHED_ITEMS
+    }
+}
 
-/// Implementors of this trait can be converted to a base-64-encoded String.
-pub trait IntoB64 {
-    fn into_b64(self) -> String;
+/// Implementors of this trait have entries that can be selected by their owning [Account] username
+/// & the blind index of their name.
+pub trait HasNameIndex {
+    // Denotes the SQLite statement for selecting the entry with the given owner & name index.
+    fn sql_select_by_name_index() -> &'static str;
 }
 
 // Implementations
-macro_rules! impl_into_b64_byte_vec {
-    ($($t:ty),+) => {
-        $(impl IntoB64 for $t {
-            fn into_b64(self) -> String {
-                let bytes_vec: Vec<u8> = self.into();
-                Base64::encode_string(&bytes_vec)
-            }
-        })*
+impl HasNameIndex for Credential {
+    fn sql_select_by_name_index() -> &'static str {
+        SELECT_CREDENTIAL_BY_NAME_INDEX
     }
 }
-impl_into_b64_byte_vec!(
-    Vec<u8>,
-    &[u8],
-    String,
-    &str,
-    [u8; 64],
-    Aes256Key,
-    Aes256Nonce
-);
-macro_rules! impl_into_b64_camino {
-    ($($t:ty),+) => {
-        $(impl IntoB64 for $t {
-            fn into_b64(self) -> String {
-                let path: &Utf8Path = self.as_ref();
-                Base64::encode_string(path.as_str().as_bytes())
-            }
-        })*
+impl HasNameIndex for FileData {
+    fn sql_select_by_name_index() -> &'static str {
+        SELECT_FILE_DATA_BY_NAME_INDEX
     }
 }
-impl_into_b64_camino!(Utf8PathBuf, &Utf8PathBuf, &Utf8Path);
 
-// Helper function to convert b64 strings to UTF-8 path buffers.
-fn b64_to_utf8_path(input: &str) -> eyre::Result<Utf8PathBuf> {
-    Ok(Utf8PathBuf::from(b64_to_utf8(input)?))
+/// Implementors of this trait can be converted to a SQLite [Value]. Bytes are stored as BLOBs,
+/// text as TEXT, & numbers as INTEGERs.
+pub trait IntoSql {
+    fn into_sql(self) -> Value;
 }
 
-// Helper function to convert b64 strings to UTF-8 strings.
-fn b64_to_utf8(input: &str) -> eyre::Result<String> {
-    Ok(String::from_utf8(b64_to_bytes(input)?)?)
+// Implementations
+impl IntoSql for Value {
+    fn into_sql(self) -> Value {
+        self
+    }
 }
-
-// Helper function to convert b64 strings to fixed-length byte slices.
-fn b64_to_fixed<const N: usize>(input: &str) -> eyre::Result<[u8; N]> {
-    let bytes_vec: Vec<u8> = Base64::decode_vec(input)?;
-    let len = bytes_vec.len();
-
-    match bytes_vec.try_into() {
-        Ok(slice) => Ok(slice),
-        Err(_) => Err(eyre!("b64_to_fixed: Expected length {}, got {}.", N, len)),
+impl IntoSql for Vec<u8> {
+    fn into_sql(self) -> Value {
+        Value::Blob(self)
     }
 }
+impl IntoSql for &[u8] {
+    fn into_sql(self) -> Value {
+        Value::Blob(self.to_vec())
+    }
+}
+impl<const N: usize> IntoSql for [u8; N] {
+    fn into_sql(self) -> Value {
+        Value::Blob(self.to_vec())
+    }
+}
+impl<const N: usize> IntoSql for &[u8; N] {
+    fn into_sql(self) -> Value {
+        Value::Blob(self.to_vec())
+    }
+}
+impl IntoSql for Nonce {
+    fn into_sql(self) -> Value {
+        Value::Blob(self.as_bytes().to_vec())
+    }
+}
+impl IntoSql for String {
+    fn into_sql(self) -> Value {
+        Value::Text(self)
+    }
+}
+impl IntoSql for &str {
+    fn into_sql(self) -> Value {
+        Value::Text(self.to_owned())
+    }
+}
+impl IntoSql for CipherSuite {
+    fn into_sql(self) -> Value {
+        Value::Text(self.as_str().to_owned())
+    }
+}
+impl IntoSql for EntityType {
+    fn into_sql(self) -> Value {
+        Value::Text(self.as_str().to_owned())
+    }
+}
+impl IntoSql for u32 {
+    fn into_sql(self) -> Value {
+        Value::Integer(self.into())
+    }
+}
+impl IntoSql for u64 {
+    fn into_sql(self) -> Value {
+        Value::Integer(i64::try_from(self).unwrap_or(i64::MAX))
+    }
+}
+macro_rules! impl_into_sql_camino {
+    ($($t:ty),+) => {
+        $(impl IntoSql for $t {
+            fn into_sql(self) -> Value {
+                let path: &Utf8Path = self.as_ref();
+                Value::Text(path.as_str().to_owned())
+            }
+        })*
+    }
+}
+impl_into_sql_camino!(Utf8PathBuf, &Utf8PathBuf, &Utf8Path);
 
-// Helper function to convert b64 strings to vectors of bytes.
-fn b64_to_bytes(input: &str) -> eyre::Result<Vec<u8>> {
-    Ok(Base64::decode_vec(input)?)
+// Helper function to convert byte slices to fixed-length byte arrays.
+fn bytes_to_fixed<const N: usize>(bytes: &[u8]) -> eyre::Result<[u8; N]> {
+    match bytes.try_into() {
+        Ok(array) => Ok(array),
+        Err(_) => Err(eyre!(
+            "bytes_to_fixed: Expected length {}, got {}.",
+            N,
+            bytes.len()
+        )),
+    }
 }
@@ -0,0 +1,315 @@
+//! Where a [Vault](super::Vault) keeps the encrypted contents of its files: either a directory tree
+//! beside the [Database](super::database::Database), or the `file_contents` & `file_chunks` tables
+//! inside it.
+use std::{
+    fs,
+    io::{self, Read, Write},
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::{self, eyre};
+use rusqlite::{Connection, OptionalExtension};
+
+use super::{
+    filesystem::{
+        get_account_file_dir, get_file_path, new_account_file_dir, new_empty_file, open_file,
+        remove_file_if_exists, replace_file,
+    },
+    sql_statements::{
+        DELETE_ALL_FILE_CONTENTS, DELETE_FILE_CONTENTS, DELETE_OWNED_FILE_CONTENTS,
+        INSERT_FILE_CHUNK, INSERT_FILE_CONTENTS, SELECT_FILE_CHUNK, SELECT_FILE_CONTENTS_LENGTH,
+        SELECT_FILE_CONTENTS_PATHS, UPDATE_FILE_CONTENTS_PATH,
+    },
+};
+
+/// The largest size of each chunk of a file in the `file_chunks` table, in bytes.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Where the encrypted contents of a [Vault](super::Vault)'s files are kept.
+///
+/// Files are found by the paths stored in their [FileData](super::super::file_data::FileData).
+/// In the [FileStore::Directory] layout, these are real paths of the form
+/// `<directory>/<account name>/<file name>`. In the [FileStore::Database] layout, they are only
+/// keys of the `file_contents` table, of the form `<account name>/<file name>`, & each file's
+/// contents are split into chunks in the `file_chunks` table, which are streamed one at a time.
+///
+/// Operations on the [FileStore::Database] layout are run on the given [Connection], so they can
+/// be part of a [rusqlite::Transaction]. The [FileStore::Directory] layout ignores it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileStore {
+    /// Each account has a directory in this directory, holding one file per file.
+    Directory(Utf8PathBuf),
+    /// Each file is a row of the `file_contents` table, with its contents in BLOB chunks in the
+    /// `file_chunks` table, so the whole vault is one file.
+    Database,
+}
+impl FileStore {
+    /// The name of this layout, as saved in the [Database](super::database::Database).
+    pub fn layout(&self) -> &'static str {
+        match self {
+            Self::Directory(_) => "directory",
+            Self::Database => "database",
+        }
+    }
+
+    /// Get the path of an account's file directory. In the [FileStore::Directory] layout, the
+    /// directory must exist & be writeable.
+    pub fn account_dir(&self, account_name: &str) -> eyre::Result<Utf8PathBuf> {
+        match self {
+            Self::Directory(dir) => get_account_file_dir(dir, account_name),
+            Self::Database => Ok(Utf8PathBuf::from(account_name)),
+        }
+    }
+
+    /// Get the path of a file from the account name & file id. In the [FileStore::Directory]
+    /// layout, the account's directory must exist & be writeable.
+    pub fn file_path(&self, account_name: &str, file_id: &str) -> eyre::Result<Utf8PathBuf> {
+        match self {
+            Self::Directory(dir) => get_file_path(dir, account_name, file_id),
+            Self::Database => Ok(self.account_dir(account_name)?.join(file_id)),
+        }
+    }
+
+    /// Create an account's file directory.
+    pub fn new_account_dir(&self, account_name: &str) -> eyre::Result<()> {
+        match self {
+            Self::Directory(dir) => new_account_file_dir(dir, account_name),
+            Self::Database => Ok(()),
+        }
+    }
+
+    /// Delete an account's file directory & everything in it.
+    pub fn remove_account_dir(&self, conn: &Connection, account_name: &str) -> eyre::Result<()> {
+        match self {
+            Self::Directory(dir) => Ok(fs::remove_dir_all(get_account_file_dir(
+                dir,
+                account_name,
+            )?)?),
+            Self::Database => {
+                conn.execute(DELETE_OWNED_FILE_CONTENTS, [account_name])?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Return whether a file exists at the given path.
+    pub fn exists(&self, conn: &Connection, path: &Utf8Path) -> eyre::Result<bool> {
+        match self {
+            Self::Directory(_) => Ok(path.try_exists()?),
+            Self::Database => Ok(Self::select_size(conn, path)?.is_some()),
+        }
+    }
+
+    /// Return the size of the file at the given path in bytes.
+    pub fn size(&self, conn: &Connection, path: &Utf8Path) -> eyre::Result<u64> {
+        match self {
+            Self::Directory(_) => Ok(fs::metadata(path)?.len()),
+            Self::Database => Self::select_size(conn, path)?.ok_or_else(|| Self::not_found(path)),
+        }
+    }
+
+    /// Open a [Read]er of the file at the given path.
+    ///
+    /// In the [FileStore::Database] layout, one chunk of the file is held in memory at a time.
+    pub fn open<'a>(
+        &self,
+        conn: &'a Connection,
+        path: &Utf8Path,
+    ) -> eyre::Result<Box<dyn Read + 'a>> {
+        match self {
+            Self::Directory(_) => Ok(Box::new(open_file(path)?)),
+            Self::Database => {
+                if !self.exists(conn, path)? {
+                    return Err(Self::not_found(path));
+                }
+                Ok(Box::new(ChunkReader {
+                    conn,
+                    path: path.to_owned(),
+                    next_index: 0,
+                    chunk: io::Cursor::new(Vec::new()),
+                }))
+            }
+        }
+    }
+
+    /// Create a new file at the given path, filling it with `write`. Fails if the file already
+    /// exists. The new file is synced to the disk, or deleted on a failure.
+    pub fn write_new<F>(&self, conn: &Connection, path: &Utf8Path, write: F) -> eyre::Result<()>
+    where
+        F: FnOnce(&mut dyn Write) -> eyre::Result<()>,
+    {
+        match self {
+            Self::Directory(_) => {
+                let mut file = new_empty_file(path)?;
+                let write_result = write(&mut file).and_then(|()| Ok(file.sync_all()?));
+                if let Err(err) = write_result {
+                    let _ = fs::remove_file(path);
+                    return Err(err);
+                }
+                Ok(())
+            }
+            Self::Database => {
+                conn.execute(
+                    INSERT_FILE_CONTENTS,
+                    (path.as_str(), Self::owner_account_name(path)?),
+                )?;
+                let mut writer = ChunkWriter {
+                    conn,
+                    path,
+                    next_index: 0,
+                    buffer: Vec::new(),
+                };
+                let write_result = write(&mut writer).and_then(|()| Ok(writer.flush()?));
+                if let Err(err) = write_result {
+                    let _ = conn.execute(DELETE_FILE_CONTENTS, [path.as_str()]);
+                    return Err(err);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Atomically replace the file at `to` with the file at `from`.
+    pub fn replace(&self, conn: &Connection, from: &Utf8Path, to: &Utf8Path) -> eyre::Result<()> {
+        match self {
+            Self::Directory(_) => replace_file(from, to),
+            Self::Database => {
+                if !self.exists(conn, from)? {
+                    return Err(Self::not_found(from));
+                }
+                conn.execute(DELETE_FILE_CONTENTS, [to.as_str()])?;
+                conn.execute(UPDATE_FILE_CONTENTS_PATH, (from.as_str(), to.as_str()))?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Delete the file at the given path.
+    pub fn remove(&self, conn: &Connection, path: &Utf8Path) -> eyre::Result<()> {
+        match self {
+            Self::Directory(_) => Ok(fs::remove_file(path)?),
+            Self::Database => match conn.execute(DELETE_FILE_CONTENTS, [path.as_str()])? {
+                0 => Err(Self::not_found(path)),
+                _ => Ok(()),
+            },
+        }
+    }
+
+    /// Delete the file at the given path, unless it's already missing.
+    pub fn remove_if_exists(&self, conn: &Connection, path: &Utf8Path) -> eyre::Result<()> {
+        match self {
+            Self::Directory(_) => remove_file_if_exists(path),
+            Self::Database => {
+                conn.execute(DELETE_FILE_CONTENTS, [path.as_str()])?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Delete every file in the `file_contents` table.
+    pub fn remove_all(conn: &Connection) -> eyre::Result<()> {
+        conn.execute(DELETE_ALL_FILE_CONTENTS, ())?;
+        Ok(())
+    }
+
+    /// List the paths of all files in the `file_contents` table.
+    pub fn select_paths(conn: &Connection) -> eyre::Result<Vec<Utf8PathBuf>> {
+        let mut statement = conn.prepare(SELECT_FILE_CONTENTS_PATHS)?;
+        let paths = statement
+            .query_map([], |row| row.get::<usize, String>(0))?
+            .map(|path| path.map(Utf8PathBuf::from))
+            .collect::<Result<Vec<Utf8PathBuf>, _>>()?;
+        Ok(paths)
+    }
+
+    // Helper function: Select the size of the file at the given path in the `file_contents` table,
+    // if it exists.
+    fn select_size(conn: &Connection, path: &Utf8Path) -> eyre::Result<Option<u64>> {
+        Ok(conn
+            .query_row(SELECT_FILE_CONTENTS_LENGTH, [path.as_str()], |row| {
+                row.get::<usize, u64>(0)
+            })
+            .optional()?)
+    }
+
+    // Helper function: Get the name of the account which owns the file at the given path in the
+    // `file_contents` table.
+    fn owner_account_name(path: &Utf8Path) -> eyre::Result<&str> {
+        path.parent()
+            .map(Utf8Path::as_str)
+            .filter(|account_name| !account_name.is_empty())
+            .ok_or_else(|| eyre!("{:?} is not in an account's file directory.", path))
+    }
+
+    // Helper function: The error returned when there's no file at the given path.
+    fn not_found(path: &Utf8Path) -> eyre::Report {
+        eyre!("No file exists at {:?}.", path)
+    }
+}
+
+/// A [Read]er of the chunks of a file in the `file_chunks` table, which selects each chunk once the
+/// previous one has been read.
+struct ChunkReader<'a> {
+    conn: &'a Connection,
+    path: Utf8PathBuf,
+    next_index: u64,
+    chunk: io::Cursor<Vec<u8>>,
+}
+impl Read for ChunkReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let num_bytes = self.chunk.read(buf)?;
+            if num_bytes > 0 || buf.is_empty() {
+                return Ok(num_bytes);
+            }
+            let Some(chunk) = self
+                .conn
+                .query_row(
+                    SELECT_FILE_CHUNK,
+                    (self.path.as_str(), self.next_index),
+                    |row| row.get::<usize, Vec<u8>>(0),
+                )
+                .optional()
+                .map_err(io::Error::other)?
+            else {
+                return Ok(0);
+            };
+            self.next_index += 1;
+            self.chunk = io::Cursor::new(chunk);
+        }
+    }
+}
+
+/// A [Write]r of the chunks of a file in the `file_chunks` table, which inserts each chunk once it
+/// reaches the [CHUNK_SIZE], or when flushed.
+struct ChunkWriter<'a> {
+    conn: &'a Connection,
+    path: &'a Utf8Path,
+    next_index: u64,
+    buffer: Vec<u8>,
+}
+impl Write for ChunkWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let num_bytes = buf.len().min(CHUNK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..num_bytes]);
+        if self.buffer.len() == CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(num_bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        self.conn
+            .execute(
+                INSERT_FILE_CHUNK,
+                (self.path.as_str(), self.next_index, &self.buffer),
+            )
+            .map_err(io::Error::other)?;
+        self.next_index += 1;
+        self.buffer.clear();
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
 //! Functionality related to saving, loading, and editing files.
 use std::{
-    fs::{create_dir, metadata, File, OpenOptions},
-    io::{Read, Write},
+    fs::{create_dir, metadata, remove_file, rename, File, OpenOptions},
+    io::ErrorKind,
 };
 
 use camino::{Utf8Path, Utf8PathBuf};
@@ -41,18 +41,46 @@ where
     Ok(dir)
 }
 
-/// Get the path of a file from the account and file names.
-pub fn get_file_path<P, U, F>(fs_dir: P, username: U, filename: F) -> eyre::Result<Utf8PathBuf>
+/// Get the path of a file from the account name and file id. Files are named after their ids, so
+/// their real names can't be seen on disk.
+pub fn get_file_path<P, U, I>(fs_dir: P, username: U, file_id: I) -> eyre::Result<Utf8PathBuf>
 where
     P: AsRef<Utf8Path>,
     U: AsRef<str>,
-    F: AsRef<str>,
+    I: AsRef<str>,
 {
     let mut path = get_account_file_dir(fs_dir.as_ref(), username.as_ref())?;
-    path.push(filename.as_ref());
+    path.push(file_id.as_ref());
     Ok(path)
 }
 
+/// Get the path of the pending sibling of a file. A file's pending sibling holds its new contents
+/// until they are committed to the [Database].
+pub fn get_pending_file_path<P>(path: P, file_id: &str) -> Utf8PathBuf
+where
+    P: AsRef<Utf8Path>,
+{
+    Utf8PathBuf::from(format!("{}.{}.pending", path.as_ref(), file_id))
+}
+
+/// Get the path of the replaced sibling of a file. A file's replaced sibling holds its old contents
+/// while an update of the file is committed to the [Database].
+pub fn get_replaced_file_path<P>(path: P, file_id: &str) -> Utf8PathBuf
+where
+    P: AsRef<Utf8Path>,
+{
+    Utf8PathBuf::from(format!("{}.{}.replaced", path.as_ref(), file_id))
+}
+
+/// Get the path of a revision of a file. A file's revisions are kept beside it, each holding the
+/// contents the file had before one of its updates.
+pub fn get_revision_file_path<P>(path: P, revision_id: &str) -> Utf8PathBuf
+where
+    P: AsRef<Utf8Path>,
+{
+    Utf8PathBuf::from(format!("{}.{}.revision", path.as_ref(), revision_id))
+}
+
 /// Create an [Account] file directory.
 pub fn new_account_file_dir<P>(fs_dir: P, username: &str) -> eyre::Result<()>
 where
@@ -65,22 +93,46 @@ where
     Ok(())
 }
 
-/// Create a new [File] containing the given contents.
-pub fn new_file<P, B>(path: P, contents: B) -> eyre::Result<()>
+/// Create a new, empty [File] open for writing.
+pub fn new_empty_file<P>(path: P) -> eyre::Result<File>
 where
     P: AsRef<Utf8Path>,
-    B: Into<Vec<u8>>,
 {
-    let mut file = File::create_new(path.as_ref())?;
-    let bytes_vec: Vec<u8> = contents.into();
-    Ok(file.write_all(&bytes_vec)?)
+    Ok(File::create_new(path.as_ref())?)
 }
 
-/// Read a [File] as bytes.
-pub fn read_file_bytes(mut file: &File) -> eyre::Result<Vec<u8>> {
-    let mut bytes: Vec<u8> = vec![];
-    file.read_to_end(&mut bytes)?;
-    Ok(bytes)
+/// Delete the file at the given path, unless it's already missing.
+pub fn remove_file_if_exists<P>(path: P) -> eyre::Result<()>
+where
+    P: AsRef<Utf8Path>,
+{
+    match remove_file(path.as_ref()) {
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+/// Atomically replace the file at `to` with the file at `from`, then sync their directory so the
+/// rename survives a crash. The file at `from` should already be synced.
+pub fn replace_file<P, Q>(from: P, to: Q) -> eyre::Result<()>
+where
+    P: AsRef<Utf8Path>,
+    Q: AsRef<Utf8Path>,
+{
+    rename(from.as_ref(), to.as_ref())?;
+    sync_parent_dir(to.as_ref())
+}
+
+// Helper function: Sync the directory containing the given path. Directories can't be opened as
+// files on Windows, where renames are already durable once they return.
+fn sync_parent_dir(path: &Utf8Path) -> eyre::Result<()> {
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        File::open(parent)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
 }
 
 /// Open an existing [File].
@@ -94,13 +146,3 @@ where
         .create(false)
         .open(path.as_ref())?)
 }
-
-/// Open an existing [File] and write the bytes to the file, overwriting the existing contents.
-pub fn write_file<P, B>(path: P, bytes: B) -> eyre::Result<()>
-where
-    P: AsRef<Utf8Path>,
-    B: AsRef<[u8]>,
-{
-    let mut f = open_file(path)?;
-    Ok(f.write_all(bytes.as_ref())?)
-}
@@ -0,0 +1,525 @@
+//! The previous versions of credentials & revisions of files kept by the [Vault](super::Vault).
+use color_eyre::eyre::{self, eyre};
+use rusqlite::Transaction;
+use zeroize::Zeroizing;
+
+use super::{
+    super::{
+        credential::{CredentialField, CredentialVersion},
+        encryption::{
+            encrypted::{Aes256Key, TryFromEncrypted},
+            stream::copy_secret,
+        },
+        file_data::FileRevision,
+        secret::Secret,
+    },
+    database::Database,
+    Vault,
+};
+
+impl Vault {
+    /// Load the previous versions of the [Credential] with the given `owner_username` & `name`,
+    /// newest first.
+    pub fn load_credential_history<O, N>(
+        &self,
+        owner_username: O,
+        name: N,
+        key: &Aes256Key,
+    ) -> eyre::Result<Vec<CredentialVersion>>
+    where
+        O: AsRef<str>,
+        N: AsRef<str>,
+    {
+        let credential = self.load_credential(owner_username, name, key)?;
+        self.database
+            .select_credential_history(credential.owner_username(), credential.id())
+    }
+
+    /// Restore the previous version of the [Credential] with the given `owner_username` & `name`
+    /// which has the given `version_id`. The values it replaces are kept as a new previous
+    /// version, so the restore can be undone.
+    pub fn restore_credential_version<O, N>(
+        &mut self,
+        owner_username: O,
+        name: N,
+        key: &Aes256Key,
+        version_id: &str,
+    ) -> eyre::Result<()>
+    where
+        O: AsRef<str>,
+        N: AsRef<str>,
+    {
+        let credential = self.load_credential(owner_username.as_ref(), name.as_ref(), key)?;
+        let version = self
+            .database
+            .select_entry::<CredentialVersion, &str, 2>([credential.owner_username(), version_id])?
+            .filter(|version| version.credential_id() == credential.id())
+            .ok_or_else(|| {
+                eyre!(
+                    "Credential \"{}\" has no previous version with id {}.",
+                    name.as_ref(),
+                    version_id
+                )
+            })?;
+        let username = version.username::<Secret<String>>(key)?;
+        let password = version.password::<Secret<String>>(key)?;
+        let notes = version.notes::<Secret<String>>(key)?;
+        self.update_credential_fields(
+            owner_username,
+            name,
+            key,
+            &[
+                (CredentialField::Username, username.expose()),
+                (CredentialField::Password, password.expose()),
+                (CredentialField::Notes, notes.expose()),
+            ],
+        )
+    }
+
+    /// Load the previous revisions of the file with the given `owner_username` & `filename`,
+    /// newest first.
+    pub fn load_file_revisions<U, F>(
+        &self,
+        username: U,
+        filename: F,
+        key: &Aes256Key,
+    ) -> eyre::Result<Vec<FileRevision>>
+    where
+        U: AsRef<str>,
+        F: AsRef<str>,
+    {
+        let file_data = self.load_file_data(username, filename, key)?;
+        self.database
+            .select_file_revisions(file_data.owner_username(), file_data.id())
+    }
+
+    /// Load the previous revision with the given `revision_id` of the file with the given
+    /// `owner_username` & `filename`, along with its decrypted contents.
+    pub fn load_file_revision<U, F, E>(
+        &self,
+        username: U,
+        filename: F,
+        key: &Aes256Key,
+        revision_id: &str,
+    ) -> eyre::Result<(FileRevision, E)>
+    where
+        U: AsRef<str>,
+        F: AsRef<str>,
+        E: TryFromEncrypted,
+    {
+        let file_data = self.load_file_data(&username, &filename, key)?;
+        let revision = self
+            .database
+            .select_entry::<FileRevision, &str, 1>([revision_id])?
+            .filter(|revision| {
+                revision.file_id() == file_data.id()
+                    && revision.owner_username() == file_data.owner_username()
+            })
+            .ok_or_else(|| {
+                eyre!(
+                    "File \"{}\" has no previous revision with id {}.",
+                    filename.as_ref(),
+                    revision_id
+                )
+            })?;
+        // Decrypt the revision's contents like the file's.
+        let conn = self.database.connection();
+        let revision_data = revision.contents_file_data(&file_data);
+        let mut decrypted_bytes = Zeroizing::new(Vec::with_capacity(
+            self.file_store.size(conn, revision.path())?.try_into()?,
+        ));
+        let mut contents = Self::open_contents(
+            &self.file_store,
+            conn,
+            &revision_data,
+            revision.path(),
+            key,
+            &file_data.contents_associated_data(),
+        )?;
+        copy_secret(&mut contents, &mut *decrypted_bytes)?;
+        let decrypted_contents = E::try_from_decrypted_bytes(std::mem::take(&mut decrypted_bytes))?;
+
+        Ok((revision, decrypted_contents))
+    }
+
+    /// Restore the previous revision with the given `revision_id` of the file with the given
+    /// `owner_username` & `filename`. The contents it replaces are kept as a new revision, so the
+    /// restore can be undone.
+    pub fn restore_file_revision<U, F>(
+        &mut self,
+        username: U,
+        filename: F,
+        key: &Aes256Key,
+        revision_id: &str,
+    ) -> eyre::Result<()>
+    where
+        U: AsRef<str>,
+        F: AsRef<str>,
+    {
+        let (_, contents): (_, Secret<Vec<u8>>) =
+            self.load_file_revision(&username, &filename, key, revision_id)?;
+        self.update_file_from(username, filename, key, &contents.expose()[..])
+    }
+
+    // Helper function: Delete the [FileRevision]s of every file beyond the `limit`, along with
+    // those older than `max_age` days at the Unix time `now`, using the current [Transaction].
+    // Return the deleted [FileRevision]s, whose contents are left to be deleted once the
+    // [Transaction] is committed.
+    pub(super) fn transaction_prune_file_revisions(
+        limit: u32,
+        max_age: Option<u32>,
+        now: u64,
+        tx: &Transaction,
+    ) -> eyre::Result<Vec<FileRevision>> {
+        let replaced_before =
+            max_age.map_or(0, |days| now.saturating_sub(u64::from(days) * 24 * 60 * 60));
+        Database::transaction_prune_file_revisions(limit, replaced_before, tx)
+    }
+
+    // Helper function: Delete the contents of the given deleted [FileRevision]s, unless they're
+    // already missing.
+    pub(super) fn remove_revision_contents(&self, revisions: &[FileRevision]) -> eyre::Result<()> {
+        for revision in revisions {
+            self.file_store
+                .remove_if_exists(self.database.connection(), revision.path())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::{assert_eq, assert_ne};
+    use std::fs;
+
+    use super::super::{
+        super::{
+            credential::{CredentialField, CredentialVersion},
+            file_data::{FileData, FileRevision},
+        },
+        tests::{db_path, fs_dir, refresh_test_db, refresh_test_fs},
+        Vault, VaultIssue,
+    };
+
+    #[test]
+    fn credential_history() {
+        let db_name = "credential_history.db";
+        let fs_name = "credential_history";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        fn clock() -> u64 {
+            1_700_000_000
+        }
+        fn later_clock() -> u64 {
+            1_700_000_060
+        }
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+        vault.set_clock(clock);
+        assert_eq!(vault.credential_history_limit(), 10);
+
+        let username = "mr_test";
+        let password = "open sesame!";
+        vault.create_new_account(username, password, None).unwrap();
+        let key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+        vault
+            .create_credential(username, &key, "c", "u1", "p1", "n1")
+            .unwrap();
+        assert!(vault
+            .load_credential_history(username, "c", &key)
+            .unwrap()
+            .is_empty());
+
+        // Saving unchanged values keeps no version.
+        vault
+            .update_credential_fields(username, "c", &key, &[(CredentialField::Password, "p1")])
+            .unwrap();
+        assert!(vault
+            .load_credential_history(username, "c", &key)
+            .unwrap()
+            .is_empty());
+
+        // Each change keeps the values it replaced, newest first.
+        vault
+            .update_credential_fields(username, "c", &key, &[(CredentialField::Password, "p2")])
+            .unwrap();
+        vault.set_clock(later_clock);
+        vault
+            .update_credential_fields(
+                username,
+                "c",
+                &key,
+                &[
+                    (CredentialField::Username, "u3"),
+                    (CredentialField::Password, "p3"),
+                ],
+            )
+            .unwrap();
+        let _ = vault
+            .update_credential_fields(username, "c", &key, &[(CredentialField::Name, "d")])
+            .unwrap_err();
+        let history = vault.load_credential_history(username, "c", &key).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].replaced_at(), later_clock());
+        assert_eq!(history[0].username::<String>(&key).unwrap(), "u1");
+        assert_eq!(history[0].password::<String>(&key).unwrap(), "p2");
+        assert_eq!(history[1].replaced_at(), clock());
+        assert_eq!(history[1].password::<String>(&key).unwrap(), "p1");
+        assert_eq!(history[1].notes::<String>(&key).unwrap(), "n1");
+
+        // Restoring a version keeps the values it replaces too.
+        vault
+            .restore_credential_version(username, "c", &key, history[1].id())
+            .unwrap();
+        let credential = vault.load_credential(username, "c", &key).unwrap();
+        assert_eq!(credential.username::<String>(&key).unwrap(), "u1");
+        assert_eq!(credential.password::<String>(&key).unwrap(), "p1");
+        let history = vault.load_credential_history(username, "c", &key).unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].username::<String>(&key).unwrap(), "u3");
+        assert_eq!(history[0].password::<String>(&key).unwrap(), "p3");
+
+        // Versions of other credentials can't be restored.
+        vault
+            .create_credential(username, &key, "d", "u", "p", "n")
+            .unwrap();
+        let _ = vault
+            .restore_credential_version(username, "d", &key, history[0].id())
+            .unwrap_err();
+
+        // The limit is saved & older versions are pruned.
+        vault.set_credential_history_limit(2).unwrap();
+        let history = vault.load_credential_history(username, "c", &key).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].password::<String>(&key).unwrap(), "p2");
+        vault
+            .update_credential_fields(username, "c", &key, &[(CredentialField::Notes, "n4")])
+            .unwrap();
+        let history = vault.load_credential_history(username, "c", &key).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].notes::<String>(&key).unwrap(), "n1");
+        drop(vault);
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+        assert_eq!(vault.credential_history_limit(), 2);
+
+        // The history is re-encrypted along with the credential.
+        vault
+            .rotate_account_key(username, password, None, None)
+            .unwrap();
+        let new_key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+        let history = vault
+            .load_credential_history(username, "c", &new_key)
+            .unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].notes::<String>(&new_key).unwrap(), "n1");
+        let _ = history[0].notes::<String>(&key).unwrap_err();
+
+        // A limit of 0 keeps no history.
+        vault.set_credential_history_limit(0).unwrap();
+        vault
+            .update_credential_fields(username, "c", &new_key, &[(CredentialField::Notes, "n5")])
+            .unwrap();
+        assert!(vault
+            .load_credential_history(username, "c", &new_key)
+            .unwrap()
+            .is_empty());
+
+        // Purging a credential deletes its history.
+        vault.set_credential_history_limit(10).unwrap();
+        vault
+            .update_credential_fields(username, "c", &new_key, &[(CredentialField::Notes, "n6")])
+            .unwrap();
+        vault.delete_credential(username, "c", &new_key).unwrap();
+        assert!(!vault.load_all::<CredentialVersion>().unwrap().is_empty());
+        vault
+            .purge_trashed_credential(username, "c", &new_key)
+            .unwrap();
+        assert!(vault.load_all::<CredentialVersion>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn file_revisions() {
+        let db_name = "file_revisions.db";
+        let fs_name = "file_revisions";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        fn clock() -> u64 {
+            1_700_000_000
+        }
+        fn later_clock() -> u64 {
+            1_700_000_060
+        }
+        fn much_later_clock() -> u64 {
+            1_700_000_060 + 3 * 24 * 60 * 60
+        }
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+        vault.set_clock(clock);
+        assert_eq!(vault.file_revision_limit(), 10);
+        assert_eq!(vault.file_revision_max_age(), None);
+
+        let username = "mr_test";
+        let password = "open sesame!";
+        vault.create_new_account(username, password, None).unwrap();
+        let key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+        vault.create_file("f", username, "one", &key).unwrap();
+        assert!(vault
+            .load_file_revisions(username, "f", &key)
+            .unwrap()
+            .is_empty());
+
+        // Each update keeps the contents it replaced, newest first, encrypted with its own nonce.
+        vault.update_file(username, "f", &key, "two").unwrap();
+        vault.set_clock(later_clock);
+        vault.update_file(username, "f", &key, "three").unwrap();
+        let revisions = vault.load_file_revisions(username, "f", &key).unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0].replaced_at(), later_clock());
+        assert_eq!(revisions[1].replaced_at(), clock());
+        assert_ne!(revisions[0].contents_nonce(), revisions[1].contents_nonce());
+        let (_, contents): (_, String) = vault
+            .load_file_revision(username, "f", &key, revisions[0].id())
+            .unwrap();
+        assert_eq!(contents, "two");
+        let (_, contents): (_, String) = vault
+            .load_file_revision(username, "f", &key, revisions[1].id())
+            .unwrap();
+        assert_eq!(contents, "one");
+        assert_eq!(vault.check(None).unwrap(), vec![]);
+
+        // Restoring a revision keeps the contents it replaces too.
+        vault
+            .restore_file_revision(username, "f", &key, revisions[1].id())
+            .unwrap();
+        let (_, contents): (FileData, String) = vault.load_file(username, "f", &key).unwrap();
+        assert_eq!(contents, "one");
+        let revisions = vault.load_file_revisions(username, "f", &key).unwrap();
+        assert_eq!(revisions.len(), 3);
+        let (_, contents): (_, String) = vault
+            .load_file_revision(username, "f", &key, revisions[0].id())
+            .unwrap();
+        assert_eq!(contents, "three");
+
+        // Revisions of other files can't be loaded or restored.
+        vault.create_file("g", username, "other", &key).unwrap();
+        let _ = vault
+            .load_file_revision::<&str, &str, String>(username, "g", &key, revisions[0].id())
+            .unwrap_err();
+        let _ = vault
+            .restore_file_revision(username, "g", &key, revisions[0].id())
+            .unwrap_err();
+
+        // The limit is saved & older revisions are pruned, along with their contents.
+        vault.set_file_revision_limit(2).unwrap();
+        assert!(!revisions[2].path().try_exists().unwrap());
+        let revisions = vault.load_file_revisions(username, "f", &key).unwrap();
+        assert_eq!(revisions.len(), 2);
+        vault.update_file(username, "f", &key, "four").unwrap();
+        assert!(!revisions[1].path().try_exists().unwrap());
+        let revisions = vault.load_file_revisions(username, "f", &key).unwrap();
+        assert_eq!(revisions.len(), 2);
+        drop(vault);
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+        assert_eq!(vault.file_revision_limit(), 2);
+
+        // The revisions are re-encrypted along with the file.
+        vault
+            .rotate_account_key(username, password, None, None)
+            .unwrap();
+        let new_key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+        let (_, contents): (_, String) = vault
+            .load_file_revision(username, "f", &new_key, revisions[0].id())
+            .unwrap();
+        assert_eq!(contents, "one");
+        let _ = vault
+            .load_file_revision::<&str, &str, String>(username, "f", &key, revisions[0].id())
+            .unwrap_err();
+
+        // The revisions move along with the files when the layout changes.
+        vault.convert_to_single_file().unwrap();
+        assert_eq!(vault.check(None).unwrap(), vec![]);
+        let revisions = vault.load_file_revisions(username, "f", &new_key).unwrap();
+        let (_, contents): (_, String) = vault
+            .load_file_revision(username, "f", &new_key, revisions[1].id())
+            .unwrap();
+        assert_eq!(contents, "three");
+        vault.convert_to_directory(&fs_dir).unwrap();
+        assert_eq!(vault.check(None).unwrap(), vec![]);
+
+        // Revisions older than the maximum age are pruned.
+        vault.set_clock(much_later_clock);
+        vault.update_file(username, "f", &new_key, "five").unwrap();
+        assert_eq!(
+            vault
+                .load_file_revisions(username, "f", &new_key)
+                .unwrap()
+                .len(),
+            2
+        );
+        vault.set_file_revision_max_age(Some(1)).unwrap();
+        let revisions = vault.load_file_revisions(username, "f", &new_key).unwrap();
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0].replaced_at(), much_later_clock());
+        drop(vault);
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+        assert_eq!(vault.file_revision_max_age(), Some(1));
+
+        // Missing revisions are found & repaired.
+        fs::remove_file(revisions[0].path()).unwrap();
+        let issues = vault.check(None).unwrap();
+        assert_eq!(
+            issues,
+            vec![VaultIssue::MissingFileRevision {
+                owner_username: username.to_owned(),
+                id: revisions[0].id().to_owned(),
+                path: revisions[0].path().to_owned(),
+            }]
+        );
+        vault.repair(&issues[0], None).unwrap();
+        assert_eq!(vault.check(None).unwrap(), vec![]);
+        assert!(vault
+            .load_file_revisions(username, "f", &new_key)
+            .unwrap()
+            .is_empty());
+
+        // A limit of 0 keeps no revisions.
+        vault.set_file_revision_limit(0).unwrap();
+        vault.update_file(username, "f", &new_key, "six").unwrap();
+        assert!(vault
+            .load_file_revisions(username, "f", &new_key)
+            .unwrap()
+            .is_empty());
+
+        // Purging a file deletes its revisions.
+        vault.set_file_revision_limit(10).unwrap();
+        vault.update_file(username, "f", &new_key, "seven").unwrap();
+        let revisions = vault.load_file_revisions(username, "f", &new_key).unwrap();
+        assert_eq!(revisions.len(), 1);
+        vault.delete_file(username, "f", &new_key).unwrap();
+        assert!(revisions[0].path().try_exists().unwrap());
+        vault.purge_trashed_file(username, "f", &new_key).unwrap();
+        assert!(vault.load_all::<FileRevision>().unwrap().is_empty());
+        assert!(!revisions[0].path().try_exists().unwrap());
+        assert_eq!(vault.check(None).unwrap(), vec![]);
+    }
+}
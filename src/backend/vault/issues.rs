@@ -0,0 +1,162 @@
+//! Problems with the coherency of the [Database](super::database::Database) & the filesystem,
+//! found by [Vault::check](super::Vault::check).
+use std::fmt;
+
+use camino::Utf8PathBuf;
+
+use super::super::encryption::associated_data::EntityType;
+
+/// A problem found by [Vault::check](super::Vault::check), which
+/// [Vault::repair](super::Vault::repair) can fix.
+///
+/// Accounts are named by the name they are stored under, which is a hash of their username in
+/// privacy mode.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum VaultIssue {
+    /// An account's file directory is missing. Repaired by creating an empty one.
+    MissingAccountDirectory {
+        owner_username: String,
+        path: Utf8PathBuf,
+    },
+    /// A directory in the filesystem directory belongs to no account. Repaired by deleting it &
+    /// everything in it.
+    OrphanedDirectory { path: Utf8PathBuf },
+    /// A file in an account's file directory, or in the `file_contents` table of a single-file
+    /// vault, belongs to no file. Repaired by deleting it.
+    OrphanedFile { path: Utf8PathBuf },
+    /// A file's data is in the database, but its encrypted contents are missing. Repaired by
+    /// deleting the file's data, & its shared copy if it's shared.
+    MissingFile {
+        owner_username: String,
+        path: Utf8PathBuf,
+    },
+    /// The encrypted contents of the shared copy of a file are missing. Repaired by deleting the
+    /// shared copy, which stops sharing the file.
+    MissingSharedFile {
+        owner_username: String,
+        shared_id: String,
+        path: Utf8PathBuf,
+    },
+    /// The encrypted contents of a previous revision of a file are missing. Repaired by deleting
+    /// the revision's data.
+    MissingFileRevision {
+        owner_username: String,
+        id: String,
+        path: Utf8PathBuf,
+    },
+    /// The shared copy of a credential or file outlived the original. Repaired by deleting the
+    /// shared copy.
+    OrphanedSharedCopy {
+        owner_username: String,
+        shared_id: String,
+        entity_type: EntityType,
+    },
+    /// A credential can't be decrypted with its owner's key. Repaired by deleting it.
+    UndecryptableCredential { owner_username: String, id: String },
+    /// A file's name or contents can't be decrypted with its owner's key. Repaired by deleting the
+    /// file.
+    UndecryptableFile {
+        owner_username: String,
+        path: Utf8PathBuf,
+    },
+    /// More than one of an account's credentials have the same name. Repaired by renaming all but
+    /// one of them to `"<name> (2)"`, `"<name> (3)"`, & so on.
+    DuplicateCredentialName {
+        owner_username: String,
+        name: String,
+        count: usize,
+    },
+}
+impl VaultIssue {
+    /// Describe how [Vault::repair](super::Vault::repair) fixes this [VaultIssue].
+    pub fn repair_description(&self) -> &'static str {
+        match self {
+            Self::MissingAccountDirectory { .. } => "Create an empty directory",
+            Self::OrphanedDirectory { .. } => "Delete the directory & everything in it",
+            Self::OrphanedFile { .. } => "Delete the file",
+            Self::MissingFile { .. } => "Delete the file's data",
+            Self::MissingFileRevision { .. } => "Delete the revision's data",
+            Self::MissingSharedFile { .. } | Self::OrphanedSharedCopy { .. } => {
+                "Delete the shared copy"
+            }
+            Self::UndecryptableCredential { .. } => "Delete the credential",
+            Self::UndecryptableFile { .. } => "Delete the file",
+            Self::DuplicateCredentialName { .. } => "Rename the duplicates",
+        }
+    }
+}
+impl fmt::Display for VaultIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingAccountDirectory {
+                owner_username,
+                path,
+            } => write!(
+                f,
+                "The directory of account \"{}\" is missing: {}",
+                owner_username, path
+            ),
+            Self::OrphanedDirectory { path } => {
+                write!(f, "Directory belongs to no account: {}", path)
+            }
+            Self::OrphanedFile { path } => write!(f, "File belongs to no entry: {}", path),
+            Self::MissingFile {
+                owner_username,
+                path,
+            } => write!(
+                f,
+                "A file of account \"{}\" is missing: {}",
+                owner_username, path
+            ),
+            Self::MissingSharedFile {
+                owner_username,
+                path,
+                ..
+            } => write!(
+                f,
+                "A shared file of account \"{}\" is missing: {}",
+                owner_username, path
+            ),
+            Self::MissingFileRevision {
+                owner_username,
+                path,
+                ..
+            } => write!(
+                f,
+                "A file revision of account \"{}\" is missing: {}",
+                owner_username, path
+            ),
+            Self::OrphanedSharedCopy {
+                owner_username,
+                shared_id,
+                entity_type,
+            } => write!(
+                f,
+                "A shared {} of account \"{}\" outlived the original: {}",
+                entity_type, owner_username, shared_id
+            ),
+            Self::UndecryptableCredential { owner_username, id } => write!(
+                f,
+                "A credential of account \"{}\" can't be decrypted: {}",
+                owner_username, id
+            ),
+            Self::UndecryptableFile {
+                owner_username,
+                path,
+            } => write!(
+                f,
+                "A file of account \"{}\" can't be decrypted: {}",
+                owner_username, path
+            ),
+            Self::DuplicateCredentialName {
+                owner_username,
+                name,
+                count,
+            } => write!(
+                f,
+                "{} credentials of account \"{}\" are named \"{}\"",
+                count, owner_username, name
+            ),
+        }
+    }
+}
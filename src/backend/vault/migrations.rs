@@ -0,0 +1,1066 @@
+//! Versioned upgrades of the [Database](super::database::Database) schema.
+//!
+//! The schema version of a database is stored in its `user_version`. Each [Migration] upgrades the
+//! schema by one version in its own transaction, so an interrupted upgrade leaves the database at
+//! the last version it reached. A backup of the database is taken before it is upgraded.
+//!
+//! Foreign keys aren't enforced while a [Migration] runs, so that it can rebuild tables. Instead,
+//! they are checked before its transaction is committed.
+//!
+//! Each [Migration] keeps its own copy of the SQL it runs, so that it always upgrades a database
+//! the same way, whatever the current schema is. New databases are created with the current schema
+//! in `sql_schemas` instead.
+use std::{
+    io::Read,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64ct::{Base64, Encoding};
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::{self, eyre};
+use rusqlite::{params_from_iter, types::Value, Connection, DatabaseName};
+use uuid::Uuid;
+
+use super::sql_schemas::CREATE_SCHEMA;
+
+/// A single upgrade of the [Database](super::database::Database) schema.
+struct Migration {
+    /// The schema version this [Migration] upgrades to.
+    version: u32,
+    /// Apply this [Migration]. It runs inside a transaction.
+    apply: fn(&Connection) -> eyre::Result<()>,
+}
+
+/// Every [Migration], in order. The schema version of an empty database is 0.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        apply: migrate_to_v1,
+    },
+    Migration {
+        version: 2,
+        apply: migrate_to_v2,
+    },
+    Migration {
+        version: 3,
+        apply: migrate_to_v3,
+    },
+    Migration {
+        version: 4,
+        apply: migrate_to_v4,
+    },
+    Migration {
+        version: 5,
+        apply: migrate_to_v5,
+    },
+    Migration {
+        version: 6,
+        apply: migrate_to_v6,
+    },
+    Migration {
+        version: 7,
+        apply: migrate_to_v7,
+    },
+    Migration {
+        version: 8,
+        apply: migrate_to_v8,
+    },
+    Migration {
+        version: 9,
+        apply: migrate_to_v9,
+    },
+];
+
+/// The schema version written by this version of `dgruft`.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Upgrade the schema of the database at `path` to the [SCHEMA_VERSION], backing it up first.
+///
+/// Databases written by a newer version of `dgruft` aren't touched.
+pub fn migrate(connection: &mut Connection, path: &Utf8Path) -> eyre::Result<()> {
+    upgrade(connection, path, true)
+}
+
+/// Upgrade the schema of the database at `path` to the [SCHEMA_VERSION] like [migrate], without
+/// backing it up, for copies of databases such as those extracted from backup archives.
+pub fn migrate_copy(connection: &mut Connection, path: &Utf8Path) -> eyre::Result<()> {
+    upgrade(connection, path, false)
+}
+
+// Helper function: Upgrade the schema of the database at `path` to the [SCHEMA_VERSION], backing
+// it up first if `back_up`.
+fn upgrade(connection: &mut Connection, path: &Utf8Path, back_up: bool) -> eyre::Result<()> {
+    let version = schema_version(connection)?;
+    if version > SCHEMA_VERSION {
+        return Err(eyre!(
+            "The vault database at {:?} was written by a newer version of dgruft (schema version {}). This version only supports schema versions up to {}.",
+            path,
+            version,
+            SCHEMA_VERSION
+        ));
+    }
+    if version == SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    // Create new databases with the current schema.
+    let table_count: u32 =
+        connection.query_row("SELECT count(*) FROM sqlite_master", [], |row| row.get(0))?;
+    if table_count == 0 {
+        return create_schema(connection);
+    }
+
+    if back_up {
+        backup(connection, path, version)?;
+    }
+
+    apply_migrations(connection, version, SCHEMA_VERSION)
+}
+
+/// Upgrade the schema of the database to the given `target` version, without backing it up, to
+/// build databases written by older versions of `dgruft`.
+#[cfg(test)]
+pub fn migrate_to(connection: &mut Connection, target: u32) -> eyre::Result<()> {
+    let version = schema_version(connection)?;
+    apply_migrations(connection, version, target)
+}
+
+/// Return the schema version of the database.
+pub fn schema_version(connection: &Connection) -> eyre::Result<u32> {
+    Ok(connection.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+// Helper function: Create every table of the current schema in an empty database.
+fn create_schema(connection: &mut Connection) -> eyre::Result<()> {
+    let tx = connection.transaction()?;
+    for create_statement in CREATE_SCHEMA {
+        tx.execute(create_statement, ())?;
+    }
+    tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    tx.commit()?;
+    Ok(())
+}
+
+// Helper function: Apply every [Migration] past the given schema `version`, up to the `target`
+// version, each in its own transaction.
+fn apply_migrations(connection: &mut Connection, version: u32, target: u32) -> eyre::Result<()> {
+    connection.pragma_update(None, "foreign_keys", false)?;
+    let result = apply_migrations_unchecked(connection, version, target);
+    connection.pragma_update(None, "foreign_keys", true)?;
+    result
+}
+
+// Helper function: Apply the [Migration]s of [apply_migrations] while foreign keys aren't
+// enforced.
+fn apply_migrations_unchecked(
+    connection: &mut Connection,
+    version: u32,
+    target: u32,
+) -> eyre::Result<()> {
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > version && migration.version <= target)
+    {
+        let tx = connection.transaction()?;
+        (migration.apply)(&tx)
+            .and_then(|()| check_foreign_keys(&tx))
+            .and_then(|()| Ok(tx.pragma_update(None, "user_version", migration.version)?))
+            .map_err(|err| {
+                eyre!(
+                    "Failed to upgrade the vault database to schema version {}: {}",
+                    migration.version,
+                    err
+                )
+            })?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+// Helper function: Return [Err] if any row refers to a row which doesn't exist.
+fn check_foreign_keys(connection: &Connection) -> eyre::Result<()> {
+    let violations: u32 =
+        connection.query_row("SELECT count(*) FROM pragma_foreign_key_check", [], |row| {
+            row.get(0)
+        })?;
+    if violations > 0 {
+        return Err(eyre!(
+            "{} rows refer to rows which don't exist.",
+            violations
+        ));
+    }
+    Ok(())
+}
+
+// Helper function: Write a copy of the database at `path` next to it, named after its schema
+// `version` & the current time.
+fn backup(connection: &Connection, path: &Utf8Path, version: u32) -> eyre::Result<()> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut backup_path = Utf8PathBuf::from(format!("{path}.v{version}-{timestamp}.bak"));
+    let mut i = 1;
+    while backup_path.try_exists()? {
+        backup_path = Utf8PathBuf::from(format!("{path}.v{version}-{timestamp}-{i}.bak"));
+        i += 1;
+    }
+    connection
+        .execute("VACUUM INTO ?1", [backup_path.as_str()])
+        .map_err(|err| {
+            eyre!(
+                "Failed to back up the vault database to {:?}: {}",
+                backup_path,
+                err
+            )
+        })?;
+    Ok(())
+}
+
+// Migration: Create every table, & add any columns missing from tables created before schema
+// versions were recorded. Every value in these tables is stored as base-64 TEXT.
+fn migrate_to_v1(connection: &Connection) -> eyre::Result<()> {
+    const CREATE_TABLES: &[&str] = &[
+        "CREATE TABLE IF NOT EXISTS accounts (
+            username TEXT PRIMARY KEY,
+            password_salt TEXT NOT NULL,
+            dbl_hashed_password_hash TEXT NOT NULL,
+            dbl_hashed_password_salt TEXT NOT NULL,
+            encrypted_key_cipherbytes TEXT NOT NULL,
+            encrypted_key_nonce TEXT NOT NULL,
+            encrypted_key_cipher TEXT NOT NULL,
+            kdf_algorithm TEXT NOT NULL,
+            kdf_memory_kib TEXT NOT NULL,
+            kdf_time_cost TEXT NOT NULL,
+            kdf_parallelism TEXT NOT NULL,
+            encryption_version TEXT NOT NULL,
+            key_file_salt TEXT NOT NULL,
+            key_file_hash TEXT NOT NULL,
+            recovery_encrypted_key_cipherbytes TEXT NOT NULL,
+            recovery_encrypted_key_nonce TEXT NOT NULL,
+            recovery_encrypted_key_cipher TEXT NOT NULL,
+            encrypted_recovery_key_cipherbytes TEXT NOT NULL,
+            encrypted_recovery_key_nonce TEXT NOT NULL,
+            encrypted_recovery_key_cipher TEXT NOT NULL,
+            encrypted_totp_secret_cipherbytes TEXT NOT NULL,
+            encrypted_totp_secret_nonce TEXT NOT NULL,
+            encrypted_totp_secret_cipher TEXT NOT NULL,
+            share_encrypted_key_cipherbytes TEXT NOT NULL,
+            share_encrypted_key_nonce TEXT NOT NULL,
+            share_encrypted_key_cipher TEXT NOT NULL,
+            encrypted_share_key_cipherbytes TEXT NOT NULL,
+            encrypted_share_key_nonce TEXT NOT NULL,
+            encrypted_share_key_cipher TEXT NOT NULL,
+            public_key TEXT NOT NULL,
+            encrypted_private_key_cipherbytes TEXT NOT NULL,
+            encrypted_private_key_nonce TEXT NOT NULL,
+            encrypted_private_key_cipher TEXT NOT NULL
+        )",
+        "CREATE TABLE IF NOT EXISTS credentials (
+            id TEXT NOT NULL,
+            owner_username TEXT NOT NULL,
+            encrypted_name_cipherbytes TEXT NOT NULL,
+            encrypted_name_nonce TEXT NOT NULL,
+            encrypted_name_cipher TEXT NOT NULL,
+            encrypted_username_cipherbytes TEXT NOT NULL,
+            encrypted_username_nonce TEXT NOT NULL,
+            encrypted_username_cipher TEXT NOT NULL,
+            encrypted_password_cipherbytes TEXT NOT NULL,
+            encrypted_password_nonce TEXT NOT NULL,
+            encrypted_password_cipher TEXT NOT NULL,
+            encrypted_notes_cipherbytes TEXT NOT NULL,
+            encrypted_notes_nonce TEXT NOT NULL,
+            encrypted_notes_cipher TEXT NOT NULL,
+            name_index TEXT NOT NULL,
+            FOREIGN KEY (owner_username)
+                REFERENCES accounts(username)
+                ON DELETE CASCADE,
+            PRIMARY KEY(owner_username, encrypted_name_cipherbytes)
+        )",
+        "CREATE TABLE IF NOT EXISTS files_data (
+            id TEXT NOT NULL,
+            path TEXT PRIMARY KEY,
+            filename TEXT NOT NULL,
+            owner_username TEXT NOT NULL,
+            contents_nonce TEXT NOT NULL,
+            contents_cipher TEXT NOT NULL,
+            contents_chunk_size TEXT NOT NULL,
+            encrypted_filename_cipherbytes TEXT NOT NULL,
+            encrypted_filename_nonce TEXT NOT NULL,
+            encrypted_filename_cipher TEXT NOT NULL,
+            FOREIGN KEY (owner_username)
+                REFERENCES accounts(username)
+                ON DELETE CASCADE
+        )",
+        "CREATE TABLE IF NOT EXISTS totp_backups (
+            id TEXT NOT NULL,
+            owner_username TEXT NOT NULL,
+            encrypted_secret_cipherbytes TEXT NOT NULL,
+            encrypted_secret_nonce TEXT NOT NULL,
+            encrypted_secret_cipher TEXT NOT NULL,
+            FOREIGN KEY (owner_username)
+                REFERENCES accounts(username)
+                ON DELETE CASCADE,
+            PRIMARY KEY(owner_username, id)
+        )",
+        "CREATE TABLE IF NOT EXISTS shared_credentials (
+            id TEXT PRIMARY KEY,
+            owner_username TEXT NOT NULL,
+            item_id TEXT NOT NULL,
+            encrypted_item_key_cipherbytes TEXT NOT NULL,
+            encrypted_item_key_nonce TEXT NOT NULL,
+            encrypted_item_key_cipher TEXT NOT NULL,
+            encrypted_name_cipherbytes TEXT NOT NULL,
+            encrypted_name_nonce TEXT NOT NULL,
+            encrypted_name_cipher TEXT NOT NULL,
+            encrypted_username_cipherbytes TEXT NOT NULL,
+            encrypted_username_nonce TEXT NOT NULL,
+            encrypted_username_cipher TEXT NOT NULL,
+            encrypted_password_cipherbytes TEXT NOT NULL,
+            encrypted_password_nonce TEXT NOT NULL,
+            encrypted_password_cipher TEXT NOT NULL,
+            encrypted_notes_cipherbytes TEXT NOT NULL,
+            encrypted_notes_nonce TEXT NOT NULL,
+            encrypted_notes_cipher TEXT NOT NULL,
+            FOREIGN KEY (owner_username)
+                REFERENCES accounts(username)
+                ON DELETE CASCADE
+        )",
+        "CREATE TABLE IF NOT EXISTS shared_files_data (
+            id TEXT PRIMARY KEY,
+            path TEXT NOT NULL,
+            owner_username TEXT NOT NULL,
+            item_id TEXT NOT NULL,
+            encrypted_item_key_cipherbytes TEXT NOT NULL,
+            encrypted_item_key_nonce TEXT NOT NULL,
+            encrypted_item_key_cipher TEXT NOT NULL,
+            contents_nonce TEXT NOT NULL,
+            contents_cipher TEXT NOT NULL,
+            contents_chunk_size TEXT NOT NULL,
+            encrypted_filename_cipherbytes TEXT NOT NULL,
+            encrypted_filename_nonce TEXT NOT NULL,
+            encrypted_filename_cipher TEXT NOT NULL,
+            FOREIGN KEY (owner_username)
+                REFERENCES accounts(username)
+                ON DELETE CASCADE
+        )",
+        "CREATE TABLE IF NOT EXISTS share_grants (
+            shared_id TEXT NOT NULL,
+            owner_username TEXT NOT NULL,
+            recipient_username TEXT NOT NULL,
+            item_type TEXT NOT NULL,
+            ephemeral_public_key TEXT NOT NULL,
+            encrypted_item_key_cipherbytes TEXT NOT NULL,
+            encrypted_item_key_nonce TEXT NOT NULL,
+            encrypted_item_key_cipher TEXT NOT NULL,
+            FOREIGN KEY (owner_username)
+                REFERENCES accounts(username)
+                ON DELETE CASCADE,
+            FOREIGN KEY (recipient_username)
+                REFERENCES accounts(username)
+                ON DELETE CASCADE,
+            PRIMARY KEY(shared_id, recipient_username)
+        )",
+        "CREATE TABLE IF NOT EXISTS settings (
+            name TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+    ];
+    // The columns added to the tables of older versions of `dgruft`, with their definitions. The
+    // defaults are base-64-encoded values which keep the old behaviour of existing rows:
+    // - PBKDF2-HMAC-SHA256 with 50,000 iterations, the only KDF of older accounts;
+    // - encryption version 0, from before associated data was added;
+    // - AES-256-GCM, the only cipher before XChaCha20-Poly1305 was added;
+    // - a chunk size of 0, marking files encrypted as a single message.
+    // Empty defaults mean that a row lacks the feature, or that its id or name index is filled in
+    // later.
+    const MISSING_COLUMNS: &[(&str, &str, &str)] = &[
+        (
+            "accounts",
+            "kdf_algorithm",
+            "TEXT NOT NULL DEFAULT 'cGJrZGYyLWhtYWMtc2hhMjU2'",
+        ),
+        (
+            "accounts",
+            "kdf_memory_kib",
+            "TEXT NOT NULL DEFAULT 'AAAAAA=='",
+        ),
+        (
+            "accounts",
+            "kdf_time_cost",
+            "TEXT NOT NULL DEFAULT 'AADDUA=='",
+        ),
+        (
+            "accounts",
+            "kdf_parallelism",
+            "TEXT NOT NULL DEFAULT 'AAAAAQ=='",
+        ),
+        (
+            "accounts",
+            "encryption_version",
+            "TEXT NOT NULL DEFAULT 'AAAAAA=='",
+        ),
+        (
+            "accounts",
+            "encrypted_key_cipher",
+            "TEXT NOT NULL DEFAULT 'YWVzLTI1Ni1nY20='",
+        ),
+        ("accounts", "key_file_salt", "TEXT NOT NULL DEFAULT ''"),
+        ("accounts", "key_file_hash", "TEXT NOT NULL DEFAULT ''"),
+        (
+            "accounts",
+            "recovery_encrypted_key_cipherbytes",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "accounts",
+            "recovery_encrypted_key_nonce",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "accounts",
+            "recovery_encrypted_key_cipher",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "accounts",
+            "encrypted_recovery_key_cipherbytes",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "accounts",
+            "encrypted_recovery_key_nonce",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "accounts",
+            "encrypted_recovery_key_cipher",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "accounts",
+            "encrypted_totp_secret_cipherbytes",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "accounts",
+            "encrypted_totp_secret_nonce",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "accounts",
+            "encrypted_totp_secret_cipher",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "accounts",
+            "share_encrypted_key_cipherbytes",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "accounts",
+            "share_encrypted_key_nonce",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "accounts",
+            "share_encrypted_key_cipher",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "accounts",
+            "encrypted_share_key_cipherbytes",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "accounts",
+            "encrypted_share_key_nonce",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "accounts",
+            "encrypted_share_key_cipher",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        ("accounts", "public_key", "TEXT NOT NULL DEFAULT ''"),
+        (
+            "accounts",
+            "encrypted_private_key_cipherbytes",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "accounts",
+            "encrypted_private_key_nonce",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "accounts",
+            "encrypted_private_key_cipher",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        ("credentials", "id", "TEXT NOT NULL DEFAULT ''"),
+        (
+            "credentials",
+            "encrypted_name_cipher",
+            "TEXT NOT NULL DEFAULT 'YWVzLTI1Ni1nY20='",
+        ),
+        (
+            "credentials",
+            "encrypted_username_cipher",
+            "TEXT NOT NULL DEFAULT 'YWVzLTI1Ni1nY20='",
+        ),
+        (
+            "credentials",
+            "encrypted_password_cipher",
+            "TEXT NOT NULL DEFAULT 'YWVzLTI1Ni1nY20='",
+        ),
+        (
+            "credentials",
+            "encrypted_notes_cipher",
+            "TEXT NOT NULL DEFAULT 'YWVzLTI1Ni1nY20='",
+        ),
+        ("credentials", "name_index", "TEXT NOT NULL DEFAULT ''"),
+        ("files_data", "id", "TEXT NOT NULL DEFAULT ''"),
+        (
+            "files_data",
+            "contents_cipher",
+            "TEXT NOT NULL DEFAULT 'YWVzLTI1Ni1nY20='",
+        ),
+        (
+            "files_data",
+            "contents_chunk_size",
+            "TEXT NOT NULL DEFAULT 'AAAAAA=='",
+        ),
+        (
+            "files_data",
+            "encrypted_filename_cipherbytes",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "files_data",
+            "encrypted_filename_nonce",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "files_data",
+            "encrypted_filename_cipher",
+            "TEXT NOT NULL DEFAULT ''",
+        ),
+    ];
+
+    // Create tables iff they don't exist
+    for create_statement in CREATE_TABLES {
+        connection.execute(create_statement, ())?;
+    }
+    // Add any columns missing from tables created by older versions of `dgruft`.
+    for (table, column, definition) in MISSING_COLUMNS {
+        add_missing_column(connection, table, column, definition)?;
+    }
+    // Only non-empty name indices must be unique.
+    connection.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS credentials_name_index
+        ON credentials(owner_username, name_index)
+        WHERE name_index != ''",
+        (),
+    )?;
+
+    // Give ids to any credentials & files which lack them.
+    fill_missing_ids(connection, "credentials")?;
+    fill_missing_ids(connection, "files_data")?;
+
+    Ok(())
+}
+
+// Migration: Create the table which holds the encrypted contents of files in single-file vaults.
+fn migrate_to_v2(connection: &Connection) -> eyre::Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS file_contents (
+            path TEXT PRIMARY KEY,
+            owner_username TEXT NOT NULL,
+            contents BLOB NOT NULL,
+            FOREIGN KEY (owner_username)
+                REFERENCES accounts(username)
+                ON DELETE CASCADE
+        )",
+        (),
+    )?;
+    Ok(())
+}
+
+// Migration: Store bytes as BLOBs, numbers as INTEGERs & text as TEXT, instead of storing every
+// value as base-64 TEXT. Each table is rebuilt as a STRICT table, so that the column types are
+// enforced.
+fn migrate_to_v3(connection: &Connection) -> eyre::Result<()> {
+    const STRICT_TABLES: &[(&str, &str)] = &[
+        (
+            "accounts",
+            "CREATE TABLE accounts (
+                username TEXT PRIMARY KEY,
+                password_salt BLOB NOT NULL,
+                dbl_hashed_password_hash BLOB NOT NULL,
+                dbl_hashed_password_salt BLOB NOT NULL,
+                encrypted_key_cipherbytes BLOB NOT NULL,
+                encrypted_key_nonce BLOB NOT NULL,
+                encrypted_key_cipher TEXT NOT NULL,
+                kdf_algorithm TEXT NOT NULL,
+                kdf_memory_kib INTEGER NOT NULL,
+                kdf_time_cost INTEGER NOT NULL,
+                kdf_parallelism INTEGER NOT NULL,
+                encryption_version INTEGER NOT NULL,
+                key_file_salt BLOB NOT NULL,
+                key_file_hash BLOB NOT NULL,
+                recovery_encrypted_key_cipherbytes BLOB NOT NULL,
+                recovery_encrypted_key_nonce BLOB NOT NULL,
+                recovery_encrypted_key_cipher TEXT NOT NULL,
+                encrypted_recovery_key_cipherbytes BLOB NOT NULL,
+                encrypted_recovery_key_nonce BLOB NOT NULL,
+                encrypted_recovery_key_cipher TEXT NOT NULL,
+                encrypted_totp_secret_cipherbytes BLOB NOT NULL,
+                encrypted_totp_secret_nonce BLOB NOT NULL,
+                encrypted_totp_secret_cipher TEXT NOT NULL,
+                share_encrypted_key_cipherbytes BLOB NOT NULL,
+                share_encrypted_key_nonce BLOB NOT NULL,
+                share_encrypted_key_cipher TEXT NOT NULL,
+                encrypted_share_key_cipherbytes BLOB NOT NULL,
+                encrypted_share_key_nonce BLOB NOT NULL,
+                encrypted_share_key_cipher TEXT NOT NULL,
+                public_key BLOB NOT NULL,
+                encrypted_private_key_cipherbytes BLOB NOT NULL,
+                encrypted_private_key_nonce BLOB NOT NULL,
+                encrypted_private_key_cipher TEXT NOT NULL
+            ) STRICT",
+        ),
+        (
+            "credentials",
+            "CREATE TABLE credentials (
+                id TEXT NOT NULL,
+                owner_username TEXT NOT NULL,
+                encrypted_name_cipherbytes BLOB NOT NULL,
+                encrypted_name_nonce BLOB NOT NULL,
+                encrypted_name_cipher TEXT NOT NULL,
+                encrypted_username_cipherbytes BLOB NOT NULL,
+                encrypted_username_nonce BLOB NOT NULL,
+                encrypted_username_cipher TEXT NOT NULL,
+                encrypted_password_cipherbytes BLOB NOT NULL,
+                encrypted_password_nonce BLOB NOT NULL,
+                encrypted_password_cipher TEXT NOT NULL,
+                encrypted_notes_cipherbytes BLOB NOT NULL,
+                encrypted_notes_nonce BLOB NOT NULL,
+                encrypted_notes_cipher TEXT NOT NULL,
+                name_index BLOB NOT NULL,
+                FOREIGN KEY (owner_username)
+                    REFERENCES accounts(username)
+                    ON DELETE CASCADE,
+                PRIMARY KEY(owner_username, encrypted_name_cipherbytes)
+            ) STRICT",
+        ),
+        (
+            "files_data",
+            "CREATE TABLE files_data (
+                id TEXT NOT NULL,
+                path TEXT PRIMARY KEY,
+                filename TEXT NOT NULL,
+                owner_username TEXT NOT NULL,
+                contents_nonce BLOB NOT NULL,
+                contents_cipher TEXT NOT NULL,
+                contents_chunk_size INTEGER NOT NULL,
+                encrypted_filename_cipherbytes BLOB NOT NULL,
+                encrypted_filename_nonce BLOB NOT NULL,
+                encrypted_filename_cipher TEXT NOT NULL,
+                FOREIGN KEY (owner_username)
+                    REFERENCES accounts(username)
+                    ON DELETE CASCADE
+            ) STRICT",
+        ),
+        (
+            "totp_backups",
+            "CREATE TABLE totp_backups (
+                id TEXT NOT NULL,
+                owner_username TEXT NOT NULL,
+                encrypted_secret_cipherbytes BLOB NOT NULL,
+                encrypted_secret_nonce BLOB NOT NULL,
+                encrypted_secret_cipher TEXT NOT NULL,
+                FOREIGN KEY (owner_username)
+                    REFERENCES accounts(username)
+                    ON DELETE CASCADE,
+                PRIMARY KEY(owner_username, id)
+            ) STRICT",
+        ),
+        (
+            "shared_credentials",
+            "CREATE TABLE shared_credentials (
+                id TEXT PRIMARY KEY,
+                owner_username TEXT NOT NULL,
+                item_id TEXT NOT NULL,
+                encrypted_item_key_cipherbytes BLOB NOT NULL,
+                encrypted_item_key_nonce BLOB NOT NULL,
+                encrypted_item_key_cipher TEXT NOT NULL,
+                encrypted_name_cipherbytes BLOB NOT NULL,
+                encrypted_name_nonce BLOB NOT NULL,
+                encrypted_name_cipher TEXT NOT NULL,
+                encrypted_username_cipherbytes BLOB NOT NULL,
+                encrypted_username_nonce BLOB NOT NULL,
+                encrypted_username_cipher TEXT NOT NULL,
+                encrypted_password_cipherbytes BLOB NOT NULL,
+                encrypted_password_nonce BLOB NOT NULL,
+                encrypted_password_cipher TEXT NOT NULL,
+                encrypted_notes_cipherbytes BLOB NOT NULL,
+                encrypted_notes_nonce BLOB NOT NULL,
+                encrypted_notes_cipher TEXT NOT NULL,
+                FOREIGN KEY (owner_username)
+                    REFERENCES accounts(username)
+                    ON DELETE CASCADE
+            ) STRICT",
+        ),
+        (
+            "shared_files_data",
+            "CREATE TABLE shared_files_data (
+                id TEXT PRIMARY KEY,
+                path TEXT NOT NULL,
+                owner_username TEXT NOT NULL,
+                item_id TEXT NOT NULL,
+                encrypted_item_key_cipherbytes BLOB NOT NULL,
+                encrypted_item_key_nonce BLOB NOT NULL,
+                encrypted_item_key_cipher TEXT NOT NULL,
+                contents_nonce BLOB NOT NULL,
+                contents_cipher TEXT NOT NULL,
+                contents_chunk_size INTEGER NOT NULL,
+                encrypted_filename_cipherbytes BLOB NOT NULL,
+                encrypted_filename_nonce BLOB NOT NULL,
+                encrypted_filename_cipher TEXT NOT NULL,
+                FOREIGN KEY (owner_username)
+                    REFERENCES accounts(username)
+                    ON DELETE CASCADE
+            ) STRICT",
+        ),
+        (
+            "share_grants",
+            "CREATE TABLE share_grants (
+                shared_id TEXT NOT NULL,
+                owner_username TEXT NOT NULL,
+                recipient_username TEXT NOT NULL,
+                item_type TEXT NOT NULL,
+                ephemeral_public_key BLOB NOT NULL,
+                encrypted_item_key_cipherbytes BLOB NOT NULL,
+                encrypted_item_key_nonce BLOB NOT NULL,
+                encrypted_item_key_cipher TEXT NOT NULL,
+                FOREIGN KEY (owner_username)
+                    REFERENCES accounts(username)
+                    ON DELETE CASCADE,
+                FOREIGN KEY (recipient_username)
+                    REFERENCES accounts(username)
+                    ON DELETE CASCADE,
+                PRIMARY KEY(shared_id, recipient_username)
+            ) STRICT",
+        ),
+        (
+            "settings",
+            "CREATE TABLE settings (
+                name TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            ) STRICT",
+        ),
+        (
+            "file_contents",
+            "CREATE TABLE file_contents (
+                path TEXT PRIMARY KEY,
+                owner_username TEXT NOT NULL,
+                contents BLOB NOT NULL,
+                FOREIGN KEY (owner_username)
+                    REFERENCES accounts(username)
+                    ON DELETE CASCADE
+            ) STRICT",
+        ),
+    ];
+
+    // The name index moves with the credentials table when it's renamed, so it's recreated after.
+    connection.execute("DROP INDEX IF EXISTS credentials_name_index", ())?;
+    // Keep the foreign keys of other tables referring to the original table names.
+    connection.pragma_update(None, "legacy_alter_table", true)?;
+    for (table, create_statement) in STRICT_TABLES {
+        rebuild_strict_table(connection, table, create_statement)?;
+    }
+    connection.pragma_update(None, "legacy_alter_table", false)?;
+    // Only non-empty name indices must be unique.
+    connection.execute(
+        "CREATE UNIQUE INDEX credentials_name_index
+        ON credentials(owner_username, name_index)
+        WHERE length(name_index) > 0",
+        (),
+    )?;
+    Ok(())
+}
+
+// Migration: Create the table which holds the previous versions of credentials.
+fn migrate_to_v4(connection: &Connection) -> eyre::Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS credential_history (
+            id TEXT NOT NULL,
+            credential_id TEXT NOT NULL,
+            owner_username TEXT NOT NULL,
+            replaced_at INTEGER NOT NULL,
+            encrypted_username_cipherbytes BLOB NOT NULL,
+            encrypted_username_nonce BLOB NOT NULL,
+            encrypted_username_cipher TEXT NOT NULL,
+            encrypted_password_cipherbytes BLOB NOT NULL,
+            encrypted_password_nonce BLOB NOT NULL,
+            encrypted_password_cipher TEXT NOT NULL,
+            encrypted_notes_cipherbytes BLOB NOT NULL,
+            encrypted_notes_nonce BLOB NOT NULL,
+            encrypted_notes_cipher TEXT NOT NULL,
+            FOREIGN KEY (owner_username)
+                REFERENCES accounts(username)
+                ON DELETE CASCADE,
+            PRIMARY KEY(owner_username, id)
+        ) STRICT",
+        (),
+    )?;
+    Ok(())
+}
+
+// Migration: Create the table which holds the previous revisions of files.
+fn migrate_to_v5(connection: &Connection) -> eyre::Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS file_revisions (
+            id TEXT PRIMARY KEY,
+            file_id TEXT NOT NULL,
+            owner_username TEXT NOT NULL,
+            path TEXT NOT NULL UNIQUE,
+            replaced_at INTEGER NOT NULL,
+            contents_nonce BLOB NOT NULL,
+            contents_cipher TEXT NOT NULL,
+            contents_chunk_size INTEGER NOT NULL,
+            FOREIGN KEY (owner_username)
+                REFERENCES accounts(username)
+                ON DELETE CASCADE
+        ) STRICT",
+        (),
+    )?;
+    Ok(())
+}
+
+// Migration: Create the table which records the items in each account's trash.
+fn migrate_to_v6(connection: &Connection) -> eyre::Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS trash (
+            owner_username TEXT NOT NULL,
+            item_type TEXT NOT NULL,
+            item_id TEXT NOT NULL,
+            trashed_at INTEGER NOT NULL,
+            FOREIGN KEY (owner_username)
+                REFERENCES accounts(username)
+                ON DELETE CASCADE,
+            PRIMARY KEY(owner_username, item_type, item_id)
+        ) STRICT",
+        (),
+    )?;
+    Ok(())
+}
+
+// Migration: Create the table which records the time step of the last TOTP code each account logged
+// in with.
+fn migrate_to_v7(connection: &Connection) -> eyre::Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS totp_steps (
+            owner_username TEXT PRIMARY KEY,
+            last_step INTEGER NOT NULL,
+            FOREIGN KEY (owner_username)
+                REFERENCES accounts(username)
+                ON DELETE CASCADE
+        ) STRICT",
+        (),
+    )?;
+    Ok(())
+}
+
+// Migration: Add the blind index of each file's name, which is filled in when the file's owner next
+// logs in. Only non-empty name indices must be unique.
+fn migrate_to_v8(connection: &Connection) -> eyre::Result<()> {
+    add_missing_column(
+        connection,
+        "files_data",
+        "name_index",
+        "BLOB NOT NULL DEFAULT x''",
+    )?;
+    connection.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS files_data_name_index
+        ON files_data(owner_username, name_index)
+        WHERE length(name_index) > 0",
+        (),
+    )?;
+    Ok(())
+}
+
+// Migration: Split the contents of each file of a single-file vault into chunks of at most 1 MiB,
+// so that files can be streamed in & out of the database. Each file's contents are read from its
+// BLOB incrementally, so no file is held in memory.
+fn migrate_to_v9(connection: &Connection) -> eyre::Result<()> {
+    const CHUNK_SIZE: u64 = 1024 * 1024;
+
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS file_chunks (
+            path TEXT NOT NULL,
+            chunk_index INTEGER NOT NULL,
+            contents BLOB NOT NULL,
+            FOREIGN KEY (path)
+                REFERENCES file_contents(path)
+                ON DELETE CASCADE
+                ON UPDATE CASCADE,
+            PRIMARY KEY(path, chunk_index)
+        ) STRICT",
+        (),
+    )?;
+    if !column_names(connection, "file_contents")?
+        .iter()
+        .any(|name| name == "contents")
+    {
+        return Ok(());
+    }
+
+    let mut statement = connection.prepare("SELECT rowid, path FROM file_contents")?;
+    let files = statement
+        .query_map([], |row| {
+            Ok((row.get::<usize, i64>(0)?, row.get::<usize, String>(1)?))
+        })?
+        .collect::<Result<Vec<(i64, String)>, _>>()?;
+    let mut insert = connection
+        .prepare("INSERT INTO file_chunks (path, chunk_index, contents) VALUES (?1, ?2, ?3)")?;
+    let mut chunk = Vec::new();
+    for (rowid, path) in files {
+        let mut blob =
+            connection.blob_open(DatabaseName::Main, "file_contents", "contents", rowid, true)?;
+        for chunk_index in 0.. {
+            chunk.clear();
+            (&mut blob).take(CHUNK_SIZE).read_to_end(&mut chunk)?;
+            if chunk.is_empty() {
+                break;
+            }
+            insert.execute((&path, chunk_index, &chunk))?;
+        }
+    }
+    connection.execute("ALTER TABLE file_contents DROP COLUMN contents", ())?;
+    Ok(())
+}
+
+// Helper function: Rebuild the given table using its STRICT `CREATE TABLE` statement, decoding its
+// base-64 TEXT values into values of each column's type. Tables which are already STRICT are left
+// alone.
+fn rebuild_strict_table(
+    connection: &Connection,
+    table: &str,
+    create_statement: &str,
+) -> eyre::Result<()> {
+    let strict: bool =
+        connection.query_row("SELECT strict FROM pragma_table_list(?1)", [table], |row| {
+            row.get(0)
+        })?;
+    if strict {
+        return Ok(());
+    }
+    let old_table = format!("{table}_b64");
+    connection.execute(&format!("ALTER TABLE {table} RENAME TO {old_table}"), ())?;
+    connection.execute(create_statement, ())?;
+
+    let mut statement = connection.prepare(&format!("PRAGMA table_info({table})"))?;
+    let columns = statement
+        .query_map([], |row| {
+            Ok((
+                row.get::<&str, String>("name")?,
+                row.get::<&str, String>("type")?,
+            ))
+        })?
+        .collect::<Result<Vec<(String, String)>, _>>()?;
+    let column_names = columns
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<&str>>()
+        .join(", ");
+    let placeholders = (1..=columns.len())
+        .map(|i| format!("?{i}"))
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    let mut select = connection.prepare(&format!("SELECT {column_names} FROM {old_table}"))?;
+    let mut insert = connection.prepare(&format!(
+        "INSERT INTO {table} ({column_names}) VALUES ({placeholders})"
+    ))?;
+    let mut rows = select.query([])?;
+    while let Some(row) = rows.next()? {
+        let values = columns
+            .iter()
+            .enumerate()
+            .map(|(i, (name, column_type))| {
+                decode_b64_value(row.get(i)?, column_type)
+                    .map_err(|err| eyre!("Bad value in column {}.{}: {}", table, name, err))
+            })
+            .collect::<eyre::Result<Vec<Value>>>()?;
+        insert.execute(params_from_iter(values))?;
+    }
+
+    connection.execute(&format!("DROP TABLE {old_table}"), ())?;
+    Ok(())
+}
+
+// Helper function: Decode a base-64 TEXT value into a value of the given column type. Values which
+// aren't TEXT were never base-64-encoded, so they're kept as they are.
+fn decode_b64_value(value: Value, column_type: &str) -> eyre::Result<Value> {
+    let Value::Text(b64) = value else {
+        return Ok(value);
+    };
+    let bytes = Base64::decode_vec(&b64)?;
+    match column_type {
+        "BLOB" => Ok(Value::Blob(bytes)),
+        "INTEGER" => {
+            let len = bytes.len();
+            let bytes: [u8; 4] = bytes
+                .try_into()
+                .map_err(|_| eyre!("Expected a 4-byte integer, got {} bytes.", len))?;
+            Ok(Value::Integer(u32::from_be_bytes(bytes).into()))
+        }
+        _ => Ok(Value::Text(String::from_utf8(bytes)?)),
+    }
+}
+
+// Helper function: give a random id to every row of the given table with an empty id. Ids are
+// base-64-encoded, like every other value in tables from before schema version 3.
+fn fill_missing_ids(connection: &Connection, table: &str) -> eyre::Result<()> {
+    let mut statement = connection.prepare(&format!("SELECT rowid FROM {table} WHERE id = ''"))?;
+    let rowids = statement
+        .query_map([], |row| row.get::<usize, i64>(0))?
+        .collect::<Result<Vec<i64>, _>>()?;
+    for rowid in rowids {
+        connection.execute(
+            &format!("UPDATE {table} SET id = ?1 WHERE rowid = ?2"),
+            (
+                Base64::encode_string(Uuid::new_v4().as_hyphenated().to_string().as_bytes()),
+                rowid,
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+// Helper function: add the column with the given definition to the table iff the table lacks it.
+fn add_missing_column(
+    connection: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> eyre::Result<()> {
+    if !column_names(connection, table)?
+        .iter()
+        .any(|name| name == column)
+    {
+        connection.execute(
+            &format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"),
+            (),
+        )?;
+    }
+    Ok(())
+}
+
+// Helper function: Return the names of the columns of the given table.
+fn column_names(connection: &Connection, table: &str) -> eyre::Result<Vec<String>> {
+    let mut statement = connection.prepare(&format!("PRAGMA table_info({table})"))?;
+    let column_names = statement
+        .query_map([], |row| row.get::<&str, String>("name"))?
+        .collect::<Result<Vec<String>, _>>()?;
+    Ok(column_names)
+}
@@ -0,0 +1,815 @@
+//! Sharing credentials & files between the accounts of a [Vault](super::Vault).
+use std::io::Write;
+
+use color_eyre::eyre::{self, eyre};
+
+use super::{
+    super::{
+        account::{Account, UnlockedAccount},
+        credential::Credential,
+        encryption::{
+            associated_data::EntityType,
+            encrypted::{new_rand_key, Aes256Key},
+            stream::{copy_secret, DEFAULT_CHUNK_SIZE},
+        },
+        file_data::FileData,
+        sharing::{new_shared_item, ShareGrant, Shared, SharedCredential, SharedFile},
+    },
+    database::Database,
+    filesystem::get_pending_file_path,
+    Vault,
+};
+
+impl Vault {
+    /// Share the [Credential] with the given `owner_username` & `name` with the account with the
+    /// given `recipient_username`, which can then read it, but not change it.
+    ///
+    /// The first time a [Credential] is shared, a shared copy of it is encrypted with a new,
+    /// random item key. The item key is encrypted to the public key of each recipient.
+    pub fn share_credential<O, N, R>(
+        &mut self,
+        owner_username: O,
+        name: N,
+        key: &Aes256Key,
+        recipient_username: R,
+    ) -> eyre::Result<()>
+    where
+        O: AsRef<str>,
+        N: AsRef<str>,
+        R: AsRef<str>,
+    {
+        let credential = self.load_credential(owner_username.as_ref(), name.as_ref(), key)?;
+        let recipient =
+            self.load_share_recipient(owner_username.as_ref(), recipient_username.as_ref())?;
+        // Reuse the shared copy if the credential is already shared.
+        let (shared_credential, item_key, is_new) =
+            match self.select_shared_credential(&credential)? {
+                Some(shared_credential) => {
+                    let item_key = shared_credential.item_key(key)?;
+                    (shared_credential, item_key, false)
+                }
+                None => {
+                    let (shared_id, item_key) = new_shared_item();
+                    let shared_credential = SharedCredential::try_new(
+                        &shared_id,
+                        &credential,
+                        key,
+                        &item_key,
+                        self.cipher_suite,
+                    )?;
+                    (shared_credential, item_key, true)
+                }
+            };
+        if self
+            .database
+            .select_entry::<ShareGrant, &str, 2>([shared_credential.id(), recipient.username()])?
+            .is_some()
+        {
+            return Err(eyre!(
+                "Credential \"{}\" is already shared with account \"{}\".",
+                name.as_ref(),
+                recipient_username.as_ref()
+            ));
+        }
+        let share_grant = self.new_share_grant(
+            shared_credential.id(),
+            credential.owner_username(),
+            &recipient,
+            EntityType::Credential,
+            &item_key,
+        )?;
+
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Add the shared copy if it's new.
+        if is_new {
+            Database::transaction_insert(shared_credential, &tx)?;
+        }
+        // Give the recipient the item key.
+        Database::transaction_insert(share_grant, &tx)?;
+        // Commit the database transaction.
+        Ok(tx.commit()?)
+    }
+
+    /// Stop sharing the [Credential] with the given `owner_username` & `name` with the account
+    /// with the given `recipient_username`.
+    ///
+    /// If the [Credential] is still shared with other accounts, its shared copy is encrypted with
+    /// a new item key, so that later changes to it can't be read with the old one.
+    pub fn unshare_credential<O, N, R>(
+        &mut self,
+        owner_username: O,
+        name: N,
+        key: &Aes256Key,
+        recipient_username: R,
+    ) -> eyre::Result<()>
+    where
+        O: AsRef<str>,
+        N: AsRef<str>,
+        R: AsRef<str>,
+    {
+        let credential = self.load_credential(owner_username.as_ref(), name.as_ref(), key)?;
+        let not_shared_err = || {
+            eyre!(
+                "Credential \"{}\" isn't shared with account \"{}\".",
+                name.as_ref(),
+                recipient_username.as_ref()
+            )
+        };
+        let shared_credential = self
+            .select_shared_credential(&credential)?
+            .ok_or_else(not_shared_err)?;
+        let share_grants = self.database.select_share_grants(shared_credential.id())?;
+        let remaining_grants =
+            self.remaining_share_grants(&share_grants, recipient_username.as_ref());
+        if remaining_grants.len() == share_grants.len() {
+            return Err(not_shared_err());
+        }
+        // Encrypt a new shared copy with a new item key for the remaining recipients.
+        let renewed = if remaining_grants.is_empty() {
+            None
+        } else {
+            let item_key = new_rand_key();
+            let renewed_credential = SharedCredential::try_new(
+                shared_credential.id(),
+                &credential,
+                key,
+                &item_key,
+                self.cipher_suite,
+            )?;
+            let renewed_grants = self.renew_share_grants(&remaining_grants, &item_key)?;
+            Some((renewed_credential, renewed_grants))
+        };
+
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Remove the old shared copy & its grants.
+        Self::transaction_delete_share_grants(&share_grants, &tx)?;
+        Database::transaction_delete::<SharedCredential, &str, 1>([shared_credential.id()], &tx)?;
+        // Add the new shared copy & its grants.
+        if let Some((renewed_credential, renewed_grants)) = renewed {
+            Database::transaction_insert(renewed_credential, &tx)?;
+            for share_grant in renewed_grants {
+                Database::transaction_insert(share_grant, &tx)?;
+            }
+        }
+        // Commit the database transaction.
+        Ok(tx.commit()?)
+    }
+
+    /// Share the file with the given `owner_username` & `filename` with the account with the
+    /// given `recipient_username`, which can then read it, but not change it.
+    ///
+    /// The first time a file is shared, a shared copy of it is encrypted with a new, random item
+    /// key. The item key is encrypted to the public key of each recipient.
+    pub fn share_file<O, F, R>(
+        &mut self,
+        owner_username: O,
+        filename: F,
+        key: &Aes256Key,
+        recipient_username: R,
+    ) -> eyre::Result<()>
+    where
+        O: AsRef<str>,
+        F: AsRef<str>,
+        R: AsRef<str>,
+    {
+        let file_data = self.load_file_data(owner_username.as_ref(), filename.as_ref(), key)?;
+        let recipient =
+            self.load_share_recipient(owner_username.as_ref(), recipient_username.as_ref())?;
+        // Reuse the shared copy if the file is already shared.
+        let (shared_file, item_key, is_new) = match self.select_shared_file(&file_data)? {
+            Some(shared_file) => {
+                let item_key = shared_file.item_key(key)?;
+                (shared_file, item_key, false)
+            }
+            None => {
+                let item_key = new_rand_key();
+                let shared_file = SharedFile::try_new(
+                    self.file_store.account_dir(file_data.owner_username())?,
+                    &file_data,
+                    key,
+                    &item_key,
+                    self.cipher_suite,
+                    self.cipher_suite.new_rand_nonce(),
+                    DEFAULT_CHUNK_SIZE,
+                )?;
+                (shared_file, item_key, true)
+            }
+        };
+        if self
+            .database
+            .select_entry::<ShareGrant, &str, 2>([shared_file.id(), recipient.username()])?
+            .is_some()
+        {
+            return Err(eyre!(
+                "File \"{}\" is already shared with account \"{}\".",
+                filename.as_ref(),
+                recipient_username.as_ref()
+            ));
+        }
+        let share_grant = self.new_share_grant(
+            shared_file.id(),
+            file_data.owner_username(),
+            &recipient,
+            EntityType::File,
+            &item_key,
+        )?;
+        let shared_file_data = shared_file.file_data().clone();
+
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Give the recipient the item key.
+        Database::transaction_insert(share_grant, &tx)?;
+        // Add the shared copy if it's new.
+        if is_new {
+            Database::transaction_insert(shared_file, &tx)?;
+            let contents = Self::open_contents(
+                &self.file_store,
+                &tx,
+                &file_data,
+                file_data.path(),
+                key,
+                &file_data.contents_associated_data(),
+            )?;
+            Self::write_contents(
+                &self.file_store,
+                &tx,
+                contents,
+                shared_file_data.path(),
+                &item_key,
+                shared_file_data.contents_nonce(),
+                &shared_file_data.contents_associated_data(),
+            )?;
+        }
+        // Commit the database transaction.
+        Ok(tx.commit()?)
+    }
+
+    /// Stop sharing the file with the given `owner_username` & `filename` with the account with
+    /// the given `recipient_username`.
+    ///
+    /// If the file is still shared with other accounts, its shared copy is encrypted with a new
+    /// item key, so that later changes to it can't be read with the old one.
+    pub fn unshare_file<O, F, R>(
+        &mut self,
+        owner_username: O,
+        filename: F,
+        key: &Aes256Key,
+        recipient_username: R,
+    ) -> eyre::Result<()>
+    where
+        O: AsRef<str>,
+        F: AsRef<str>,
+        R: AsRef<str>,
+    {
+        let file_data = self.load_file_data(owner_username.as_ref(), filename.as_ref(), key)?;
+        let not_shared_err = || {
+            eyre!(
+                "File \"{}\" isn't shared with account \"{}\".",
+                filename.as_ref(),
+                recipient_username.as_ref()
+            )
+        };
+        let shared_file = self
+            .select_shared_file(&file_data)?
+            .ok_or_else(not_shared_err)?;
+        let share_grants = self.database.select_share_grants(shared_file.id())?;
+        let remaining_grants =
+            self.remaining_share_grants(&share_grants, recipient_username.as_ref());
+        if remaining_grants.len() == share_grants.len() {
+            return Err(not_shared_err());
+        }
+
+        if remaining_grants.is_empty() {
+            // Open a new database transaction.
+            let tx = self.database.open_transaction()?;
+            // Remove the shared copy & its grant.
+            Self::transaction_delete_share_grants(&share_grants, &tx)?;
+            Database::transaction_delete::<SharedFile, &str, 1>([shared_file.id()], &tx)?;
+            self.file_store
+                .remove(&tx, shared_file.file_data().path())?;
+            // Commit the database transaction.
+            return Ok(tx.commit()?);
+        }
+        // Encrypt the shared copy with a new item key for the remaining recipients.
+        let item_key = new_rand_key();
+        let renewed_grants = self.renew_share_grants(&remaining_grants, &item_key)?;
+        self.replace_shared_file(
+            &file_data,
+            key,
+            &shared_file,
+            &item_key,
+            &share_grants,
+            &renewed_grants,
+        )
+    }
+
+    /// Load all [Credential]s shared with the given [UnlockedAccount], each along with the item
+    /// key which decrypts it.
+    pub fn load_shared_credentials(
+        &self,
+        unlocked_account: &UnlockedAccount,
+    ) -> eyre::Result<Vec<Shared<Credential>>> {
+        self.load_shared_items(unlocked_account, EntityType::Credential, |shared_id| {
+            Ok(self
+                .database
+                .select_entry_err_none::<SharedCredential, &str, 1>([shared_id])?
+                .credential()
+                .clone())
+        })
+    }
+
+    /// Load the [Credential] with the given `name` shared with the given [UnlockedAccount].
+    pub fn load_shared_credential<N>(
+        &self,
+        unlocked_account: &UnlockedAccount,
+        name: N,
+    ) -> eyre::Result<Shared<Credential>>
+    where
+        N: AsRef<str>,
+    {
+        let mut matches = Vec::new();
+        for shared in self.load_shared_credentials(unlocked_account)? {
+            if shared.item().name::<String>(shared.key())? == name.as_ref() {
+                matches.push(shared);
+            }
+        }
+        match matches.len() {
+            0 => Err(eyre!(
+                "No credentials named \"{}\" are shared with this account.",
+                name.as_ref()
+            )),
+            1 => Ok(matches.remove(0)),
+            num_matches => Err(eyre!(
+                "{} credentials named \"{}\" are shared with this account.",
+                num_matches,
+                name.as_ref()
+            )),
+        }
+    }
+
+    /// Load the [FileData] of all files shared with the given [UnlockedAccount], each along with
+    /// the item key which decrypts it.
+    pub fn load_shared_files_data(
+        &self,
+        unlocked_account: &UnlockedAccount,
+    ) -> eyre::Result<Vec<Shared<FileData>>> {
+        self.load_shared_items(unlocked_account, EntityType::File, |shared_id| {
+            Ok(self
+                .database
+                .select_entry_err_none::<SharedFile, &str, 1>([shared_id])?
+                .file_data()
+                .clone())
+        })
+    }
+
+    /// Load the [FileData] of the file with the given `filename` shared with the given
+    /// [UnlockedAccount].
+    pub fn load_shared_file_data<F>(
+        &self,
+        unlocked_account: &UnlockedAccount,
+        filename: F,
+    ) -> eyre::Result<Shared<FileData>>
+    where
+        F: AsRef<str>,
+    {
+        let mut matches = Vec::new();
+        for shared in self.load_shared_files_data(unlocked_account)? {
+            if shared.item().filename::<String>(shared.key())? == filename.as_ref() {
+                matches.push(shared);
+            }
+        }
+        match matches.len() {
+            0 => Err(eyre!(
+                "No files named \"{}\" are shared with this account.",
+                filename.as_ref()
+            )),
+            1 => Ok(matches.remove(0)),
+            num_matches => Err(eyre!(
+                "{} files named \"{}\" are shared with this account.",
+                num_matches,
+                filename.as_ref()
+            )),
+        }
+    }
+
+    /// Decrypt the file with the given `filename` shared with the given [UnlockedAccount],
+    /// writing its contents to the `writer`. Return the number of bytes written.
+    pub fn export_shared_file<F, W>(
+        &self,
+        unlocked_account: &UnlockedAccount,
+        filename: F,
+        mut writer: W,
+    ) -> eyre::Result<u64>
+    where
+        F: AsRef<str>,
+        W: Write,
+    {
+        let shared = self.load_shared_file_data(unlocked_account, filename)?;
+        let file_data = shared.item();
+        // Decrypt the shared copy's contents into the writer.
+        let aad = file_data.contents_associated_data();
+        let mut contents = Self::open_contents(
+            &self.file_store,
+            self.database.connection(),
+            file_data,
+            file_data.path(),
+            shared.key(),
+            &aad,
+        )?;
+        let num_bytes = copy_secret(&mut contents, &mut writer)?;
+        writer.flush()?;
+        Ok(num_bytes)
+    }
+
+    // Helper function: Load the [Account] which the account with the given `owner_username` is
+    // sharing an item with.
+    fn load_share_recipient(
+        &self,
+        owner_username: &str,
+        recipient_username: &str,
+    ) -> eyre::Result<Account> {
+        if owner_username == recipient_username {
+            return Err(eyre!("Items can't be shared with their owner."));
+        }
+        let recipient = self.load_account(recipient_username)?;
+        if recipient.keypair().is_none() {
+            return Err(eyre!(
+                "Account \"{}\" can't be shared with until it next logs in.",
+                recipient_username
+            ));
+        }
+        Ok(recipient)
+    }
+
+    // Helper function: Encrypt the item key of a shared item to the `recipient`'s public key.
+    fn new_share_grant(
+        &self,
+        shared_id: &str,
+        owner_username: &str,
+        recipient: &Account,
+        entity_type: EntityType,
+        item_key: &Aes256Key,
+    ) -> eyre::Result<ShareGrant> {
+        let keypair = recipient
+            .keypair()
+            .ok_or_else(|| eyre!("Account \"{}\" has no public key.", recipient.username()))?;
+        ShareGrant::try_new(
+            shared_id,
+            owner_username,
+            recipient.username(),
+            entity_type,
+            item_key,
+            keypair.public_key(),
+            self.cipher_suite,
+        )
+    }
+
+    // Helper function: Return the given [ShareGrant]s, without the one of the account with the
+    // given `recipient_username`.
+    fn remaining_share_grants(
+        &self,
+        share_grants: &[ShareGrant],
+        recipient_username: &str,
+    ) -> Vec<ShareGrant> {
+        let recipient_name = self.account_name(recipient_username);
+        share_grants
+            .iter()
+            .filter(|share_grant| share_grant.recipient_username() != recipient_name)
+            .cloned()
+            .collect()
+    }
+
+    // Helper function: Encrypt a new item key to the recipient of each of the given
+    // [ShareGrant]s.
+    fn renew_share_grants(
+        &self,
+        share_grants: &[ShareGrant],
+        item_key: &Aes256Key,
+    ) -> eyre::Result<Vec<ShareGrant>> {
+        share_grants
+            .iter()
+            .map(|share_grant| {
+                let recipient = self.database.select_entry_err_none::<Account, &str, 1>([
+                    share_grant.recipient_username(),
+                ])?;
+                self.new_share_grant(
+                    share_grant.shared_id(),
+                    share_grant.owner_username(),
+                    &recipient,
+                    share_grant.entity_type(),
+                    item_key,
+                )
+            })
+            .collect()
+    }
+
+    // Helper function: Load the items of the given [EntityType] shared with the given
+    // [UnlockedAccount], selecting each by its shared id with `select`. Accounts without a keypair
+    // have nothing shared with them.
+    fn load_shared_items<T, S>(
+        &self,
+        unlocked_account: &UnlockedAccount,
+        entity_type: EntityType,
+        select: S,
+    ) -> eyre::Result<Vec<Shared<T>>>
+    where
+        S: Fn(&str) -> eyre::Result<T>,
+    {
+        if unlocked_account.keypair().is_none() {
+            return Ok(Vec::new());
+        }
+        let private_key = unlocked_account.private_key()?;
+        self.database
+            .select_received_share_grants(unlocked_account.username())?
+            .into_iter()
+            .filter(|share_grant| share_grant.entity_type() == entity_type)
+            .map(|share_grant| {
+                Ok(Shared::new(
+                    select(share_grant.shared_id())?,
+                    share_grant.item_key(&private_key)?,
+                ))
+            })
+            .collect()
+    }
+
+    // Helper function: Select the shared copy of the given [Credential], if it's shared.
+    pub(super) fn select_shared_credential(
+        &self,
+        credential: &Credential,
+    ) -> eyre::Result<Option<SharedCredential>> {
+        Ok(self
+            .database
+            .select_owned_entries::<SharedCredential, &str, 1>([credential.owner_username()])?
+            .into_iter()
+            .find(|shared_credential| shared_credential.item_id() == credential.id()))
+    }
+
+    // Helper function: Select the shared copy of the file with the given [FileData], if it's
+    // shared.
+    pub(super) fn select_shared_file(
+        &self,
+        file_data: &FileData,
+    ) -> eyre::Result<Option<SharedFile>> {
+        Ok(self
+            .database
+            .select_owned_entries::<SharedFile, &str, 1>([file_data.owner_username()])?
+            .into_iter()
+            .find(|shared_file| shared_file.item_id() == file_data.id()))
+    }
+
+    // Helper function: Encrypt the contents of the owner's file to the pending sibling of its
+    // shared copy with the given item key, then commit the new shared copy, replacing the
+    // `old_share_grants` with the `new_share_grants`. The pending sibling then replaces the shared
+    // copy. If this is interrupted, it is either finished or undone the next time the owner is
+    // loaded.
+    pub(super) fn replace_shared_file(
+        &mut self,
+        file_data: &FileData,
+        key: &Aes256Key,
+        shared_file: &SharedFile,
+        item_key: &Aes256Key,
+        old_share_grants: &[ShareGrant],
+        new_share_grants: &[ShareGrant],
+    ) -> eyre::Result<()> {
+        let nonce = self.cipher_suite.new_rand_nonce();
+        let updated_shared_file = shared_file.with_item_key(
+            key,
+            item_key,
+            self.cipher_suite,
+            nonce,
+            DEFAULT_CHUNK_SIZE,
+        )?;
+        let shared_path = updated_shared_file.file_data().path().to_owned();
+        let pending_path = get_pending_file_path(&shared_path, updated_shared_file.id());
+        // Encrypt the owner's file contents to the pending sibling.
+        let conn = self.database.connection();
+        let contents = Self::open_contents(
+            &self.file_store,
+            conn,
+            file_data,
+            file_data.path(),
+            key,
+            &file_data.contents_associated_data(),
+        )?;
+        Self::write_contents(
+            &self.file_store,
+            conn,
+            contents,
+            &pending_path,
+            item_key,
+            nonce,
+            &updated_shared_file.file_data().contents_associated_data(),
+        )?;
+        // Commit the new shared copy.
+        let commit_result = self.database.open_transaction().and_then(|tx| {
+            Self::transaction_delete_share_grants(old_share_grants, &tx)?;
+            Database::transaction_delete::<SharedFile, &str, 1>([shared_file.id()], &tx)?;
+            Database::transaction_insert(updated_shared_file, &tx)?;
+            for share_grant in new_share_grants {
+                Database::transaction_insert(share_grant.clone(), &tx)?;
+            }
+            Ok(tx.commit()?)
+        });
+        let conn = self.database.connection();
+        if let Err(err) = commit_result {
+            let _ = self.file_store.remove(conn, &pending_path);
+            return Err(err);
+        }
+        // Replace the shared copy with its pending sibling.
+        self.file_store.replace(conn, &pending_path, &shared_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::{assert_eq, assert_ne};
+    use std::fs;
+
+    use super::super::{
+        super::credential::CredentialField,
+        filesystem::get_account_file_dir,
+        tests::{db_path, fs_dir, refresh_test_db, refresh_test_fs},
+        Vault,
+    };
+
+    #[test]
+    fn share_items() {
+        let db_name = "share_items.db";
+        let fs_name = "share_items";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+
+        let password = "open sesame!";
+        for username in ["owner", "alice", "bob"] {
+            vault.create_new_account(username, password, None).unwrap();
+        }
+        let key = vault
+            .load_unlocked_account("owner", password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+        let alice = vault
+            .load_unlocked_account("alice", password, None, None)
+            .unwrap();
+        let bob = vault
+            .load_unlocked_account("bob", password, None, None)
+            .unwrap();
+
+        // Credentials.
+        vault
+            .create_credential("owner", &key, "wifi", "guest", "hunter2", "")
+            .unwrap();
+        assert!(vault.load_shared_credentials(&alice).unwrap().is_empty());
+        vault
+            .share_credential("owner", "wifi", &key, "alice")
+            .unwrap();
+        vault
+            .share_credential("owner", "wifi", &key, "bob")
+            .unwrap();
+        let _ = vault
+            .share_credential("owner", "wifi", &key, "alice")
+            .unwrap_err();
+        let _ = vault
+            .share_credential("owner", "wifi", &key, "owner")
+            .unwrap_err();
+        let _ = vault
+            .share_credential("owner", "wifi", &key, "nobody")
+            .unwrap_err();
+
+        let shared = vault.load_shared_credential(&alice, "wifi").unwrap();
+        assert_eq!(shared.item().owner_username(), "owner");
+        assert_eq!(
+            shared.item().password::<String>(shared.key()).unwrap(),
+            "hunter2"
+        );
+        let _ = shared.item().password::<String>(alice.key()).unwrap_err();
+        let _ = vault.load_shared_credential(&alice, "nothing").unwrap_err();
+
+        // Updates reach the recipients.
+        vault
+            .update_credential_fields(
+                "owner",
+                "wifi",
+                &key,
+                &[(CredentialField::Password, "hunter3")],
+            )
+            .unwrap();
+        let shared = vault.load_shared_credential(&bob, "wifi").unwrap();
+        assert_eq!(
+            shared.item().password::<String>(shared.key()).unwrap(),
+            "hunter3"
+        );
+
+        // Unsharing re-keys the shared copy for the remaining recipients.
+        let old_item_key = vault
+            .load_shared_credential(&alice, "wifi")
+            .unwrap()
+            .key()
+            .clone();
+        vault
+            .unshare_credential("owner", "wifi", &key, "bob")
+            .unwrap();
+        let _ = vault
+            .unshare_credential("owner", "wifi", &key, "bob")
+            .unwrap_err();
+        assert!(vault.load_shared_credentials(&bob).unwrap().is_empty());
+        let shared = vault.load_shared_credential(&alice, "wifi").unwrap();
+        assert_ne!(shared.key(), &old_item_key);
+        assert_eq!(
+            shared.item().password::<String>(shared.key()).unwrap(),
+            "hunter3"
+        );
+
+        // Files.
+        vault
+            .create_file("notes.txt", "owner", "shared notes", &key)
+            .unwrap();
+        vault
+            .share_file("owner", "notes.txt", &key, "alice")
+            .unwrap();
+        vault.share_file("owner", "notes.txt", &key, "bob").unwrap();
+        let mut contents = vec![];
+        vault
+            .export_shared_file(&bob, "notes.txt", &mut contents)
+            .unwrap();
+        assert_eq!(contents, b"shared notes");
+        let _ = vault
+            .export_file("alice", "notes.txt", alice.key(), vec![])
+            .unwrap_err();
+
+        vault
+            .update_file("owner", "notes.txt", &key, "new notes")
+            .unwrap();
+        vault
+            .unshare_file("owner", "notes.txt", &key, "alice")
+            .unwrap();
+        assert!(vault.load_shared_files_data(&alice).unwrap().is_empty());
+        let mut contents = vec![];
+        vault
+            .export_shared_file(&bob, "notes.txt", &mut contents)
+            .unwrap();
+        assert_eq!(contents, b"new notes");
+
+        // Rotating the owner's key keeps everything shared.
+        vault
+            .rotate_account_key("owner", password, None, None)
+            .unwrap();
+        let key = vault
+            .load_unlocked_account("owner", password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+        let shared = vault.load_shared_credential(&alice, "wifi").unwrap();
+        assert_eq!(
+            shared.item().password::<String>(shared.key()).unwrap(),
+            "hunter3"
+        );
+        let mut contents = vec![];
+        vault
+            .export_shared_file(&bob, "notes.txt", &mut contents)
+            .unwrap();
+        assert_eq!(contents, b"new notes");
+        // The file, its previous revision & its shared copy are left, without pending siblings.
+        let account_dir = get_account_file_dir(&fs_dir, "owner").unwrap();
+        assert_eq!(fs::read_dir(&account_dir).unwrap().count(), 3);
+
+        // Rotating a recipient's key replaces their keypair & re-encrypts the items shared with
+        // them to it, so their old private key no longer decrypts the item keys.
+        let old_public_key = *bob.keypair().unwrap().public_key();
+        let old_private_key = bob.private_key().unwrap();
+        vault
+            .rotate_account_key("bob", password, None, None)
+            .unwrap();
+        let bob = vault
+            .load_unlocked_account("bob", password, None, None)
+            .unwrap();
+        assert_ne!(bob.keypair().unwrap().public_key(), &old_public_key);
+        let share_grants = vault
+            .database
+            .select_received_share_grants(bob.username())
+            .unwrap();
+        assert_eq!(share_grants.len(), 1);
+        let _ = share_grants[0].item_key(&old_private_key).unwrap_err();
+        let mut contents = vec![];
+        vault
+            .export_shared_file(&bob, "notes.txt", &mut contents)
+            .unwrap();
+        assert_eq!(contents, b"new notes");
+
+        // Deleting an item stops sharing it.
+        vault.delete_credential("owner", "wifi", &key).unwrap();
+        assert!(vault.load_shared_credentials(&alice).unwrap().is_empty());
+        vault.delete_file("owner", "notes.txt", &key).unwrap();
+        assert!(vault.load_shared_files_data(&bob).unwrap().is_empty());
+        // The trashed file & its previous revision are kept until they're purged.
+        assert_eq!(fs::read_dir(&account_dir).unwrap().count(), 2);
+        assert_eq!(vault.empty_trash("owner").unwrap(), 2);
+        assert_eq!(fs::read_dir(&account_dir).unwrap().count(), 0);
+    }
+}
@@ -1,40 +1,286 @@
 pub const CREATE_ACCOUNTS: &str = "
 CREATE TABLE IF NOT EXISTS accounts (
     username TEXT PRIMARY KEY,
-    password_salt TEXT NOT NULL,
-    dbl_hashed_password_hash TEXT NOT NULL,
-    dbl_hashed_password_salt TEXT NOT NULL,
-    encrypted_key_cipherbytes TEXT NOT NULL,
-    encrypted_key_nonce TEXT NOT NULL
-)
+    password_salt BLOB NOT NULL,
+    dbl_hashed_password_hash BLOB NOT NULL,
+    dbl_hashed_password_salt BLOB NOT NULL,
+    encrypted_key_cipherbytes BLOB NOT NULL,
+    encrypted_key_nonce BLOB NOT NULL,
+    encrypted_key_cipher TEXT NOT NULL,
+    kdf_algorithm TEXT NOT NULL,
+    kdf_memory_kib INTEGER NOT NULL,
+    kdf_time_cost INTEGER NOT NULL,
+    kdf_parallelism INTEGER NOT NULL,
+    encryption_version INTEGER NOT NULL,
+    key_file_salt BLOB NOT NULL,
+    key_file_hash BLOB NOT NULL,
+    recovery_encrypted_key_cipherbytes BLOB NOT NULL,
+    recovery_encrypted_key_nonce BLOB NOT NULL,
+    recovery_encrypted_key_cipher TEXT NOT NULL,
+    encrypted_recovery_key_cipherbytes BLOB NOT NULL,
+    encrypted_recovery_key_nonce BLOB NOT NULL,
+    encrypted_recovery_key_cipher TEXT NOT NULL,
+    encrypted_totp_secret_cipherbytes BLOB NOT NULL,
+    encrypted_totp_secret_nonce BLOB NOT NULL,
+    encrypted_totp_secret_cipher TEXT NOT NULL,
+    share_encrypted_key_cipherbytes BLOB NOT NULL,
+    share_encrypted_key_nonce BLOB NOT NULL,
+    share_encrypted_key_cipher TEXT NOT NULL,
+    encrypted_share_key_cipherbytes BLOB NOT NULL,
+    encrypted_share_key_nonce BLOB NOT NULL,
+    encrypted_share_key_cipher TEXT NOT NULL,
+    public_key BLOB NOT NULL,
+    encrypted_private_key_cipherbytes BLOB NOT NULL,
+    encrypted_private_key_nonce BLOB NOT NULL,
+    encrypted_private_key_cipher TEXT NOT NULL
+) STRICT
 ";
 
 pub const CREATE_CREDENTIALS: &str = "
 CREATE TABLE IF NOT EXISTS credentials (
+    id TEXT NOT NULL,
     owner_username TEXT NOT NULL,
-    encrypted_name_cipherbytes TEXT NOT NULL,
-    encrypted_name_nonce TEXT NOT NULL,
-    encrypted_username_cipherbytes TEXT NOT NULL,
-    encrypted_username_nonce TEXT NOT NULL,
-    encrypted_password_cipherbytes TEXT NOT NULL,
-    encrypted_password_nonce TEXT NOT NULL,
-    encrypted_notes_cipherbytes TEXT NOT NULL,
-    encrypted_notes_nonce TEXT NOT NULL,
+    encrypted_name_cipherbytes BLOB NOT NULL,
+    encrypted_name_nonce BLOB NOT NULL,
+    encrypted_name_cipher TEXT NOT NULL,
+    encrypted_username_cipherbytes BLOB NOT NULL,
+    encrypted_username_nonce BLOB NOT NULL,
+    encrypted_username_cipher TEXT NOT NULL,
+    encrypted_password_cipherbytes BLOB NOT NULL,
+    encrypted_password_nonce BLOB NOT NULL,
+    encrypted_password_cipher TEXT NOT NULL,
+    encrypted_notes_cipherbytes BLOB NOT NULL,
+    encrypted_notes_nonce BLOB NOT NULL,
+    encrypted_notes_cipher TEXT NOT NULL,
+    name_index BLOB NOT NULL,
     FOREIGN KEY (owner_username)
         REFERENCES accounts(username)
         ON DELETE CASCADE,
     PRIMARY KEY(owner_username, encrypted_name_cipherbytes)
-)
+) STRICT
 ";
 
 pub const CREATE_FILES_DATA: &str = "
 CREATE TABLE IF NOT EXISTS files_data (
+    id TEXT NOT NULL,
     path TEXT PRIMARY KEY,
     filename TEXT NOT NULL,
     owner_username TEXT NOT NULL,
-    contents_nonce TEXT NOT NULL,
+    contents_nonce BLOB NOT NULL,
+    contents_cipher TEXT NOT NULL,
+    contents_chunk_size INTEGER NOT NULL,
+    encrypted_filename_cipherbytes BLOB NOT NULL,
+    encrypted_filename_nonce BLOB NOT NULL,
+    encrypted_filename_cipher TEXT NOT NULL,
+    name_index BLOB NOT NULL,
     FOREIGN KEY (owner_username)
         REFERENCES accounts(username)
         ON DELETE CASCADE
-)
+) STRICT
 ";
+
+pub const CREATE_TOTP_BACKUPS: &str = "
+CREATE TABLE IF NOT EXISTS totp_backups (
+    id TEXT NOT NULL,
+    owner_username TEXT NOT NULL,
+    encrypted_secret_cipherbytes BLOB NOT NULL,
+    encrypted_secret_nonce BLOB NOT NULL,
+    encrypted_secret_cipher TEXT NOT NULL,
+    FOREIGN KEY (owner_username)
+        REFERENCES accounts(username)
+        ON DELETE CASCADE,
+    PRIMARY KEY(owner_username, id)
+) STRICT
+";
+
+pub const CREATE_SHARED_CREDENTIALS: &str = "
+CREATE TABLE IF NOT EXISTS shared_credentials (
+    id TEXT PRIMARY KEY,
+    owner_username TEXT NOT NULL,
+    item_id TEXT NOT NULL,
+    encrypted_item_key_cipherbytes BLOB NOT NULL,
+    encrypted_item_key_nonce BLOB NOT NULL,
+    encrypted_item_key_cipher TEXT NOT NULL,
+    encrypted_name_cipherbytes BLOB NOT NULL,
+    encrypted_name_nonce BLOB NOT NULL,
+    encrypted_name_cipher TEXT NOT NULL,
+    encrypted_username_cipherbytes BLOB NOT NULL,
+    encrypted_username_nonce BLOB NOT NULL,
+    encrypted_username_cipher TEXT NOT NULL,
+    encrypted_password_cipherbytes BLOB NOT NULL,
+    encrypted_password_nonce BLOB NOT NULL,
+    encrypted_password_cipher TEXT NOT NULL,
+    encrypted_notes_cipherbytes BLOB NOT NULL,
+    encrypted_notes_nonce BLOB NOT NULL,
+    encrypted_notes_cipher TEXT NOT NULL,
+    FOREIGN KEY (owner_username)
+        REFERENCES accounts(username)
+        ON DELETE CASCADE
+) STRICT
+";
+
+pub const CREATE_SHARED_FILES_DATA: &str = "
+CREATE TABLE IF NOT EXISTS shared_files_data (
+    id TEXT PRIMARY KEY,
+    path TEXT NOT NULL,
+    owner_username TEXT NOT NULL,
+    item_id TEXT NOT NULL,
+    encrypted_item_key_cipherbytes BLOB NOT NULL,
+    encrypted_item_key_nonce BLOB NOT NULL,
+    encrypted_item_key_cipher TEXT NOT NULL,
+    contents_nonce BLOB NOT NULL,
+    contents_cipher TEXT NOT NULL,
+    contents_chunk_size INTEGER NOT NULL,
+    encrypted_filename_cipherbytes BLOB NOT NULL,
+    encrypted_filename_nonce BLOB NOT NULL,
+    encrypted_filename_cipher TEXT NOT NULL,
+    FOREIGN KEY (owner_username)
+        REFERENCES accounts(username)
+        ON DELETE CASCADE
+) STRICT
+";
+
+pub const CREATE_SHARE_GRANTS: &str = "
+CREATE TABLE IF NOT EXISTS share_grants (
+    shared_id TEXT NOT NULL,
+    owner_username TEXT NOT NULL,
+    recipient_username TEXT NOT NULL,
+    item_type TEXT NOT NULL,
+    ephemeral_public_key BLOB NOT NULL,
+    encrypted_item_key_cipherbytes BLOB NOT NULL,
+    encrypted_item_key_nonce BLOB NOT NULL,
+    encrypted_item_key_cipher TEXT NOT NULL,
+    FOREIGN KEY (owner_username)
+        REFERENCES accounts(username)
+        ON DELETE CASCADE,
+    FOREIGN KEY (recipient_username)
+        REFERENCES accounts(username)
+        ON DELETE CASCADE,
+    PRIMARY KEY(shared_id, recipient_username)
+) STRICT
+";
+
+pub const CREATE_SETTINGS: &str = "
+CREATE TABLE IF NOT EXISTS settings (
+    name TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+) STRICT
+";
+
+pub const CREATE_FILE_CONTENTS: &str = "
+CREATE TABLE IF NOT EXISTS file_contents (
+    path TEXT PRIMARY KEY,
+    owner_username TEXT NOT NULL,
+    FOREIGN KEY (owner_username)
+        REFERENCES accounts(username)
+        ON DELETE CASCADE
+) STRICT
+";
+
+pub const CREATE_FILE_CHUNKS: &str = "
+CREATE TABLE IF NOT EXISTS file_chunks (
+    path TEXT NOT NULL,
+    chunk_index INTEGER NOT NULL,
+    contents BLOB NOT NULL,
+    FOREIGN KEY (path)
+        REFERENCES file_contents(path)
+        ON DELETE CASCADE
+        ON UPDATE CASCADE,
+    PRIMARY KEY(path, chunk_index)
+) STRICT
+";
+
+pub const CREATE_CREDENTIAL_HISTORY: &str = "
+CREATE TABLE IF NOT EXISTS credential_history (
+    id TEXT NOT NULL,
+    credential_id TEXT NOT NULL,
+    owner_username TEXT NOT NULL,
+    replaced_at INTEGER NOT NULL,
+    encrypted_username_cipherbytes BLOB NOT NULL,
+    encrypted_username_nonce BLOB NOT NULL,
+    encrypted_username_cipher TEXT NOT NULL,
+    encrypted_password_cipherbytes BLOB NOT NULL,
+    encrypted_password_nonce BLOB NOT NULL,
+    encrypted_password_cipher TEXT NOT NULL,
+    encrypted_notes_cipherbytes BLOB NOT NULL,
+    encrypted_notes_nonce BLOB NOT NULL,
+    encrypted_notes_cipher TEXT NOT NULL,
+    FOREIGN KEY (owner_username)
+        REFERENCES accounts(username)
+        ON DELETE CASCADE,
+    PRIMARY KEY(owner_username, id)
+) STRICT
+";
+
+pub const CREATE_FILE_REVISIONS: &str = "
+CREATE TABLE IF NOT EXISTS file_revisions (
+    id TEXT PRIMARY KEY,
+    file_id TEXT NOT NULL,
+    owner_username TEXT NOT NULL,
+    path TEXT NOT NULL UNIQUE,
+    replaced_at INTEGER NOT NULL,
+    contents_nonce BLOB NOT NULL,
+    contents_cipher TEXT NOT NULL,
+    contents_chunk_size INTEGER NOT NULL,
+    FOREIGN KEY (owner_username)
+        REFERENCES accounts(username)
+        ON DELETE CASCADE
+) STRICT
+";
+
+pub const CREATE_TRASH: &str = "
+CREATE TABLE IF NOT EXISTS trash (
+    owner_username TEXT NOT NULL,
+    item_type TEXT NOT NULL,
+    item_id TEXT NOT NULL,
+    trashed_at INTEGER NOT NULL,
+    FOREIGN KEY (owner_username)
+        REFERENCES accounts(username)
+        ON DELETE CASCADE,
+    PRIMARY KEY(owner_username, item_type, item_id)
+) STRICT
+";
+
+pub const CREATE_TOTP_STEPS: &str = "
+CREATE TABLE IF NOT EXISTS totp_steps (
+    owner_username TEXT PRIMARY KEY,
+    last_step INTEGER NOT NULL,
+    FOREIGN KEY (owner_username)
+        REFERENCES accounts(username)
+        ON DELETE CASCADE
+) STRICT
+";
+
+// Only non-empty name indices must be unique.
+pub const CREATE_CREDENTIALS_NAME_INDEX: &str = "
+CREATE UNIQUE INDEX IF NOT EXISTS credentials_name_index
+ON credentials(owner_username, name_index)
+WHERE length(name_index) > 0
+";
+
+// Only non-empty name indices must be unique.
+pub const CREATE_FILES_DATA_NAME_INDEX: &str = "
+CREATE UNIQUE INDEX IF NOT EXISTS files_data_name_index
+ON files_data(owner_username, name_index)
+WHERE length(name_index) > 0
+";
+
+/// Every statement which creates the current schema of a new database, in order.
+pub const CREATE_SCHEMA: &[&str] = &[
+    CREATE_ACCOUNTS,
+    CREATE_CREDENTIALS,
+    CREATE_FILES_DATA,
+    CREATE_TOTP_BACKUPS,
+    CREATE_SHARED_CREDENTIALS,
+    CREATE_SHARED_FILES_DATA,
+    CREATE_SHARE_GRANTS,
+    CREATE_SETTINGS,
+    CREATE_FILE_CONTENTS,
+    CREATE_FILE_CHUNKS,
+    CREATE_CREDENTIAL_HISTORY,
+    CREATE_FILE_REVISIONS,
+    CREATE_TRASH,
+    CREATE_TOTP_STEPS,
+    CREATE_CREDENTIALS_NAME_INDEX,
+    CREATE_FILES_DATA_NAME_INDEX,
+];
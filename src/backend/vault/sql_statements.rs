@@ -5,7 +5,34 @@ pub const SELECT_ACCOUNT: &str = "
         dbl_hashed_password_hash,
         dbl_hashed_password_salt,
         encrypted_key_cipherbytes,
-        encrypted_key_nonce
+        encrypted_key_nonce,
+        encrypted_key_cipher,
+        kdf_algorithm,
+        kdf_memory_kib,
+        kdf_time_cost,
+        kdf_parallelism,
+        encryption_version,
+        key_file_salt,
+        key_file_hash,
+        recovery_encrypted_key_cipherbytes,
+        recovery_encrypted_key_nonce,
+        recovery_encrypted_key_cipher,
+        encrypted_recovery_key_cipherbytes,
+        encrypted_recovery_key_nonce,
+        encrypted_recovery_key_cipher,
+        encrypted_totp_secret_cipherbytes,
+        encrypted_totp_secret_nonce,
+        encrypted_totp_secret_cipher,
+        share_encrypted_key_cipherbytes,
+        share_encrypted_key_nonce,
+        share_encrypted_key_cipher,
+        encrypted_share_key_cipherbytes,
+        encrypted_share_key_nonce,
+        encrypted_share_key_cipher,
+        public_key,
+        encrypted_private_key_cipherbytes,
+        encrypted_private_key_nonce,
+        encrypted_private_key_cipher
     FROM accounts
     WHERE
         username = ?1
@@ -18,7 +45,34 @@ pub const SELECT_ALL_ACCOUNTS: &str = "
         dbl_hashed_password_hash,
         dbl_hashed_password_salt,
         encrypted_key_cipherbytes,
-        encrypted_key_nonce
+        encrypted_key_nonce,
+        encrypted_key_cipher,
+        kdf_algorithm,
+        kdf_memory_kib,
+        kdf_time_cost,
+        kdf_parallelism,
+        encryption_version,
+        key_file_salt,
+        key_file_hash,
+        recovery_encrypted_key_cipherbytes,
+        recovery_encrypted_key_nonce,
+        recovery_encrypted_key_cipher,
+        encrypted_recovery_key_cipherbytes,
+        encrypted_recovery_key_nonce,
+        encrypted_recovery_key_cipher,
+        encrypted_totp_secret_cipherbytes,
+        encrypted_totp_secret_nonce,
+        encrypted_totp_secret_cipher,
+        share_encrypted_key_cipherbytes,
+        share_encrypted_key_nonce,
+        share_encrypted_key_cipher,
+        encrypted_share_key_cipherbytes,
+        encrypted_share_key_nonce,
+        encrypted_share_key_cipher,
+        public_key,
+        encrypted_private_key_cipherbytes,
+        encrypted_private_key_nonce,
+        encrypted_private_key_cipher
     FROM accounts
 ";
 
@@ -29,9 +83,39 @@ pub const INSERT_ACCOUNT: &str = "
         dbl_hashed_password_hash,
         dbl_hashed_password_salt,
         encrypted_key_cipherbytes,
-        encrypted_key_nonce
+        encrypted_key_nonce,
+        encrypted_key_cipher,
+        kdf_algorithm,
+        kdf_memory_kib,
+        kdf_time_cost,
+        kdf_parallelism,
+        encryption_version,
+        key_file_salt,
+        key_file_hash,
+        recovery_encrypted_key_cipherbytes,
+        recovery_encrypted_key_nonce,
+        recovery_encrypted_key_cipher,
+        encrypted_recovery_key_cipherbytes,
+        encrypted_recovery_key_nonce,
+        encrypted_recovery_key_cipher,
+        encrypted_totp_secret_cipherbytes,
+        encrypted_totp_secret_nonce,
+        encrypted_totp_secret_cipher,
+        share_encrypted_key_cipherbytes,
+        share_encrypted_key_nonce,
+        share_encrypted_key_cipher,
+        encrypted_share_key_cipherbytes,
+        encrypted_share_key_nonce,
+        encrypted_share_key_cipher,
+        public_key,
+        encrypted_private_key_cipherbytes,
+        encrypted_private_key_nonce,
+        encrypted_private_key_cipher
+    )
+    VALUES (
+        ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20,
+        ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30, ?31, ?32, ?33
     )
-    VALUES (?1, ?2, ?3, ?4, ?5, ?6)
 ";
 
 pub const DELETE_ACCOUNT: &str = "
@@ -70,17 +154,185 @@ pub const UPDATE_ACCOUNT_ENCRYPTED_KEY_NONCE: &str = "
     WHERE username = ?2
 ";
 
+pub const UPDATE_ACCOUNT_ENCRYPTED_KEY_CIPHER: &str = "
+    UPDATE accounts
+    SET encrypted_key_cipher = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_KDF_ALGORITHM: &str = "
+    UPDATE accounts
+    SET kdf_algorithm = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_KDF_MEMORY_KIB: &str = "
+    UPDATE accounts
+    SET kdf_memory_kib = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_KDF_TIME_COST: &str = "
+    UPDATE accounts
+    SET kdf_time_cost = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_KDF_PARALLELISM: &str = "
+    UPDATE accounts
+    SET kdf_parallelism = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_ENCRYPTION_VERSION: &str = "
+    UPDATE accounts
+    SET encryption_version = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_KEY_FILE_SALT: &str = "
+    UPDATE accounts
+    SET key_file_salt = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_KEY_FILE_HASH: &str = "
+    UPDATE accounts
+    SET key_file_hash = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_RECOVERY_ENCRYPTED_KEY_CIPHERBYTES: &str = "
+    UPDATE accounts
+    SET recovery_encrypted_key_cipherbytes = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_RECOVERY_ENCRYPTED_KEY_NONCE: &str = "
+    UPDATE accounts
+    SET recovery_encrypted_key_nonce = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_RECOVERY_ENCRYPTED_KEY_CIPHER: &str = "
+    UPDATE accounts
+    SET recovery_encrypted_key_cipher = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_ENCRYPTED_RECOVERY_KEY_CIPHERBYTES: &str = "
+    UPDATE accounts
+    SET encrypted_recovery_key_cipherbytes = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_ENCRYPTED_RECOVERY_KEY_NONCE: &str = "
+    UPDATE accounts
+    SET encrypted_recovery_key_nonce = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_ENCRYPTED_RECOVERY_KEY_CIPHER: &str = "
+    UPDATE accounts
+    SET encrypted_recovery_key_cipher = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_ENCRYPTED_TOTP_SECRET_CIPHERBYTES: &str = "
+    UPDATE accounts
+    SET encrypted_totp_secret_cipherbytes = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_ENCRYPTED_TOTP_SECRET_NONCE: &str = "
+    UPDATE accounts
+    SET encrypted_totp_secret_nonce = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_ENCRYPTED_TOTP_SECRET_CIPHER: &str = "
+    UPDATE accounts
+    SET encrypted_totp_secret_cipher = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_SHARE_ENCRYPTED_KEY_CIPHERBYTES: &str = "
+    UPDATE accounts
+    SET share_encrypted_key_cipherbytes = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_SHARE_ENCRYPTED_KEY_NONCE: &str = "
+    UPDATE accounts
+    SET share_encrypted_key_nonce = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_SHARE_ENCRYPTED_KEY_CIPHER: &str = "
+    UPDATE accounts
+    SET share_encrypted_key_cipher = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_ENCRYPTED_SHARE_KEY_CIPHERBYTES: &str = "
+    UPDATE accounts
+    SET encrypted_share_key_cipherbytes = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_ENCRYPTED_SHARE_KEY_NONCE: &str = "
+    UPDATE accounts
+    SET encrypted_share_key_nonce = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_ENCRYPTED_SHARE_KEY_CIPHER: &str = "
+    UPDATE accounts
+    SET encrypted_share_key_cipher = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_PUBLIC_KEY: &str = "
+    UPDATE accounts
+    SET public_key = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_ENCRYPTED_PRIVATE_KEY_CIPHERBYTES: &str = "
+    UPDATE accounts
+    SET encrypted_private_key_cipherbytes = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_ENCRYPTED_PRIVATE_KEY_NONCE: &str = "
+    UPDATE accounts
+    SET encrypted_private_key_nonce = ?1
+    WHERE username = ?2
+";
+
+pub const UPDATE_ACCOUNT_ENCRYPTED_PRIVATE_KEY_CIPHER: &str = "
+    UPDATE accounts
+    SET encrypted_private_key_cipher = ?1
+    WHERE username = ?2
+";
+
 pub const SELECT_CREDENTIAL: &str = "
     SELECT
+        id,
         owner_username,
         encrypted_name_cipherbytes,
         encrypted_name_nonce,
+        encrypted_name_cipher,
         encrypted_username_cipherbytes,
         encrypted_username_nonce,
+        encrypted_username_cipher,
         encrypted_password_cipherbytes,
         encrypted_password_nonce,
+        encrypted_password_cipher,
         encrypted_notes_cipherbytes,
-        encrypted_notes_nonce
+        encrypted_notes_nonce,
+        encrypted_notes_cipher,
+        name_index
     FROM credentials
     WHERE
         owner_username = ?1
@@ -89,47 +341,88 @@ pub const SELECT_CREDENTIAL: &str = "
 
 pub const SELECT_ALL_CREDENTIALS: &str = "
     SELECT
+        id,
         owner_username,
         encrypted_name_cipherbytes,
         encrypted_name_nonce,
+        encrypted_name_cipher,
         encrypted_username_cipherbytes,
         encrypted_username_nonce,
+        encrypted_username_cipher,
         encrypted_password_cipherbytes,
         encrypted_password_nonce,
+        encrypted_password_cipher,
         encrypted_notes_cipherbytes,
-        encrypted_notes_nonce
+        encrypted_notes_nonce,
+        encrypted_notes_cipher,
+        name_index
     FROM credentials
 ";
 
 pub const SELECT_ACCOUNT_CREDENTIALS: &str = "
     SELECT
+        id,
         owner_username,
         encrypted_name_cipherbytes,
         encrypted_name_nonce,
+        encrypted_name_cipher,
         encrypted_username_cipherbytes,
         encrypted_username_nonce,
+        encrypted_username_cipher,
         encrypted_password_cipherbytes,
         encrypted_password_nonce,
+        encrypted_password_cipher,
         encrypted_notes_cipherbytes,
-        encrypted_notes_nonce
+        encrypted_notes_nonce,
+        encrypted_notes_cipher,
+        name_index
     FROM credentials
     WHERE
         owner_username = ?1
 ";
 
+pub const SELECT_CREDENTIAL_BY_NAME_INDEX: &str = "
+    SELECT
+        id,
+        owner_username,
+        encrypted_name_cipherbytes,
+        encrypted_name_nonce,
+        encrypted_name_cipher,
+        encrypted_username_cipherbytes,
+        encrypted_username_nonce,
+        encrypted_username_cipher,
+        encrypted_password_cipherbytes,
+        encrypted_password_nonce,
+        encrypted_password_cipher,
+        encrypted_notes_cipherbytes,
+        encrypted_notes_nonce,
+        encrypted_notes_cipher,
+        name_index
+    FROM credentials
+    WHERE
+        owner_username = ?1
+        AND name_index = ?2
+";
+
 pub const INSERT_CREDENTIAL: &str = "
     INSERT INTO credentials (
+        id,
         owner_username,
         encrypted_name_cipherbytes,
         encrypted_name_nonce,
+        encrypted_name_cipher,
         encrypted_username_cipherbytes,
         encrypted_username_nonce,
+        encrypted_username_cipher,
         encrypted_password_cipherbytes,
         encrypted_password_nonce,
+        encrypted_password_cipher,
         encrypted_notes_cipherbytes,
-        encrypted_notes_nonce
+        encrypted_notes_nonce,
+        encrypted_notes_cipher,
+        name_index
     )
-    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)
 ";
 
 pub const DELETE_CREDENTIAL: &str = "
@@ -155,6 +448,14 @@ pub const UPDATE_CREDENTIAL_ENCRYPTED_USERNAME_NONCE: &str = "
         AND encrypted_name_cipherbytes = ?3
 ";
 
+pub const UPDATE_CREDENTIAL_ENCRYPTED_USERNAME_CIPHER: &str = "
+    UPDATE credentials
+    SET encrypted_username_cipher = ?1
+    WHERE
+        owner_username = ?2
+        AND encrypted_name_cipherbytes = ?3
+";
+
 pub const UPDATE_CREDENTIAL_ENCRYPTED_PASSWORD_CIPHERBYTES: &str = "
     UPDATE credentials
     SET encrypted_password_cipherbytes = ?1
@@ -171,6 +472,14 @@ pub const UPDATE_CREDENTIAL_ENCRYPTED_PASSWORD_NONCE: &str = "
         AND encrypted_name_cipherbytes = ?3
 ";
 
+pub const UPDATE_CREDENTIAL_ENCRYPTED_PASSWORD_CIPHER: &str = "
+    UPDATE credentials
+    SET encrypted_password_cipher = ?1
+    WHERE
+        owner_username = ?2
+        AND encrypted_name_cipherbytes = ?3
+";
+
 pub const UPDATE_CREDENTIAL_ENCRYPTED_NOTES_CIPHERBYTES: &str = "
     UPDATE credentials
     SET encrypted_notes_cipherbytes = ?1
@@ -187,12 +496,35 @@ pub const UPDATE_CREDENTIAL_ENCRYPTED_NOTES_NONCE: &str = "
         AND encrypted_name_cipherbytes = ?3
 ";
 
+pub const UPDATE_CREDENTIAL_ENCRYPTED_NOTES_CIPHER: &str = "
+    UPDATE credentials
+    SET encrypted_notes_cipher = ?1
+    WHERE
+        owner_username = ?2
+        AND encrypted_name_cipherbytes = ?3
+";
+
+pub const UPDATE_CREDENTIAL_NAME_INDEX: &str = "
+    UPDATE credentials
+    SET name_index = ?1
+    WHERE
+        owner_username = ?2
+        AND encrypted_name_cipherbytes = ?3
+";
+
 pub const SELECT_FILE_DATA: &str = "
     SELECT
+        id,
         path,
         filename,
         owner_username,
-        contents_nonce
+        contents_nonce,
+        contents_cipher,
+        contents_chunk_size,
+        encrypted_filename_cipherbytes,
+        encrypted_filename_nonce,
+        encrypted_filename_cipher,
+        name_index
     FROM files_data
     WHERE
         path = ?1
@@ -200,32 +532,72 @@ pub const SELECT_FILE_DATA: &str = "
 
 pub const SELECT_ALL_FILES_DATA: &str = "
     SELECT
+        id,
         path,
         filename,
         owner_username,
-        contents_nonce
+        contents_nonce,
+        contents_cipher,
+        contents_chunk_size,
+        encrypted_filename_cipherbytes,
+        encrypted_filename_nonce,
+        encrypted_filename_cipher,
+        name_index
     FROM files_data
 ";
 
 pub const SELECT_ACCOUNT_FILES_DATA: &str = "
     SELECT
+        id,
+        path,
+        filename,
+        owner_username,
+        contents_nonce,
+        contents_cipher,
+        contents_chunk_size,
+        encrypted_filename_cipherbytes,
+        encrypted_filename_nonce,
+        encrypted_filename_cipher,
+        name_index
+    FROM files_data
+    WHERE
+        owner_username = ?1
+";
+
+pub const SELECT_FILE_DATA_BY_NAME_INDEX: &str = "
+    SELECT
+        id,
         path,
         filename,
         owner_username,
-        contents_nonce
+        contents_nonce,
+        contents_cipher,
+        contents_chunk_size,
+        encrypted_filename_cipherbytes,
+        encrypted_filename_nonce,
+        encrypted_filename_cipher,
+        name_index
     FROM files_data
     WHERE
         owner_username = ?1
+        AND name_index = ?2
 ";
 
 pub const INSERT_FILE_DATA: &str = "
     INSERT INTO files_data (
+        id,
         path,
         filename,
         owner_username,
-        contents_nonce
+        contents_nonce,
+        contents_cipher,
+        contents_chunk_size,
+        encrypted_filename_cipherbytes,
+        encrypted_filename_nonce,
+        encrypted_filename_cipher,
+        name_index
     )
-    VALUES (?1, ?2, ?3, ?4)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
 ";
 
 pub const DELETE_FILE_DATA: &str = "
@@ -234,8 +606,809 @@ pub const DELETE_FILE_DATA: &str = "
         path = ?1
 ";
 
+pub const UPDATE_FILE_DATA_PATH: &str = "
+    UPDATE files_data
+    SET path = ?1
+    WHERE path = ?2
+";
+
 pub const UPDATE_FILE_DATA_CONTENTS_NONCE: &str = "
     UPDATE files_data
     SET contents_nonce = ?1
     WHERE path = ?2
 ";
+
+pub const UPDATE_FILE_DATA_CONTENTS_CIPHER: &str = "
+    UPDATE files_data
+    SET contents_cipher = ?1
+    WHERE path = ?2
+";
+
+pub const UPDATE_FILE_DATA_CONTENTS_CHUNK_SIZE: &str = "
+    UPDATE files_data
+    SET contents_chunk_size = ?1
+    WHERE path = ?2
+";
+
+pub const UPDATE_FILE_DATA_ENCRYPTED_FILENAME_CIPHERBYTES: &str = "
+    UPDATE files_data
+    SET encrypted_filename_cipherbytes = ?1
+    WHERE path = ?2
+";
+
+pub const UPDATE_FILE_DATA_ENCRYPTED_FILENAME_NONCE: &str = "
+    UPDATE files_data
+    SET encrypted_filename_nonce = ?1
+    WHERE path = ?2
+";
+
+pub const UPDATE_FILE_DATA_ENCRYPTED_FILENAME_CIPHER: &str = "
+    UPDATE files_data
+    SET encrypted_filename_cipher = ?1
+    WHERE path = ?2
+";
+
+pub const UPDATE_FILE_DATA_NAME_INDEX: &str = "
+    UPDATE files_data
+    SET name_index = ?1
+    WHERE path = ?2
+";
+
+pub const SELECT_FILE_REVISION: &str = "
+    SELECT
+        id,
+        file_id,
+        owner_username,
+        path,
+        replaced_at,
+        contents_nonce,
+        contents_cipher,
+        contents_chunk_size
+    FROM file_revisions
+    WHERE
+        id = ?1
+";
+
+pub const SELECT_ALL_FILE_REVISIONS: &str = "
+    SELECT
+        id,
+        file_id,
+        owner_username,
+        path,
+        replaced_at,
+        contents_nonce,
+        contents_cipher,
+        contents_chunk_size
+    FROM file_revisions
+";
+
+// Newest first.
+pub const SELECT_ACCOUNT_FILE_REVISIONS: &str = "
+    SELECT
+        id,
+        file_id,
+        owner_username,
+        path,
+        replaced_at,
+        contents_nonce,
+        contents_cipher,
+        contents_chunk_size
+    FROM file_revisions
+    WHERE
+        owner_username = ?1    ORDER BY replaced_at DESC, rowid DESC
+";
+
+// Newest first. Revisions replaced in the same second are ordered by when they were inserted.
+pub const SELECT_FILE_REVISIONS_OF_FILE: &str = "
+    SELECT
+        id,
+        file_id,
+        owner_username,
+        path,
+        replaced_at,
+        contents_nonce,
+        contents_cipher,
+        contents_chunk_size
+    FROM file_revisions
+    WHERE
+        owner_username = ?1
+        AND file_id = ?2
+    ORDER BY replaced_at DESC, rowid DESC
+";
+
+// All but the newest ?1 revisions of each file, along with any replaced before ?2.
+pub const SELECT_PRUNABLE_FILE_REVISIONS: &str = "
+    SELECT
+        id,
+        file_id,
+        owner_username,
+        path,
+        replaced_at,
+        contents_nonce,
+        contents_cipher,
+        contents_chunk_size
+    FROM file_revisions
+    WHERE rowid IN (
+        SELECT rowid
+        FROM (
+            SELECT
+                rowid,
+                replaced_at,
+                row_number() OVER (
+                    PARTITION BY owner_username, file_id
+                    ORDER BY replaced_at DESC, rowid DESC
+                ) AS position
+            FROM file_revisions
+        )
+        WHERE
+            position > ?1
+            OR replaced_at < ?2
+    )
+";
+
+pub const INSERT_FILE_REVISION: &str = "
+    INSERT INTO file_revisions (
+        id,
+        file_id,
+        owner_username,
+        path,
+        replaced_at,
+        contents_nonce,
+        contents_cipher,
+        contents_chunk_size
+    )
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+";
+
+pub const DELETE_FILE_REVISION: &str = "
+    DELETE FROM file_revisions
+    WHERE
+        id = ?1
+";
+
+pub const UPDATE_FILE_REVISION_PATH: &str = "
+    UPDATE file_revisions
+    SET path = ?1
+    WHERE id = ?2
+";
+
+pub const UPDATE_FILE_REVISION_CONTENTS_NONCE: &str = "
+    UPDATE file_revisions
+    SET contents_nonce = ?1
+    WHERE id = ?2
+";
+
+pub const UPDATE_FILE_REVISION_CONTENTS_CIPHER: &str = "
+    UPDATE file_revisions
+    SET contents_cipher = ?1
+    WHERE id = ?2
+";
+
+pub const UPDATE_FILE_REVISION_CONTENTS_CHUNK_SIZE: &str = "
+    UPDATE file_revisions
+    SET contents_chunk_size = ?1
+    WHERE id = ?2
+";
+
+pub const SELECT_TRASHED_ITEM: &str = "
+    SELECT
+        owner_username,
+        item_type,
+        item_id,
+        trashed_at
+    FROM trash
+    WHERE
+        owner_username = ?1
+        AND item_type = ?2
+        AND item_id = ?3
+";
+
+pub const SELECT_ALL_TRASHED_ITEMS: &str = "
+    SELECT
+        owner_username,
+        item_type,
+        item_id,
+        trashed_at
+    FROM trash
+";
+
+// Newest first.
+pub const SELECT_ACCOUNT_TRASHED_ITEMS: &str = "
+    SELECT
+        owner_username,
+        item_type,
+        item_id,
+        trashed_at
+    FROM trash
+    WHERE
+        owner_username = ?1
+    ORDER BY trashed_at DESC, rowid DESC
+";
+
+pub const SELECT_EXPIRED_TRASHED_ITEMS: &str = "
+    SELECT
+        owner_username,
+        item_type,
+        item_id,
+        trashed_at
+    FROM trash
+    WHERE
+        owner_username = ?1
+        AND trashed_at < ?2
+";
+
+pub const INSERT_TRASHED_ITEM: &str = "
+    INSERT INTO trash (
+        owner_username,
+        item_type,
+        item_id,
+        trashed_at
+    )
+    VALUES (?1, ?2, ?3, ?4)
+";
+
+pub const DELETE_TRASHED_ITEM: &str = "
+    DELETE FROM trash
+    WHERE
+        owner_username = ?1
+        AND item_type = ?2
+        AND item_id = ?3
+";
+
+pub const ADVANCE_TOTP_STEP: &str = "
+    INSERT INTO totp_steps (owner_username, last_step)
+    VALUES (?1, ?2)
+    ON CONFLICT(owner_username) DO UPDATE SET last_step = excluded.last_step
+    WHERE last_step < excluded.last_step
+";
+
+pub const SELECT_TOTP_BACKUP: &str = "
+    SELECT
+        id,
+        owner_username,
+        encrypted_secret_cipherbytes,
+        encrypted_secret_nonce,
+        encrypted_secret_cipher
+    FROM totp_backups
+    WHERE
+        owner_username = ?1
+        AND id = ?2
+";
+
+pub const SELECT_ALL_TOTP_BACKUPS: &str = "
+    SELECT
+        id,
+        owner_username,
+        encrypted_secret_cipherbytes,
+        encrypted_secret_nonce,
+        encrypted_secret_cipher
+    FROM totp_backups
+";
+
+pub const SELECT_ACCOUNT_TOTP_BACKUPS: &str = "
+    SELECT
+        id,
+        owner_username,
+        encrypted_secret_cipherbytes,
+        encrypted_secret_nonce,
+        encrypted_secret_cipher
+    FROM totp_backups
+    WHERE
+        owner_username = ?1
+";
+
+pub const INSERT_TOTP_BACKUP: &str = "
+    INSERT INTO totp_backups (
+        id,
+        owner_username,
+        encrypted_secret_cipherbytes,
+        encrypted_secret_nonce,
+        encrypted_secret_cipher
+    )
+    VALUES (?1, ?2, ?3, ?4, ?5)
+";
+
+pub const DELETE_TOTP_BACKUP: &str = "
+    DELETE FROM totp_backups
+    WHERE
+        owner_username = ?1
+        AND id = ?2
+";
+
+pub const SELECT_CREDENTIAL_VERSION: &str = "
+    SELECT
+        id,
+        credential_id,
+        owner_username,
+        replaced_at,
+        encrypted_username_cipherbytes,
+        encrypted_username_nonce,
+        encrypted_username_cipher,
+        encrypted_password_cipherbytes,
+        encrypted_password_nonce,
+        encrypted_password_cipher,
+        encrypted_notes_cipherbytes,
+        encrypted_notes_nonce,
+        encrypted_notes_cipher
+    FROM credential_history
+    WHERE
+        owner_username = ?1
+        AND id = ?2
+";
+
+pub const SELECT_ALL_CREDENTIAL_VERSIONS: &str = "
+    SELECT
+        id,
+        credential_id,
+        owner_username,
+        replaced_at,
+        encrypted_username_cipherbytes,
+        encrypted_username_nonce,
+        encrypted_username_cipher,
+        encrypted_password_cipherbytes,
+        encrypted_password_nonce,
+        encrypted_password_cipher,
+        encrypted_notes_cipherbytes,
+        encrypted_notes_nonce,
+        encrypted_notes_cipher
+    FROM credential_history
+";
+
+pub const SELECT_ACCOUNT_CREDENTIAL_VERSIONS: &str = "
+    SELECT
+        id,
+        credential_id,
+        owner_username,
+        replaced_at,
+        encrypted_username_cipherbytes,
+        encrypted_username_nonce,
+        encrypted_username_cipher,
+        encrypted_password_cipherbytes,
+        encrypted_password_nonce,
+        encrypted_password_cipher,
+        encrypted_notes_cipherbytes,
+        encrypted_notes_nonce,
+        encrypted_notes_cipher
+    FROM credential_history
+    WHERE
+        owner_username = ?1
+";
+
+// Newest first. Versions replaced in the same second are ordered by when they were inserted.
+pub const SELECT_CREDENTIAL_HISTORY: &str = "
+    SELECT
+        id,
+        credential_id,
+        owner_username,
+        replaced_at,
+        encrypted_username_cipherbytes,
+        encrypted_username_nonce,
+        encrypted_username_cipher,
+        encrypted_password_cipherbytes,
+        encrypted_password_nonce,
+        encrypted_password_cipher,
+        encrypted_notes_cipherbytes,
+        encrypted_notes_nonce,
+        encrypted_notes_cipher
+    FROM credential_history
+    WHERE
+        owner_username = ?1
+        AND credential_id = ?2
+    ORDER BY replaced_at DESC, rowid DESC
+";
+
+pub const INSERT_CREDENTIAL_VERSION: &str = "
+    INSERT INTO credential_history (
+        id,
+        credential_id,
+        owner_username,
+        replaced_at,
+        encrypted_username_cipherbytes,
+        encrypted_username_nonce,
+        encrypted_username_cipher,
+        encrypted_password_cipherbytes,
+        encrypted_password_nonce,
+        encrypted_password_cipher,
+        encrypted_notes_cipherbytes,
+        encrypted_notes_nonce,
+        encrypted_notes_cipher
+    )
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+";
+
+pub const DELETE_CREDENTIAL_VERSION: &str = "
+    DELETE FROM credential_history
+    WHERE
+        owner_username = ?1
+        AND id = ?2
+";
+
+pub const DELETE_CREDENTIAL_HISTORY: &str = "
+    DELETE FROM credential_history
+    WHERE
+        owner_username = ?1
+        AND credential_id = ?2
+";
+
+// Keep only the newest ?1 versions of each credential.
+pub const PRUNE_CREDENTIAL_HISTORY: &str = "
+    DELETE FROM credential_history
+    WHERE rowid IN (
+        SELECT rowid
+        FROM (
+            SELECT
+                rowid,
+                row_number() OVER (
+                    PARTITION BY owner_username, credential_id
+                    ORDER BY replaced_at DESC, rowid DESC
+                ) AS position
+            FROM credential_history
+        )
+        WHERE position > ?1
+    )
+";
+
+pub const SELECT_SHARED_CREDENTIAL: &str = "
+    SELECT
+        id,
+        owner_username,
+        item_id,
+        encrypted_item_key_cipherbytes,
+        encrypted_item_key_nonce,
+        encrypted_item_key_cipher,
+        encrypted_name_cipherbytes,
+        encrypted_name_nonce,
+        encrypted_name_cipher,
+        encrypted_username_cipherbytes,
+        encrypted_username_nonce,
+        encrypted_username_cipher,
+        encrypted_password_cipherbytes,
+        encrypted_password_nonce,
+        encrypted_password_cipher,
+        encrypted_notes_cipherbytes,
+        encrypted_notes_nonce,
+        encrypted_notes_cipher
+    FROM shared_credentials
+    WHERE
+        id = ?1
+";
+
+pub const SELECT_ALL_SHARED_CREDENTIALS: &str = "
+    SELECT
+        id,
+        owner_username,
+        item_id,
+        encrypted_item_key_cipherbytes,
+        encrypted_item_key_nonce,
+        encrypted_item_key_cipher,
+        encrypted_name_cipherbytes,
+        encrypted_name_nonce,
+        encrypted_name_cipher,
+        encrypted_username_cipherbytes,
+        encrypted_username_nonce,
+        encrypted_username_cipher,
+        encrypted_password_cipherbytes,
+        encrypted_password_nonce,
+        encrypted_password_cipher,
+        encrypted_notes_cipherbytes,
+        encrypted_notes_nonce,
+        encrypted_notes_cipher
+    FROM shared_credentials
+";
+
+pub const SELECT_ACCOUNT_SHARED_CREDENTIALS: &str = "
+    SELECT
+        id,
+        owner_username,
+        item_id,
+        encrypted_item_key_cipherbytes,
+        encrypted_item_key_nonce,
+        encrypted_item_key_cipher,
+        encrypted_name_cipherbytes,
+        encrypted_name_nonce,
+        encrypted_name_cipher,
+        encrypted_username_cipherbytes,
+        encrypted_username_nonce,
+        encrypted_username_cipher,
+        encrypted_password_cipherbytes,
+        encrypted_password_nonce,
+        encrypted_password_cipher,
+        encrypted_notes_cipherbytes,
+        encrypted_notes_nonce,
+        encrypted_notes_cipher
+    FROM shared_credentials
+    WHERE
+        owner_username = ?1
+";
+
+pub const INSERT_SHARED_CREDENTIAL: &str = "
+    INSERT INTO shared_credentials (
+        id,
+        owner_username,
+        item_id,
+        encrypted_item_key_cipherbytes,
+        encrypted_item_key_nonce,
+        encrypted_item_key_cipher,
+        encrypted_name_cipherbytes,
+        encrypted_name_nonce,
+        encrypted_name_cipher,
+        encrypted_username_cipherbytes,
+        encrypted_username_nonce,
+        encrypted_username_cipher,
+        encrypted_password_cipherbytes,
+        encrypted_password_nonce,
+        encrypted_password_cipher,
+        encrypted_notes_cipherbytes,
+        encrypted_notes_nonce,
+        encrypted_notes_cipher
+    )
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)
+";
+
+pub const DELETE_SHARED_CREDENTIAL: &str = "
+    DELETE FROM shared_credentials
+    WHERE
+        id = ?1
+";
+
+pub const SELECT_SHARED_FILE_DATA: &str = "
+    SELECT
+        id,
+        path,
+        owner_username,
+        item_id,
+        encrypted_item_key_cipherbytes,
+        encrypted_item_key_nonce,
+        encrypted_item_key_cipher,
+        contents_nonce,
+        contents_cipher,
+        contents_chunk_size,
+        encrypted_filename_cipherbytes,
+        encrypted_filename_nonce,
+        encrypted_filename_cipher
+    FROM shared_files_data
+    WHERE
+        id = ?1
+";
+
+pub const SELECT_ALL_SHARED_FILES_DATA: &str = "
+    SELECT
+        id,
+        path,
+        owner_username,
+        item_id,
+        encrypted_item_key_cipherbytes,
+        encrypted_item_key_nonce,
+        encrypted_item_key_cipher,
+        contents_nonce,
+        contents_cipher,
+        contents_chunk_size,
+        encrypted_filename_cipherbytes,
+        encrypted_filename_nonce,
+        encrypted_filename_cipher
+    FROM shared_files_data
+";
+
+pub const SELECT_ACCOUNT_SHARED_FILES_DATA: &str = "
+    SELECT
+        id,
+        path,
+        owner_username,
+        item_id,
+        encrypted_item_key_cipherbytes,
+        encrypted_item_key_nonce,
+        encrypted_item_key_cipher,
+        contents_nonce,
+        contents_cipher,
+        contents_chunk_size,
+        encrypted_filename_cipherbytes,
+        encrypted_filename_nonce,
+        encrypted_filename_cipher
+    FROM shared_files_data
+    WHERE
+        owner_username = ?1
+";
+
+pub const INSERT_SHARED_FILE_DATA: &str = "
+    INSERT INTO shared_files_data (
+        id,
+        path,
+        owner_username,
+        item_id,
+        encrypted_item_key_cipherbytes,
+        encrypted_item_key_nonce,
+        encrypted_item_key_cipher,
+        contents_nonce,
+        contents_cipher,
+        contents_chunk_size,
+        encrypted_filename_cipherbytes,
+        encrypted_filename_nonce,
+        encrypted_filename_cipher
+    )
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+";
+
+pub const DELETE_SHARED_FILE_DATA: &str = "
+    DELETE FROM shared_files_data
+    WHERE
+        id = ?1
+";
+
+pub const UPDATE_SHARED_FILE_DATA_PATH: &str = "
+    UPDATE shared_files_data
+    SET path = ?1
+    WHERE id = ?2
+";
+
+pub const SELECT_SHARE_GRANT: &str = "
+    SELECT
+        shared_id,
+        owner_username,
+        recipient_username,
+        item_type,
+        ephemeral_public_key,
+        encrypted_item_key_cipherbytes,
+        encrypted_item_key_nonce,
+        encrypted_item_key_cipher
+    FROM share_grants
+    WHERE
+        shared_id = ?1
+        AND recipient_username = ?2
+";
+
+pub const SELECT_ALL_SHARE_GRANTS: &str = "
+    SELECT
+        shared_id,
+        owner_username,
+        recipient_username,
+        item_type,
+        ephemeral_public_key,
+        encrypted_item_key_cipherbytes,
+        encrypted_item_key_nonce,
+        encrypted_item_key_cipher
+    FROM share_grants
+";
+
+pub const SELECT_ACCOUNT_SHARE_GRANTS: &str = "
+    SELECT
+        shared_id,
+        owner_username,
+        recipient_username,
+        item_type,
+        ephemeral_public_key,
+        encrypted_item_key_cipherbytes,
+        encrypted_item_key_nonce,
+        encrypted_item_key_cipher
+    FROM share_grants
+    WHERE
+        owner_username = ?1
+";
+
+pub const SELECT_SHARED_ITEM_GRANTS: &str = "
+    SELECT
+        shared_id,
+        owner_username,
+        recipient_username,
+        item_type,
+        ephemeral_public_key,
+        encrypted_item_key_cipherbytes,
+        encrypted_item_key_nonce,
+        encrypted_item_key_cipher
+    FROM share_grants
+    WHERE
+        shared_id = ?1
+";
+
+pub const SELECT_RECIPIENT_SHARE_GRANTS: &str = "
+    SELECT
+        shared_id,
+        owner_username,
+        recipient_username,
+        item_type,
+        ephemeral_public_key,
+        encrypted_item_key_cipherbytes,
+        encrypted_item_key_nonce,
+        encrypted_item_key_cipher
+    FROM share_grants
+    WHERE
+        recipient_username = ?1
+";
+
+pub const INSERT_SHARE_GRANT: &str = "
+    INSERT INTO share_grants (
+        shared_id,
+        owner_username,
+        recipient_username,
+        item_type,
+        ephemeral_public_key,
+        encrypted_item_key_cipherbytes,
+        encrypted_item_key_nonce,
+        encrypted_item_key_cipher
+    )
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+";
+
+pub const DELETE_SHARE_GRANT: &str = "
+    DELETE FROM share_grants
+    WHERE
+        shared_id = ?1
+        AND recipient_username = ?2
+";
+
+pub const SELECT_SETTING: &str = "
+    SELECT value
+    FROM settings
+    WHERE
+        name = ?1
+";
+
+pub const UPSERT_SETTING: &str = "
+    INSERT INTO settings (name, value)
+    VALUES (?1, ?2)
+    ON CONFLICT(name) DO UPDATE SET value = excluded.value
+";
+
+pub const SELECT_FILE_CHUNK: &str = "
+    SELECT contents
+    FROM file_chunks
+    WHERE
+        path = ?1
+        AND chunk_index = ?2
+";
+
+pub const SELECT_FILE_CONTENTS_LENGTH: &str = "
+    SELECT (
+        SELECT coalesce(sum(length(contents)), 0)
+        FROM file_chunks
+        WHERE
+            path = ?1
+    )
+    FROM file_contents
+    WHERE
+        path = ?1
+";
+
+pub const SELECT_FILE_CONTENTS_PATHS: &str = "
+    SELECT path
+    FROM file_contents
+";
+
+pub const INSERT_FILE_CONTENTS: &str = "
+    INSERT INTO file_contents (
+        path,
+        owner_username
+    )
+    VALUES (?1, ?2)
+";
+
+pub const INSERT_FILE_CHUNK: &str = "
+    INSERT INTO file_chunks (
+        path,
+        chunk_index,
+        contents
+    )
+    VALUES (?1, ?2, ?3)
+";
+
+pub const UPDATE_FILE_CONTENTS_PATH: &str = "
+    UPDATE file_contents
+    SET path = ?2
+    WHERE
+        path = ?1
+";
+
+pub const DELETE_FILE_CONTENTS: &str = "
+    DELETE FROM file_contents
+    WHERE
+        path = ?1
+";
+
+pub const DELETE_OWNED_FILE_CONTENTS: &str = "
+    DELETE FROM file_contents
+    WHERE
+        owner_username = ?1
+";
+
+pub const DELETE_ALL_FILE_CONTENTS: &str = "
+    DELETE FROM file_contents
+";
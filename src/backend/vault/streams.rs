@@ -0,0 +1,290 @@
+//! Streaming the encrypted contents of files in & out of a [Vault](super::Vault)'s
+//! [FileStore](super::file_store::FileStore).
+use std::io::{self, Read};
+
+use camino::Utf8Path;
+use color_eyre::eyre;
+use rusqlite::Connection;
+use zeroize::Zeroizing;
+
+use super::{
+    super::{
+        encryption::{
+            cipher_suite::CipherSuite,
+            encrypted::{Aes256Key, Encrypted, Nonce},
+            stream::{copy_secret, StreamDecryptor, StreamEncryptor, DEFAULT_CHUNK_SIZE},
+        },
+        file_data::FileData,
+    },
+    file_store::FileStore,
+    filesystem::get_pending_file_path,
+    Vault,
+};
+
+impl Vault {
+    // Helper function: Open a [Read]er of the decrypted contents of the file at `path` in the
+    // [FileStore], which are described by the given [FileData].
+    pub(super) fn open_contents<'a>(
+        file_store: &FileStore,
+        conn: &'a Connection,
+        file_data: &FileData,
+        path: &Utf8Path,
+        key: &'a Aes256Key,
+        aad: &[u8],
+    ) -> eyre::Result<Box<dyn Read + 'a>> {
+        let mut file = file_store.open(conn, path)?;
+        match file_data.contents_chunk_size() {
+            // Files encrypted before streaming encryption was added are a single message.
+            0 => {
+                let mut cipherbytes = Vec::new();
+                file.read_to_end(&mut cipherbytes)?;
+                let encrypted_file =
+                    Encrypted::from_fields(cipherbytes, file_data.contents_nonce());
+                let contents = Zeroizing::new(encrypted_file.try_decrypt_bytes(key, aad)?);
+                Ok(Box::new(io::Cursor::new(contents)))
+            }
+            chunk_size => Ok(Box::new(StreamDecryptor::new(
+                file,
+                key,
+                file_data.contents_nonce(),
+                chunk_size,
+                aad,
+            )?)),
+        }
+    }
+
+    // Helper function: Encrypt the contents read from the `reader` to a new file at `path` in the
+    // [FileStore] with the [DEFAULT_CHUNK_SIZE]. The new file is deleted on a failure.
+    pub(super) fn write_contents<R>(
+        file_store: &FileStore,
+        conn: &Connection,
+        mut reader: R,
+        path: &Utf8Path,
+        key: &Aes256Key,
+        nonce: Nonce,
+        aad: &[u8],
+    ) -> eyre::Result<()>
+    where
+        R: Read,
+    {
+        file_store.write_new(conn, path, |file| {
+            let mut encryptor = StreamEncryptor::new(file, key, nonce, DEFAULT_CHUNK_SIZE, aad)?;
+            copy_secret(&mut reader, &mut encryptor)?;
+            encryptor.finish()?;
+            Ok(())
+        })
+    }
+
+    // Helper function: Re-encrypt the contents of each file to its pending sibling with
+    // `new_key`, returning the new header [Nonce] of each. `old_aad` gives the associated data
+    // the contents of each file are currently encrypted with. All the pending siblings are
+    // deleted on a failure.
+    pub(super) fn write_pending_files<A>(
+        file_store: &FileStore,
+        conn: &Connection,
+        files_data: &[FileData],
+        old_key: &Aes256Key,
+        old_aad: A,
+        new_key: &Aes256Key,
+        cipher_suite: CipherSuite,
+    ) -> eyre::Result<Vec<Nonce>>
+    where
+        A: Fn(&FileData) -> Vec<u8>,
+    {
+        let mut new_nonces = Vec::with_capacity(files_data.len());
+        let mut pending_paths = Vec::with_capacity(files_data.len());
+        let write_result = files_data.iter().try_for_each(|file_data| {
+            let contents = Self::open_contents(
+                file_store,
+                conn,
+                file_data,
+                file_data.path(),
+                old_key,
+                &old_aad(file_data),
+            )?;
+            let pending_path = get_pending_file_path(file_data.path(), file_data.id());
+            let new_nonce = cipher_suite.new_rand_nonce();
+            Self::write_contents(
+                file_store,
+                conn,
+                contents,
+                &pending_path,
+                new_key,
+                new_nonce,
+                &file_data.contents_associated_data(),
+            )?;
+            pending_paths.push(pending_path);
+            new_nonces.push(new_nonce);
+            Ok::<(), eyre::Report>(())
+        });
+        if let Err(err) = write_result {
+            for pending_path in pending_paths {
+                let _ = file_store.remove(conn, &pending_path);
+            }
+            return Err(err);
+        }
+        Ok(new_nonces)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::{assert_eq, assert_ne};
+    use std::fs;
+
+    use super::super::{
+        super::{
+            encryption::{encrypted::new_rand_key, stream::DEFAULT_CHUNK_SIZE},
+            file_data::FileData,
+        },
+        filesystem::get_pending_file_path,
+        tests::{db_path, fs_dir, refresh_test_db, refresh_test_fs},
+        Vault,
+    };
+
+    #[test]
+    fn stream_files() {
+        let db_name = "stream_files.db";
+        let fs_name = "stream_files";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+
+        let username = "mr_test";
+        let password = "open sesame!";
+        vault.create_new_account(username, password, None).unwrap();
+        let key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+
+        // Contents spanning several chunks.
+        let contents: Vec<u8> = (0..(3 * DEFAULT_CHUNK_SIZE + 123))
+            .map(|i| (i % 251) as u8)
+            .collect();
+        vault
+            .import_file("big", username, contents.as_slice(), &key)
+            .unwrap();
+        let _ = vault
+            .import_file("big", username, &b"again"[..], &key)
+            .unwrap_err();
+
+        let mut exported = vec![];
+        let num_bytes = vault
+            .export_file(username, "big", &key, &mut exported)
+            .unwrap();
+        assert_eq!(num_bytes, contents.len() as u64);
+        assert_eq!(exported, contents);
+        assert_eq!(
+            vault.verify_file(username, "big", &key).unwrap(),
+            contents.len() as u64
+        );
+        let (file_data, loaded): (FileData, Vec<u8>) =
+            vault.load_file(username, "big", &key).unwrap();
+        assert_eq!(file_data.contents_chunk_size(), DEFAULT_CHUNK_SIZE);
+        assert_eq!(loaded, contents);
+        let _ = vault
+            .verify_file(username, "big", &new_rand_key())
+            .unwrap_err();
+
+        // Replacing the contents with shorter ones.
+        vault
+            .update_file_from(username, "big", &key, &b"small"[..])
+            .unwrap();
+        let (new_file_data, loaded): (FileData, String) =
+            vault.load_file(username, "big", &key).unwrap();
+        assert_eq!(loaded, "small");
+        assert_ne!(new_file_data.contents_nonce(), file_data.contents_nonce());
+        assert!(!get_pending_file_path(file_data.path(), file_data.id())
+            .try_exists()
+            .unwrap());
+
+        // Truncation is detected.
+        vault
+            .update_file_from(username, "big", &key, contents.as_slice())
+            .unwrap();
+        let encrypted_len = fs::metadata(file_data.path()).unwrap().len();
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .open(file_data.path())
+            .unwrap();
+        file.set_len(encrypted_len - (DEFAULT_CHUNK_SIZE as u64 + 123 + 32))
+            .unwrap();
+        let _ = vault.verify_file(username, "big", &key).unwrap_err();
+    }
+    #[test]
+    fn stream_files_single_file() {
+        let db_name = "stream_files_single_file.db";
+        let db_path = db_path(db_name);
+        refresh_test_db(db_name);
+
+        let mut vault = Vault::connect_single_file(&db_path).unwrap();
+
+        let username = "mr_test";
+        let password = "open sesame!";
+        vault.create_new_account(username, password, None).unwrap();
+        let key = vault
+            .load_unlocked_account(username, password, None, None)
+            .unwrap()
+            .key()
+            .clone();
+
+        // Contents spanning several stream chunks & several chunks in the database.
+        let contents: Vec<u8> = (0..(2 * 1024 * 1024 + 3 * DEFAULT_CHUNK_SIZE + 123))
+            .map(|i| (i % 251) as u8)
+            .collect();
+        vault
+            .import_file("big", username, contents.as_slice(), &key)
+            .unwrap();
+        let file_data = vault.load_file_data(username, "big", &key).unwrap();
+        let count_chunks = |vault: &Vault| -> u32 {
+            vault
+                .database
+                .connection()
+                .query_row(
+                    "SELECT count(*) FROM file_chunks WHERE path = ?1",
+                    [file_data.path().as_str()],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+        assert_eq!(count_chunks(&vault), 3);
+
+        let mut exported = vec![];
+        let num_bytes = vault
+            .export_file(username, "big", &key, &mut exported)
+            .unwrap();
+        assert_eq!(num_bytes, contents.len() as u64);
+        assert_eq!(exported, contents);
+        assert_eq!(
+            vault.verify_file(username, "big", &key).unwrap(),
+            contents.len() as u64
+        );
+
+        // Replacing the contents with shorter ones.
+        vault
+            .update_file_from(username, "big", &key, &b"small"[..])
+            .unwrap();
+        let (_, loaded): (FileData, String) = vault.load_file(username, "big", &key).unwrap();
+        assert_eq!(loaded, "small");
+        assert_eq!(count_chunks(&vault), 1);
+
+        // Truncation is detected.
+        vault
+            .update_file_from(username, "big", &key, contents.as_slice())
+            .unwrap();
+        vault
+            .database
+            .connection()
+            .execute(
+                "DELETE FROM file_chunks WHERE path = ?1 AND chunk_index = 2",
+                [file_data.path().as_str()],
+            )
+            .unwrap();
+        let _ = vault.verify_file(username, "big", &key).unwrap_err();
+    }
+}
@@ -0,0 +1,612 @@
+//! The trash of a [Vault](super::Vault), which keeps deleted credentials, files & accounts until
+//! they're restored or purged.
+use std::collections::HashSet;
+
+use color_eyre::eyre::{self, eyre};
+use rusqlite::{types::Value, Transaction};
+
+use super::{
+    super::{
+        account::UnlockedAccount,
+        credential::Credential,
+        encryption::{associated_data::EntityType, encrypted::Aes256Key},
+        file_data::FileData,
+        key_file::KeyFile,
+        secret::Secret,
+        totp::TwoFactorCode,
+        trash::TrashedItem,
+    },
+    database::Database,
+    database_traits::{credential_primary_key, CredentialUpdateField},
+    Vault,
+};
+
+impl Vault {
+    /// Check whether the [Account] with the given `username` is in the trash.
+    pub fn is_account_trashed<S>(&self, username: S) -> eyre::Result<bool>
+    where
+        S: AsRef<str>,
+    {
+        let account_name = self.account_name(username.as_ref());
+        Ok(self
+            .select_trashed_ids(&account_name, EntityType::Account)?
+            .contains(&account_name))
+    }
+
+    /// Load the [Credential]s in the trash of the given `owner_username`, along with their
+    /// [TrashedItem]s, most recently trashed first.
+    pub fn load_trashed_credentials<S>(
+        &self,
+        owner_username: S,
+    ) -> eyre::Result<Vec<(TrashedItem, Credential)>>
+    where
+        S: AsRef<str>,
+    {
+        let account_name = self.account_name(owner_username.as_ref());
+        let mut credentials = self.select_account_credentials(&account_name)?;
+        Ok(self
+            .select_account_trash(&account_name, EntityType::Credential)?
+            .into_iter()
+            .filter_map(|trashed_item| {
+                let index = credentials
+                    .iter()
+                    .position(|credential| credential.id() == trashed_item.item_id())?;
+                Some((trashed_item, credentials.swap_remove(index)))
+            })
+            .collect())
+    }
+
+    /// Load the [FileData] of the files in the trash of the given `owner_username`, along with
+    /// their [TrashedItem]s, most recently trashed first.
+    pub fn load_trashed_files_data<S>(
+        &self,
+        owner_username: S,
+    ) -> eyre::Result<Vec<(TrashedItem, FileData)>>
+    where
+        S: AsRef<str>,
+    {
+        let account_name = self.account_name(owner_username.as_ref());
+        let mut files_data = self.select_account_files_data(&account_name)?;
+        Ok(self
+            .select_account_trash(&account_name, EntityType::File)?
+            .into_iter()
+            .filter_map(|trashed_item| {
+                let index = files_data
+                    .iter()
+                    .position(|file_data| file_data.id() == trashed_item.item_id())?;
+                Some((trashed_item, files_data.swap_remove(index)))
+            })
+            .collect())
+    }
+
+    /// Restore the most recently trashed [Credential] with the given `owner_username` & `name`
+    /// from the trash. Fails if the owner already has a [Credential] with that name.
+    pub fn restore_trashed_credential<O, N>(
+        &mut self,
+        owner_username: O,
+        name: N,
+        key: &Aes256Key,
+    ) -> eyre::Result<()>
+    where
+        O: AsRef<str>,
+        N: AsRef<str>,
+    {
+        let (trashed_item, credential) =
+            self.find_trashed_credential(&owner_username, &name, key)?;
+        if self.load_credential(&owner_username, &name, key).is_ok() {
+            return Err(eyre!(
+                "Credential \"{}\" already exists. Rename or delete it before restoring.",
+                name.as_ref()
+            ));
+        }
+        let name_index = Credential::compute_name_index(key, name.as_ref())?;
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Index its name again.
+        let num_rows = Database::transaction_update::<Credential, Value, &[u8], 2, 1>(
+            credential_primary_key(
+                credential.owner_username(),
+                credential.encrypted_name().cipherbytes(),
+            ),
+            CredentialUpdateField::NameIndex,
+            [&name_index],
+            &tx,
+        )?;
+        Self::validate_one_row(num_rows)?;
+        Self::transaction_untrash(&trashed_item, &tx)?;
+        // Commit the database transaction.
+        Ok(tx.commit()?)
+    }
+
+    /// Restore the most recently trashed file with the given `owner_username` & `filename` from
+    /// the trash. Fails if the owner already has a file with that name.
+    pub fn restore_trashed_file<O, F>(
+        &mut self,
+        owner_username: O,
+        filename: F,
+        key: &Aes256Key,
+    ) -> eyre::Result<()>
+    where
+        O: AsRef<str>,
+        F: AsRef<str>,
+    {
+        let (trashed_item, file_data) = self.find_trashed_file(&owner_username, &filename, key)?;
+        if self.load_file_data(&owner_username, &filename, key).is_ok() {
+            return Err(eyre!(
+                "File \"{}\" already exists. Rename or delete it before restoring.",
+                filename.as_ref()
+            ));
+        }
+        let name_index = FileData::compute_name_index(key, filename.as_ref())?;
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        // Index its name again.
+        Self::transaction_update_file_name_index(file_data.path(), &name_index, &tx)?;
+        Self::transaction_untrash(&trashed_item, &tx)?;
+        // Commit the database transaction.
+        Ok(tx.commit()?)
+    }
+
+    /// Permanently delete the most recently trashed [Credential] with the given `owner_username`
+    /// & `name`, along with its previous versions.
+    pub fn purge_trashed_credential<O, N>(
+        &mut self,
+        owner_username: O,
+        name: N,
+        key: &Aes256Key,
+    ) -> eyre::Result<()>
+    where
+        O: AsRef<str>,
+        N: AsRef<str>,
+    {
+        let (_, credential) = self.find_trashed_credential(&owner_username, &name, key)?;
+        self.remove_credential(&credential)
+    }
+
+    /// Permanently delete the most recently trashed file with the given `owner_username` &
+    /// `filename`, along with its revisions.
+    pub fn purge_trashed_file<O, F>(
+        &mut self,
+        owner_username: O,
+        filename: F,
+        key: &Aes256Key,
+    ) -> eyre::Result<()>
+    where
+        O: AsRef<str>,
+        F: AsRef<str>,
+    {
+        let (_, file_data) = self.find_trashed_file(&owner_username, &filename, key)?;
+        self.remove_file(&file_data, false)
+    }
+
+    /// Permanently delete every [Credential] & file in the trash of the given `owner_username`.
+    /// Return the number of items purged.
+    pub fn empty_trash<S>(&mut self, owner_username: S) -> eyre::Result<usize>
+    where
+        S: AsRef<str>,
+    {
+        let trashed_credentials = self.load_trashed_credentials(&owner_username)?;
+        let trashed_files_data = self.load_trashed_files_data(&owner_username)?;
+        let num_purged = trashed_credentials.len() + trashed_files_data.len();
+        for (_, credential) in trashed_credentials {
+            self.remove_credential(&credential)?;
+        }
+        for (_, file_data) in trashed_files_data {
+            self.remove_file(&file_data, true)?;
+        }
+        Ok(num_purged)
+    }
+
+    /// Restore the [Account] with the given `username` from the trash & unlock it, like
+    /// [Vault::load_unlocked_account]. The items it shared before it was trashed aren't shared
+    /// again.
+    pub fn restore_account<U, P>(
+        &mut self,
+        username: U,
+        password: P,
+        key_file: Option<&KeyFile>,
+        two_factor_code: Option<&TwoFactorCode>,
+    ) -> eyre::Result<UnlockedAccount>
+    where
+        U: AsRef<str>,
+        P: AsRef<str>,
+    {
+        let trashed_item = self.find_trashed_account(username.as_ref())?;
+        let unlocked_account =
+            self.unlock_account(username.as_ref(), password, key_file, two_factor_code)?;
+        // The account may have been in the trash for longer than the retention period.
+        self.purge_expired_trash(trashed_item.item_id())?;
+        if !self.is_account_trashed(username.as_ref())? {
+            return Err(eyre!(
+                "Account \"{}\" was in the trash for longer than the retention period & has been \
+                purged.",
+                username.as_ref()
+            ));
+        }
+        // Open a new database transaction.
+        let tx = self.database.open_transaction()?;
+        Self::transaction_untrash(&trashed_item, &tx)?;
+        // Commit the database transaction.
+        tx.commit()?;
+        Ok(unlocked_account)
+    }
+
+    /// Permanently delete the [Account] with the given `username` from the trash, along with all
+    /// its credentials & files. It must be unlocked with its credentials to be purged.
+    pub fn purge_account<U, P>(
+        &mut self,
+        username: U,
+        password: P,
+        key_file: Option<&KeyFile>,
+        two_factor_code: Option<&TwoFactorCode>,
+    ) -> eyre::Result<()>
+    where
+        U: AsRef<str>,
+        P: AsRef<str>,
+    {
+        let trashed_item = self.find_trashed_account(username.as_ref())?;
+        self.unlock_account(username, password, key_file, two_factor_code)?;
+        self.remove_account(trashed_item.item_id())
+    }
+
+    // Helper function: Permanently delete every item of the account stored under the given
+    // `account_name` which has been in the trash for longer than the [Vault]'s trash retention
+    // period.
+    pub(super) fn purge_expired_trash(&mut self, account_name: &str) -> eyre::Result<()> {
+        let Some(retention_days) = self.trash_retention else {
+            return Ok(());
+        };
+        let trashed_before =
+            (self.clock)().saturating_sub(u64::from(retention_days) * 24 * 60 * 60);
+        for trashed_item in self
+            .database
+            .select_expired_trash(account_name, trashed_before)?
+        {
+            let owner_username = trashed_item.owner_username();
+            match trashed_item.entity_type() {
+                EntityType::Account => {
+                    // Its items may have been purged along with an earlier entry.
+                    if self
+                        .select_trashed_ids(owner_username, EntityType::Account)?
+                        .contains(owner_username)
+                    {
+                        self.remove_account(owner_username)?;
+                    }
+                }
+                EntityType::Credential => {
+                    let credential = self
+                        .select_account_credentials(owner_username)?
+                        .into_iter()
+                        .find(|credential| credential.id() == trashed_item.item_id());
+                    if let Some(credential) = credential {
+                        self.remove_credential(&credential)?;
+                    }
+                }
+                EntityType::File => {
+                    let file_data = self
+                        .select_account_files_data(owner_username)?
+                        .into_iter()
+                        .find(|file_data| file_data.id() == trashed_item.item_id());
+                    if let Some(file_data) = file_data {
+                        self.remove_file(&file_data, true)?;
+                    }
+                }
+                _ => {}
+            }
+            // Drop the entry if its item was already gone.
+            let tx = self.database.open_transaction()?;
+            Self::transaction_untrash(&trashed_item, &tx)?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    // Helper function: Find the most recently trashed [Credential] with the given
+    // `owner_username` & `name`.
+    fn find_trashed_credential<O, N>(
+        &self,
+        owner_username: O,
+        name: N,
+        key: &Aes256Key,
+    ) -> eyre::Result<(TrashedItem, Credential)>
+    where
+        O: AsRef<str>,
+        N: AsRef<str>,
+    {
+        for (trashed_item, credential) in self.load_trashed_credentials(&owner_username)? {
+            if credential.name::<Secret<String>>(key)?.expose() == name.as_ref() {
+                return Ok((trashed_item, credential));
+            }
+        }
+        Err(eyre!(
+            "No credentials named \"{}\" are in the trash of account \"{}\".",
+            name.as_ref(),
+            owner_username.as_ref()
+        ))
+    }
+
+    // Helper function: Find the most recently trashed file with the given `owner_username` &
+    // `filename`.
+    fn find_trashed_file<O, F>(
+        &self,
+        owner_username: O,
+        filename: F,
+        key: &Aes256Key,
+    ) -> eyre::Result<(TrashedItem, FileData)>
+    where
+        O: AsRef<str>,
+        F: AsRef<str>,
+    {
+        for (trashed_item, file_data) in self.load_trashed_files_data(&owner_username)? {
+            if file_data.filename::<String>(key)? == filename.as_ref() {
+                return Ok((trashed_item, file_data));
+            }
+        }
+        Err(eyre!(
+            "No files named \"{}\" are in the trash of account \"{}\".",
+            filename.as_ref(),
+            owner_username.as_ref()
+        ))
+    }
+
+    // Helper function: Find the trash entry of the [Account] with the given `username`.
+    fn find_trashed_account(&self, username: &str) -> eyre::Result<TrashedItem> {
+        let account_name = self.account_name(username);
+        self.select_account_trash(&account_name, EntityType::Account)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| eyre!("Account \"{}\" is not in the trash.", username))
+    }
+
+    // Helper function: Select the entries of the given `entity_type` in the trash of the account
+    // stored under the given `account_name`, most recently trashed first.
+    fn select_account_trash(
+        &self,
+        account_name: &str,
+        entity_type: EntityType,
+    ) -> eyre::Result<Vec<TrashedItem>> {
+        let mut trashed_items = self
+            .database
+            .select_owned_entries::<TrashedItem, &str, 1>([account_name])?;
+        trashed_items.retain(|trashed_item| trashed_item.entity_type() == entity_type);
+        Ok(trashed_items)
+    }
+
+    // Helper function: Select the ids of the items of the given `entity_type` in the trash of the
+    // account stored under the given `account_name`.
+    pub(super) fn select_trashed_ids(
+        &self,
+        account_name: &str,
+        entity_type: EntityType,
+    ) -> eyre::Result<HashSet<String>> {
+        Ok(self
+            .select_account_trash(account_name, entity_type)?
+            .into_iter()
+            .map(|trashed_item| trashed_item.item_id().to_owned())
+            .collect())
+    }
+
+    // Helper function: Take the given [TrashedItem] out of the trash.
+    fn transaction_untrash(trashed_item: &TrashedItem, tx: &Transaction) -> eyre::Result<()> {
+        Database::transaction_delete_trashed_item(
+            trashed_item.owner_username(),
+            trashed_item.entity_type(),
+            trashed_item.item_id(),
+            tx,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::super::{
+        super::{credential::Credential, file_data::FileData, trash::TrashedItem},
+        tests::{db_path, fs_dir, refresh_test_db, refresh_test_fs},
+        Vault,
+    };
+
+    #[test]
+    fn trash() {
+        let db_name = "trash.db";
+        let fs_name = "trash";
+        let db_path = db_path(db_name);
+        let fs_dir = fs_dir(fs_name);
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+
+        fn clock() -> u64 {
+            1_700_000_000
+        }
+        fn later_clock() -> u64 {
+            1_700_000_060
+        }
+        fn much_later_clock() -> u64 {
+            1_700_000_060 + 31 * 24 * 60 * 60
+        }
+        fn even_later_clock() -> u64 {
+            1_700_000_060 + 33 * 24 * 60 * 60
+        }
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+        vault.set_clock(clock);
+        assert_eq!(vault.trash_retention(), Some(30));
+
+        let password = "open sesame!";
+        vault.create_new_account("owner", password, None).unwrap();
+        vault.create_new_account("other", password, None).unwrap();
+        let owner = vault
+            .load_unlocked_account("owner", password, None, None)
+            .unwrap();
+        let key = owner.key().clone();
+        let other = vault
+            .load_unlocked_account("other", password, None, None)
+            .unwrap();
+        vault
+            .create_credential("owner", &key, "wifi", "u", "p", "n")
+            .unwrap();
+        vault
+            .create_file("notes.txt", "owner", "notes", &key)
+            .unwrap();
+        vault
+            .share_credential("owner", "wifi", &key, "other")
+            .unwrap();
+
+        // Deleted items are moved to the trash, out of the listings, & stop being shared.
+        vault.delete_credential("owner", "wifi", &key).unwrap();
+        vault.set_clock(later_clock);
+        vault.delete_file("owner", "notes.txt", &key).unwrap();
+        assert!(vault.load_account_credentials("owner").unwrap().is_empty());
+        assert!(vault.load_account_files_data("owner").unwrap().is_empty());
+        let _ = vault.load_credential("owner", "wifi", &key).unwrap_err();
+        let _ = vault.delete_credential("owner", "wifi", &key).unwrap_err();
+        assert!(vault.load_shared_credentials(&other).unwrap().is_empty());
+        let trashed_credentials = vault.load_trashed_credentials("owner").unwrap();
+        assert_eq!(trashed_credentials.len(), 1);
+        assert_eq!(trashed_credentials[0].0.trashed_at(), clock());
+        assert_eq!(
+            trashed_credentials[0].1.name::<String>(&key).unwrap(),
+            "wifi"
+        );
+        let trashed_files = vault.load_trashed_files_data("owner").unwrap();
+        assert_eq!(trashed_files.len(), 1);
+        assert_eq!(trashed_files[0].0.trashed_at(), later_clock());
+        assert_eq!(vault.check(Some(&owner)).unwrap(), vec![]);
+
+        // A new credential can take a trashed credential's name, which blocks restoring it.
+        vault
+            .create_credential("owner", &key, "wifi", "u2", "p2", "n2")
+            .unwrap();
+        let _ = vault
+            .restore_trashed_credential("owner", "wifi", &key)
+            .unwrap_err();
+        assert_eq!(vault.check(Some(&owner)).unwrap(), vec![]);
+
+        // The most recently trashed item with a name is restored first.
+        vault.delete_credential("owner", "wifi", &key).unwrap();
+        assert_eq!(vault.load_trashed_credentials("owner").unwrap().len(), 2);
+        vault
+            .restore_trashed_credential("owner", "wifi", &key)
+            .unwrap();
+        let credential = vault.load_credential("owner", "wifi", &key).unwrap();
+        assert_eq!(credential.password::<String>(&key).unwrap(), "p2");
+        vault
+            .restore_trashed_file("owner", "notes.txt", &key)
+            .unwrap();
+        let (_, contents): (FileData, String) =
+            vault.load_file("owner", "notes.txt", &key).unwrap();
+        assert_eq!(contents, "notes");
+        let _ = vault
+            .restore_trashed_file("owner", "notes.txt", &key)
+            .unwrap_err();
+
+        // Purging deletes an item for good.
+        vault
+            .purge_trashed_credential("owner", "wifi", &key)
+            .unwrap();
+        let _ = vault
+            .purge_trashed_credential("owner", "wifi", &key)
+            .unwrap_err();
+        assert!(vault.load_all::<TrashedItem>().unwrap().is_empty());
+        assert_eq!(vault.load_all::<Credential>().unwrap().len(), 1);
+
+        // Items are purged once they've been in the trash for longer than the retention period,
+        // when their owner next logs in.
+        vault.delete_file("owner", "notes.txt", &key).unwrap();
+        let other_key = other.key().clone();
+        vault
+            .create_credential("other", &other_key, "tv", "u", "p", "n")
+            .unwrap();
+        vault.delete_credential("other", "tv", &other_key).unwrap();
+        vault.set_clock(much_later_clock);
+        vault.delete_credential("owner", "wifi", &key).unwrap();
+        let _ = vault
+            .load_unlocked_account("owner", "wrong password", None, None)
+            .unwrap_err();
+        assert_eq!(vault.load_trashed_files_data("owner").unwrap().len(), 1);
+        vault
+            .load_unlocked_account("owner", password, None, None)
+            .unwrap();
+        assert!(vault.load_trashed_files_data("owner").unwrap().is_empty());
+        assert!(vault.load_all::<FileData>().unwrap().is_empty());
+        assert_eq!(vault.load_trashed_credentials("owner").unwrap().len(), 1);
+        assert_eq!(vault.check(None).unwrap(), vec![]);
+
+        // Other accounts' items are left alone until they log in too.
+        assert_eq!(vault.load_trashed_credentials("other").unwrap().len(), 1);
+        vault
+            .load_unlocked_account("other", password, None, None)
+            .unwrap();
+        assert!(vault.load_trashed_credentials("other").unwrap().is_empty());
+
+        // Emptying the trash purges everything in it.
+        assert_eq!(vault.empty_trash("owner").unwrap(), 1);
+        assert!(vault.load_all::<Credential>().unwrap().is_empty());
+
+        // Trashed accounts can't log in until they're restored, & must be unlocked to be purged.
+        vault.delete_account("other").unwrap();
+        assert!(vault.is_account_trashed("other").unwrap());
+        let _ = vault.delete_account("other").unwrap_err();
+        let _ = vault
+            .load_unlocked_account("other", password, None, None)
+            .unwrap_err();
+        let _ = vault
+            .restore_account("other", "wrong password", None, None)
+            .unwrap_err();
+        let _ = vault
+            .purge_account("other", "wrong password", None, None)
+            .unwrap_err();
+        vault
+            .restore_account("other", password, None, None)
+            .unwrap();
+        assert!(!vault.is_account_trashed("other").unwrap());
+        let _ = vault
+            .purge_account("other", password, None, None)
+            .unwrap_err();
+        vault
+            .load_unlocked_account("other", password, None, None)
+            .unwrap();
+
+        // Shortening the retention period purges the items which have been trashed for too long,
+        // once their owner authenticates.
+        vault.delete_account("other").unwrap();
+        vault.set_clock(even_later_clock);
+        vault.set_trash_retention(Some(1)).unwrap();
+        vault
+            .load_unlocked_account("owner", password, None, None)
+            .unwrap();
+        assert!(vault.is_account_trashed("other").unwrap());
+        let _ = vault
+            .restore_account("other", "wrong password", None, None)
+            .unwrap_err();
+        assert!(vault.is_account_trashed("other").unwrap());
+        let _ = vault
+            .restore_account("other", password, None, None)
+            .unwrap_err();
+        let _ = vault.load_account("other").unwrap_err();
+        assert!(!fs_dir.join("other").try_exists().unwrap());
+        vault.load_account("owner").unwrap();
+
+        // The retention period is saved.
+        vault.set_trash_retention(None).unwrap();
+        drop(vault);
+        let vault = Vault::connect(&db_path, &fs_dir).unwrap();
+        assert_eq!(vault.trash_retention(), None);
+        drop(vault);
+
+        // Accounts stored under hashed usernames can't be trashed twice either.
+        refresh_test_db(db_name);
+        refresh_test_fs(fs_name);
+        let mut vault = Vault::connect(&db_path, &fs_dir).unwrap();
+        vault.enable_privacy_mode().unwrap();
+        vault.create_new_account("other", password, None).unwrap();
+        vault.delete_account("other").unwrap();
+        assert!(vault.is_account_trashed("other").unwrap());
+        let err = vault.delete_account("other").unwrap_err();
+        assert!(err.to_string().contains("already in the trash"));
+        vault
+            .restore_account("other", password, None, None)
+            .unwrap();
+        assert!(!vault.is_account_trashed("other").unwrap());
+    }
+}
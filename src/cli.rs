@@ -2,3 +2,4 @@
 pub mod arg_matcher;
 pub mod arg_parser;
 pub mod processes;
+pub mod profiles;
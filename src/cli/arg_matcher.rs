@@ -4,29 +4,102 @@ use color_eyre::eyre::{self, eyre};
 use super::{
     arg_parser::{Cli, Command},
     processes::*,
+    profiles::VaultProfiles,
 };
 
 /// This statement matches the CLI arguments with its proper functionality in `processes`.
 pub fn match_args(args: Cli) -> eyre::Result<()> {
+    // Only commands which create a vault may be given a path which doesn't exist yet.
+    let creates_vault = matches!(
+        args.command,
+        Command::Accounts { new: true, .. }
+            | Command::Vault {
+                single_file: true,
+                ..
+            }
+            | Command::Vault {
+                restore: Some(_),
+                into: None,
+                ..
+            }
+    );
+    let vault_location = &VaultProfiles::load()?.resolve(args.vault.as_deref(), creates_vault)?;
+    let username = || {
+        args.username
+            .clone()
+            .ok_or_else(|| eyre!("This command needs a username: dgruft <USERNAME> <COMMAND>."))
+    };
     // Match the CLI commands/arguments.
     match args.command {
         Command::Accounts {
             new,
             list,
             password_change,
+            recover,
+            new_recovery,
+            revoke_recovery,
+            recover_shares,
+            new_shares,
+            threshold,
+            revoke_shares,
+            enable_two_factor: enable_2fa,
+            disable_two_factor: disable_2fa,
+            new_backup_codes,
+            calibrate,
+            rotate_key,
             delete,
             force_delete,
+            undelete,
+            purge,
+            empty_trash: empty,
+            gen_key_file,
+            key_file,
+            no_key_file,
         } => {
             if new {
-                new_account(args.username)?;
+                new_account(vault_location, username()?, gen_key_file, key_file)?;
             } else if list {
-                list_accounts()?;
+                list_accounts(vault_location)?;
             } else if password_change {
-                change_password(args.username)?;
+                change_password(
+                    vault_location,
+                    username()?,
+                    gen_key_file,
+                    key_file,
+                    no_key_file,
+                )?;
+            } else if recover {
+                recover_account(vault_location, username()?, gen_key_file, key_file)?;
+            } else if new_recovery {
+                regenerate_recovery_code(vault_location, username()?)?;
+            } else if revoke_recovery {
+                revoke_recovery_code(vault_location, username()?)?;
+            } else if recover_shares {
+                recover_account_with_shares(vault_location, username()?, gen_key_file, key_file)?;
+            } else if let (Some(count), Some(threshold)) = (new_shares, threshold) {
+                split_account_key(vault_location, username()?, threshold, count)?;
+            } else if revoke_shares {
+                revoke_key_shares(vault_location, username()?)?;
+            } else if enable_2fa {
+                enable_two_factor(vault_location, username()?)?;
+            } else if disable_2fa {
+                disable_two_factor(vault_location, username()?)?;
+            } else if new_backup_codes {
+                regenerate_backup_codes(vault_location, username()?)?;
+            } else if calibrate {
+                calibrate_account(vault_location, username()?)?;
+            } else if rotate_key {
+                rotate_account_key(vault_location, username()?)?;
             } else if delete {
-                delete_account(args.username, false)?;
+                delete_account(vault_location, username()?, false)?;
             } else if force_delete {
-                delete_account(args.username, true)?;
+                delete_account(vault_location, username()?, true)?;
+            } else if undelete {
+                restore_account(vault_location, username()?)?;
+            } else if purge {
+                purge_account(vault_location, username()?)?;
+            } else if empty {
+                empty_trash(vault_location, username()?)?;
             } else {
                 return Err(eyre!("Invalid option combination."));
             }
@@ -35,23 +108,69 @@ pub fn match_args(args: Cli) -> eyre::Result<()> {
             new,
             edit,
             view,
+            export_age,
+            to,
+            share,
+            unshare,
+            history,
+            restore_version,
             list,
             delete,
             force_delete,
+            trash,
+            undelete,
+            purge,
             credentialname,
         } => {
             if new {
-                new_credential(args.username, credentialname.unwrap())?;
+                new_credential(vault_location, username()?, credentialname.unwrap())?;
             } else if edit {
-                edit_credential(args.username, credentialname.unwrap())?;
+                edit_credential(vault_location, username()?, credentialname.unwrap())?;
             } else if view {
-                view_credential(args.username, credentialname.unwrap())?;
+                view_credential(vault_location, username()?, credentialname.unwrap())?;
+            } else if let Some(destination) = export_age {
+                export_credential_age(
+                    vault_location,
+                    username()?,
+                    credentialname.unwrap(),
+                    destination,
+                    to,
+                )?;
+            } else if let Some(recipient) = share {
+                share_credential(
+                    vault_location,
+                    username()?,
+                    credentialname.unwrap(),
+                    recipient,
+                )?;
+            } else if let Some(recipient) = unshare {
+                unshare_credential(
+                    vault_location,
+                    username()?,
+                    credentialname.unwrap(),
+                    recipient,
+                )?;
+            } else if history {
+                credential_history(vault_location, username()?, credentialname.unwrap())?;
+            } else if let Some(number) = restore_version {
+                restore_credential_version(
+                    vault_location,
+                    username()?,
+                    credentialname.unwrap(),
+                    number,
+                )?;
             } else if list {
-                list_credentials(args.username)?;
+                list_credentials(vault_location, username()?)?;
             } else if delete {
-                delete_credential(args.username, credentialname.unwrap(), false)?;
+                delete_credential(vault_location, username()?, credentialname.unwrap(), false)?;
             } else if force_delete {
-                delete_credential(args.username, credentialname.unwrap(), true)?;
+                delete_credential(vault_location, username()?, credentialname.unwrap(), true)?;
+            } else if trash {
+                list_trashed_credentials(vault_location, username()?)?;
+            } else if undelete {
+                restore_trashed_credential(vault_location, username()?, credentialname.unwrap())?;
+            } else if purge {
+                purge_trashed_credential(vault_location, username()?, credentialname.unwrap())?;
             } else {
                 return Err(eyre!("Invalid option combination."));
             }
@@ -59,21 +178,145 @@ pub fn match_args(args: Cli) -> eyre::Result<()> {
         Command::Files {
             new,
             open,
+            import,
+            export,
+            import_age,
+            export_age,
+            identity,
+            to,
+            verify,
+            share,
+            unshare,
+            revisions,
+            diff,
+            restore_revision,
             list,
             delete,
             force_delete,
+            trash,
+            undelete,
+            purge,
             filename,
         } => {
             if new {
-                new_file(args.username, filename.unwrap())?;
+                new_file(vault_location, username()?, filename.unwrap())?;
             } else if open {
-                open_file(args.username, filename.unwrap())?;
+                open_file(vault_location, username()?, filename.unwrap())?;
+            } else if let Some(source) = import {
+                import_file(vault_location, username()?, filename.unwrap(), source)?;
+            } else if let Some(destination) = export {
+                export_file(vault_location, username()?, filename.unwrap(), destination)?;
+            } else if let Some(source) = import_age {
+                import_file_age(
+                    vault_location,
+                    username()?,
+                    filename.unwrap(),
+                    source,
+                    identity,
+                )?;
+            } else if let Some(destination) = export_age {
+                export_file_age(
+                    vault_location,
+                    username()?,
+                    filename.unwrap(),
+                    destination,
+                    to,
+                )?;
+            } else if verify {
+                verify_file(vault_location, username()?, filename.unwrap())?;
+            } else if let Some(recipient) = share {
+                share_file(vault_location, username()?, filename.unwrap(), recipient)?;
+            } else if let Some(recipient) = unshare {
+                unshare_file(vault_location, username()?, filename.unwrap(), recipient)?;
+            } else if revisions {
+                file_revisions(vault_location, username()?, filename.unwrap())?;
+            } else if let Some([from, to]) = diff.as_deref() {
+                diff_file_revisions(vault_location, username()?, filename.unwrap(), *from, *to)?;
+            } else if let Some(number) = restore_revision {
+                restore_file_revision(vault_location, username()?, filename.unwrap(), number)?;
             } else if list {
-                list_files(args.username)?;
+                list_files(vault_location, username()?)?;
             } else if delete {
-                delete_file(args.username, filename.unwrap(), false)?;
+                delete_file(vault_location, username()?, filename.unwrap(), false)?;
             } else if force_delete {
-                delete_file(args.username, filename.unwrap(), true)?;
+                delete_file(vault_location, username()?, filename.unwrap(), true)?;
+            } else if trash {
+                list_trashed_files(vault_location, username()?)?;
+            } else if undelete {
+                restore_trashed_file(vault_location, username()?, filename.unwrap())?;
+            } else if purge {
+                purge_trashed_file(vault_location, username()?, filename.unwrap())?;
+            } else {
+                return Err(eyre!("Invalid option combination."));
+            }
+        }
+        Command::Vault {
+            info,
+            cipher,
+            history_limit,
+            revision_limit,
+            revision_max_age,
+            trash_retention,
+            privacy,
+            single_file,
+            directory,
+            check,
+            repair,
+            backup,
+            to,
+            restore,
+            identity,
+            into,
+            yes,
+        } => {
+            if info {
+                vault_info(vault_location)?;
+            } else if let Some(cipher) = cipher {
+                set_cipher_suite(vault_location, cipher)?;
+            } else if let Some(limit) = history_limit {
+                set_credential_history_limit(vault_location, limit)?;
+            } else if let Some(limit) = revision_limit {
+                set_file_revision_limit(vault_location, limit)?;
+            } else if let Some(days) = revision_max_age {
+                set_file_revision_max_age(vault_location, days)?;
+            } else if let Some(days) = trash_retention {
+                set_trash_retention(vault_location, days)?;
+            } else if privacy {
+                enable_privacy_mode(vault_location)?;
+            } else if single_file {
+                convert_to_single_file(vault_location)?;
+            } else if directory {
+                convert_to_directory(vault_location)?;
+            } else if check {
+                check_vault(vault_location, username()?)?;
+            } else if repair {
+                repair_vault(vault_location, username()?, yes)?;
+            } else if let Some(destination) = backup {
+                backup_vault(vault_location, destination, to)?;
+            } else if let Some(source) = restore {
+                restore_vault(vault_location, source, identity, into, yes)?;
+            } else {
+                return Err(eyre!("Invalid option combination."));
+            }
+        }
+        Command::Profiles {
+            new,
+            path,
+            list,
+            remove,
+            default,
+            no_default,
+        } => {
+            if let Some(name) = new {
+                new_profile(name, path)?;
+            } else if list {
+                list_profiles()?;
+            } else if let Some(name) = remove {
+                remove_profile(name)?;
+            } else if let Some(name) = default {
+                set_default_profile(Some(name))?;
+            } else if no_default {
+                set_default_profile(None)?;
             } else {
                 return Err(eyre!("Invalid option combination."));
             }
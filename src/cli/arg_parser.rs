@@ -1,4 +1,5 @@
 //! Parse command-line arguments for the CLI version of `dgruft`.
+use camino::Utf8PathBuf;
 use clap::{ArgGroup, Parser, Subcommand};
 
 use crate::utils;
@@ -9,9 +10,19 @@ use crate::utils;
 #[command(author)]
 #[command(version = utils::info())]
 #[command(about = "Encrypted storage for passwords & data.")]
+#[command(
+    long_about = "Encrypted storage for passwords & data.\n\nRun `dgruft help <COMMAND>` or \
+    `dgruft <COMMAND> --help` for the details of each command's options."
+)]
 pub struct Cli {
-    /// Account username.
-    pub username: String,
+    /// Account username. Not needed by the profiles command or by vault commands which don't log
+    /// in.
+    pub username: Option<String>,
+    /// Use the vault profile with the given name, or else the existing single-file vault at the
+    /// given path, instead of the default vault. A new single-file vault is only created by
+    /// `accounts --new`, `vault --singlefile` & `vault --restore`, at a path such as ./work.db.
+    #[clap(long, global = true, value_name = "NAME|FILE")]
+    pub vault: Option<String>,
     /// All the possible commands the user can give CLI `dgruft`.
     #[command(subcommand)]
     pub command: Command,
@@ -28,25 +39,160 @@ pub enum Command {
         group(
             ArgGroup::new("account")
                 .required(true)
-                .args(&["new", "list", "password_change", "delete", "force_delete"])
+                .args(&[
+                    "new",
+                    "list",
+                    "password_change",
+                    "recover",
+                    "recover_shares",
+                    "new_recovery",
+                    "revoke_recovery",
+                    "new_shares",
+                    "revoke_shares",
+                    "enable_two_factor",
+                    "disable_two_factor",
+                    "new_backup_codes",
+                    "calibrate",
+                    "rotate_key",
+                    "delete",
+                    "force_delete",
+                    "undelete",
+                    "purge",
+                    "empty_trash",
+                ])
         )
     )]
     Accounts {
         /// Create a new account.
         #[clap(short, long)]
         new: bool,
-        /// List all accounts.
+        /// List all accounts. Unavailable in privacy mode.
         #[clap(short, long)]
         list: bool,
         /// Change an account's password.
         #[clap(short = 'p', long = "passwordchange")]
         password_change: bool,
-        /// Delete an account.
+        /// Set a new password for an account using its recovery code.
+        ///
+        /// Each new account is given a recovery code, which is shown once. Recovering the account
+        /// turns two-factor login off.
+        #[clap(short = 'R', long = "recover")]
+        recover: bool,
+        /// Replace an account's recovery code with a new one.
+        #[clap(long = "newrecovery")]
+        new_recovery: bool,
+        /// Remove an account's recovery code.
+        #[clap(long = "revokerecovery")]
+        revoke_recovery: bool,
+        /// Set a new password for an account using enough of its key shares.
+        #[clap(long = "recovershares")]
+        recover_shares: bool,
+        /// Split an account's key into the given number of key shares, replacing any old ones.
+        ///
+        /// The key is split with Shamir's secret sharing. Any --threshold of the key shares can
+        /// recover the account with --recovershares, while fewer reveal nothing.
+        #[clap(long = "newshares", value_name = "COUNT", requires = "threshold")]
+        new_shares: Option<u8>,
+        /// The number of key shares needed to recover the account.
+        #[clap(long = "threshold", value_name = "THRESHOLD", requires = "new_shares")]
+        threshold: Option<u8>,
+        /// Remove an account's key shares.
+        #[clap(long = "revokeshares")]
+        revoke_shares: bool,
+        /// Turn on two-factor login, showing a QR code to scan with an authenticator app.
+        ///
+        /// Logging in then needs a code from the authenticator app after the password. Each code
+        /// only works once. Eight single-use backup codes are shown too, in case the authenticator
+        /// app is lost.
+        #[clap(long = "enable2fa")]
+        enable_two_factor: bool,
+        /// Turn off two-factor login.
+        #[clap(long = "disable2fa")]
+        disable_two_factor: bool,
+        /// Replace an account's two-factor backup codes with new ones.
+        #[clap(long = "newbackupcodes")]
+        new_backup_codes: bool,
+        /// Re-tune an account's password hashing cost to this machine.
+        #[clap(short = 'c', long = "calibrate")]
+        calibrate: bool,
+        /// Replace an account's encryption key, re-encrypting all its credentials & files. The
+        /// recovery code & keypair are replaced too, & any key shares are revoked.
+        #[clap(short = 'r', long = "rotatekey")]
+        rotate_key: bool,
+        /// Move an account to the trash.
+        ///
+        /// A trashed account can't log in until it's restored with --undelete, or deleted for good
+        /// with --purge.
         #[clap(short = 'd', long = "delete")]
         delete: bool,
-        /// Delete an account without confirmation.
+        /// Move an account to the trash without confirmation.
         #[clap(short = 'D', long = "forcedelete")]
         force_delete: bool,
+        /// Restore an account from the trash.
+        #[clap(long)]
+        undelete: bool,
+        /// Permanently delete an account in the trash.
+        #[clap(long)]
+        purge: bool,
+        /// Permanently delete every credential & file in an account's trash.
+        #[clap(long = "emptytrash")]
+        empty_trash: bool,
+        /// Generate a new key file at the given path & require it to unlock the account.
+        #[clap(
+            short = 'g',
+            long = "genkeyfile",
+            value_name = "KEYFILE",
+            conflicts_with_all = &[
+                "key_file",
+                "list",
+                "new_recovery",
+                "revoke_recovery",
+                "new_shares",
+                "revoke_shares",
+                "enable_two_factor",
+                "disable_two_factor",
+                "new_backup_codes",
+                "calibrate",
+                "rotate_key",
+                "delete",
+                "force_delete",
+                "undelete",
+                "purge",
+                "empty_trash",
+            ],
+        )]
+        gen_key_file: Option<Utf8PathBuf>,
+        /// Require the given existing file to unlock the account.
+        #[clap(
+            short = 'k',
+            long = "keyfile",
+            value_name = "KEYFILE",
+            conflicts_with_all = &[
+                "list",
+                "new_recovery",
+                "revoke_recovery",
+                "new_shares",
+                "revoke_shares",
+                "enable_two_factor",
+                "disable_two_factor",
+                "new_backup_codes",
+                "calibrate",
+                "rotate_key",
+                "delete",
+                "force_delete",
+                "undelete",
+                "purge",
+                "empty_trash",
+            ],
+        )]
+        key_file: Option<Utf8PathBuf>,
+        /// Stop requiring a key file to unlock the account.
+        #[clap(
+            long = "nokeyfile",
+            requires = "password_change",
+            conflicts_with_all = &["gen_key_file", "key_file"],
+        )]
+        no_key_file: bool,
     },
 
     /// File-related functionality.
@@ -57,25 +203,113 @@ pub enum Command {
         group(
             ArgGroup::new("file")
                 .required(true)
-                .args(&["new", "open", "list", "delete", "force_delete"])
+                .args(&[
+                    "new",
+                    "open",
+                    "import",
+                    "export",
+                    "import_age",
+                    "export_age",
+                    "verify",
+                    "share",
+                    "unshare",
+                    "revisions",
+                    "diff",
+                    "restore_revision",
+                    "list",
+                    "delete",
+                    "force_delete",
+                    "trash",
+                    "undelete",
+                    "purge",
+                ])
         )
     )]
     Files {
         /// Create a new file.
         #[clap(short, long, requires = "filename")]
         new: bool,
+        /// Create a new file, or replace an existing file's contents, with the contents of the
+        /// given file.
+        #[clap(short, long, requires = "filename", value_name = "SOURCE")]
+        import: Option<Utf8PathBuf>,
+        /// Decrypt a file to the given path.
+        #[clap(short = 'x', long, requires = "filename", value_name = "DESTINATION")]
+        export: Option<Utf8PathBuf>,
+        /// Create a new file from the given age payload.
+        #[clap(long = "importage", requires = "filename", value_name = "SOURCE")]
+        import_age: Option<Utf8PathBuf>,
+        /// Encrypt a file to the given path as an age payload.
+        ///
+        /// The payload is encrypted to the age public key given to --to, or else to a passphrase.
+        #[clap(long = "exportage", requires = "filename", value_name = "DESTINATION")]
+        export_age: Option<Utf8PathBuf>,
+        /// The age identity file to decrypt an age payload with. Prompts for a passphrase if not
+        /// given.
+        #[clap(long, requires = "import_age", value_name = "IDENTITY_FILE")]
+        identity: Option<Utf8PathBuf>,
+        /// The age public key ("age1...") to encrypt an age payload to. Prompts for a passphrase
+        /// if not given.
+        #[clap(long, requires = "export_age", value_name = "RECIPIENT")]
+        to: Option<String>,
+        /// Check that a file decrypts successfully.
+        #[clap(short, long, requires = "filename")]
+        verify: bool,
+        /// Let the given account read a file.
+        ///
+        /// The recipient can read the file but not change it. It's listed alongside the
+        /// recipient's own files, & stays up to date with the owner's changes until --unshare.
+        #[clap(long, requires = "filename", value_name = "RECIPIENT")]
+        share: Option<String>,
+        /// Stop letting the given account read a file.
+        #[clap(long, requires = "filename", value_name = "RECIPIENT")]
+        unshare: Option<String>,
+        /// List the previous revisions of a file, newest first.
+        ///
+        /// Editing or importing over a file keeps the contents it had before as a revision. How
+        /// many are kept is set with `vault --revisionlimit` & `vault --revisionmaxage`.
+        #[clap(long, requires = "filename")]
+        revisions: bool,
+        /// Show the differences between two revisions of a file, numbered as in --revisions. The
+        /// current contents are revision 0.
+        #[clap(
+            long,
+            requires = "filename",
+            num_args = 2,
+            value_names = ["FROM", "TO"]
+        )]
+        diff: Option<Vec<usize>>,
+        /// Restore the given previous revision of a file, numbered as in --revisions.
+        ///
+        /// The contents it replaces are kept as a new revision, so the restore can be undone.
+        #[clap(long = "restorerevision", requires = "filename", value_name = "NUMBER")]
+        restore_revision: Option<usize>,
         /// Open & edit a file.
         #[clap(short, long, requires = "filename")]
         open: bool,
-        /// List all files owned by this account.
+        /// List all files owned by or shared with this account.
         #[clap(short, long)]
         list: bool,
-        /// Delete a file.
+        /// Move a file to the trash.
+        ///
+        /// Trashed files stay encrypted, are left out of listings & stop being shared. They're
+        /// purged when their owner logs in once the vault's --trashretention has passed.
         #[clap(short = 'd', long = "delete", requires = "filename")]
         delete: bool,
-        /// Delete a file without confirmation.
+        /// Move a file to the trash without confirmation.
         #[clap(short = 'D', long = "forcedelete", requires = "filename")]
         force_delete: bool,
+        /// List the files in the trash, most recently deleted first.
+        #[clap(long)]
+        trash: bool,
+        /// Restore a file from the trash.
+        ///
+        /// It can only be restored as long as no other file has taken its name.
+        #[clap(long, requires = "filename")]
+        undelete: bool,
+        /// Permanently delete a file in the trash.
+        #[clap(long, requires = "filename")]
+        purge: bool,
         /// The name of the file.
         filename: Option<String>,
     },
@@ -90,7 +324,22 @@ pub enum Command {
         group(
             ArgGroup::new("credential")
                 .required(true)
-                .args(&["new", "edit", "view", "list", "delete", "force_delete"])
+                .args(&[
+                    "new",
+                    "edit",
+                    "view",
+                    "export_age",
+                    "share",
+                    "unshare",
+                    "history",
+                    "restore_version",
+                    "list",
+                    "delete",
+                    "force_delete",
+                    "trash",
+                    "undelete",
+                    "purge",
+                ])
         )
     )]
     Credentials {
@@ -103,16 +352,221 @@ pub enum Command {
         /// View a credential.
         #[clap(short, long, requires = "credentialname")]
         view: bool,
-        /// List all credentials owned by this account.
+        /// Encrypt a credential to the given path as an age payload.
+        ///
+        /// The payload is encrypted to the age public key given to --to, or else to a passphrase.
+        #[clap(
+            long = "exportage",
+            requires = "credentialname",
+            value_name = "DESTINATION"
+        )]
+        export_age: Option<Utf8PathBuf>,
+        /// The age public key ("age1...") to encrypt an age payload to. Prompts for a passphrase
+        /// if not given.
+        #[clap(long, requires = "export_age", value_name = "RECIPIENT")]
+        to: Option<String>,
+        /// Let the given account read a credential.
+        ///
+        /// The recipient can read the credential but not change it. It's listed alongside the
+        /// recipient's own credentials, & stays up to date with the owner's changes until
+        /// --unshare.
+        #[clap(long, requires = "credentialname", value_name = "RECIPIENT")]
+        share: Option<String>,
+        /// Stop letting the given account read a credential.
+        #[clap(long, requires = "credentialname", value_name = "RECIPIENT")]
+        unshare: Option<String>,
+        /// List the previous versions of a credential, newest first.
+        ///
+        /// Editing a credential keeps the username, password & notes it had before, encrypted,
+        /// with the time they were replaced. How many are kept is set with `vault --historylimit`.
+        #[clap(long, requires = "credentialname")]
+        history: bool,
+        /// Restore the given previous version of a credential, numbered as in --history.
+        ///
+        /// The values it replaces are kept as a new version, so the restore can be undone.
+        #[clap(
+            long = "restoreversion",
+            requires = "credentialname",
+            value_name = "NUMBER"
+        )]
+        restore_version: Option<usize>,
+        /// List all credentials owned by or shared with this account.
         #[clap(short, long)]
         list: bool,
-        /// Delete a credential.
+        /// Move a credential to the trash.
+        ///
+        /// Trashed credentials stay encrypted, are left out of listings & stop being shared.
+        /// They're purged when their owner logs in once the vault's --trashretention has passed.
         #[clap(short = 'd', long = "delete", requires = "credentialname")]
         delete: bool,
-        /// Delete a credential without confirmation.
+        /// Move a credential to the trash without confirmation.
         #[clap(short = 'D', long = "forcedelete", requires = "credentialname")]
         force_delete: bool,
+        /// List the credentials in the trash, most recently deleted first.
+        #[clap(long)]
+        trash: bool,
+        /// Restore a credential from the trash.
+        ///
+        /// It can only be restored as long as no other credential has taken its name.
+        #[clap(long, requires = "credentialname")]
+        undelete: bool,
+        /// Permanently delete a credential in the trash.
+        #[clap(long, requires = "credentialname")]
+        purge: bool,
         /// The name of the credential.
         credentialname: Option<String>,
     },
+    /// Vault-wide settings.
+    #[command(arg_required_else_help = true)]
+    #[command(alias = "v")]
+    #[clap(
+        group(
+            ArgGroup::new("vault_command")
+                .required(true)
+                .args(&[
+                    "info",
+                    "cipher",
+                    "history_limit",
+                    "revision_limit",
+                    "revision_max_age",
+                    "trash_retention",
+                    "privacy",
+                    "single_file",
+                    "directory",
+                    "check",
+                    "repair",
+                    "backup",
+                    "restore",
+                ])
+        ),
+        group(ArgGroup::new("confirmable").args(&["repair", "restore"]))
+    )]
+    Vault {
+        /// Show the vault's current settings.
+        #[clap(short, long)]
+        info: bool,
+        /// Set the cipher suite used to encrypt new data ("aes-256-gcm" or "xchacha20-poly1305").
+        #[clap(short, long, value_name = "SUITE")]
+        cipher: Option<String>,
+        /// Set how many previous versions of each credential are kept. Older versions are deleted.
+        ///
+        /// Ten are kept by default. A limit of 0 keeps no history.
+        #[clap(long = "historylimit", value_name = "COUNT")]
+        history_limit: Option<u32>,
+        /// Set how many previous revisions of each file are kept. Older revisions are deleted.
+        ///
+        /// Ten are kept by default.
+        #[clap(long = "revisionlimit", value_name = "COUNT")]
+        revision_limit: Option<u32>,
+        /// Set how many days previous revisions of files are kept for, or 0 to keep them
+        /// regardless of their age. Older revisions are deleted.
+        #[clap(long = "revisionmaxage", value_name = "DAYS")]
+        revision_max_age: Option<u32>,
+        /// Set how many days deleted items are kept in the trash for, or 0 to keep them until
+        /// they're purged. Older items are purged when their owner next logs in.
+        ///
+        /// Items are kept for 30 days by default.
+        #[clap(long = "trashretention", value_name = "DAYS")]
+        trash_retention: Option<u32>,
+        /// Enable privacy mode: store accounts under salted hashes of their usernames & stop
+        /// listing them. Only possible before any accounts are created.
+        #[clap(short, long)]
+        privacy: bool,
+        /// Move the contents of the vault's files into its database, so the whole vault is one
+        /// file which can be opened from anywhere with --vault.
+        ///
+        /// A single-file vault can be copied & backed up like any other file. A new one is created
+        /// by giving a new path to --vault with a command which creates a vault, such as
+        /// `dgruft --vault ./work.db <USERNAME> accounts --new`.
+        #[clap(long = "singlefile")]
+        single_file: bool,
+        /// Move the contents of the default vault's files out of its database, into the data
+        /// directory.
+        #[clap(long)]
+        directory: bool,
+        /// Report missing & orphaned files & directories, along with any of this account's
+        /// entries which can't be decrypted or have the same name.
+        ///
+        /// Shared copies which outlived their originals & missing file revisions are reported
+        /// too. Run --repair to fix them.
+        #[clap(long)]
+        check: bool,
+        /// Find & fix the problems reported by --check, asking before fixing each one.
+        #[clap(long)]
+        repair: bool,
+        /// Back up the whole vault to a new archive at the given path, encrypted as an age
+        /// payload.
+        ///
+        /// The archive holds the database & the encrypted contents of every file, & ends with a
+        /// checksummed manifest of everything in it. The database is snapshotted with SQLite's
+        /// online backup API, so the vault can stay in use.
+        #[clap(long, value_name = "DESTINATION")]
+        backup: Option<Utf8PathBuf>,
+        /// The age public key ("age1...") to encrypt a backup to. Prompts for a passphrase if not
+        /// given.
+        #[clap(long, requires = "backup", value_name = "RECIPIENT")]
+        to: Option<String>,
+        /// Verify the given backup archive & replace the vault with it.
+        ///
+        /// The whole archive is checked against its manifest before the vault is touched.
+        #[clap(long, value_name = "SOURCE")]
+        restore: Option<Utf8PathBuf>,
+        /// The age identity file to decrypt a backup with. Prompts for a passphrase if not given.
+        #[clap(long, requires = "restore", value_name = "IDENTITY_FILE")]
+        identity: Option<Utf8PathBuf>,
+        /// Restore a backup into the given new or empty directory instead of replacing the vault,
+        /// so it can be inspected.
+        #[clap(long, requires = "restore", value_name = "DIRECTORY")]
+        into: Option<Utf8PathBuf>,
+        /// Fix every problem found by --repair, or replace the vault with the backup given to
+        /// --restore, without asking.
+        #[clap(short, long, requires = "confirmable")]
+        yes: bool,
+    },
+    /// Named vault profiles, which can be selected with --vault.
+    #[command(arg_required_else_help = true)]
+    #[command(alias = "profile")]
+    #[clap(
+        group(
+            ArgGroup::new("profile")
+                .required(true)
+                .args(&["new", "list", "remove", "default", "no_default"])
+        )
+    )]
+    Profiles {
+        /// Create a new vault profile with the given name.
+        ///
+        /// Its vault is kept in a new directory beside the data directory, unless --path is
+        /// given. Profiles are registered in `vaults.conf` in the config directory.
+        #[clap(short, long, value_name = "NAME")]
+        new: Option<String>,
+        /// Keep the new profile's vault in the given data directory, which may already hold one,
+        /// instead of a new directory beside the data directory.
+        #[clap(long, requires = "new", value_name = "DIRECTORY")]
+        path: Option<Utf8PathBuf>,
+        /// List all vault profiles.
+        #[clap(short, long)]
+        list: bool,
+        /// Remove the vault profile with the given name. Its vault is kept.
+        #[clap(long, value_name = "NAME")]
+        remove: Option<String>,
+        /// Use the vault profile with the given name when --vault isn't given.
+        #[clap(long, value_name = "NAME")]
+        default: Option<String>,
+        /// Use the vault in the data directory when --vault isn't given.
+        #[clap(long = "nodefault")]
+        no_default: bool,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn valid_cli() {
+        Cli::command().debug_assert();
+    }
 }
@@ -1,44 +1,92 @@
 //! These are all the functional processes run by `dgruft` CLI commands.
-use std::io::{self, Write};
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    time::Duration,
+};
 
+use camino::{Utf8Path, Utf8PathBuf};
 use color_eyre::eyre::{self, eyre};
+use similar::TextDiff;
 
 use crate::{
-    backend::{Account, CredentialUpdateField, FileData, UnlockedAccount, Vault},
+    backend::{
+        Account, AgeIdentity, AgeRecipient, BackupCode, CipherSuite, CredentialField, KdfParams,
+        KeyFile, KeyShare, RecoveryCode, Secret, TotpSecret, TwoFactorCode, UnlockedAccount, Vault,
+        VaultIssue,
+    },
     edit::{edit_bytes, edit_string},
-    utils::{data_dir, db_path, temp_dir},
+    utils::{data_dir, db_path, format_unix_time, temp_dir, DB_NAME},
 };
 
+use super::profiles::{profiles_dir, VaultLocation, VaultProfiles};
+
+// The time it should take to unlock an account on this machine when its password hashing cost is
+// calibrated.
+const TARGET_UNLOCK_TIME: Duration = Duration::from_millis(500);
+
 // ACCOUNTS
 
-/// Create a new account.
-pub fn new_account(username: String) -> eyre::Result<()> {
+/// Create a new account, requiring a key file iff one is generated or given.
+pub fn new_account(
+    vault_location: &VaultLocation,
+    username: String,
+    gen_key_file: Option<Utf8PathBuf>,
+    key_file: Option<Utf8PathBuf>,
+) -> eyre::Result<()> {
     // Prompt for password.
     let password = prompt_password(&username)?;
     // Confirm password.
-    let confirm_password =
-        rpassword::prompt_password(format!("Confirm password for new account {}: ", username))?;
+    let confirm_password = Secret::new(rpassword::prompt_password(format!(
+        "Confirm password for new account {}: ",
+        username
+    ))?);
     if confirm_password != password {
         return Err(eyre!("Passwords for new account do not match."));
     }
 
     // Connect to the vault.
-    let mut vault = vault_connect()?;
-
-    // Add the new account.
-    vault.create_new_account(&username, password)?;
+    let mut vault = vault_connect(vault_location)?;
+    // Tune the password hashing cost to this machine.
+    vault.set_kdf_params(KdfParams::calibrate(TARGET_UNLOCK_TIME)?);
+
+    // Get the key file, if any.
+    let key_file = new_key_file(gen_key_file.as_deref(), key_file.as_deref())?;
+
+    // Add the new account. Don't leave behind a key file for an account which doesn't exist.
+    let recovery_code =
+        match vault.create_new_account(&username, password.expose(), key_file.as_ref()) {
+            Ok(recovery_code) => recovery_code,
+            Err(err) => {
+                if let Some(path) = gen_key_file {
+                    fs::remove_file(path)?;
+                }
+                return Err(err);
+            }
+        };
 
     println!("Account {} created.", username);
+    print_recovery_code(&recovery_code);
     Ok(())
 }
 
 /// List all existing accounts.
-pub fn list_accounts() -> eyre::Result<()> {
+pub fn list_accounts(vault_location: &VaultLocation) -> eyre::Result<()> {
     // Connect to the vault.
-    let vault = vault_connect()?;
+    let vault = vault_connect(vault_location)?;
+
+    // Usernames aren't stored in privacy mode.
+    if vault.privacy_mode() {
+        return Err(eyre!("Accounts can't be listed in privacy mode."));
+    }
 
-    // Load all accounts.
-    let mut accounts = vault.load_all::<Account>()?;
+    // Load all accounts, except those in the trash.
+    let mut accounts = Vec::new();
+    for account in vault.load_all::<Account>()? {
+        if !vault.is_account_trashed(account.username())? {
+            accounts.push(account);
+        }
+    }
     accounts.sort_unstable();
 
     // Create username list output.
@@ -52,356 +100,2088 @@ pub fn list_accounts() -> eyre::Result<()> {
     Ok(())
 }
 
-/// Change an account's password.
-pub fn change_password(username: String) -> eyre::Result<()> {
+/// Change an account's password. The account's key file is replaced iff a new one is generated or
+/// given, and removed iff `no_key_file` is set.
+pub fn change_password(
+    vault_location: &VaultLocation,
+    username: String,
+    gen_key_file: Option<Utf8PathBuf>,
+    key_file: Option<Utf8PathBuf>,
+    no_key_file: bool,
+) -> eyre::Result<()> {
     // Connect to the vault.
-    let mut vault = vault_connect()?;
+    let mut vault = vault_connect(vault_location)?;
     // Login.
-    let unlocked = login(&vault, &username)?;
+    let unlocked = login(&mut vault, &username)?;
 
     // Confirm new password.
-    let new_password =
-        rpassword::prompt_password(format!("New password for account {}: ", username))?;
-    let confirm_new_password =
-        rpassword::prompt_password(format!("Confirm new password for account {}: ", username))?;
-    if confirm_new_password != new_password {
-        return Err(eyre!("New passwords do not match."));
-    }
+    let new_password = prompt_new_password(&username)?;
 
-    // Update account password.
-    vault.change_account_password(&username, unlocked.password(), new_password)?;
+    // Get the new key file, if any.
+    let new_key_file = if no_key_file {
+        None
+    } else if gen_key_file.is_some() || key_file.is_some() {
+        new_key_file(gen_key_file.as_deref(), key_file.as_deref())?
+    } else {
+        unlocked.key_file().cloned()
+    };
+
+    // Update account password. Don't leave behind a key file which unlocks nothing.
+    if let Err(err) = vault.change_account_password(
+        &username,
+        unlocked.password(),
+        unlocked.key_file(),
+        unlocked.two_factor_code().as_ref(),
+        new_password.expose(),
+        new_key_file.as_ref(),
+    ) {
+        if let Some(path) = gen_key_file {
+            fs::remove_file(path)?;
+        }
+        return Err(err);
+    }
 
     println!("{} password updated.", username);
     Ok(())
 }
 
-/// Delete an existing account along with all its files and passwords.
-pub fn delete_account(username: String, force: bool) -> eyre::Result<()> {
+/// Set a new password for an account using its recovery code. The account requires a key file iff
+/// a new one is generated or given.
+pub fn recover_account(
+    vault_location: &VaultLocation,
+    username: String,
+    gen_key_file: Option<Utf8PathBuf>,
+    key_file: Option<Utf8PathBuf>,
+) -> eyre::Result<()> {
     // Connect to the vault.
-    let mut vault = vault_connect()?;
-    // Login.
-    let unlocked = login(&vault, &username)?;
+    let mut vault = vault_connect(vault_location)?;
 
-    // Get all files & credentials of this account.
-    let credentials = vault.load_account_credentials(&username)?;
-    let files = vault.load_account_files_data(&username)?;
+    // Prompt for recovery code.
+    let recovery_code: RecoveryCode = Secret::new(rpassword::prompt_password(format!(
+        "Recovery code for {}: ",
+        username
+    ))?)
+    .expose()
+    .parse()?;
 
-    if !force
-        && !cli_confirm(
-            format!(
-                "Really delete account {} with {} credential(s) & {} file(s)? [y/N] ",
-                unlocked.username(),
-                credentials.len(),
-                files.len()
-            ),
-            false,
-        )?
-    {
-        println!("Account deletion cancelled.");
-        return Ok(());
-    }
+    // Confirm new password.
+    let new_password = prompt_new_password(&username)?;
 
-    // Delete account and all its associated files.
-    vault.delete_account(&username)?;
+    // Get the new key file, if any.
+    let new_key_file = new_key_file(gen_key_file.as_deref(), key_file.as_deref())?;
+
+    // Recover the account. Don't leave behind a key file which unlocks nothing.
+    if let Err(err) = vault.recover_account(
+        &username,
+        &recovery_code,
+        new_password.expose(),
+        new_key_file.as_ref(),
+    ) {
+        if let Some(path) = gen_key_file {
+            fs::remove_file(path)?;
+        }
+        return Err(err);
+    }
 
-    println!("Account {} deleted.", username);
+    println!("{} recovered. Password updated.", username);
     Ok(())
 }
 
-// CREDENTIALS
-
-/// Create a new credential.
-pub fn new_credential(username: String, credentialname: String) -> eyre::Result<()> {
+/// Set a new password for an account using enough of its key shares. The account requires a key
+/// file iff a new one is generated or given.
+pub fn recover_account_with_shares(
+    vault_location: &VaultLocation,
+    username: String,
+    gen_key_file: Option<Utf8PathBuf>,
+    key_file: Option<Utf8PathBuf>,
+) -> eyre::Result<()> {
     // Connect to the vault.
-    let mut vault = vault_connect()?;
-    // Login.
-    let unlocked = login(&vault, &username)?;
+    let mut vault = vault_connect(vault_location)?;
 
-    // Prompt for credential name.
-    let credential_username = cli_prompt(format!("{} username: ", credentialname))?;
-    let credential_password = cli_prompt(format!("{} password: ", credentialname))?;
-    let credential_notes = cli_prompt(format!("{} notes: ", credentialname))?;
+    // Prompt for key shares until there are enough of them.
+    let mut shares: Vec<KeyShare> = vec![prompt_key_share(&username, 1, None)?];
+    let threshold = shares[0].threshold();
+    for i in 2..=threshold {
+        shares.push(prompt_key_share(&username, i, Some(threshold))?);
+    }
 
-    // Add credential to vault.
-    vault.create_credential(
-        unlocked.username(),
-        unlocked.key(),
-        &credentialname,
-        credential_username,
-        credential_password,
-        credential_notes,
-    )?;
+    // Confirm new password.
+    let new_password = prompt_new_password(&username)?;
 
-    println!("Credential \"{}\" created.", credentialname);
+    // Get the new key file, if any.
+    let new_key_file = new_key_file(gen_key_file.as_deref(), key_file.as_deref())?;
+
+    // Recover the account. Don't leave behind a key file which unlocks nothing.
+    if let Err(err) = vault.recover_account_with_shares(
+        &username,
+        &shares,
+        new_password.expose(),
+        new_key_file.as_ref(),
+    ) {
+        if let Some(path) = gen_key_file {
+            fs::remove_file(path)?;
+        }
+        return Err(err);
+    }
+
+    println!("{} recovered. Password updated.", username);
     Ok(())
 }
 
-/// Edit an existing credential.
-pub fn edit_credential(username: String, credentialname: String) -> eyre::Result<()> {
+/// Replace an account's recovery code with a new one.
+pub fn regenerate_recovery_code(
+    vault_location: &VaultLocation,
+    username: String,
+) -> eyre::Result<()> {
     // Connect to the vault.
-    let mut vault = vault_connect()?;
+    let mut vault = vault_connect(vault_location)?;
     // Login.
-    let unlocked = login(&vault, &username)?;
-    let key = unlocked.key();
+    let unlocked = login(&mut vault, &username)?;
 
-    // Load the credential & its fields.
-    let credential = vault.load_credential(&username, &credentialname, key)?;
-    let mut credential_username: String = credential.username(key)?;
-    let mut credential_password: String = credential.password(key)?;
-    let mut credential_notes: String = credential.notes(key)?;
-
-    // Prompt to edit each credential field.
-    if cli_confirm(
-        format!(
-            "Username: \"{}\"\nEdit username? [Y/n] ",
-            credential_username
-        ),
-        true,
-    )? {
-        credential_username = edit_string(temp_dir()?, credential_username)?;
-    }
-    vault.update_credential(
+    // Generate a new recovery code.
+    let recovery_code = vault.regenerate_recovery_code(
         &username,
-        &credentialname,
-        key,
-        CredentialUpdateField::UsernameCipherbytes,
-        CredentialUpdateField::UsernameNonce,
-        &credential_username,
+        unlocked.password(),
+        unlocked.key_file(),
+        unlocked.two_factor_code().as_ref(),
     )?;
 
-    if cli_confirm(
-        format!(
-            "Password: \"{}\"\nEdit password? [Y/n] ",
-            credential_password
-        ),
-        true,
-    )? {
-        credential_password = edit_string(temp_dir()?, credential_password)?;
-    }
-    vault.update_credential(
-        &username,
-        &credentialname,
-        key,
-        CredentialUpdateField::PasswordCipherbytes,
-        CredentialUpdateField::PasswordNonce,
-        &credential_password,
-    )?;
+    println!(
+        "{} recovery code replaced. The old recovery code no longer works.",
+        username
+    );
+    print_recovery_code(&recovery_code);
+    Ok(())
+}
 
-    if cli_confirm(
-        format!("Notes: \"{}\"\nEdit notes? [Y/n] ", credential_notes),
-        true,
-    )? {
-        credential_notes = edit_string(temp_dir()?, credential_notes)?;
-    }
-    vault.update_credential(
+/// Remove an account's recovery code.
+pub fn revoke_recovery_code(vault_location: &VaultLocation, username: String) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Remove the recovery code.
+    vault.revoke_recovery_code(
         &username,
-        &credentialname,
-        key,
-        CredentialUpdateField::NotesCipherbytes,
-        CredentialUpdateField::NotesNonce,
-        &credential_notes,
+        unlocked.password(),
+        unlocked.key_file(),
+        unlocked.two_factor_code().as_ref(),
     )?;
 
-    println!("Credential \"{}\" edited successfully.", credentialname);
+    println!(
+        "{} recovery code revoked. The account can only be unlocked with its password.",
+        username
+    );
     Ok(())
 }
 
-/// View an existing credential.
-pub fn view_credential(username: String, credentialname: String) -> eyre::Result<()> {
+/// Split an account's key into `count` key shares, any `threshold` of which can recover it.
+pub fn split_account_key(
+    vault_location: &VaultLocation,
+    username: String,
+    threshold: u8,
+    count: u8,
+) -> eyre::Result<()> {
     // Connect to the vault.
-    let vault = vault_connect()?;
+    let mut vault = vault_connect(vault_location)?;
     // Login.
-    let unlocked = login(&vault, &username)?;
-    let key = unlocked.key();
+    let unlocked = login(&mut vault, &username)?;
 
-    // Load the credential & its fields.
-    let credential = vault.load_credential(&username, &credentialname, key)?;
-    let credential_username: String = credential.username(key)?;
-    let credential_password: String = credential.password(key)?;
-    let credential_notes: String = credential.notes(key)?;
+    // Split the key.
+    let shares = vault.split_account_key(
+        &username,
+        unlocked.password(),
+        unlocked.key_file(),
+        unlocked.two_factor_code().as_ref(),
+        threshold,
+        count,
+    )?;
 
-    // Output credential
     println!(
-        "Credential \"{}\"\nUsername: {}Password: {}Notes:{}",
-        credentialname, credential_username, credential_password, credential_notes
+        "{} key split into {} shares. Any old key shares no longer work.",
+        username, count
+    );
+    for share in &shares {
+        println!(
+            "Key share {} of {}: {}",
+            share.index(),
+            share.count(),
+            share.encode().expose()
+        );
+    }
+    println!(
+        "Give each key share to a different person. Any {} of them can recover your account, & \
+        they won't be shown again.",
+        threshold
     );
-
     Ok(())
 }
 
-/// List all credentials owned by the given account.
-pub fn list_credentials(username: String) -> eyre::Result<()> {
+/// Remove an account's key shares.
+pub fn revoke_key_shares(vault_location: &VaultLocation, username: String) -> eyre::Result<()> {
     // Connect to the vault.
-    let vault = vault_connect()?;
+    let mut vault = vault_connect(vault_location)?;
     // Login.
-    let unlocked = login(&vault, &username)?;
+    let unlocked = login(&mut vault, &username)?;
 
-    // Load all owned credentials.
-    let credentials = vault.load_account_credentials(&username)?;
-    // Convert to credential names.
-    let mut credential_names = credentials
-        .iter()
-        .map(|cred| cred.name::<String>(unlocked.key()).unwrap_or_default())
-        .collect::<Vec<String>>();
-
-    credential_names.sort_unstable();
-
-    let credential_names_string = credential_names
-        .iter()
-        .fold(String::new(), |acc, next| acc + next + "\n");
-
-    // Print credential names.
-    println!("{}", credential_names_string);
+    // Remove the key shares.
+    vault.revoke_key_shares(
+        &username,
+        unlocked.password(),
+        unlocked.key_file(),
+        unlocked.two_factor_code().as_ref(),
+    )?;
 
+    println!(
+        "{} key shares revoked. They can no longer recover the account.",
+        username
+    );
     Ok(())
 }
 
-/// Delete a credential.
-pub fn delete_credential(
-    username: String,
-    credentialname: String,
-    force: bool,
-) -> eyre::Result<()> {
+/// Turn on two-factor login for an account, showing the TOTP secret to add to an authenticator
+/// app.
+pub fn enable_two_factor(vault_location: &VaultLocation, username: String) -> eyre::Result<()> {
     // Connect to the vault.
-    let mut vault = vault_connect()?;
+    let mut vault = vault_connect(vault_location)?;
+    if vault.load_account(&username)?.requires_two_factor() {
+        return Err(eyre!(
+            "Two-factor login is already enabled for account {}.",
+            username
+        ));
+    }
     // Login.
-    let unlocked = login(&vault, &username)?;
-
-    // Load credential.
-    let credential = vault.load_credential(unlocked.username(), credentialname, unlocked.key())?;
-    let loaded_name: String = credential.name(unlocked.key())?;
+    let unlocked = login(&mut vault, &username)?;
 
-    if !force
-        && !cli_confirm(
-            format!("Really delete credential \"{}\"? [y/N] ", loaded_name,),
-            false,
-        )?
-    {
-        println!("Credential deletion cancelled.");
-        return Ok(());
-    }
+    // Show the new TOTP secret.
+    let totp_secret = TotpSecret::generate();
+    println!(
+        "Scan this QR code with your authenticator app:\n{}",
+        totp_secret.qr_code(&username)?.expose()
+    );
+    println!(
+        "Or enter this secret manually: {}\nURI: {}",
+        totp_secret.encode().expose(),
+        totp_secret.uri(&username).expose()
+    );
 
-    // Delete credential.
-    vault.delete_credential(username, &loaded_name, unlocked.key())?;
+    // Confirm that the authenticator app has the TOTP secret.
+    let code = Secret::new(cli_prompt(format!(
+        "Two-factor code from your authenticator app for {}: ",
+        username
+    ))?);
+    let backup_codes = vault.enable_two_factor(
+        &username,
+        unlocked.password(),
+        unlocked.key_file(),
+        &totp_secret,
+        code.expose().trim(),
+    )?;
 
-    println!("Credential \"{}\" deleted.", loaded_name);
+    println!("{} two-factor login enabled.", username);
+    print_backup_codes(&backup_codes);
     Ok(())
 }
 
-// FILES
-
-/// Create a new file.
-pub fn new_file(username: String, filename: String) -> eyre::Result<()> {
+/// Turn off two-factor login for an account.
+pub fn disable_two_factor(vault_location: &VaultLocation, username: String) -> eyre::Result<()> {
     // Connect to the vault.
-    let mut vault = vault_connect()?;
+    let mut vault = vault_connect(vault_location)?;
     // Login.
-    let unlocked = login(&vault, &username)?;
+    let unlocked = login(&mut vault, &username)?;
 
-    // Add empty file to vault.
-    vault.create_file(&filename, username, &b""[..], unlocked.key())?;
+    // Turn off two-factor login.
+    vault.disable_two_factor(
+        &username,
+        unlocked.password(),
+        unlocked.key_file(),
+        unlocked.two_factor_code().as_ref(),
+    )?;
 
-    println!("File \"{}\" created.", filename);
+    println!("{} two-factor login disabled.", username);
     Ok(())
 }
 
-/// Open & edit an existing file.
-pub fn open_file(username: String, filename: String) -> eyre::Result<()> {
+/// Replace an account's two-factor backup codes with new ones.
+pub fn regenerate_backup_codes(
+    vault_location: &VaultLocation,
+    username: String,
+) -> eyre::Result<()> {
     // Connect to the vault.
-    let mut vault = vault_connect()?;
+    let mut vault = vault_connect(vault_location)?;
     // Login.
-    let unlocked = login(&vault, &username)?;
+    let unlocked = login(&mut vault, &username)?;
 
-    // Load file.
-    let (_, file_contents): (_, Vec<u8>) = vault.load_file(&username, &filename, unlocked.key())?;
+    // Generate new backup codes.
+    let backup_codes = vault.regenerate_backup_codes(
+        &username,
+        unlocked.password(),
+        unlocked.key_file(),
+        unlocked.two_factor_code().as_ref(),
+    )?;
 
-    // Edit file.
-    let edited_file_contents = edit_bytes(temp_dir()?, file_contents)?;
+    println!(
+        "{} backup codes replaced. The old backup codes no longer work.",
+        username
+    );
+    print_backup_codes(&backup_codes);
+    Ok(())
+}
 
-    // Update file.
-    vault.update_file(&username, &filename, unlocked.key(), edited_file_contents)?;
+/// Re-tune an account's password hashing cost to take roughly [TARGET_UNLOCK_TIME] to unlock on
+/// this machine.
+pub fn calibrate_account(vault_location: &VaultLocation, username: String) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Calibrate the password hashing cost.
+    vault.set_kdf_params(KdfParams::calibrate(TARGET_UNLOCK_TIME)?);
+    // Login. The password is re-hashed iff the calibrated cost is stronger than the current one.
+    let unlocked = login(&mut vault, &username)?;
 
-    println!("File \"{}\" updated successfully.", filename);
+    let kdf_params = unlocked.kdf_params();
+    println!(
+        "{} password hashed with {} (memory: {} KiB, time cost: {}, parallelism: {}).",
+        username,
+        kdf_params.algorithm(),
+        kdf_params.memory_kib(),
+        kdf_params.time_cost(),
+        kdf_params.parallelism()
+    );
     Ok(())
 }
 
-/// List all files owned by the given account.
-pub fn list_files(username: String) -> eyre::Result<()> {
+/// Replace an account's encryption key, re-encrypting all its files and passwords.
+pub fn rotate_account_key(vault_location: &VaultLocation, username: String) -> eyre::Result<()> {
     // Connect to the vault.
-    let vault = vault_connect()?;
+    let mut vault = vault_connect(vault_location)?;
     // Login.
-    login(&vault, &username)?;
+    let unlocked = login(&mut vault, &username)?;
 
-    // Load all owned files data.
+    // Get all files & credentials of this account.
+    let credentials = vault.load_account_credentials(&username)?;
     let files = vault.load_account_files_data(&username)?;
-    // Convert to file names.
-    let mut file_names = files
-        .iter()
-        .map(|file| file.filename())
-        .collect::<Vec<&str>>();
-
-    file_names.sort_unstable();
-
-    let file_names_string = file_names
-        .iter()
-        .fold(String::new(), |acc, next| acc + next + "\n");
+    let had_key_shares = vault.load_account(&username)?.share_recovery().is_some();
 
-    // Print file names.
-    println!("{}", file_names_string);
+    // Rotate the key.
+    let recovery_code = vault.rotate_account_key(
+        &username,
+        unlocked.password(),
+        unlocked.key_file(),
+        unlocked.two_factor_code().as_ref(),
+    )?;
 
+    println!(
+        "{} key rotated. {} credential(s) & {} file(s) re-encrypted.",
+        username,
+        credentials.len(),
+        files.len()
+    );
+    if let Some(recovery_code) = recovery_code {
+        println!("The recovery code was replaced. The old recovery code no longer works.");
+        print_recovery_code(&recovery_code);
+    }
+    if had_key_shares {
+        println!(
+            "The key shares were revoked & no longer work. Split the key again with \
+            `dgruft {} accounts --newshares <COUNT> --threshold <THRESHOLD>`.",
+            username
+        );
+    }
     Ok(())
 }
 
-/// Delete a file.
-pub fn delete_file(username: String, filename: String, force: bool) -> eyre::Result<()> {
+/// Move an existing account, along with all its files and passwords, to the trash.
+pub fn delete_account(
+    vault_location: &VaultLocation,
+    username: String,
+    force: bool,
+) -> eyre::Result<()> {
     // Connect to the vault.
-    let mut vault = vault_connect()?;
+    let mut vault = vault_connect(vault_location)?;
     // Login.
-    let unlocked = login(&vault, &username)?;
+    login(&mut vault, &username)?;
 
-    // Load file data.
-    let (file_data, file_contents): (FileData, Vec<u8>) =
-        vault.load_file(unlocked.username(), filename, unlocked.key())?;
+    // Get all files & credentials of this account.
+    let credentials = vault.load_account_credentials(&username)?;
+    let files = vault.load_account_files_data(&username)?;
 
     if !force
         && !cli_confirm(
             format!(
-                "Really delete file \"{}\" ({} bytes)? [y/N] ",
-                file_data.filename(),
-                file_contents.len()
+                "Really delete account {} with {} credential(s) & {} file(s)? [y/N] ",
+                username,
+                credentials.len(),
+                files.len()
             ),
             false,
         )?
     {
-        println!("File deletion cancelled.");
+        println!("Account deletion cancelled.");
         return Ok(());
     }
 
-    // Delete file.
-    vault.delete_file(username, file_data.filename())?;
+    // Move the account to the trash.
+    vault.delete_account(&username)?;
 
-    println!("File \"{}\" deleted.", file_data.filename());
+    println!(
+        "Account {} moved to the trash. Restore it with --undelete.",
+        username
+    );
     Ok(())
 }
 
-// HELPERS
+/// Restore an account from the trash.
+pub fn restore_account(vault_location: &VaultLocation, username: String) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login, restoring the account.
+    authenticate(
+        &mut vault,
+        &username,
+        |vault, username, password, key_file, two_factor_code| {
+            vault.restore_account(username, password, key_file, two_factor_code)
+        },
+    )?;
 
-// Connect to the vault.
-fn vault_connect() -> eyre::Result<Vault> {
-    Vault::connect(db_path()?, data_dir()?)
+    println!("Account {} restored from the trash.", username);
+    Ok(())
 }
 
-// Verify login into correct, returning account data.
-fn login(vault: &Vault, username: &str) -> eyre::Result<UnlockedAccount> {
-    let password = prompt_password(username)?;
-    vault.load_unlocked_account(username, &password)
+/// Permanently delete an account in the trash, along with all its files and passwords.
+pub fn purge_account(vault_location: &VaultLocation, username: String) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+
+    if !cli_confirm(
+        format!(
+            "Really permanently delete account {}? This can't be undone. [y/N] ",
+            username
+        ),
+        false,
+    )? {
+        println!("Account purge cancelled.");
+        return Ok(());
+    }
+
+    // Login, purging the account.
+    authenticate(
+        &mut vault,
+        &username,
+        |vault, username, password, key_file, two_factor_code| {
+            vault.purge_account(username, password, key_file, two_factor_code)
+        },
+    )?;
+
+    println!("Account {} permanently deleted.", username);
+    Ok(())
 }
 
-// Password prompt.
-fn prompt_password(username: &str) -> eyre::Result<String> {
-    Ok(rpassword::prompt_password(format!(
-        "Password for {}: ",
+/// Permanently delete every credential & file in an account's trash.
+pub fn empty_trash(vault_location: &VaultLocation, username: String) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    login(&mut vault, &username)?;
+
+    // Count the trashed credentials & files.
+    let num_credentials = vault.load_trashed_credentials(&username)?.len();
+    let num_files = vault.load_trashed_files_data(&username)?.len();
+    if num_credentials + num_files == 0 {
+        println!("The trash is already empty.");
+        return Ok(());
+    }
+
+    if !cli_confirm(
+        format!(
+            "Really permanently delete {} credential(s) & {} file(s) in the trash? [y/N] ",
+            num_credentials, num_files
+        ),
+        false,
+    )? {
+        println!("Emptying the trash cancelled.");
+        return Ok(());
+    }
+
+    // Purge everything in the trash.
+    let num_purged = vault.empty_trash(&username)?;
+
+    println!("{} item(s) permanently deleted from the trash.", num_purged);
+    Ok(())
+}
+
+// CREDENTIALS
+
+/// Create a new credential.
+pub fn new_credential(
+    vault_location: &VaultLocation,
+    username: String,
+    credentialname: String,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Prompt for credential name.
+    let credential_username = Secret::new(cli_prompt(format!("{} username: ", credentialname))?);
+    let credential_password = Secret::new(cli_prompt(format!("{} password: ", credentialname))?);
+    let credential_notes = Secret::new(cli_prompt(format!("{} notes: ", credentialname))?);
+
+    // Add credential to vault.
+    vault.create_credential(
+        &username,
+        unlocked.key(),
+        &credentialname,
+        credential_username.expose(),
+        credential_password.expose(),
+        credential_notes.expose(),
+    )?;
+
+    println!("Credential \"{}\" created.", credentialname);
+    Ok(())
+}
+
+/// Edit an existing credential.
+pub fn edit_credential(
+    vault_location: &VaultLocation,
+    username: String,
+    credentialname: String,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+    let key = unlocked.key();
+
+    // Load the credential & its fields.
+    let credential = vault.load_credential(&username, &credentialname, key)?;
+    let mut credential_username: Secret<String> = credential.username(key)?;
+    let mut credential_password: Secret<String> = credential.password(key)?;
+    let mut credential_notes: Secret<String> = credential.notes(key)?;
+
+    // Prompt to edit each credential field.
+    if cli_confirm(
+        format!(
+            "Username: \"{}\"\nEdit username? [Y/n] ",
+            credential_username.expose()
+        ),
+        true,
+    )? {
+        credential_username = Secret::new(edit_string(temp_dir()?, credential_username.expose())?);
+    }
+    if cli_confirm(
+        format!(
+            "Password: \"{}\"\nEdit password? [Y/n] ",
+            credential_password.expose()
+        ),
+        true,
+    )? {
+        credential_password = Secret::new(edit_string(temp_dir()?, credential_password.expose())?);
+    }
+    if cli_confirm(
+        format!(
+            "Notes: \"{}\"\nEdit notes? [Y/n] ",
+            credential_notes.expose()
+        ),
+        true,
+    )? {
+        credential_notes = Secret::new(edit_string(temp_dir()?, credential_notes.expose())?);
+    }
+
+    // Save every field at once, so the edit is kept as one previous version.
+    vault.update_credential_fields(
+        &username,
+        &credentialname,
+        key,
+        &[
+            (CredentialField::Username, credential_username.expose()),
+            (CredentialField::Password, credential_password.expose()),
+            (CredentialField::Notes, credential_notes.expose()),
+        ],
+    )?;
+
+    println!("Credential \"{}\" edited successfully.", credentialname);
+    Ok(())
+}
+
+/// View an existing credential.
+pub fn view_credential(
+    vault_location: &VaultLocation,
+    username: String,
+    credentialname: String,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+    let key = unlocked.key();
+
+    // Load the credential, or a credential shared with this account, & its fields.
+    let (credential, key) = match vault.load_credential(&username, &credentialname, key) {
+        Ok(credential) => (credential, key.clone()),
+        Err(err) => match vault.load_shared_credential(&unlocked, &credentialname) {
+            Ok(shared) => (shared.item().clone(), shared.key().clone()),
+            Err(_) => return Err(err),
+        },
+    };
+    let credential_username: Secret<String> = credential.username(&key)?;
+    let credential_password: Secret<String> = credential.password(&key)?;
+    let credential_notes: Secret<String> = credential.notes(&key)?;
+
+    // Output credential
+    println!(
+        "Credential \"{}\"\nUsername: {}Password: {}Notes:{}",
+        credentialname,
+        credential_username.expose(),
+        credential_password.expose(),
+        credential_notes.expose()
+    );
+
+    Ok(())
+}
+
+/// List the previous versions of a credential, newest first.
+pub fn credential_history(
+    vault_location: &VaultLocation,
+    username: String,
+    credentialname: String,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+    let key = unlocked.key();
+
+    // Load the previous versions.
+    let versions = vault.load_credential_history(&username, &credentialname, key)?;
+    if versions.is_empty() {
+        println!(
+            "Credential \"{}\" has no previous versions.",
+            credentialname
+        );
+        return Ok(());
+    }
+
+    // Output each version.
+    println!("Previous versions of credential \"{}\":", credentialname);
+    for (i, version) in versions.iter().enumerate() {
+        let version_username: Secret<String> = version.username(key)?;
+        let version_password: Secret<String> = version.password(key)?;
+        let version_notes: Secret<String> = version.notes(key)?;
+        println!(
+            "{}. Replaced {}\nUsername: {}\nPassword: {}\nNotes: {}",
+            i + 1,
+            format_unix_time(version.replaced_at()),
+            version_username.expose().trim_end_matches('\n'),
+            version_password.expose().trim_end_matches('\n'),
+            version_notes.expose().trim_end_matches('\n'),
+        );
+    }
+    Ok(())
+}
+
+/// Restore a previous version of a credential, numbered as in its history.
+pub fn restore_credential_version(
+    vault_location: &VaultLocation,
+    username: String,
+    credentialname: String,
+    number: usize,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+    let key = unlocked.key();
+
+    // Find the version to restore.
+    let versions = vault.load_credential_history(&username, &credentialname, key)?;
+    let version = number
+        .checked_sub(1)
+        .and_then(|i| versions.get(i))
+        .ok_or_else(|| {
+            eyre!(
+                "Credential \"{}\" has {} previous version(s); there is no version {}.",
+                credentialname,
+                versions.len(),
+                number
+            )
+        })?;
+
+    // Restore it.
+    vault.restore_credential_version(&username, &credentialname, key, version.id())?;
+
+    println!(
+        "Credential \"{}\" restored to the version replaced {}.",
+        credentialname,
+        format_unix_time(version.replaced_at())
+    );
+    Ok(())
+}
+
+/// Export a credential to a new file outside the vault as an age payload, encrypted to the given
+/// recipient or to a passphrase.
+pub fn export_credential_age(
+    vault_location: &VaultLocation,
+    username: String,
+    credentialname: String,
+    destination: Utf8PathBuf,
+    to: Option<String>,
+) -> eyre::Result<()> {
+    // Parse the recipient before asking for anything else.
+    let recipient = age_recipient(to)?;
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Encrypt the credential to the destination, removing the destination on a failure.
+    let destination_file = BufWriter::new(File::create_new(&destination)?);
+    if let Err(err) = vault.export_credential_age(
+        &username,
+        &credentialname,
+        unlocked.key(),
+        &recipient,
+        destination_file,
+    ) {
+        let _ = fs::remove_file(&destination);
+        return Err(err);
+    }
+
+    println!(
+        "Credential \"{}\" exported to {} as an age payload.",
+        credentialname, destination
+    );
+    Ok(())
+}
+
+/// List all credentials owned by or shared with the given account.
+pub fn list_credentials(vault_location: &VaultLocation, username: String) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Load all owned credentials.
+    let credentials = vault.load_account_credentials(&username)?;
+    // Convert to credential names.
+    let mut credential_names = credentials
+        .iter()
+        .map(|cred| cred.name::<String>(unlocked.key()).unwrap_or_default())
+        .collect::<Vec<String>>();
+
+    credential_names.sort_unstable();
+
+    // Add the names of the credentials shared with this account.
+    let mut shared_names = vault
+        .load_shared_credentials(&unlocked)?
+        .iter()
+        .map(|shared| {
+            format!(
+                "{} {}",
+                shared
+                    .item()
+                    .name::<String>(shared.key())
+                    .unwrap_or_default(),
+                shared_by(&vault, shared.item().owner_username())
+            )
+        })
+        .collect::<Vec<String>>();
+    shared_names.sort_unstable();
+    credential_names.append(&mut shared_names);
+
+    let credential_names_string = credential_names
+        .iter()
+        .fold(String::new(), |acc, next| acc + next + "\n");
+
+    // Print credential names.
+    println!("{}", credential_names_string);
+
+    Ok(())
+}
+
+/// Move a credential to the trash.
+pub fn delete_credential(
+    vault_location: &VaultLocation,
+    username: String,
+    credentialname: String,
+    force: bool,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Load credential.
+    let credential = vault.load_credential(&username, credentialname, unlocked.key())?;
+    let loaded_name: String = credential.name(unlocked.key())?;
+
+    if !force
+        && !cli_confirm(
+            format!("Really delete credential \"{}\"? [y/N] ", loaded_name,),
+            false,
+        )?
+    {
+        println!("Credential deletion cancelled.");
+        return Ok(());
+    }
+
+    // Move the credential to the trash.
+    vault.delete_credential(username, &loaded_name, unlocked.key())?;
+
+    println!(
+        "Credential \"{}\" moved to the trash. Restore it with --undelete.",
+        loaded_name
+    );
+    Ok(())
+}
+
+/// List the credentials in an account's trash, most recently deleted first.
+pub fn list_trashed_credentials(
+    vault_location: &VaultLocation,
+    username: String,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Load the trashed credentials.
+    let trashed_credentials = vault.load_trashed_credentials(&username)?;
+    if trashed_credentials.is_empty() {
+        println!("No credentials are in the trash.");
+        return Ok(());
+    }
+
+    // Output each trashed credential.
+    println!("Credentials in the trash:");
+    for (trashed_item, credential) in trashed_credentials {
+        println!(
+            "{} (deleted {})",
+            credential
+                .name::<String>(unlocked.key())
+                .unwrap_or_default(),
+            format_unix_time(trashed_item.trashed_at())
+        );
+    }
+    Ok(())
+}
+
+/// Restore a credential from the trash.
+pub fn restore_trashed_credential(
+    vault_location: &VaultLocation,
+    username: String,
+    credentialname: String,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Restore the credential.
+    vault.restore_trashed_credential(&username, &credentialname, unlocked.key())?;
+
+    println!("Credential \"{}\" restored from the trash.", credentialname);
+    Ok(())
+}
+
+/// Permanently delete a credential in the trash.
+pub fn purge_trashed_credential(
+    vault_location: &VaultLocation,
+    username: String,
+    credentialname: String,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    if !cli_confirm(
+        format!(
+            "Really permanently delete credential \"{}\"? This can't be undone. [y/N] ",
+            credentialname
+        ),
+        false,
+    )? {
+        println!("Credential purge cancelled.");
+        return Ok(());
+    }
+
+    // Purge the credential.
+    vault.purge_trashed_credential(&username, &credentialname, unlocked.key())?;
+
+    println!("Credential \"{}\" permanently deleted.", credentialname);
+    Ok(())
+}
+
+/// Let another account read a credential.
+pub fn share_credential(
+    vault_location: &VaultLocation,
+    username: String,
+    credentialname: String,
+    recipient: String,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Share the credential.
+    vault.share_credential(&username, &credentialname, unlocked.key(), &recipient)?;
+
+    println!(
+        "Credential \"{}\" shared with account \"{}\".",
+        credentialname, recipient
+    );
+    Ok(())
+}
+
+/// Stop letting another account read a credential.
+pub fn unshare_credential(
+    vault_location: &VaultLocation,
+    username: String,
+    credentialname: String,
+    recipient: String,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Stop sharing the credential.
+    vault.unshare_credential(&username, &credentialname, unlocked.key(), &recipient)?;
+
+    println!(
+        "Credential \"{}\" is no longer shared with account \"{}\".",
+        credentialname, recipient
+    );
+    Ok(())
+}
+
+// FILES
+
+/// Create a new file.
+pub fn new_file(
+    vault_location: &VaultLocation,
+    username: String,
+    filename: String,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Add empty file to vault.
+    vault.create_file(&filename, username, &b""[..], unlocked.key())?;
+
+    println!("File \"{}\" created.", filename);
+    Ok(())
+}
+
+/// Open & edit an existing file.
+pub fn open_file(
+    vault_location: &VaultLocation,
+    username: String,
+    filename: String,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Load file.
+    let (_, file_contents): (_, Secret<Vec<u8>>) =
+        vault.load_file(&username, &filename, unlocked.key())?;
+
+    // Edit file.
+    let edited_file_contents = Secret::new(edit_bytes(temp_dir()?, file_contents.expose())?);
+
+    // Update file.
+    vault.update_file(
+        &username,
+        &filename,
+        unlocked.key(),
+        edited_file_contents.expose().as_slice(),
+    )?;
+
+    println!("File \"{}\" updated successfully.", filename);
+    Ok(())
+}
+
+/// Import the contents of a file outside the vault, creating a new file or replacing the contents
+/// of an existing one.
+pub fn import_file(
+    vault_location: &VaultLocation,
+    username: String,
+    filename: String,
+    source: Utf8PathBuf,
+) -> eyre::Result<()> {
+    // Open the source file.
+    let source_file = BufReader::new(File::open(&source)?);
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Replace the contents of the file if it already exists.
+    let file_exists = vault
+        .load_file_data(&username, &filename, unlocked.key())
+        .is_ok();
+    if file_exists {
+        vault.update_file_from(&username, &filename, unlocked.key(), source_file)?;
+        println!("File \"{}\" updated from {}.", filename, source);
+    } else {
+        vault.import_file(&filename, &username, source_file, unlocked.key())?;
+        println!("File \"{}\" imported from {}.", filename, source);
+    }
+    Ok(())
+}
+
+/// Export the decrypted contents of a file to a new file outside the vault.
+pub fn export_file(
+    vault_location: &VaultLocation,
+    username: String,
+    filename: String,
+    destination: Utf8PathBuf,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Decrypt the file, or a file shared with this account, to the destination, removing the
+    // destination on a failure.
+    let destination_file = BufWriter::new(File::create_new(&destination)?);
+    let export_result = if vault
+        .load_file_data(&username, &filename, unlocked.key())
+        .is_ok()
+        || vault.load_shared_file_data(&unlocked, &filename).is_err()
+    {
+        vault.export_file(&username, &filename, unlocked.key(), destination_file)
+    } else {
+        vault.export_shared_file(&unlocked, &filename, destination_file)
+    };
+    let num_bytes = match export_result {
+        Ok(num_bytes) => num_bytes,
+        Err(err) => {
+            let _ = fs::remove_file(&destination);
+            return Err(err);
+        }
+    };
+
+    println!(
+        "File \"{}\" ({} bytes) exported to {}.",
+        filename, num_bytes, destination
+    );
+    Ok(())
+}
+
+/// Create a new file from an age payload outside the vault, decrypted with the given identity
+/// file or with a passphrase. The decrypted contents are never written outside the vault.
+pub fn import_file_age(
+    vault_location: &VaultLocation,
+    username: String,
+    filename: String,
+    source: Utf8PathBuf,
+    identity: Option<Utf8PathBuf>,
+) -> eyre::Result<()> {
+    // Open the source file.
+    let source_file = BufReader::new(File::open(&source)?);
+    // Read the identity before asking for anything else.
+    let identity = age_identity(identity)?;
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    vault.import_file_age(&filename, &username, &identity, source_file, unlocked.key())?;
+    println!(
+        "File \"{}\" imported from age payload {}.",
+        filename, source
+    );
+    Ok(())
+}
+
+/// Export the contents of a file to a new file outside the vault as an age payload, encrypted to
+/// the given recipient or to a passphrase.
+pub fn export_file_age(
+    vault_location: &VaultLocation,
+    username: String,
+    filename: String,
+    destination: Utf8PathBuf,
+    to: Option<String>,
+) -> eyre::Result<()> {
+    // Parse the recipient before asking for anything else.
+    let recipient = age_recipient(to)?;
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Encrypt the file to the destination, removing the destination on a failure.
+    let destination_file = BufWriter::new(File::create_new(&destination)?);
+    let num_bytes = match vault.export_file_age(
+        &username,
+        &filename,
+        unlocked.key(),
+        &recipient,
+        destination_file,
+    ) {
+        Ok(num_bytes) => num_bytes,
+        Err(err) => {
+            let _ = fs::remove_file(&destination);
+            return Err(err);
+        }
+    };
+
+    println!(
+        "File \"{}\" ({} bytes) exported to {} as an age payload.",
+        filename, num_bytes, destination
+    );
+    Ok(())
+}
+
+/// Check that a file decrypts successfully.
+pub fn verify_file(
+    vault_location: &VaultLocation,
+    username: String,
+    filename: String,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Decrypt the file without keeping its contents.
+    let num_bytes = vault.verify_file(&username, &filename, unlocked.key())?;
+
+    println!("File \"{}\" ({} bytes) is intact.", filename, num_bytes);
+    Ok(())
+}
+
+/// List all files owned by or shared with the given account.
+pub fn list_files(vault_location: &VaultLocation, username: String) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Load all owned files data.
+    let files = vault.load_account_files_data(&username)?;
+    // Decrypt the file names.
+    let mut file_names = files
+        .iter()
+        .map(|file| file.filename::<String>(unlocked.key()).unwrap_or_default())
+        .collect::<Vec<String>>();
+
+    file_names.sort_unstable();
+
+    // Add the names of the files shared with this account.
+    let mut shared_names = vault
+        .load_shared_files_data(&unlocked)?
+        .iter()
+        .map(|shared| {
+            format!(
+                "{} {}",
+                shared
+                    .item()
+                    .filename::<String>(shared.key())
+                    .unwrap_or_default(),
+                shared_by(&vault, shared.item().owner_username())
+            )
+        })
+        .collect::<Vec<String>>();
+    shared_names.sort_unstable();
+    file_names.append(&mut shared_names);
+
+    let file_names_string = file_names
+        .iter()
+        .fold(String::new(), |acc, next| acc + next + "\n");
+
+    // Print file names.
+    println!("{}", file_names_string);
+
+    Ok(())
+}
+
+/// Move a file to the trash.
+pub fn delete_file(
+    vault_location: &VaultLocation,
+    username: String,
+    filename: String,
+    force: bool,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Get the size of the file.
+    let num_bytes = vault.verify_file(&username, &filename, unlocked.key())?;
+
+    if !force
+        && !cli_confirm(
+            format!(
+                "Really delete file \"{}\" ({} bytes)? [y/N] ",
+                filename, num_bytes
+            ),
+            false,
+        )?
+    {
+        println!("File deletion cancelled.");
+        return Ok(());
+    }
+
+    // Move the file to the trash.
+    vault.delete_file(username, &filename, unlocked.key())?;
+
+    println!(
+        "File \"{}\" moved to the trash. Restore it with --undelete.",
+        filename
+    );
+    Ok(())
+}
+
+/// List the files in an account's trash, most recently deleted first.
+pub fn list_trashed_files(vault_location: &VaultLocation, username: String) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Load the trashed files.
+    let trashed_files = vault.load_trashed_files_data(&username)?;
+    if trashed_files.is_empty() {
+        println!("No files are in the trash.");
+        return Ok(());
+    }
+
+    // Output each trashed file.
+    println!("Files in the trash:");
+    for (trashed_item, file_data) in trashed_files {
+        println!(
+            "{} (deleted {})",
+            file_data
+                .filename::<String>(unlocked.key())
+                .unwrap_or_default(),
+            format_unix_time(trashed_item.trashed_at())
+        );
+    }
+    Ok(())
+}
+
+/// Restore a file from the trash.
+pub fn restore_trashed_file(
+    vault_location: &VaultLocation,
+    username: String,
+    filename: String,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Restore the file.
+    vault.restore_trashed_file(&username, &filename, unlocked.key())?;
+
+    println!("File \"{}\" restored from the trash.", filename);
+    Ok(())
+}
+
+/// Permanently delete a file in the trash.
+pub fn purge_trashed_file(
+    vault_location: &VaultLocation,
+    username: String,
+    filename: String,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    if !cli_confirm(
+        format!(
+            "Really permanently delete file \"{}\"? This can't be undone. [y/N] ",
+            filename
+        ),
+        false,
+    )? {
+        println!("File purge cancelled.");
+        return Ok(());
+    }
+
+    // Purge the file.
+    vault.purge_trashed_file(&username, &filename, unlocked.key())?;
+
+    println!("File \"{}\" permanently deleted.", filename);
+    Ok(())
+}
+
+/// List the previous revisions of a file, newest first.
+pub fn file_revisions(
+    vault_location: &VaultLocation,
+    username: String,
+    filename: String,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Load the previous revisions.
+    let revisions = vault.load_file_revisions(&username, &filename, unlocked.key())?;
+    if revisions.is_empty() {
+        println!("File \"{}\" has no previous revisions.", filename);
+        return Ok(());
+    }
+
+    // Output each revision.
+    println!("Previous revisions of file \"{}\":", filename);
+    for (i, revision) in revisions.iter().enumerate() {
+        println!(
+            "{}. Replaced {}",
+            i + 1,
+            format_unix_time(revision.replaced_at())
+        );
+    }
+    Ok(())
+}
+
+/// Show the differences between two revisions of a file, numbered as in its revision list. The
+/// current contents are revision 0.
+pub fn diff_file_revisions(
+    vault_location: &VaultLocation,
+    username: String,
+    filename: String,
+    from: usize,
+    to: usize,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+    let key = unlocked.key();
+
+    // Decrypt both revisions.
+    let revisions = vault.load_file_revisions(&username, &filename, key)?;
+    let mut decrypted = Vec::with_capacity(2);
+    for number in [from, to] {
+        let (label, contents): (String, Secret<Vec<u8>>) = if number == 0 {
+            (
+                "current".to_owned(),
+                vault.load_file(&username, &filename, key)?.1,
+            )
+        } else {
+            let revision = revisions.get(number - 1).ok_or_else(|| {
+                eyre!(
+                    "File \"{}\" has {} previous revision(s); there is no revision {}.",
+                    filename,
+                    revisions.len(),
+                    number
+                )
+            })?;
+            let label = format!(
+                "revision {} (replaced {})",
+                number,
+                format_unix_time(revision.replaced_at())
+            );
+            (
+                label,
+                vault
+                    .load_file_revision(&username, &filename, key, revision.id())?
+                    .1,
+            )
+        };
+        let text = Secret::new(String::from_utf8_lossy(contents.expose()).into_owned());
+        decrypted.push((format!("{} ({})", filename, label), text));
+    }
+
+    // Output the differences as a unified diff.
+    let [(from_label, from_text), (to_label, to_text)] = &decrypted[..] else {
+        unreachable!();
+    };
+    let diff = TextDiff::from_lines(from_text.expose(), to_text.expose());
+    print!("{}", diff.unified_diff().header(from_label, to_label));
+    Ok(())
+}
+
+/// Restore a previous revision of a file, numbered as in its revision list.
+pub fn restore_file_revision(
+    vault_location: &VaultLocation,
+    username: String,
+    filename: String,
+    number: usize,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+    let key = unlocked.key();
+
+    // Find the revision to restore.
+    let revisions = vault.load_file_revisions(&username, &filename, key)?;
+    let revision = number
+        .checked_sub(1)
+        .and_then(|i| revisions.get(i))
+        .ok_or_else(|| {
+            eyre!(
+                "File \"{}\" has {} previous revision(s); there is no revision {}.",
+                filename,
+                revisions.len(),
+                number
+            )
+        })?;
+
+    // Restore it.
+    vault.restore_file_revision(&username, &filename, key, revision.id())?;
+
+    println!(
+        "File \"{}\" restored to the revision replaced {}.",
+        filename,
+        format_unix_time(revision.replaced_at())
+    );
+    Ok(())
+}
+
+/// Let another account read a file.
+pub fn share_file(
+    vault_location: &VaultLocation,
+    username: String,
+    filename: String,
+    recipient: String,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Share the file.
+    vault.share_file(&username, &filename, unlocked.key(), &recipient)?;
+
+    println!(
+        "File \"{}\" shared with account \"{}\".",
+        filename, recipient
+    );
+    Ok(())
+}
+
+/// Stop letting another account read a file.
+pub fn unshare_file(
+    vault_location: &VaultLocation,
+    username: String,
+    filename: String,
+    recipient: String,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Stop sharing the file.
+    vault.unshare_file(&username, &filename, unlocked.key(), &recipient)?;
+
+    println!(
+        "File \"{}\" is no longer shared with account \"{}\".",
+        filename, recipient
+    );
+    Ok(())
+}
+
+// VAULT
+
+/// Show the vault's current settings.
+pub fn vault_info(vault_location: &VaultLocation) -> eyre::Result<()> {
+    // Connect to the vault.
+    let vault = vault_connect(vault_location)?;
+
+    println!("Schema version: {}", vault.schema_version()?);
+    println!("Cipher suite: {}", vault.cipher_suite());
+    println!(
+        "Layout: {}",
+        if vault.is_single_file() {
+            "single file"
+        } else {
+            "directory"
+        }
+    );
+    println!(
+        "Privacy mode: {}",
+        if vault.privacy_mode() { "on" } else { "off" }
+    );
+    println!(
+        "Credential history limit: {}",
+        vault.credential_history_limit()
+    );
+    println!("File revision limit: {}", vault.file_revision_limit());
+    println!(
+        "File revision maximum age: {}",
+        match vault.file_revision_max_age() {
+            Some(days) => format!("{} day(s)", days),
+            None => "none".to_owned(),
+        }
+    );
+    println!(
+        "Trash retention: {}",
+        match vault.trash_retention() {
+            Some(days) => format!("{} day(s)", days),
+            None => "until purged".to_owned(),
+        }
+    );
+    Ok(())
+}
+
+/// Enable privacy mode for the vault.
+pub fn enable_privacy_mode(vault_location: &VaultLocation) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+
+    // Enable privacy mode.
+    vault.enable_privacy_mode()?;
+
+    println!("Privacy mode enabled. Usernames will no longer be stored.");
+    Ok(())
+}
+
+/// Set the cipher suite used to encrypt new data in the vault.
+pub fn set_cipher_suite(vault_location: &VaultLocation, cipher_suite: String) -> eyre::Result<()> {
+    // Parse the cipher suite.
+    let cipher_suite: CipherSuite = cipher_suite.parse()?;
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+
+    // Save the new cipher suite.
+    vault.set_cipher_suite(cipher_suite)?;
+
+    println!("New data will be encrypted with {}.", cipher_suite);
+    Ok(())
+}
+
+/// Set how many previous versions of each credential the vault keeps.
+pub fn set_credential_history_limit(
+    vault_location: &VaultLocation,
+    limit: u32,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+
+    // Save the new limit, deleting older versions.
+    vault.set_credential_history_limit(limit)?;
+
+    println!(
+        "Up to {} previous version(s) of each credential will be kept.",
+        limit
+    );
+    Ok(())
+}
+
+/// Set how many previous revisions of each file the vault keeps.
+pub fn set_file_revision_limit(vault_location: &VaultLocation, limit: u32) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+
+    // Save the new limit, deleting older revisions.
+    vault.set_file_revision_limit(limit)?;
+
+    println!(
+        "Up to {} previous revision(s) of each file will be kept.",
+        limit
+    );
+    Ok(())
+}
+
+/// Set how many days previous revisions of files are kept for, where 0 keeps them regardless of
+/// their age.
+pub fn set_file_revision_max_age(vault_location: &VaultLocation, days: u32) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+
+    // Save the new maximum age, deleting older revisions.
+    let max_age = (days > 0).then_some(days);
+    vault.set_file_revision_max_age(max_age)?;
+
+    match max_age {
+        Some(days) => println!(
+            "Previous revisions of files will be kept for {} day(s).",
+            days
+        ),
+        None => println!("Previous revisions of files will be kept regardless of their age."),
+    }
+    Ok(())
+}
+
+/// Set how many days deleted items are kept in the trash for, where 0 keeps them until they're
+/// purged.
+pub fn set_trash_retention(vault_location: &VaultLocation, days: u32) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+
+    // Save the new retention period.
+    let retention = (days > 0).then_some(days);
+    vault.set_trash_retention(retention)?;
+
+    match retention {
+        Some(days) => println!(
+            "Deleted items will be kept in the trash for {} day(s).",
+            days
+        ),
+        None => println!("Deleted items will be kept in the trash until they're purged."),
+    }
+    Ok(())
+}
+
+/// Move the contents of the vault's files into its database, so the whole vault is one file.
+pub fn convert_to_single_file(vault_location: &VaultLocation) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+
+    // Convert the vault.
+    vault.convert_to_single_file()?;
+
+    println!(
+        "The vault is now a single file: {}",
+        vault_location.db_path()?
+    );
+    Ok(())
+}
+
+/// Move the contents of the single-file default vault's files out of its database, into the data
+/// directory.
+pub fn convert_to_directory(vault_location: &VaultLocation) -> eyre::Result<()> {
+    // Only the default vault & the vaults of profiles have a directory.
+    let Some(data_dir) = vault_location.data_dir()? else {
+        return Err(eyre!(
+            "Only the default vault & the vaults of profiles can keep their files in a directory. Copy the vault file to {} first.",
+            db_path()?
+        ));
+    };
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+
+    // Convert the vault.
+    vault.convert_to_directory(&data_dir)?;
+
+    println!("The vault now keeps its files in {}.", data_dir);
+    Ok(())
+}
+
+/// Report any problems with the vault, checking the given account's entries too.
+pub fn check_vault(vault_location: &VaultLocation, username: String) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Find & print the problems.
+    let issues = find_vault_issues(&vault, &unlocked)?;
+    if issues.is_empty() {
+        println!("No problems found.");
+        return Ok(());
+    }
+    for issue in &issues {
+        println!("{}.", issue);
+    }
+    println!(
+        "{} problem(s) found. Run `dgruft {} vault --repair` to fix them.",
+        issues.len(),
+        username
+    );
+    Ok(())
+}
+
+/// Fix any problems with the vault, checking the given account's entries too. Unless `yes` is
+/// set, the user is asked before each problem is fixed.
+pub fn repair_vault(
+    vault_location: &VaultLocation,
+    username: String,
+    yes: bool,
+) -> eyre::Result<()> {
+    // Connect to the vault.
+    let mut vault = vault_connect(vault_location)?;
+    // Login.
+    let unlocked = login(&mut vault, &username)?;
+
+    // Find the problems.
+    let issues = find_vault_issues(&vault, &unlocked)?;
+    if issues.is_empty() {
+        println!("No problems found.");
+        return Ok(());
+    }
+
+    // Fix each problem.
+    let mut num_repaired = 0;
+    for issue in &issues {
+        println!("{}.", issue);
+        if !yes && !cli_confirm(format!("{}? [y/N] ", issue.repair_description()), false)? {
+            println!("Skipped.");
+            continue;
+        }
+        vault.repair(issue, Some(&unlocked))?;
+        num_repaired += 1;
+    }
+
+    println!("{} of {} problem(s) fixed.", num_repaired, issues.len());
+    Ok(())
+}
+
+/// Back up the whole vault to a new archive, encrypted to the given recipient or to a
+/// passphrase.
+pub fn backup_vault(
+    vault_location: &VaultLocation,
+    destination: Utf8PathBuf,
+    to: Option<String>,
+) -> eyre::Result<()> {
+    // Parse the recipient before asking for anything else.
+    let recipient = age_recipient(to)?;
+    // Connect to the vault.
+    let vault = vault_connect(vault_location)?;
+
+    // Back up the vault.
+    let num_files = vault.backup(&recipient, &destination)?;
+
+    println!(
+        "Vault backed up to {} with {} file(s).",
+        destination, num_files
+    );
+    Ok(())
+}
+
+/// Verify a backup archive & restore it, either replacing the vault or into a separate directory.
+/// Unless `yes` is set, the user is asked before the vault is replaced.
+pub fn restore_vault(
+    vault_location: &VaultLocation,
+    source: Utf8PathBuf,
+    identity: Option<Utf8PathBuf>,
+    into: Option<Utf8PathBuf>,
+    yes: bool,
+) -> eyre::Result<()> {
+    // Open the backup archive.
+    let archive = BufReader::new(File::open(&source)?);
+
+    let Some(dir) = into else {
+        if !yes
+            && !cli_confirm(
+                format!("Really replace the vault with backup {}? [y/N] ", source),
+                false,
+            )?
+        {
+            println!("Restore cancelled.");
+            return Ok(());
+        }
+        let identity = age_identity(identity)?;
+
+        // Replace the vault.
+        Vault::restore_backup(
+            &identity,
+            archive,
+            &vault_location.db_path()?,
+            vault_location.data_dir()?.as_deref(),
+        )?;
+
+        println!("Vault replaced with backup {}.", source);
+        return Ok(());
+    };
+
+    // Nothing in the directory may be replaced.
+    if dir.try_exists()? {
+        if fs::read_dir(&dir)?.next().is_some() {
+            return Err(eyre!("Failed to restore backup: {} is not empty.", dir));
+        }
+    } else {
+        fs::create_dir_all(&dir)?;
+    }
+    let identity = age_identity(identity)?;
+
+    // Restore the backup into the directory.
+    Vault::restore_backup(&identity, archive, &dir.join(DB_NAME), Some(&dir))?;
+
+    println!(
+        "Backup {} restored into {}. Run `dgruft <username> profiles --new <name> --path {}` to open it with --vault <name>.",
+        source,
+        dir,
+        dir
+    );
+    Ok(())
+}
+
+// PROFILES
+
+/// Register a new vault profile, whose vault is kept in the given data directory, or else in a new
+/// directory named after the profile.
+pub fn new_profile(name: String, path: Option<Utf8PathBuf>) -> eyre::Result<()> {
+    let mut profiles = VaultProfiles::load()?;
+    let dir = match path {
+        Some(path) => Utf8PathBuf::try_from(std::path::absolute(path)?)?,
+        None => profiles_dir()?.join(&name),
+    };
+    profiles.add(&name, dir.clone())?;
+
+    // Create the vault's data directory & database file, like the default vault's.
+    let db_path = dir.join(DB_NAME);
+    fs::create_dir_all(&dir)?;
+    if fs::metadata(&db_path).is_err() {
+        File::create_new(&db_path)?;
+    }
+    profiles.save()?;
+
+    println!(
+        "Vault profile {} created in {}. Select it with --vault {}.",
+        name, dir, name
+    );
+    Ok(())
+}
+
+/// List all vault profiles & their data directories.
+pub fn list_profiles() -> eyre::Result<()> {
+    let profiles = VaultProfiles::load()?;
+
+    // Create profile list output, marking the default profile.
+    let profiles_string = profiles.iter().fold(String::new(), |acc, (name, dir)| {
+        let marker = if profiles.default_name() == Some(name) {
+            " (default)"
+        } else {
+            ""
+        };
+        acc + &format!("{}{}: {}\n", name, marker, dir)
+    });
+    if profiles_string.is_empty() {
+        println!("No vault profiles exist.");
+        return Ok(());
+    }
+
+    // Print profiles.
+    println!("{}", profiles_string);
+    Ok(())
+}
+
+/// Unregister a vault profile. Its vault is left where it is.
+pub fn remove_profile(name: String) -> eyre::Result<()> {
+    let mut profiles = VaultProfiles::load()?;
+    let dir = profiles.remove(&name)?;
+    profiles.save()?;
+
+    println!(
+        "Vault profile {} removed. Its vault is still in {}.",
+        name, dir
+    );
+    Ok(())
+}
+
+/// Set the vault profile to use when --vault isn't given, or go back to the vault in the data
+/// directory.
+pub fn set_default_profile(name: Option<String>) -> eyre::Result<()> {
+    let mut profiles = VaultProfiles::load()?;
+    profiles.set_default(name.as_deref())?;
+    profiles.save()?;
+
+    match name {
+        Some(name) => println!(
+            "Vault profile {} will be used when --vault isn't given.",
+            name
+        ),
+        None => println!(
+            "The vault in {} will be used when --vault isn't given.",
+            data_dir()?
+        ),
+    }
+    Ok(())
+}
+
+// HELPERS
+
+// Connect to the vault at the given location. A single-file vault is created if it doesn't exist.
+fn vault_connect(vault_location: &VaultLocation) -> eyre::Result<Vault> {
+    let db_path = vault_location.db_path()?;
+    match vault_location.data_dir()? {
+        Some(data_dir) => Vault::connect(db_path, data_dir),
+        None => {
+            if fs::metadata(&db_path).is_err() {
+                File::create_new(&db_path)?;
+            }
+            Vault::connect_single_file(db_path)
+        }
+    }
+}
+
+// Find any problems with the vault. The temp directory lives beside the account directories, but
+// isn't one, & neither are any directories holding the vaults of profiles, such as those created
+// inside the data directory by older versions of `dgruft`.
+fn find_vault_issues(vault: &Vault, unlocked: &UnlockedAccount) -> eyre::Result<Vec<VaultIssue>> {
+    let temp_dir = temp_dir()?;
+    let profiles = VaultProfiles::load()?;
+    let is_ignored = |path: &Utf8PathBuf| {
+        *path == temp_dir || profiles.iter().any(|(_, dir)| dir.starts_with(path))
+    };
+    Ok(vault
+        .check(Some(unlocked))?
+        .into_iter()
+        .filter(
+            |issue| !matches!(issue, VaultIssue::OrphanedDirectory { path } if is_ignored(path)),
+        )
+        .collect())
+}
+
+// Describe who shared an item. Usernames aren't stored in privacy mode, so the owner is left out.
+fn shared_by(vault: &Vault, owner_username: &str) -> String {
+    if vault.privacy_mode() {
+        String::from("(shared)")
+    } else {
+        format!("(shared by {})", owner_username)
+    }
+}
+
+// Verify login into correct, returning account data.
+fn login(vault: &mut Vault, username: &str) -> eyre::Result<UnlockedAccount> {
+    authenticate(
+        vault,
+        username,
+        |vault, username, password, key_file, two_factor_code| {
+            vault.load_unlocked_account(username, password, key_file, two_factor_code)
+        },
+    )
+}
+
+// Prompt for an account's password, key file & two-factor code, then pass them to `unlock`.
+fn authenticate<T, F>(vault: &mut Vault, username: &str, unlock: F) -> eyre::Result<T>
+where
+    F: FnOnce(&mut Vault, &str, &str, Option<&KeyFile>, Option<&TwoFactorCode>) -> eyre::Result<T>,
+{
+    let password = prompt_password(username)?;
+    // Only prompt for a key file if the account requires one.
+    let account = vault.load_account(username)?;
+    let key_file = if account.requires_key_file() {
+        let path = cli_prompt(format!("Key file for {}: ", username))?;
+        Some(KeyFile::open(path.trim())?)
+    } else {
+        None
+    };
+    // Only prompt for a two-factor code if the account has two-factor login.
+    let two_factor_code = if account.requires_two_factor() {
+        Some(prompt_two_factor_code(username)?)
+    } else {
+        None
+    };
+    let unlocked = unlock(
+        vault,
+        username,
+        password.expose(),
+        key_file.as_ref(),
+        two_factor_code.as_ref(),
+    )?;
+    // Warn the user when they're running out of backup codes, unless `unlock` purged the account.
+    if let Some(TwoFactorCode::Backup(_)) = two_factor_code {
+        if let Ok(num_left) = vault.count_backup_codes(username) {
+            println!("Backup code used. {} backup code(s) left.", num_left);
+        }
+    }
+    Ok(unlocked)
+}
+
+// Two-factor code prompt. Accepts either a TOTP code or a backup code.
+fn prompt_two_factor_code(username: &str) -> eyre::Result<TwoFactorCode> {
+    Secret::new(rpassword::prompt_password(format!(
+        "Two-factor code for {}: ",
         username
     ))?)
+    .expose()
+    .parse()
+}
+
+// Key share prompt. The number of key shares needed is only known once the first one is given.
+fn prompt_key_share(username: &str, i: u8, threshold: Option<u8>) -> eyre::Result<KeyShare> {
+    let message = match threshold {
+        Some(threshold) => format!("Key share {} of {} for {}: ", i, threshold, username),
+        None => format!("Key share {} for {}: ", i, username),
+    };
+    Secret::new(rpassword::prompt_password(message)?)
+        .expose()
+        .parse()
+}
+
+// New password prompt, confirmed by typing it twice.
+fn prompt_new_password(username: &str) -> eyre::Result<Secret<String>> {
+    let new_password = Secret::new(rpassword::prompt_password(format!(
+        "New password for account {}: ",
+        username
+    ))?);
+    let confirm_new_password = Secret::new(rpassword::prompt_password(format!(
+        "Confirm new password for account {}: ",
+        username
+    ))?);
+    if confirm_new_password != new_password {
+        return Err(eyre!("New passwords do not match."));
+    }
+    Ok(new_password)
+}
+
+// Parse the given age public key, or prompt for a passphrase to encrypt to if none is given.
+fn age_recipient(to: Option<String>) -> eyre::Result<AgeRecipient> {
+    if let Some(to) = to {
+        return to.parse();
+    }
+    let passphrase = Secret::new(rpassword::prompt_password("Passphrase for age payload: ")?);
+    let confirm_passphrase = Secret::new(rpassword::prompt_password(
+        "Confirm passphrase for age payload: ",
+    )?);
+    if confirm_passphrase != passphrase {
+        return Err(eyre!("Passphrases do not match."));
+    }
+    Ok(AgeRecipient::Passphrase(passphrase))
+}
+
+// Read the first age identity from the given identity file, or prompt for a passphrase to decrypt
+// with if none is given.
+fn age_identity(identity_file: Option<Utf8PathBuf>) -> eyre::Result<AgeIdentity> {
+    match identity_file {
+        Some(identity_file) => AgeIdentity::from_identity_file(identity_file),
+        None => Ok(AgeIdentity::Passphrase(Secret::new(
+            rpassword::prompt_password("Passphrase for age payload: ")?,
+        ))),
+    }
+}
+
+// Show backup codes to the user.
+fn print_backup_codes(backup_codes: &[BackupCode]) {
+    println!("Backup codes:");
+    for backup_code in backup_codes {
+        println!("    {}", backup_code.encode().expose());
+    }
+    println!(
+        "Write them down & keep them safe. Each can be used once in place of a two-factor code if \
+        you lose your authenticator app, & they won't be shown again."
+    );
+}
+
+// Show a recovery code to the user.
+fn print_recovery_code(recovery_code: &RecoveryCode) {
+    println!(
+        "Recovery code: {}\nWrite it down & keep it safe. It can unlock your account if you \
+        forget your password, & it won't be shown again.",
+        recovery_code.encode().expose()
+    );
+}
+
+// Generate a new key file at `gen_path`, or open the existing key file at `path`.
+fn new_key_file(
+    gen_path: Option<&Utf8Path>,
+    path: Option<&Utf8Path>,
+) -> eyre::Result<Option<KeyFile>> {
+    match (gen_path, path) {
+        (Some(gen_path), _) => {
+            let key_file = KeyFile::generate(gen_path)?;
+            println!("Key file generated at {}. Keep it safe!", gen_path);
+            Ok(Some(key_file))
+        }
+        (None, Some(path)) => Ok(Some(KeyFile::open(path)?)),
+        (None, None) => Ok(None),
+    }
+}
+
+// Password prompt.
+fn prompt_password(username: &str) -> eyre::Result<Secret<String>> {
+    Ok(Secret::new(rpassword::prompt_password(format!(
+        "Password for {}: ",
+        username
+    ))?))
 }
 
 // General CLI prompt.
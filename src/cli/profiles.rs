@@ -0,0 +1,326 @@
+//! Named vault profiles, registered in the `dgruft` config directory & selected with `--vault`.
+use std::{collections::BTreeMap, fs};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::{self, eyre};
+
+use crate::utils::{config_dir, data_dir, db_path, DB_NAME};
+
+/// The name of the file in the config directory where vault profiles are registered.
+const PROFILES_FILE_NAME: &str = "vaults.conf";
+
+/// The suffix added to the name of the data directory to name the directory beside it where new
+/// vault profiles are kept by default.
+const PROFILES_DIR_SUFFIX: &str = "-vaults";
+
+/// Where a vault is kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VaultLocation {
+    /// The vault in the `dgruft` data directory.
+    Default,
+    /// The vault of a profile, whose database & files are kept in the given data directory.
+    Profile(Utf8PathBuf),
+    /// The single-file vault at the given path.
+    SingleFile(Utf8PathBuf),
+}
+impl VaultLocation {
+    /// Get the path to the database of the vault.
+    pub fn db_path(&self) -> eyre::Result<Utf8PathBuf> {
+        match self {
+            Self::Default => db_path(),
+            Self::Profile(dir) => Ok(dir.join(DB_NAME)),
+            Self::SingleFile(path) => Ok(path.clone()),
+        }
+    }
+
+    /// Get the data directory of the vault, unless it's a single file.
+    pub fn data_dir(&self) -> eyre::Result<Option<Utf8PathBuf>> {
+        match self {
+            Self::Default => Ok(Some(data_dir()?)),
+            Self::Profile(dir) => Ok(Some(dir.clone())),
+            Self::SingleFile(_) => Ok(None),
+        }
+    }
+}
+
+/// The vault profiles registered in the config directory, each with the data directory of its
+/// vault, along with the profile to use when `--vault` isn't given.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VaultProfiles {
+    profiles: BTreeMap<String, Utf8PathBuf>,
+    default: Option<String>,
+}
+impl VaultProfiles {
+    /// Load the [VaultProfiles] registered in the config directory.
+    pub fn load() -> eyre::Result<Self> {
+        let path = profiles_path()?;
+        if !path.try_exists()? {
+            return Ok(Self::default());
+        }
+        Self::parse(&fs::read_to_string(&path)?)
+            .map_err(|err| eyre!("Failed to read vault profiles from {}: {}", path, err))
+    }
+
+    /// Save these [VaultProfiles] to the config directory.
+    pub fn save(&self) -> eyre::Result<()> {
+        fs::create_dir_all(config_dir()?)?;
+        Ok(fs::write(profiles_path()?, self.to_config_string())?)
+    }
+
+    /// Register a new profile with the given `name` & the data directory of its vault.
+    pub fn add<S>(&mut self, name: S, dir: Utf8PathBuf) -> eyre::Result<()>
+    where
+        S: AsRef<str>,
+    {
+        let name = name.as_ref();
+        validate_name(name)?;
+        if self.profiles.contains_key(name) {
+            return Err(eyre!("Vault profile \"{}\" already exists.", name));
+        }
+        self.profiles.insert(name.to_owned(), dir);
+        Ok(())
+    }
+
+    /// Unregister the profile with the given `name`, returning the data directory of its vault.
+    /// If it's the default profile, the vault in the data directory becomes the default again.
+    pub fn remove<S>(&mut self, name: S) -> eyre::Result<Utf8PathBuf>
+    where
+        S: AsRef<str>,
+    {
+        let name = name.as_ref();
+        let dir = self
+            .profiles
+            .remove(name)
+            .ok_or_else(|| eyre!("No vault profile named \"{}\" exists.", name))?;
+        if self.default.as_deref() == Some(name) {
+            self.default = None;
+        }
+        Ok(dir)
+    }
+
+    /// Set the profile to use when `--vault` isn't given, or use the vault in the data directory
+    /// if `None`.
+    pub fn set_default(&mut self, name: Option<&str>) -> eyre::Result<()> {
+        if let Some(name) = name {
+            if !self.profiles.contains_key(name) {
+                return Err(eyre!("No vault profile named \"{}\" exists.", name));
+            }
+        }
+        self.default = name.map(str::to_owned);
+        Ok(())
+    }
+
+    /// Get the name of the profile to use when `--vault` isn't given, if any.
+    pub fn default_name(&self) -> Option<&str> {
+        self.default.as_deref()
+    }
+
+    /// Iterate over the names of the profiles & the data directories of their vaults, in order of
+    /// name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Utf8Path)> {
+        self.profiles
+            .iter()
+            .map(|(name, dir)| (name.as_str(), dir.as_path()))
+    }
+
+    /// Find the vault selected by the given `--vault` argument: the profile with that name if
+    /// there is one, or else the single-file vault at that path. Without an argument, the default
+    /// profile is selected, or the vault in the data directory if there is no default profile.
+    ///
+    /// Unless `create` is set, the single-file vault must already exist. A new single-file vault
+    /// can't have a name which could be mistaken for a profile's, such as `work` rather than
+    /// `work.db` or `./work`.
+    pub fn resolve(&self, vault: Option<&str>, create: bool) -> eyre::Result<VaultLocation> {
+        let Some(name_or_path) = vault.or(self.default.as_deref()) else {
+            return Ok(VaultLocation::Default);
+        };
+        if let Some(dir) = self.profiles.get(name_or_path) {
+            return Ok(VaultLocation::Profile(dir.clone()));
+        }
+        let path = Utf8PathBuf::from(name_or_path);
+        if path.try_exists()? || (create && validate_name(name_or_path).is_err()) {
+            Ok(VaultLocation::SingleFile(path))
+        } else {
+            Err(eyre!(
+                "No vault profile or vault file named \"{}\" exists.",
+                name_or_path
+            ))
+        }
+    }
+
+    // Helper function: Parse [VaultProfiles] from the contents of a profiles file. Each line is
+    // either `profile <name> <directory>` or `default <name>`, & lines starting with `#` are
+    // comments.
+    fn parse(contents: &str) -> eyre::Result<Self> {
+        let mut profiles = Self::default();
+        let mut default = None;
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, rest) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "profile" => match rest.trim_start().split_once(' ') {
+                    Some((name, dir)) => profiles.add(name, Utf8PathBuf::from(dir.trim_start()))?,
+                    None => return Err(eyre!("Line {} has no directory: {}", i + 1, line)),
+                },
+                "default" => default = Some(rest.trim_start()),
+                _ => return Err(eyre!("Line {} isn't understood: {}", i + 1, line)),
+            }
+        }
+        profiles.set_default(default)?;
+        Ok(profiles)
+    }
+
+    // Helper function: Write these [VaultProfiles] in the format of a profiles file.
+    fn to_config_string(&self) -> String {
+        let mut contents = String::from("# dgruft vault profiles\n");
+        if let Some(default) = &self.default {
+            contents += &format!("default {}\n", default);
+        }
+        for (name, dir) in self.iter() {
+            contents += &format!("profile {} {}\n", name, dir);
+        }
+        contents
+    }
+}
+
+/// Get the directory where new vault profiles are kept by default, beside the data directory so
+/// that their vaults aren't mistaken for the account directories of the vault in it.
+pub fn profiles_dir() -> eyre::Result<Utf8PathBuf> {
+    profiles_dir_beside(&data_dir()?)
+}
+
+// Get the directory beside the given data directory where new vault profiles are kept by default.
+fn profiles_dir_beside(data_dir: &Utf8Path) -> eyre::Result<Utf8PathBuf> {
+    match (data_dir.parent(), data_dir.file_name()) {
+        (Some(parent), Some(name)) => Ok(parent.join(format!("{}{}", name, PROFILES_DIR_SUFFIX))),
+        _ => Err(eyre!(
+            "Failed to find a directory for vault profiles beside the data directory {}.",
+            data_dir
+        )),
+    }
+}
+
+// Get the path to the file where vault profiles are registered.
+fn profiles_path() -> eyre::Result<Utf8PathBuf> {
+    Ok(config_dir()?.join(PROFILES_FILE_NAME))
+}
+
+// Ensure that a profile name can be told apart from a path & written to a profiles file.
+fn validate_name(name: &str) -> eyre::Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(eyre!(
+            "Invalid vault profile name \"{}\". Use only letters, numbers, '-' & '_'.",
+            name
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn profiles_round_trip() {
+        let mut profiles = VaultProfiles::default();
+        profiles
+            .add("personal", Utf8PathBuf::from("/home/me/vaults/personal"))
+            .unwrap();
+        profiles
+            .add("work", Utf8PathBuf::from("/home/me/my vaults/work"))
+            .unwrap();
+        let _ = profiles
+            .add("work", Utf8PathBuf::from("/elsewhere"))
+            .unwrap_err();
+        let _ = profiles
+            .add("work.db", Utf8PathBuf::from("/elsewhere"))
+            .unwrap_err();
+        let _ = profiles.set_default(Some("missing")).unwrap_err();
+        profiles.set_default(Some("work")).unwrap();
+
+        let parsed = VaultProfiles::parse(&profiles.to_config_string()).unwrap();
+        assert_eq!(parsed, profiles);
+        assert_eq!(parsed.default_name(), Some("work"));
+        assert_eq!(
+            parsed.iter().collect::<Vec<_>>(),
+            [
+                ("personal", Utf8Path::new("/home/me/vaults/personal")),
+                ("work", Utf8Path::new("/home/me/my vaults/work")),
+            ]
+        );
+
+        let _ = VaultProfiles::parse("profile work").unwrap_err();
+        let _ = VaultProfiles::parse("default work").unwrap_err();
+        let _ = VaultProfiles::parse("vault work /home/me").unwrap_err();
+    }
+
+    #[test]
+    fn profiles_dir_outside_data_dir() {
+        let data_dir = Utf8Path::new("/home/me/.local/share/dgruft");
+        let dir = profiles_dir_beside(data_dir).unwrap();
+        assert_eq!(dir, Utf8Path::new("/home/me/.local/share/dgruft-vaults"));
+        assert!(!dir.starts_with(data_dir));
+        assert_eq!(
+            profiles_dir_beside(Utf8Path::new(".config")).unwrap(),
+            Utf8Path::new(".config-vaults")
+        );
+        let _ = profiles_dir_beside(Utf8Path::new("/")).unwrap_err();
+    }
+
+    #[test]
+    fn resolve_vaults() {
+        let mut profiles = VaultProfiles::default();
+        assert_eq!(
+            profiles.resolve(None, false).unwrap(),
+            VaultLocation::Default
+        );
+        profiles
+            .add("work", Utf8PathBuf::from("/home/me/work"))
+            .unwrap();
+        assert_eq!(
+            profiles.resolve(Some("work"), false).unwrap(),
+            VaultLocation::Profile(Utf8PathBuf::from("/home/me/work"))
+        );
+        assert_eq!(
+            profiles.resolve(Some("Cargo.toml"), false).unwrap(),
+            VaultLocation::SingleFile(Utf8PathBuf::from("Cargo.toml"))
+        );
+
+        // Unknown names aren't mistaken for new single-file vaults.
+        let _ = profiles.resolve(Some("wrok"), false).unwrap_err();
+        let _ = profiles.resolve(Some("wrok"), true).unwrap_err();
+        let _ = profiles.resolve(Some("work.db"), false).unwrap_err();
+        assert_eq!(
+            profiles.resolve(Some("work.db"), true).unwrap(),
+            VaultLocation::SingleFile(Utf8PathBuf::from("work.db"))
+        );
+
+        // The default profile is used when no vault is given.
+        profiles.set_default(Some("work")).unwrap();
+        assert_eq!(
+            profiles.resolve(None, false).unwrap(),
+            VaultLocation::Profile(Utf8PathBuf::from("/home/me/work"))
+        );
+
+        // Removing the default profile goes back to the vault in the data directory.
+        assert_eq!(
+            profiles.remove("work").unwrap(),
+            Utf8PathBuf::from("/home/me/work")
+        );
+        let _ = profiles.remove("work").unwrap_err();
+        assert_eq!(profiles.default_name(), None);
+        assert_eq!(
+            profiles.resolve(None, false).unwrap(),
+            VaultLocation::Default
+        );
+    }
+}
@@ -9,7 +9,7 @@ use color_eyre::eyre::{self, eyre};
 use directories::ProjectDirs;
 
 /// The name of the `dgruft` SQLite database.
-const DB_NAME: &str = "dgruft.db";
+pub const DB_NAME: &str = "dgruft.db";
 
 /// The name of the temporary directory where edited files are stored.
 const TEMP_DIR_NAME: &str = "temp";
@@ -156,3 +156,47 @@ pub fn crate_name_constant_case() -> String {
 pub fn project_directory() -> Option<ProjectDirs> {
     ProjectDirs::from("ca", "maxgmr", env!("CARGO_PKG_NAME"))
 }
+
+/// Format the given Unix time as a UTC date & time, e.g. `"2024-02-29 13:05:09 UTC"`.
+pub fn format_unix_time(unix_time: u64) -> String {
+    let days = unix_time / 86_400;
+    let seconds = unix_time % 86_400;
+    // Convert the days since 1970-01-01 to a civil date, counting from 0000-03-01 so that leap
+    // days fall at the end of each year.
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds / 3_600,
+        seconds % 3_600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unix_time_format() {
+        assert_eq!(format_unix_time(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_unix_time(951_782_400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_unix_time(1_709_211_909), "2024-02-29 13:05:09 UTC");
+        assert_eq!(format_unix_time(1_735_689_599), "2024-12-31 23:59:59 UTC");
+    }
+}